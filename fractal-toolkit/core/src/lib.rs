@@ -0,0 +1,286 @@
+//! # Fractal Toolkit Core
+//!
+//! The arithmetic of custom imaginary units and the escape-time iteration of `z^n + c`, without
+//! dependencies and, with the default `std` feature turned off, without the standard library:
+//! the crate is `no_std` and uses only `core` and `alloc`, so it builds for embedded targets and
+//! small `wasm32-unknown-unknown` bundles.
+//!
+//! `fractal-toolkit` builds its `CustomComplex` arithmetic on this crate, so both give the same
+//! values bit for bit.
+//!
+//! ```toml
+//! [dependencies]
+//! fractal-toolkit-core = { path = "../fractal-toolkit/core", default-features = false }
+//! ```
+//!
+//! ## Number systems
+//!
+//! An [`Element`] a + bi lives in the 2D real algebra where i² = q + p*i ([`ISquared`]):
+//! the standard complex numbers for i² = -1, the split complex numbers for i² = 1, the dual
+//! numbers for i² = 0, and less familiar systems for other values.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::ops::{Add, Neg, Sub};
+
+/// The value of i², read as q + p*i with q = `re` and p = `im`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ISquared {
+    /// q, the real part of i²
+    pub re: f64,
+    /// p, the coefficient of i in i²
+    pub im: f64,
+}
+
+impl ISquared {
+    /// The standard complex numbers, i² = -1
+    pub const STANDARD: ISquared = ISquared { re: -1.0, im: 0.0 };
+    /// The split complex (hyperbolic) numbers, i² = 1
+    pub const SPLIT: ISquared = ISquared { re: 1.0, im: 0.0 };
+    /// The dual numbers, i² = 0
+    pub const DUAL: ISquared = ISquared { re: 0.0, im: 0.0 };
+
+    /// i² = re + im*i
+    pub const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+}
+
+/// A number a + bi, whose products depend on the value of i²
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Element {
+    /// a, the real component
+    pub re: f64,
+    /// b, the imaginary component
+    pub im: f64,
+}
+
+impl Element {
+    /// a + bi
+    pub const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    /// a² + b², the squared distance from 0 in the plane, whatever i² is
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The product with `other` when i² = `i_squared`
+    ///
+    /// (a + bi)(c + di) = ac + (ad + bc)i + bd*i² = (ac + bd*q) + (ad + bc + bd*p)i
+    pub fn mul(self, other: Element, i_squared: ISquared) -> Element {
+        let (a, b, c, d) = (self.re, self.im, other.re, other.im);
+        let bd = b * d;
+        Element { re: a * c + bd * i_squared.re, im: a * d + b * c + bd * i_squared.im }
+    }
+
+    /// The multiplicative inverse when i² = `i_squared`
+    ///
+    /// The conjugate of c + di is (c + dp) - di, and their product is the real number
+    /// c² + cdp - d²q. Zero divisors, such as c = ±d when i² = 1, have infinite or NaN inverses.
+    pub fn inverse(self, i_squared: ISquared) -> Element {
+        let (c, d) = (self.re, self.im);
+        let (q, p) = (i_squared.re, i_squared.im);
+        let norm = c * c + c * d * p - d * d * q;
+        Element { re: (c + d * p) / norm, im: -d / norm }
+    }
+
+    /// The quotient by `other` when i² = `i_squared`
+    pub fn div(self, other: Element, i_squared: ISquared) -> Element {
+        self.mul(other.inverse(i_squared), i_squared)
+    }
+
+    /// The `n`-th power by repeated squaring when i² = `i_squared`
+    pub fn powi(self, n: i32, i_squared: ISquared) -> Element {
+        let mut base = if n < 0 { self.inverse(i_squared) } else { self };
+        let mut result = Element::new(1.0, 0.0);
+        let mut n = n.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result.mul(base, i_squared);
+            }
+            base = base.mul(base, i_squared);
+            n >>= 1;
+        }
+        result
+    }
+}
+
+impl Add for Element {
+    type Output = Element;
+    fn add(self, other: Element) -> Element {
+        Element { re: self.re + other.re, im: self.im + other.im }
+    }
+}
+
+impl Sub for Element {
+    type Output = Element;
+    fn sub(self, other: Element) -> Element {
+        Element { re: self.re - other.re, im: self.im - other.im }
+    }
+}
+
+impl Neg for Element {
+    type Output = Element;
+    fn neg(self) -> Element {
+        Element { re: -self.re, im: -self.im }
+    }
+}
+
+/// The map z ↦ z^power + c iterated in one number system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Iteration {
+    /// The exponent, at least 2
+    pub power: u32,
+    /// The number system
+    pub i_squared: ISquared,
+    /// Iterations before a point counts as bounded
+    pub max_iterations: u32,
+    /// Escape radius
+    pub bailout: f64,
+}
+
+/// Where an orbit stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Escape {
+    /// Iterations before |z| exceeded the bailout, or max_iterations for bounded orbits
+    pub iterations: u32,
+    /// Whether |z| exceeded the bailout
+    pub escaped: bool,
+    /// The value of z where iteration stopped
+    pub final_z: Element,
+}
+
+impl Iteration {
+    /// `z^2 + c` in the standard complex numbers
+    pub fn quadratic(max_iterations: u32, bailout: f64) -> Self {
+        Self { power: 2, i_squared: ISquared::STANDARD, max_iterations, bailout }
+    }
+
+    /// One step z^power + c
+    pub fn step(&self, z: Element, c: Element) -> Element {
+        let mut value = z;
+        for _ in 1..self.power {
+            value = value.mul(z, self.i_squared);
+        }
+        value + c
+    }
+
+    /// Iterate from `z` until |z| exceeds the bailout or the iterations run out
+    ///
+    /// Counts the same way as the toolkit's escape-time renderers: the count is the number of
+    /// steps whose result stayed inside the bailout.
+    pub fn escape_time(&self, z: Element, c: Element) -> Escape {
+        let bailout_sqr = self.bailout * self.bailout;
+        let mut z = z;
+        let mut iterations = 0;
+        while iterations < self.max_iterations {
+            z = self.step(z, c);
+            if z.norm_sqr() > bailout_sqr {
+                return Escape { iterations, escaped: true, final_z: z };
+            }
+            iterations += 1;
+        }
+        Escape { iterations, escaped: false, final_z: z }
+    }
+
+    /// The orbit of `z`, from `z` itself up to the point before it escaped
+    pub fn orbit(&self, z: Element, c: Element) -> Vec<Element> {
+        let bailout_sqr = self.bailout * self.bailout;
+        let mut orbit = Vec::new();
+        let mut z = z;
+        for _ in 0..self.max_iterations {
+            orbit.push(z);
+            z = self.step(z, c);
+            if z.norm_sqr() > bailout_sqr {
+                break;
+            }
+        }
+        orbit
+    }
+
+    /// Escape times of a grid of pixels, row by row
+    ///
+    /// # Arguments
+    ///
+    /// * `bounds` - [x_min, x_max, y_min, y_max]; the first and last pixel of each row and
+    ///   column sit on the bounds
+    /// * `width`, `height` - Size of the grid in pixels
+    /// * `julia` - The constant c of a Julia set, or `None` for the Mandelbrot set (z starting at 0)
+    ///
+    /// # Returns
+    ///
+    /// `width * height` iteration counts
+    pub fn escape_grid(&self, bounds: [f64; 4], width: u32, height: u32, julia: Option<Element>) -> Vec<u32> {
+        let mut counts = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            self.escape_row(bounds, width, height, y, julia, &mut counts);
+        }
+        counts
+    }
+
+    /// `escape_grid` with the rows split across threads
+    #[cfg(feature = "std")]
+    pub fn escape_grid_parallel(&self, bounds: [f64; 4], width: u32, height: u32, julia: Option<Element>) -> Vec<u32> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get()) as u32;
+        let rows_per_thread = height.div_ceil(threads.max(1)).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..height)
+                .step_by(rows_per_thread as usize)
+                .map(|start| {
+                    scope.spawn(move || {
+                        let mut counts = Vec::new();
+                        for y in start..(start + rows_per_thread).min(height) {
+                            self.escape_row(bounds, width, height, y, julia, &mut counts);
+                        }
+                        counts
+                    })
+                })
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().expect("escape-time worker panicked")).collect()
+        })
+    }
+
+    fn escape_row(&self, bounds: [f64; 4], width: u32, height: u32, y: u32, julia: Option<Element>, counts: &mut Vec<u32>) {
+        let [x_min, x_max, y_min, y_max] = bounds;
+        let step = |min: f64, max: f64, size: u32| if size > 1 { (max - min) / (size - 1) as f64 } else { 0.0 };
+        let im = y_min + y as f64 * step(y_min, y_max, height);
+        for x in 0..width {
+            let point = Element::new(x_min + x as f64 * step(x_min, x_max, width), im);
+            let escape = match julia {
+                Some(c) => self.escape_time(point, c),
+                None => self.escape_time(Element::default(), point),
+            };
+            counts.push(escape.iterations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic_and_escape_time() {
+        let i_squared = ISquared::new(0.5, 1.5);
+        let z = Element::new(0.7, -1.3);
+        let product = Element::new(1.0, 2.0).div(z, i_squared).mul(z, i_squared);
+        assert!((product.re - 1.0).abs() < 1e-12 && (product.im - 2.0).abs() < 1e-12);
+        assert_eq!(z.powi(3, ISquared::SPLIT), z.mul(z, ISquared::SPLIT).mul(z, ISquared::SPLIT));
+
+        let iteration = Iteration::quadratic(100, 2.0);
+        assert!(!iteration.escape_time(Element::default(), Element::new(-1.0, 0.0)).escaped);
+        let escape = iteration.escape_time(Element::default(), Element::new(1.0, 0.0));
+        assert_eq!((escape.iterations, escape.escaped), (2, true));
+        assert_eq!(iteration.orbit(Element::default(), Element::new(1.0, 0.0)).len(), 3);
+
+        let grid = iteration.escape_grid([-2.0, 1.0, -1.5, 1.5], 7, 5, None);
+        assert_eq!(grid.len(), 35);
+        #[cfg(feature = "std")]
+        assert_eq!(iteration.escape_grid_parallel([-2.0, 1.0, -1.5, 1.5], 7, 5, None), grid);
+    }
+}
@@ -0,0 +1,32 @@
+//! Compile arbitrary formulas and evaluate them at arbitrary points
+//!
+//! The first 32 bytes are z and c, the rest is the formula. Evaluation may fail or give NaN,
+//! but must not panic, including with derivatives and in custom number systems.
+
+#![no_main]
+
+use fractal_toolkit::algebra::{Dual, NumberSystem};
+use fractal_toolkit::{CompiledFormula, EvaluatorOptions};
+use libfuzzer_sys::fuzz_target;
+use num_complex::Complex;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 32 {
+        return;
+    }
+    let (numbers, text) = data.split_at(32);
+    let Ok(text) = std::str::from_utf8(text) else {
+        return;
+    };
+    let number = |i: usize| f64::from_le_bytes(numbers[8 * i..8 * i + 8].try_into().unwrap());
+    let (z, c) = (Complex::new(number(0), number(1)), Complex::new(number(2), number(3)));
+
+    let Ok(formula) = CompiledFormula::with_options(text, &EvaluatorOptions::untrusted()) else {
+        return;
+    };
+    let _ = formula.try_eval(z, c);
+    let _ = formula.try_eval_dual(Dual::variable(z), Dual::constant(c));
+    for i_squared in [Complex::new(1.0, 0.0), Complex::new(0.0, 0.0), Complex::new(-0.5, 2.0)] {
+        let _ = NumberSystem::for_params(i_squared, &formula).step(&formula, z, c);
+    }
+});
@@ -0,0 +1,30 @@
+//! Tokenize and parse arbitrary text in both parser modes
+//!
+//! Any input may be rejected with a `FormulaError`, but none may panic or overflow the stack.
+
+#![no_main]
+
+use fractal_toolkit::formula::MAX_FORMULA_TOKENS;
+use fractal_toolkit::{EvaluatorOptions, Formula, MathEvaluator};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let strict = EvaluatorOptions { max_tokens: Some(MAX_FORMULA_TOKENS), ..EvaluatorOptions::strict() };
+    for options in [EvaluatorOptions::untrusted(), strict.clone()] {
+        if let Err(error) = MathEvaluator::validate_formula(text, &options) {
+            // The span must point inside the formula so the diagnostic can underline it
+            assert!(error.span.end <= text.len() && text.is_char_boundary(error.span.start));
+        }
+    }
+    // `Formula::parse` takes trusted input without a token limit
+    if MathEvaluator::validate_formula(text, &strict).is_err() {
+        return;
+    }
+    if let Ok(formula) = Formula::parse(text) {
+        // A formula written back out parses again
+        assert!(Formula::parse(&formula.to_string()).is_ok());
+    }
+});
@@ -0,0 +1,292 @@
+//! Numerical audit of the algebraic identities of a custom i² system
+//!
+//! With i² = q + p·i, `CustomComplex` arithmetic is the algebra R[t]/(t² − p·t − q). Which
+//! identities it keeps from the complex numbers decides what a render can rely on: division and
+//! negative powers need inverses, the multiplier of a cycle needs a field, and the escape radius
+//! bounds of `auto_bailout` need a multiplicative norm. `audit_identities` tests each identity on
+//! sample elements through the same arithmetic the renderers use, and searches the unit circle
+//! for zero divisors, the elements whose multiplication map is singular. The `IdentityReport`
+//! records which identities hold, the largest error seen and a counterexample for each failure,
+//! and classifies the system by the discriminant p² + 4q:
+//!
+//! - negative: elliptic, isomorphic to the complex numbers, a field
+//! - positive: hyperbolic, isomorphic to the split complex numbers, with two lines of zero divisors
+//! - zero: parabolic, isomorphic to the dual numbers, with one line of zero divisors
+
+use crate::rng::CounterRng;
+use crate::CustomComplex;
+use num_complex::Complex;
+use rand::Rng;
+use serde::Serialize;
+use std::fmt;
+
+/// Seed of the sample elements, so audits are reproducible
+const AUDIT_SEED: u64 = 0x1D3_A11D;
+
+/// Relative error below which an identity counts as holding
+const TOLERANCE: f64 = 1e-9;
+
+/// Directions of the unit circle scanned for zero divisors
+const SCAN_STEPS: usize = 3600;
+
+/// The two sides of an identity, evaluated at a triple of elements
+type Sides<'a> = &'a dyn Fn(&[Complex<f64>; 3]) -> (Complex<f64>, Complex<f64>);
+
+/// The kind of 2D algebra a value of i² gives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemKind {
+    /// Isomorphic to the complex numbers (p² + 4q < 0)
+    Elliptic,
+    /// Isomorphic to the split complex numbers (p² + 4q > 0)
+    Hyperbolic,
+    /// Isomorphic to the dual numbers (p² + 4q = 0)
+    Parabolic,
+}
+
+impl SystemKind {
+    /// The kind of the system with i² = q + p·i, read from `i_squared` as (q, p)
+    pub fn of(i_squared: Complex<f64>) -> Self {
+        let discriminant = i_squared.im * i_squared.im + 4.0 * i_squared.re;
+        if discriminant < 0.0 {
+            SystemKind::Elliptic
+        } else if discriminant > 0.0 {
+            SystemKind::Hyperbolic
+        } else {
+            SystemKind::Parabolic
+        }
+    }
+}
+
+/// An identity the audit tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Identity {
+    /// zw = wz
+    Commutativity,
+    /// (zw)v = z(wv)
+    Associativity,
+    /// z(w + v) = zw + zv
+    Distributivity,
+    /// 1z = z
+    MultiplicativeIdentity,
+    /// Every nonzero z has a w with zw = 1
+    Inverses,
+    /// zw = 0 only if z = 0 or w = 0
+    NoZeroDivisors,
+    /// |zw| = |z||w| with the Euclidean norm of the plane
+    MultiplicativeNorm,
+}
+
+/// The outcome of testing one identity
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IdentityCheck {
+    /// The identity tested
+    pub identity: Identity,
+    /// Whether it held for every sample, within the tolerance
+    pub holds: bool,
+    /// Largest relative error seen
+    pub max_error: f64,
+    /// The elements where it failed worst (z, w, v as the identity names them), if it failed
+    pub counterexample: Option<Vec<Complex<f64>>>,
+}
+
+/// Which identities a custom i² system satisfies
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IdentityReport {
+    /// The value of i² audited
+    pub i_squared: Complex<f64>,
+    /// Its kind, from the discriminant
+    pub kind: SystemKind,
+    /// Number of sample elements (or triples) each identity was tested on
+    pub samples: usize,
+    /// One check per identity, in the order of `Identity`
+    pub checks: Vec<IdentityCheck>,
+}
+
+impl IdentityReport {
+    /// Whether an identity held
+    pub fn holds(&self, identity: Identity) -> bool {
+        self.checks.iter().any(|check| check.identity == identity && check.holds)
+    }
+
+    /// Whether every nonzero element can be divided by, so division and negative powers are
+    /// defined everywhere except at 0
+    pub fn is_field(&self) -> bool {
+        self.holds(Identity::Inverses) && self.holds(Identity::NoZeroDivisors)
+    }
+
+    /// A pair of nonzero elements whose product is zero, if the audit found one
+    pub fn zero_divisor(&self) -> Option<(Complex<f64>, Complex<f64>)> {
+        let check = self.checks.iter().find(|check| check.identity == Identity::NoZeroDivisors)?;
+        match check.counterexample.as_deref() {
+            Some([z, w, ..]) => Some((*z, *w)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for IdentityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Identities of i² = {} ({:?}, {} samples):", self.i_squared, self.kind, self.samples)?;
+        for check in &self.checks {
+            let verdict = if check.holds { "holds" } else { "fails" };
+            write!(f, "  {:?}: {} (max error {:.1e})", check.identity, verdict, check.max_error)?;
+            if let Some(elements) = &check.counterexample {
+                let elements: Vec<String> = elements.iter().map(|z| z.to_string()).collect();
+                write!(f, ", e.g. at {}", elements.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        if self.is_field() {
+            write!(f, "  A field: division and negative powers are defined for every nonzero value")
+        } else {
+            write!(f, "  Not a field: division and negative powers are undefined on the zero divisors")
+        }
+    }
+}
+
+/// Test the algebraic identities of the system with a custom i²
+///
+/// # Arguments
+///
+/// * `i_squared` - The value of i², read as in `CustomComplex` (re + im·i)
+/// * `samples` - Number of random elements, or triples of elements, each identity is tested on
+///
+/// # Returns
+///
+/// The report, with a check per identity
+pub fn audit_identities(i_squared: Complex<f64>, samples: usize) -> IdentityReport {
+    let element = |z: Complex<f64>| CustomComplex::from_standard(z, i_squared);
+    let mul = |z: Complex<f64>, w: Complex<f64>| element(z).multiply(&element(w)).to_standard();
+    let mut rng = CounterRng::new(AUDIT_SEED, 0);
+    let triples: Vec<[Complex<f64>; 3]> = (0..samples.max(1))
+        .map(|_| std::array::from_fn(|_| Complex::new(rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0))))
+        .collect();
+
+    // Worst relative error of lhs = rhs over the samples, and where it occurred
+    let check = |identity: Identity, arity: usize, sides: Sides| {
+        let (max_error, worst) = triples
+            .iter()
+            .map(|triple| {
+                let (lhs, rhs) = sides(triple);
+                let error = (lhs - rhs).norm() / (1.0 + lhs.norm() + rhs.norm());
+                (if error.is_nan() { f64::INFINITY } else { error }, triple)
+            })
+            .fold((0.0, &triples[0]), |worst, next| if next.0 > worst.0 { next } else { worst });
+        let holds = max_error <= TOLERANCE;
+        IdentityCheck { identity, holds, max_error, counterexample: (!holds).then(|| worst[..arity].to_vec()) }
+    };
+
+    let one = Complex::new(1.0, 0.0);
+    let mut checks = vec![
+        check(Identity::Commutativity, 2, &|[z, w, _]| (mul(*z, *w), mul(*w, *z))),
+        check(Identity::Associativity, 3, &|[z, w, v]| (mul(mul(*z, *w), *v), mul(*z, mul(*w, *v)))),
+        check(Identity::Distributivity, 3, &|[z, w, v]| (mul(*z, *w + *v), mul(*z, *w) + mul(*z, *v))),
+        check(Identity::MultiplicativeIdentity, 1, &|[z, _, _]| (mul(one, *z), *z)),
+    ];
+
+    // Inverses of the samples, then a zero divisor anywhere on the unit circle
+    let mut inverses = check(Identity::Inverses, 1, &|[z, _, _]| (mul(*z, element(*z).inverse().to_standard()), one));
+    let zero_divisor = find_zero_divisor(i_squared);
+    let mut divisors = IdentityCheck { identity: Identity::NoZeroDivisors, holds: true, max_error: 0.0, counterexample: None };
+    if let Some((z, w)) = zero_divisor {
+        divisors = IdentityCheck { identity: Identity::NoZeroDivisors, holds: false, max_error: 1.0, counterexample: Some(vec![z, w]) };
+        if inverses.holds {
+            inverses.holds = false;
+            inverses.max_error = f64::INFINITY;
+            inverses.counterexample = Some(vec![z]);
+        }
+    }
+    checks.push(inverses);
+    checks.push(divisors);
+    checks.push(check(Identity::MultiplicativeNorm, 2, &|[z, w, _]| {
+        (Complex::new(mul(*z, *w).norm(), 0.0), Complex::new(z.norm() * w.norm(), 0.0))
+    }));
+
+    IdentityReport { i_squared, kind: SystemKind::of(i_squared), samples: triples.len(), checks }
+}
+
+/// A unit z and a unit w with zw = 0, found where the multiplication map of z is singular
+fn find_zero_divisor(i_squared: Complex<f64>) -> Option<(Complex<f64>, Complex<f64>)> {
+    let element = |z: Complex<f64>| CustomComplex::from_standard(z, i_squared);
+    let unit = |angle: f64| Complex::new(angle.cos(), angle.sin());
+    // Multiplication by z maps 1 and i to its columns; its determinant vanishes at zero divisors
+    let columns = |z: Complex<f64>| {
+        let z = element(z);
+        (z.multiply(&element(Complex::new(1.0, 0.0))).to_standard(), z.multiply(&element(Complex::new(0.0, 1.0))).to_standard())
+    };
+    let determinant = |angle: f64| {
+        let (first, second) = columns(unit(angle));
+        (first.re * second.im - first.im * second.re).abs()
+    };
+
+    let step = std::f64::consts::PI / SCAN_STEPS as f64;
+    for index in 0..SCAN_STEPS {
+        let angle = index as f64 * step;
+        // Local minima of |det|, refined by golden-section search; double roots don't change sign
+        if determinant(angle) > determinant(angle - step) || determinant(angle) > determinant(angle + step) {
+            continue;
+        }
+        let (mut low, mut high) = (angle - step, angle + step);
+        let ratio = (5f64.sqrt() - 1.0) / 2.0;
+        for _ in 0..100 {
+            let (left, right) = (high - ratio * (high - low), low + ratio * (high - low));
+            if determinant(left) < determinant(right) {
+                high = right;
+            } else {
+                low = left;
+            }
+        }
+        let angle = (low + high) / 2.0;
+        if determinant(angle) > 1e-12 {
+            continue;
+        }
+        // Snap rounding noise so exact zero divisors such as i print as such
+        let snap = |z: Complex<f64>| {
+            let snap = |x: f64| if x.abs() > 1e-12 { x } else { 0.0 };
+            Complex::new(snap(z.re), snap(z.im))
+        };
+        let z = snap(unit(angle));
+        let (first, second) = columns(z);
+        // The kernel of the map: a combination of 1 and i that z sends to 0
+        let kernel = if first.re.abs() + second.re.abs() > first.im.abs() + second.im.abs() {
+            Complex::new(second.re, -first.re)
+        } else {
+            Complex::new(second.im, -first.im)
+        };
+        let w = snap(kernel / kernel.norm());
+        if element(z).multiply(&element(w)).to_standard().norm() < 1e-9 {
+            return Some((z, w));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_classifies_the_three_systems() {
+        let complex = audit_identities(Complex::new(-1.0, 0.0), 200);
+        assert_eq!(complex.kind, SystemKind::Elliptic);
+        assert!(complex.is_field() && complex.holds(Identity::MultiplicativeNorm));
+
+        // Split complex numbers: (1 + i)(1 - i) = 1 - i² = 0
+        let split = audit_identities(Complex::new(1.0, 0.0), 200);
+        assert_eq!(split.kind, SystemKind::Hyperbolic);
+        assert!(split.holds(Identity::Commutativity) && split.holds(Identity::Associativity) && split.holds(Identity::Distributivity));
+        assert!(!split.is_field() && !split.holds(Identity::MultiplicativeNorm));
+        let (z, w) = split.zero_divisor().unwrap();
+        assert!((z.re.abs() - z.im.abs()).abs() < 1e-6 && (w.re.abs() - w.im.abs()).abs() < 1e-6);
+
+        // Dual numbers: i·i = 0; and a field with i² = -2 + 0.5i
+        let dual = audit_identities(Complex::new(0.0, 0.0), 200);
+        assert_eq!(dual.kind, SystemKind::Parabolic);
+        let (z, _) = dual.zero_divisor().unwrap();
+        assert!(z.re.abs() < 1e-6);
+        let skewed = audit_identities(Complex::new(-2.0, 0.5), 200);
+        assert!(skewed.is_field() && !skewed.holds(Identity::MultiplicativeNorm));
+    }
+}
@@ -0,0 +1,237 @@
+//! Bicomplex numbers and their escape-time fractals, such as the Tetrabrot
+//!
+//! A [`Bicomplex`] number is a pair of complex numbers `z1 + z2·j`, where `j` is a second
+//! imaginary unit that commutes with `i` and also squares to -1. Written out in real components
+//! it is `re + i·i + j·j + k·k` with `k = i·j`, which squares to +1. Unlike the quaternions the
+//! multiplication is commutative, so every formula in z and c has a bicomplex meaning.
+//!
+//! Formulas are evaluated through the idempotent basis `e1 = (1 + k)/2`, `e2 = (1 - k)/2`:
+//! `z1 + z2·j = (z1 - i·z2) e1 + (z1 + i·z2) e2`, and since `e1 e2 = 0` and `e1² = e1`,
+//! `e2² = e2`, a holomorphic formula acts on the two complex coordinates separately. Any
+//! formula `CompiledFormula` evaluates therefore iterates in the bicomplex numbers at the cost
+//! of two complex evaluations; functions that aren't holomorphic, such as `conj` or `abs`, act
+//! on each coordinate instead of having a bicomplex meaning of their own.
+//!
+//! Bicomplex fractals are four-dimensional and rendered as slices through a plane spanned by
+//! two axes (see `QuaternionSlice`, whose `re`, `i`, `j`, `k` axes name the same components).
+//! The Mandelbrot set of `z^2 + c` over the bicomplex numbers is the Tetrabrot; its `re,i`
+//! slice is the ordinary Mandelbrot set, and its `re,k` slice is the Mandelbrot set of the
+//! split complex numbers.
+
+use crate::quaternion::{Quaternion, QuaternionSlice};
+use crate::{generate_fractal_image_from_results, ColorStop, CompiledFormula, FractalError, FractalParams, IterationResult};
+use image::{ImageBuffer, Rgba};
+use num_complex::Complex;
+use std::ops::{Add, Mul, Sub};
+
+/// A bicomplex number `z1 + z2·j`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bicomplex {
+    /// The component without j, `re + im·i`
+    pub z1: Complex<f64>,
+    /// The coefficient of j, whose parts are the j and k components
+    pub z2: Complex<f64>,
+}
+
+impl Bicomplex {
+    pub fn new(z1: Complex<f64>, z2: Complex<f64>) -> Self {
+        Self { z1, z2 }
+    }
+
+    /// The number with the idempotent coordinates `a e1 + b e2`
+    pub fn from_idempotent(a: Complex<f64>, b: Complex<f64>) -> Self {
+        let i = Complex::new(0.0, 1.0);
+        Self::new((a + b) / 2.0, (a - b) * i / 2.0)
+    }
+
+    /// The idempotent coordinates `(z1 - i·z2, z1 + i·z2)`, on which arithmetic is componentwise
+    pub fn to_idempotent(self) -> (Complex<f64>, Complex<f64>) {
+        let i = Complex::new(0.0, 1.0);
+        (self.z1 - i * self.z2, self.z1 + i * self.z2)
+    }
+
+    /// Squared Euclidean norm of the four real components
+    pub fn norm_sqr(&self) -> f64 {
+        self.z1.norm_sqr() + self.z2.norm_sqr()
+    }
+
+    /// Euclidean norm of the four real components
+    pub fn norm(&self) -> f64 {
+        self.norm_sqr().sqrt()
+    }
+
+    /// A formula evaluated at bicomplex `z` and `c`, one idempotent coordinate at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula
+    /// * `z` - The current value in the iteration
+    /// * `c` - The parameter
+    ///
+    /// # Returns
+    ///
+    /// The value of the formula, or the first evaluation error
+    pub fn eval_formula(formula: &CompiledFormula, z: Bicomplex, c: Bicomplex) -> Result<Self, FractalError> {
+        let ((z_1, z_2), (c_1, c_2)) = (z.to_idempotent(), c.to_idempotent());
+        Ok(Self::from_idempotent(formula.try_eval(z_1, c_1)?, formula.try_eval(z_2, c_2)?))
+    }
+}
+
+impl From<Complex<f64>> for Bicomplex {
+    fn from(z: Complex<f64>) -> Self {
+        Self::new(z, Complex::new(0.0, 0.0))
+    }
+}
+
+/// The same four components, re, i, j and k
+impl From<Quaternion> for Bicomplex {
+    fn from(q: Quaternion) -> Self {
+        Self::new(Complex::new(q.re, q.i), Complex::new(q.j, q.k))
+    }
+}
+
+impl From<Bicomplex> for Quaternion {
+    fn from(x: Bicomplex) -> Self {
+        Quaternion::new(x.z1.re, x.z1.im, x.z2.re, x.z2.im)
+    }
+}
+
+impl Add for Bicomplex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.z1 + other.z1, self.z2 + other.z2)
+    }
+}
+
+impl Sub for Bicomplex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.z1 - other.z1, self.z2 - other.z2)
+    }
+}
+
+/// `(a + b·j)(c + d·j) = (ac - bd) + (ad + bc)·j`; commutative
+impl Mul for Bicomplex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(self.z1 * other.z1 - self.z2 * other.z2, self.z1 * other.z2 + self.z2 * other.z1)
+    }
+}
+
+/// Iterate a formula over the bicomplex numbers from `z`
+///
+/// Formulas that fail to evaluate fall back to `z^2 + c`, as in the complex renderers.
+///
+/// # Arguments
+///
+/// * `formula` - The formula
+/// * `z` - The starting point
+/// * `c` - The parameter
+/// * `max_iterations` - Maximum number of iterations
+/// * `bailout` - Escape radius, for the norm of all four components
+///
+/// # Returns
+///
+/// The iteration result, with the norm of the last value as a real `final_z` for smooth coloring
+pub fn bicomplex_result(formula: &CompiledFormula, mut z: Bicomplex, c: Bicomplex, max_iterations: u32, bailout: f64) -> IterationResult {
+    formula.start_orbit();
+    let mut iter = 0;
+    while iter < max_iterations {
+        z = Bicomplex::eval_formula(formula, z, c).unwrap_or_else(|_| z * z + c);
+        if z.norm_sqr() > bailout * bailout {
+            break;
+        }
+        iter += 1;
+    }
+    IterationResult { iterations: iter, escaped: iter < max_iterations, final_z: Complex::new(z.norm(), 0.0), period: None, multiplier: None }
+}
+
+/// Render a 2D slice of a bicomplex Mandelbrot or Julia set
+///
+/// `params.bounds` is the region of the slice's plane, with `params.formula`,
+/// `params.max_iterations`, `params.bailout` and the coloring options used as for complex
+/// renders.
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Formula, bounds, iteration and coloring parameters
+/// * `julia` - The constant c of a Julia set, or `None` for the Mandelbrot set (the Tetrabrot
+///   for `z^2 + c`), iterated from 0 with the point of the slice as c
+/// * `slice` - The plane to render
+/// * `color_palette` - Optional color palette
+///
+/// # Returns
+///
+/// The rendered image
+pub fn generate_bicomplex_image(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: Option<Bicomplex>,
+    slice: &QuaternionSlice,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let formula = CompiledFormula::new(&params.formula);
+    generate_fractal_image_from_results(
+        width,
+        height,
+        params,
+        |point, p| {
+            let point = Bicomplex::from(slice.point(point));
+            match julia {
+                Some(c) => bicomplex_result(&formula, point, c, p.max_iterations, p.bailout),
+                None => bicomplex_result(&formula, Bicomplex::default(), point, p.max_iterations, p.bailout),
+            }
+        },
+        color_palette,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bicomplex_slices() {
+        let (i, j, k) = (
+            Bicomplex::from(Quaternion::new(0.0, 1.0, 0.0, 0.0)),
+            Bicomplex::from(Quaternion::new(0.0, 0.0, 1.0, 0.0)),
+            Bicomplex::from(Quaternion::new(0.0, 0.0, 0.0, 1.0)),
+        );
+        let one = Bicomplex::from(Complex::new(1.0, 0.0));
+        assert_eq!(i * j, k);
+        assert_eq!(j * i, k);
+        assert_eq!(j * j, Bicomplex::from(Complex::new(-1.0, 0.0)));
+        assert_eq!(k * k, one);
+
+        // Formulas evaluated through the idempotent coordinates agree with the arithmetic
+        let formula = CompiledFormula::new("z^3 - 2*z*c + c");
+        let z = Bicomplex::new(Complex::new(0.3, -0.2), Complex::new(0.1, 0.4));
+        let c = Bicomplex::new(Complex::new(-0.5, 0.1), Complex::new(0.2, -0.3));
+        let expected = z * z * z - Bicomplex::from(Complex::new(2.0, 0.0)) * z * c + c;
+        let value = Bicomplex::eval_formula(&formula, z, c).unwrap();
+        assert!((value - expected).norm() < 1e-12, "{:?} vs {:?}", value, expected);
+
+        // The re,i slice of the Tetrabrot is the Mandelbrot set
+        let formula = CompiledFormula::new("z^2 + c");
+        let slice = QuaternionSlice::parse("re,i", "0,0,0,0").unwrap();
+        for c in [Complex::new(-0.2, 0.6), Complex::new(0.3, 0.5), Complex::new(-1.9, 0.05)] {
+            let mut z = Complex::new(0.0, 0.0);
+            let mut iterations = 0;
+            while iterations < 200 {
+                z = z * z + c;
+                if z.norm_sqr() > 4.0 {
+                    break;
+                }
+                iterations += 1;
+            }
+            let result = bicomplex_result(&formula, Bicomplex::default(), slice.point(c).into(), 200, 2.0);
+            assert_eq!(result.iterations, iterations);
+        }
+    }
+}
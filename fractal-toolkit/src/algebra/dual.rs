@@ -0,0 +1,165 @@
+//! Dual numbers over the complex numbers, for automatic derivatives
+//!
+//! A [`Dual`] is v + d*ε with ε² = 0: the algebra of `CustomAlgebra::dual`, but with complex
+//! components. Because ε² = 0, f(v + d*ε) = f(v) + f'(v) d*ε for any holomorphic f, so carrying
+//! the derivative part through the arithmetic of a formula differentiates it exactly, with no
+//! symbolic work and no step size. Seeding c with d = 1 (and z₀ with d = 0) gives dz/dc along a
+//! Mandelbrot orbit; seeding z₀ with d = 1 gives dz/dz₀ for a Julia set.
+
+use num_complex::Complex;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A value with the derivative of the value carried along, v + d*ε with ε² = 0
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dual {
+    /// v, the value
+    pub value: Complex<f64>,
+    /// d, the derivative of the value with respect to the seeded variable
+    pub derivative: Complex<f64>,
+}
+
+impl Dual {
+    /// v + d*ε
+    pub fn new(value: Complex<f64>, derivative: Complex<f64>) -> Self {
+        Self { value, derivative }
+    }
+
+    /// A value that doesn't depend on the seeded variable (d = 0)
+    pub fn constant(value: Complex<f64>) -> Self {
+        Self::new(value, Complex::new(0.0, 0.0))
+    }
+
+    /// The seeded variable itself (d = 1)
+    pub fn variable(value: Complex<f64>) -> Self {
+        Self::new(value, Complex::new(1.0, 0.0))
+    }
+
+    /// f(self) from the value f(v) and the slope f'(v), by the chain rule
+    pub fn chain(self, value: Complex<f64>, slope: Complex<f64>) -> Self {
+        Self::new(value, slope * self.derivative)
+    }
+
+    /// The `n`-th power
+    pub fn powi(self, n: i32) -> Self {
+        match n {
+            0 => Self::constant(Complex::new(1.0, 0.0)),
+            _ => self.chain(self.value.powi(n), self.value.powi(n - 1) * n as f64),
+        }
+    }
+
+    /// The principal power with a complex exponent
+    pub fn powc(self, exponent: Dual) -> Self {
+        let value = self.value.powc(exponent.value);
+        let derivative = value * (exponent.value * self.derivative / self.value + self.value.ln() * exponent.derivative);
+        Self::new(value, derivative)
+    }
+
+    /// e^self
+    pub fn exp(self) -> Self {
+        let value = self.value.exp();
+        self.chain(value, value)
+    }
+
+    /// The principal logarithm
+    pub fn ln(self) -> Self {
+        self.chain(self.value.ln(), self.value.inv())
+    }
+
+    /// The principal square root
+    pub fn sqrt(self) -> Self {
+        let value = self.value.sqrt();
+        self.chain(value, (value * 2.0).inv())
+    }
+
+    /// The sine
+    pub fn sin(self) -> Self {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    /// The cosine
+    pub fn cos(self) -> Self {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    /// The tangent
+    pub fn tan(self) -> Self {
+        let value = self.value.tan();
+        self.chain(value, value * value + 1.0)
+    }
+
+    /// The hyperbolic sine
+    pub fn sinh(self) -> Self {
+        self.chain(self.value.sinh(), self.value.cosh())
+    }
+
+    /// The hyperbolic cosine
+    pub fn cosh(self) -> Self {
+        self.chain(self.value.cosh(), self.value.sinh())
+    }
+
+    /// The hyperbolic tangent
+    pub fn tanh(self) -> Self {
+        let value = self.value.tanh();
+        self.chain(value, -(value * value) + 1.0)
+    }
+}
+
+impl From<Complex<f64>> for Dual {
+    fn from(value: Complex<f64>) -> Self {
+        Dual::constant(value)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, other: Dual) -> Dual {
+        Dual::new(self.value + other.value, self.derivative + other.derivative)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, other: Dual) -> Dual {
+        Dual::new(self.value - other.value, self.derivative - other.derivative)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, other: Dual) -> Dual {
+        Dual::new(self.value * other.value, self.derivative * other.value + self.value * other.derivative)
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, other: Dual) -> Dual {
+        let value = self.value / other.value;
+        Dual::new(value, (self.derivative - value * other.derivative) / other.value)
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual::new(-self.value, -self.derivative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dual_arithmetic_differentiates() {
+        // f(x) = sin(x) * x^3 / exp(x), f'(x) = f(x) * (cot(x) + 3/x - 1)
+        let x = Complex::new(0.7, -0.4);
+        let f = Dual::variable(x).sin() * Dual::variable(x).powi(3) / Dual::variable(x).exp();
+        let expected = f.value * (x.cos() / x.sin() + 3.0 / x - 1.0);
+        assert!((f.derivative - expected).norm() < 1e-12, "{} vs {}", f.derivative, expected);
+
+        let root = Dual::variable(x).sqrt() * Dual::variable(x).sqrt();
+        assert!((root.derivative - 1.0).norm() < 1e-12);
+        assert_eq!(Dual::constant(x).ln().derivative, Complex::new(0.0, 0.0));
+    }
+}
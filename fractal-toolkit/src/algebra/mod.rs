@@ -0,0 +1,252 @@
+//! Number systems the escape-time iterations run in
+//!
+//! Formulas are iterated in the standard complex numbers by default, or in the 2D real algebra
+//! where i² takes a custom value (`CustomComplex`), such as the split complex numbers (i² = 1)
+//! or the dual numbers (i² = 0). [`Algebra`] abstracts over the number system: it moves points
+//! of the plane in and out of its element type and takes one step of a formula, and the
+//! iteration loop ([`iterate`]) is written once, generic over it, instead of once per system.
+//!
+//! Renderers pick the system at run time from `i_sqrt_value` with [`NumberSystem::for_params`],
+//! which dispatches to the monomorphized loop of each algebra. Points always enter and leave an
+//! algebra as `Complex<f64>` holding its two components, so the bailout, the cycle detection and
+//! the orbits drawn by the Buddhabrot are the same for every system.
+//!
+//! [`Dual`] numbers with complex components carry first derivatives through the arithmetic of a
+//! formula, for distance estimation and derivative-based coloring.
+//!
+//! [`Bicomplex`] numbers, pairs of complex numbers with a second imaginary unit j, extend
+//! iteration to a four-dimensional commutative algebra, rendered as 2D slices like the
+//! quaternion Julia sets; the Mandelbrot set of `z^2 + c` there is the Tetrabrot.
+//!
+//! [`audit_identities`] tests which algebraic identities a custom i² keeps, such as inverses
+//! and the absence of zero divisors, and reports where the ones that fail break down.
+
+pub mod audit;
+pub mod bicomplex;
+pub mod dual;
+
+pub use audit::{audit_identities, Identity, IdentityCheck, IdentityReport, SystemKind};
+pub use bicomplex::{bicomplex_result, generate_bicomplex_image, Bicomplex};
+pub use dual::Dual;
+
+use crate::iteration::{CycleDetector, InteriorColoring, IterationResult};
+use crate::{analysis, CompiledFormula, CustomComplex, FractalParams};
+use num_complex::Complex;
+
+/// A number system formulas can be iterated in
+pub trait Algebra {
+    /// An element of the number system
+    type Element: Copy;
+
+    /// Whether one step of a formula is holomorphic in the standard coordinates, so that the
+    /// complex derivative of a cycle (its multiplier) is meaningful
+    const HOLOMORPHIC: bool;
+
+    /// The element with the components of a point of the plane
+    fn embed(&self, z: Complex<f64>) -> Self::Element;
+
+    /// The point of the plane with the components of an element
+    fn project(&self, x: Self::Element) -> Complex<f64>;
+
+    /// One step of a formula, falling back to z^2 + c where it can't be evaluated
+    fn step(&self, formula: &CompiledFormula, z: Self::Element, c: Self::Element) -> Self::Element;
+}
+
+/// The standard complex numbers, i² = -1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StandardComplex;
+
+impl Algebra for StandardComplex {
+    type Element = Complex<f64>;
+    const HOLOMORPHIC: bool = true;
+
+    fn embed(&self, z: Complex<f64>) -> Complex<f64> {
+        z
+    }
+
+    fn project(&self, x: Complex<f64>) -> Complex<f64> {
+        x
+    }
+
+    fn step(&self, formula: &CompiledFormula, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        formula.eval(z, c)
+    }
+}
+
+/// The number system where i² takes a custom value, with `CustomComplex` elements
+///
+/// Formulas with real coefficients (`CompiledFormula::has_real_coefficients`) are evaluated in
+/// the system itself (`CustomComplex::eval_formula`). The others use literals or functions with
+/// no counterpart outside the standard complex numbers, and are iterated as z^2 + c instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CustomAlgebra {
+    i_squared: Complex<f64>,
+    native: bool,
+}
+
+impl CustomAlgebra {
+    /// The system with the given i², for iterating `formula`
+    ///
+    /// # Arguments
+    ///
+    /// * `i_squared` - The value of i², read as re + im*i
+    /// * `formula` - The formula that will be iterated
+    pub fn new(i_squared: Complex<f64>, formula: &CompiledFormula) -> Self {
+        Self { i_squared, native: formula.has_real_coefficients() }
+    }
+
+    /// The split complex (hyperbolic) numbers, i² = 1
+    pub fn split_complex(formula: &CompiledFormula) -> Self {
+        Self::new(Complex::new(1.0, 0.0), formula)
+    }
+
+    /// The dual numbers, i² = 0
+    pub fn dual(formula: &CompiledFormula) -> Self {
+        Self::new(Complex::new(0.0, 0.0), formula)
+    }
+
+    /// The value of i² in this system
+    pub fn i_squared(&self) -> Complex<f64> {
+        self.i_squared
+    }
+
+    /// Whether the formula is evaluated in this system rather than replaced by z^2 + c
+    pub fn is_native(&self) -> bool {
+        self.native
+    }
+}
+
+impl Algebra for CustomAlgebra {
+    type Element = CustomComplex;
+    const HOLOMORPHIC: bool = false;
+
+    fn embed(&self, z: Complex<f64>) -> CustomComplex {
+        CustomComplex::from_standard(z, self.i_squared)
+    }
+
+    fn project(&self, x: CustomComplex) -> Complex<f64> {
+        x.to_standard()
+    }
+
+    fn step(&self, formula: &CompiledFormula, z: CustomComplex, c: CustomComplex) -> CustomComplex {
+        match self.native {
+            true => CustomComplex::eval_formula(formula, &z, &c).unwrap_or_else(|_| z * z + c),
+            false => z * z + c,
+        }
+    }
+}
+
+/// The number system of a render, chosen at run time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberSystem {
+    /// The standard complex numbers
+    Standard(StandardComplex),
+    /// A custom value of i²
+    Custom(CustomAlgebra),
+}
+
+impl NumberSystem {
+    /// The number system selected by an `i_sqrt_value` parameter
+    ///
+    /// The default `i_sqrt_value` of i (the standard imaginary unit) selects the standard complex
+    /// numbers; any other value is taken as the value of i².
+    ///
+    /// # Arguments
+    ///
+    /// * `i_sqrt_value` - `FractalParams::i_sqrt_value` or its Buddhabrot counterpart
+    /// * `formula` - The formula that will be iterated
+    pub fn for_params(i_sqrt_value: Complex<f64>, formula: &CompiledFormula) -> Self {
+        if i_sqrt_value == Complex::new(0.0, 1.0) {
+            NumberSystem::Standard(StandardComplex)
+        } else {
+            NumberSystem::Custom(CustomAlgebra::new(i_sqrt_value, formula))
+        }
+    }
+
+    /// One step of a formula in this system, on points of the plane
+    pub fn step(&self, formula: &CompiledFormula, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match self {
+            NumberSystem::Standard(algebra) => algebra.step(formula, z, c),
+            NumberSystem::Custom(algebra) => algebra.project(algebra.step(formula, algebra.embed(z), algebra.embed(c))),
+        }
+    }
+
+    /// `iterate` in this system
+    pub fn iterate(&self, formula: &CompiledFormula, z: Complex<f64>, c: Complex<f64>, params: &FractalParams) -> IterationResult {
+        match self {
+            NumberSystem::Standard(algebra) => iterate(algebra, formula, z, c, params),
+            NumberSystem::Custom(algebra) => iterate(algebra, formula, z, c, params),
+        }
+    }
+}
+
+/// Iterate a formula from `z` until it escapes, falls into a cycle or runs out of iterations
+///
+/// # Arguments
+///
+/// * `algebra` - The number system
+/// * `formula` - `params.formula`, compiled
+/// * `z` - The starting point
+/// * `c` - The parameter (the pixel for Mandelbrot sets, the spawn point for Julia sets)
+/// * `params` - Fractal parameters (max_iterations, bailout, interior_coloring)
+///
+/// # Returns
+///
+/// The iteration count and the value where iteration stopped, with the period of a detected
+/// cycle and, for holomorphic algebras with multiplier coloring, its multiplier
+pub fn iterate<A: Algebra>(algebra: &A, formula: &CompiledFormula, z: Complex<f64>, c: Complex<f64>, params: &FractalParams) -> IterationResult {
+    formula.start_orbit();
+    let c = algebra.embed(c);
+    let mut z = algebra.embed(z);
+    let mut iter = 0;
+    let mut cycle = CycleDetector::new(algebra.project(z));
+    let mut period = None;
+    let mut multiplier = None;
+
+    while iter < params.max_iterations {
+        z = algebra.step(formula, z, c);
+        let point = algebra.project(z);
+
+        if point.norm_sqr() > params.bailout * params.bailout {
+            break;
+        }
+        iter += 1;
+        // An orbit that repeats itself stays bounded, so there is no need to iterate on
+        if let Some(found) = cycle.check(point) {
+            period = Some(found);
+            iter = params.max_iterations;
+            if A::HOLOMORPHIC && params.interior_coloring == InteriorColoring::Multiplier {
+                let step = |w| algebra.project(algebra.step(formula, algebra.embed(w), c));
+                multiplier = analysis::orbit_class::cycle_multiplier(step, point, found);
+            }
+        }
+    }
+
+    IterationResult { iterations: iter, escaped: iter < params.max_iterations, final_z: algebra.project(z), period, multiplier }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systems_share_the_iteration_loop() {
+        let formula = CompiledFormula::new("z^2 + c");
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 100, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let c = Complex::new(-0.2, 0.6);
+
+        // i² = -1 as a custom value iterates like the standard complex numbers
+        let standard = NumberSystem::for_params(Complex::new(0.0, 1.0), &formula).iterate(&formula, Complex::new(0.0, 0.0), c, &params);
+        let custom = NumberSystem::for_params(Complex::new(-1.0, 0.0), &formula).iterate(&formula, Complex::new(0.0, 0.0), c, &params);
+        assert_eq!((standard.iterations, standard.escaped), (custom.iterations, custom.escaped));
+        assert!((standard.final_z - custom.final_z).norm() < 1e-9);
+
+        // In the dual numbers z^2 + c is re^2 + c.re and 2*re*im + c.im, which escapes for c.re = 0.5
+        let dual = CustomAlgebra::dual(&formula);
+        assert!(dual.is_native());
+        let escaped = iterate(&dual, &formula, Complex::new(0.0, 0.0), Complex::new(0.5, 0.0), &params);
+        assert!(escaped.escaped);
+        let step = NumberSystem::Custom(dual).step(&formula, Complex::new(0.5, 1.0), Complex::new(0.1, 0.2));
+        assert!((step - Complex::new(0.35, 1.2)).norm() < 1e-9, "{}", step);
+    }
+}
@@ -0,0 +1,209 @@
+//! One-dimensional profiles along a line segment in the complex plane
+//!
+//! An image shows where a formula escapes quickly or slowly, but not by how much. Sampling
+//! along a segment instead gives exact numbers that can be plotted or compared: the iteration
+//! counts across a filament, the jump of the smooth iteration count at a band edge, or the
+//! modulus and phase of the formula itself near a branch cut.
+
+use crate::{escape_degree, julia_result_compiled, mandelbrot_result_compiled, CompiledFormula, FractalError, FractalParams};
+use image::{Rgb, RgbImage};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+/// What is computed at each point of a cross-section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    /// Iterate the formula from z = 0 with the point as c
+    Mandelbrot,
+    /// Iterate the formula from the point with `params.spawn` as c
+    Julia,
+    /// Evaluate the formula once with the point as z and `params.spawn` as c
+    Function,
+}
+
+/// Scalar plotted from a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileQuantity {
+    /// Integer iteration count
+    Iterations,
+    /// Fractional iteration count
+    SmoothIterations,
+    /// Modulus of the value
+    Modulus,
+    /// Argument of the value in radians
+    Argument,
+    /// Real part of the value
+    Real,
+    /// Imaginary part of the value
+    Imaginary,
+}
+
+/// One point of a cross-section
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileSample {
+    /// Position along the segment, from 0 at the start to 1 at the end
+    pub t: f64,
+    /// The sampled point
+    pub point: Complex<f64>,
+    /// Iterations before escape (the iteration limit if bounded, 0 in `Function` mode)
+    pub iterations: u32,
+    /// Fractional iteration count for smooth coloring (0 in `Function` mode)
+    pub smooth_iterations: f64,
+    /// Last orbit point when iterating, or the formula's value in `Function` mode (NaN if the
+    /// formula can't be evaluated there)
+    pub value: Complex<f64>,
+}
+
+impl ProfileSample {
+    /// The given quantity of this sample
+    pub fn quantity(&self, quantity: ProfileQuantity) -> f64 {
+        match quantity {
+            ProfileQuantity::Iterations => self.iterations as f64,
+            ProfileQuantity::SmoothIterations => self.smooth_iterations,
+            ProfileQuantity::Modulus => self.value.norm(),
+            ProfileQuantity::Argument => self.value.arg(),
+            ProfileQuantity::Real => self.value.re,
+            ProfileQuantity::Imaginary => self.value.im,
+        }
+    }
+}
+
+/// Sample a formula at evenly spaced points of a line segment
+///
+/// # Arguments
+///
+/// * `start` - First point of the segment
+/// * `end` - Last point of the segment
+/// * `samples` - Number of points, including both ends (at least 2)
+/// * `params` - Formula, iteration limit, bailout and spawn point
+/// * `mode` - Whether to iterate as a Mandelbrot or Julia set or evaluate the formula once
+///
+/// # Returns
+///
+/// The samples in order from `start` to `end`
+pub fn cross_section(
+    start: Complex<f64>,
+    end: Complex<f64>,
+    samples: usize,
+    params: &FractalParams,
+    mode: TraceMode,
+) -> Vec<ProfileSample> {
+    let samples = samples.max(2);
+    let formula = CompiledFormula::new(&params.formula);
+    let degree = escape_degree(&params.formula);
+
+    (0..samples)
+        .into_par_iter()
+        .map(|index| {
+            let t = index as f64 / (samples - 1) as f64;
+            let point = start + (end - start) * t;
+            let result = match mode {
+                TraceMode::Mandelbrot => mandelbrot_result_compiled(point, params, &formula),
+                TraceMode::Julia => julia_result_compiled(point, params, &formula),
+                TraceMode::Function => {
+                    let value = formula.try_eval(point, params.spawn).unwrap_or(Complex::new(f64::NAN, f64::NAN));
+                    return ProfileSample { t, point, iterations: 0, smooth_iterations: 0.0, value };
+                }
+            };
+            ProfileSample {
+                t,
+                point,
+                iterations: result.iterations,
+                smooth_iterations: result.smooth_iterations(degree),
+                value: result.final_z,
+            }
+        })
+        .collect()
+}
+
+/// Write a profile as CSV with one row per sample
+pub fn write_profile_csv(path: &str, profile: &[ProfileSample]) -> Result<(), FractalError> {
+    let mut csv = String::from("t,re,im,iterations,smooth_iterations,value_re,value_im\n");
+    for sample in profile {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            sample.t, sample.point.re, sample.point.im, sample.iterations, sample.smooth_iterations, sample.value.re, sample.value.im
+        ));
+    }
+    crate::output::write_atomic(path, csv.as_bytes())
+}
+
+/// Draw a simple line plot of one quantity of a profile
+///
+/// The vertical axis spans the range of the finite values, with a grey line at zero when the
+/// range includes it. Non-finite values leave a gap in the line.
+///
+/// # Arguments
+///
+/// * `profile` - Samples from `cross_section`
+/// * `quantity` - Value to plot against position along the segment
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+///
+/// # Returns
+///
+/// The plot, dark blue on white
+pub fn plot_profile(profile: &[ProfileSample], quantity: ProfileQuantity, width: u32, height: u32) -> RgbImage {
+    let mut image = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+    let values: Vec<f64> = profile.iter().map(|sample| sample.quantity(quantity)).collect();
+    let (low, high) = values
+        .iter()
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &value| (low.min(value), high.max(value)));
+    if profile.len() < 2 || width < 2 || height < 2 || low > high {
+        return image;
+    }
+    let span = if high > low { high - low } else { 1.0 };
+    let to_y = |value: f64| (height - 1) as f64 * (1.0 - (value - low) / span);
+
+    if low < 0.0 && high > 0.0 {
+        let zero = to_y(0.0).round() as u32;
+        for x in 0..width {
+            image.put_pixel(x, zero, Rgb([200, 200, 200]));
+        }
+    }
+
+    let points: Vec<Option<(f64, f64)>> = profile
+        .iter()
+        .zip(&values)
+        .map(|(sample, &value)| value.is_finite().then(|| (sample.t * (width - 1) as f64, to_y(value))))
+        .collect();
+    for pair in points.windows(2) {
+        if let [Some((x0, y0)), Some((x1, y1))] = *pair {
+            // Enough steps that consecutive dots touch
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0) as u32;
+            for step in 0..=steps {
+                let f = step as f64 / steps as f64;
+                let (x, y) = ((x0 + (x1 - x0) * f).round() as u32, (y0 + (y1 - y0) * f).round() as u32);
+                image.put_pixel(x.min(width - 1), y.min(height - 1), Rgb([20, 40, 140]));
+            }
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cross_section_along_real_axis() {
+        let params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 100, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let profile = cross_section(Complex::new(-2.5, 0.0), Complex::new(1.0, 0.0), 36, &params, TraceMode::Mandelbrot);
+        assert_eq!(profile.len(), 36);
+        assert_eq!(profile[35].point, Complex::new(1.0, 0.0));
+
+        // The real axis is inside the set exactly on [-2, 1/4]
+        for sample in &profile {
+            let inside = sample.iterations == 100;
+            assert_eq!(inside, (-2.0..=0.25).contains(&sample.point.re), "{:?}", sample.point);
+        }
+
+        let values = cross_section(Complex::new(0.0, 0.0), Complex::new(0.0, 2.0), 3, &params, TraceMode::Function);
+        assert_eq!(values[1].quantity(ProfileQuantity::Real), -1.0);
+
+        let plot = plot_profile(&profile, ProfileQuantity::Iterations, 64, 32);
+        assert_eq!(plot.dimensions(), (64, 32));
+        assert!(plot.pixels().any(|pixel| pixel.0 == [20, 40, 140]));
+    }
+}
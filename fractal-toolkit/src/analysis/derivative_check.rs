@@ -0,0 +1,257 @@
+//! Numerical checks of formula derivatives
+//!
+//! Distance estimation and Lyapunov exponents chain the derivative of the formula along the
+//! orbit, treating it as a single complex number `f'(z)`. That only makes sense where the
+//! formula is holomorphic (complex differentiable) and smooth at the scale of a pixel. Functions
+//! restricted to real arguments such as `gamma`, non-standard imaginary units, and orbits that
+//! cross the branch cut of `log`, `sqrt` or `^` break this and give garbage distance estimates
+//! without any error.
+//!
+//! For each test point the derivative is estimated three ways:
+//!
+//! - a complex-step (Lyness) estimate, `f'(z) ≈ 1/(N h) Σ f(z + h ω^k) ω^-k` with `ω = e^(2πi/N)`,
+//!   which is accurate to high order in `h` for holomorphic functions and has no cancellation
+//!   problem, computed at two step sizes
+//! - central differences along the real and along the imaginary direction, which agree only if
+//!   the Cauchy-Riemann equations hold
+//!
+//! and, when given, compared against a reference derivative such as one from automatic
+//! differentiation. Points where the estimates disagree are counted by kind in a
+//! `DerivativeReport`.
+
+use crate::{CompiledFormula, FractalError};
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Points on the contour of the complex-step estimate
+const CONTOUR_POINTS: usize = 8;
+
+/// Relative disagreement above which two derivative estimates count as different
+const TOLERANCE: f64 = 1e-4;
+
+/// Derivative of a formula with respect to z and c, for reference
+pub type DerivativeFn<'a> = dyn Fn(Complex<f64>, Complex<f64>) -> (Complex<f64>, Complex<f64>) + Sync + 'a;
+
+/// Estimates of the derivative of a function at one point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DerivativeEstimate {
+    /// Complex-step estimate with step `h`
+    pub complex_step: Complex<f64>,
+    /// Complex-step estimate with step `h / 4`, to check the estimate has settled
+    pub complex_step_fine: Complex<f64>,
+    /// Central difference along the real direction
+    pub along_real: Complex<f64>,
+    /// Central difference along the imaginary direction
+    pub along_imaginary: Complex<f64>,
+}
+
+/// How the derivative estimates at a point disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DerivativeIssue {
+    /// The function isn't finite or fails to evaluate near the point
+    Undefined,
+    /// The real and imaginary directional derivatives differ: the function isn't holomorphic
+    NotHolomorphic,
+    /// The estimate changes with the step size: a singularity, branch cut or discontinuity is
+    /// within a step of the point
+    Unstable,
+    /// The estimates agree with each other but not with the reference derivative
+    ReferenceMismatch,
+}
+
+/// Outcome of checking a formula's derivatives at many points
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivativeReport {
+    /// The checked formula
+    pub formula: String,
+    /// Number of (z, c) points checked
+    pub points: usize,
+    /// Issues found with respect to z, with the point (z, c) where each occurred
+    pub z_issues: Vec<(Complex<f64>, Complex<f64>, DerivativeIssue)>,
+    /// Issues found with respect to c
+    pub c_issues: Vec<(Complex<f64>, Complex<f64>, DerivativeIssue)>,
+}
+
+impl DerivativeReport {
+    /// Number of issues of one kind, with respect to z and c together
+    pub fn count(&self, issue: DerivativeIssue) -> usize {
+        self.z_issues.iter().chain(&self.c_issues).filter(|(_, _, found)| *found == issue).count()
+    }
+
+    /// Whether derivative-based features can be trusted: no point showed an issue other than
+    /// an isolated singularity (at most 1% of points unstable or undefined)
+    pub fn is_reliable(&self) -> bool {
+        let isolated = self.count(DerivativeIssue::Unstable) + self.count(DerivativeIssue::Undefined);
+        self.count(DerivativeIssue::NotHolomorphic) == 0
+            && self.count(DerivativeIssue::ReferenceMismatch) == 0
+            && isolated * 100 <= self.points
+    }
+}
+
+impl fmt::Display for DerivativeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Derivative check of '{}' at {} points:", self.formula, self.points)?;
+        for issue in [
+            DerivativeIssue::NotHolomorphic,
+            DerivativeIssue::Unstable,
+            DerivativeIssue::Undefined,
+            DerivativeIssue::ReferenceMismatch,
+        ] {
+            let count = self.count(issue);
+            if count > 0 {
+                let (z, c, _) = self.z_issues.iter().chain(&self.c_issues).find(|(_, _, found)| *found == issue).unwrap();
+                writeln!(f, "  {:?}: {} (first at z = {}, c = {})", issue, count, z, c)?;
+            }
+        }
+        if self.is_reliable() {
+            write!(f, "  Derivatives are reliable; distance estimation and Lyapunov exponents can be used")
+        } else {
+            write!(f, "  Derivatives are unreliable; distance estimation and Lyapunov exponents will be wrong")
+        }
+    }
+}
+
+/// Estimate the derivative of a function at a point
+///
+/// # Arguments
+///
+/// * `function` - The function to differentiate
+/// * `point` - Where to differentiate
+/// * `step` - Step size `h`, relative to `max(1, |point|)`
+///
+/// # Returns
+///
+/// The estimates, or `None` if the function fails or isn't finite at a sampled point
+pub fn estimate_derivative<F>(function: F, point: Complex<f64>, step: f64) -> Option<DerivativeEstimate>
+where
+    F: Fn(Complex<f64>) -> Result<Complex<f64>, FractalError>,
+{
+    let h = step * point.norm().max(1.0);
+    let value = |z: Complex<f64>| function(z).ok().filter(|v| v.is_finite());
+    let contour = |h: f64| -> Option<Complex<f64>> {
+        let mut sum = Complex::new(0.0, 0.0);
+        for k in 0..CONTOUR_POINTS {
+            let omega = Complex::from_polar(1.0, 2.0 * PI * k as f64 / CONTOUR_POINTS as f64);
+            sum += value(point + omega * h)? / omega;
+        }
+        Some(sum / (CONTOUR_POINTS as f64 * h))
+    };
+    let i = Complex::new(0.0, 1.0);
+    Some(DerivativeEstimate {
+        complex_step: contour(h)?,
+        complex_step_fine: contour(h / 4.0)?,
+        along_real: (value(point + h)? - value(point - h)?) / (2.0 * h),
+        along_imaginary: (value(point + i * h)? - value(point - i * h)?) / (2.0 * i * h),
+    })
+}
+
+/// Classify the estimates at a point, returning the most serious disagreement
+fn classify(estimate: Option<DerivativeEstimate>, reference: Option<Complex<f64>>) -> Option<DerivativeIssue> {
+    let Some(estimate) = estimate else { return Some(DerivativeIssue::Undefined) };
+    let differ = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() > TOLERANCE * (a.norm().max(b.norm()).max(1.0));
+    // A cut or jump within a step also breaks Cauchy-Riemann, so look for one first
+    if differ(estimate.complex_step, estimate.complex_step_fine) {
+        Some(DerivativeIssue::Unstable)
+    } else if differ(estimate.along_real, estimate.along_imaginary) {
+        Some(DerivativeIssue::NotHolomorphic)
+    } else if differ(estimate.complex_step, estimate.along_real) {
+        Some(DerivativeIssue::Unstable)
+    } else if reference.is_some_and(|reference| !reference.is_finite() || differ(reference, estimate.complex_step)) {
+        Some(DerivativeIssue::ReferenceMismatch)
+    } else {
+        None
+    }
+}
+
+/// Check the derivatives of a formula with respect to z and c at many points
+///
+/// # Arguments
+///
+/// * `formula` - The formula, as used by the renderers
+/// * `points` - (z, c) pairs to check at
+/// * `reference` - Derivatives (df/dz, df/dc) to compare with, such as from automatic
+///   differentiation; `None` checks the formula's own consistency only
+///
+/// # Returns
+///
+/// The report, or an error if the formula doesn't parse
+pub fn check_formula_derivatives(
+    formula: &str,
+    points: &[(Complex<f64>, Complex<f64>)],
+    reference: Option<&DerivativeFn>,
+) -> Result<DerivativeReport, String> {
+    let compiled = CompiledFormula::new(formula);
+    if let Some(error) = compiled.error() {
+        return Err(error.to_string());
+    }
+
+    let issues: Vec<(Option<DerivativeIssue>, Option<DerivativeIssue>)> = points
+        .par_iter()
+        .map(|&(z, c)| {
+            let reference = reference.map(|derivative| derivative(z, c));
+            let in_z = estimate_derivative(|z| compiled.try_eval(z, c), z, 1e-3);
+            let in_c = estimate_derivative(|c| compiled.try_eval(z, c), c, 1e-3);
+            (classify(in_z, reference.map(|r| r.0)), classify(in_c, reference.map(|r| r.1)))
+        })
+        .collect();
+
+    let mut report = DerivativeReport { formula: formula.to_string(), points: points.len(), z_issues: Vec::new(), c_issues: Vec::new() };
+    for (&(z, c), (in_z, in_c)) in points.iter().zip(issues) {
+        report.z_issues.extend(in_z.map(|issue| (z, c, issue)));
+        report.c_issues.extend(in_c.map(|issue| (z, c, issue)));
+    }
+    Ok(report)
+}
+
+/// A grid of test points with z and c both spread over a rectangle
+///
+/// # Arguments
+///
+/// * `bounds` - [x_min, x_max, y_min, y_max] for both z and c
+/// * `per_axis` - Grid points along each axis of each variable; `per_axis^4` points in total
+pub fn derivative_test_points(bounds: [f64; 4], per_axis: usize) -> Vec<(Complex<f64>, Complex<f64>)> {
+    let [x_min, x_max, y_min, y_max] = bounds;
+    let per_axis = per_axis.max(1);
+    // Offset by half a cell so no point lands on an axis, where branch cuts usually lie
+    let axis = |min: f64, max: f64| -> Vec<f64> {
+        (0..per_axis).map(|i| min + (max - min) * (i as f64 + 0.5) / per_axis as f64).collect()
+    };
+    let grid: Vec<Complex<f64>> = axis(y_min, y_max)
+        .into_iter()
+        .flat_map(|im| axis(x_min, x_max).into_iter().map(move |re| Complex::new(re, im)))
+        .collect();
+    grid.iter().flat_map(|&z| grid.iter().map(move |&c| (z, c))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_non_holomorphic_formulas() {
+        let points = derivative_test_points([-1.5, 1.5, -1.5, 1.5], 3);
+        assert_eq!(points.len(), 81);
+
+        let derivative = |z: Complex<f64>, _c: Complex<f64>| (3.0 * z * z + z.cos(), Complex::new(1.0, 0.0));
+        let report = check_formula_derivatives("z^3 + sin(z) + c", &points, Some(&derivative)).unwrap();
+        assert!(report.is_reliable(), "{}", report);
+        assert_eq!(report.z_issues.len() + report.c_issues.len(), 0);
+
+        // A wrong reference derivative is caught
+        let wrong = |z: Complex<f64>, _c: Complex<f64>| (2.0 * z, Complex::new(1.0, 0.0));
+        let report = check_formula_derivatives("z^3 + c", &points, Some(&wrong)).unwrap();
+        assert!(report.count(DerivativeIssue::ReferenceMismatch) > 0);
+
+        // Crossing the branch cut of sqrt on the negative real axis
+        let near_cut = [(Complex::new(-1.0, 1e-5), Complex::new(0.0, 0.0))];
+        let report = check_formula_derivatives("sqrt(z) + c", &near_cut, None).unwrap();
+        assert_eq!(report.z_issues[0].2, DerivativeIssue::Unstable);
+        assert!(!report.is_reliable());
+
+        // The conjugate is not complex differentiable anywhere
+        let conjugate = estimate_derivative(|z| Ok(z.conj() * z.conj()), Complex::new(0.5, 0.5), 1e-3);
+        assert_eq!(classify(conjugate, None), Some(DerivativeIssue::NotHolomorphic));
+    }
+}
@@ -0,0 +1,154 @@
+//! Distance estimation and slope shading from derivatives along the orbit
+//!
+//! Iterating a formula on dual numbers (`algebra::Dual`) carries the derivative of z with
+//! respect to the pixel along the orbit: dz/dc for Mandelbrot sets, with c seeded as the
+//! variable, and dz/dz₀ for Julia sets. From the final z and its derivative,
+//!
+//! - Milnor's distance estimate `|z| ln|z| / (2 |dz|)` is a lower bound on the distance from an
+//!   escaping point to the set, which draws thin filaments at any zoom
+//! - the direction of `z / dz` is the normal of the potential surface, which lights the
+//!   exterior as relief (slope shading)
+//!
+//! This works for any formula the parser accepts, with no symbolic differentiation.
+
+use crate::algebra::Dual;
+use crate::{CompiledFormula, FractalParams};
+use num_complex::Complex;
+
+/// How `generate_derivative_image` colors a render
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DerivativeColoring {
+    /// By the distance estimate, relative to the pixel size
+    Distance,
+    /// Relief lighting of the potential surface
+    Slope {
+        /// Direction of the light, in radians counterclockwise from the positive real axis
+        light_angle: f64,
+        /// Height of the light above the plane; larger values flatten the relief
+        height: f64,
+    },
+}
+
+impl DerivativeColoring {
+    /// Parse a mode name: `distance` (or `de`) or `slope` (or `normal`), lit from the upper
+    /// right at height 1.5
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "distance" | "de" => Ok(DerivativeColoring::Distance),
+            "slope" | "normal" => Ok(DerivativeColoring::Slope { light_angle: std::f64::consts::FRAC_PI_4, height: 1.5 }),
+            other => Err(format!("Unknown derivative coloring '{}' (expected distance or slope)", other)),
+        }
+    }
+}
+
+/// Where an orbit iterated with its derivative stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DerivativeOrbit {
+    /// Iterations before the orbit escaped, or max_iterations for bounded orbits
+    pub iterations: u32,
+    /// Whether the orbit escaped
+    pub escaped: bool,
+    /// The value of z where iteration stopped
+    pub z: Complex<f64>,
+    /// The derivative of z with respect to the seeded variable
+    pub dz: Complex<f64>,
+}
+
+impl DerivativeOrbit {
+    /// Milnor's distance estimate from an escaped point to the set
+    ///
+    /// # Returns
+    ///
+    /// `|z| ln|z| / (2 |dz|)`, or `None` for bounded orbits and degenerate derivatives
+    pub fn distance(&self) -> Option<f64> {
+        let (norm, slope) = (self.z.norm(), self.dz.norm());
+        let distance = norm * norm.ln() / (2.0 * slope);
+        (self.escaped && norm > 1.0 && distance.is_finite()).then_some(distance)
+    }
+
+    /// Brightness of the potential surface lit by a distant light
+    ///
+    /// # Arguments
+    ///
+    /// * `light_angle` - Direction of the light, in radians
+    /// * `height` - Height of the light above the plane
+    ///
+    /// # Returns
+    ///
+    /// A brightness between 0 and 1, or `None` for bounded orbits and degenerate derivatives
+    pub fn slope(&self, light_angle: f64, height: f64) -> Option<f64> {
+        let normal = self.z / self.dz;
+        let normal = normal / normal.norm();
+        if !(self.escaped && normal.re.is_finite() && normal.im.is_finite()) {
+            return None;
+        }
+        let shade = normal.re * light_angle.cos() + normal.im * light_angle.sin() + height;
+        Some((shade / (1.0 + height)).clamp(0.0, 1.0))
+    }
+}
+
+/// Iterate a formula from `z` with the derivatives of `z` and `c` carried along
+///
+/// Uses `params.bailout` as the escape radius; larger radii give smoother estimates.
+///
+/// # Arguments
+///
+/// * `formula` - `params.formula`, compiled
+/// * `z` - The starting point and its derivative
+/// * `c` - The parameter and its derivative
+/// * `params` - Fractal parameters (max_iterations, bailout)
+///
+/// # Returns
+///
+/// The iteration count, and z with its derivative where iteration stopped
+pub fn iterate_with_derivative(formula: &CompiledFormula, z: Dual, c: Dual, params: &FractalParams) -> DerivativeOrbit {
+    formula.start_orbit();
+    let mut z = z;
+    let mut iterations = 0;
+    while iterations < params.max_iterations {
+        z = formula.eval_dual(z, c);
+        if z.value.norm_sqr() > params.bailout * params.bailout {
+            return DerivativeOrbit { iterations, escaped: true, z: z.value, dz: z.derivative };
+        }
+        iterations += 1;
+    }
+    DerivativeOrbit { iterations, escaped: false, z: z.value, dz: z.derivative }
+}
+
+/// The orbit of 0 under a Mandelbrot formula, with dz/dc
+pub fn mandelbrot_derivative(formula: &CompiledFormula, c: Complex<f64>, params: &FractalParams) -> DerivativeOrbit {
+    iterate_with_derivative(formula, Dual::constant(Complex::new(0.0, 0.0)), Dual::variable(c), params)
+}
+
+/// The orbit of `z` under a Julia formula with parameter `c`, with dz/dz₀
+pub fn julia_derivative(formula: &CompiledFormula, z: Complex<f64>, c: Complex<f64>, params: &FractalParams) -> DerivativeOrbit {
+    iterate_with_derivative(formula, Dual::variable(z), Dual::constant(c), params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_estimate_bounds_the_distance() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 500, [0.0, 0.0], 1000.0, "z^2 + c".to_string());
+        // The Mandelbrot set meets the real axis at 0.25; the estimate is a lower bound, within
+        // about a factor of 4 of the distance
+        for formula in ["z^2 + c", "z*z + c*1"] {
+            let formula = CompiledFormula::new(formula);
+            let distance = mandelbrot_derivative(&formula, Complex::new(0.5, 0.0), &params).distance().unwrap();
+            assert!(distance > 0.25 / 5.0 && distance < 0.25, "{}", distance);
+        }
+        assert_eq!(mandelbrot_derivative(&CompiledFormula::new("z^2 + c"), Complex::new(-0.5, 0.0), &params).distance(), None);
+
+        // The derivative of an analytic formula matches a difference of the orbit
+        let formula = CompiledFormula::new("sin(z) * c + z^3 / (1 + c)");
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 3, [0.0, 0.0], 1e6, String::new());
+        let c = Complex::new(0.3, 0.4);
+        let orbit = julia_derivative(&formula, Complex::new(0.2, -0.1), c, &params);
+        let h = 1e-6;
+        let ahead = julia_derivative(&formula, Complex::new(0.2 + h, -0.1), c, &params).z;
+        let behind = julia_derivative(&formula, Complex::new(0.2 - h, -0.1), c, &params).z;
+        assert!((orbit.dz - (ahead - behind) / (2.0 * h)).norm() < 1e-6 * (1.0 + orbit.dz.norm()));
+    }
+}
@@ -0,0 +1,24 @@
+//! Numerical analysis of fractal parameter space
+//!
+//! Tools in this module study the dynamics behind an image rather than rendering it, for
+//! example locating the exact coordinates of features that are worth zooming into, or sampling
+//! a formula along a line to see exactly how it behaves across a feature, checking whether
+//! its derivatives can be trusted for distance estimation, or classifying the orbit of a point
+//! as escaping, periodic (with the cycle's period and multiplier) or bounded without a cycle, and
+//! estimating distances to the set from derivatives carried along the orbit.
+
+pub mod cross_section;
+pub mod derivative_check;
+pub mod distance;
+pub mod orbit_class;
+pub mod periodic_points;
+
+pub use cross_section::{cross_section, plot_profile, write_profile_csv, ProfileQuantity, ProfileSample, TraceMode};
+pub use derivative_check::{
+    check_formula_derivatives, derivative_test_points, estimate_derivative, DerivativeEstimate, DerivativeIssue, DerivativeReport,
+};
+pub use distance::{iterate_with_derivative, julia_derivative, mandelbrot_derivative, DerivativeColoring, DerivativeOrbit};
+pub use orbit_class::{classify_orbit, cycle_multiplier, Cycle, OrbitClass};
+pub use periodic_points::{
+    find_hyperbolic_center, find_misiurewicz_point, locate_periodic_points, PeriodicPoint, PeriodicPointKind,
+};
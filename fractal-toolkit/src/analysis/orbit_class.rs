@@ -0,0 +1,176 @@
+//! Classification of single orbits
+//!
+//! The renderers only need to know whether a point escaped and when. Studying the dynamics at a
+//! point needs more: whether its orbit settles into a cycle, of which period, and how strongly
+//! the cycle attracts. `classify_orbit` iterates a point like the renderers do, detecting cycles
+//! with `iteration::CycleDetector`, and returns an `OrbitClass`:
+//!
+//! - `Escaping` orbits left the bailout radius
+//! - `Periodic` orbits came back to a previous value; the `Cycle` lists its points and its
+//!   multiplier, the derivative of the p-th iterate along the cycle. The cycle attracts when the
+//!   multiplier lies inside the unit circle, and the multiplier's argument is the internal angle
+//!   of the hyperbolic component
+//! - `Bounded` orbits did neither within `max_iterations`: chaotic orbits, orbits on the boundary
+//!   of the set, and cycles that attract too slowly to be detected (near-parabolic points)
+//!
+//! The multiplier is the product of `f'(z_k)` over the cycle, each estimated with the complex-step
+//! estimate of `derivative_check`, so it works for any holomorphic formula. Renderers compute it
+//! for `InteriorColoring::Multiplier` with `cycle_multiplier`.
+
+use super::derivative_check::estimate_derivative;
+use crate::formula::compiled::compiled_for;
+use crate::iteration::kind::{lambda_to_c, lambda_z_to_w};
+use crate::iteration::CycleDetector;
+use crate::{FractalKind, FractalParams};
+use num_complex::Complex;
+
+/// Step of the derivative estimates, relative to `max(1, |z|)`
+const DERIVATIVE_STEP: f64 = 1e-4;
+
+/// A cycle an orbit settled into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cycle {
+    /// Number of points in the cycle
+    pub period: u32,
+    /// The points of the cycle, in orbit order, starting where it was detected
+    pub points: Vec<Complex<f64>>,
+    /// Derivative of the `period`-th iterate at any point of the cycle
+    pub multiplier: Complex<f64>,
+}
+
+impl Cycle {
+    /// True if nearby orbits converge to the cycle: `|multiplier| < 1`
+    pub fn is_attracting(&self) -> bool {
+        self.multiplier.norm() < 1.0
+    }
+}
+
+/// How an orbit behaves
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrbitClass {
+    /// The orbit left the bailout radius after `iterations` steps, at `final_z`
+    Escaping { iterations: u32, final_z: Complex<f64> },
+    /// The orbit settled into a cycle after `iterations` steps
+    Periodic { iterations: u32, cycle: Cycle },
+    /// The orbit stayed bounded for `max_iterations` steps without repeating
+    Bounded { final_z: Complex<f64> },
+}
+
+impl OrbitClass {
+    /// The cycle of a periodic orbit
+    pub fn cycle(&self) -> Option<&Cycle> {
+        match self {
+            OrbitClass::Periodic { cycle, .. } => Some(cycle),
+            _ => None,
+        }
+    }
+}
+
+/// Classify the orbit of a point
+///
+/// The orbit follows `params.kind`, or `params.formula` with the standard imaginary unit; custom
+/// imaginary units are not supported here.
+///
+/// # Arguments
+///
+/// * `point` - The pixel's value: c, or the starting z when `julia` is set
+/// * `params` - Fractal parameters (formula or kind, max_iterations, bailout, spawn)
+/// * `julia` - Iterate Julia-style from `point` with `params.spawn` as c, instead of from the
+///   critical point with `point` as c
+pub fn classify_orbit(point: Complex<f64>, params: &FractalParams, julia: bool) -> OrbitClass {
+    let formula = compiled_for(&params.formula);
+    let (start, c) = if julia { (point, params.spawn) } else { (params.kind.critical_point(), point) };
+    // Lambda is iterated in the coordinates w = λ(1/2 - z) of its conjugate w^2 + c, like the renderers do
+    let (mut z, c) = match params.kind {
+        FractalKind::Lambda => (lambda_z_to_w(start, c), lambda_to_c(c)),
+        _ => (start, c),
+    };
+    let step = |z: Complex<f64>| match params.kind {
+        FractalKind::Formula => formula.eval(z, c),
+        FractalKind::Lambda => z * z + c,
+        kind => kind.step(z, c),
+    };
+
+    formula.start_orbit();
+    let mut detector = CycleDetector::new(z);
+    for iterations in 0..params.max_iterations {
+        z = step(z);
+        if !z.is_finite() || z.norm_sqr() > params.bailout * params.bailout {
+            return OrbitClass::Escaping { iterations, final_z: z };
+        }
+        if let Some(period) = detector.check(z) {
+            if let Some(cycle) = cycle_at(step, z, period) {
+                return OrbitClass::Periodic { iterations: iterations + 1, cycle };
+            }
+        }
+    }
+    OrbitClass::Bounded { final_z: z }
+}
+
+/// The multiplier of the cycle of period `period` through `start`
+///
+/// # Arguments
+///
+/// * `step` - One iteration of the map, with c fixed
+/// * `start` - A point of the cycle
+/// * `period` - Length of the cycle
+///
+/// # Returns
+///
+/// The derivative of the `period`-th iterate at `start`, or `None` if the map can't be
+/// differentiated along the cycle
+pub fn cycle_multiplier<F>(step: F, start: Complex<f64>, period: u32) -> Option<Complex<f64>>
+where
+    F: Fn(Complex<f64>) -> Complex<f64>,
+{
+    cycle_at(step, start, period).map(|cycle| cycle.multiplier)
+}
+
+fn cycle_at<F>(step: F, start: Complex<f64>, period: u32) -> Option<Cycle>
+where
+    F: Fn(Complex<f64>) -> Complex<f64>,
+{
+    let mut points = Vec::with_capacity(period as usize);
+    let mut multiplier = Complex::new(1.0, 0.0);
+    let mut z = start;
+    for _ in 0..period {
+        points.push(z);
+        let derivative = estimate_derivative(|z| Ok(step(z)), z, DERIVATIVE_STEP)?;
+        multiplier *= derivative.complex_step;
+        z = step(z);
+    }
+    multiplier.is_finite().then_some(Cycle { period, points, multiplier })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_orbits() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 1000, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+
+        // In the main cardioid the fixed point z* attracts with multiplier 2z*
+        let c = Complex::new(-0.2, 0.3);
+        let OrbitClass::Periodic { cycle, .. } = classify_orbit(c, &params, false) else { panic!("not periodic") };
+        let fixed = (1.0 - (1.0 - 4.0 * c).sqrt()) / 2.0;
+        assert_eq!(cycle.period, 1);
+        assert!((cycle.multiplier - 2.0 * fixed).norm() < 1e-6, "{:?}", cycle);
+        assert!(cycle.is_attracting());
+
+        // In the period-2 bulb the 2-cycle attracts with multiplier 4(c + 1)
+        let c = Complex::new(-1.1, 0.05);
+        let cycle = classify_orbit(c, &params, false).cycle().cloned().unwrap();
+        assert_eq!((cycle.period, cycle.points.len()), (2, 2));
+        assert!((cycle.multiplier - 4.0 * (c + 1.0)).norm() < 1e-6, "{:?}", cycle);
+
+        assert!(matches!(classify_orbit(Complex::new(0.5, 0.0), &params, false), OrbitClass::Escaping { .. }));
+        // c = -2 is a Misiurewicz point: 0 -> -2 -> 2 -> 2, a repelling fixed point reached exactly
+        let cycle = classify_orbit(Complex::new(-2.0, 0.0), &params, false).cycle().cloned().unwrap();
+        assert!(!cycle.is_attracting());
+        // At the cusp of the cardioid the fixed point is parabolic and the orbit creeps towards
+        // it too slowly to repeat within the iteration limit
+        let cusp = classify_orbit(Complex::new(0.25, 0.0), &params, false);
+        assert!(matches!(cusp, OrbitClass::Bounded { .. }), "{:?}", cusp);
+    }
+}
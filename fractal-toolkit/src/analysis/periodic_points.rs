@@ -0,0 +1,386 @@
+//! Locating hyperbolic-component centers and Misiurewicz points of the Mandelbrot set
+//!
+//! Both kinds of point are roots of a polynomial in c built from the critical orbit of
+//! `z^2 + c` (the orbit of 0), so they can be found with Newton's method from a nearby guess:
+//!
+//! - a center (nucleus) of period p satisfies `f_c^p(0) = 0`
+//! - a Misiurewicz point with preperiod k and period p satisfies `f_c^(k+p)(0) = f_c^k(0)`
+//!
+//! The derivative with respect to c is carried along the orbit, which is all Newton needs.
+//! The search runs in double precision. Located points also carry their coordinates as decimal
+//! strings for zoom targets: with the `double-double` feature, a few more Newton steps in
+//! double-double arithmetic refine them to about 32 significant digits, enough to center zooms
+//! down to about 1e-30; without it the strings hold the f64 digits, good to about 1e-14.
+
+#[cfg(feature = "double-double")]
+use crate::iteration::DoubleDouble;
+use crate::viewport::Viewport;
+use num_complex::Complex;
+use std::ops::Sub;
+
+/// Maximum number of Newton steps before a search from one starting point is abandoned
+const MAX_NEWTON_STEPS: u32 = 64;
+
+/// Tolerance used when deciding whether two orbit points coincide
+///
+/// Newton's method converges only linearly to the multiple roots that lower periods introduce,
+/// stopping around the square root of machine precision, so this is deliberately loose.
+const ORBIT_TOLERANCE: f64 = 1e-6;
+
+/// Relative distance below which two located points are considered the same
+const DUPLICATE_TOLERANCE: f64 = 1e-9;
+
+/// Newton steps in double-double after the f64 search has converged; each doubles the digits
+#[cfg(feature = "double-double")]
+const REFINE_STEPS: u32 = 4;
+
+/// The kind of feature a located point is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeriodicPointKind {
+    /// The center of a hyperbolic component (a minibrot or bulb), where 0 is periodic
+    Center,
+    /// A Misiurewicz point, where 0 is strictly preperiodic (spiral and branch tips)
+    Misiurewicz,
+}
+
+/// A center or Misiurewicz point found near a requested coordinate
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodicPoint {
+    /// Location of the point in the parameter plane
+    pub c: Complex<f64>,
+    /// Whether this is a component center or a Misiurewicz point
+    pub kind: PeriodicPointKind,
+    /// Period of the critical orbit
+    pub period: u32,
+    /// Number of iterations before the critical orbit becomes periodic (0 for centers)
+    pub preperiod: u32,
+    /// Distance from the coordinate the search started at
+    pub distance: f64,
+    /// Real part of the point in decimal, with every digit the refinement found
+    pub re: String,
+    /// Imaginary part of the point in decimal, with every digit the refinement found
+    pub im: String,
+}
+
+impl PeriodicPoint {
+    /// A view centered exactly on this point at the given magnification
+    pub fn viewport(&self, zoom: f64) -> Viewport {
+        Viewport { center_re: self.re.clone(), center_im: self.im.clone(), zoom, rotation: 0.0 }
+    }
+}
+
+/// The equation a point solves: `f_c^end(0) = f_c^start(0)`, or `f_c^end(0) = 0` for centers
+#[derive(Debug, Clone, Copy)]
+struct Equation {
+    end: usize,
+    start: Option<usize>,
+}
+
+impl Equation {
+    fn center(period: u32) -> Self {
+        Equation { end: period as usize, start: None }
+    }
+
+    fn misiurewicz(preperiod: u32, period: u32) -> Self {
+        Equation { end: (preperiod + period) as usize, start: Some(preperiod as usize) }
+    }
+
+    /// Value and derivative in c of the equation's residual along a critical orbit
+    fn residual<T: Copy + Sub<Output = T>>(&self, orbit: &[(T, T)]) -> (T, T) {
+        match self.start {
+            None => orbit[self.end],
+            Some(start) => (orbit[self.end].0 - orbit[start].0, orbit[self.end].1 - orbit[start].1),
+        }
+    }
+}
+
+/// Iterate the critical orbit of `z^2 + c`, returning every point with its derivative in c
+fn critical_orbit(c: Complex<f64>, length: u32) -> Vec<(Complex<f64>, Complex<f64>)> {
+    let mut orbit = Vec::with_capacity(length as usize + 1);
+    let mut z = Complex::new(0.0, 0.0);
+    let mut dz = Complex::new(0.0, 0.0);
+    orbit.push((z, dz));
+
+    for _ in 0..length {
+        // d/dc (z^2 + c) = 2 z dz + 1
+        dz = 2.0 * z * dz + 1.0;
+        z = z * z + c;
+        orbit.push((z, dz));
+    }
+    orbit
+}
+
+/// Run Newton's method on an equation of the critical orbit
+fn newton(guess: Complex<f64>, equation: Equation) -> Option<Complex<f64>> {
+    let mut c = guess;
+    for _ in 0..MAX_NEWTON_STEPS {
+        let orbit = critical_orbit(c, equation.end as u32);
+        let (value, derivative) = equation.residual(&orbit);
+        if derivative.norm_sqr() == 0.0 || !value.is_finite() {
+            return None;
+        }
+
+        let step = value / derivative;
+        c -= step;
+        if !c.is_finite() {
+            return None;
+        }
+        if step.norm() <= f64::EPSILON * 4.0 * c.norm().max(1.0) {
+            return Some(c);
+        }
+    }
+    None
+}
+
+/// Proper divisors of n (excluding n itself)
+fn proper_divisors(n: u32) -> impl Iterator<Item = u32> {
+    (1..n).filter(move |d| n % d == 0)
+}
+
+/// A complex number in double-double arithmetic, for refining located points
+#[cfg(feature = "double-double")]
+#[derive(Debug, Clone, Copy)]
+struct WideComplex {
+    re: DoubleDouble,
+    im: DoubleDouble,
+}
+
+#[cfg(feature = "double-double")]
+impl WideComplex {
+    fn from_complex(c: Complex<f64>) -> Self {
+        WideComplex { re: DoubleDouble::from(c.re), im: DoubleDouble::from(c.im) }
+    }
+
+    fn real(x: f64) -> Self {
+        WideComplex { re: DoubleDouble::from(x), im: DoubleDouble::from(0.0) }
+    }
+
+    fn add(self, other: Self) -> Self {
+        WideComplex { re: self.re + other.re, im: self.im + other.im }
+    }
+
+    fn mul(self, other: Self) -> Self {
+        WideComplex { re: self.re * other.re - self.im * other.im, im: self.re * other.im + self.im * other.re }
+    }
+
+    fn div(self, other: Self) -> Self {
+        let norm = other.re * other.re + other.im * other.im;
+        WideComplex {
+            re: (self.re * other.re + self.im * other.im) / norm,
+            im: (self.im * other.re - self.re * other.im) / norm,
+        }
+    }
+}
+
+#[cfg(feature = "double-double")]
+impl Sub for WideComplex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        WideComplex { re: self.re - other.re, im: self.im - other.im }
+    }
+}
+
+/// Decimal coordinates of a converged point, refined with Newton steps in double-double
+#[cfg(feature = "double-double")]
+fn decimal_coordinates(c: Complex<f64>, equation: Equation) -> (String, String) {
+    let (zero, one, two) = (WideComplex::real(0.0), WideComplex::real(1.0), WideComplex::real(2.0));
+    let mut c = WideComplex::from_complex(c);
+    for _ in 0..REFINE_STEPS {
+        let mut orbit = Vec::with_capacity(equation.end + 1);
+        let (mut z, mut dz) = (zero, zero);
+        orbit.push((z, dz));
+        for _ in 0..equation.end {
+            dz = two.mul(z).mul(dz).add(one);
+            z = z.mul(z).add(c);
+            orbit.push((z, dz));
+        }
+        let (value, derivative) = equation.residual(&orbit);
+        if derivative.re.hi == 0.0 && derivative.im.hi == 0.0 {
+            break;
+        }
+        let step = value.div(derivative);
+        if !(step.re.hi.is_finite() && step.im.hi.is_finite()) {
+            break;
+        }
+        c = c - step;
+        if step.re.hi.hypot(step.im.hi) <= 1e-31 * c.re.hi.hypot(c.im.hi).max(1.0) {
+            break;
+        }
+    }
+    (c.re.to_string(), c.im.to_string())
+}
+
+/// Decimal coordinates of a converged point, with the shortest digits that read back as `c`
+#[cfg(not(feature = "double-double"))]
+fn decimal_coordinates(c: Complex<f64>, _equation: Equation) -> (String, String) {
+    (format!("{}", c.re), format!("{}", c.im))
+}
+
+/// Refine a guess to the center of a hyperbolic component of the given period
+///
+/// # Arguments
+///
+/// * `guess` - Starting point for Newton's method, ideally inside the component
+/// * `period` - Period of the component (1 for the main cardioid, 2 for the period-2 bulb, ...)
+///
+/// # Returns
+///
+/// The center, or `None` if Newton's method did not converge or converged to a center whose
+/// period is a proper divisor of `period`
+pub fn find_hyperbolic_center(guess: Complex<f64>, period: u32) -> Option<Complex<f64>> {
+    if period == 0 {
+        return None;
+    }
+    let c = newton(guess, Equation::center(period))?;
+
+    // Reject centers of lower period, which are also roots of f^p(0)
+    let orbit = critical_orbit(c, period);
+    if proper_divisors(period).any(|d| orbit[d as usize].0.norm() < ORBIT_TOLERANCE) {
+        return None;
+    }
+    Some(c)
+}
+
+/// Refine a guess to a Misiurewicz point with the given preperiod and period
+///
+/// # Arguments
+///
+/// * `guess` - Starting point for Newton's method
+/// * `preperiod` - Number of iterations before the critical orbit becomes periodic (at least 1)
+/// * `period` - Period of the cycle the critical orbit lands on
+///
+/// # Returns
+///
+/// The Misiurewicz point, or `None` if Newton's method did not converge or the point found has
+/// a smaller preperiod or period than requested
+pub fn find_misiurewicz_point(guess: Complex<f64>, preperiod: u32, period: u32) -> Option<Complex<f64>> {
+    if preperiod == 0 || period == 0 {
+        return None;
+    }
+    let k = preperiod as usize;
+    let p = period as usize;
+    let c = newton(guess, Equation::misiurewicz(preperiod, period))?;
+
+    let orbit = critical_orbit(c, preperiod + period);
+    let coincide = |a: usize, b: usize| (orbit[a].0 - orbit[b].0).norm() < ORBIT_TOLERANCE;
+
+    // The orbit must not already be periodic one step earlier (smaller preperiod, or a center)
+    if coincide(k - 1 + p, k - 1) {
+        return None;
+    }
+    // The cycle must not have a shorter period
+    if proper_divisors(period).any(|d| coincide(k + d as usize, k)) {
+        return None;
+    }
+    Some(c)
+}
+
+/// Find centers and Misiurewicz points near a clicked coordinate
+///
+/// Every period from 1 to `max_period` is tried for centers, and every combination of
+/// preperiod and period up to `max_period` for Misiurewicz points, starting Newton's method at
+/// `click`. Points farther than `search_radius` from the click are discarded.
+///
+/// # Arguments
+///
+/// * `click` - The coordinate to search around
+/// * `max_period` - Largest period (and preperiod) to try; the cost grows with its square
+/// * `search_radius` - Maximum distance from `click` for a point to be reported
+///
+/// # Returns
+///
+/// The distinct points found, nearest first, with their coordinates refined as far as the
+/// build's precision allows (see the module documentation)
+pub fn locate_periodic_points(click: Complex<f64>, max_period: u32, search_radius: f64) -> Vec<PeriodicPoint> {
+    let mut points: Vec<PeriodicPoint> = Vec::new();
+    let mut add = |c: Complex<f64>, kind: PeriodicPointKind, period: u32, preperiod: u32| {
+        let distance = (c - click).norm();
+        if distance > search_radius {
+            return;
+        }
+        // Different starting periods can converge to the same point; keep the first
+        let duplicate = points
+            .iter()
+            .any(|p| (p.c - c).norm() <= DUPLICATE_TOLERANCE * c.norm().max(1.0));
+        if !duplicate {
+            let equation = match kind {
+                PeriodicPointKind::Center => Equation::center(period),
+                PeriodicPointKind::Misiurewicz => Equation::misiurewicz(preperiod, period),
+            };
+            let (re, im) = decimal_coordinates(c, equation);
+            points.push(PeriodicPoint { c, kind, period, preperiod, distance, re, im });
+        }
+    };
+
+    for period in 1..=max_period {
+        if let Some(c) = find_hyperbolic_center(click, period) {
+            add(c, PeriodicPointKind::Center, period, 0);
+        }
+    }
+
+    for preperiod in 1..=max_period {
+        for period in 1..=max_period {
+            if let Some(c) = find_misiurewicz_point(click, preperiod, period) {
+                add(c, PeriodicPointKind::Misiurewicz, period, preperiod);
+            }
+        }
+    }
+
+    points.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_known_centers() {
+        let c = find_hyperbolic_center(Complex::new(-0.9, 0.1), 2).unwrap();
+        assert!((c - Complex::new(-1.0, 0.0)).norm() < 1e-14);
+
+        // The period-3 minibrot on the real axis ("airship")
+        let c = find_hyperbolic_center(Complex::new(-1.76, 0.0), 3).unwrap();
+        assert!((c.re + 1.754_877_666_246_692_7).abs() < 1e-13);
+
+        // The main cardioid center is period 1, not period 2
+        assert!(find_hyperbolic_center(Complex::new(0.01, 0.01), 2).is_none_or(|c| c.norm() > 1e-6));
+    }
+
+    #[test]
+    fn test_finds_known_misiurewicz_points() {
+        // c = i: 0 -> i -> -1+i -> -i -> -1+i, preperiod 2, period 2
+        let c = find_misiurewicz_point(Complex::new(0.02, 0.97), 2, 2).unwrap();
+        assert!((c - Complex::new(0.0, 1.0)).norm() < 1e-12);
+
+        // c = -2: 0 -> -2 -> 2 -> 2, preperiod 2, period 1
+        let c = find_misiurewicz_point(Complex::new(-1.98, 0.0), 2, 1).unwrap();
+        assert!((c + 2.0).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_locate_sorts_by_distance() {
+        let points = locate_periodic_points(Complex::new(-1.01, 0.0), 4, 0.5);
+        assert!(!points.is_empty());
+        assert_eq!(points[0].kind, PeriodicPointKind::Center);
+        assert_eq!(points[0].period, 2);
+        assert!(points.windows(2).all(|w| w[0].distance <= w[1].distance));
+        assert_eq!(points[0].viewport(1e6).center_re.parse::<f64>(), Ok(points[0].c.re));
+    }
+
+    #[cfg(feature = "double-double")]
+    #[test]
+    fn test_refines_coordinates_beyond_f64() {
+        use crate::iteration::IterationFloat;
+
+        // The airship center is the real root of c^3 + 2c^2 + c + 1 = 0
+        let points = locate_periodic_points(Complex::new(-1.76, 0.0), 3, 0.05);
+        let airship = points.iter().find(|point| point.kind == PeriodicPointKind::Center && point.period == 3).unwrap();
+        let c = DoubleDouble::parse(&airship.re).unwrap();
+        let expected = DoubleDouble::parse("-1.75487766624669276004950889635852869189460661777279").unwrap();
+        assert!((c - expected).to_f64().abs() < 1e-29);
+        let residual = ((c + DoubleDouble::from(2.0)) * c + DoubleDouble::from(1.0)) * c + DoubleDouble::from(1.0);
+        assert!(residual.to_f64().abs() < 1e-29);
+    }
+}
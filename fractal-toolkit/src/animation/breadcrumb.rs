@@ -0,0 +1,181 @@
+//! Breadcrumb strips documenting a zoom
+//!
+//! A breadcrumb strip shows every view of a zoom path side by side as a thumbnail, left to
+//! right, and outlines in each thumbnail where the next view lies. It records how a deep
+//! location was found in one still image, which the frames of the zoom itself don't. Views far
+//! smaller than a pixel of the previous thumbnail still get a small visible marker.
+
+use super::sequence::iterate_frame;
+use crate::{color_for_result_equalized, escape_degree, ColorStop, FractalParams, IterationHistogram, IterationResult};
+use image::{Rgba, RgbaImage};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+/// Smallest side in pixels of the outline of the next view
+const MIN_MARKER_SIZE: f64 = 6.0;
+
+/// Layout of a breadcrumb strip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreadcrumbOptions {
+    /// Height of every thumbnail in pixels; widths follow the aspect ratio of each view
+    pub thumbnail_height: u32,
+    /// Space between thumbnails and around the strip, in pixels
+    pub gap: u32,
+    /// Width of the outlines in pixels
+    pub outline_width: u32,
+    /// Color of the outlines
+    pub outline_color: Rgba<u8>,
+    /// Color of the space between thumbnails
+    pub background: Rgba<u8>,
+}
+
+impl Default for BreadcrumbOptions {
+    fn default() -> Self {
+        Self {
+            thumbnail_height: 256,
+            gap: 8,
+            outline_width: 2,
+            outline_color: Rgba([255, 255, 255, 255]),
+            background: Rgba([0, 0, 0, 255]),
+        }
+    }
+}
+
+/// Pixel rectangle (x0, y0, x1, y1), inclusive, outlining a view inside a thumbnail of another
+///
+/// # Arguments
+///
+/// * `outer` - Bounds of the thumbnail's view
+/// * `inner` - Bounds of the view to outline
+/// * `width` - Thumbnail width in pixels
+/// * `height` - Thumbnail height in pixels
+///
+/// # Returns
+///
+/// The rectangle, at least `MIN_MARKER_SIZE` pixels on each side and clipped to the thumbnail,
+/// or `None` if the view lies entirely outside it
+pub fn view_rectangle(outer: [f64; 4], inner: [f64; 4], width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    // The same mapping as pixel_to_complex, inverted
+    let to_pixel = |value: f64, min: f64, max: f64, size: u32| (value - min) / (max - min) * size.saturating_sub(1) as f64;
+    let axis = |a: f64, b: f64, min: f64, max: f64, size: u32| -> Option<(u32, u32)> {
+        let (mut low, mut high) = (to_pixel(a, min, max, size), to_pixel(b, min, max, size));
+        if low > high {
+            std::mem::swap(&mut low, &mut high);
+        }
+        if high - low < MIN_MARKER_SIZE {
+            let center = (low + high) / 2.0;
+            low = center - MIN_MARKER_SIZE / 2.0;
+            high = center + MIN_MARKER_SIZE / 2.0;
+        }
+        let last = size.saturating_sub(1) as f64;
+        if !(low.is_finite() && high.is_finite()) || high < 0.0 || low > last {
+            return None;
+        }
+        Some((low.round().clamp(0.0, last) as u32, high.round().clamp(0.0, last) as u32))
+    };
+    let (x0, x1) = axis(inner[0], inner[1], outer[0], outer[1], width)?;
+    let (y0, y1) = axis(inner[2], inner[3], outer[2], outer[3], height)?;
+    Some((x0, y0, x1, y1))
+}
+
+/// Draw the outline of a rectangle, growing inwards from its edges
+fn draw_outline(image: &mut RgbaImage, (x0, y0, x1, y1): (u32, u32, u32, u32), line_width: u32, color: Rgba<u8>) {
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let from_edge = (x - x0).min(x1 - x).min(y - y0).min(y1 - y);
+            if from_edge < line_width.max(1) {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Render a breadcrumb strip of a zoom path
+///
+/// Each thumbnail is colored like a render of its view, with its own histogram when
+/// `params.histogram_coloring` is set; anti-aliasing and post-processing effects are skipped.
+///
+/// # Arguments
+///
+/// * `views` - Bounds [x_min, x_max, y_min, y_max] of each view, from the widest to the deepest
+/// * `params` - Parameters of every thumbnail; the bounds are replaced by each view's
+/// * `iteration_func` - Iteration of one point, e.g. `mandelbrot_result_compiled`
+/// * `palette` - Optional color palette
+/// * `options` - Layout of the strip
+///
+/// # Returns
+///
+/// The strip, or an error for an empty path or a view with no area
+pub fn render_breadcrumb<F>(
+    views: &[[f64; 4]],
+    params: &FractalParams,
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+    options: &BreadcrumbOptions,
+) -> Result<RgbaImage, String>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    if views.is_empty() {
+        return Err("A breadcrumb strip needs at least one view".to_string());
+    }
+    if let Some(flat) = views.iter().find(|b| b[0] == b[1] || b[2] == b[3] || b.iter().any(|v| !v.is_finite())) {
+        return Err(format!("View {:?} has no area", flat));
+    }
+
+    let height = options.thumbnail_height.max(2);
+    let widths: Vec<u32> = views
+        .iter()
+        .map(|b| (height as f64 * ((b[1] - b[0]) / (b[3] - b[2])).abs()).round().max(2.0) as u32)
+        .collect();
+    let gap = options.gap;
+    let strip_width = widths.iter().sum::<u32>() + gap * (views.len() as u32 + 1);
+    let mut strip = RgbaImage::from_pixel(strip_width, height + 2 * gap, options.background);
+
+    let degree = escape_degree(&params.formula);
+    let mut left = gap;
+    for (index, (&bounds, &width)) in views.iter().zip(&widths).enumerate() {
+        let view_params = FractalParams { bounds, ..params.clone() };
+        let results = iterate_frame(width, height, &view_params, &iteration_func);
+        let histogram = view_params
+            .histogram_coloring
+            .then(|| IterationHistogram::from_results(&results, view_params.max_iterations));
+        let colors: Vec<Rgba<u8>> = results
+            .par_iter()
+            .map(|result| color_for_result_equalized(result, degree, &view_params, palette, histogram.as_ref()))
+            .collect();
+        let mut thumbnail = RgbaImage::from_raw(width, height, colors.iter().flat_map(|color| color.0).collect())
+            .expect("one color per pixel");
+
+        if let Some(rectangle) = views.get(index + 1).and_then(|&next| view_rectangle(bounds, next, width, height)) {
+            draw_outline(&mut thumbnail, rectangle, options.outline_width, options.outline_color);
+        }
+        image::imageops::overlay(&mut strip, &thumbnail, left as i64, gap as i64);
+        left += width + gap;
+        println!("Breadcrumb thumbnail {}/{} done", index + 1, views.len());
+    }
+    Ok(strip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_rectangle_marks_nested_views() {
+        let outer = [-2.0, 2.0, -2.0, 2.0];
+        assert_eq!(view_rectangle(outer, [0.0, 2.0, -2.0, 0.0], 101, 101), Some((50, 0, 100, 50)));
+
+        // A view far below a pixel still gets a visible marker around its center
+        let (x0, y0, x1, y1) = view_rectangle(outer, [1e-9, 2e-9, 1e-9, 2e-9], 101, 101).unwrap();
+        assert_eq!((x1 - x0, y1 - y0), (6, 6));
+        assert!(x0 <= 50 && 50 <= x1 && y0 <= 50 && 50 <= y1);
+
+        assert_eq!(view_rectangle(outer, [3.0, 4.0, 0.0, 1.0], 101, 101), None);
+
+        let mut image = RgbaImage::new(10, 10);
+        draw_outline(&mut image, (2, 2, 7, 7), 1, Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(2, 5)[0], 255);
+        assert_eq!(image.get_pixel(4, 4)[0], 0);
+    }
+}
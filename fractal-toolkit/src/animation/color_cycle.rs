@@ -0,0 +1,180 @@
+//! Palette-rotation ("color cycling") animations
+//!
+//! Every frame recolors the same iteration buffer with the palette shifted a little further, so
+//! an N-frame loop costs one render plus N cheap coloring passes. Points inside the set, and the
+//! background when one is set, keep their color in every frame.
+
+use super::{write_animation, AnimationFormat};
+use crate::output::OutputOptions;
+use crate::{interpolate_color_from_palette, ColorStop, FractalError};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Settings for a color cycling animation
+#[derive(Debug, Clone)]
+pub struct ColorCycleOptions {
+    /// Number of frames in one loop
+    pub frames: u32,
+    /// Display time of each frame in milliseconds
+    pub delay_ms: u32,
+    /// How many times the palette repeats across the iteration range; larger values give
+    /// narrower color bands
+    pub palette_repeats: f64,
+    /// RGBA color of points inside the set; `None` uses the palette's first stop
+    pub inside_color: Option<[u8; 4]>,
+    /// RGBA color of points that escape on the first iteration; `None` cycles them with the rest
+    pub outside_background: Option<[u8; 4]>,
+    /// How frames saved to a directory are written
+    pub output: OutputOptions,
+}
+
+impl Default for ColorCycleOptions {
+    fn default() -> Self {
+        Self {
+            frames: 32,
+            delay_ms: 40,
+            palette_repeats: 1.0,
+            inside_color: None,
+            outside_background: None,
+            output: OutputOptions::default(),
+        }
+    }
+}
+
+/// Color of an escaped point at palette position `t` (any real number, wrapped to one period)
+///
+/// Custom palettes are mirrored (0 → 1 → 0) so the loop has no seam between the last and first
+/// stop. The built-in gradient already starts and ends at black and is used as is.
+fn cycle_color(t: f64, palette: &[ColorStop]) -> Rgba<u8> {
+    let t = t.rem_euclid(1.0);
+    if palette.is_empty() {
+        let r = (9.0 * (1.0 - t) * t * t * t * 255.0) as u8;
+        let g = (15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0) as u8;
+        let b = (8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0) as u8;
+        Rgba([r, g, b, 255])
+    } else {
+        interpolate_color_from_palette(1.0 - (2.0 * t - 1.0).abs(), palette)
+    }
+}
+
+/// Color one iteration buffer repeatedly with the palette rotated by 1/N per frame
+///
+/// # Arguments
+///
+/// * `iterations` - Iteration counts in row-major order, `max_iterations` meaning inside the set
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `max_iterations` - Iteration limit the buffer was rendered with
+/// * `palette` - Color stops; an empty slice uses the built-in gradient
+/// * `options` - Frame count, palette repeats, and inside/background colors
+///
+/// # Returns
+///
+/// The frames of one full palette rotation, or an error if the buffer size doesn't match
+pub fn color_cycle_frames(
+    iterations: &[u32],
+    width: u32,
+    height: u32,
+    max_iterations: u32,
+    palette: &[ColorStop],
+    options: &ColorCycleOptions,
+) -> Result<Vec<RgbaImage>, String> {
+    if iterations.len() != (width as usize) * (height as usize) {
+        return Err(format!(
+            "Iteration buffer has {} values but the image is {}x{}",
+            iterations.len(),
+            width,
+            height
+        ));
+    }
+    if options.frames == 0 {
+        return Err("Color cycling needs at least one frame".to_string());
+    }
+
+    let inside = match (options.inside_color, palette.first()) {
+        (Some(color), _) => Rgba(color),
+        (None, Some(stop)) => Rgba([stop.color[0], stop.color[1], stop.color[2], 255]),
+        (None, None) => Rgba([0, 0, 0, 255]),
+    };
+    let max = max_iterations.max(1) as f64;
+
+    let frames = (0..options.frames)
+        .into_par_iter()
+        .map(|frame| {
+            let shift = frame as f64 / options.frames as f64;
+            let pixels: Vec<u8> = iterations
+                .iter()
+                .flat_map(|&iter| {
+                    let color = if iter >= max_iterations {
+                        inside
+                    } else if let (0, Some(background)) = (iter, options.outside_background) {
+                        Rgba(background)
+                    } else {
+                        cycle_color(iter as f64 / max * options.palette_repeats + shift, palette)
+                    };
+                    color.0
+                })
+                .collect();
+            RgbaImage::from_raw(width, height, pixels).expect("buffer size checked above")
+        })
+        .collect();
+    Ok(frames)
+}
+
+/// Render a color cycling animation from an iteration buffer and write it to disk
+///
+/// The format follows the extension of `path`: `.gif`, `.png`/`.apng` (animated PNG), or no
+/// extension for a directory of numbered PNG frames.
+///
+/// # Arguments
+///
+/// * `path` - Output file or directory
+/// * `iterations` - Iteration counts in row-major order
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `max_iterations` - Iteration limit the buffer was rendered with
+/// * `palette` - Color stops; an empty slice uses the built-in gradient
+/// * `options` - Frame count, frame delay and palette repeats
+///
+/// # Returns
+///
+/// Ok(()) on success, `FractalError::InvalidInput` for an unknown extension or options that
+/// make no frames, or an error as from `write_animation`
+pub fn write_color_cycle_animation(
+    path: &str,
+    iterations: &[u32],
+    width: u32,
+    height: u32,
+    max_iterations: u32,
+    palette: &[ColorStop],
+    options: &ColorCycleOptions,
+) -> Result<(), FractalError> {
+    let format = AnimationFormat::from_path(path).map_err(FractalError::InvalidInput)?;
+    let frames = color_cycle_frames(iterations, width, height, max_iterations, palette, options).map_err(FractalError::InvalidInput)?;
+    write_animation(path, &frames, options.delay_ms, format, &options.output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Easing;
+
+    #[test]
+    fn test_frames_rotate_palette_and_keep_interior() {
+        let palette = vec![
+            ColorStop { color: [255, 0, 0], position: 0.0, easing: Easing::Linear },
+            ColorStop { color: [0, 0, 255], position: 1.0, easing: Easing::Linear },
+        ];
+        let iterations = vec![0, 25, 50, 100];
+        let options = ColorCycleOptions { frames: 4, ..Default::default() };
+        let frames = color_cycle_frames(&iterations, 2, 2, 100, &palette, &options).unwrap();
+        assert_eq!(frames.len(), 4);
+
+        // Interior pixel is constant; shifting by a quarter turn moves pixel 25 onto pixel 50's color
+        assert!(frames.iter().all(|f| *f.get_pixel(1, 1) == Rgba([255, 0, 0, 255])));
+        assert_eq!(frames[1].get_pixel(1, 0), frames[0].get_pixel(0, 1));
+        assert_ne!(frames[0], frames[1]);
+
+        assert!(color_cycle_frames(&iterations, 3, 2, 100, &palette, &options).is_err());
+    }
+}
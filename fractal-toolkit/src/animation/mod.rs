@@ -0,0 +1,118 @@
+//! Animated output
+//!
+//! Frames are plain RGBA images; `write_animation` encodes a sequence of them as an animated
+//! GIF, an animated PNG, or a directory of numbered PNG files for external encoders. Longer
+//! sequences that render every frame, such as zooms and Julia set morphs, are written frame by frame with
+//! `FrameSequenceWriter`, to numbered PNG files or a `.y4m` video stream. A `Timeline` keyframes
+//! any of the view, the Julia constant, i², the palette offset and the bailout on splines, with
+//! an easing per keyframe, for animations beyond a plain zoom. A zoom path can also be
+//! summarized in one image as a breadcrumb strip of thumbnails with `render_breadcrumb`.
+
+pub mod breadcrumb;
+pub mod color_cycle;
+pub mod morph;
+pub mod sequence;
+pub mod timeline;
+pub mod zoom;
+
+pub use breadcrumb::{render_breadcrumb, view_rectangle, BreadcrumbOptions};
+pub use color_cycle::{color_cycle_frames, write_color_cycle_animation, ColorCycleOptions};
+pub use morph::{morph_path, render_julia_morph, ParameterPath};
+pub use sequence::{render_sequence, FrameMetadata, FrameSequenceWriter, ManifestEntry, SequenceFormat, SequenceManifest};
+pub use timeline::{render_timeline, Channel, Interpolation, Keyframe, Timeline, TimelineFrame, Track};
+pub use zoom::{interpolate_bounds, parse_keyframes, render_zoom_animation, zoom_path};
+
+use crate::output::{save_image_atomic, AtomicFile, OutputOptions};
+use crate::FractalError;
+use image::RgbaImage;
+
+/// Container for a sequence of frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Animated GIF (256 colours per frame)
+    Gif,
+    /// Animated PNG (full colour, supported by current browsers)
+    Apng,
+    /// Numbered PNG files (`frame_0000.png`, ...) in a directory
+    Frames,
+}
+
+impl AnimationFormat {
+    /// Pick the format from the output path: `.gif`, `.png`/`.apng`, or no extension for a directory
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        match std::path::Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gif") => Ok(AnimationFormat::Gif),
+            Some(ext) if ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("apng") => Ok(AnimationFormat::Apng),
+            None => Ok(AnimationFormat::Frames),
+            Some(ext) => Err(format!("Unsupported animation extension '.{}' (use .gif, .png or a directory)", ext)),
+        }
+    }
+}
+
+/// Write frames as an animation
+///
+/// # Arguments
+///
+/// * `path` - Output file, or directory for `AnimationFormat::Frames` (created if missing)
+/// * `frames` - Frames in order; all must have the same size
+/// * `delay_ms` - Display time of each frame in milliseconds
+/// * `format` - Container format
+/// * `options` - How the frames of `AnimationFormat::Frames` are written
+///
+/// # Returns
+///
+/// Ok(()) on success, `FractalError::InvalidInput` if there are no frames or their sizes differ,
+/// or `FractalError::IoError` if the output can't be written
+pub fn write_animation(path: &str, frames: &[RgbaImage], delay_ms: u32, format: AnimationFormat, options: &OutputOptions) -> Result<(), FractalError> {
+    let invalid = |message: &str| FractalError::InvalidInput(message.to_string());
+    let (width, height) = frames.first().ok_or_else(|| invalid("No frames to write"))?.dimensions();
+    if frames.iter().any(|f| f.dimensions() != (width, height)) {
+        return Err(invalid("All frames must have the same dimensions"));
+    }
+    let unencoded = |e: &dyn std::fmt::Display| FractalError::InvalidInput(format!("Failed to encode '{}': {}", path, e));
+
+    match format {
+        AnimationFormat::Gif => {
+            use image::codecs::gif::{GifEncoder, Repeat};
+            use image::{Delay, Frame};
+
+            let mut file = AtomicFile::create(path)?;
+            file.set_checksum(options.checksum);
+            {
+                // The encoder writes the GIF trailer when it is dropped
+                let mut encoder = GifEncoder::new_with_speed(&mut file, 10);
+                encoder.set_repeat(Repeat::Infinite).map_err(|e| unencoded(&e))?;
+                let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+                encoder
+                    .encode_frames(frames.iter().map(|f| Frame::from_parts(f.clone(), 0, 0, delay)))
+                    .map_err(|e| unencoded(&e))?;
+            }
+            file.commit()
+        }
+        AnimationFormat::Apng => {
+            let mut file = AtomicFile::create(path)?;
+            file.set_checksum(options.checksum);
+            let mut encoder = png::Encoder::new(&mut file, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, 0).map_err(|e| unencoded(&e))?;
+            encoder
+                .set_frame_delay(delay_ms.min(u16::MAX as u32) as u16, 1000)
+                .map_err(|e| unencoded(&e))?;
+            let mut writer = encoder.write_header().map_err(|e| unencoded(&e))?;
+            for frame in frames {
+                writer.write_image_data(frame.as_raw()).map_err(|e| unencoded(&e))?;
+            }
+            writer.finish().map_err(|e| unencoded(&e))?;
+            file.commit()
+        }
+        AnimationFormat::Frames => {
+            std::fs::create_dir_all(path).map_err(|e| FractalError::io("create", path, e))?;
+            for (i, frame) in frames.iter().enumerate() {
+                let frame_path = std::path::Path::new(path).join(format!("frame_{:04}.png", i));
+                save_image_atomic(frame, &frame_path, options)?;
+            }
+            Ok(())
+        }
+    }
+}
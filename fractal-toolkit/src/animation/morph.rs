@@ -0,0 +1,215 @@
+//! Julia set morphing along a path of the spawn constant
+//!
+//! A Julia set changes continuously with its constant c, so moving c along a path and rendering
+//! a frame at every step shows the set morphing: connected while c stays in the Mandelbrot set,
+//! breaking into dust as it leaves. Paths are straight lines, circles (for instance around the
+//! center of a Mandelbrot bulb, which loops through the bulb's family of Julia sets), or
+//! Catmull-Rom splines through waypoints. Circles and closed splines loop seamlessly: the frame
+//! after the last would be the first.
+
+use super::sequence::{render_sequence, FrameSequenceWriter};
+use crate::{ColorStop, Easing, FractalError, FractalParams, IterationResult};
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+/// A path of the spawn constant c
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterPath {
+    /// Straight line from one value to another
+    Line {
+        /// Start of the line
+        from: Complex<f64>,
+        /// End of the line
+        to: Complex<f64>,
+    },
+    /// Full turn around a circle, counterclockwise from its rightmost point
+    Circle {
+        /// Center, such as the center of a Mandelbrot bulb
+        center: Complex<f64>,
+        /// Radius
+        radius: f64,
+    },
+    /// Catmull-Rom spline through waypoints, with equal time between consecutive waypoints
+    Spline {
+        /// Points the path passes through, at least two
+        waypoints: Vec<Complex<f64>>,
+        /// Return from the last waypoint to the first, for a loop
+        closed: bool,
+    },
+}
+
+impl ParameterPath {
+    /// Parse a path
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - `line:re,im;re,im`, `circle:re,im;radius`, `spline:re,im;re,im;...` or
+    ///   `loop:re,im;re,im;...` for a closed spline
+    ///
+    /// # Returns
+    ///
+    /// The path, or an error for malformed input
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (kind, rest) = text.trim().split_once(':').ok_or_else(|| format!("Path must start with line:, circle:, spline: or loop:, found '{}'", text.trim()))?;
+        let parts: Vec<&str> = rest.split(';').map(str::trim).filter(|part| !part.is_empty()).collect();
+        let point = |part: &str| -> Result<Complex<f64>, String> {
+            let values: Vec<f64> = part
+                .split(',')
+                .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in path point '{}'", value.trim(), part)))
+                .collect::<Result<_, _>>()?;
+            match values[..] {
+                [re, im] => Ok(Complex::new(re, im)),
+                _ => Err(format!("Path point '{}' must be re,im", part)),
+            }
+        };
+
+        match kind.trim().to_lowercase().as_str() {
+            "line" => match parts[..] {
+                [from, to] => Ok(ParameterPath::Line { from: point(from)?, to: point(to)? }),
+                _ => Err("A line needs two points: line:re,im;re,im".to_string()),
+            },
+            "circle" => match parts[..] {
+                [center, radius] => Ok(ParameterPath::Circle {
+                    center: point(center)?,
+                    radius: radius.parse().map_err(|_| format!("Invalid circle radius '{}'", radius))?,
+                }),
+                _ => Err("A circle needs a center and a radius: circle:re,im;radius".to_string()),
+            },
+            kind @ ("spline" | "loop") => {
+                let waypoints = parts.iter().map(|&part| point(part)).collect::<Result<Vec<_>, _>>()?;
+                if waypoints.len() < 2 {
+                    return Err(format!("A {} needs at least two waypoints", kind));
+                }
+                Ok(ParameterPath::Spline { waypoints, closed: kind == "loop" })
+            }
+            other => Err(format!("Unknown path kind: {} (expected line, circle, spline or loop)", other)),
+        }
+    }
+
+    /// Whether the path ends where it starts
+    pub fn is_closed(&self) -> bool {
+        matches!(self, ParameterPath::Circle { .. } | ParameterPath::Spline { closed: true, .. })
+    }
+
+    /// The point at a position along the path
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - Position from 0 at the start to 1 at the end (clamped)
+    pub fn point(&self, t: f64) -> Complex<f64> {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            ParameterPath::Line { from, to } => from + (to - from) * t,
+            ParameterPath::Circle { center, radius } => center + Complex::from_polar(*radius, 2.0 * PI * t),
+            ParameterPath::Spline { waypoints, closed } => {
+                let count = waypoints.len();
+                let segments = if *closed { count } else { count - 1 };
+                let position = t * segments as f64;
+                let segment = (position.floor() as usize).min(segments - 1);
+                let local = position - segment as f64;
+                // Neighbouring waypoints, wrapping around a loop and repeating the ends otherwise
+                let at = |index: isize| -> Complex<f64> {
+                    if *closed {
+                        waypoints[index.rem_euclid(count as isize) as usize]
+                    } else {
+                        waypoints[index.clamp(0, count as isize - 1) as usize]
+                    }
+                };
+                let index = segment as isize;
+                catmull_rom(at(index - 1), at(index), at(index + 1), at(index + 2), local)
+            }
+        }
+    }
+}
+
+/// Uniform Catmull-Rom interpolation between `p1` and `p2`
+fn catmull_rom(p0: Complex<f64>, p1: Complex<f64>, p2: Complex<f64>, p3: Complex<f64>, t: f64) -> Complex<f64> {
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0 + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5
+}
+
+/// Spawn constants of every frame of a morph
+///
+/// # Arguments
+///
+/// * `path` - Path of the constant
+/// * `frames` - Number of frames, at least one
+/// * `easing` - Pace along the path; `Easing::Linear` keeps loops seamless
+///
+/// # Returns
+///
+/// The constant of each frame. Open paths end exactly at their last point; closed paths stop
+/// one step short of returning to the start, so the sequence can repeat.
+pub fn morph_path(path: &ParameterPath, frames: u32, easing: Easing) -> Vec<Complex<f64>> {
+    let steps = if path.is_closed() { frames } else { frames.saturating_sub(1) }.max(1);
+    (0..frames).map(|frame| path.point(easing.apply(frame as f64 / steps as f64))).collect()
+}
+
+/// Render a Julia set morphing along a path of its constant and write the frames as they finish
+///
+/// # Arguments
+///
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `params` - Parameters of every frame; the spawn constant is replaced by the path's
+/// * `path` - Path of the constant
+/// * `frames` - Number of frames
+/// * `easing` - Pace along the path
+/// * `iteration_func` - Iteration of one point, e.g. `julia_result_compiled`
+/// * `palette` - Optional color palette
+/// * `output` - Where the frames go
+///
+/// # Returns
+///
+/// Ok(()) once every frame is written, or an error
+#[allow(clippy::too_many_arguments)]
+pub fn render_julia_morph<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    path: &ParameterPath,
+    frames: u32,
+    easing: Easing,
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+    output: &mut FrameSequenceWriter,
+) -> Result<(), FractalError>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    if frames == 0 {
+        return Err(FractalError::InvalidInput("A morph needs at least one frame".to_string()));
+    }
+    let frame_params: Vec<FractalParams> = morph_path(path, frames, easing)
+        .into_iter()
+        .map(|spawn| FractalParams { spawn, ..params.clone() })
+        .collect();
+    render_sequence(width, height, &frame_params, iteration_func, palette, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_pass_through_their_points() {
+        let line = ParameterPath::parse("line:-0.8,0.1;-0.7,0.3").unwrap();
+        assert_eq!(morph_path(&line, 3, Easing::Linear), vec![Complex::new(-0.8, 0.1), Complex::new(-0.75, 0.2), Complex::new(-0.7, 0.3)]);
+
+        // Around the period-2 bulb; a loop of 4 frames visits the quarter turns and doesn't repeat the start
+        let circle = ParameterPath::parse("circle:-1,0;0.25").unwrap();
+        let frames = morph_path(&circle, 4, Easing::Linear);
+        assert!((frames[1] - Complex::new(-1.0, 0.25)).norm() < 1e-12);
+        assert!((frames[2] - Complex::new(-1.25, 0.0)).norm() < 1e-12);
+
+        let waypoints = [Complex::new(0.0, 0.0), Complex::new(1.0, 1.0), Complex::new(2.0, 0.0)];
+        let spline = ParameterPath::Spline { waypoints: waypoints.to_vec(), closed: false };
+        assert_eq!(morph_path(&spline, 5, Easing::Linear)[2], waypoints[1]);
+        assert_eq!(spline.point(1.0), waypoints[2]);
+        let closed = ParameterPath::parse("loop:0,0;1,1;2,0").unwrap();
+        assert!((closed.point(1.0) - waypoints[0]).norm() < 1e-12);
+
+        assert!(ParameterPath::parse("spiral:0,0;1").is_err());
+        assert!(ParameterPath::parse("line:0,0").is_err());
+    }
+}
@@ -0,0 +1,498 @@
+//! Rendering and writing frame sequences for videos
+//!
+//! Animations that change the view or the formula's parameters render every frame from
+//! scratch, so frames are written out as soon as they are done rather than collected like the
+//! frames of `write_animation`. They go either to a directory of numbered PNG files or to a
+//! YUV4MPEG2 (`.y4m`) stream, which video encoders such as ffmpeg read directly, including
+//! through a named pipe. A stream written to a regular file is written atomically like the
+//! images (see `output::atomic`), so an interrupted render doesn't leave a truncated video.
+//!
+//! Coloring is kept consistent from frame to frame: histogram coloring uses one histogram for
+//! the whole sequence, counted from a low-resolution preview of every frame, instead of
+//! equalizing each frame separately, which makes the colors flicker.
+//!
+//! With metadata enabled, every frame also gets a JSON sidecar (`FrameMetadata`) with its index,
+//! time and the full parameters and palette it was rendered with, and the sequence a
+//! `manifest.json` (`SequenceManifest`) listing them. Single frames can then be rendered again
+//! at a higher quality, and compositors can line overlays up with the frames. Sidecars of
+//! frame directories sit next to the frames; those of a `.y4m` stream go in `<stream>.meta`.
+
+use crate::{
+    antialias, color_for_result_equalized, escape_degree, pixel_to_complex, postfx, AntiAliasing, ColorStop, FractalError,
+    FractalParams, IterationHistogram, IterationResult,
+};
+use crate::output::{save_image_atomic, write_atomic, AtomicFile, OutputOptions};
+use image::{Rgba, RgbaImage};
+use num_complex::Complex;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+/// Largest side of the preview frames histogram coloring is counted from
+const PREVIEW_SIZE: u32 = 128;
+
+/// How a frame sequence is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceFormat {
+    /// Numbered PNG files (`frame_00000.png`, ...) in a directory
+    Frames,
+    /// One YUV4MPEG2 stream with 4:4:4 chroma
+    Y4m,
+}
+
+impl SequenceFormat {
+    /// Pick the format from the output path: `.y4m` or `-` (standard output) for a stream,
+    /// no extension for a directory of frames
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        if path == "-" {
+            return Ok(SequenceFormat::Y4m);
+        }
+        match std::path::Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("y4m") => Ok(SequenceFormat::Y4m),
+            None => Ok(SequenceFormat::Frames),
+            Some(ext) => Err(format!("Unsupported frame sequence extension '.{}' (use .y4m or a directory)", ext)),
+        }
+    }
+
+    /// Parse a format name: `frames` or `y4m`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "frames" | "png" => Ok(SequenceFormat::Frames),
+            "y4m" => Ok(SequenceFormat::Y4m),
+            _ => Err(format!("Unknown frame sequence format: {} (expected frames or y4m)", name.trim())),
+        }
+    }
+}
+
+enum Target {
+    Directory(PathBuf),
+    /// A `.y4m` stream in a regular file, renamed into place when the sequence is finished
+    File(AtomicFile),
+    /// A `.y4m` stream to standard output or a named pipe, which can't be renamed
+    Stream(Box<dyn Write>),
+}
+
+impl Target {
+    /// Where the frames of a `.y4m` sequence go
+    fn stream(&mut self) -> Option<&mut dyn Write> {
+        match self {
+            Target::Directory(_) => None,
+            Target::File(file) => Some(file),
+            Target::Stream(stream) => Some(stream),
+        }
+    }
+}
+
+/// The sidecar of one frame, with everything needed to render it again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameMetadata {
+    /// Position of the frame in the sequence, from 0
+    pub index: u32,
+    /// Time of the frame in the video, in seconds
+    pub time: f64,
+    /// File name of the frame's image, for sequences of PNG files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Parameters the frame was rendered with
+    pub params: FractalParams,
+    /// Palette the frame was colored with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Vec<ColorStop>>,
+}
+
+/// A frame as listed in a `SequenceManifest`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Position of the frame in the sequence, from 0
+    pub index: u32,
+    /// Time of the frame in the video, in seconds
+    pub time: f64,
+    /// File name of the frame's image, for sequences of PNG files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// File name of the frame's `FrameMetadata` sidecar
+    pub metadata: String,
+}
+
+/// Index of the frames of a sequence, written as `manifest.json` next to their sidecars
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SequenceManifest {
+    /// Frame width in pixels
+    pub width: u32,
+    /// Frame height in pixels
+    pub height: u32,
+    /// Frame rate
+    pub fps: u32,
+    /// The video stream holding the frames, for `.y4m` sequences
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// Every frame, in order
+    pub frames: Vec<ManifestEntry>,
+}
+
+/// Writes the frames of a sequence one at a time
+pub struct FrameSequenceWriter {
+    target: Target,
+    path: String,
+    width: u32,
+    height: u32,
+    fps: u32,
+    frames_written: u32,
+    /// Directory of the sidecars and manifest, when metadata is enabled
+    metadata: Option<PathBuf>,
+    manifest: Vec<ManifestEntry>,
+    /// How frames saved to a directory are written
+    output: OutputOptions,
+}
+
+impl FrameSequenceWriter {
+    /// Start a frame sequence
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Directory for `Frames` (created if missing); file, named pipe or `-` for
+    ///   standard output for `Y4m`
+    /// * `format` - How to store the frames
+    /// * `width` - Frame width in pixels
+    /// * `height` - Frame height in pixels
+    /// * `fps` - Frame rate recorded in the stream header and the frame metadata
+    ///
+    /// # Returns
+    ///
+    /// The writer, or an error if the output can't be created
+    pub fn create(path: &str, format: SequenceFormat, width: u32, height: u32, fps: u32) -> Result<Self, FractalError> {
+        let target = match format {
+            SequenceFormat::Frames => {
+                std::fs::create_dir_all(path).map_err(|e| FractalError::io("create", path, e))?;
+                Target::Directory(PathBuf::from(path))
+            }
+            SequenceFormat::Y4m if path == "-" => Target::Stream(Box::new(BufWriter::new(std::io::stdout()))),
+            SequenceFormat::Y4m => match std::fs::metadata(path) {
+                // Named pipes and devices are opened in place, as the reader is already waiting
+                Ok(metadata) if !metadata.is_file() => {
+                    let file = std::fs::OpenOptions::new().write(true).open(path).map_err(|e| FractalError::io("open", path, e))?;
+                    Target::Stream(Box::new(BufWriter::new(file)))
+                }
+                _ => Target::File(AtomicFile::create(path)?),
+            },
+        };
+        Ok(Self {
+            target,
+            path: path.to_string(),
+            width,
+            height,
+            fps: fps.max(1),
+            frames_written: 0,
+            metadata: None,
+            manifest: Vec::new(),
+            output: OutputOptions::default(),
+        })
+    }
+
+    /// Write a `FrameMetadata` sidecar for every frame written with `write_frame_with_metadata`,
+    /// and a `SequenceManifest` when the sequence is finished
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to write metadata; `false` leaves the writer as it is
+    ///
+    /// # Returns
+    ///
+    /// The writer, or an error if the metadata directory can't be created or the frames go to
+    /// standard output, which has nowhere to put sidecars
+    pub fn with_metadata(mut self, enabled: bool) -> Result<Self, FractalError> {
+        if !enabled {
+            return Ok(self);
+        }
+        let directory = match &self.target {
+            Target::Directory(directory) => directory.clone(),
+            _ if self.path == "-" => {
+                return Err(FractalError::InvalidInput("Frame metadata needs a file or directory output, not standard output".to_string()))
+            }
+            _ => {
+                let directory = PathBuf::from(format!("{}.meta", self.path));
+                std::fs::create_dir_all(&directory).map_err(|e| FractalError::io("create", &directory, e))?;
+                directory
+            }
+        };
+        self.metadata = Some(directory);
+        Ok(self)
+    }
+
+    /// Write the frames of a directory sequence with these options, e.g. to embed a profile;
+    /// a `.y4m` file only takes the checksum option
+    pub fn with_output_options(mut self, options: OutputOptions) -> Self {
+        if let Target::File(file) = &mut self.target {
+            file.set_checksum(options.checksum);
+        }
+        self.output = options;
+        self
+    }
+
+    /// Append a frame together with the parameters and palette it was rendered with, which go
+    /// in its sidecar when metadata is enabled
+    pub fn write_frame_with_metadata(&mut self, frame: &RgbaImage, params: &FractalParams, palette: Option<&[ColorStop]>) -> Result<(), FractalError> {
+        let index = self.frames_written;
+        self.write_frame(frame)?;
+        let Some(directory) = &self.metadata else { return Ok(()) };
+
+        let image = matches!(self.target, Target::Directory(_)).then(|| format!("frame_{:05}.png", index));
+        let sidecar = FrameMetadata {
+            index,
+            time: index as f64 / self.fps as f64,
+            image: image.clone(),
+            params: params.clone(),
+            palette: palette.map(<[ColorStop]>::to_vec),
+        };
+        let name = format!("frame_{:05}.json", index);
+        let json = serde_json::to_string_pretty(&sidecar)
+            .map_err(|e| FractalError::InvalidInput(format!("Failed to encode frame metadata: {}", e)))?;
+        write_atomic(directory.join(&name), json.as_bytes())?;
+        self.manifest.push(ManifestEntry { index, time: sidecar.time, image, metadata: name });
+        Ok(())
+    }
+
+    /// Append a frame; transparent pixels are composited over black
+    pub fn write_frame(&mut self, frame: &RgbaImage) -> Result<(), FractalError> {
+        if frame.dimensions() != (self.width, self.height) {
+            return Err(FractalError::InvalidInput(format!(
+                "Frame is {}x{} but the sequence is {}x{}",
+                frame.width(),
+                frame.height(),
+                self.width,
+                self.height
+            )));
+        }
+        if self.frames_written == 0 {
+            self.write_header()?;
+        }
+        let written = match &mut self.target {
+            Target::Directory(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frames_written));
+                save_image_atomic(frame, &path, &self.output)?;
+                Ok(())
+            }
+            Target::File(file) => write_y4m_frame(file, frame),
+            Target::Stream(stream) => write_y4m_frame(stream, frame),
+        };
+        written.map_err(|e| FractalError::io("write", &self.path, e))?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Write the `.y4m` stream header, once, before the first frame
+    ///
+    /// It is not written by `create` so that `with_output_options` can still turn on the
+    /// checksum, which has to cover the whole file.
+    fn write_header(&mut self) -> Result<(), FractalError> {
+        let header = format!("YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444\n", self.width, self.height, self.fps);
+        match self.target.stream() {
+            Some(stream) => stream.write_all(header.as_bytes()).map_err(|e| FractalError::io("write", &self.path, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush the output, returning the number of frames written
+    pub fn finish(mut self) -> Result<u32, FractalError> {
+        if self.frames_written == 0 {
+            self.write_header()?;
+        }
+        let is_stream = !matches!(self.target, Target::Directory(_));
+        match self.target {
+            Target::Directory(_) => {}
+            Target::File(file) => file.commit()?,
+            Target::Stream(mut stream) => stream.flush().map_err(|e| FractalError::io("write", &self.path, e))?,
+        }
+        if let Some(directory) = &self.metadata {
+            let stream = is_stream.then(|| self.path.clone());
+            let manifest = SequenceManifest { width: self.width, height: self.height, fps: self.fps, stream, frames: std::mem::take(&mut self.manifest) };
+            let json = serde_json::to_string_pretty(&manifest)
+                .map_err(|e| FractalError::InvalidInput(format!("Failed to encode manifest: {}", e)))?;
+            write_atomic(directory.join("manifest.json"), json.as_bytes())?;
+        }
+        Ok(self.frames_written)
+    }
+}
+
+/// Append a frame to a `.y4m` stream as planar 4:4:4 Y'CbCr
+fn write_y4m_frame(stream: &mut dyn Write, frame: &RgbaImage) -> std::io::Result<()> {
+    let pixels = frame.width() as usize * frame.height() as usize;
+    let mut planes = vec![0u8; pixels * 3];
+    for (index, pixel) in frame.pixels().enumerate() {
+        let [y, cb, cr] = rgb_to_ycbcr(pixel);
+        planes[index] = y;
+        planes[pixels + index] = cb;
+        planes[2 * pixels + index] = cr;
+    }
+    stream.write_all(b"FRAME\n")?;
+    stream.write_all(&planes)
+}
+
+/// BT.601 limited-range Y'CbCr of a pixel composited over black
+fn rgb_to_ycbcr(pixel: &Rgba<u8>) -> [u8; 3] {
+    let alpha = pixel[3] as f64 / 255.0;
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f64 * alpha);
+    let y = 16.0 + 0.256_788 * r + 0.504_129 * g + 0.097_906 * b;
+    let cb = 128.0 - 0.148_223 * r - 0.290_993 * g + 0.439_216 * b;
+    let cr = 128.0 + 0.439_216 * r - 0.367_788 * g - 0.071_427 * b;
+    [y, cb, cr].map(|value| value.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Iterate every pixel of a frame
+pub(super) fn iterate_frame<F>(width: u32, height: u32, params: &FractalParams, iteration_func: &F) -> Vec<IterationResult>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| match params.cancel.is_cancelled() {
+            true => IterationResult::UNRENDERED,
+            false => iteration_func(pixel_to_complex(index % width, index / width, width, height, params.bounds), params),
+        })
+        .collect()
+}
+
+/// Render a sequence of frames with consistent coloring and write them out as they finish
+///
+/// Each frame is colored as `generate_fractal_image_from_results` would, including
+/// anti-aliasing and post-processing effects, except that histogram coloring shares one
+/// histogram across the sequence.
+///
+/// # Arguments
+///
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `frames` - Parameters of each frame, such as the bounds along a zoom
+/// * `iteration_func` - Iteration of one point, e.g. `mandelbrot_result_compiled`
+/// * `palette` - Optional color palette
+/// * `output` - Where the frames go
+///
+/// # Returns
+///
+/// Ok(()) once every frame is written, or an error if writing fails
+pub fn render_sequence<F>(
+    width: u32,
+    height: u32,
+    frames: &[FractalParams],
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+    output: &mut FrameSequenceWriter,
+) -> Result<(), FractalError>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    render_frames(width, height, frames, iteration_func, &vec![palette; frames.len()], output)
+}
+
+/// `render_sequence` with a palette for each frame, for animations that shift the colors
+pub(super) fn render_frames<F>(
+    width: u32,
+    height: u32,
+    frames: &[FractalParams],
+    iteration_func: F,
+    palettes: &[Option<&[ColorStop]>],
+    output: &mut FrameSequenceWriter,
+) -> Result<(), FractalError>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    let Some(first) = frames.first() else { return Ok(()) };
+    let histogram = if first.histogram_coloring {
+        let scale = (PREVIEW_SIZE as f64 / width.max(height) as f64).min(1.0);
+        let (preview_width, preview_height) = (((width as f64 * scale) as u32).max(2), ((height as f64 * scale) as u32).max(2));
+        let max_iterations = frames.iter().map(|params| params.max_iterations).max().unwrap_or(0);
+        let mut counts = vec![0u64; max_iterations as usize];
+        for params in frames {
+            for result in iterate_frame(preview_width, preview_height, params, &iteration_func) {
+                if result.iterations < params.max_iterations {
+                    counts[result.iterations as usize] += 1;
+                }
+            }
+        }
+        Some(IterationHistogram::from_counts(&counts))
+    } else {
+        None
+    };
+
+    for (index, (params, &palette)) in frames.iter().zip(palettes).enumerate() {
+        let degree = escape_degree(&params.formula);
+        let color = |result: &IterationResult| color_for_result_equalized(result, degree, params, palette, histogram.as_ref());
+        let results = iterate_frame(width, height, params, &iteration_func);
+        params.cancel.check()?;
+        let mut colors: Vec<Rgba<u8>> = results.par_iter().map(color).collect();
+        if params.antialiasing != AntiAliasing::None {
+            antialias::refine_colors(&mut colors, width, height, params.bounds, &params.antialiasing, |c| {
+                color(&iteration_func(c, params))
+            });
+        }
+        let mut frame = RgbaImage::from_raw(width, height, colors.iter().flat_map(|color| color.0).collect())
+            .expect("one color per pixel");
+        postfx::apply_post_effects(&mut frame, &params.post_effects);
+        output.write_frame_with_metadata(&frame, params, palette)?;
+
+        // Standard output may be carrying the video
+        let message = format!("Frame {}/{} done", index + 1, frames.len());
+        if !matches!(output.target, Target::Directory(_)) {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_y4m_stream_layout() {
+        assert_eq!(rgb_to_ycbcr(&Rgba([0, 0, 0, 255])), [16, 128, 128]);
+        assert_eq!(rgb_to_ycbcr(&Rgba([255, 255, 255, 255])), [235, 128, 128]);
+        assert_eq!(rgb_to_ycbcr(&Rgba([255, 255, 255, 0])), [16, 128, 128]);
+
+        let path = std::env::temp_dir().join(format!("ftk_sequence_{}.y4m", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        assert_eq!(SequenceFormat::from_path(&path), Ok(SequenceFormat::Y4m));
+        let checksummed = OutputOptions { checksum: true, ..OutputOptions::default() };
+        let mut writer = FrameSequenceWriter::create(&path, SequenceFormat::Y4m, 3, 2, 24).unwrap().with_output_options(checksummed);
+        let frame = RgbaImage::from_pixel(3, 2, Rgba([255, 255, 255, 255]));
+        writer.write_frame(&frame).unwrap();
+        writer.write_frame(&frame).unwrap();
+        assert!(writer.write_frame(&RgbaImage::new(2, 2)).is_err());
+        // The stream only appears under its name once it is complete
+        assert!(!std::path::Path::new(&path).exists());
+        assert_eq!(writer.finish(), Ok(2));
+        assert_eq!(crate::output::verify_checksum(&path), Ok(()));
+
+        let bytes = std::fs::read(&path).unwrap();
+        let header = b"YUV4MPEG2 W3 H2 F24:1 Ip A1:1 C444\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 2 * (6 + 3 * 6));
+        assert_eq!(bytes[header.len() + 6], 235);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(crate::output::checksum_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_frame_metadata_sidecars() {
+        let directory = std::env::temp_dir().join(format!("ftk_sequence_meta_{}", std::process::id()));
+        let path = directory.to_string_lossy().into_owned();
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 20, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let frames = [params.clone(), FractalParams { max_iterations: 40, ..params }];
+        let formula = crate::CompiledFormula::new("z^2 + c");
+        let mut writer = FrameSequenceWriter::create(&path, SequenceFormat::Frames, 4, 3, 10).unwrap().with_metadata(true).unwrap();
+        render_sequence(4, 3, &frames, |c, p| crate::mandelbrot_result_compiled(c, p, &formula), None, &mut writer).unwrap();
+        assert_eq!(writer.finish(), Ok(2));
+
+        let manifest: SequenceManifest = serde_json::from_str(&std::fs::read_to_string(directory.join("manifest.json")).unwrap()).unwrap();
+        assert_eq!(manifest.frames.len(), 2);
+        assert_eq!(manifest.frames[1].image.as_deref(), Some("frame_00001.png"));
+        let sidecar: FrameMetadata = serde_json::from_str(&std::fs::read_to_string(directory.join(&manifest.frames[1].metadata)).unwrap()).unwrap();
+        assert_eq!((sidecar.index, sidecar.time, sidecar.params.max_iterations), (1, 0.1, 40));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(FrameSequenceWriter::create("-", SequenceFormat::Y4m, 4, 3, 10).unwrap().with_metadata(true).is_err());
+    }
+}
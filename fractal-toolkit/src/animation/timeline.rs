@@ -0,0 +1,369 @@
+//! Keyframed timelines of render parameters
+//!
+//! A [`Timeline`] holds one track per animated parameter: the center of the view, its zoom,
+//! the Julia constant, the value of i², the palette offset or the bailout. Each track passes
+//! through its keyframes, linearly, along a cubic spline or in steps, and every keyframe's
+//! easing shapes the pace of the segment that starts at it. Parameters without a track keep
+//! the value they have in the base parameters, so a timeline can move the view and morph the
+//! constant independently, on keyframes of their own.
+//!
+//! Zoom tracks interpolate the logarithm of the magnification, so a zoom feels equally fast at
+//! every depth, as in `zoom_path`.
+//!
+//! Timelines are written one track per line (or separated by `;`): the parameter, optionally
+//! the interpolation, and keyframes as `time=value` with an optional `/easing`, e.g.
+//!
+//! ```text
+//! zoom spline 0=1 4=50/smooth 10=2000
+//! center.re 0=-0.5 10=-0.7435
+//! palette-offset 0=0 10=3
+//! ```
+//!
+//! They also (de)serialize with serde, for JSON project files.
+
+use super::sequence::{render_frames, FrameSequenceWriter};
+use crate::palette::rotate_palette;
+use crate::{ColorStop, Easing, FractalError, FractalParams, IterationResult};
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+/// A parameter a timeline can animate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Channel {
+    /// Real part of the center of the view
+    CenterRe,
+    /// Imaginary part of the center of the view
+    CenterIm,
+    /// Magnification relative to the base view, 1 showing the base bounds
+    Zoom,
+    /// Real part of the Julia constant
+    SpawnRe,
+    /// Imaginary part of the Julia constant
+    SpawnIm,
+    /// Real part of i²
+    ISquaredRe,
+    /// Imaginary part of i²
+    ISquaredIm,
+    /// Shift of the palette along itself, in palette lengths (see `rotate_palette`)
+    PaletteOffset,
+    /// Escape radius
+    Bailout,
+}
+
+impl Channel {
+    /// Parse a parameter name: `center.re`, `center.im`, `zoom`, `spawn.re`, `spawn.im`,
+    /// `i2.re`, `i2.im`, `palette-offset` or `bailout`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().replace('_', "-").as_str() {
+            "center.re" | "re" => Ok(Channel::CenterRe),
+            "center.im" | "im" => Ok(Channel::CenterIm),
+            "zoom" => Ok(Channel::Zoom),
+            "spawn.re" => Ok(Channel::SpawnRe),
+            "spawn.im" => Ok(Channel::SpawnIm),
+            "i2.re" => Ok(Channel::ISquaredRe),
+            "i2.im" => Ok(Channel::ISquaredIm),
+            "palette-offset" => Ok(Channel::PaletteOffset),
+            "bailout" => Ok(Channel::Bailout),
+            other => Err(format!(
+                "Unknown timeline parameter '{}' (expected center.re, center.im, zoom, spawn.re, spawn.im, i2.re, i2.im, palette-offset or bailout)",
+                other
+            )),
+        }
+    }
+}
+
+/// How a track moves between its keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Straight lines between keyframes
+    #[default]
+    Linear,
+    /// A cubic (Catmull-Rom) spline through the keyframes, with no kinks at them
+    Spline,
+    /// Hold each keyframe's value until the next keyframe
+    Step,
+}
+
+impl Interpolation {
+    /// Parse an interpolation name: `linear`, `spline` (or `cubic`) or `step`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "spline" | "cubic" => Ok(Interpolation::Spline),
+            "step" => Ok(Interpolation::Step),
+            other => Err(format!("Unknown interpolation '{}' (expected linear, spline or step)", other)),
+        }
+    }
+}
+
+/// The value of a parameter at one time
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Time of the keyframe, in any unit shared by the whole timeline (e.g. seconds)
+    pub time: f64,
+    /// Value of the parameter
+    pub value: f64,
+    /// Pace of the segment from this keyframe to the next
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// The keyframes of one parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    /// The animated parameter
+    pub channel: Channel,
+    /// How values between keyframes are found
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// Keyframes, in increasing time
+    pub keys: Vec<Keyframe>,
+}
+
+impl Track {
+    /// The value of the track at `time`, holding the first and last values outside the keyframes
+    pub fn value(&self, time: f64) -> f64 {
+        // Zooms move geometrically
+        let logarithmic = self.channel == Channel::Zoom;
+        let value_at = |index: usize| if logarithmic { self.keys[index].value.ln() } else { self.keys[index].value };
+        let last = self.keys.len() - 1;
+        let segment = self.keys.partition_point(|key| key.time <= time);
+        let value = if segment == 0 {
+            value_at(0)
+        } else if segment > last {
+            value_at(last)
+        } else {
+            let (start, end) = (&self.keys[segment - 1], &self.keys[segment]);
+            let t = start.easing.apply((time - start.time) / (end.time - start.time));
+            let (p1, p2) = (value_at(segment - 1), value_at(segment));
+            match self.interpolation {
+                Interpolation::Linear => p1 + (p2 - p1) * t,
+                Interpolation::Step => p1,
+                Interpolation::Spline => {
+                    // Cubic Hermite segment with Catmull-Rom tangents, scaled for uneven key spacing
+                    let tangent = |index: usize| {
+                        let (before, after) = (index.saturating_sub(1), (index + 1).min(last));
+                        (value_at(after) - value_at(before)) / (self.keys[after].time - self.keys[before].time)
+                    };
+                    let span = end.time - start.time;
+                    let (m1, m2) = (tangent(segment - 1) * span, tangent(segment) * span);
+                    let (t2, t3) = (t * t, t * t * t);
+                    (2.0 * t3 - 3.0 * t2 + 1.0) * p1 + (t3 - 2.0 * t2 + t) * m1 + (-2.0 * t3 + 3.0 * t2) * p2 + (t3 - t2) * m2
+                }
+            }
+        };
+        if logarithmic {
+            value.exp()
+        } else {
+            value
+        }
+    }
+}
+
+/// Parameters of one frame of a timeline
+#[derive(Debug, Clone)]
+pub struct TimelineFrame {
+    /// Render parameters, with the animated values filled in
+    pub params: FractalParams,
+    /// Palette offset of the frame (0 without a palette-offset track)
+    pub palette_offset: f64,
+}
+
+/// Tracks of keyframed parameters
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Timeline {
+    /// One track per animated parameter
+    pub tracks: Vec<Track>,
+}
+
+impl Timeline {
+    /// Parse a timeline written one track per line or `;`-separated, as in the module docs, or
+    /// as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Tracks such as `zoom spline 0=1 4=50/smooth 10=2000`, or a JSON object
+    ///
+    /// # Returns
+    ///
+    /// The timeline, or an error for malformed tracks, unsorted keyframes, a parameter with two
+    /// tracks, or a zoom that isn't positive
+    pub fn parse(text: &str) -> Result<Self, String> {
+        if text.trim_start().starts_with('{') {
+            let timeline: Timeline = serde_json::from_str(text).map_err(|e| format!("Invalid timeline JSON: {}", e))?;
+            timeline.validate()?;
+            return Ok(timeline);
+        }
+        let mut tracks = Vec::new();
+        for line in text.split(['\n', ';']).map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let mut words = line.split_whitespace();
+            let channel = Channel::parse(words.next().unwrap_or_default())?;
+            let mut interpolation = Interpolation::default();
+            let mut keys = Vec::new();
+            for word in words {
+                let Some((time, rest)) = word.split_once('=') else {
+                    interpolation = Interpolation::parse(word)?;
+                    continue;
+                };
+                let (value, easing) = match rest.split_once('/') {
+                    Some((value, easing)) => (value, Easing::parse(easing)?),
+                    None => (rest, Easing::Linear),
+                };
+                let number = |text: &str| text.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in keyframe '{}'", text, word));
+                keys.push(Keyframe { time: number(time)?, value: number(value)?, easing });
+            }
+            tracks.push(Track { channel, interpolation, keys });
+        }
+        let timeline = Timeline { tracks };
+        timeline.validate()?;
+        Ok(timeline)
+    }
+
+    /// Check that every track has sorted, finite keyframes and that no parameter has two tracks
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, track) in self.tracks.iter().enumerate() {
+            if track.keys.is_empty() {
+                return Err(format!("The {:?} track has no keyframes", track.channel));
+            }
+            if track.keys.iter().any(|key| !key.time.is_finite() || !key.value.is_finite()) {
+                return Err(format!("The {:?} track has a keyframe that isn't finite", track.channel));
+            }
+            if track.keys.windows(2).any(|pair| pair[1].time <= pair[0].time) {
+                return Err(format!("The keyframes of the {:?} track must be in increasing time", track.channel));
+            }
+            if track.channel == Channel::Zoom && track.keys.iter().any(|key| key.value <= 0.0) {
+                return Err("Zoom keyframes must be positive".to_string());
+            }
+            if self.tracks[..index].iter().any(|other| other.channel == track.channel) {
+                return Err(format!("The {:?} parameter has more than one track", track.channel));
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of a parameter at `time`, or `None` if it has no track
+    pub fn value(&self, channel: Channel, time: f64) -> Option<f64> {
+        self.tracks.iter().find(|track| track.channel == channel).map(|track| track.value(time))
+    }
+
+    /// Time of the last keyframe of any track
+    pub fn duration(&self) -> f64 {
+        self.tracks.iter().filter_map(|track| track.keys.last()).map(|key| key.time).fold(0.0, f64::max)
+    }
+
+    /// The parameters at `time`
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Values of the parameters without a track; the zoom is relative to its bounds
+    /// * `time` - Time on the timeline
+    pub fn frame(&self, base: &FractalParams, time: f64) -> TimelineFrame {
+        let value = |channel, default: f64| self.value(channel, time).unwrap_or(default);
+        let [x_min, x_max, y_min, y_max] = base.bounds;
+        let zoom = value(Channel::Zoom, 1.0);
+        let center = (value(Channel::CenterRe, (x_min + x_max) / 2.0), value(Channel::CenterIm, (y_min + y_max) / 2.0));
+        let (half_width, half_height) = ((x_max - x_min) / (2.0 * zoom), (y_max - y_min) / (2.0 * zoom));
+
+        let mut params = base.clone();
+        params.bounds = [center.0 - half_width, center.0 + half_width, center.1 - half_height, center.1 + half_height];
+        params.spawn = Complex::new(value(Channel::SpawnRe, base.spawn.re), value(Channel::SpawnIm, base.spawn.im));
+        params.bailout = value(Channel::Bailout, base.bailout);
+        if self.value(Channel::ISquaredRe, time).is_some() || self.value(Channel::ISquaredIm, time).is_some() {
+            // The default i_sqrt_value of i stands for the standard i² = -1
+            let i_squared = if base.i_sqrt_value == Complex::new(0.0, 1.0) { Complex::new(-1.0, 0.0) } else { base.i_sqrt_value };
+            params.i_sqrt_value = Complex::new(value(Channel::ISquaredRe, i_squared.re), value(Channel::ISquaredIm, i_squared.im));
+        }
+        TimelineFrame { params, palette_offset: value(Channel::PaletteOffset, 0.0) }
+    }
+
+    /// The parameters of every frame, at evenly spaced times from 0 to the duration
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Values of the parameters without a track
+    /// * `frames` - Number of frames; the first shows time 0 and the last the duration
+    pub fn frames(&self, base: &FractalParams, frames: u32) -> Vec<TimelineFrame> {
+        let step = if frames > 1 { self.duration() / (frames - 1) as f64 } else { 0.0 };
+        (0..frames).map(|frame| self.frame(base, frame as f64 * step)).collect()
+    }
+}
+
+/// Render a timeline and write the frames as they finish
+///
+/// # Arguments
+///
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `params` - Base parameters of every frame
+/// * `timeline` - Keyframed parameters
+/// * `frames` - Number of frames, at least one
+/// * `iteration_func` - Iteration of one point, e.g. `mandelbrot_result_compiled`
+/// * `palette` - Optional color palette, shifted by the palette-offset track
+/// * `output` - Where the frames go
+///
+/// # Returns
+///
+/// Ok(()) once every frame is written, or an error
+#[allow(clippy::too_many_arguments)]
+pub fn render_timeline<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    timeline: &Timeline,
+    frames: u32,
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+    output: &mut FrameSequenceWriter,
+) -> Result<(), FractalError>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    if frames == 0 {
+        return Err(FractalError::InvalidInput("A timeline needs at least one frame".to_string()));
+    }
+    timeline.validate().map_err(FractalError::InvalidInput)?;
+    let (frame_params, palettes): (Vec<FractalParams>, Vec<Option<Vec<ColorStop>>>) = timeline
+        .frames(params, frames)
+        .into_iter()
+        .map(|frame| (frame.params, palette.map(|palette| rotate_palette(palette, frame.palette_offset))))
+        .unzip();
+    let palettes: Vec<Option<&[ColorStop]>> = palettes.iter().map(Option::as_deref).collect();
+    render_frames(width, height, &frame_params, iteration_func, &palettes, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_pass_through_keyframes() {
+        let timeline = Timeline::parse("zoom spline 0=1 4=100/smooth 10=1e4; spawn.re 0=-0.8 10=-0.7\ni2.re step 0=-1 5=1").unwrap();
+        let base = FractalParams::new([-2.0, 2.0, -1.0, 1.0], 100, [-0.8, 0.2], 2.0, "z^2 + c".to_string());
+        assert_eq!(timeline.duration(), 10.0);
+
+        // Keyframe values are hit exactly, and zooms move geometrically between them
+        assert!((timeline.value(Channel::Zoom, 4.0).unwrap() - 100.0).abs() < 1e-9);
+        let frame = timeline.frame(&base, 10.0);
+        assert!((frame.params.bounds[1] - frame.params.bounds[0] - 4e-4).abs() < 1e-12);
+        assert!((frame.params.spawn - Complex::new(-0.7, 0.2)).norm() < 1e-12);
+        assert!((timeline.value(Channel::SpawnRe, 5.0).unwrap() + 0.75).abs() < 1e-12);
+        assert_eq!(timeline.frame(&base, 4.9).params.i_sqrt_value, Complex::new(-1.0, 0.0));
+        assert_eq!(timeline.frame(&base, 5.0).params.i_sqrt_value, Complex::new(1.0, 0.0));
+
+        // The spline has no kink at a keyframe
+        let slope = |t: f64| (timeline.value(Channel::Zoom, t + 1e-6).unwrap().ln() - timeline.value(Channel::Zoom, t - 1e-6).unwrap().ln()) / 2e-6;
+        let zoom = &timeline.tracks[0];
+        assert_eq!(zoom.interpolation, Interpolation::Spline);
+        assert!(slope(3.999) > 0.0 && slope(4.001) > 0.0);
+
+        let frames = timeline.frames(&base, 11);
+        assert_eq!(frames[0].params.bounds, base.bounds);
+        assert!(Timeline::parse("zoom 0=1 0=2").is_err());
+        assert!(Timeline::parse("zoom 0=0").is_err());
+        assert!(Timeline::parse("speed 0=1").is_err());
+        let json = serde_json::to_string(&timeline).unwrap();
+        assert_eq!(Timeline::parse(&json), Ok(timeline));
+    }
+}
@@ -0,0 +1,163 @@
+//! Zoom animations through a list of keyframe views
+//!
+//! Between two keyframes the width and height of the view change geometrically, so the zoom
+//! feels equally fast at every magnification, and the center moves so that one point of the
+//! plane stays fixed on screen: the one both views share. A plain zoom into a point therefore
+//! never drifts sideways, however deep it goes. The easing shapes the pace of each segment, for
+//! instance slowing down into and out of every keyframe with `Easing::Smoothstep`.
+
+use super::sequence::{render_sequence, FrameSequenceWriter};
+use crate::{ColorStop, Easing, FractalError, FractalParams, IterationResult};
+use num_complex::Complex;
+
+/// The view between two keyframes
+///
+/// # Arguments
+///
+/// * `from` - Bounds [x_min, x_max, y_min, y_max] at the start of the segment
+/// * `to` - Bounds at the end of the segment
+/// * `t` - Position in the segment, from 0 at `from` to 1 at `to`
+///
+/// # Returns
+///
+/// The interpolated bounds
+pub fn interpolate_bounds(from: [f64; 4], to: [f64; 4], t: f64) -> [f64; 4] {
+    // Per axis: size s(t) = s0^(1-t) s1^t, and center c(t) = c0 + (c1 - c0)(s0 - s(t)) / (s0 - s1),
+    // which keeps the point p = c0 + (c1 - c0) s0 / (s0 - s1) at the same place on screen
+    let axis = |min0: f64, max0: f64, min1: f64, max1: f64| -> (f64, f64) {
+        let (size0, size1) = ((max0 - min0).abs(), (max1 - min1).abs());
+        let (center0, center1) = ((min0 + max0) / 2.0, (min1 + max1) / 2.0);
+        let size = size0.powf(1.0 - t) * size1.powf(t);
+        let center = if (size0 - size1).abs() > 1e-12 * size0.max(size1) {
+            center0 + (center1 - center0) * (size0 - size) / (size0 - size1)
+        } else {
+            center0 + (center1 - center0) * t
+        };
+        // Keep the orientation of the start view
+        let half = (max0 - min0).signum() * size / 2.0;
+        (center - half, center + half)
+    };
+    let (x_min, x_max) = axis(from[0], from[1], to[0], to[1]);
+    let (y_min, y_max) = axis(from[2], from[3], to[2], to[3]);
+    [x_min, x_max, y_min, y_max]
+}
+
+/// Bounds of every frame of a zoom through keyframes
+///
+/// The segments between consecutive keyframes get equal numbers of frames, and the first and
+/// last frames show the first and last keyframe exactly.
+///
+/// # Arguments
+///
+/// * `keyframes` - Views to pass through, at least two
+/// * `frames` - Number of frames, at least two
+/// * `easing` - Pace of the motion within each segment
+///
+/// # Returns
+///
+/// The bounds of each frame, or an error for too few keyframes or frames, or a keyframe with
+/// zero width or height
+pub fn zoom_path(keyframes: &[[f64; 4]], frames: u32, easing: Easing) -> Result<Vec<[f64; 4]>, String> {
+    if keyframes.len() < 2 {
+        return Err("A zoom needs at least two keyframes".to_string());
+    }
+    if frames < 2 {
+        return Err("A zoom needs at least two frames".to_string());
+    }
+    if let Some(flat) = keyframes.iter().find(|b| b[0] == b[1] || b[2] == b[3] || b.iter().any(|v| !v.is_finite())) {
+        return Err(format!("Keyframe {:?} has no area", flat));
+    }
+
+    let segments = keyframes.len() - 1;
+    Ok((0..frames)
+        .map(|frame| {
+            let position = frame as f64 / (frames - 1) as f64 * segments as f64;
+            let segment = (position.floor() as usize).min(segments - 1);
+            let t = easing.apply(position - segment as f64);
+            interpolate_bounds(keyframes[segment], keyframes[segment + 1], t)
+        })
+        .collect())
+}
+
+/// Parse keyframes written as `x_min,x_max,y_min,y_max` separated by `;`
+pub fn parse_keyframes(text: &str) -> Result<Vec<[f64; 4]>, String> {
+    text.split(';')
+        .filter(|keyframe| !keyframe.trim().is_empty())
+        .map(|keyframe| {
+            let values: Vec<f64> = keyframe
+                .split(',')
+                .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in keyframe '{}'", value.trim(), keyframe.trim())))
+                .collect::<Result<_, _>>()?;
+            <[f64; 4]>::try_from(values).map_err(|_| format!("Keyframe '{}' must have 4 values: x_min,x_max,y_min,y_max", keyframe.trim()))
+        })
+        .collect()
+}
+
+/// Render a zoom through keyframes and write the frames as they finish
+///
+/// # Arguments
+///
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `params` - Parameters of every frame; the bounds are replaced by those along the zoom
+/// * `keyframes` - Views to pass through, at least two
+/// * `frames` - Number of frames
+/// * `easing` - Pace of the motion within each segment
+/// * `iteration_func` - Iteration of one point, e.g. `mandelbrot_result_compiled`
+/// * `palette` - Optional color palette
+/// * `output` - Where the frames go
+///
+/// # Returns
+///
+/// Ok(()) once every frame is written, or an error
+#[allow(clippy::too_many_arguments)]
+pub fn render_zoom_animation<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    keyframes: &[[f64; 4]],
+    frames: u32,
+    easing: Easing,
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+    output: &mut FrameSequenceWriter,
+) -> Result<(), FractalError>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    let frame_params: Vec<FractalParams> = zoom_path(keyframes, frames, easing).map_err(FractalError::InvalidInput)?
+        .into_iter()
+        .map(|bounds| FractalParams { bounds, ..params.clone() })
+        .collect();
+    render_sequence(width, height, &frame_params, iteration_func, palette, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_keeps_shared_point_fixed() {
+        let start = [-2.0, 2.0, -2.0, 2.0];
+        let end = [0.2, 0.4, 0.5, 0.7];
+        let path = zoom_path(&[start, end], 11, Easing::Linear).unwrap();
+        assert_eq!(path[0], start);
+        assert!(path[10].iter().zip(end).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        // Widths shrink by the same factor every frame
+        let widths: Vec<f64> = path.iter().map(|b| b[1] - b[0]).collect();
+        assert!(((widths[1] / widths[0]) - (widths[7] / widths[6])).abs() < 1e-12);
+
+        // The fixed point sits at the same fraction of every frame
+        let fixed = (0.0 + (0.3 - 0.0) * 4.0 / (4.0 - 0.2), 0.0 + (0.6 - 0.0) * 4.0 / (4.0 - 0.2));
+        for bounds in &path {
+            let u = (fixed.0 - bounds[0]) / (bounds[1] - bounds[0]);
+            let v = (fixed.1 - bounds[2]) / (bounds[3] - bounds[2]);
+            assert!((u - (fixed.0 + 2.0) / 4.0).abs() < 1e-9 && (v - (fixed.1 + 2.0) / 4.0).abs() < 1e-9);
+        }
+
+        assert_eq!(parse_keyframes("-2,2,-2,2; 0.2,0.4,0.5,0.7").unwrap(), vec![start, end]);
+        assert!(parse_keyframes("1,2,3").is_err());
+        assert!(zoom_path(&[start], 10, Easing::Linear).is_err());
+    }
+}
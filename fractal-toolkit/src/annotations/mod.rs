@@ -0,0 +1,252 @@
+//! Labelled points and regions of the complex plane, shared as GeoJSON
+//!
+//! Exploration sessions turn up places worth returning to: a minibrot, a spiral, a region to
+//! render at higher resolution. Annotations record them as labelled points and rectangles in
+//! complex-plane coordinates, in a GeoJSON `FeatureCollection` where `[re, im]` stands in for
+//! `[longitude, latitude]`, so other tools and scripts can read them. Each feature carries its
+//! label in `properties.label`, and the collection is marked with
+//! `"coordinate_space": "complex-plane"`.
+//!
+//! Annotations can be drawn over any render as outlines (regions) and crosshairs (points).
+//! Labels are not drawn; `draw_annotations` returns where each one landed in the image instead.
+
+use crate::FractalError;
+use image::{Rgba, RgbaImage};
+use num_complex::Complex;
+use serde_json::{json, Value};
+
+/// Value of the collection's `coordinate_space` member
+pub const COORDINATE_SPACE: &str = "complex-plane";
+
+/// Half the length in pixels of the arms of a point's crosshair
+const MARKER_RADIUS: i64 = 6;
+
+/// What an annotation marks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnnotationShape {
+    /// A single point of the plane
+    Point(Complex<f64>),
+    /// A rectangle [x_min, x_max, y_min, y_max]
+    Region([f64; 4]),
+}
+
+/// A labelled point or region
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// Text describing the place
+    pub label: String,
+    /// The place
+    pub shape: AnnotationShape,
+}
+
+impl Annotation {
+    /// Parse `label@re,im` for a point or `label@x_min,x_max,y_min,y_max` for a region
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (label, coordinates) = text
+            .rsplit_once('@')
+            .ok_or_else(|| format!("Annotation '{}' must be label@re,im or label@x_min,x_max,y_min,y_max", text.trim()))?;
+        let values: Vec<f64> = coordinates
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in annotation '{}'", value.trim(), text.trim())))
+            .collect::<Result<_, _>>()?;
+        let shape = match values[..] {
+            [re, im] => AnnotationShape::Point(Complex::new(re, im)),
+            [x_min, x_max, y_min, y_max] => AnnotationShape::Region([x_min.min(x_max), x_min.max(x_max), y_min.min(y_max), y_min.max(y_max)]),
+            _ => return Err(format!("Annotation '{}' needs 2 coordinates for a point or 4 for a region", text.trim())),
+        };
+        Ok(Self { label: label.trim().to_string(), shape })
+    }
+
+    /// The annotation as a GeoJSON feature
+    pub fn to_feature(&self) -> Value {
+        let geometry = match self.shape {
+            AnnotationShape::Point(z) => json!({ "type": "Point", "coordinates": [z.re, z.im] }),
+            AnnotationShape::Region([x_min, x_max, y_min, y_max]) => json!({
+                "type": "Polygon",
+                "coordinates": [[[x_min, y_min], [x_max, y_min], [x_max, y_max], [x_min, y_max], [x_min, y_min]]],
+            }),
+        };
+        json!({ "type": "Feature", "geometry": geometry, "properties": { "label": self.label } })
+    }
+
+    /// Read an annotation from a GeoJSON feature
+    ///
+    /// Polygons become the rectangle bounding their outer ring, so hand-drawn shapes are kept
+    /// as the region they cover.
+    ///
+    /// # Returns
+    ///
+    /// The annotation, or an error for geometry other than a point or polygon
+    pub fn from_feature(feature: &Value) -> Result<Self, String> {
+        let label = feature["properties"]["label"].as_str().unwrap_or_default().to_string();
+        let geometry = &feature["geometry"];
+        let position = |value: &Value| -> Result<(f64, f64), String> {
+            match (value[0].as_f64(), value[1].as_f64()) {
+                (Some(re), Some(im)) => Ok((re, im)),
+                _ => Err(format!("Invalid position {} in annotation '{}'", value, label)),
+            }
+        };
+        let shape = match geometry["type"].as_str() {
+            Some("Point") => {
+                let (re, im) = position(&geometry["coordinates"])?;
+                AnnotationShape::Point(Complex::new(re, im))
+            }
+            Some("Polygon") => {
+                let ring = geometry["coordinates"][0]
+                    .as_array()
+                    .filter(|ring| !ring.is_empty())
+                    .ok_or_else(|| format!("Polygon of annotation '{}' has no outer ring", label))?;
+                let mut region = [f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY];
+                for value in ring {
+                    let (re, im) = position(value)?;
+                    region = [region[0].min(re), region[1].max(re), region[2].min(im), region[3].max(im)];
+                }
+                AnnotationShape::Region(region)
+            }
+            other => return Err(format!("Unsupported geometry {:?} in annotation '{}' (expected Point or Polygon)", other, label)),
+        };
+        Ok(Self { label, shape })
+    }
+}
+
+/// Annotations as a GeoJSON feature collection
+pub fn to_geojson(annotations: &[Annotation]) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "coordinate_space": COORDINATE_SPACE,
+        "features": annotations.iter().map(Annotation::to_feature).collect::<Vec<_>>(),
+    })
+}
+
+/// Read annotations from GeoJSON text
+///
+/// # Arguments
+///
+/// * `text` - A `FeatureCollection`, or a single `Feature`
+///
+/// # Returns
+///
+/// The annotations, or an error for invalid JSON, a collection in another coordinate space, or
+/// an unsupported feature
+pub fn from_geojson(text: &str) -> Result<Vec<Annotation>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid annotation file: {}", e))?;
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            if let Some(space) = value["coordinate_space"].as_str().filter(|&space| space != COORDINATE_SPACE) {
+                return Err(format!("Annotations are in '{}' coordinates, not '{}'", space, COORDINATE_SPACE));
+            }
+            value["features"]
+                .as_array()
+                .map(|features| features.iter().map(Annotation::from_feature).collect())
+                .unwrap_or_else(|| Ok(Vec::new()))
+        }
+        Some("Feature") => Ok(vec![Annotation::from_feature(&value)?]),
+        other => Err(format!("Unsupported annotation file type {:?} (expected FeatureCollection)", other)),
+    }
+}
+
+/// Write annotations to a GeoJSON file
+pub fn save_annotations(path: &str, annotations: &[Annotation]) -> Result<(), FractalError> {
+    let text = serde_json::to_string_pretty(&to_geojson(annotations)).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+    crate::output::write_atomic(path, text.as_bytes())
+}
+
+/// Read annotations from a GeoJSON file
+pub fn load_annotations(path: &str) -> Result<Vec<Annotation>, FractalError> {
+    let text = std::fs::read_to_string(path).map_err(|e| FractalError::io("read", path, e))?;
+    from_geojson(&text).map_err(FractalError::InvalidInput)
+}
+
+/// Draw annotations over a render
+///
+/// Regions are outlined and points marked with a crosshair; parts outside the image are
+/// clipped.
+///
+/// # Arguments
+///
+/// * `image` - The render
+/// * `bounds` - Bounds [x_min, x_max, y_min, y_max] the render shows
+/// * `annotations` - What to draw
+/// * `color` - Color of the outlines and crosshairs
+///
+/// # Returns
+///
+/// The label and pixel position (the center of a region) of each annotation that is at least
+/// partly visible, for listing or labelling
+pub fn draw_annotations(image: &mut RgbaImage, bounds: [f64; 4], annotations: &[Annotation], color: Rgba<u8>) -> Vec<(String, (i64, i64))> {
+    let (width, height) = image.dimensions();
+    // The inverse of pixel_to_complex
+    let to_pixel = |re: f64, im: f64| -> (i64, i64) {
+        let x = (re - bounds[0]) / (bounds[1] - bounds[0]) * width.saturating_sub(1) as f64;
+        let y = (im - bounds[2]) / (bounds[3] - bounds[2]) * height.saturating_sub(1) as f64;
+        (x.round().clamp(-1e9, 1e9) as i64, y.round().clamp(-1e9, 1e9) as i64)
+    };
+    let mut put = |x: i64, y: i64| {
+        if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+            image.put_pixel(x as u32, y as u32, color);
+        }
+    };
+
+    let mut visible = Vec::new();
+    for annotation in annotations {
+        let (anchor, (x0, y0, x1, y1)) = match annotation.shape {
+            AnnotationShape::Point(z) => {
+                let (x, y) = to_pixel(z.re, z.im);
+                for offset in -MARKER_RADIUS..=MARKER_RADIUS {
+                    put(x + offset, y);
+                    put(x, y + offset);
+                }
+                ((x, y), (x - MARKER_RADIUS, y - MARKER_RADIUS, x + MARKER_RADIUS, y + MARKER_RADIUS))
+            }
+            AnnotationShape::Region([x_min, x_max, y_min, y_max]) => {
+                let (ax, ay) = to_pixel(x_min, y_min);
+                let (bx, by) = to_pixel(x_max, y_max);
+                let (x0, x1, y0, y1) = (ax.min(bx), ax.max(bx), ay.min(by), ay.max(by));
+                // Two pixels wide, growing inwards; only the visible part of the outline is walked
+                for line in 0..2 {
+                    for x in x0.max(-1)..=x1.min(width as i64) {
+                        put(x, y0 + line);
+                        put(x, y1 - line);
+                    }
+                    for y in y0.max(-1)..=y1.min(height as i64) {
+                        put(x0 + line, y);
+                        put(x1 - line, y);
+                    }
+                }
+                (((x0 + x1) / 2, (y0 + y1) / 2), (x0, y0, x1, y1))
+            }
+        };
+        if x1 >= 0 && y1 >= 0 && x0 < width as i64 && y0 < height as i64 {
+            visible.push((annotation.label.clone(), anchor));
+        }
+    }
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_annotations_round_trip_and_draw() {
+        let annotations = vec![
+            Annotation::parse("Seahorse valley@-0.75,0.1").unwrap(),
+            Annotation::parse("Minibrot @ -1.8,-1.7,-0.05,0.05").unwrap(),
+        ];
+        assert_eq!(annotations[1].label, "Minibrot");
+        let text = serde_json::to_string(&to_geojson(&annotations)).unwrap();
+        assert_eq!(from_geojson(&text).unwrap(), annotations);
+        assert_eq!(to_geojson(&annotations)["features"][1]["geometry"]["type"], "Polygon");
+        assert!(Annotation::parse("nowhere@1,2,3").is_err());
+        assert!(from_geojson(r#"{"type": "FeatureCollection", "coordinate_space": "EPSG:4326", "features": []}"#).is_err());
+
+        let mut image = RgbaImage::new(41, 41);
+        let white = Rgba([255, 255, 255, 255]);
+        let visible = draw_annotations(&mut image, [-2.0, 2.0, -2.0, 2.0], &annotations, white);
+        // (-0.75, 0.1) is pixel (12.5, 21) before rounding
+        assert_eq!(visible[0], ("Seahorse valley".to_string(), (13, 21)));
+        assert_eq!(*image.get_pixel(13, 21), white);
+        assert_eq!(*image.get_pixel(2, 20), white);
+        assert_eq!(*image.get_pixel(30, 30), Rgba([0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,184 @@
+//! Anti-aliasing for escape-time renders
+//!
+//! Coloring one point per pixel leaves jagged, flickering edges wherever the color changes
+//! faster than the pixel grid, such as on the boundary of the set and along thin filaments.
+//! Averaging several samples spread over each pixel's area smooths them out:
+//!
+//! - `Supersample` takes an NxN grid of samples in every pixel, N² times the cost of the render
+//! - `Adaptive` only resamples pixels whose color differs from a neighbour's by more than a
+//!   threshold, which is usually a small fraction of the image
+//!
+//! Samples are colored like the pixel centers were, including histogram coloring (which keeps
+//! the histogram of the centers), and the colors are averaged.
+
+use image::Rgba;
+use num_complex::Complex;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How pixels are sampled
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AntiAliasing {
+    /// One sample at the center of each pixel
+    #[default]
+    None,
+    /// `samples` x `samples` samples in every pixel
+    Supersample {
+        /// Samples along each axis
+        samples: u32,
+    },
+    /// `samples` x `samples` samples in pixels on an edge, one elsewhere
+    Adaptive {
+        /// Samples along each axis in refined pixels
+        samples: u32,
+        /// Largest difference of any color channel, as a fraction of the full range (0 to 1),
+        /// between a pixel and its neighbours before the pixel is refined
+        threshold: f64,
+    },
+}
+
+impl AntiAliasing {
+    /// Parse an anti-aliasing mode
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - `none`, `NxN` (or just `N`) for supersampling, or `adaptive`, `adaptive:NxN` or
+    ///   `adaptive:NxN:threshold` (defaults 4x4 and 0.1)
+    ///
+    /// # Returns
+    ///
+    /// The mode, or an error for malformed input or fewer than 1 sample per axis
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parse_samples = |grid: &str| -> Result<u32, String> {
+            let (across, down) = grid.split_once(['x', 'X']).unwrap_or((grid, grid));
+            let samples: u32 = across.trim().parse().map_err(|_| format!("Invalid sample grid: '{}'", grid))?;
+            if down.trim().parse::<u32>().ok() != Some(samples) || samples == 0 {
+                return Err(format!("Sample grid must be NxN with N at least 1, found '{}'", grid));
+            }
+            Ok(samples)
+        };
+
+        let text = text.trim().to_lowercase();
+        let mut parts = text.split(':');
+        match parts.next().unwrap_or("") {
+            "none" | "off" | "" => Ok(AntiAliasing::None),
+            "adaptive" => {
+                let samples = parts.next().map(parse_samples).transpose()?.unwrap_or(4);
+                let threshold = match parts.next() {
+                    Some(value) => value.parse().map_err(|_| format!("Invalid adaptive threshold: '{}'", value))?,
+                    None => 0.1,
+                };
+                Ok(AntiAliasing::Adaptive { samples, threshold })
+            }
+            grid => Ok(AntiAliasing::Supersample { samples: parse_samples(grid)? }),
+        }
+    }
+}
+
+/// Improve the colors of a rendered image by sampling each pixel's area more densely
+///
+/// # Arguments
+///
+/// * `colors` - Colors of the pixel centers in row-major order, replaced by the averages
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `bounds` - Complex plane bounds the image was rendered from [x_min, x_max, y_min, y_max],
+///   mapped to pixels as by `pixel_to_complex`
+/// * `mode` - Sampling to apply
+/// * `sample` - Color of the point at a complex coordinate
+///
+/// # Returns
+///
+/// The number of pixels that were resampled
+pub fn refine_colors<F>(colors: &mut [Rgba<u8>], width: u32, height: u32, bounds: [f64; 4], mode: &AntiAliasing, sample: F) -> usize
+where
+    F: Fn(Complex<f64>) -> Rgba<u8> + Sync,
+{
+    let (samples, refine): (u32, Vec<bool>) = match *mode {
+        AntiAliasing::None => return 0,
+        AntiAliasing::Supersample { samples } => (samples, vec![true; colors.len()]),
+        AntiAliasing::Adaptive { samples, threshold } => (samples, edge_pixels(colors, width as usize, height as usize, threshold)),
+    };
+    if samples <= 1 {
+        return 0;
+    }
+
+    let [x_min, x_max, y_min, y_max] = bounds;
+    let step_x = if width > 1 { (x_max - x_min) / (width - 1) as f64 } else { 0.0 };
+    let step_y = if height > 1 { (y_max - y_min) / (height - 1) as f64 } else { 0.0 };
+    let offsets: Vec<f64> = (0..samples).map(|i| (i as f64 + 0.5) / samples as f64 - 0.5).collect();
+
+    let refined: Vec<(usize, Rgba<u8>)> = (0..colors.len())
+        .into_par_iter()
+        .filter(|&index| refine[index])
+        .map(|index| {
+            let (x, y) = ((index % width as usize) as f64, (index / width as usize) as f64);
+            let mut sum = [0u32; 4];
+            for &dy in &offsets {
+                for &dx in &offsets {
+                    let color = sample(Complex::new(x_min + (x + dx) * step_x, y_min + (y + dy) * step_y));
+                    for channel in 0..4 {
+                        sum[channel] += color[channel] as u32;
+                    }
+                }
+            }
+            let count = samples * samples;
+            (index, Rgba(sum.map(|total| ((total + count / 2) / count) as u8)))
+        })
+        .collect();
+
+    let resampled = refined.len();
+    for (index, color) in refined {
+        colors[index] = color;
+    }
+    resampled
+}
+
+/// Pixels whose color differs from one of their eight neighbours by more than `threshold`
+fn edge_pixels(colors: &[Rgba<u8>], width: usize, height: usize, threshold: f64) -> Vec<bool> {
+    let limit = (threshold.clamp(0.0, 1.0) * 255.0) as i32;
+    (0..colors.len())
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let center = colors[index];
+            (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                (x.saturating_sub(1)..(x + 2).min(width)).any(|nx| {
+                    let neighbour = colors[ny * width + nx];
+                    (0..4).any(|channel| (center[channel] as i32 - neighbour[channel] as i32).abs() > limit)
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supersampling_softens_edges() {
+        assert_eq!(AntiAliasing::parse("3x3"), Ok(AntiAliasing::Supersample { samples: 3 }));
+        assert_eq!(AntiAliasing::parse("adaptive:2x2:0.2"), Ok(AntiAliasing::Adaptive { samples: 2, threshold: 0.2 }));
+        assert!(AntiAliasing::parse("2x3").is_err());
+
+        // A vertical edge through the middle of pixel 4 on a 9-pixel-wide image over [0, 8]
+        let sample = |c: Complex<f64>| if c.re < 4.0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) };
+        let render = |mode: AntiAliasing| {
+            let mut colors: Vec<Rgba<u8>> = (0..9 * 5).map(|i| sample(Complex::new((i % 9) as f64, 0.0))).collect();
+            let refined = refine_colors(&mut colors, 9, 5, [0.0, 8.0, 0.0, 4.0], &mode, sample);
+            (colors, refined)
+        };
+
+        let (full, refined) = render(AntiAliasing::Supersample { samples: 4 });
+        assert_eq!(refined, 45);
+        assert_eq!(full[4], Rgba([128, 128, 128, 255]));
+        assert_eq!(full[3], Rgba([0, 0, 0, 255]));
+
+        // Adaptive sampling only touches the columns next to the edge and gives the same result
+        let (adaptive, refined) = render(AntiAliasing::Adaptive { samples: 4, threshold: 0.1 });
+        assert_eq!(refined, 2 * 5);
+        assert_eq!(adaptive, full);
+    }
+}
@@ -1,5 +1,17 @@
 use clap::Parser;
-use fractal_toolkit::{BuddhabrotParams, BuddhabrotChannels, BuddhabrotChannel, generate_buddhabrot, generate_html_file};
+use fractal_toolkit::{BuddhabrotParams, BuddhabrotChannels, BuddhabrotChannel, buddhabrot_densities, generate_buddhabrot, generate_html_file, render_buddhabrot_densities, DensityGrid, FractalError, BUDDHABROT_SEED};
+use fractal_toolkit::output::{
+    record_render_hashes, save_image_atomic, write_hdr_image, write_scientific_densities, ColorProfile, OutputOptions,
+    DisplayTransform, HdrImage,
+};
+use fractal_toolkit::postfx::parse_post_effects;
+use fractal_toolkit::tiled::single_image_limit;
+use fractal_toolkit::quality::{apply_buddhabrot_profile, Profile};
+use fractal_toolkit::telemetry::{self, TelemetryOptions};
+use fractal_toolkit::buddhabrot::{
+    auto_sample_bounds, generate_buddhabrot_exposures, generate_buddhabrot_metropolis, generate_buddhabrot_roi, record_orbit_stream, ChannelMix,
+    DenoiseOptions, ExposureBlend, MetropolisOptions, OrbitInterpolation, OrbitMode, OrbitStream, RebinOptions, RoiSamplingOptions, WhiteBalance,
+};
 use rayon::ThreadPoolBuilder;
 use num_complex::Complex;
 
@@ -19,6 +31,16 @@ struct Args {
     /// Bounds of the fractal [x_min, x_max, y_min, y_max] (default: [-2.0, 1.0, -1.2, 1.2] for Mandelbrot region)
     #[arg(long, value_delimiter = ',', num_args = 1..=4, default_values_t = [-2.0, 1.0, -1.2, 1.2])]
     bounds: Vec<f64>,
+
+    /// Area c is sampled from [x_min, x_max, y_min, y_max], if not the bounds. For a zoomed view
+    /// it must be larger than the view, since most orbits through the view start outside it
+    #[arg(long, value_delimiter = ',', num_args = 4, allow_negative_numbers = true, conflicts_with = "auto_sample_bounds")]
+    sample_bounds: Option<Vec<f64>>,
+
+    /// Fit the sampling area to the view: probe c over the bailout disk and sample the bounding box
+    /// of the c values whose orbits are drawn in the view
+    #[arg(long)]
+    auto_sample_bounds: bool,
     
     /// Dimensions of the output image [width, height]
     #[arg(long, value_delimiter = ',', num_args = 1..=2)]
@@ -55,6 +77,11 @@ struct Args {
     /// Blue channel: min_iter,max_iter,samples
     #[arg(long, value_delimiter = ',', num_args = 1..=3)]
     blue_channel: Vec<u64>,
+
+    /// Repair channels that would render nothing (min_iter not below max_iter, max_iter above
+    /// --max-iterations, no samples) instead of stopping with an error
+    #[arg(long)]
+    fix_channels: bool,
     
     /// Output file name
     #[arg(long, default_value = "buddha_output.png")]
@@ -71,13 +98,173 @@ struct Args {
     /// Point coordinates for orbit debugging [real, imag] (requires --orbit-debug)
     #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [0.0, 0.0])]
     debug_point: Vec<f64>,
+
+    /// Sample c near the preimages of the view instead of uniformly (for zoomed-in renders).
+    /// Samples are drawn over [-2, 2] x [-2, 2] rather than only inside the bounds
+    #[arg(long)]
+    roi_sampling: bool,
+
+    /// Sample c with Metropolis-Hastings chains that mutate c values whose orbits are drawn, for
+    /// renders with a high min_iter. Like --roi-sampling, samples are drawn over [-2, 2] x [-2, 2];
+    /// each channel's sample count is the number of chain steps
+    #[arg(long)]
+    metropolis: bool,
+
+    /// Orbits to accumulate: escaping (the Buddhabrot), bounded (the anti-Buddhabrot, orbits of
+    /// points that never escape, drawn from each channel's min_iter to its max_iter) or combined
+    /// (escaping in red and green, bounded in blue)
+    #[arg(long, default_value = "escaping")]
+    orbits: String,
+
+    /// Skip samples whose escape time is clearly outside the channel's range, judged from a coarse
+    /// grid of this many cells per axis computed before sampling (0 disables; try 256)
+    #[arg(long, default_value_t = 0)]
+    prefilter_grid: usize,
+
+    /// Accumulate the orbits on the GPU (requires the gpu feature; f32 precision, falls back to the
+    /// CPU for unsupported formulas; ignores --prefilter-grid)
+    #[arg(long)]
+    gpu: bool,
+
+    /// Accumulate every channel at each of these bailouts and fuse the exposures per pixel, e.g.
+    /// 2,8,32 (small bailouts bring out the core, large ones the halo); an optional :sigma sets
+    /// how strongly the best exposed value wins (default 0.2)
+    #[arg(long)]
+    exposures: Option<String>,
+
+    /// Smooth the sampling noise of the histograms before combining them; the value is how many
+    /// noise standard deviations two pixels may differ by and still be averaged (try 1)
+    #[arg(long)]
+    denoise: Option<f64>,
+
+    /// Radius in pixels of the denoising filter (use with --denoise)
+    #[arg(long, default_value_t = 1.5)]
+    denoise_radius: f64,
+
+    /// Insert points along orbit segments longer than this many pixels, sharing each iterate's
+    /// weight between them, for smoother filaments at low sample counts (try 1; uniform sampling only)
+    #[arg(long)]
+    interpolate: Option<f64>,
+
+    /// Longest orbit, in points, kept in memory per thread; channels with a higher max iteration
+    /// count iterate each drawn orbit twice instead of storing it
+    #[arg(long, default_value_t = fractal_toolkit::buddhabrot::scratch::DEFAULT_ORBIT_BUFFER_LIMIT)]
+    orbit_buffer_limit: usize,
+
+    /// Mix the channels into output colors through a 3x3 matrix: identity, hubble, natural,
+    /// reverse, gray, or 9 comma-separated weights row by row (red output first). Each channel's
+    /// weights are scaled to sum to 1, so mixing shifts hue without changing brightness
+    #[arg(long)]
+    channel_mix: Option<String>,
+
+    /// White balance of the mixed colors: none, neutral (equal channels mix to gray), daylight,
+    /// tungsten, fluorescent, shade or a color temperature like 5000K
+    #[arg(long, default_value = "none")]
+    white_balance: String,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;unsharp:amount=0.5;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Quality profile: draft (a tenth of the samples, denoised), standard, or production (four
+    /// times the samples)
+    #[arg(long, default_value = "standard")]
+    quality: String,
+
+    /// Write hashes of the image and of square tiles of this many pixels to <output>.hashes.json,
+    /// for checking that another run (another worker, a resumed render) produced the same image
+    #[arg(long)]
+    tile_hashes: Option<u32>,
+
+    /// Compare the render with a hashes file from another run and exit with an error if they differ
+    #[arg(long)]
+    verify_hashes: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Encode the linear-light densities for display before they are quantized: `srgb`, or `gamma:<g>` such as `gamma:2.2`
+    #[arg(long)]
+    display_transform: Option<String>,
+
+    /// Also write the raw linear orbit densities of the three channels for grading: 32-bit float
+    /// OpenEXR (.exr) or TIFF (.tif), or 16-bit PNG (.png) scaled to the densest pixel
+    #[arg(long)]
+    hdr_output: Option<String>,
+
+    /// Also write the total orbit density, log-scaled and percentile-normalized, as a float
+    /// TIFF (.tif) or 16-bit PGM (.pgm) for quantitative analysis
+    #[arg(long)]
+    scientific_output: Option<String>,
+
+    /// Also record the orbit points inside --record-region to this stream file, to render again
+    /// later at other sizes, crops and rotations with --rebin
+    #[arg(long)]
+    record_orbits: Option<String>,
+
+    /// Region of the plane to record with --record-orbits: x_min,x_max,y_min,y_max (default: the bounds)
+    #[arg(long, value_delimiter = ',', num_args = 4, allow_negative_numbers = true, requires = "record_orbits")]
+    record_region: Option<Vec<f64>>,
+
+    /// Render the orbit points of a stream recorded with --record-orbits instead of sampling, at
+    /// --dimensions over --bounds; the formula, channels and orbit mode are the recorded ones
+    #[arg(long, conflicts_with = "record_orbits")]
+    rebin: Option<String>,
+
+    /// Rotate the view of --record-orbits or --rebin by this many degrees counter-clockwise about its center
+    #[arg(long, default_value_t = 0.0)]
+    rebin_rotation: f64,
+
+    /// Print health telemetry (progress, throughput, memory, busy threads) every this many seconds
+    #[arg(long)]
+    telemetry: Option<f64>,
+
+    /// Seconds a thread may spend on one pixel or sample before it is reported as stalled (use with --telemetry)
+    #[arg(long, default_value_t = 600.0)]
+    stall_timeout: f64,
 }
 
 fn main() {
     // Initialize rayon thread pool with CPU core count
     init_rayon_pool();
 
-    let args = Args::parse();
+    let mut args = Args::parse();
+    // The channels of a rebinned render are the recorded ones, so they needn't be given again
+    let rebin_stream = args.rebin.as_deref().map(|path| {
+        OrbitStream::open(path).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+    if let Some(ref stream) = rebin_stream {
+        let channels = &stream.header().params.channels;
+        for (arg, channel) in [&mut args.red_channel, &mut args.green_channel, &mut args.blue_channel].into_iter().zip([&channels.red, &channels.green, &channels.blue]) {
+            *arg = vec![channel.min_iter as u64, channel.max_iter as u64, channel.samples];
+        }
+    }
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+    let display_transform = args.display_transform.as_deref().map(DisplayTransform::parse).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(seconds) = args.telemetry {
+        telemetry::install(TelemetryOptions::printing(
+            std::time::Duration::from_secs_f64(seconds),
+            std::time::Duration::from_secs_f64(args.stall_timeout),
+        ));
+    }
 
     println!("Generating Buddhabrot with:");
     println!("  Bounds: {:?}", args.bounds);
@@ -91,6 +278,9 @@ fn main() {
     println!("  Green channel: {:?}", args.green_channel);
     println!("  Blue channel: {:?}", args.blue_channel);
     println!("  Output: {}", args.output);
+    println!("  Region-of-interest sampling: {}", args.roi_sampling);
+    println!("  Metropolis sampling: {}", args.metropolis);
+    println!("  Orbits: {}", args.orbits);
     
     // Validate bounds
     if args.bounds.len() != 4 {
@@ -112,6 +302,12 @@ fn main() {
         std::process::exit(1);
     }
 
+    // Orbits cross the whole image, so a Buddhabrot can't be split into tiles like escape-time renders
+    if let Some(reason) = single_image_limit(width, height, &args.output) {
+        eprintln!("Error: a {}x{} image can't be saved as one file ({})", width, height, reason);
+        std::process::exit(1);
+    }
+
     // Validate channel parameters
     if args.red_channel.len() != 3 {
         eprintln!("Error: red-channel must have exactly 3 values [min_iter, max_iter, samples]");
@@ -168,12 +364,105 @@ fn main() {
         args.bailout,
         args.formula,
         BuddhabrotChannels {
-            red: red_channel.clone(),
-            green: green_channel.clone(),
-            blue: blue_channel.clone(),
+            red: red_channel,
+            green: green_channel,
+            blue: blue_channel,
         },
     );
     params.i_sqrt_value = i_sqrt_complex;
+    params.orbits = OrbitMode::parse(&args.orbits).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if args.roi_sampling && params.orbits != OrbitMode::Escaping {
+        eprintln!("Error: --roi-sampling only applies to escaping orbits");
+        std::process::exit(1);
+    }
+    if args.metropolis && params.orbits != OrbitMode::Escaping {
+        eprintln!("Error: --metropolis only applies to escaping orbits");
+        std::process::exit(1);
+    }
+    if [args.roi_sampling, args.metropolis, args.gpu, args.exposures.is_some()].iter().filter(|&&enabled| enabled).count() > 1 {
+        eprintln!("Error: --roi-sampling, --metropolis, --gpu and --exposures can't be combined");
+        std::process::exit(1);
+    }
+    if args.interpolate.is_some() && (args.roi_sampling || args.metropolis || args.gpu) {
+        eprintln!("Error: --interpolate only applies to uniform sampling on the CPU");
+        std::process::exit(1);
+    }
+    let exposures = args.exposures.as_deref().map(|spec| {
+        ExposureBlend::parse(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    // An empty channel would otherwise just come out black
+    if args.fix_channels {
+        for problem in params.channels.fix(params.max_iterations, params.samples) {
+            println!("Fixed {}", problem);
+        }
+    } else if let Err(e) = params.channels.validate(params.max_iterations) {
+        eprintln!("Error: {} (use --fix-channels to repair it)", e);
+        std::process::exit(1);
+    }
+    params.sample_bounds = match args.sample_bounds {
+        Some(ref b) => Some([b[0], b[1], b[2], b[3]]),
+        None if args.auto_sample_bounds => Some(auto_sample_bounds(&params, 256)),
+        None => None,
+    };
+    if params.sample_bounds.is_some() {
+        println!("  Sample bounds: {:?}", params.sampling_bounds());
+    }
+    params.prefilter_cells = args.prefilter_grid;
+    params.denoise = args.denoise.map(|strength| DenoiseOptions { strength, radius: args.denoise_radius });
+    params.interpolation = args.interpolate.map(|step| {
+        OrbitInterpolation::with_step(step).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+    params.orbit_buffer_limit = args.orbit_buffer_limit;
+    if args.channel_mix.is_some() || args.white_balance != "none" {
+        let mix = WhiteBalance::parse(&args.white_balance)
+            .and_then(|balance| ChannelMix::parse(args.channel_mix.as_deref().unwrap_or("identity"), balance));
+        params.channel_mix = Some(mix.unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }));
+    }
+    if let Some(spec) = &args.post_fx {
+        params.post_effects = parse_post_effects(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+    params.display_transform = display_transform;
+    params.quality = Profile::parse(&args.quality).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let quality = params.quality;
+    let requested_channels = params.channels.clone();
+    apply_buddhabrot_profile(&mut params);
+    println!("  Quality: {}", quality);
+
+    // A rebinned render shows what was sampled, whatever the sampling options say now
+    if let Some(stream) = rebin_stream {
+        let recorded = stream.header().params.clone();
+        println!("  Rebinning with formula {}, recorded over {:?}", recorded.formula, stream.header().region);
+        params = BuddhabrotParams {
+            bounds: params.bounds,
+            width: params.width,
+            height: params.height,
+            denoise: params.denoise.take(),
+            channel_mix: params.channel_mix.take(),
+            display_transform: params.display_transform,
+            post_effects: std::mem::take(&mut params.post_effects),
+            cancel: params.cancel.clone(),
+            ..recorded
+        };
+    }
 
     // If orbit debugging is enabled, trace the orbit for a specific point
     if args.orbit_debug {
@@ -190,24 +479,82 @@ fn main() {
         return; // Exit after debugging
     }
     
+    if (args.hdr_output.is_some() || args.scientific_output.is_some()) && (args.roi_sampling || args.metropolis || exposures.is_some() || args.gpu) {
+        eprintln!("Error: --hdr-output and --scientific-output need the default sampler (no --roi-sampling, --metropolis, --exposures or --gpu)");
+        std::process::exit(1);
+    }
+    let streamed = args.record_orbits.is_some() || args.rebin.is_some();
+    if streamed && (args.roi_sampling || args.metropolis || exposures.is_some() || args.gpu || args.interpolate.is_some()) {
+        eprintln!("Error: --record-orbits and --rebin need the default sampler (no --roi-sampling, --metropolis, --exposures, --gpu or --interpolate)");
+        std::process::exit(1);
+    }
+
     // Generate the Buddhabrot image
-    let img = generate_buddhabrot(&params);
-    
+    let img = if args.roi_sampling {
+        generate_buddhabrot_roi(&params, &RoiSamplingOptions::default())
+    } else if args.metropolis {
+        generate_buddhabrot_metropolis(&params, &MetropolisOptions::default())
+    } else if let Some(blend) = &exposures {
+        generate_buddhabrot_exposures(&params, blend)
+    } else if args.gpu {
+        render_on_gpu(&params).unwrap_or_else(|| generate_buddhabrot(&params))
+    } else {
+        let densities = if streamed { stream_densities(&params, args.record_orbits.as_deref(), args.record_region.as_deref(), args.rebin.as_deref(), args.rebin_rotation) } else { buddhabrot_densities(&params) };
+        if let Some(ref path) = args.hdr_output {
+            match write_hdr_image(path, &HdrImage::from_densities(&densities), &output_options) {
+                Ok(format) => println!("{:?} densities saved to {}", format, path),
+                Err(e) => {
+                    eprintln!("Error writing high bit depth output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(ref path) = args.scientific_output {
+            match write_scientific_densities(path, &densities, args.checksum) {
+                Ok(normalization) => println!(
+                    "Scientific output saved to {} (normalized log {} between {} and {})",
+                    path, normalization.quantity, normalization.low_value, normalization.high_value
+                ),
+                Err(e) => {
+                    eprintln!("Error writing scientific output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        render_buddhabrot_densities(params.width, params.height, &densities, params.channel_mix.as_ref(), params.display_transform, &params.post_effects)
+    };
+
     // Save the image
-    img.save(&args.output).expect("Failed to save image");
+    save_image_atomic(&img, &args.output, &output_options).expect("Failed to save image");
     println!("Buddhabrot image saved to {}", args.output);
+    if let Err(e) = record_render_hashes(&img, &params, vec![BUDDHABROT_SEED], &args.output, args.tile_hashes, args.verify_hashes.as_deref()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
     
     // Generate command template for the HTML
     let command_template = format!(
-        "ftk-buddha --bounds={{bounds}} --dimensions={{dimensions}} --min-iterations={} --max-iterations={} --samples={} --bailout={} --formula=\"{}\" --red-channel={},{},{} --green-channel={},{},{} --blue-channel={},{},{} --output=\"buddha_zoom_$(date +%Y%m%d_%H%M%S).png\"",
+        "ftk-buddha --bounds={{bounds}} --dimensions={{dimensions}} --min-iterations={} --max-iterations={} --samples={} --bailout={} --formula=\"{}\" --red-channel={},{},{} --green-channel={},{},{} --blue-channel={},{},{}{}{}{}{}{}{}{}{} --output=\"buddha_zoom_$(date +%Y%m%d_%H%M%S).png\"",
         args.min_iterations,
         args.max_iterations,
         args.samples,
         args.bailout,
         params.formula,
-        red_channel.min_iter, red_channel.max_iter, red_channel.samples,
-        green_channel.min_iter, green_channel.max_iter, green_channel.samples,
-        blue_channel.min_iter, blue_channel.max_iter, blue_channel.samples
+        requested_channels.red.min_iter, requested_channels.red.max_iter, requested_channels.red.samples,
+        requested_channels.green.min_iter, requested_channels.green.max_iter, requested_channels.green.samples,
+        requested_channels.blue.min_iter, requested_channels.blue.max_iter, requested_channels.blue.samples,
+        if args.roi_sampling { " --roi-sampling".to_string() } else if args.metropolis { " --metropolis".to_string() } else if args.prefilter_grid > 0 { format!(" --prefilter-grid={}", args.prefilter_grid) } else { String::new() },
+        args.exposures.as_ref().map_or(String::new(), |spec| format!(" --exposures={}", spec)),
+        if params.orbits == OrbitMode::Escaping { String::new() } else { format!(" --orbits={}", args.orbits) },
+        match args.sample_bounds {
+            Some(ref b) => format!(" --sample-bounds={},{},{},{}", b[0], b[1], b[2], b[3]),
+            None if args.auto_sample_bounds => " --auto-sample-bounds".to_string(),
+            None => String::new(),
+        },
+        channel_mix_args(&args.channel_mix, &args.white_balance),
+        args.interpolate.map_or(String::new(), |step| format!(" --interpolate={}", step)),
+        if args.orbit_buffer_limit == fractal_toolkit::buddhabrot::scratch::DEFAULT_ORBIT_BUFFER_LIMIT { String::new() } else { format!(" --orbit-buffer-limit={}", args.orbit_buffer_limit) },
+        if quality == Profile::Standard { String::new() } else { format!(" --quality={}", quality) }
     );
     
     // Generate the HTML file
@@ -255,11 +602,11 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
         let mut imag_str = "";
 
         // Find the position of the imaginary part
-        if let Some(i_pos) = s.find(|c| c == 'i' || c == 'I') {
+        if let Some(i_pos) = s.find(['i', 'I']) {
             let before_i = &s[..i_pos];
 
             // Look for the last occurrence of + or - before the i
-            if let Some(last_sign_pos) = before_i.rfind(|c: char| c == '+' || c == '-') {
+            if let Some(last_sign_pos) = before_i.rfind(['+', '-']) {
                 if last_sign_pos == 0 {
                     // Starts with a sign, e.g., "-2.5i" or "+3.2i"
                     real_str = "0";
@@ -282,7 +629,7 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
 
             // Parse imaginary part
             if !imag_str.is_empty() {
-                if imag_str == "+" || imag_str == "" {
+                if imag_str == "+" || imag_str.is_empty() {
                     imag_part = 1.0;
                 } else if imag_str == "-" {
                     imag_part = -1.0;
@@ -300,4 +647,60 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
     }
 
     Ok(Complex::new(real_part, imag_part))
-}
\ No newline at end of file
+}
+
+/// Record the orbits to a stream and bin them at the view, or bin a stream recorded earlier
+fn stream_densities(
+    params: &BuddhabrotParams,
+    record_orbits: Option<&str>,
+    record_region: Option<&[f64]>,
+    rebin: Option<&str>,
+    rotation: f64,
+) -> [DensityGrid; 3] {
+    let fail = |e: FractalError| -> ! {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    };
+    let path = match (record_orbits, rebin) {
+        (Some(path), _) => {
+            let region = record_region.map_or(params.bounds, |r| [r[0], r[1], r[2], r[3]]);
+            let counts = record_orbit_stream(params, region, path).unwrap_or_else(|e| fail(e));
+            println!("Recorded {} orbit points to {}", counts.iter().sum::<u64>(), path);
+            path
+        }
+        (None, Some(path)) => path,
+        (None, None) => unreachable!("only called for --record-orbits or --rebin"),
+    };
+    let options = RebinOptions {
+        width: params.width,
+        height: params.height,
+        bounds: params.bounds,
+        rotation,
+        denoise: params.denoise.clone(),
+    };
+    OrbitStream::open(path).and_then(|stream| stream.rebin(&options)).unwrap_or_else(|e| fail(e))
+}
+
+#[cfg(feature = "gpu")]
+fn render_on_gpu(params: &BuddhabrotParams) -> Option<image::RgbImage> {
+    let result = fractal_toolkit::gpu::GpuRenderer::new().and_then(|renderer| renderer.generate_buddhabrot(params));
+    result.map_err(|e| eprintln!("GPU rendering unavailable ({}), rendering on the CPU", e)).ok()
+}
+
+#[cfg(not(feature = "gpu"))]
+fn render_on_gpu(_params: &BuddhabrotParams) -> Option<image::RgbImage> {
+    eprintln!("Built without the gpu feature, rendering on the CPU");
+    None
+}
+
+/// The --channel-mix and --white-balance options of the command template, if not the defaults
+fn channel_mix_args(channel_mix: &Option<String>, white_balance: &str) -> String {
+    let mut options = String::new();
+    if let Some(mix) = channel_mix {
+        options.push_str(&format!(" --channel-mix=\"{}\"", mix));
+    }
+    if white_balance != "none" {
+        options.push_str(&format!(" --white-balance={}", white_balance));
+    }
+    options
+}
@@ -1,5 +1,14 @@
 use clap::Parser;
-use fractal_toolkit::{BuddhabrotJuliaParams, BuddhabrotChannels, BuddhabrotChannel, generate_buddhabrot_julia, generate_html_file};
+use fractal_toolkit::{BuddhabrotJuliaParams, BuddhabrotChannels, BuddhabrotChannel, buddhabrot_julia_densities, generate_html_file, render_buddhabrot_densities, BUDDHABROT_JULIA_SEED};
+use fractal_toolkit::buddhabrot::{ChannelMix, DenoiseOptions, WhiteBalance};
+use fractal_toolkit::output::{
+    record_render_hashes, save_image_atomic, write_hdr_image, write_scientific_densities, ColorProfile, OutputOptions,
+    DisplayTransform, HdrImage,
+};
+use fractal_toolkit::postfx::parse_post_effects;
+use fractal_toolkit::tiled::single_image_limit;
+use fractal_toolkit::quality::{apply_buddhabrot_julia_profile, Profile};
+use fractal_toolkit::telemetry::{self, TelemetryOptions};
 use rayon::ThreadPoolBuilder;
 use num_complex::Complex;
 
@@ -59,6 +68,11 @@ struct Args {
     /// Blue channel: min_iter,max_iter,samples
     #[arg(long, value_delimiter = ',', num_args = 1..=3)]
     blue_channel: Vec<u64>,
+
+    /// Repair channels that would render nothing (min_iter not below max_iter, max_iter above
+    /// --max-iterations, no samples) instead of stopping with an error
+    #[arg(long)]
+    fix_channels: bool,
     
     /// Output file name
     #[arg(long, default_value = "buddhaj_output.png")]
@@ -75,6 +89,74 @@ struct Args {
     /// Point coordinates for orbit debugging [real, imag] (requires --orbit-debug)
     #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [0.0, 0.0])]
     debug_point: Vec<f64>,
+
+    /// Smooth the sampling noise of the histograms before combining them; the value is how many
+    /// noise standard deviations two pixels may differ by and still be averaged (try 1)
+    #[arg(long)]
+    denoise: Option<f64>,
+
+    /// Radius in pixels of the denoising filter (use with --denoise)
+    #[arg(long, default_value_t = 1.5)]
+    denoise_radius: f64,
+
+    /// Mix the channels into output colors through a 3x3 matrix: identity, hubble, natural,
+    /// reverse, gray, or 9 comma-separated weights row by row (red output first). Each channel's
+    /// weights are scaled to sum to 1, so mixing shifts hue without changing brightness
+    #[arg(long)]
+    channel_mix: Option<String>,
+
+    /// White balance of the mixed colors: none, neutral (equal channels mix to gray), daylight,
+    /// tungsten, fluorescent, shade or a color temperature like 5000K
+    #[arg(long, default_value = "none")]
+    white_balance: String,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;unsharp:amount=0.5;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Quality profile: draft (a tenth of the samples, denoised), standard, or production (four
+    /// times the samples)
+    #[arg(long, default_value = "standard")]
+    quality: String,
+
+    /// Write hashes of the image and of square tiles of this many pixels to <output>.hashes.json,
+    /// for checking that another run (another worker, a resumed render) produced the same image
+    #[arg(long)]
+    tile_hashes: Option<u32>,
+
+    /// Compare the render with a hashes file from another run and exit with an error if they differ
+    #[arg(long)]
+    verify_hashes: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Encode the linear-light densities for display before they are quantized: `srgb`, or `gamma:<g>` such as `gamma:2.2`
+    #[arg(long)]
+    display_transform: Option<String>,
+
+    /// Also write the raw linear orbit densities of the three channels for grading: 32-bit float
+    /// OpenEXR (.exr) or TIFF (.tif), or 16-bit PNG (.png) scaled to the densest pixel
+    #[arg(long)]
+    hdr_output: Option<String>,
+
+    /// Also write the total orbit density, log-scaled and percentile-normalized, as a float
+    /// TIFF (.tif) or 16-bit PGM (.pgm) for quantitative analysis
+    #[arg(long)]
+    scientific_output: Option<String>,
+
+    /// Print health telemetry (progress, throughput, memory, busy threads) every this many seconds
+    #[arg(long)]
+    telemetry: Option<f64>,
+
+    /// Seconds a thread may spend on one pixel or sample before it is reported as stalled (use with --telemetry)
+    #[arg(long, default_value_t = 600.0)]
+    stall_timeout: f64,
 }
 
 fn main() {
@@ -82,6 +164,24 @@ fn main() {
     init_rayon_pool();
 
     let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+    let display_transform = args.display_transform.as_deref().map(DisplayTransform::parse).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(seconds) = args.telemetry {
+        telemetry::install(TelemetryOptions::printing(
+            std::time::Duration::from_secs_f64(seconds),
+            std::time::Duration::from_secs_f64(args.stall_timeout),
+        ));
+    }
 
     println!("Generating Buddhabrot Julia with:");
     println!("  Bounds: {:?}", args.bounds);
@@ -117,6 +217,12 @@ fn main() {
         std::process::exit(1);
     }
 
+    // Orbits cross the whole image, so a Buddhabrot can't be split into tiles like escape-time renders
+    if let Some(reason) = single_image_limit(width, height, &args.output) {
+        eprintln!("Error: a {}x{} image can't be saved as one file ({})", width, height, reason);
+        std::process::exit(1);
+    }
+
     // Validate spawn
     if args.spawn.len() != 2 {
         eprintln!("Error: spawn must have exactly 2 values [real, imag]");
@@ -180,13 +286,47 @@ fn main() {
         [args.spawn[0], args.spawn[1]],
         args.formula,
         BuddhabrotChannels {
-            red: red_channel.clone(),
-            green: green_channel.clone(),
-            blue: blue_channel.clone(),
+            red: red_channel,
+            green: green_channel,
+            blue: blue_channel,
         },
     );
     params.i_sqrt_value = i_sqrt_complex;
 
+    // An empty channel would otherwise just come out black
+    if args.fix_channels {
+        for problem in params.channels.fix(params.max_iterations, params.samples) {
+            println!("Fixed {}", problem);
+        }
+    } else if let Err(e) = params.channels.validate(params.max_iterations) {
+        eprintln!("Error: {} (use --fix-channels to repair it)", e);
+        std::process::exit(1);
+    }
+    params.denoise = args.denoise.map(|strength| DenoiseOptions { strength, radius: args.denoise_radius });
+    if args.channel_mix.is_some() || args.white_balance != "none" {
+        let mix = WhiteBalance::parse(&args.white_balance)
+            .and_then(|balance| ChannelMix::parse(args.channel_mix.as_deref().unwrap_or("identity"), balance));
+        params.channel_mix = Some(mix.unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }));
+    }
+    if let Some(spec) = &args.post_fx {
+        params.post_effects = parse_post_effects(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+    params.display_transform = display_transform;
+    params.quality = Profile::parse(&args.quality).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let quality = params.quality;
+    let requested_channels = params.channels.clone();
+    apply_buddhabrot_julia_profile(&mut params);
+    println!("  Quality: {}", quality);
+
     // If orbit debugging is enabled, trace the orbit for a specific point
     if args.orbit_debug {
         // Validate debug point
@@ -203,24 +343,54 @@ fn main() {
     }
     
     // Generate the Buddhabrot Julia image
-    let img = generate_buddhabrot_julia(&params);
-    
+    let img = {
+        let densities = buddhabrot_julia_densities(&params);
+        if let Some(ref path) = args.hdr_output {
+            match write_hdr_image(path, &HdrImage::from_densities(&densities), &output_options) {
+                Ok(format) => println!("{:?} densities saved to {}", format, path),
+                Err(e) => {
+                    eprintln!("Error writing high bit depth output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Some(ref path) = args.scientific_output {
+            match write_scientific_densities(path, &densities, args.checksum) {
+                Ok(normalization) => println!(
+                    "Scientific output saved to {} (normalized log {} between {} and {})",
+                    path, normalization.quantity, normalization.low_value, normalization.high_value
+                ),
+                Err(e) => {
+                    eprintln!("Error writing scientific output: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        render_buddhabrot_densities(params.width, params.height, &densities, params.channel_mix.as_ref(), params.display_transform, &params.post_effects)
+    };
+
     // Save the image
-    img.save(&args.output).expect("Failed to save image");
+    save_image_atomic(&img, &args.output, &output_options).expect("Failed to save image");
     println!("Buddhabrot Julia image saved to {}", args.output);
+    if let Err(e) = record_render_hashes(&img, &params, vec![BUDDHABROT_JULIA_SEED], &args.output, args.tile_hashes, args.verify_hashes.as_deref()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
     
     // Generate command template for the HTML
     let command_template = format!(
-        "ftk-buddhaj --bounds={{bounds}} --dimensions={{dimensions}} --min-iterations={} --max-iterations={} --samples={} --bailout={} --spawn={},{} --formula=\"{}\" --red-channel={},{},{} --green-channel={},{},{} --blue-channel={},{},{} --output=\"buddhaj_zoom_$(date +%Y%m%d_%H%M%S).png\"",
+        "ftk-buddhaj --bounds={{bounds}} --dimensions={{dimensions}} --min-iterations={} --max-iterations={} --samples={} --bailout={} --spawn={},{} --formula=\"{}\" --red-channel={},{},{} --green-channel={},{},{} --blue-channel={},{},{}{}{} --output=\"buddhaj_zoom_$(date +%Y%m%d_%H%M%S).png\"",
         args.min_iterations,
         args.max_iterations,
         args.samples,
         args.bailout,
         args.spawn[0], args.spawn[1],
         params.formula,
-        red_channel.min_iter, red_channel.max_iter, red_channel.samples,
-        green_channel.min_iter, green_channel.max_iter, green_channel.samples,
-        blue_channel.min_iter, blue_channel.max_iter, blue_channel.samples
+        requested_channels.red.min_iter, requested_channels.red.max_iter, requested_channels.red.samples,
+        requested_channels.green.min_iter, requested_channels.green.max_iter, requested_channels.green.samples,
+        requested_channels.blue.min_iter, requested_channels.blue.max_iter, requested_channels.blue.samples,
+        channel_mix_args(&args.channel_mix, &args.white_balance),
+        if quality == Profile::Standard { String::new() } else { format!(" --quality={}", quality) }
     );
     
     // Generate the HTML file
@@ -268,11 +438,11 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
         let mut imag_str = "";
 
         // Find the position of the imaginary part
-        if let Some(i_pos) = s.find(|c| c == 'i' || c == 'I') {
+        if let Some(i_pos) = s.find(['i', 'I']) {
             let before_i = &s[..i_pos];
 
             // Look for the last occurrence of + or - before the i
-            if let Some(last_sign_pos) = before_i.rfind(|c: char| c == '+' || c == '-') {
+            if let Some(last_sign_pos) = before_i.rfind(['+', '-']) {
                 if last_sign_pos == 0 {
                     // Starts with a sign, e.g., "-2.5i" or "+3.2i"
                     real_str = "0";
@@ -295,7 +465,7 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
 
             // Parse imaginary part
             if !imag_str.is_empty() {
-                if imag_str == "+" || imag_str == "" {
+                if imag_str == "+" || imag_str.is_empty() {
                     imag_part = 1.0;
                 } else if imag_str == "-" {
                     imag_part = -1.0;
@@ -313,4 +483,16 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
     }
 
     Ok(Complex::new(real_part, imag_part))
-}
\ No newline at end of file
+}
+
+/// The --channel-mix and --white-balance options of the command template, if not the defaults
+fn channel_mix_args(channel_mix: &Option<String>, white_balance: &str) -> String {
+    let mut options = String::new();
+    if let Some(mix) = channel_mix {
+        options.push_str(&format!(" --channel-mix=\"{}\"", mix));
+    }
+    if white_balance != "none" {
+        options.push_str(&format!(" --white-balance={}", white_balance));
+    }
+    options
+}
@@ -0,0 +1,183 @@
+use clap::Parser;
+use fractal_toolkit::output::{save_image_atomic, ColorProfile, OutputOptions};
+use fractal_toolkit::parse_color_palette;
+use fractal_toolkit::postfx::{apply_post_effects, parse_post_effects};
+use fractal_toolkit::raymarch::{render_raymarch, Camera, DistanceEstimator, RaymarchParams, Vec3};
+use rayon::ThreadPoolBuilder;
+
+fn init_rayon_pool() {
+    let num_threads = num_cpus::get();
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+        .expect("Failed to initialize Rayon thread pool");
+}
+
+#[derive(Parser)]
+#[command(name = "ftk-bulb")]
+#[command(version = "1.0")]
+#[command(about = "Ray-marches 3D fractals such as the Mandelbulb and Mandelbox")]
+struct Args {
+    /// Fractal to render: mandelbulb or mandelbox
+    #[arg(long, default_value = "mandelbulb")]
+    fractal: String,
+
+    /// Power of the Mandelbulb, or scale of the Mandelbox (defaults 8 and -1.5)
+    #[arg(long, allow_negative_numbers = true)]
+    power: Option<f64>,
+
+    /// Iterations of the distance estimator per step
+    #[arg(long, default_value_t = 16)]
+    iterations: u32,
+
+    /// Dimensions of the output image [width, height]
+    #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [1024, 1024])]
+    dimensions: Vec<u32>,
+
+    /// Position of the camera x,y,z
+    #[arg(long, allow_negative_numbers = true, default_value = "0,1.5,3")]
+    camera: String,
+
+    /// Point the camera looks at x,y,z
+    #[arg(long, allow_negative_numbers = true, default_value = "0,0,0")]
+    target: String,
+
+    /// Direction that is up in the image x,y,z
+    #[arg(long, allow_negative_numbers = true, default_value = "0,1,0")]
+    up: String,
+
+    /// Vertical field of view in degrees
+    #[arg(long, default_value_t = 40.0)]
+    fov: f64,
+
+    /// Direction towards the light x,y,z
+    #[arg(long, allow_negative_numbers = true, default_value = "-0.5,0.8,0.6")]
+    light: String,
+
+    /// Maximum number of steps along each ray
+    #[arg(long, default_value_t = 256)]
+    max_steps: u32,
+
+    /// Distance to the surface at which a ray counts as hitting it; smaller shows finer detail
+    #[arg(long, default_value_t = 1e-4)]
+    epsilon: f64,
+
+    /// Rays per pixel along each axis, averaged for anti-aliasing
+    #[arg(long, default_value_t = 1)]
+    supersampling: u32,
+
+    /// Color palette for the surface [(hex_color, position), ...], indexed by an orbit trap
+    #[arg(long)]
+    color_pallette: Option<String>,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;unsharp:amount=0.5;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Output file name
+    #[arg(long, default_value = "bulb_output.png")]
+    output: String,
+}
+
+// Parse a vector argument, exiting on error
+fn parse_vector(text: &str) -> Vec3 {
+    Vec3::parse(text).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn main() {
+    // Initialize rayon thread pool with CPU core count
+    init_rayon_pool();
+
+    let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+
+    println!("Ray marching 3D fractal with:");
+    println!("  Fractal: {}", args.fractal);
+    println!("  Camera: {} looking at {}", args.camera, args.target);
+    println!("  Dimensions: {:?}", args.dimensions);
+    println!("  Output: {}", args.output);
+
+    // Validate dimensions
+    if args.dimensions.len() != 2 {
+        eprintln!("Error: dimensions must have exactly 2 values [width, height]");
+        std::process::exit(1);
+    }
+    let width = args.dimensions[0];
+    let height = args.dimensions[1];
+
+    let mut fractal = DistanceEstimator::parse(&args.fractal).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    match &mut fractal {
+        DistanceEstimator::Mandelbulb { power, iterations, .. } => {
+            *power = args.power.unwrap_or(*power);
+            *iterations = args.iterations;
+        }
+        DistanceEstimator::Mandelbox { scale, iterations, .. } => {
+            *scale = args.power.unwrap_or(*scale);
+            *iterations = args.iterations;
+        }
+    }
+
+    let camera = Camera {
+        position: parse_vector(&args.camera),
+        target: parse_vector(&args.target),
+        up: parse_vector(&args.up),
+        fov_degrees: args.fov,
+    };
+    let mut params = RaymarchParams::new(fractal, camera);
+    params.lighting.light_direction = parse_vector(&args.light);
+    params.max_steps = args.max_steps;
+    params.epsilon = args.epsilon;
+    params.supersampling = args.supersampling;
+    // Rays must reach past the fractal, whose bounding radius is a few units at most
+    params.max_distance = params.max_distance.max((camera.position - camera.target).length() + 10.0);
+
+    let post_effects = args.post_fx.as_deref().map(parse_post_effects).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    // Parse color palette if provided
+    let color_palette = if let Some(ref palette_str) = args.color_pallette {
+        match parse_color_palette(palette_str) {
+            Ok(palette) => {
+                println!("Using color palette with {} stops", palette.len());
+                Some(palette)
+            },
+            Err(e) => {
+                eprintln!("Error parsing color palette: {}", e);
+                eprintln!("Using default coloring instead.");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut img = render_raymarch(width, height, &params, color_palette.as_deref());
+    if let Some(effects) = post_effects {
+        apply_post_effects(&mut img, &effects);
+    }
+
+    save_image_atomic(&img, &args.output, &output_options).expect("Failed to save image");
+    println!("3D fractal image saved to {}", args.output);
+}
@@ -1,5 +1,7 @@
 use clap::Parser;
 use fractal_toolkit::{MathEvaluator, parse_complex_number};
+use fractal_toolkit::algebra::audit_identities;
+use fractal_toolkit::analysis::{check_formula_derivatives, derivative_test_points};
 use num_complex::Complex;
 
 #[derive(Parser)]
@@ -8,7 +10,7 @@ use num_complex::Complex;
 #[command(about = "A complex number calculator for testing fractal functions and hyperoperations")]
 struct Args {
     /// The mathematical expression to evaluate
-    #[arg()]
+    #[arg(required_unless_present = "audit_identities", default_value = "")]
     expression: String,
 
     /// Point coordinates for evaluation [real, imag] (default: [0, 0])
@@ -30,11 +32,44 @@ struct Args {
     /// Enable multivalue mode to evaluate the expression with multiple values
     #[arg(long, value_delimiter = ',', num_args = 1..=3)]
     multivalue: Option<Vec<f64>>,
+
+    /// Check numerically whether the expression's derivatives in z and c are reliable enough for
+    /// distance estimation and Lyapunov exponents, at points with z and c in [-2, 2] x [-2, 2]
+    #[arg(long)]
+    check_derivatives: bool,
+
+    /// Report which algebraic identities (commutativity, associativity, inverses, zero divisors,
+    /// ...) hold in the number system of --i-sqrt-value, tested on this many random elements
+    #[arg(long, num_args = 0..=1, default_missing_value = "1000")]
+    audit_identities: Option<usize>,
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Some(samples) = args.audit_identities {
+        match parse_complex_number(&args.i_sqrt_value) {
+            Ok(i_squared) => println!("{}", audit_identities(i_squared, samples)),
+            Err(e) => {
+                eprintln!("Error parsing i_sqrt_value: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.check_derivatives {
+        let points = derivative_test_points([-2.0, 2.0, -2.0, 2.0], 6);
+        match check_formula_derivatives(&args.expression, &points, None) {
+            Ok(report) => println!("{}", report),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     if let Some(ref multivalues) = args.multivalue {
         // Multivalue mode: evaluate expression with multiple values
         if multivalues.len() < 2 {
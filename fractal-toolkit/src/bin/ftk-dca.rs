@@ -1,5 +1,7 @@
 use clap::Parser;
 use fractal_toolkit::{DomainColorParams, generate_domain_color_plot, generate_html_file};
+use fractal_toolkit::output::{save_image_atomic, ColorProfile, OutputOptions};
+use fractal_toolkit::palette::PhaseWheel;
 use rayon::ThreadPoolBuilder;
 use num_complex::Complex;
 
@@ -28,6 +30,14 @@ struct Args {
     #[arg(long, default_value = "z^2")]
     formula: String,
     
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
     /// Output file name
     #[arg(long, default_value = "domain_color_output.png")]
     output: String,
@@ -36,6 +46,14 @@ struct Args {
     #[arg(long, default_value = "-1")]
     i_sqrt_value: String,
 
+    /// Draw branch cuts in white: pixels whose phase jumps by more than this many radians (default 2.0 when given without a value)
+    #[arg(long, num_args = 0..=1, default_missing_value = "2.0")]
+    branch_cuts: Option<f64>,
+
+    /// Cyclic palette for the phase: hsv (default), hsv-lite, twilight, a palette such as [(#000000,0),(#FFFFFF,0.5)], or a colormap file with one RGB color per line
+    #[arg(long, default_value = "hsv")]
+    phase_wheel: String,
+
     /// Enable orbit debugging to trace the iteration path for a specific point
     #[arg(long)]
     orbit_debug: bool,
@@ -50,12 +68,26 @@ fn main() {
     init_rayon_pool();
 
     let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
 
     println!("Generating domain color plot with:");
     println!("  Bounds: {:?}", args.bounds);
     println!("  Dimensions: {:?}", args.dimensions);
     println!("  Formula: {}", args.formula);
     println!("  Output: {}", args.output);
+    if let Some(threshold) = args.branch_cuts {
+        println!("  Branch cuts: phase jumps over {} rad", threshold);
+    }
+    let phase_wheel = PhaseWheel::parse(&args.phase_wheel).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
     
     // Validate bounds
     if args.bounds.len() != 4 {
@@ -101,19 +133,23 @@ fn main() {
         height,
         formula: args.formula,
         i_sqrt_value: i_sqrt_complex,
+        branch_cut_threshold: args.branch_cuts,
+        phase_wheel,
     };
     
     // Generate the domain color plot
     let img = generate_domain_color_plot(&params);
     
     // Save the image
-    img.save(&args.output).expect("Failed to save image");
+    save_image_atomic(&img, &args.output, &output_options).expect("Failed to save image");
     println!("Domain color plot saved to {}", args.output);
     
     // Generate command template for the HTML
+    let branch_cuts_arg = params.branch_cut_threshold.map(|threshold| format!(" --branch-cuts={}", threshold)).unwrap_or_default();
+    let phase_wheel_arg = if args.phase_wheel == "hsv" { String::new() } else { format!(" --phase-wheel=\"{}\"", args.phase_wheel) };
     let command_template = format!(
-        "ftk-dca --bounds={{bounds}} --dimensions={{dimensions}} --formula=\"{}\" --output=\"dca_zoom_$(date +%Y%m%d_%H%M%S).png\"",
-        params.formula
+        "ftk-dca --bounds={{bounds}} --dimensions={{dimensions}} --formula=\"{}\"{}{} --output=\"dca_zoom_$(date +%Y%m%d_%H%M%S).png\"",
+        params.formula, branch_cuts_arg, phase_wheel_arg
     );
     
     // Generate the HTML file with axis marks
@@ -161,11 +197,11 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
         let mut imag_str = "";
 
         // Find the position of the imaginary part
-        if let Some(i_pos) = s.find(|c| c == 'i' || c == 'I') {
+        if let Some(i_pos) = s.find(['i', 'I']) {
             let before_i = &s[..i_pos];
 
             // Look for the last occurrence of + or - before the i
-            if let Some(last_sign_pos) = before_i.rfind(|c: char| c == '+' || c == '-') {
+            if let Some(last_sign_pos) = before_i.rfind(['+', '-']) {
                 if last_sign_pos == 0 {
                     // Starts with a sign, e.g., "-2.5i" or "+3.2i"
                     real_str = "0";
@@ -188,7 +224,7 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
 
             // Parse imaginary part
             if !imag_str.is_empty() {
-                if imag_str == "+" || imag_str == "" {
+                if imag_str == "+" || imag_str.is_empty() {
                     imag_part = 1.0;
                 } else if imag_str == "-" {
                     imag_part = -1.0;
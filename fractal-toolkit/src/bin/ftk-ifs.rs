@@ -0,0 +1,172 @@
+use clap::Parser;
+use fractal_toolkit::ifs::flame::{render_flame, Flame};
+use fractal_toolkit::ifs::{render_ifs, Ifs, IfsParams};
+use fractal_toolkit::output::{save_image_atomic, ColorProfile, OutputOptions};
+use fractal_toolkit::parse_color_palette;
+use fractal_toolkit::postfx::{apply_post_effects_rgb, parse_post_effects};
+use rayon::ThreadPoolBuilder;
+
+fn init_rayon_pool() {
+    let num_threads = num_cpus::get();
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+        .expect("Failed to initialize Rayon thread pool");
+}
+
+#[derive(Parser)]
+#[command(name = "ftk-ifs")]
+#[command(version = "1.0")]
+#[command(about = "Renders iterated function systems such as Barnsley's fern, and fractal flames, with the chaos game")]
+struct Args {
+    /// Built-in system: fern, sierpinski, dragon or levy (ignored when --maps is given)
+    #[arg(long, default_value = "fern")]
+    preset: String,
+
+    /// Maps separated by ';', each affine:a,b,c,d,e,f or mobius:a,b,c,d with an optional @probability
+    #[arg(long)]
+    maps: Option<String>,
+
+    /// Render a fractal flame instead: a preset (swirl-sierpinski, spherical, swirl, heart) or a
+    /// flame JSON file
+    #[arg(long)]
+    flame: Option<String>,
+
+    /// Gamma of the flame's tone mapping; higher values bring out faint regions (use with --flame)
+    #[arg(long, default_value_t = 2.2)]
+    gamma: f64,
+
+    /// Bounds [x_min, x_max, y_min, y_max]; fitted to the attractor if omitted
+    #[arg(long, value_delimiter = ',', num_args = 4, allow_negative_numbers = true)]
+    bounds: Option<Vec<f64>>,
+
+    /// Dimensions of the output image [width, height]
+    #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [1024, 1024])]
+    dimensions: Vec<u32>,
+
+    /// Number of points to plot
+    #[arg(long, default_value_t = 10_000_000)]
+    points: u64,
+
+    /// Color palette [(hex_color, position), ...] for the normalized density, or for the color
+    /// indices of a flame
+    #[arg(long)]
+    color_pallette: Option<String>,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Output file name
+    #[arg(long, default_value = "ifs_output.png")]
+    output: String,
+}
+
+fn main() {
+    // Initialize rayon thread pool with CPU core count
+    init_rayon_pool();
+
+    let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+
+    let flame = args.flame.as_deref().map(|flame| {
+        if flame.ends_with(".json") {
+            std::fs::read_to_string(flame)
+                .map_err(|e| format!("Failed to read '{}': {}", flame, e))
+                .and_then(|text| Flame::from_json(&text))
+        } else {
+            Flame::preset(flame)
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    let ifs = match args.maps {
+        Some(ref maps) => Ifs::parse(maps),
+        None => Ifs::preset(&args.preset),
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    // Validate dimensions
+    if args.dimensions.len() != 2 {
+        eprintln!("Error: dimensions must have exactly 2 values [width, height]");
+        std::process::exit(1);
+    }
+    let width = args.dimensions[0];
+    let height = args.dimensions[1];
+
+    let bounds = match (&args.bounds, &flame) {
+        (Some(bounds), _) => [bounds[0], bounds[1], bounds[2], bounds[3]],
+        (None, Some(flame)) => flame.bounds(100_000, 0.05),
+        (None, None) => ifs.bounds(100_000, 0.05),
+    };
+
+    match flame {
+        Some(ref flame) => {
+            println!("Rendering fractal flame with:");
+            println!("  Transforms: {}", flame.transforms.len());
+            println!("  Gamma: {}", args.gamma);
+        }
+        None => {
+            println!("Rendering iterated function system with:");
+            println!("  Maps: {}", ifs.maps.len());
+        }
+    }
+    println!("  Bounds: {:?}", bounds);
+    println!("  Dimensions: {}x{}", width, height);
+    println!("  Points: {}", args.points);
+    println!("  Output: {}", args.output);
+
+    let post_effects = args.post_fx.as_deref().map(parse_post_effects).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    // Parse color palette if provided
+    let color_palette = if let Some(ref palette_str) = args.color_pallette {
+        match parse_color_palette(palette_str) {
+            Ok(palette) => {
+                println!("Using color palette with {} stops", palette.len());
+                Some(palette)
+            },
+            Err(e) => {
+                eprintln!("Error parsing color palette: {}", e);
+                eprintln!("Using default coloring instead.");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let params = IfsParams { bounds, width, height, points: args.points, cancel: Default::default() };
+    let mut img = match flame {
+        Some(ref flame) => render_flame(flame, &params, color_palette.as_deref(), args.gamma),
+        None => render_ifs(&ifs, &params, color_palette.as_deref()),
+    };
+    if let Some(effects) = post_effects {
+        apply_post_effects_rgb(&mut img, &effects);
+    }
+
+    save_image_atomic(&img, &args.output, &output_options).expect("Failed to save image");
+    println!("IFS image saved to {}", args.output);
+}
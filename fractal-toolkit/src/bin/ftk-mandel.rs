@@ -1,5 +1,28 @@
 use clap::Parser;
-use fractal_toolkit::{FractalParams, mandelbrot_iterations, generate_html_file, parse_color_palette, ColorStop, generate_fractal_image};
+use fractal_toolkit::{AntiAliasing, FractalKind, FractalParams, InteriorColoring, MathEvaluator, EvaluatorOptions, CompiledFormula, generate_html_file, parse_color_palette, ColorStop, generate_escape_time_image_and_results, IterationResult, Viewport, iterate_batch, pixel_to_complex, Precision, parse_rgba_color, color_results};
+use fractal_toolkit::algebra::generate_bicomplex_image;
+use fractal_toolkit::coloring::ColoringPipeline;
+use fractal_toolkit::palette::PhaseWheel;
+use fractal_toolkit::postfx::{apply_post_effects, parse_post_effects};
+use fractal_toolkit::quadtree::{generate_quadtree_image, QuadtreeOptions};
+use fractal_toolkit::quality::{apply_profile, Profile};
+use fractal_toolkit::scene::{Scene, SceneFractal};
+use fractal_toolkit::quaternion::QuaternionSlice;
+use fractal_toolkit::sonify::SonifyOptions;
+use fractal_toolkit::telemetry::{self, TelemetryOptions};
+use fractal_toolkit::cli::{annotate_image, render_on_gpu, render_tiled, render_timeline_frames, render_zoom, save_breadcrumb, write_sonification, AnimationOutput};
+use fractal_toolkit::animation::{write_color_cycle_animation, ColorCycleOptions};
+use fractal_toolkit::formula::cache::cache_stats;
+use fractal_toolkit::formula::profile::{evaluation_profiles, set_profiling};
+use fractal_toolkit::formula::escape_radius_over;
+use fractal_toolkit::iteration::{iterate_viewport, BatchOptions};
+use fractal_toolkit::analysis::{cross_section, plot_profile, write_profile_csv, ProfileQuantity, TraceMode};
+use fractal_toolkit::tiled::split::{split_directory, split_tile_size};
+use fractal_toolkit::tiled::single_image_limit;
+use fractal_toolkit::output::{
+    color_results_hdr, record_render_hashes, save_image_atomic, save_image_with_params,
+    update_gallery, write_hdr_image, write_scientific_image, write_split_layers, ColorProfile, OutputOptions, ScientificOutputOptions,
+};
 use image::{ImageBuffer, Rgba};
 use rayon::ThreadPoolBuilder;
 use num_complex::Complex;
@@ -40,7 +63,7 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
     }
 
     // Find the position of 'i' or 'I'
-    let i_pos = s.find(|c| c == 'i' || c == 'I');
+    let i_pos = s.find(['i', 'I']);
 
     if let Some(i_pos) = i_pos {
         // Complex number with imaginary part
@@ -48,13 +71,13 @@ fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
             // Format like "ai" or "bi" where a or b is the coefficient
             let coeff_str = &s[..i_pos];
             if coeff_str.is_empty() || coeff_str == "+" {
-                return Ok(Complex::new(0.0, 1.0)); // Just "i"
+                Ok(Complex::new(0.0, 1.0)) // Just "i"
             } else if coeff_str == "-" {
-                return Ok(Complex::new(0.0, -1.0)); // Just "-i"
+                Ok(Complex::new(0.0, -1.0)) // Just "-i"
             } else {
                 let coeff = coeff_str.parse::<f64>()
                     .map_err(|_| format!("Invalid imaginary coefficient: {}", coeff_str))?;
-                return Ok(Complex::new(0.0, coeff));
+                Ok(Complex::new(0.0, coeff))
             }
         } else {
             // Complex number with both real and imaginary parts, like "a+bi" or "a-bi"
@@ -128,7 +151,8 @@ struct Args {
     #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [0.0, 0.0])]
     spawn: Vec<f64>,
 
-    /// Color palette [(hex_color, position), ...]
+    /// Color palette [(hex_color, position), ...], a built-in palette (grayscale, inferno, magma,
+    /// viridis, ultrafractal, fire, ice) or a .map, .ggr or .json palette file
     #[arg(long)]
     color_pallette: Option<String>,
 
@@ -136,10 +160,28 @@ struct Args {
     #[arg(long, default_value = "z^2 + c")]
     formula: String,
 
+    /// Built-in fractal to iterate instead of the formula: formula, burning-ship, perpendicular, celtic,
+    /// buffalo or lambda (the logistic map λz(1-z), with the pixel or spawn as λ)
+    #[arg(long, default_value = "formula")]
+    kind: String,
+
+    /// Skip iterating points in the main cardioid and the period-2 bulb, found with analytic tests (z^2 + c only)
+    #[arg(long)]
+    bulb_check: bool,
+
+    /// Reject ambiguous formulas (implicit multiplication, 'i' run into a name, more than four carets) instead of guessing
+    #[arg(long)]
+    strict_formula: bool,
+
     /// Bailout value
     #[arg(long, default_value_t = 4.0)]
     bailout: f64,
 
+    /// Replace --bailout with the radius beyond which every orbit escapes, derived from the
+    /// coefficients of polynomial formulas (other formulas keep --bailout)
+    #[arg(long)]
+    auto_bailout: bool,
+
     /// Output file name
     #[arg(long, default_value = "mandel_output.png")]
     output: String,
@@ -164,9 +206,271 @@ struct Args {
     #[arg(long)]
     no_bailout: bool,
 
-    /// Maximum precision in bits for arbitrary precision arithmetic (0 = disabled, standard f64 used)
+    /// Bits of precision the iteration needs (0 = use --precision); picks the cheapest tier with
+    /// that many: up to 53 for f64, 106 for double-double
     #[arg(long, default_value_t = 0)]
     max_prec: u32,
+
+    /// Kalles Fraktaler / Mandel Machine location file (.kfr) to render instead of --bounds
+    #[arg(long)]
+    location: Option<String>,
+
+    /// View in center + magnification notation ("re=.. im=.. zoom=.. rotation=..") to render instead of --bounds
+    #[arg(long)]
+    view: Option<String>,
+
+    /// Also write percentile-normalized iteration counts as a float TIFF (.tif) or 16-bit PGM (.pgm)
+    #[arg(long)]
+    scientific_output: Option<String>,
+
+    /// Also write the render at a higher bit depth for grading: 16-bit PNG (.png), or linear 32-bit
+    /// float OpenEXR (.exr) or TIFF (.tif)
+    #[arg(long)]
+    hdr_output: Option<String>,
+
+    /// Also write the inside and outside of the set as separate transparent layers, <output>_interior.png and <output>_exterior.png
+    #[arg(long)]
+    split_layers: bool,
+
+    /// Floating-point precision of the z^2 + c iteration: auto (chosen from the pixel size), f32 (fast previews), f64 or dd (double-double, requires the double-double feature)
+    #[arg(long, default_value = "auto")]
+    precision: String,
+
+    /// Also write a palette-rotation animation of the render: .gif, .png (animated PNG), or a directory for numbered frames
+    #[arg(long)]
+    color_cycle: Option<String>,
+
+    /// Number of frames in the color cycling loop (use with --color-cycle)
+    #[arg(long, default_value_t = 32)]
+    color_cycle_frames: u32,
+
+    /// Color of points inside the set (#RRGGBB, #RRGGBBAA or transparent); defaults to the palette's first stop
+    #[arg(long)]
+    inside_color: Option<String>,
+
+    /// Color of points inside the set: solid (the inside color), or from the palette by the cycle's period (period), the attractor's angle (angle) or magnitude (magnitude), or the cycle's multiplier (multiplier)
+    #[arg(long, default_value = "solid")]
+    interior: String,
+
+    /// Cyclic palette for angles (--interior angle and --domain-color): hsv-lite, twilight, a palette such as [(#000000,0),(#FFFFFF,0.5)], or a colormap file with one RGB color per line
+    #[arg(long)]
+    phase_wheel: Option<String>,
+
+    /// Color of the background where points escape immediately (#RRGGBB, #RRGGBBAA or transparent); defaults to the palette
+    #[arg(long)]
+    outside_background: Option<String>,
+
+    /// Color by fractional iteration counts so the bands between iteration counts disappear
+    #[arg(long)]
+    smooth: bool,
+
+    /// Equalize iteration counts across the image so the palette is spread evenly over the escaped pixels
+    #[arg(long)]
+    histogram: bool,
+
+    /// Times the palette repeats over the iteration range; above 1 it cycles, giving deep zooms a
+    /// steady banding rhythm
+    #[arg(long, default_value_t = 1.0)]
+    color_density: f64,
+
+    /// Shift of the palette as a fraction of its length (0 to 1), applied after --color-density
+    #[arg(long, default_value_t = 0.0)]
+    color_offset: f64,
+
+    /// Color with layers composited in order instead, e.g.
+    /// "escape;trap:shape=cross,scale=0.3,blend=multiply;angle:blend=overlay,opacity=0.4". Sources are
+    /// escape, trap (shape=point|cross|circle, x, y, radius, scale), distance and angle; every layer
+    /// takes blend=alpha|multiply|screen|overlay, opacity and palette
+    #[arg(long)]
+    coloring_layers: Option<String>,
+
+    /// Also write a sonification as a WAV file: the orbit of --sonify-orbit, or else a row of pixels (--sonify-row)
+    #[arg(long)]
+    sonify: Option<String>,
+
+    /// Point re,im whose orbit --sonify plays, one tone per iteration
+    #[arg(long, value_delimiter = ',', num_args = 2, allow_negative_numbers = true)]
+    sonify_orbit: Option<Vec<f64>>,
+
+    /// Pixel row --sonify plays from left to right, one tone per pixel (default: the middle row)
+    #[arg(long)]
+    sonify_row: Option<u32>,
+
+    /// Length of each --sonify tone in milliseconds
+    #[arg(long, default_value_t = 20.0)]
+    tone_ms: f64,
+
+    /// Render on the GPU (requires the gpu feature; f32 precision, falls back to the CPU for unsupported formulas)
+    #[arg(long)]
+    gpu: bool,
+
+    /// Render adaptively: iterate the corners and center of quadtree cells and fill cells whose
+    /// samples agree, refining only the others (fast for views dominated by the interior)
+    #[arg(long)]
+    quadtree: bool,
+
+    /// Side in pixels of the first --quadtree cells; smaller cells miss fewer small features
+    #[arg(long, default_value_t = 32)]
+    quadtree_cell: u32,
+
+    /// Time every function and operator of the formula during the render and print how the
+    /// evaluation time breaks down between them (slows the render)
+    #[arg(long)]
+    profile_formula: bool,
+
+    /// Color the outside of the set from derivatives along each orbit instead of iteration counts: distance (Milnor's distance estimate, sharp filaments at any zoom) or slope (relief lighting)
+    #[arg(long)]
+    derivative_coloring: Option<String>,
+
+    /// Iterate the formula over the bicomplex numbers and render a slice of the 4D set instead
+    /// (the Tetrabrot for z^2 + c; f64 CPU renders only)
+    #[arg(long)]
+    bicomplex: bool,
+
+    /// The two axes of the --bicomplex slice across and up the image, from re, i, j and k
+    #[arg(long, default_value = "re,i")]
+    slice_axes: String,
+
+    /// Values re,i,j,k of the two components held fixed in the --bicomplex slice
+    #[arg(long, default_value = "0,0,0,0")]
+    slice_at: String,
+
+    /// Instead of rendering, sample the iteration count along the segment re0,im0,re1,im1 and write it
+    /// to <output>.profile.csv with a plot in <output>.profile.png
+    #[arg(long, value_delimiter = ',', num_args = 4)]
+    cross_section: Option<Vec<f64>>,
+
+    /// Number of points sampled along --cross-section
+    #[arg(long, default_value_t = 1000)]
+    cross_section_samples: usize,
+
+    /// Anti-aliasing: none, NxN supersampling (e.g. 3x3), or adaptive[:NxN[:threshold]] to resample
+    /// only pixels on edges (f64 CPU renders only)
+    #[arg(long, default_value = "none")]
+    antialias: String,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;unsharp:amount=0.5;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Quality profile: draft (a quarter of the iterations, no anti-aliasing, f32 where the view
+    /// allows), standard, or production (twice the iterations, adaptive anti-aliasing, smooth
+    /// coloring and the precision the zoom needs)
+    #[arg(long, default_value = "standard")]
+    quality: String,
+
+    /// Write hashes of the image and of square tiles of this many pixels to <output>.hashes.json,
+    /// for checking that another run (another worker, a resumed render) produced the same image
+    #[arg(long)]
+    tile_hashes: Option<u32>,
+
+    /// Compare the render with a hashes file from another run and exit with an error if they differ
+    #[arg(long)]
+    verify_hashes: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// After saving, rewrite index.html and thumbnails/ in the output's directory: a gallery of
+    /// every render there with its parameters
+    #[arg(long)]
+    gallery: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Write the scene of this render (parameters, dimensions, palette and output) to this file,
+    /// as TOML (.toml) or JSON, to render it again with ftk-render --scene
+    #[arg(long)]
+    dump_scene: Option<String>,
+
+    /// Render a zoom from the view through these keyframes, each x_min,x_max,y_min,y_max and
+    /// separated by ';', as a frame sequence instead of a single image (f64 CPU renders only)
+    #[arg(long)]
+    zoom: Option<String>,
+
+    /// Number of frames of the --zoom animation
+    #[arg(long, default_value_t = 120)]
+    zoom_frames: u32,
+
+    /// Pace of the --zoom between keyframes: linear, smoothstep, cubic or step
+    #[arg(long, default_value = "smoothstep")]
+    zoom_easing: String,
+
+    /// Where the --zoom frames go: a directory of numbered PNG files, or a .y4m video stream
+    /// (which may be a named pipe read by a video encoder)
+    #[arg(long, default_value = "zoom_frames")]
+    zoom_output: String,
+
+    /// Render a timeline of keyframed parameters from this file or text, as a frame sequence:
+    /// tracks such as "zoom spline 0=1 4=50/smooth 10=2000; center.re 0=-0.5 10=-0.7435", one per
+    /// line or separated by ';', animating center.re, center.im, zoom, spawn.re, spawn.im, i2.re,
+    /// i2.im, palette-offset or bailout (f64 CPU renders only)
+    #[arg(long)]
+    timeline: Option<String>,
+
+    /// Number of frames of the --timeline animation, spread evenly over its keyframes
+    #[arg(long, default_value_t = 120)]
+    timeline_frames: u32,
+
+    /// Where the --timeline frames go: a directory of numbered PNG files, or a .y4m video stream
+    #[arg(long, default_value = "timeline_frames")]
+    timeline_output: String,
+
+    /// Save a breadcrumb strip of thumbnails of the view and the --zoom keyframes to this PNG,
+    /// each outlining where the next lies, instead of rendering the zoom
+    #[arg(long)]
+    breadcrumb: Option<String>,
+
+    /// Height in pixels of the --breadcrumb thumbnails
+    #[arg(long, default_value_t = 256)]
+    breadcrumb_height: u32,
+
+    /// Frame rate recorded in .y4m output
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    /// Write a JSON sidecar with the parameters, index and time of every frame of --zoom and --timeline,
+    /// and a manifest.json listing them, next to the frames (or in <stream>.meta for .y4m)
+    #[arg(long)]
+    frame_metadata: bool,
+
+    /// Annotate a point (label@re,im) or region (label@x_min,x_max,y_min,y_max) of the plane;
+    /// repeat for more. Annotations are drawn over the image
+    #[arg(long)]
+    annotate: Vec<String>,
+
+    /// Draw the annotations of this GeoJSON file over the image
+    #[arg(long)]
+    annotations: Option<String>,
+
+    /// Save the annotations of --annotate and --annotations to this GeoJSON file
+    #[arg(long)]
+    annotations_out: Option<String>,
+
+    /// Render in tiles kept in this directory, resuming from the tiles already there, and stitch
+    /// them into the output once all are done (f64 CPU renders only; no anti-aliasing or --post-fx)
+    #[arg(long)]
+    tile_dir: Option<String>,
+
+    /// Side length in pixels of the tiles of --tile-dir
+    #[arg(long, default_value_t = 1024)]
+    tile_size: u32,
+
+    /// Render only every n-th tile of --tile-dir, as worker k of n (e.g. 2/4), so that several
+    /// processes can share the directory
+    #[arg(long)]
+    tile_worker: Option<String>,
+
+    /// Print health telemetry (progress, throughput, memory, busy threads) every this many seconds
+    #[arg(long)]
+    telemetry: Option<f64>,
+
+    /// Seconds a thread may spend on one pixel or sample before it is reported as stalled (use with --telemetry)
+    #[arg(long, default_value_t = 600.0)]
+    stall_timeout: f64,
 }
 
 fn main() {
@@ -174,6 +478,20 @@ fn main() {
     init_rayon_pool();
 
     let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+
+    if let Some(seconds) = args.telemetry {
+        telemetry::install(TelemetryOptions::printing(
+            std::time::Duration::from_secs_f64(seconds),
+            std::time::Duration::from_secs_f64(args.stall_timeout),
+        ));
+    }
 
     println!("Generating Mandelbrot set with:");
     println!("  Bounds: {:?}", args.bounds);
@@ -181,6 +499,9 @@ fn main() {
     println!("  Dimensions: {:?}", args.dimensions);
     println!("  Spawn: {:?}", args.spawn);
     println!("  Formula: {}", args.formula);
+    if args.kind != "formula" {
+        println!("  Kind: {}", args.kind);
+    }
     println!("  Bailout: {}", args.bailout);
     println!("  Output: {}", args.output);
 
@@ -188,6 +509,17 @@ fn main() {
         println!("  Color palette: {}", palette);
     }
 
+    // Validate the formula once up front; lenient parsing accepts anything strict parsing does.
+    // Rendering falls back to z^2 + c for formulas that don't parse, so show where it failed
+    let formula_options = if args.strict_formula { EvaluatorOptions::strict() } else { EvaluatorOptions::default() };
+    if let Err(e) = MathEvaluator::validate_formula(&args.formula, &formula_options) {
+        if args.strict_formula {
+            eprintln!("Error in formula: {}", e);
+            std::process::exit(1);
+        }
+        eprintln!("Warning: formula does not parse, rendering z^2 + c instead: {}", e);
+    }
+
     // Validate dimensions
     if args.dimensions.len() != 2 {
         eprintln!("Error: dimensions must have exactly 2 values [width, height]");
@@ -202,11 +534,49 @@ fn main() {
         std::process::exit(1);
     }
 
-    if args.bounds.len() != 4 {
-        eprintln!("Error: bounds must have exactly 4 values [x_min, x_max, y_min, y_max]");
+    let mut max_iterations = args.max_iterations;
+    let mut viewport = None;
+    let bounds = if let Some(ref location_path) = args.location {
+        let location = std::fs::read_to_string(location_path)
+            .map_err(|e| format!("Could not read '{}': {}", location_path, e))
+            .and_then(|source| fractal_toolkit::interop::parse_kfr_location(&source).map_err(String::from))
+            .unwrap_or_else(|e| {
+                eprintln!("Error loading location: {}", e);
+                std::process::exit(1);
+            });
+        for warning in &location.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        if let Some(iterations) = location.max_iterations {
+            max_iterations = iterations;
+        }
+        let bounds = location.viewport.to_bounds(width, height);
+        println!("  Location: {} ({:?}, max iterations {})", location_path, bounds, max_iterations);
+        viewport = Some(location.viewport);
+        bounds
+    } else if let Some(ref notation) = args.view {
+        let parsed = Viewport::from_notation(notation).unwrap_or_else(|e| {
+            eprintln!("Error parsing view: {}", e);
+            std::process::exit(1);
+        });
+        let bounds = parsed.to_bounds(width, height);
+        viewport = Some(parsed);
+        bounds
+    } else {
+        if args.bounds.len() != 4 {
+            eprintln!("Error: bounds must have exactly 4 values [x_min, x_max, y_min, y_max]");
+            std::process::exit(1);
+        }
+        [args.bounds[0], args.bounds[1], args.bounds[2], args.bounds[3]]
+    };
+    // Views given as a center keep all of their digits for extended-precision rendering
+    let viewport = viewport.unwrap_or_else(|| Viewport::from_bounds(bounds));
+    if viewport.rotation != 0.0 {
+        // The renderers work from axis-aligned bounds, so a rotated view would render unrotated
+        eprintln!("Error: the view is rotated by {} degrees, which the renderers don't support; use rotation=0", viewport.rotation);
         std::process::exit(1);
     }
-    let bounds = [args.bounds[0], args.bounds[1], args.bounds[2], args.bounds[3]];
+    println!("  View: {}", viewport);
 
     // Parse the custom i_sqrt_value
     let i_sqrt_complex = parse_complex_number(&args.i_sqrt_value).unwrap_or_else(|e| {
@@ -219,12 +589,99 @@ fn main() {
     let formula_clone = args.formula.clone();
     let mut params = FractalParams::new(
         bounds,
-        args.max_iterations,
+        max_iterations,
         [args.spawn[0], args.spawn[1]],
         args.bailout,
         formula_clone,
     );
     params.i_sqrt_value = i_sqrt_complex;
+    params.kind = FractalKind::parse(&args.kind).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    params.bulb_check = args.bulb_check;
+    params.inside_color = args.inside_color.as_deref().map(parse_rgba_color).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    params.interior_coloring = InteriorColoring::parse(&args.interior).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let derivative_coloring = args.derivative_coloring.as_deref().map(|name| {
+        fractal_toolkit::analysis::DerivativeColoring::parse(name).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+    params.phase_wheel = args.phase_wheel.as_deref().map(|spec| {
+        PhaseWheel::parse(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    });
+    params.outside_background = args.outside_background.as_deref().map(parse_rgba_color).transpose().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    params.smooth_coloring = args.smooth;
+    params.histogram_coloring = args.histogram;
+    params.color_density = args.color_density;
+    params.color_offset = args.color_offset;
+    params.antialiasing = AntiAliasing::parse(&args.antialias).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(spec) = &args.post_fx {
+        params.post_effects = parse_post_effects(spec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+    if let Some(spec) = &args.coloring_layers {
+        params.coloring_pipeline = Some(ColoringPipeline::parse(spec, false).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }));
+    }
+    let center = viewport.center();
+    let magnitude = center.re.abs().max(center.im.abs());
+    params.precision = if args.precision == "auto" {
+        Precision::for_pixel_size(viewport.pixel_size(height), magnitude)
+    } else {
+        Precision::parse(&args.precision).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        })
+    };
+    if args.max_prec > 0 {
+        params.precision = Precision::with_bits(args.max_prec).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
+    }
+    params.quality = Profile::parse(&args.quality).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let quality = params.quality;
+    apply_profile(&mut params, height);
+    println!("  Quality: {} ({} iterations)", quality, params.max_iterations);
+    println!("  Precision: {}", params.precision);
+    if viewport.pixel_size(height) < params.precision.min_pixel_size() * magnitude.max(1.0) {
+        eprintln!("Warning: zoom exceeds {} precision; the image will be pixelated", params.precision);
+    }
+
+    if args.auto_bailout {
+        let formula = CompiledFormula::new(&params.formula);
+        match escape_radius_over(&formula, params.bounds) {
+            Some(radius) => {
+                println!("  Bailout: {} (escape radius of the formula for every c in the view)", radius);
+                params.bailout = radius;
+            }
+            None => eprintln!("Warning: formula is not a polynomial in z of degree 2 or more; keeping bailout {}", params.bailout),
+        }
+    }
 
     // If orbit debugging is enabled, trace the orbit for a specific point
     if args.orbit_debug {
@@ -242,6 +699,24 @@ fn main() {
         return; // Exit after debugging
     }
 
+    // Sample a line instead of rendering, for analysing the formula across a feature
+    if let Some(ref segment) = args.cross_section {
+        let start = Complex::new(segment[0], segment[1]);
+        let end = Complex::new(segment[2], segment[3]);
+        let profile = cross_section(start, end, args.cross_section_samples, &params, TraceMode::Mandelbrot);
+        let output = std::path::Path::new(&args.output);
+        let csv_path = output.with_extension("profile.csv").to_string_lossy().into_owned();
+        if let Err(e) = write_profile_csv(&csv_path, &profile) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        let plot_path = output.with_extension("profile.png");
+        save_image_atomic(&plot_profile(&profile, ProfileQuantity::SmoothIterations, 800, 300), &plot_path, &output_options)
+            .expect("Failed to save plot");
+        println!("Cross-section of {} points saved to {} and {}", profile.len(), csv_path, plot_path.display());
+        return;
+    }
+
     // Parse color palette if provided
     let color_palette = if let Some(ref palette_str) = args.color_pallette {
         match parse_color_palette(palette_str) {
@@ -259,26 +734,230 @@ fn main() {
         None
     };
 
-    // Generate the fractal image
-    let img = if args.max_prec > 0 {
-        // Use arbitrary precision mode
-        fractal_toolkit::generate_mandelbrot_image_arbitrary_precision(width, height, &params, args.max_prec, color_palette.as_ref())
-    } else if args.domain_color {
+    let animation = |path: &str, frames: u32| AnimationOutput {
+        path: path.to_string(),
+        frames,
+        fps: args.fps,
+        metadata: args.frame_metadata,
+        options: output_options.clone(),
+    };
+
+    if let Some(ref path) = args.breadcrumb {
+        if let Err(e) = save_breadcrumb(path, args.zoom.as_deref(), args.breadcrumb_height, &params, false, color_palette.as_deref(), &output_options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(ref timeline) = args.timeline {
+        if let Err(e) = render_timeline_frames(timeline, &animation(&args.timeline_output, args.timeline_frames), width, height, &params, false, color_palette.as_deref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(ref keyframes) = args.zoom {
+        if let Err(e) = render_zoom(keyframes, &args.zoom_easing, &animation(&args.zoom_output, args.zoom_frames), width, height, &params, false, color_palette.as_deref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Sizes no single image file can hold are rendered in tiles saved as separate images
+    let oversize = single_image_limit(width, height, &args.output);
+    if let Some(ref reason) = oversize {
+        let untiled = [
+            (args.domain_color, "--domain-color"),
+            (params.precision.is_extended(), "Extended precision"),
+            (args.bicomplex, "--bicomplex"),
+            (derivative_coloring.is_some(), "--derivative-coloring"),
+        ];
+        if let Some((_, option)) = untiled.iter().find(|(set, _)| *set) {
+            eprintln!("Error: a {}x{} image can't be saved as one file ({}), and {} renders can't be split into tiles", width, height, reason, option);
+            std::process::exit(1);
+        }
+    }
+    let tile_dir = args.tile_dir.clone().or_else(|| {
+        let reason = oversize.as_ref()?;
+        let directory = split_directory(&args.output);
+        println!("A {}x{} image can't be saved as one file ({}); rendering it in tiles kept in {} instead", width, height, reason, directory);
+        Some(directory)
+    });
+
+    if let Some(ref directory) = tile_dir {
+        let tile_size = if args.tile_dir.is_some() { args.tile_size } else { split_tile_size(&args.output) };
+        if let Err(e) = render_tiled(directory, args.tile_worker.as_deref(), &args.output, width, height, tile_size, &params, false, color_palette.as_deref(), &output_options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(ref path) = args.dump_scene {
+        let scene = Scene {
+            fractal: SceneFractal::Mandelbrot,
+            dimensions: [width, height],
+            output: args.output.clone(),
+            palette: args.color_pallette.clone(),
+            params: params.clone(),
+        };
+        match scene.save(path) {
+            Ok(()) => println!("Scene saved to {}", path),
+            Err(e) => eprintln!("Error saving scene: {}", e),
+        }
+    }
+
+    // Generate the fractal image, keeping the iteration results when the renderer provides them
+    set_profiling(args.profile_formula);
+    let render_start = std::time::Instant::now();
+    let (mut img, rendered_results) = if args.domain_color {
         // Use domain coloring mode with standard precision
-        fractal_toolkit::generate_mandelbrot_domain_color_image(width, height, &params, args.no_bailout, color_palette.as_ref())
+        (fractal_toolkit::generate_mandelbrot_domain_color_image(width, height, &params, args.no_bailout, color_palette.as_ref()), Vec::new())
+    } else if params.precision.is_extended() {
+        generate_viewport_image(width, height, &viewport, &params, color_palette.as_ref())
+    } else if args.bicomplex {
+        match QuaternionSlice::parse(&args.slice_axes, &args.slice_at) {
+            Ok(slice) => {
+                println!("  Bicomplex slice along {:?} and {:?}", slice.horizontal, slice.vertical);
+                (generate_bicomplex_image(width, height, &params, None, &slice, color_palette.as_ref()), Vec::new())
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(coloring) = derivative_coloring {
+        (fractal_toolkit::generate_derivative_image(width, height, &params, false, coloring, color_palette.as_ref()), Vec::new())
+    } else if args.quadtree {
+        let options = QuadtreeOptions { initial_cell: args.quadtree_cell, ..QuadtreeOptions::default() };
+        generate_quadtree_image(width, height, &params, false, &options, color_palette.as_ref())
+    } else if args.gpu {
+        match render_on_gpu(width, height, &params, false, color_palette.as_ref()) {
+            Some(rendered) => rendered,
+            None => generate_mandelbrot_image(width, height, &params, color_palette.as_ref()),
+        }
     } else {
         generate_mandelbrot_image(width, height, &params, color_palette.as_ref())
     };
 
-    // Save the image
-    img.save(&args.output).expect("Failed to save image");
+    let cache = cache_stats();
+    if cache.hits + cache.misses > 0 {
+        println!(
+            "Rendered in {:.2?}; cached() subtrees: {} hits, {} misses ({:.1}% hit rate)",
+            render_start.elapsed(),
+            cache.hits,
+            cache.misses,
+            100.0 * cache.hit_rate()
+        );
+    }
+    if args.profile_formula {
+        let profiles = evaluation_profiles();
+        if profiles.iter().all(|profile| profile.total_time().is_zero()) {
+            println!("Formula profile: no evaluations recorded; this render didn't use the formula evaluator");
+        }
+        for profile in profiles.iter().filter(|profile| !profile.total_time().is_zero()) {
+            print!("{}", profile);
+        }
+    }
+
+    // Iteration counts for outputs derived from the render, computed only if the renderer didn't keep them
+    let results = if !rendered_results.is_empty() || (args.scientific_output.is_none() && args.hdr_output.is_none() && args.color_cycle.is_none() && !args.split_layers) {
+        rendered_results
+    } else {
+        let points: Vec<Complex<f64>> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| pixel_to_complex(x, y, width, height, bounds)))
+            .collect();
+        iterate_batch(&points, &params)
+    };
+
+    // Layers are split before annotations are drawn over the render
+    if args.split_layers {
+        if args.bicomplex {
+            eprintln!("Error: --split-layers doesn't support --bicomplex");
+            std::process::exit(1);
+        }
+        match write_split_layers(&args.output, &img, &results, &output_options) {
+            Ok((interior, exterior)) => println!("Interior and exterior layers saved to {} and {}", interior, exterior),
+            Err(e) => {
+                eprintln!("Error writing layers: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Err(e) = annotate_image(&mut img, args.annotations.as_deref(), &args.annotate, args.annotations_out.as_deref(), bounds) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    // Save the image, with the parameters embedded in PNGs so the render can be reproduced from it
+    save_image_with_params(&img, &args.output, &params, color_palette.as_deref(), Some(&viewport), &output_options).expect("Failed to save image");
     println!("Mandelbrot image saved to {}", args.output);
+    if let Err(e) = record_render_hashes(&img, &params, Vec::new(), &args.output, args.tile_hashes, args.verify_hashes.as_deref()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(ref path) = args.sonify {
+        let point = args.sonify_orbit.as_ref().map(|point| Complex::new(point[0], point[1]));
+        let options = SonifyOptions { tone_seconds: args.tone_ms / 1000.0, ..SonifyOptions::default() };
+        if let Err(e) = write_sonification(path, point, args.sonify_row, width, height, &params, false, &options) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(ref scientific_path) = args.scientific_output {
+        let iterations: Vec<f64> = results.iter().map(|r| r.iterations as f64).collect();
+        match write_scientific_image(scientific_path, width, height, &iterations, &ScientificOutputOptions { checksum: args.checksum, ..ScientificOutputOptions::default() }) {
+            Ok(normalization) => println!(
+                "Scientific output saved to {} (normalized {} between {} and {})",
+                scientific_path, normalization.quantity, normalization.low_value, normalization.high_value
+            ),
+            Err(e) => {
+                eprintln!("Error writing scientific output: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(ref path) = args.hdr_output {
+        let written = color_results_hdr(width, height, &results, &params, color_palette.as_deref()).and_then(|image| write_hdr_image(path, &image, &output_options).map_err(String::from));
+        match written {
+            Ok(format) => println!("{:?} output saved to {}", format, path),
+            Err(e) => {
+                eprintln!("Error writing high bit depth output: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(ref cycle_path) = args.color_cycle {
+        let iterations: Vec<u32> = results.iter().map(|r| r.iterations).collect();
+        let options = ColorCycleOptions {
+            frames: args.color_cycle_frames,
+            inside_color: params.inside_color,
+            outside_background: params.outside_background,
+            ..Default::default()
+        };
+        let palette = color_palette.as_deref().unwrap_or(&[]);
+        match write_color_cycle_animation(cycle_path, &iterations, width, height, params.max_iterations, palette, &options) {
+            Ok(()) => println!("Color cycling animation ({} frames) saved to {}", options.frames, cycle_path),
+            Err(e) => {
+                eprintln!("Error writing color cycling animation: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 
     // Generate command template for the HTML
-    let command_template = if let Some(ref palette) = args.color_pallette {
+    let mut command_template = if let Some(ref palette) = args.color_pallette {
         format!(
             "ftk-mandel --bounds={{bounds}} --dimensions={{dimensions}} --max-iterations={} --spawn={},{} --color-pallette=\"{}\" --bailout={} --formula=\"{}\" --output=\"mandel_zoom_$(date +%Y%m%d_%H%M%S).png\"",
-            args.max_iterations,
+            max_iterations,
             args.spawn[0],
             args.spawn[1],
             palette,
@@ -288,7 +967,7 @@ fn main() {
     } else {
         format!(
             "ftk-mandel --bounds={{bounds}} --dimensions={{dimensions}} --max-iterations={} --spawn={},{} --bailout={} --formula=\"{}\" --output=\"mandel_zoom_$(date +%Y%m%d_%H%M%S).png\"",
-            args.max_iterations,
+            max_iterations,
             args.spawn[0],
             args.spawn[1],
             args.bailout,
@@ -296,6 +975,25 @@ fn main() {
         )
     };
 
+    if args.kind != "formula" {
+        command_template.push_str(&format!(" --kind={}", args.kind));
+    }
+    if args.bulb_check {
+        command_template.push_str(" --bulb-check");
+    }
+    if quality != Profile::Standard {
+        command_template.push_str(&format!(" --quality={}", quality));
+    }
+    if args.interior != "solid" {
+        command_template.push_str(&format!(" --interior={}", args.interior));
+    }
+    if let Some(ref wheel) = args.phase_wheel {
+        command_template.push_str(&format!(" --phase-wheel=\"{}\"", wheel));
+    }
+    if args.bicomplex {
+        command_template.push_str(&format!(" --bicomplex --slice-axes={} --slice-at={}", args.slice_axes, args.slice_at));
+    }
+
     // Generate the HTML file
     if let Err(e) = generate_html_file(&args.output, bounds, [width, height], &command_template) {
         eprintln!("Error generating HTML file: {}", e);
@@ -303,8 +1001,31 @@ fn main() {
         println!("HTML explorer saved to {}",
                  std::path::Path::new(&args.output).with_extension("html").display());
     }
+
+    if args.gallery {
+        match update_gallery(&args.output) {
+            Ok((index, count)) => println!("Gallery of {} renders saved to {}", count, index.display()),
+            Err(e) => eprintln!("Error updating the gallery: {}", e),
+        }
+    }
 }
 
-fn generate_mandelbrot_image(width: u32, height: u32, params: &FractalParams, color_palette: Option<&Vec<ColorStop>>) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    generate_fractal_image(width, height, params, |c, p| mandelbrot_iterations(c, p), color_palette)
-}
\ No newline at end of file
+fn generate_mandelbrot_image(width: u32, height: u32, params: &FractalParams, color_palette: Option<&Vec<ColorStop>>) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, Vec<IterationResult>) {
+    generate_escape_time_image_and_results(width, height, params, false, color_palette)
+}
+/// Render from the viewport's decimal center, for precisions beyond f64
+fn generate_viewport_image(width: u32, height: u32, viewport: &Viewport, params: &FractalParams, color_palette: Option<&Vec<ColorStop>>) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, Vec<IterationResult>) {
+    println!("Rendering fractal in {} precision: {}x{} pixels", params.precision, width, height);
+    let results = iterate_viewport(viewport, width, height, params, &BatchOptions::default()).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    let colors = color_results(&results, params, color_palette.map(|p| p.as_slice()));
+    let mut imgbuf = ImageBuffer::new(width, height);
+    for (pixel, color) in imgbuf.pixels_mut().zip(colors) {
+        *pixel = color;
+    }
+    apply_post_effects(&mut imgbuf, &params.post_effects);
+    (imgbuf, results)
+}
@@ -0,0 +1,152 @@
+use clap::Parser;
+use fractal_toolkit::newton::{generate_newton_image, NewtonParams};
+use fractal_toolkit::output::{save_image_atomic, ColorProfile, OutputOptions};
+use fractal_toolkit::postfx::{apply_post_effects, parse_post_effects};
+use fractal_toolkit::{generate_html_file, parse_complex_number, parse_hex_color};
+use rayon::ThreadPoolBuilder;
+
+fn init_rayon_pool() {
+    let num_threads = num_cpus::get();
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+        .expect("Failed to initialize Rayon thread pool");
+}
+
+#[derive(Parser)]
+#[command(name = "ftk-newton")]
+#[command(version = "1.0")]
+#[command(about = "Renders Newton fractals: the basins of the roots Newton's method converges to")]
+struct Args {
+    /// Bounds of the complex plane [x_min, x_max, y_min, y_max]
+    #[arg(long, value_delimiter = ',', num_args = 1..=4, default_values_t = [-2.0, 2.0, -2.0, 2.0])]
+    bounds: Vec<f64>,
+
+    /// Dimensions of the output image [width, height]
+    #[arg(long, value_delimiter = ',', num_args = 1..=2, default_values_t = [1024, 1024])]
+    dimensions: Vec<u32>,
+
+    /// Degree n of z^n - 1, whose roots are the n-th roots of unity (ignored when --roots is given)
+    #[arg(long, default_value_t = 3)]
+    degree: u32,
+
+    /// Roots of the polynomial separated by ';', e.g. "1; -1; 0.5+0.5i"
+    #[arg(long)]
+    roots: Option<String>,
+
+    /// Maximum number of Newton steps per pixel
+    #[arg(long, default_value_t = 64)]
+    max_iterations: u32,
+
+    /// Distance to a root at which a point counts as converged
+    #[arg(long, default_value_t = 1e-6)]
+    tolerance: f64,
+
+    /// Strength of the color blending across basin boundaries: 0 for hard edges, 1 by default
+    #[arg(long, default_value_t = 1.0)]
+    boundary_smoothing: f64,
+
+    /// Basin colors separated by ',', one per root in order, e.g. "#FF0000,#00FF00,#0000FF";
+    /// evenly spaced hues if omitted
+    #[arg(long)]
+    root_colors: Option<String>,
+
+    /// Post-processing effects applied in order, e.g. "bloom:threshold=0.6,radius=8;vignette"
+    #[arg(long)]
+    post_fx: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+
+    /// Output file name
+    #[arg(long, default_value = "newton_output.png")]
+    output: String,
+}
+
+fn main() {
+    // Initialize rayon thread pool with CPU core count
+    init_rayon_pool();
+
+    let args = Args::parse();
+    let exit = |message: String| -> ! {
+        eprintln!("Error: {}", message);
+        std::process::exit(1);
+    };
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| exit(e)),
+        checksum: args.checksum,
+    };
+
+    // Validate bounds
+    if args.bounds.len() != 4 {
+        exit("bounds must have exactly 4 values [x_min, x_max, y_min, y_max]".to_string());
+    }
+
+    // Validate dimensions
+    if args.dimensions.len() != 2 {
+        exit("dimensions must have exactly 2 values [width, height]".to_string());
+    }
+
+    let width = args.dimensions[0];
+    let height = args.dimensions[1];
+    let bounds = [args.bounds[0], args.bounds[1], args.bounds[2], args.bounds[3]];
+
+    let mut params = match args.roots {
+        Some(ref roots) => {
+            let roots: Vec<_> = roots
+                .split(';')
+                .map(|root| parse_complex_number(root).map_err(|e| format!("Invalid root '{}': {}", root.trim(), e)))
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|e| exit(e));
+            NewtonParams::new(bounds, args.max_iterations, roots)
+        }
+        None if args.degree >= 2 => NewtonParams::roots_of_unity(bounds, args.max_iterations, args.degree),
+        None => exit(format!("degree must be at least 2, found {}", args.degree)),
+    };
+    if params.roots.len() < 2 {
+        exit("a Newton fractal needs at least 2 roots".to_string());
+    }
+    params.tolerance = args.tolerance;
+    params.boundary_smoothing = args.boundary_smoothing;
+
+    let root_colors = args.root_colors.as_deref().map(|colors| {
+        colors.split(',').map(|color| parse_hex_color(color.trim())).collect::<Result<Vec<_>, _>>().unwrap_or_else(|e| exit(e))
+    });
+    let post_effects = args.post_fx.as_deref().map(parse_post_effects).transpose().unwrap_or_else(|e| exit(e)).unwrap_or_default();
+
+    println!("Generating Newton fractal with:");
+    println!("  Bounds: {:?}", bounds);
+    println!("  Dimensions: {}x{}", width, height);
+    println!("  Roots: {:?}", params.roots);
+    println!("  Output: {}", args.output);
+
+    let mut img = generate_newton_image(width, height, &params, root_colors.as_deref());
+    apply_post_effects(&mut img, &post_effects);
+
+    // Save the image
+    save_image_atomic(&img, &args.output, &output_options).unwrap_or_else(|e| exit(e.to_string()));
+    println!("Newton fractal saved to {}", args.output);
+
+    // Generate command template for the HTML
+    let roots_arg = match args.roots {
+        Some(ref roots) => format!(" --roots=\"{}\"", roots),
+        None => format!(" --degree={}", args.degree),
+    };
+    let colors_arg = args.root_colors.as_ref().map(|colors| format!(" --root-colors=\"{}\"", colors)).unwrap_or_default();
+    let command_template = format!(
+        "ftk-newton --bounds={{bounds}} --dimensions={{dimensions}}{}{} --max-iterations={} --output=\"newton_zoom_$(date +%Y%m%d_%H%M%S).png\"",
+        roots_arg, colors_arg, args.max_iterations
+    );
+
+    // Generate the HTML file with axis marks
+    if let Err(e) = generate_html_file(&args.output, bounds, [width, height], &command_template) {
+        eprintln!("Error generating HTML file: {}", e);
+    } else {
+        println!("HTML explorer saved to {}", std::path::Path::new(&args.output).with_extension("html").display());
+    }
+}
@@ -0,0 +1,102 @@
+use clap::Parser;
+use fractal_toolkit::output::{save_image_with_params, update_gallery, ColorProfile, OutputOptions};
+use fractal_toolkit::parse_color_palette;
+use fractal_toolkit::scene::{render_scene, Scene};
+use rayon::ThreadPoolBuilder;
+
+fn init_rayon_pool() {
+    let num_threads = num_cpus::get();
+    ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+        .expect("Failed to initialize Rayon thread pool");
+}
+
+#[derive(Parser)]
+#[command(name = "ftk-render")]
+#[command(version = "1.0")]
+#[command(about = "Renders a Mandelbrot or Julia scene described in a JSON or TOML scene file")]
+struct Args {
+    /// Scene file: fractal, dimensions, palette, output and the render parameters, as TOML
+    /// (.toml) or JSON
+    #[arg(long)]
+    scene: String,
+
+    /// Output file name, instead of the scene's
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Also write the scene as rendered to this file (.toml or .json), e.g. to convert between formats
+    #[arg(long)]
+    dump_scene: Option<String>,
+
+    /// Write a <file>.sha256 file with the SHA-256 of every output, to check later with sha256sum -c
+    #[arg(long)]
+    checksum: bool,
+
+    /// After saving, rewrite index.html and thumbnails/ in the output's directory: a gallery of
+    /// every render there with its parameters
+    #[arg(long)]
+    gallery: bool,
+
+    /// Embed an ICC profile in PNG and TIFF outputs: `srgb`, or the path of an .icc/.icm file
+    #[arg(long)]
+    icc_profile: Option<String>,
+}
+
+fn main() {
+    // Initialize rayon thread pool with CPU core count
+    init_rayon_pool();
+
+    let args = Args::parse();
+    let output_options = OutputOptions {
+        color_profile: args.icc_profile.as_deref().map(ColorProfile::parse).transpose().unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        checksum: args.checksum,
+    };
+
+    let mut scene = Scene::load(&args.scene).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    if let Some(ref output) = args.output {
+        scene.output = output.clone();
+    }
+
+    println!("Rendering scene {}:", args.scene);
+    println!("  Fractal: {:?}", scene.fractal);
+    println!("  Dimensions: {}x{}", scene.dimensions[0], scene.dimensions[1]);
+    println!("  Bounds: {:?}", scene.params.bounds);
+    println!("  Formula: {}", scene.params.formula);
+    println!("  Max iterations: {}", scene.params.max_iterations);
+
+    let img = render_scene(&scene).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    let palette = scene.palette.as_deref().map(parse_color_palette).transpose().unwrap_or_default();
+    if let Err(e) = save_image_with_params(&img, &scene.output, &scene.params, palette.as_deref(), None, &output_options) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    println!("Image saved to {}", scene.output);
+
+    if args.gallery {
+        match update_gallery(&scene.output) {
+            Ok((index, count)) => println!("Gallery of {} renders saved to {}", count, index.display()),
+            Err(e) => eprintln!("Error updating the gallery: {}", e),
+        }
+    }
+
+    if let Some(ref path) = args.dump_scene {
+        match scene.save(path) {
+            Ok(()) => println!("Scene saved to {}", path),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
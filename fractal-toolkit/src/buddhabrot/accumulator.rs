@@ -0,0 +1,248 @@
+//! Progressive Buddhabrot accumulation
+//!
+//! `generate_buddhabrot` takes every sample before it returns. A `BuddhabrotAccumulator` owns
+//! the three channel histograms and takes samples in batches, so a caller can preview a render
+//! while it converges, stop once it looks good, or split the samples across machines and merge
+//! the histograms afterwards. Sample `n` of a channel always draws the same c, so accumulating
+//! in batches gives exactly the histograms of a single call, and accumulators that cover
+//! disjoint sample ranges merge into the histograms of their union.
+
+use super::prefilter::EscapeTimeGrid;
+use crate::{accumulate_buddhabrot_samples, combine_buddhabrot_channels, postfx, telemetry, BuddhabrotParams, DensityGrid, FractalError};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Channel histograms of a Buddhabrot render that is still taking samples
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuddhabrotAccumulator {
+    params: BuddhabrotParams,
+    /// Red, green and blue histograms
+    histograms: [DensityGrid; 3],
+    /// Index of the first sample this accumulator takes itself
+    first_sample: u64,
+    /// Samples each channel has taken itself, from `first_sample` on
+    taken: [u64; 3],
+    /// Sample ranges of each channel in the histograms, including merged ones
+    ranges: [Vec<Range<u64>>; 3],
+    /// Escape-time prefilter grid, recomputed after deserializing
+    #[serde(skip)]
+    grid: Option<EscapeTimeGrid>,
+}
+
+impl BuddhabrotAccumulator {
+    /// An empty accumulation that takes each channel's samples `0..samples`
+    pub fn new(params: &BuddhabrotParams) -> Self {
+        Self::starting_at(params, 0)
+    }
+
+    /// An empty accumulation that takes each channel's samples from `first_sample` on
+    ///
+    /// Machines sharing a render start at multiples of the channels' sample counts, so that
+    /// their accumulations can be merged.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Buddhabrot parameters; each channel's `samples` is how many samples this
+    ///   accumulator takes in total
+    /// * `first_sample` - Index of the first sample
+    pub fn starting_at(params: &BuddhabrotParams, first_sample: u64) -> Self {
+        let empty = DensityGrid::new(params.width as usize, params.height as usize);
+        Self {
+            params: params.clone(),
+            histograms: [empty.clone(), empty.clone(), empty],
+            first_sample,
+            taken: [0; 3],
+            ranges: [Vec::new(), Vec::new(), Vec::new()],
+            grid: None,
+        }
+    }
+
+    /// The parameters the accumulation renders
+    pub fn params(&self) -> &BuddhabrotParams {
+        &self.params
+    }
+
+    /// Red, green and blue histograms
+    pub fn histograms(&self) -> &[DensityGrid; 3] {
+        &self.histograms
+    }
+
+    /// Samples in the red, green and blue histograms, including merged ones
+    pub fn samples(&self) -> [u64; 3] {
+        self.ranges.clone().map(|ranges| ranges.iter().map(|range| range.end - range.start).sum())
+    }
+
+    /// Whether every channel has taken all of its own samples
+    pub fn is_complete(&self) -> bool {
+        self.remaining().iter().all(|&remaining| remaining == 0)
+    }
+
+    fn channel_samples(&self) -> [u64; 3] {
+        let channels = &self.params.channels;
+        [channels.red.samples, channels.green.samples, channels.blue.samples]
+    }
+
+    fn remaining(&self) -> [u64; 3] {
+        let samples = self.channel_samples();
+        [0, 1, 2].map(|channel| samples[channel].saturating_sub(self.taken[channel]))
+    }
+
+    /// Take up to `n` more samples in every channel
+    ///
+    /// # Returns
+    ///
+    /// The number of samples taken by the channel that took the most; 0 once the accumulation is
+    /// complete. `FractalError::Cancelled` if the parameters' token is cancelled, in which case
+    /// the channels that hadn't finished their batch keep their histograms from before the call
+    pub fn add_samples(&mut self, n: u64) -> Result<u64, FractalError> {
+        let batches = self.remaining().map(|remaining| remaining.min(n));
+        for (channel, &count) in batches.iter().enumerate() {
+            let start = self.first_sample + self.taken[channel];
+            if count > 0 && self.ranges[channel].iter().any(|range| range.start < start + count && start < range.end) {
+                return Err(FractalError::InvalidInput(format!(
+                    "Samples {}..{} were already merged into this accumulation",
+                    start,
+                    start + count
+                )));
+            }
+        }
+        if self.grid.is_none() && self.params.prefilter_cells > 0 {
+            let channels = [&self.params.channels.red, &self.params.channels.green, &self.params.channels.blue];
+            let max_iter = channels.iter().map(|channel| channel.max_iter).max().unwrap_or(self.params.max_iterations);
+            self.grid = Some(EscapeTimeGrid::compute(&self.params, self.params.prefilter_cells, max_iter));
+        }
+
+        let bounded = self.params.orbits.bounded_channels();
+        let telemetry = telemetry::RenderTelemetry::start("Accumulating Buddhabrot samples", batches.iter().sum());
+        for (channel, &count) in batches.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let config = match channel {
+                0 => &self.params.channels.red,
+                1 => &self.params.channels.green,
+                _ => &self.params.channels.blue,
+            };
+            let start = self.first_sample + self.taken[channel];
+            // Sample into a scratch histogram so that a batch cut short by a cancel is dropped
+            // whole, leaving the channel to take those samples again
+            let mut batch = DensityGrid::new(self.params.width as usize, self.params.height as usize);
+            accumulate_buddhabrot_samples(
+                &self.params,
+                config,
+                bounded[channel],
+                self.grid.as_ref(),
+                start..start + count,
+                &mut batch,
+                &telemetry,
+            );
+            self.params.cancel.check()?;
+            self.histograms[channel].add_grid(&batch);
+            // Extend this accumulator's own range rather than adding one per batch
+            match self.ranges[channel].iter_mut().find(|range| range.end == start) {
+                Some(range) => range.end += count,
+                None => self.ranges[channel].push(start..start + count),
+            }
+            self.taken[channel] += count;
+        }
+        Ok(batches.into_iter().max().unwrap_or(0))
+    }
+
+    /// Add another accumulation of the same render, e.g. from another machine
+    ///
+    /// # Returns
+    ///
+    /// An error if the renders differ in anything that affects the histograms (except sample
+    /// counts) or if both have taken some of the same samples
+    pub fn merge(&mut self, other: &BuddhabrotAccumulator) -> Result<(), FractalError> {
+        let (a, b) = (&self.params, &other.params);
+        let iteration_ranges = |params: &BuddhabrotParams| {
+            let channels = &params.channels;
+            [&channels.red, &channels.green, &channels.blue].map(|channel| (channel.min_iter, channel.max_iter))
+        };
+        if (a.width, a.height) != (b.width, b.height)
+            || a.bounds != b.bounds
+            || a.sampling_bounds() != b.sampling_bounds()
+            || a.formula != b.formula
+            || a.bailout != b.bailout
+            || a.i_sqrt_value != b.i_sqrt_value
+            || a.orbits != b.orbits
+            || iteration_ranges(a) != iteration_ranges(b)
+        {
+            return Err(FractalError::InvalidInput("Cannot merge accumulations of different Buddhabrot renders".to_string()));
+        }
+        for channel in 0..3 {
+            for theirs in &other.ranges[channel] {
+                if self.ranges[channel].iter().any(|ours| ours.start < theirs.end && theirs.start < ours.end) {
+                    return Err(FractalError::InvalidInput(format!(
+                        "Both accumulations contain samples {}..{}; give each machine its own first sample",
+                        theirs.start, theirs.end
+                    )));
+                }
+            }
+        }
+        for channel in 0..3 {
+            self.histograms[channel].add_grid(&other.histograms[channel]);
+            self.ranges[channel].extend(other.ranges[channel].iter().cloned());
+        }
+        Ok(())
+    }
+
+    /// Render the histograms so far as `generate_buddhabrot` would
+    pub fn snapshot_image(&self) -> image::RgbImage {
+        let [mut red_hist, mut green_hist, mut blue_hist] = self.histograms.clone();
+        super::denoise::denoise_channels(self.params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+        let mut img = combine_buddhabrot_channels(
+            self.params.width,
+            self.params.height,
+            &red_hist,
+            &green_hist,
+            &blue_hist,
+            self.params.channel_mix.as_ref(),
+            self.params.display_transform,
+        );
+        postfx::apply_post_effects_rgb(&mut img, &self.params.post_effects);
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buddhabrot_channel, BuddhabrotChannel, BuddhabrotChannels};
+
+    #[test]
+    fn test_batches_and_merges_match_single_render() {
+        let channel = |samples| BuddhabrotChannel { min_iter: 5, max_iter: 60, samples };
+        let channels = BuddhabrotChannels { red: channel(3000), green: channel(2000), blue: channel(1000) };
+        let params = BuddhabrotParams::new([-2.0, 2.0, -2.0, 2.0], 16, 16, 5, 60, 3000, 2.0, "z^2 + c".to_string(), channels);
+
+        let mut progressive = BuddhabrotAccumulator::new(&params);
+        assert_eq!(progressive.add_samples(1200).unwrap(), 1200);
+        assert!(!progressive.is_complete());
+        while progressive.add_samples(1200).unwrap() > 0 {}
+        assert!(progressive.is_complete());
+        assert_eq!(progressive.samples(), [3000, 2000, 1000]);
+        assert_eq!(progressive.histograms()[0], buddhabrot_channel(&params, &params.channels.red, 0));
+
+        // A second machine takes the next samples; merging gives a render with twice as many
+        let mut second = BuddhabrotAccumulator::starting_at(&params, 3000);
+        second.add_samples(u64::MAX).unwrap();
+        let mut merged = progressive.clone();
+        merged.merge(&second).unwrap();
+        assert_eq!(merged.samples(), [6000, 4000, 2000]);
+        let mut doubled = params.clone();
+        doubled.channels.red.samples = 6000;
+        assert_eq!(merged.histograms()[0], buddhabrot_channel(&doubled, &doubled.channels.red, 0));
+
+        assert!(merged.merge(&second).is_err());
+        assert_eq!(merged.snapshot_image().dimensions(), (16, 16));
+
+        // A cancelled batch isn't counted, so its samples are taken again later
+        let mut cancelled = BuddhabrotAccumulator::new(&params);
+        params.cancel.cancel();
+        assert_eq!(cancelled.add_samples(1200), Err(FractalError::Cancelled));
+        assert_eq!(cancelled.samples(), [0; 3]);
+        assert!(cancelled.histograms()[0].as_slice().iter().all(|&count| count == 0.0));
+    }
+}
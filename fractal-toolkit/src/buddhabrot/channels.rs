@@ -0,0 +1,204 @@
+//! Consistency checks for Buddhabrot channel configurations
+//!
+//! A channel draws the orbits of points that escape after at least `min_iter` and fewer than
+//! `max_iter` iterations, from `samples` random samples. A channel whose range is empty or
+//! that has no samples renders black without any warning, which is easy to miss among three
+//! channels. `BuddhabrotChannels::validate` reports such channels as typed errors, and
+//! `BuddhabrotChannels::fix` repairs them with the nearest consistent settings.
+
+use crate::{BuddhabrotChannel, BuddhabrotChannels, BuddhabrotJuliaParams, BuddhabrotParams};
+use std::fmt;
+
+/// What is wrong with a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelProblem {
+    /// `min_iter` is not below `max_iter`, so no escape time is in range
+    EmptyRange {
+        /// The channel's minimum iteration count
+        min_iter: u32,
+        /// The channel's maximum iteration count
+        max_iter: u32,
+    },
+    /// `max_iter` exceeds the render's iteration limit
+    ExceedsMaxIterations {
+        /// The channel's maximum iteration count
+        max_iter: u32,
+        /// `max_iterations` of the render
+        limit: u32,
+    },
+    /// The channel takes no samples
+    NoSamples,
+}
+
+/// A channel configuration that would render nothing or disagrees with the render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelError {
+    /// `red`, `green` or `blue`
+    pub channel: &'static str,
+    /// The problem
+    pub problem: ChannelProblem,
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problem {
+            ChannelProblem::EmptyRange { min_iter, max_iter } => write!(
+                f,
+                "{} channel min_iter {} is not below max_iter {}, so the channel is empty",
+                self.channel, min_iter, max_iter
+            ),
+            ChannelProblem::ExceedsMaxIterations { max_iter, limit } => {
+                write!(f, "{} channel max_iter {} exceeds max_iterations {}", self.channel, max_iter, limit)
+            }
+            ChannelProblem::NoSamples => write!(f, "{} channel has no samples, so it is empty", self.channel),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+impl From<ChannelError> for String {
+    fn from(error: ChannelError) -> String {
+        error.to_string()
+    }
+}
+
+impl BuddhabrotChannel {
+    /// Problems of this channel, in the order `fix` repairs them
+    ///
+    /// # Arguments
+    ///
+    /// * `max_iterations` - Iteration limit of the render
+    pub fn problems(&self, max_iterations: u32) -> Vec<ChannelProblem> {
+        let mut problems = Vec::new();
+        if self.max_iter > max_iterations {
+            problems.push(ChannelProblem::ExceedsMaxIterations { max_iter: self.max_iter, limit: max_iterations });
+        }
+        if self.min_iter >= self.max_iter {
+            problems.push(ChannelProblem::EmptyRange { min_iter: self.min_iter, max_iter: self.max_iter });
+        }
+        if self.samples == 0 {
+            problems.push(ChannelProblem::NoSamples);
+        }
+        problems
+    }
+
+    /// Repair the channel
+    ///
+    /// An inverted range is swapped and then capped at the limit (a range of a single value is
+    /// widened by one iteration, downwards where the limit requires it), and a channel without
+    /// samples takes `samples`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_iterations` - Iteration limit of the render
+    /// * `samples` - Samples to give a channel that has none
+    pub fn fix(&mut self, max_iterations: u32, samples: u64) {
+        if self.min_iter > self.max_iter {
+            std::mem::swap(&mut self.min_iter, &mut self.max_iter);
+        }
+        self.max_iter = self.max_iter.min(max_iterations);
+        self.min_iter = self.min_iter.min(self.max_iter);
+        if self.min_iter == self.max_iter {
+            if self.max_iter < max_iterations {
+                self.max_iter += 1;
+            } else {
+                self.min_iter = self.min_iter.saturating_sub(1);
+            }
+        }
+        if self.samples == 0 {
+            self.samples = samples;
+        }
+    }
+}
+
+impl BuddhabrotChannels {
+    fn named_mut(&mut self) -> [(&'static str, &mut BuddhabrotChannel); 3] {
+        [("red", &mut self.red), ("green", &mut self.green), ("blue", &mut self.blue)]
+    }
+
+    /// Every problem of the three channels
+    ///
+    /// # Arguments
+    ///
+    /// * `max_iterations` - Iteration limit of the render
+    pub fn problems(&self, max_iterations: u32) -> Vec<ChannelError> {
+        [("red", &self.red), ("green", &self.green), ("blue", &self.blue)]
+            .into_iter()
+            .flat_map(|(channel, config)| {
+                config.problems(max_iterations).into_iter().map(move |problem| ChannelError { channel, problem })
+            })
+            .collect()
+    }
+
+    /// Check that every channel can render something within the iteration limit
+    ///
+    /// # Returns
+    ///
+    /// `Ok`, or the first problem found
+    pub fn validate(&self, max_iterations: u32) -> Result<(), ChannelError> {
+        match self.problems(max_iterations).first() {
+            Some(&error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Repair every channel (see `BuddhabrotChannel::fix`)
+    ///
+    /// # Returns
+    ///
+    /// The problems that were repaired
+    pub fn fix(&mut self, max_iterations: u32, samples: u64) -> Vec<ChannelError> {
+        let problems = self.problems(max_iterations);
+        for (_, channel) in self.named_mut() {
+            channel.fix(max_iterations, samples);
+        }
+        problems
+    }
+}
+
+impl BuddhabrotParams {
+    /// Check the channels against `max_iterations` (see `BuddhabrotChannels::validate`)
+    pub fn validated(self) -> Result<Self, ChannelError> {
+        self.channels.validate(self.max_iterations)?;
+        Ok(self)
+    }
+}
+
+impl BuddhabrotJuliaParams {
+    /// Check the channels against `max_iterations` (see `BuddhabrotChannels::validate`)
+    pub fn validated(self) -> Result<Self, ChannelError> {
+        self.channels.validate(self.max_iterations)?;
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_validation_and_fix() {
+        let channel = |min_iter, max_iter, samples| BuddhabrotChannel { min_iter, max_iter, samples };
+        let mut channels = BuddhabrotChannels {
+            red: channel(10, 100, 1000),
+            green: channel(50, 20, 1000),
+            blue: channel(100, 500, 0),
+        };
+        let problems = channels.problems(200);
+        assert_eq!(problems.len(), 3);
+        assert_eq!(problems[0], ChannelError { channel: "green", problem: ChannelProblem::EmptyRange { min_iter: 50, max_iter: 20 } });
+        assert_eq!(channels.validate(200), Err(problems[0]));
+        assert_eq!(problems[2].problem, ChannelProblem::NoSamples);
+
+        assert_eq!(channels.fix(200, 5000).len(), 3);
+        assert_eq!(channels.validate(200), Ok(()));
+        assert_eq!((channels.green.min_iter, channels.green.max_iter), (20, 50));
+        assert_eq!((channels.blue.min_iter, channels.blue.max_iter, channels.blue.samples), (100, 200, 5000));
+
+        // An inverted range above the limit collapses to the limit and widens downwards
+        let mut single = channel(300, 250, 1);
+        single.fix(200, 1);
+        assert_eq!((single.min_iter, single.max_iter), (199, 200));
+    }
+}
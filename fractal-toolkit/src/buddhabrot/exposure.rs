@@ -0,0 +1,180 @@
+//! Buddhabrot exposure blending
+//!
+//! One normalization can't show both ends of a Buddhabrot's range: scaled to the bright core,
+//! the faint halo of long orbits is lost in black, and scaled to the halo, the core saturates.
+//! The bailout moves that balance too. A small bailout ends orbits as soon as they leave the set
+//! and concentrates their points in the core, while a large one follows every orbit further out
+//! and strengthens the halo. Here a channel is accumulated once per bailout, like the bracketed
+//! exposures of an HDR photograph, and the normalized exposures are fused per pixel, each
+//! weighted by how well exposed it is there (how close to mid-gray, as in Mertens exposure
+//! fusion). Every exposure draws its own samples, so the fused image also averages their noise.
+
+use super::EscapeTimeGrid;
+use crate::{
+    accumulate_buddhabrot_samples, combine_normalized_channels, normalize_density_histogram, postfx, telemetry,
+    BuddhabrotChannel, BuddhabrotParams, DensityGrid,
+};
+
+/// Bailouts to accumulate and how to fuse the exposures
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExposureBlend {
+    /// Bailout of each exposure
+    pub bailouts: Vec<f64>,
+    /// Width of the well-exposedness weight around mid-gray; smaller values favour the best
+    /// exposed exposure more strongly
+    pub sigma: f64,
+}
+
+impl Default for ExposureBlend {
+    fn default() -> Self {
+        Self { bailouts: vec![2.0, 8.0, 32.0], sigma: 0.2 }
+    }
+}
+
+impl ExposureBlend {
+    /// Parse comma-separated bailouts, optionally followed by `:sigma`, e.g. `2,8,32:0.25`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (bailouts, sigma) = spec.split_once(':').unwrap_or((spec, ""));
+        let bailouts = bailouts
+            .split(',')
+            .map(|value| match value.trim().parse::<f64>() {
+                Ok(bailout) if bailout.is_finite() && bailout > 0.0 => Ok(bailout),
+                _ => Err(format!("Invalid exposure bailout '{}' (expected a positive number)", value.trim())),
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        let sigma = match sigma.trim() {
+            "" => ExposureBlend::default().sigma,
+            value => match value.parse::<f64>() {
+                Ok(sigma) if sigma.is_finite() && sigma > 0.0 => sigma,
+                _ => return Err(format!("Invalid exposure sigma '{}' (expected a positive number)", value)),
+            },
+        };
+        Ok(Self { bailouts, sigma })
+    }
+}
+
+/// Accumulate one channel at every bailout
+///
+/// Exposure `k` takes the channel's samples `k * samples..(k + 1) * samples`, so no two
+/// exposures share a sample.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters; `bailout` is replaced by each exposure's
+/// * `channel_params` - Channel-specific parameters (min/max iterations, sample count)
+/// * `bounded` - Draw the orbits of bounded instead of escaping points
+/// * `bailouts` - Bailout of each exposure
+///
+/// # Returns
+///
+/// One histogram per bailout, in order
+pub fn buddhabrot_channel_exposures(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    bounded: bool,
+    bailouts: &[f64],
+) -> Vec<DensityGrid> {
+    bailouts
+        .iter()
+        .enumerate()
+        .map(|(exposure, &bailout)| {
+            let exposure_params = BuddhabrotParams { bailout, ..params.clone() };
+            // Escape times, and with them the prefilter, depend on the bailout
+            let grid = (params.prefilter_cells > 0 && !bounded).then(|| {
+                EscapeTimeGrid::compute(&exposure_params, params.prefilter_cells, channel_params.max_iter)
+            });
+            let label = format!("Generating Buddhabrot exposure at bailout {}", bailout);
+            let telemetry = telemetry::RenderTelemetry::start(&label, channel_params.samples);
+            println!("{}: {} samples", label, channel_params.samples);
+            let mut histogram = DensityGrid::new(params.width as usize, params.height as usize);
+            let first = exposure as u64 * channel_params.samples;
+            accumulate_buddhabrot_samples(
+                &exposure_params,
+                channel_params,
+                bounded,
+                grid.as_ref(),
+                first..first + channel_params.samples,
+                &mut histogram,
+                &telemetry,
+            );
+            histogram
+        })
+        .collect()
+}
+
+/// Fuse histograms of the same channel into one normalized channel
+///
+/// Each exposure is normalized like a Buddhabrot channel, then every pixel is the average of
+/// the exposures' values weighted by `exp(-(v - 0.5)² / (2 sigma²))`.
+///
+/// # Returns
+///
+/// Values from 0 to 1; an empty list gives an empty grid
+pub fn blend_exposures(exposures: &[DensityGrid], sigma: f64) -> DensityGrid {
+    let normalized: Vec<DensityGrid> = exposures.iter().map(normalize_density_histogram).collect();
+    let Some(first) = normalized.first() else {
+        return DensityGrid::new(0, 0);
+    };
+    let scale = -1.0 / (2.0 * sigma * sigma);
+    let mut blended = DensityGrid::new(first.width(), first.height());
+    for (cell, value) in blended.as_mut_slice().iter_mut().enumerate() {
+        let (mut sum, mut total) = (0.0, 0.0);
+        for exposure in &normalized {
+            let v = exposure.as_slice()[cell];
+            let weight = ((v - 0.5) * (v - 0.5) * scale).exp();
+            sum += weight * v;
+            total += weight;
+        }
+        *value = sum / total;
+    }
+    blended
+}
+
+/// Generate a Buddhabrot whose channels each fuse exposures at several bailouts
+///
+/// Denoising applies to every exposure before fusion; the orbit mode, prefilter, channel mix
+/// and post effects apply as in `generate_buddhabrot`.
+pub fn generate_buddhabrot_exposures(params: &BuddhabrotParams, blend: &ExposureBlend) -> image::RgbImage {
+    let bounded = params.orbits.bounded_channels();
+    let channels = [&params.channels.red, &params.channels.green, &params.channels.blue];
+    let [red, green, blue] = [0, 1, 2].map(|channel| {
+        let mut exposures = buddhabrot_channel_exposures(params, channels[channel], bounded[channel], &blend.bailouts);
+        if let Some(options) = params.denoise.as_ref().filter(|options| options.strength > 0.0) {
+            println!("Denoising exposures (strength {}, radius {})", options.strength, options.radius);
+            exposures = exposures.iter().map(|exposure| options.apply(exposure)).collect();
+        }
+        blend_exposures(&exposures, blend.sigma)
+    });
+
+    let mut img = combine_normalized_channels(params.width, params.height, [&red, &green, &blue], params.channel_mix.as_ref(), params.display_transform);
+    postfx::apply_post_effects_rgb(&mut img, &params.post_effects);
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuddhabrotChannels;
+
+    #[test]
+    fn test_exposures_blend_per_pixel() {
+        assert_eq!(ExposureBlend::parse("2, 16:0.3"), Ok(ExposureBlend { bailouts: vec![2.0, 16.0], sigma: 0.3 }));
+        assert!(ExposureBlend::parse("2,-1").is_err());
+
+        // The second pixel is black in the first exposure and mid-gray (0.497) in the second,
+        // which dominates its blend
+        let dark = DensityGrid::from_vec(2, 1, vec![1.0, 0.0]).unwrap();
+        let bright = DensityGrid::from_vec(2, 1, vec![1000.0, 30.0]).unwrap();
+        let blended = blend_exposures(&[dark, bright], 0.2);
+        assert_eq!(blended[(0, 0)], 1.0);
+        assert!(blended[(1, 0)] > 0.45 && blended[(1, 0)] < 0.5, "{:?}", blended);
+
+        let channel = BuddhabrotChannel { min_iter: 5, max_iter: 60, samples: 20_000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() };
+        let params = BuddhabrotParams::new([-2.0, 2.0, -2.0, 2.0], 16, 16, 5, 60, 20_000, 2.0, "z^2 + c".to_string(), channels);
+        let exposures = buddhabrot_channel_exposures(&params, &channel, false, &[2.0, 32.0]);
+        assert_eq!(exposures.len(), 2);
+        assert_ne!(exposures[0], exposures[1]);
+        assert_eq!(generate_buddhabrot_exposures(&params, &ExposureBlend::default()).dimensions(), (16, 16));
+    }
+}
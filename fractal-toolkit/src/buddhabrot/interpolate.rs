@@ -0,0 +1,115 @@
+//! Interpolation of orbits between iterates
+//!
+//! Each iterate of an orbit lands in one histogram cell, and consecutive iterates can lie many
+//! pixels apart, so at low sample counts the filaments show up as scattered dots. With
+//! `OrbitInterpolation`, segments between consecutive iterates longer than `max_step` pixels get
+//! points inserted along them, and the iterate's unit weight is shared equally between itself and
+//! the points after it on its segment. Every orbit point still adds a total weight of 1, so the
+//! histogram keeps the scale of uninterpolated renders and only the spread of the weight changes.
+
+use super::orbit_pixel;
+use crate::BuddhabrotParams;
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+/// How orbit segments are filled in before binning
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OrbitInterpolation {
+    /// Longest gap, in pixels, left between the points plotted along a segment
+    pub max_step: f64,
+    /// Most points inserted into one segment, which bounds the cost of segments that jump
+    /// across the whole view
+    pub max_inserted: u32,
+}
+
+impl Default for OrbitInterpolation {
+    fn default() -> Self {
+        Self { max_step: 1.0, max_inserted: 64 }
+    }
+}
+
+impl OrbitInterpolation {
+    /// Interpolation with the given step in pixels and the default cap on inserted points
+    pub fn with_step(max_step: f64) -> Result<Self, String> {
+        if !(max_step > 0.0 && max_step.is_finite()) {
+            return Err(format!("Interpolation step must be a positive number of pixels, found {}", max_step));
+        }
+        Ok(Self { max_step, ..Self::default() })
+    }
+
+    /// Plot an orbit with its segments filled in
+    ///
+    /// # Arguments
+    ///
+    /// * `orbit` - Consecutive iterates, each of weight 1
+    /// * `params` - Buddhabrot parameters, for the view and the image size
+    /// * `plot` - Called with the histogram cell and weight of every point inside the view
+    pub fn plot_orbit<F>(&self, orbit: &[Complex<f64>], params: &BuddhabrotParams, mut plot: F)
+    where
+        F: FnMut(usize, usize, f64),
+    {
+        for (index, &start) in orbit.iter().enumerate() {
+            self.plot_segment(start, orbit.get(index + 1).copied(), params, &mut plot);
+        }
+    }
+
+    /// Plot one iterate and the points inserted between it and the next
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The iterate, of weight 1
+    /// * `end` - The next iterate, `None` for the last point of an orbit
+    /// * `params` - Buddhabrot parameters, for the view and the image size
+    /// * `plot` - Called with the histogram cell and weight of every point inside the view
+    pub fn plot_segment<F>(&self, start: Complex<f64>, end: Option<Complex<f64>>, params: &BuddhabrotParams, plot: &mut F)
+    where
+        F: FnMut(usize, usize, f64),
+    {
+        let [x_min, x_max, y_min, y_max] = params.bounds;
+        let scale_x = params.width as f64 / (x_max - x_min);
+        let scale_y = params.height as f64 / (y_max - y_min);
+        let inserted = match end {
+            Some(end) => {
+                let length = ((end.re - start.re) * scale_x).hypot((end.im - start.im) * scale_y);
+                // NaN lengths, from orbits that overflowed, fail the comparison and insert nothing
+                if length > self.max_step {
+                    ((length / self.max_step).ceil() as u32 - 1).min(self.max_inserted)
+                } else {
+                    0
+                }
+            }
+            None => 0,
+        };
+        let weight = 1.0 / (inserted + 1) as f64;
+        let step = end.map_or(Complex::new(0.0, 0.0), |end| (end - start) * weight);
+        for k in 0..=inserted {
+            if let Some((x, y)) = orbit_pixel(start + step * k as f64, params) {
+                plot(x, y, weight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BuddhabrotChannel, BuddhabrotChannels, DensityGrid};
+
+    #[test]
+    fn test_interpolation_spreads_weight_without_changing_total() {
+        let channel = BuddhabrotChannel { min_iter: 0, max_iter: 10, samples: 1 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel };
+        let params = BuddhabrotParams::new([0.0, 1.0, 0.0, 1.0], 100, 100, 0, 10, 1, 2.0, "z^2 + c".to_string(), channels);
+        // Jumps of 50 and 10 pixels, then a last point
+        let orbit = [Complex::new(0.2, 0.2), Complex::new(0.7, 0.2), Complex::new(0.7, 0.3)];
+
+        let mut grid = DensityGrid::new(100, 100);
+        OrbitInterpolation::with_step(2.0).unwrap().plot_orbit(&orbit, &params, |x, y, weight| *grid.get_mut(x, y).unwrap() += weight);
+        let total: f64 = grid.as_slice().iter().sum();
+        assert!((total - 3.0).abs() < 1e-9, "{}", total);
+        assert_eq!(grid.as_slice().iter().filter(|&&count| count > 0.0).count(), 25 + 5 + 1);
+        let smallest = grid.as_slice().iter().copied().filter(|&count| count > 0.0).fold(f64::INFINITY, f64::min);
+        assert!((smallest - 1.0 / 25.0).abs() < 1e-12);
+        assert!(OrbitInterpolation::with_step(0.0).is_err());
+    }
+}
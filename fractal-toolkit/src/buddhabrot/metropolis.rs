@@ -0,0 +1,345 @@
+//! Metropolis-Hastings sampling for Buddhabrots with sparse contributions
+//!
+//! With a high `min_iter` only c values very close to the boundary of the set escape late
+//! enough to be drawn, and uniform sampling throws almost every sample away. This sampler runs
+//! Markov chains whose stationary density is roughly proportional to each c's contribution, the
+//! number of points of its orbit that land in the view (zero for orbits outside the channel's
+//! range). Once a chain has found a contributing c, it keeps proposing small mutations of it,
+//! which mostly contribute too:
+//!
+//! - a **small mutation** moves c by a random angle and a distance between `min_mutation` and
+//!   `max_mutation` view sizes, log-uniformly, so both fine and coarse structure is explored;
+//! - a **large mutation** draws c uniformly over `sample_bounds`, letting chains jump between
+//!   separate regions.
+//!
+//! Chains that only ever accepted contributing c values would stay in the region they started
+//! in, and separate regions would be drawn in proportion to how many chains happened to start
+//! there. The target density is therefore the contribution plus a small floor, so that a share
+//! `exploration` of the chains' time is spent wandering through c values that contribute
+//! nothing, from where large mutations carry them anywhere.
+//!
+//! Both proposals are symmetric, so a proposal is accepted with probability
+//! min(1, target(c') / target(c)). Each step draws the chain's current orbit with weight
+//! 1 / target(c), which makes the histogram an estimate of the orbit density of uniform sampling
+//! over `sample_bounds`; a uniform pilot run supplies the floor and the scale, so the result is
+//! comparable to `channel_params.samples` uniform samples. If the pilot finds nothing to start
+//! from, chains start at the preimages of the view found as for region-of-interest sampling,
+//! have no floor, and the histogram is only correct up to its overall scale.
+
+use super::roi::{find_seeds, RoiSamplingOptions};
+use super::{orbit_pixel, trace_escaping_orbit};
+use crate::rng::CounterRng;
+use crate::{combine_buddhabrot_channels, AtomicGrid, BuddhabrotChannel, BuddhabrotParams, DensityGrid};
+use chrono::Local;
+use num_complex::Complex;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Seed of the pilot run, used like `BUDDHABROT_SEED` (which seeds the chains)
+const METROPOLIS_SEED: u64 = 0x5eed_0004;
+
+/// Options for Metropolis-Hastings Buddhabrot sampling
+#[derive(Debug, Clone)]
+pub struct MetropolisOptions {
+    /// Area of the c plane the estimate corresponds to [x_min, x_max, y_min, y_max]
+    pub sample_bounds: [f64; 4],
+    /// Number of uniform samples used to find starting points and the scale of the estimate
+    pub pilot_samples: u64,
+    /// Number of independent chains, run in parallel
+    pub chains: usize,
+    /// Steps each chain takes before it starts drawing
+    pub burn_in: u32,
+    /// Probability that a proposal is a large mutation
+    pub large_mutation: f64,
+    /// Share of the chains' time spent among c values that contribute nothing
+    pub exploration: f64,
+    /// Smallest distance of a small mutation, as a fraction of the view size
+    pub min_mutation: f64,
+    /// Largest distance of a small mutation, as a fraction of the view size
+    pub max_mutation: f64,
+}
+
+impl Default for MetropolisOptions {
+    fn default() -> Self {
+        Self {
+            sample_bounds: [-2.0, 2.0, -2.0, 2.0],
+            pilot_samples: 100_000,
+            chains: 1024,
+            burn_in: 100,
+            large_mutation: 0.05,
+            exploration: 0.1,
+            min_mutation: 1e-3,
+            max_mutation: 1.0,
+        }
+    }
+}
+
+/// Trace c and collect the pixels its orbit visits if the orbit is in the channel's range
+///
+/// # Returns
+///
+/// The number of visits, the contribution of c
+fn contribution(
+    c: Complex<f64>,
+    params: &BuddhabrotParams,
+    channel: &BuddhabrotChannel,
+    orbit: &mut Vec<Complex<f64>>,
+    pixels: &mut Vec<(usize, usize)>,
+) -> usize {
+    pixels.clear();
+    if let Some(iter) = trace_escaping_orbit(c, params, channel.max_iter, orbit) {
+        if iter >= channel.min_iter {
+            pixels.extend(orbit.iter().filter_map(|&z| orbit_pixel(z, params)));
+        }
+    }
+    pixels.len()
+}
+
+fn in_bounds(c: Complex<f64>, bounds: [f64; 4]) -> bool {
+    c.re >= bounds[0] && c.re <= bounds[1] && c.im >= bounds[2] && c.im <= bounds[3]
+}
+
+fn uniform_in<R: Rng>(rng: &mut R, bounds: [f64; 4]) -> Complex<f64> {
+    Complex::new(
+        bounds[0] + (bounds[1] - bounds[0]) * rng.gen::<f64>(),
+        bounds[2] + (bounds[3] - bounds[2]) * rng.gen::<f64>(),
+    )
+}
+
+/// Uniform pilot run: contributing c values with their contributions, and the mean contribution
+fn pilot(
+    params: &BuddhabrotParams,
+    channel: &BuddhabrotChannel,
+    options: &MetropolisOptions,
+) -> (Vec<(Complex<f64>, usize)>, f64) {
+    let batch = 1000u64;
+    let batches = options.pilot_samples.div_ceil(batch);
+    let hits: Vec<(Complex<f64>, usize)> = (0..batches)
+        .into_par_iter()
+        .flat_map_iter(|b| {
+            if params.cancel.is_cancelled() {
+                return Vec::new();
+            }
+            let mut rng = CounterRng::new(METROPOLIS_SEED, b);
+            let (mut orbit, mut pixels) = (Vec::new(), Vec::new());
+            let count = batch.min(options.pilot_samples - b * batch);
+            (0..count)
+                .filter_map(|_| {
+                    let c = uniform_in(&mut rng, options.sample_bounds);
+                    let visits = contribution(c, params, channel, &mut orbit, &mut pixels);
+                    (visits > 0).then_some((c, visits))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let total: usize = hits.iter().map(|&(_, visits)| visits).sum();
+    (hits, total as f64 / options.pilot_samples.max(1) as f64)
+}
+
+/// Calculate one Buddhabrot channel with Metropolis-Hastings sampling
+///
+/// Like `buddhabrot_channel_roi`, the histogram estimates the density that
+/// `channel_params.samples` uniform samples over `options.sample_bounds` would produce, so the
+/// sampled c values are not restricted to the view.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters; `bounds` is the rendered view
+/// * `channel_params` - Channel-specific parameters (min/max iterations, number of chain steps)
+/// * `options` - Chain and mutation options
+///
+/// # Returns
+///
+/// A histogram of weighted orbit visits
+pub fn buddhabrot_channel_metropolis(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    options: &MetropolisOptions,
+) -> DensityGrid {
+    use std::time::Instant;
+
+    let start_time = Instant::now();
+    println!("Searching for Buddhabrot chain starting points - Started at {:?}", Local::now().format("%H:%M:%S"));
+    let (hits, mean_contribution) = pilot(params, channel_params, options);
+
+    // Chains start at pilot hits chosen in proportion to their contribution, the density they
+    // mostly sample, or at preimages of the view when the pilot found none. The floor of the
+    // target density is chosen so that the contributing c values, whose mean contribution over
+    // the sampling area the pilot estimates, hold 1 - exploration of the total.
+    let exploration = options.exploration.clamp(0.0, 0.99);
+    let floor_ratio = exploration / (1.0 - exploration);
+    let (starts, floor, scale): (Vec<Complex<f64>>, f64, f64) = if hits.is_empty() {
+        let roi_options = RoiSamplingOptions { sample_bounds: options.sample_bounds, pilot_samples: 0, ..Default::default() };
+        (find_seeds(params, channel_params, &roi_options), 0.0, 1.0)
+    } else {
+        let cumulative: Vec<usize> = hits
+            .iter()
+            .scan(0, |sum, &(_, visits)| {
+                *sum += visits;
+                Some(*sum)
+            })
+            .collect();
+        let total = *cumulative.last().unwrap_or(&0);
+        let mut rng = CounterRng::new(METROPOLIS_SEED, u64::MAX);
+        let starts = (0..options.chains)
+            .map(|_| {
+                let pick = rng.gen_range(0..total);
+                hits[cumulative.partition_point(|&sum| sum <= pick)].0
+            })
+            .collect();
+        (starts, floor_ratio * mean_contribution, (1.0 + floor_ratio) * mean_contribution)
+    };
+    if starts.is_empty() {
+        println!("No orbits through the view were found; the channel is empty");
+        return DensityGrid::new(params.width as usize, params.height as usize);
+    }
+    println!(
+        "Found {} chain starting points in {:.1}s",
+        if hits.is_empty() { starts.len() } else { hits.len() },
+        start_time.elapsed().as_secs_f64()
+    );
+
+    let total_samples = channel_params.samples;
+    let chains = (options.chains.max(1) as u64).min(total_samples.max(1));
+    println!(
+        "Generating Buddhabrot channel (Metropolis, {} chains): 0% (0/{}) - Using {} threads.",
+        chains,
+        total_samples,
+        rayon::current_num_threads()
+    );
+
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let view_size = (x_max - x_min).max(y_max - y_min);
+    let (min_step, max_step) = (options.min_mutation * view_size, options.max_mutation * view_size);
+    let large_mutation = options.large_mutation.clamp(0.0, 1.0);
+
+    let shared_histogram = AtomicGrid::new(params.width as usize, params.height as usize);
+    let accepted: u64 = (0..chains)
+        .into_par_iter()
+        .map(|chain| {
+            let steps = total_samples / chains + u64::from(chain < total_samples % chains);
+            let mut rng = CounterRng::new(crate::BUDDHABROT_SEED, chain);
+            let mut orbit = Vec::new();
+            let (mut pixels, mut proposed_pixels) = (Vec::new(), Vec::new());
+
+            let mut c = starts[chain as usize % starts.len()];
+            let mut target = contribution(c, params, channel_params, &mut orbit, &mut pixels) as f64 + floor;
+            let mut accepted = 0u64;
+            for step in 0..options.burn_in as u64 + steps {
+                if params.cancel.is_cancelled() {
+                    break;
+                }
+                let proposal = if rng.gen::<f64>() < large_mutation {
+                    uniform_in(&mut rng, options.sample_bounds)
+                } else {
+                    let distance = max_step * (-(max_step / min_step).ln() * rng.gen::<f64>()).exp();
+                    c + Complex::from_polar(distance, std::f64::consts::TAU * rng.gen::<f64>())
+                };
+                // The target density is zero outside the sampling area
+                let proposed_target = if in_bounds(proposal, options.sample_bounds) {
+                    contribution(proposal, params, channel_params, &mut orbit, &mut proposed_pixels) as f64 + floor
+                } else {
+                    0.0
+                };
+                // A start of zero density (a seed whose orbit just misses) accepts anything better
+                if proposed_target > 0.0 && rng.gen::<f64>() * target <= proposed_target {
+                    c = proposal;
+                    target = proposed_target;
+                    std::mem::swap(&mut pixels, &mut proposed_pixels);
+                    accepted += 1;
+                }
+
+                if step >= options.burn_in as u64 && !pixels.is_empty() {
+                    let weight = scale / target;
+                    for &(x, y) in &pixels {
+                        shared_histogram.add(x, y, weight);
+                    }
+                }
+            }
+            accepted
+        })
+        .sum();
+    let final_histogram = shared_histogram.into_grid();
+
+    println!(
+        "Generating Buddhabrot channel (Metropolis, {} chains): 100% ({}/{}), Completed in {:.1}s",
+        chains,
+        total_samples,
+        total_samples,
+        start_time.elapsed().as_secs_f64()
+    );
+    println!(
+        "  Accepted {:.1}% of proposals",
+        100.0 * accepted as f64 / (total_samples + chains * options.burn_in as u64).max(1) as f64
+    );
+
+    final_histogram
+}
+
+/// Generate a complete Buddhabrot image using Metropolis-Hastings sampling for every channel
+///
+/// # Arguments
+///
+/// * `params` - Complete Buddhabrot parameters including all channel configurations
+/// * `options` - Chain and mutation options
+///
+/// # Returns
+///
+/// An RGB image normalized the same way as `generate_buddhabrot`
+pub fn generate_buddhabrot_metropolis(params: &BuddhabrotParams, options: &MetropolisOptions) -> image::RgbImage {
+    let mut red_hist = buddhabrot_channel_metropolis(params, &params.channels.red, options);
+    let mut green_hist = buddhabrot_channel_metropolis(params, &params.channels.green, options);
+    let mut blue_hist = buddhabrot_channel_metropolis(params, &params.channels.blue, options);
+    super::denoise::denoise_channels(params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+
+    let mut img = combine_buddhabrot_channels(params.width, params.height, &red_hist, &green_hist, &blue_hist, params.channel_mix.as_ref(), params.display_transform);
+    crate::postfx::apply_post_effects_rgb(&mut img, &params.post_effects);
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buddhabrot::roi::buddhabrot_channel_roi;
+    use crate::BuddhabrotChannels;
+
+    #[test]
+    fn test_metropolis_matches_uniform_estimate() {
+        // A high min_iter leaves only a thin shell of c values near the boundary contributing
+        let channel = BuddhabrotChannel { min_iter: 100, max_iter: 1000, samples: 100_000 };
+        let params = BuddhabrotParams::new(
+            [-0.5, -0.3, 0.5, 0.7],
+            4,
+            4,
+            100,
+            1000,
+            100_000,
+            4.0,
+            "z^2 + c".to_string(),
+            BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() },
+        );
+        let options = MetropolisOptions { pilot_samples: 500_000, ..Default::default() };
+        let metropolis = buddhabrot_channel_metropolis(&params, &channel, &options);
+
+        let uniform_channel = BuddhabrotChannel { samples: 2_000_000, ..channel };
+        let uniform_options = RoiSamplingOptions { pilot_samples: 0, preimage_attempts: 0, ..Default::default() };
+        let uniform = buddhabrot_channel_roi(&params, &uniform_channel, &uniform_options);
+
+        // Both estimate the same density; scale the uniform run to the same sample count
+        let metropolis_total: f64 = metropolis.as_slice().iter().sum();
+        let uniform_total: f64 = uniform.as_slice().iter().sum::<f64>() / 20.0;
+        assert!(
+            (metropolis_total / uniform_total - 1.0).abs() < 0.25,
+            "metropolis {} vs uniform {}",
+            metropolis_total,
+            uniform_total
+        );
+        let distance: f64 = metropolis
+            .as_slice()
+            .iter()
+            .zip(uniform.as_slice().iter())
+            .map(|(m, u)| (m / metropolis_total - u / (20.0 * uniform_total)).abs())
+            .sum();
+        assert!(distance < 0.2, "histograms differ by {}", distance);
+    }
+}
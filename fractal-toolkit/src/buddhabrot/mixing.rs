@@ -0,0 +1,204 @@
+//! Channel mixing and white balance for Nebulabrots
+//!
+//! A Nebulabrot renders three iteration bands and shows them directly as red, green and blue.
+//! Like the narrowband filters of an astrophotograph, the bands need not map one-to-one onto
+//! display colors: `ChannelMix` sends them through a 3x3 matrix, `output = matrix * bands`, and
+//! then scales the outputs with white-balance gains.
+//!
+//! The matrix is energy-conserving when every column sums to 1: each band's brightness is shared
+//! out among the outputs rather than amplified, so remixing changes hue but not overall exposure.
+//! `ChannelMix::new` normalizes the columns. The white-balance gains are normalized to a mean of
+//! 1 for the same reason.
+
+use serde::{Deserialize, Serialize};
+
+/// Color temperature in kelvin that `WhiteBalance::Kelvin` gains are relative to
+const REFERENCE_TEMPERATURE: f64 = 6500.0;
+
+/// How the mixed outputs are balanced
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WhiteBalance {
+    /// No correction; the gains are all 1
+    None,
+    /// Equal band intensities mix to gray: each output is divided by its row sum
+    Neutral,
+    /// Correct for light of this color temperature, like a camera's white balance: a lower
+    /// temperature than 6500 K cools the image, a higher one warms it
+    Kelvin(f64),
+}
+
+impl WhiteBalance {
+    /// Parse `none`, `neutral`, `daylight` (6500 K), `tungsten` (3200 K), `fluorescent`
+    /// (4000 K), `shade` (7500 K) or a temperature like `5000K`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim().to_lowercase();
+        match name.as_str() {
+            "none" => Ok(WhiteBalance::None),
+            "neutral" => Ok(WhiteBalance::Neutral),
+            "daylight" => Ok(WhiteBalance::Kelvin(6500.0)),
+            "tungsten" => Ok(WhiteBalance::Kelvin(3200.0)),
+            "fluorescent" => Ok(WhiteBalance::Kelvin(4000.0)),
+            "shade" => Ok(WhiteBalance::Kelvin(7500.0)),
+            other => match other.strip_suffix('k').map(|kelvin| kelvin.trim().parse::<f64>()) {
+                Some(Ok(kelvin)) if (1000.0..=40000.0).contains(&kelvin) => Ok(WhiteBalance::Kelvin(kelvin)),
+                _ => Err(format!(
+                    "Unknown white balance '{}' (expected none, neutral, daylight, tungsten, fluorescent, shade or a temperature from 1000K to 40000K)",
+                    other
+                )),
+            },
+        }
+    }
+}
+
+/// Approximate sRGB color of a black body, each component in [0, 1]
+///
+/// Uses the fit of Tanner Helland's blackbody table, which is accurate to a few percent from
+/// 1000 K to 40000 K.
+fn blackbody_rgb(kelvin: f64) -> [f64; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+    let green = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    [red, green, blue].map(|component| (component / 255.0).clamp(0.0, 1.0))
+}
+
+/// A 3x3 mix of the red, green and blue bands followed by white-balance gains
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelMix {
+    /// `matrix[output][band]`: how much of each band goes into each output channel
+    pub matrix: [[f64; 3]; 3],
+    /// Gains applied to the red, green and blue outputs
+    pub gains: [f64; 3],
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        Self { matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], gains: [1.0; 3] }
+    }
+}
+
+impl ChannelMix {
+    /// An energy-conserving mix: every column of `matrix` is scaled to sum to 1
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix` - `matrix[output][band]`; negative weights are allowed, a column that sums to
+    ///   0 is left as it is
+    /// * `white_balance` - How to balance the outputs
+    pub fn new(matrix: [[f64; 3]; 3], white_balance: WhiteBalance) -> Self {
+        let mut matrix = matrix;
+        for band in 0..3 {
+            let total: f64 = matrix.iter().map(|row| row[band]).sum();
+            if total.abs() > f64::EPSILON {
+                for row in matrix.iter_mut() {
+                    row[band] /= total;
+                }
+            }
+        }
+        let mut mix = Self { matrix, gains: [1.0; 3] };
+        mix.gains = mix.white_balance_gains(white_balance);
+        mix
+    }
+
+    /// Parse a mix preset or nine comma-separated weights, row by row (red output first)
+    ///
+    /// Presets: `identity`; `hubble`, the gold and teal of the Hubble palette, with the low
+    /// band in red, the middle band split between red and green and the high band in blue and
+    /// green; `natural`, warm low iterations fading to blue high iterations; `reverse`, which
+    /// swaps the red and blue bands; and `gray`.
+    pub fn parse(spec: &str, white_balance: WhiteBalance) -> Result<Self, String> {
+        let matrix = match spec.trim().to_lowercase().as_str() {
+            "identity" => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            "hubble" => [[0.8, 0.5, 0.0], [0.2, 0.5, 0.3], [0.0, 0.0, 0.7]],
+            "natural" => [[0.7, 0.2, 0.0], [0.3, 0.6, 0.2], [0.0, 0.2, 0.8]],
+            "reverse" => [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+            "gray" | "grey" => [[1.0; 3]; 3],
+            other => {
+                let weights = other
+                    .split(',')
+                    .map(|weight| weight.trim().parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|e| format!("Invalid channel mix '{}': {}", spec, e))?;
+                if weights.len() != 9 {
+                    return Err(format!(
+                        "Channel mix '{}' must be identity, hubble, natural, reverse, gray or 9 comma-separated weights",
+                        spec
+                    ));
+                }
+                [
+                    [weights[0], weights[1], weights[2]],
+                    [weights[3], weights[4], weights[5]],
+                    [weights[6], weights[7], weights[8]],
+                ]
+            }
+        };
+        Ok(Self::new(matrix, white_balance))
+    }
+
+    /// Gains for `white_balance`, normalized to a mean of 1
+    fn white_balance_gains(&self, white_balance: WhiteBalance) -> [f64; 3] {
+        let gains = match white_balance {
+            WhiteBalance::None => [1.0; 3],
+            WhiteBalance::Neutral => self.matrix.map(|row| {
+                let total: f64 = row.iter().sum();
+                if total > f64::EPSILON { 1.0 / total } else { 0.0 }
+            }),
+            WhiteBalance::Kelvin(kelvin) => {
+                let (reference, light) = (blackbody_rgb(REFERENCE_TEMPERATURE), blackbody_rgb(kelvin));
+                [0, 1, 2].map(|i| reference[i] / light[i].max(1e-3))
+            }
+        };
+        let mean = gains.iter().sum::<f64>() / 3.0;
+        if mean > f64::EPSILON { gains.map(|gain| gain / mean) } else { [1.0; 3] }
+    }
+
+    /// Mix normalized band values into display values, clamped to [0, 1]
+    ///
+    /// # Arguments
+    ///
+    /// * `bands` - Red, green and blue band values in [0, 1]
+    pub fn apply(&self, bands: [f64; 3]) -> [f64; 3] {
+        [0, 1, 2].map(|output| {
+            let mixed: f64 = (0..3).map(|band| self.matrix[output][band] * bands[band]).sum();
+            (mixed * self.gains[output]).clamp(0.0, 1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_conserves_energy_and_balances() {
+        let mix = ChannelMix::parse("hubble", WhiteBalance::None).unwrap();
+        for band in 0..3 {
+            let total: f64 = mix.matrix.iter().map(|row| row[band]).sum();
+            assert!((total - 1.0).abs() < 1e-12);
+        }
+        let out = mix.apply([0.2, 0.3, 0.1]);
+        assert!((out.iter().sum::<f64>() - 0.6).abs() < 1e-12);
+
+        // Neutral balance turns equal bands gray
+        let neutral = ChannelMix::parse("hubble", WhiteBalance::Neutral).unwrap();
+        let [r, g, b] = neutral.apply([0.5; 3]);
+        assert!((r - g).abs() < 1e-12 && (g - b).abs() < 1e-12, "{} {} {}", r, g, b);
+
+        // Correcting for tungsten light cools the image
+        let tungsten = ChannelMix::new(ChannelMix::default().matrix, WhiteBalance::parse("tungsten").unwrap());
+        assert!(tungsten.gains[2] > tungsten.gains[0]);
+        assert_eq!(WhiteBalance::parse("6500K").unwrap(), WhiteBalance::Kelvin(6500.0));
+        assert!(ChannelMix::parse("1,2,3", WhiteBalance::None).is_err());
+    }
+}
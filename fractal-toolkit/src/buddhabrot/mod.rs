@@ -0,0 +1,154 @@
+//! Buddhabrot sampling strategies
+//!
+//! `buddhabrot_channel` samples c uniformly over `BuddhabrotParams::sample_bounds`, which
+//! `region` can fit to a zoomed view. That is fine for full views but wastes almost every sample
+//! once the view is zoomed in far. The samplers here choose c more carefully while
+//! keeping the histogram an estimate of the same orbit density: `roi` concentrates samples on
+//! the preimages of a zoomed view, and `metropolis` runs Markov chains that mutate the c values
+//! whose orbits are drawn, for renders with a high `min_iter`. `denoise` smooths the noise of
+//! low-sample histograms before the channels are combined, `exposure` fuses renders of a channel
+//! at several bailouts to show the core and the halo at once, `mixing` maps the channels to output
+//! colors through a color matrix with white balance, and `channels` checks that channel
+//! configurations can render anything at all. `accumulator` takes uniform samples in batches for
+//! progressive previews and renders split across machines. `interpolate` fills in the segments
+//! between distant iterates for smoother filaments at low sample counts. `stream` records orbit
+//! points to a file that can be binned again at other sizes, crops and rotations. `OrbitMode` chooses between the orbits of escaping
+//! points, the Buddhabrot, and those of bounded points, the anti-Buddhabrot.
+
+pub mod accumulator;
+pub mod channels;
+pub mod denoise;
+pub mod exposure;
+pub mod interpolate;
+pub mod metropolis;
+pub mod mixing;
+pub mod prefilter;
+pub mod region;
+pub mod roi;
+pub mod scratch;
+pub mod stream;
+
+pub use accumulator::BuddhabrotAccumulator;
+pub use channels::{ChannelError, ChannelProblem};
+pub use denoise::DenoiseOptions;
+pub use exposure::{blend_exposures, buddhabrot_channel_exposures, generate_buddhabrot_exposures, ExposureBlend};
+pub use interpolate::OrbitInterpolation;
+pub use metropolis::{buddhabrot_channel_metropolis, generate_buddhabrot_metropolis, MetropolisOptions};
+pub use mixing::{ChannelMix, WhiteBalance};
+pub use prefilter::EscapeTimeGrid;
+pub use region::auto_sample_bounds;
+pub use roi::{buddhabrot_channel_roi, generate_buddhabrot_roi, RoiSamplingOptions};
+pub use stream::{record_orbit_stream, OrbitStream, OrbitStreamHeader, RebinOptions};
+
+use crate::algebra::NumberSystem;
+use crate::formula::compiled::compiled_for;
+use crate::data_structures::cell_index;
+use crate::BuddhabrotParams;
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+/// Which orbits a Buddhabrot accumulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrbitMode {
+    /// Orbits of points that escape after `min_iter` to `max_iter` iterations: the Buddhabrot
+    #[default]
+    Escaping,
+    /// Orbits of points that don't escape within `max_iter` iterations, from iteration `min_iter`
+    /// on: the anti-Buddhabrot, which traces the attracting cycles inside the set
+    Bounded,
+    /// Escaping orbits in the red and green channels and bounded orbits in the blue channel
+    Combined,
+}
+
+impl OrbitMode {
+    /// Parse `escaping`, `bounded` (or `anti`) or `combined`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "escaping" | "buddhabrot" => Ok(OrbitMode::Escaping),
+            "bounded" | "anti" | "anti-buddhabrot" => Ok(OrbitMode::Bounded),
+            "combined" => Ok(OrbitMode::Combined),
+            other => Err(format!("Unknown orbit mode '{}' (expected escaping, bounded or combined)", other)),
+        }
+    }
+
+    /// Whether the red, green and blue channels accumulate bounded orbits
+    pub fn bounded_channels(self) -> [bool; 3] {
+        match self {
+            OrbitMode::Escaping => [false; 3],
+            OrbitMode::Bounded => [true; 3],
+            OrbitMode::Combined => [false, false, true],
+        }
+    }
+}
+
+/// Iterate c from z = 0 and record the orbit, with the same step as `buddhabrot_channel`
+///
+/// # Arguments
+///
+/// * `c` - The sampled parameter
+/// * `params` - Buddhabrot parameters (formula, bailout, custom imaginary unit)
+/// * `max_iter` - Maximum number of iterations
+/// * `orbit` - Cleared and filled with the orbit points before escape, starting with z = 0
+///
+/// # Returns
+///
+/// The iteration at which the orbit escaped, or `None` if it stayed bounded
+pub(crate) fn trace_escaping_orbit(
+    c: Complex<f64>,
+    params: &BuddhabrotParams,
+    max_iter: u32,
+    orbit: &mut Vec<Complex<f64>>,
+) -> Option<u32> {
+    orbit.clear();
+    let bailout_sqr = params.bailout * params.bailout;
+    let mut z = Complex::new(0.0, 0.0);
+
+    for iter in 0..max_iter {
+        orbit.push(z);
+        z = orbit_step(z, c, params);
+        if z.norm_sqr() > bailout_sqr {
+            return Some(iter);
+        }
+    }
+    None
+}
+
+/// One iteration of the Buddhabrot formula, falling back to `z^2 + c` if evaluation fails
+pub(crate) fn orbit_step(z: Complex<f64>, c: Complex<f64>, params: &BuddhabrotParams) -> Complex<f64> {
+    let formula = compiled_for(&params.formula);
+    NumberSystem::for_params(params.i_sqrt_value, &formula).step(&formula, z, c)
+}
+
+/// Histogram cell of an orbit point, or `None` if the point lies outside the view
+pub(crate) fn orbit_pixel(point: Complex<f64>, params: &BuddhabrotParams) -> Option<(usize, usize)> {
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let fx = (point.re - x_min) / (x_max - x_min) * params.width as f64;
+    let fy = (point.im - y_min) / (y_max - y_min) * params.height as f64;
+    Some((cell_index(fx, params.width as usize)?, cell_index(fy, params.height as usize)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buddhabrot_channel, BuddhabrotChannel, BuddhabrotChannels};
+
+    #[test]
+    fn test_anti_buddhabrot_draws_bounded_orbits() {
+        let channel = BuddhabrotChannel { min_iter: 10, max_iter: 60, samples: 20_000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() };
+        let mut params = BuddhabrotParams::new([-2.0, 2.0, -2.0, 2.0], 16, 16, 10, 60, 20_000, 2.0, "z^2 + c".to_string(), channels);
+        let escaping = buddhabrot_channel(&params, &channel, 0);
+
+        params.orbits = OrbitMode::Bounded;
+        let bounded = buddhabrot_channel(&params, &channel, 0);
+        // Bounded orbits stay within |z| <= 2, so each one lands 50 points in the view
+        let total: f64 = bounded.as_slice().iter().sum();
+        assert!(total > 0.0);
+        assert_eq!(total % 50.0, 0.0);
+        assert_ne!(bounded, escaping);
+
+        assert_eq!(OrbitMode::parse("anti"), Ok(OrbitMode::Bounded));
+        assert_eq!(OrbitMode::Combined.bounded_channels(), [false, false, true]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Escape-time prefilter for Buddhabrot sampling
+//!
+//! Most uniformly sampled c values either stay bounded or escape long before `min_iter`, and
+//! finding that out costs a full orbit. An [`EscapeTimeGrid`] is computed once per render by
+//! iterating the corners of a coarse grid over the sampling area; each cell then knows the range
+//! of escape times seen on and around it. A sample is skipped without iterating when that range
+//! lies entirely outside the channel's `[min_iter, max_iter]`.
+//!
+//! The range of a cell is taken over its own corners and those of its eight neighbours, so a
+//! sample is only skipped when the escape time is out of range over a whole 3x3 block of cells.
+//! This is still a heuristic: features smaller than a cell, such as thin filaments of escaping
+//! points inside a block that looked bounded, can be missed. Use a finer grid if in doubt.
+
+use super::orbit_step;
+use crate::{BuddhabrotChannel, BuddhabrotParams};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+/// Escape times observed around one grid cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct EscapeRange {
+    /// Earliest escape iteration, `u32::MAX` when no probe escaped
+    earliest: u32,
+    /// Latest escape iteration, `u32::MAX` when some probe stayed bounded
+    latest: u32,
+}
+
+/// Coarse grid of known escape times over the sampling area
+#[derive(Debug, Clone)]
+pub struct EscapeTimeGrid {
+    bounds: [f64; 4],
+    cells: usize,
+    /// Iteration limit of the probes; later escapes are unknown
+    max_iter: u32,
+    /// Row-major, `cells * cells` entries
+    ranges: Vec<EscapeRange>,
+}
+
+impl EscapeTimeGrid {
+    /// Iterate the grid corners and record the escape-time range of every cell
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Buddhabrot parameters; the grid covers `params.sampling_bounds()`
+    /// * `cells` - Number of cells along each axis
+    /// * `max_iter` - Iteration limit for the probes, the largest `max_iter` of the channels that
+    ///   will use the grid
+    ///
+    /// # Returns
+    ///
+    /// The grid
+    pub fn compute(params: &BuddhabrotParams, cells: usize, max_iter: u32) -> Self {
+        let cells = cells.max(1);
+        let [x_min, x_max, y_min, y_max] = params.sampling_bounds();
+        let corners = cells + 1;
+        let escape_times: Vec<Option<u32>> = (0..corners * corners)
+            .into_par_iter()
+            .map(|index| {
+                let c = Complex::new(
+                    x_min + (x_max - x_min) * (index % corners) as f64 / cells as f64,
+                    y_min + (y_max - y_min) * (index / corners) as f64 / cells as f64,
+                );
+                escape_time(c, params, max_iter)
+            })
+            .collect();
+
+        // Range over each cell's own four corners
+        let mut own = Vec::with_capacity(cells * cells);
+        for y in 0..cells {
+            for x in 0..cells {
+                let mut range = EscapeRange { earliest: u32::MAX, latest: 0 };
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let time = escape_times[(y + dy) * corners + x + dx].unwrap_or(u32::MAX);
+                    range.earliest = range.earliest.min(time);
+                    range.latest = range.latest.max(time);
+                }
+                own.push(range);
+            }
+        }
+
+        // Widen by the neighbouring cells so nothing is skipped on the strength of one cell alone
+        let mut ranges = own.clone();
+        for y in 0..cells {
+            for x in 0..cells {
+                let range = &mut ranges[y * cells + x];
+                for ny in y.saturating_sub(1)..=(y + 1).min(cells - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(cells - 1) {
+                        let neighbour = own[ny * cells + nx];
+                        range.earliest = range.earliest.min(neighbour.earliest);
+                        range.latest = range.latest.max(neighbour.latest);
+                    }
+                }
+            }
+        }
+
+        Self { bounds: params.sampling_bounds(), cells, max_iter, ranges }
+    }
+
+    /// Whether a sample can be skipped because its orbit won't escape within the channel's range
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - The sampled parameter
+    /// * `channel` - Channel whose `[min_iter, max_iter]` range the orbit must escape in
+    ///
+    /// # Returns
+    ///
+    /// `true` if every escape time seen around `c` is outside the range; points outside the
+    /// grid are never skipped
+    pub fn can_skip(&self, c: Complex<f64>, channel: &BuddhabrotChannel) -> bool {
+        let [x_min, x_max, y_min, y_max] = self.bounds;
+        let fx = (c.re - x_min) / (x_max - x_min) * self.cells as f64;
+        let fy = (c.im - y_min) / (y_max - y_min) * self.cells as f64;
+        if !(fx >= 0.0 && fy >= 0.0 && fx <= self.cells as f64 && fy <= self.cells as f64) {
+            return false;
+        }
+        let x = (fx as usize).min(self.cells - 1);
+        let y = (fy as usize).min(self.cells - 1);
+        self.skips(self.ranges[y * self.cells + x], channel)
+    }
+
+    /// Fraction of cells that skip every sample for a channel, a rough estimate of the saving
+    pub fn skipped_fraction(&self, channel: &BuddhabrotChannel) -> f64 {
+        let skipped = self.ranges.iter().filter(|&&range| self.skips(range, channel)).count();
+        skipped as f64 / self.ranges.len() as f64
+    }
+
+    /// Orbits are drawn when they escape at an iteration in `min_iter..max_iter`; a range entirely
+    /// below or above that is skipped. "Bounded" only counts as above when the probes ran long enough
+    fn skips(&self, range: EscapeRange, channel: &BuddhabrotChannel) -> bool {
+        range.latest < channel.min_iter || (range.earliest >= channel.max_iter && self.max_iter >= channel.max_iter)
+    }
+}
+
+/// Iteration at which the orbit of c escapes, with the same step as the Buddhabrot samplers
+fn escape_time(c: Complex<f64>, params: &BuddhabrotParams, max_iter: u32) -> Option<u32> {
+    let bailout_sqr = params.bailout * params.bailout;
+    let mut z = Complex::new(0.0, 0.0);
+    for iter in 0..max_iter {
+        z = orbit_step(z, c, params);
+        if z.norm_sqr() > bailout_sqr {
+            return Some(iter);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuddhabrotChannels;
+
+    #[test]
+    fn test_grid_skips_interior_and_fast_escapes_only() {
+        let channel = BuddhabrotChannel { min_iter: 20, max_iter: 200, samples: 0 };
+        let params = BuddhabrotParams::new(
+            [-2.0, 1.0, -1.5, 1.5],
+            16,
+            16,
+            20,
+            200,
+            0,
+            4.0,
+            "z^2 + c".to_string(),
+            BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() },
+        );
+        let grid = EscapeTimeGrid::compute(&params, 64, 200);
+
+        // Deep inside the main cardioid nothing escapes; far outside everything escapes at once
+        assert!(grid.can_skip(Complex::new(-0.1, 0.0), &channel));
+        assert!(grid.can_skip(Complex::new(0.9, 1.4), &channel));
+        // Near the boundary escape times straddle the range, so samples there are kept
+        assert!(!grid.can_skip(Complex::new(-0.75, 0.05), &channel));
+        assert!(!grid.can_skip(Complex::new(5.0, 0.0), &channel));
+
+        let fraction = grid.skipped_fraction(&channel);
+        assert!(fraction > 0.3 && fraction < 1.0, "skipped {}", fraction);
+    }
+}
@@ -0,0 +1,111 @@
+//! Sampling areas for zoomed Buddhabrot renders
+//!
+//! A Buddhabrot pixel counts the orbits that pass through it, wherever their c lies. Sampling c
+//! only over the view is right for the full set, but once the view is zoomed in most of the
+//! orbits crossing it start outside it, and the render misses them. `BuddhabrotParams` therefore
+//! keeps the area c is sampled from, `sample_bounds`, separate from the view, `bounds`.
+//! `auto_sample_bounds` finds an area that contains every c whose orbit reaches the view.
+
+use super::{orbit_pixel, trace_escaping_orbit};
+use crate::rng::CounterRng;
+use crate::BuddhabrotParams;
+use num_complex::Complex;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Seed of the probes of `auto_sample_bounds`, used like `BUDDHABROT_SEED`
+const PROBE_SEED: u64 = 0x5eed_0005;
+
+impl BuddhabrotParams {
+    /// Area c is sampled from [x_min, x_max, y_min, y_max]: `sample_bounds`, or the view if unset
+    pub fn sampling_bounds(&self) -> [f64; 4] {
+        self.sample_bounds.unwrap_or(self.bounds)
+    }
+}
+
+/// Find a sampling area containing every c whose orbit is drawn in the view
+///
+/// One jittered probe per cell of a `cells` x `cells` grid over the square of the bailout radius
+/// is traced with each channel's settings (escaping or bounded orbits as `params.orbits` says).
+/// The result is the bounding box of the cells whose probe draws into the view, grown by one cell
+/// on every side so structure finer than the grid isn't cut off. c values beyond the bailout
+/// radius are assumed to escape at once, as they do for `z^2 + c`, so only their starting point
+/// is ever drawn.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters; `bounds` is the view
+/// * `cells` - Number of probe cells along each axis (256 is plenty for most views)
+///
+/// # Returns
+///
+/// The sampling area, or the view itself if no probe reached it
+pub fn auto_sample_bounds(params: &BuddhabrotParams, cells: usize) -> [f64; 4] {
+    let cells = cells.max(1);
+    let radius = params.bailout.abs().max(2.0);
+    let cell_size = 2.0 * radius / cells as f64;
+    let channels = [&params.channels.red, &params.channels.green, &params.channels.blue];
+    let bounded = params.orbits.bounded_channels();
+    let max_iter = channels.iter().map(|channel| channel.max_iter).max().unwrap_or(params.max_iterations);
+
+    let hits: Vec<(usize, usize)> = (0..cells * cells)
+        .into_par_iter()
+        .filter_map(|index| {
+            let (x, y) = (index % cells, index / cells);
+            let mut rng = CounterRng::new(PROBE_SEED, index as u64);
+            let c = Complex::new(
+                -radius + (x as f64 + rng.gen::<f64>()) * cell_size,
+                -radius + (y as f64 + rng.gen::<f64>()) * cell_size,
+            );
+            let mut orbit = Vec::new();
+            let escape = trace_escaping_orbit(c, params, max_iter, &mut orbit);
+            let drawn = channels.iter().zip(bounded).any(|(channel, bounded)| {
+                let range = channel.min_iter as usize..channel.max_iter as usize;
+                let points = match escape {
+                    Some(iter) if !bounded && iter >= channel.min_iter && iter < channel.max_iter => {
+                        &orbit[..=iter as usize]
+                    }
+                    // Bounded for this channel's max_iter, although it escapes before the largest
+                    Some(iter) if bounded && iter >= channel.max_iter => orbit.get(range).unwrap_or(&[]),
+                    None if bounded => orbit.get(range).unwrap_or(&[]),
+                    _ => &[],
+                };
+                points.iter().any(|&z| orbit_pixel(z, params).is_some())
+            });
+            drawn.then_some((x, y))
+        })
+        .collect();
+
+    if hits.is_empty() {
+        return params.bounds;
+    }
+    let (x_lo, x_hi) = hits.iter().fold((cells, 0), |(lo, hi), &(x, _)| (lo.min(x), hi.max(x)));
+    let (y_lo, y_hi) = hits.iter().fold((cells, 0), |(lo, hi), &(_, y)| (lo.min(y), hi.max(y)));
+    let edge = |cell: usize| -radius + cell as f64 * cell_size;
+    [
+        edge(x_lo.saturating_sub(1)),
+        edge((x_hi + 2).min(cells)),
+        edge(y_lo.saturating_sub(1)),
+        edge((y_hi + 2).min(cells)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BuddhabrotChannel, BuddhabrotChannels};
+
+    #[test]
+    fn test_auto_sample_bounds_cover_orbit_sources() {
+        let channel = BuddhabrotChannel { min_iter: 0, max_iter: 50, samples: 1000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel };
+        // A view off the real axis that orbits from all over the set pass through
+        let params = BuddhabrotParams::new([0.1, 0.3, 0.5, 0.7], 8, 8, 0, 50, 1000, 2.0, "z^2 + c".to_string(), channels);
+        assert_eq!(params.sampling_bounds(), params.bounds);
+
+        let [x_min, x_max, y_min, y_max] = auto_sample_bounds(&params, 64);
+        assert!(x_min < -1.5 && x_max > 0.3, "{} {}", x_min, x_max);
+        assert!(y_min < -0.5 && y_max > 0.7, "{} {}", y_min, y_max);
+        assert!(x_min >= -2.0 && x_max <= 2.0 && y_min >= -2.0 && y_max <= 2.0);
+    }
+}
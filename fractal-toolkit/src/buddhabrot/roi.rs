@@ -0,0 +1,440 @@
+//! Region-of-interest sampling for zoomed-in Buddhabrots
+//!
+//! A Buddhabrot pixel only receives contributions from the c values whose orbits pass through
+//! it. For a deep zoom those c values fill a tiny fraction of the plane, so uniform sampling
+//! spends nearly all of its time on orbits that never touch the image. This sampler finds
+//! where the useful c values are and concentrates the samples there:
+//!
+//! 1. **Seeds.** c values whose orbit passes through the view are collected from a uniform
+//!    pilot run (enough for shallow zooms) and by solving `z_n(c) = w` with Newton's method
+//!    for random points w in the view, which lands directly on preimages of the view.
+//! 2. **Adaptive enlargement.** Around each seed a square is grown by doubling, starting far
+//!    below the view size, for as long as most probe samples in it still pass through the
+//!    view. Each region records its measured hit rate.
+//! 3. **Sampling.** Samples are drawn from a mixture of the regions, weighted by their expected
+//!    number of hits, and a small uniform share over the whole sampling area so nothing is
+//!    excluded outright.
+//!
+//! Every orbit is weighted by the ratio of the uniform density to the mixture density, so the
+//! histogram is an unbiased estimate of what uniform sampling over `sample_bounds` with the same
+//! number of samples would produce, only with far less noise.
+
+use super::scratch::with_orbit_buffer;
+use super::{orbit_pixel, orbit_step, trace_escaping_orbit};
+use crate::rng::CounterRng;
+use crate::{combine_buddhabrot_channels, AtomicGrid, BuddhabrotChannel, BuddhabrotParams, DensityGrid};
+use chrono::Local;
+use num_complex::Complex;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Maximum number of Newton steps when solving for a preimage of the view
+const MAX_NEWTON_STEPS: u32 = 50;
+
+/// Options for region-of-interest Buddhabrot sampling
+#[derive(Debug, Clone)]
+pub struct RoiSamplingOptions {
+    /// Area of the c plane the estimate corresponds to [x_min, x_max, y_min, y_max]
+    pub sample_bounds: [f64; 4],
+    /// Number of uniform samples used to look for seeds
+    pub pilot_samples: u64,
+    /// Number of Newton solves for preimages of the view
+    pub preimage_attempts: u32,
+    /// Largest orbit index n used when solving `z_n(c) = w`
+    pub max_preimage_depth: u32,
+    /// Maximum number of sampling regions kept
+    pub max_regions: usize,
+    /// Samples used to measure the hit rate of a region while enlarging it
+    pub probe_samples: u32,
+    /// A region keeps doubling in size while at least this fraction of its probes hit the view
+    pub enlarge_threshold: f64,
+    /// Fraction of samples drawn uniformly over `sample_bounds`
+    pub exploration: f64,
+}
+
+impl Default for RoiSamplingOptions {
+    fn default() -> Self {
+        Self {
+            sample_bounds: [-2.0, 2.0, -2.0, 2.0],
+            pilot_samples: 100_000,
+            preimage_attempts: 2000,
+            max_preimage_depth: 12,
+            max_regions: 256,
+            probe_samples: 32,
+            enlarge_threshold: 0.25,
+            exploration: 0.05,
+        }
+    }
+}
+
+/// A square of the c plane that is sampled uniformly
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    center: Complex<f64>,
+    half_width: f64,
+    hit_rate: f64,
+}
+
+impl Region {
+    fn contains(&self, c: Complex<f64>) -> bool {
+        (c.re - self.center.re).abs() <= self.half_width && (c.im - self.center.im).abs() <= self.half_width
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * self.half_width * self.half_width
+    }
+}
+
+/// Whether an escaping orbit within the channel's iteration range passes through the view
+fn passes_through_view(
+    c: Complex<f64>,
+    params: &BuddhabrotParams,
+    channel: &BuddhabrotChannel,
+    orbit: &mut Vec<Complex<f64>>,
+) -> bool {
+    match trace_escaping_orbit(c, params, channel.max_iter, orbit) {
+        Some(iter) if iter >= channel.min_iter => orbit.iter().any(|&z| orbit_pixel(z, params).is_some()),
+        _ => false,
+    }
+}
+
+/// Uniform random point in a rectangle [x_min, x_max, y_min, y_max]
+fn uniform_in<R: Rng>(rng: &mut R, bounds: [f64; 4]) -> Complex<f64> {
+    Complex::new(
+        bounds[0] + (bounds[1] - bounds[0]) * rng.gen::<f64>(),
+        bounds[2] + (bounds[3] - bounds[2]) * rng.gen::<f64>(),
+    )
+}
+
+fn in_bounds(c: Complex<f64>, bounds: [f64; 4]) -> bool {
+    c.re >= bounds[0] && c.re <= bounds[1] && c.im >= bounds[2] && c.im <= bounds[3]
+}
+
+/// Solve `z_n(c) = target` with Newton's method, using a finite-difference derivative in c
+fn solve_preimage(guess: Complex<f64>, target: Complex<f64>, depth: u32, params: &BuddhabrotParams) -> Option<Complex<f64>> {
+    let orbit_point = |c: Complex<f64>| {
+        let mut z = Complex::new(0.0, 0.0);
+        for _ in 0..depth {
+            z = orbit_step(z, c, params);
+        }
+        z
+    };
+
+    let mut c = guess;
+    for _ in 0..MAX_NEWTON_STEPS {
+        let value = orbit_point(c) - target;
+        let h = 1e-7 * c.norm().max(1e-3);
+        let derivative = (orbit_point(c + h) - orbit_point(c)) / h;
+        if !value.is_finite() || derivative.norm_sqr() == 0.0 || !derivative.is_finite() {
+            return None;
+        }
+        let step = value / derivative;
+        c -= step;
+        if !c.is_finite() {
+            return None;
+        }
+        if step.norm() <= f64::EPSILON * 8.0 * c.norm().max(1e-3) {
+            break;
+        }
+    }
+    Some(c)
+}
+
+/// Collect c values whose orbits pass through the view
+pub(super) fn find_seeds(params: &BuddhabrotParams, channel: &BuddhabrotChannel, options: &RoiSamplingOptions) -> Vec<Complex<f64>> {
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let view = [x_min, x_max, y_min, y_max];
+    let max_depth = options.max_preimage_depth.min(channel.max_iter).max(1);
+
+    // Both searches run in parallel batches with fixed seeds, so results are reproducible
+    let batch = 1000u64;
+    let pilot_batches = options.pilot_samples.div_ceil(batch);
+    let pilot: Vec<Complex<f64>> = (0..pilot_batches)
+        .into_par_iter()
+        .flat_map_iter(|b| {
+            if params.cancel.is_cancelled() {
+                return Vec::new();
+            }
+            let mut rng = CounterRng::new(0x5eed_0001, b);
+            let mut orbit = Vec::new();
+            let count = batch.min(options.pilot_samples - b * batch);
+            (0..count)
+                .filter_map(|_| {
+                    let c = uniform_in(&mut rng, options.sample_bounds);
+                    passes_through_view(c, params, channel, &mut orbit).then_some(c)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let preimages: Vec<Complex<f64>> = (0..options.preimage_attempts)
+        .into_par_iter()
+        .filter_map(|attempt| {
+            if params.cancel.is_cancelled() {
+                return None;
+            }
+            let mut rng = CounterRng::new(0x5eed_0002, attempt as u64);
+            let depth = rng.gen_range(1..=max_depth);
+            let target = uniform_in(&mut rng, view);
+            let guess = uniform_in(&mut rng, options.sample_bounds);
+            let c = solve_preimage(guess, target, depth, params)?;
+            (in_bounds(c, options.sample_bounds)
+                && with_orbit_buffer(|orbit| passes_through_view(c, params, channel, orbit)))
+            .then_some(c)
+        })
+        .collect();
+
+    // Prefer preimage seeds, which are spread over all the view's preimages
+    let mut seeds = preimages;
+    seeds.extend(pilot);
+    seeds
+}
+
+/// Grow a square around a seed while most of its samples still pass through the view
+fn enlarge_region(
+    seed: Complex<f64>,
+    index: usize,
+    params: &BuddhabrotParams,
+    channel: &BuddhabrotChannel,
+    options: &RoiSamplingOptions,
+) -> Region {
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let [sx_min, sx_max, sy_min, sy_max] = options.sample_bounds;
+    let view_half_width = 0.5 * (x_max - x_min).max(y_max - y_min);
+    let max_half_width = 0.5 * (sx_max - sx_min).max(sy_max - sy_min);
+
+    let mut rng = CounterRng::new(0x5eed_0003, index as u64);
+    let mut orbit = Vec::new();
+    let probes = options.probe_samples.max(1);
+    let mut measure = |half_width: f64| {
+        let bounds = [seed.re - half_width, seed.re + half_width, seed.im - half_width, seed.im + half_width];
+        let hits = (0..probes)
+            .filter(|_| passes_through_view(uniform_in(&mut rng, bounds), params, channel, &mut orbit))
+            .count();
+        hits as f64 / probes as f64
+    };
+
+    // Start well below the view size (preimages are usually smaller than the view itself)
+    let mut half_width = (view_half_width * 1e-3).max(seed.norm() * 1e-14).max(f64::MIN_POSITIVE);
+    let mut hit_rate = measure(half_width);
+    while hit_rate >= options.enlarge_threshold && half_width * 2.0 <= max_half_width {
+        let rate = measure(half_width * 2.0);
+        if rate < options.enlarge_threshold {
+            break;
+        }
+        half_width *= 2.0;
+        hit_rate = rate;
+    }
+
+    Region {
+        center: seed,
+        half_width,
+        // The seed itself hit, so a region never has a zero rate
+        hit_rate: hit_rate.max(1.0 / probes as f64),
+    }
+}
+
+/// Calculate one Buddhabrot channel with region-of-interest sampling
+///
+/// The histogram estimates the density that `channel_params.samples` uniform samples over
+/// `options.sample_bounds` would produce, so unlike `buddhabrot_channel` the sampled c values
+/// are not restricted to the view. Orbits from c values outside the view are exactly what
+/// makes a zoomed Buddhabrot look like the corresponding part of the full one.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters; `bounds` is the rendered view
+/// * `channel_params` - Channel-specific parameters (min/max iterations, sample count)
+/// * `options` - Region search and sampling options
+///
+/// # Returns
+///
+/// A histogram of importance-weighted orbit visits
+pub fn buddhabrot_channel_roi(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    options: &RoiSamplingOptions,
+) -> DensityGrid {
+    use std::time::Instant;
+
+    let start_time = Instant::now();
+    println!(
+        "Searching for Buddhabrot regions of interest - Started at {:?}",
+        Local::now().format("%H:%M:%S")
+    );
+
+    let mut seeds = find_seeds(params, channel_params, options);
+    seeds.truncate(options.max_regions);
+    let regions: Vec<Region> = seeds
+        .par_iter()
+        .enumerate()
+        .map(|(i, &seed)| enlarge_region(seed, i, params, channel_params, options))
+        .collect();
+
+    // Sample each region in proportion to the number of hits it is expected to produce
+    let [sx_min, sx_max, sy_min, sy_max] = options.sample_bounds;
+    let total_area = (sx_max - sx_min) * (sy_max - sy_min);
+    let weights: Vec<f64> = regions.iter().map(|r| r.area().min(total_area) * r.hit_rate).collect();
+    let total_weight: f64 = weights.iter().sum();
+    let shares: Vec<f64> = weights.iter().map(|w| w / total_weight).collect();
+    let cumulative: Vec<f64> = weights
+        .iter()
+        .scan(0.0, |sum, w| {
+            *sum += w;
+            Some(*sum)
+        })
+        .collect();
+    let exploration = if regions.is_empty() { 1.0 } else { options.exploration.clamp(0.0, 1.0) };
+    if regions.is_empty() {
+        println!("No orbits through the view were found; falling back to uniform sampling");
+    } else {
+        println!(
+            "Found {} regions of interest in {:.1}s",
+            regions.len(),
+            start_time.elapsed().as_secs_f64()
+        );
+    }
+
+    // Probability density of the sampling mixture at c
+    let density = |c: Complex<f64>| {
+        let uniform = if in_bounds(c, options.sample_bounds) { exploration / total_area } else { 0.0 };
+        let mixture: f64 = regions
+            .iter()
+            .zip(&shares)
+            .filter(|(region, _)| region.contains(c))
+            .map(|(region, share)| share / region.area())
+            .sum();
+        uniform + (1.0 - exploration) * mixture
+    };
+
+    let total_samples = channel_params.samples;
+    println!(
+        "Generating Buddhabrot channel (region of interest): 0% (0/{}) - Using {} threads.",
+        total_samples,
+        rayon::current_num_threads()
+    );
+
+    let chunk_size = (total_samples / (rayon::current_num_threads() as u64 * 4)).max(1000);
+    let num_chunks = total_samples.div_ceil(chunk_size).max(1);
+    let shared_histogram = AtomicGrid::new(params.width as usize, params.height as usize);
+    (0..num_chunks)
+        .into_par_iter()
+        .for_each(|chunk_idx| {
+            let start_sample = chunk_idx * chunk_size;
+            let end_sample = (start_sample + chunk_size).min(total_samples);
+            let mut orbit = Vec::new();
+
+            for sample in start_sample..end_sample {
+                if params.cancel.is_cancelled() {
+                    break;
+                }
+                let mut rng = CounterRng::new(crate::BUDDHABROT_SEED, sample);
+                let c = if rng.gen::<f64>() < exploration {
+                    uniform_in(&mut rng, options.sample_bounds)
+                } else {
+                    let pick = rng.gen::<f64>() * total_weight;
+                    let region = &regions[cumulative.partition_point(|&w| w <= pick).min(regions.len() - 1)];
+                    let h = region.half_width;
+                    uniform_in(&mut rng, [region.center.re - h, region.center.re + h, region.center.im - h, region.center.im + h])
+                };
+                // Points outside the sampling area have zero target density
+                if !in_bounds(c, options.sample_bounds) {
+                    continue;
+                }
+
+                match trace_escaping_orbit(c, params, channel_params.max_iter, &mut orbit) {
+                    Some(iter) if iter >= channel_params.min_iter => {}
+                    _ => continue,
+                }
+                let mut weight = None;
+                for &z in &orbit {
+                    if let Some((x, y)) = orbit_pixel(z, params) {
+                        let w = *weight.get_or_insert_with(|| 1.0 / (total_area * density(c)));
+                        shared_histogram.add(x, y, w);
+                    }
+                }
+            }
+        });
+    let final_histogram = shared_histogram.into_grid();
+
+    println!(
+        "Generating Buddhabrot channel (region of interest): 100% ({}/{}), Completed in {:.1}s",
+        total_samples,
+        total_samples,
+        start_time.elapsed().as_secs_f64()
+    );
+
+    final_histogram
+}
+
+/// Generate a complete Buddhabrot image using region-of-interest sampling for every channel
+///
+/// # Arguments
+///
+/// * `params` - Complete Buddhabrot parameters including all channel configurations
+/// * `options` - Region search and sampling options
+///
+/// # Returns
+///
+/// An RGB image normalized the same way as `generate_buddhabrot`
+pub fn generate_buddhabrot_roi(params: &BuddhabrotParams, options: &RoiSamplingOptions) -> image::RgbImage {
+    let mut red_hist = buddhabrot_channel_roi(params, &params.channels.red, options);
+    let mut green_hist = buddhabrot_channel_roi(params, &params.channels.green, options);
+    let mut blue_hist = buddhabrot_channel_roi(params, &params.channels.blue, options);
+    super::denoise::denoise_channels(params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+
+    let mut img = combine_buddhabrot_channels(params.width, params.height, &red_hist, &green_hist, &blue_hist, params.channel_mix.as_ref(), params.display_transform);
+    crate::postfx::apply_post_effects_rgb(&mut img, &params.post_effects);
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuddhabrotChannels;
+
+    fn square_params(center: Complex<f64>, half_width: f64, max_iter: u32, samples: u64) -> BuddhabrotParams {
+        let channel = BuddhabrotChannel { min_iter: 0, max_iter, samples };
+        BuddhabrotParams::new(
+            [center.re - half_width, center.re + half_width, center.im - half_width, center.im + half_width],
+            8,
+            8,
+            0,
+            max_iter,
+            samples,
+            4.0,
+            "z^2 + c".to_string(),
+            BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel },
+        )
+    }
+
+    #[test]
+    fn test_roi_sampling_matches_uniform_estimate() {
+        let center = Complex::new(-0.75, 0.1);
+        let params = square_params(center, 0.05, 40, 50_000);
+        let options = RoiSamplingOptions { pilot_samples: 5_000, preimage_attempts: 200, ..Default::default() };
+        let roi = buddhabrot_channel_roi(&params, &params.channels.red, &options);
+
+        let uniform_params = square_params(center, 0.05, 40, 500_000);
+        let uniform_options = RoiSamplingOptions { pilot_samples: 0, preimage_attempts: 0, ..Default::default() };
+        let uniform = buddhabrot_channel_roi(&uniform_params, &uniform_params.channels.red, &uniform_options);
+
+        // Both estimate the same density; scale the uniform run to the same sample count
+        let roi_total: f64 = roi.as_slice().iter().sum();
+        let uniform_total: f64 = uniform.as_slice().iter().sum::<f64>() / 10.0;
+        assert!((roi_total / uniform_total - 1.0).abs() < 0.25, "roi {} vs uniform {}", roi_total, uniform_total);
+    }
+
+    #[test]
+    fn test_roi_sampling_covers_deep_zoom() {
+        let params = square_params(Complex::new(-0.743643887, 0.131825904), 1e-5, 300, 20_000);
+        let uniform_options = RoiSamplingOptions { pilot_samples: 0, preimage_attempts: 0, ..Default::default() };
+        let uniform = buddhabrot_channel_roi(&params, &params.channels.red, &uniform_options);
+        assert!(uniform.as_slice().iter().all(|&v| v == 0.0));
+
+        let options = RoiSamplingOptions { pilot_samples: 0, preimage_attempts: 200, max_regions: 32, ..Default::default() };
+        let roi = buddhabrot_channel_roi(&params, &params.channels.red, &options);
+        let covered = roi.as_slice().iter().filter(|&&v| v > 0.0).count();
+        assert!(covered > 32, "only {} of 64 pixels reached", covered);
+    }
+}
@@ -0,0 +1,127 @@
+//! Reusable per-thread orbit buffers
+//!
+//! Every Buddhabrot sample records its orbit before knowing whether it will be drawn. Allocating
+//! a fresh `Vec` for each one costs an allocation plus a reallocation every time the orbit
+//! doubles in length, millions of times per channel. The samplers instead borrow one buffer per
+//! worker thread, which keeps its capacity from sample to sample and only grows when an orbit is
+//! longer than any seen before on that thread.
+//!
+//! With `max_iter` in the hundreds of thousands even one buffer per thread grows large, and most
+//! of what it records is thrown away when the orbit doesn't escape in range. Channels whose
+//! `max_iter` exceeds `BuddhabrotParams::orbit_buffer_limit` therefore record nothing: each sample
+//! is iterated once to find where its orbit escapes, and only orbits that are drawn are iterated
+//! again and plotted point by point (`replay_orbit`).
+
+use num_complex::Complex;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Largest capacity, in points, a thread keeps between channels; longer buffers are released
+pub const MAX_RETAINED_POINTS: usize = 1 << 20;
+
+/// Default of `BuddhabrotParams::orbit_buffer_limit`: orbits of channels with a higher `max_iter`
+/// are replayed instead of recorded, keeping each thread's buffer under 2 MiB
+pub const DEFAULT_ORBIT_BUFFER_LIMIT: usize = 1 << 17;
+
+/// Times any orbit buffer had to grow
+static GROWTHS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static ORBIT_BUFFER: RefCell<Vec<Complex<f64>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Number of times an orbit buffer was allocated or grown, across all threads
+///
+/// Compared to the number of samples, this shows how many allocations the reuse saved.
+pub fn orbit_buffer_growths() -> u64 {
+    GROWTHS.load(Ordering::Relaxed)
+}
+
+/// Reset the growth count, e.g. before rendering a channel
+pub fn reset_orbit_buffer_growths() {
+    GROWTHS.store(0, Ordering::Relaxed);
+}
+
+/// Run `f` with this thread's orbit buffer, emptied
+///
+/// # Arguments
+///
+/// * `f` - Work that records orbits in the buffer; it may clear and refill it any number of times
+///
+/// # Returns
+///
+/// What `f` returns
+pub(crate) fn with_orbit_buffer<R>(f: impl FnOnce(&mut Vec<Complex<f64>>) -> R) -> R {
+    // Taken out of the cell so that a nested call gets a buffer of its own instead of a panic
+    let mut orbit = ORBIT_BUFFER.with(|buffer| std::mem::take(&mut *buffer.borrow_mut()));
+    orbit.clear();
+    let capacity = orbit.capacity();
+
+    let result = f(&mut orbit);
+
+    if orbit.capacity() > capacity {
+        GROWTHS.fetch_add(1, Ordering::Relaxed);
+    }
+    if orbit.capacity() > MAX_RETAINED_POINTS {
+        orbit = Vec::new();
+    }
+    ORBIT_BUFFER.with(|buffer| *buffer.borrow_mut() = orbit);
+    result
+}
+
+/// Recompute part of an orbit from z = 0 and hand over its points one at a time
+///
+/// # Arguments
+///
+/// * `step` - One iteration of the map, with c fixed
+/// * `points` - Indices of the orbit points wanted, z_0 being the starting point
+/// * `plot` - Called with every wanted point and the point after it, `None` after the last one
+pub(crate) fn replay_orbit<S, P>(step: S, points: std::ops::Range<u32>, mut plot: P)
+where
+    S: Fn(Complex<f64>) -> Complex<f64>,
+    P: FnMut(Complex<f64>, Option<Complex<f64>>),
+{
+    let mut z = Complex::new(0.0, 0.0);
+    for index in 0..points.end {
+        let next = step(z);
+        if index >= points.start {
+            plot(z, (index + 1 < points.end).then_some(next));
+        }
+        z = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orbit_buffer_keeps_capacity_between_uses() {
+        with_orbit_buffer(|orbit| orbit.extend((0..1000).map(|n| Complex::new(n as f64, 0.0))));
+        with_orbit_buffer(|orbit| {
+            assert!(orbit.is_empty());
+            assert!(orbit.capacity() >= 1000);
+            // A nested borrow works and gets its own buffer
+            with_orbit_buffer(|inner| assert!(inner.is_empty()));
+        });
+    }
+
+    #[test]
+    fn test_replayed_orbits_match_buffered_orbits() {
+        use crate::buddhabrot::OrbitMode;
+        use crate::{buddhabrot_channel, BuddhabrotChannel, BuddhabrotChannels, BuddhabrotParams};
+
+        let channel = BuddhabrotChannel { min_iter: 5, max_iter: 60, samples: 3000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() };
+        let mut params = BuddhabrotParams::new([-2.0, 1.0, -1.5, 1.5], 48, 48, 5, 60, 3000, 2.0, "z^2 + c".to_string(), channels);
+        for orbits in [OrbitMode::Escaping, OrbitMode::Bounded] {
+            params.orbits = orbits;
+            params.orbit_buffer_limit = DEFAULT_ORBIT_BUFFER_LIMIT;
+            let buffered = buddhabrot_channel(&params, &channel, 0);
+            params.orbit_buffer_limit = 10;
+            let replayed = buddhabrot_channel(&params, &channel, 0);
+            assert!(buffered.as_slice().iter().any(|&count| count > 0.0));
+            assert_eq!(buffered.as_slice(), replayed.as_slice());
+        }
+    }
+}
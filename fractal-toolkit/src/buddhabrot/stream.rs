@@ -0,0 +1,333 @@
+//! Recorded orbit point streams: sample a Buddhabrot once, bin it many times
+//!
+//! A Buddhabrot histogram is fixed to the size and view it was accumulated at; rendering another
+//! size, a crop or a rotated view means iterating every sample again. `record_orbit_stream`
+//! writes the orbit points themselves instead, quantized to 16 bits per coordinate over a
+//! recording region, and `OrbitStream::rebin` bins them into histograms of any size, crop and
+//! rotation within that region without iterating anything.
+//!
+//! A stream file starts with the magic bytes `FTKORBIT`, a format version and a JSON header
+//! (`OrbitStreamHeader`) with the region and the parameters the samples were taken with. Blocks
+//! of points follow, written by the worker threads as their buffers fill: a channel byte (0 red,
+//! 1 green, 2 blue), a little-endian `u32` point count, and that many pairs of `u16` cells,
+//! column then row, on a 65536 x 65536 grid over the region. Each point takes 4 bytes, so a
+//! stream is about 4 bytes times the number of orbit points inside the region.
+//!
+//! Points outside the region are dropped, so record a region larger than the view to crop or
+//! rotate beyond it later. Rebinning is exact up to the quantization, a 65536th of the region,
+//! which only moves points lying within that distance of a pixel edge; when the pixel edges are
+//! cell edges, such as for the whole region at a power-of-two size, it is exact. Denoising
+//! depends on the pixel grid, so it is applied at rebinning (`RebinOptions::denoise`). Orbit
+//! interpolation needs the consecutive points of each orbit, which the stream doesn't keep, so
+//! it isn't available for recorded streams.
+
+use super::denoise::{denoise_channels, DenoiseOptions};
+use crate::output::AtomicFile;
+use crate::{sample_buddhabrot_orbits, telemetry, BuddhabrotParams, DensityGrid, FractalError};
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufReader, Read, Write};
+use std::sync::Mutex;
+
+/// Magic bytes at the start of every stream
+const MAGIC: &[u8; 8] = b"FTKORBIT";
+
+/// Format version written after the magic bytes
+const VERSION: u32 = 1;
+
+/// Cells per axis of the quantization grid
+const CELLS: f64 = 65536.0;
+
+/// Points a worker buffers before writing them as a block
+const BLOCK_POINTS: usize = 1 << 16;
+
+/// Longest JSON header read; a header is a few kilobytes, so a longer one is a corrupt file
+const MAX_HEADER_LEN: u32 = 1 << 20;
+
+/// Description of a recorded stream, stored as JSON after its magic bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrbitStreamHeader {
+    /// Region of the plane the points were recorded in [x_min, x_max, y_min, y_max]
+    pub region: [f64; 4],
+    /// Parameters the samples were taken with
+    pub params: BuddhabrotParams,
+}
+
+/// How to bin a recorded stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct RebinOptions {
+    /// Width of the histograms in pixels
+    pub width: u32,
+    /// Height of the histograms in pixels
+    pub height: u32,
+    /// View to bin [x_min, x_max, y_min, y_max]; any part of it outside the recorded region
+    /// stays empty
+    pub bounds: [f64; 4],
+    /// Rotation of the view about its center, in degrees counter-clockwise
+    pub rotation: f64,
+    /// Denoising pass applied to the histograms after binning
+    pub denoise: Option<DenoiseOptions>,
+}
+
+/// Sample a Buddhabrot and record the orbit points inside a region to a stream file
+///
+/// The channels are sampled as by `buddhabrot_densities`, with the same seeds and orbit mode,
+/// so rebinning the stream at the parameters' size and bounds gives the same histograms up to
+/// the quantization of the points.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters without interpolation; their size and bounds are not used
+/// * `region` - Region to record [x_min, x_max, y_min, y_max], e.g. the view bounds
+/// * `path` - Stream file to write
+///
+/// # Returns
+///
+/// The number of points recorded in each channel, or an error if the file can't be written
+pub fn record_orbit_stream(params: &BuddhabrotParams, region: [f64; 4], path: &str) -> Result<[u64; 3], FractalError> {
+    let [x_min, x_max, y_min, y_max] = region;
+    if !(x_max > x_min && y_max > y_min) {
+        return Err(FractalError::InvalidInput(format!("Invalid recording region {:?}: the maximums must exceed the minimums", region)));
+    }
+    if params.interpolation.is_some() {
+        return Err(FractalError::InvalidInput("Orbit interpolation can't be recorded in an orbit stream".to_string()));
+    }
+    let mut file = AtomicFile::create(path)?;
+    let header = serde_json::to_vec(&OrbitStreamHeader { region, params: params.clone() })
+        .map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+    let write_error = |e: std::io::Error| FractalError::io("write", path, e);
+    file.write_all(MAGIC).map_err(write_error)?;
+    file.write_all(&VERSION.to_le_bytes()).map_err(write_error)?;
+    file.write_all(&(header.len() as u32).to_le_bytes()).map_err(write_error)?;
+    file.write_all(&header).map_err(write_error)?;
+
+    // Each rayon job fills its own buffer and writes it as a block once full
+    let output = Mutex::new((file, None::<std::io::Error>));
+    let write_block = |channel: u8, cells: &[u16]| {
+        let mut output = output.lock().unwrap();
+        let (file, error) = &mut *output;
+        if error.is_some() {
+            return;
+        }
+        let mut block = Vec::with_capacity(5 + cells.len() * 2);
+        block.push(channel);
+        block.extend_from_slice(&((cells.len() / 2) as u32).to_le_bytes());
+        block.extend(cells.iter().flat_map(|cell| cell.to_le_bytes()));
+        if let Err(e) = file.write_all(&block) {
+            *error = Some(e);
+        }
+    };
+
+    let mut counts = [0u64; 3];
+    let channels = [&params.channels.red, &params.channels.green, &params.channels.blue];
+    let grid = (params.prefilter_cells > 0).then(|| {
+        let max_iter = channels.iter().map(|channel| channel.max_iter).max().unwrap_or(params.max_iterations);
+        super::EscapeTimeGrid::compute(params, params.prefilter_cells, max_iter)
+    });
+    for (index, (channel_params, bounded)) in channels.into_iter().zip(params.orbits.bounded_channels()).enumerate() {
+        let channel = index as u8;
+        let recorded = std::sync::atomic::AtomicU64::new(0);
+        let flush = |buffer: &mut Vec<u16>| {
+            if !buffer.is_empty() {
+                recorded.fetch_add(buffer.len() as u64 / 2, std::sync::atomic::Ordering::Relaxed);
+                write_block(channel, buffer);
+                buffer.clear();
+            }
+        };
+        let record = |buffer: &mut Vec<u16>, point: Complex<f64>, _next: Option<Complex<f64>>| {
+            let column = ((point.re - x_min) / (x_max - x_min) * CELLS).floor();
+            let row = ((point.im - y_min) / (y_max - y_min) * CELLS).floor();
+            if !(0.0..CELLS).contains(&column) || !(0.0..CELLS).contains(&row) {
+                return;
+            }
+            buffer.push(column as u16);
+            buffer.push(row as u16);
+            if buffer.len() >= BLOCK_POINTS * 2 {
+                flush(buffer);
+            }
+        };
+        // A finished job's points are written out rather than carried into the next job's buffer
+        let merge = |mut finished: Vec<u16>, buffer: Vec<u16>| {
+            flush(&mut finished);
+            buffer
+        };
+        let telemetry = telemetry::RenderTelemetry::start("Recording Buddhabrot orbits", channel_params.samples);
+        println!("Recording Buddhabrot channel {} of 3: {} samples", index + 1, channel_params.samples);
+        let (mut buffer, _) =
+            sample_buddhabrot_orbits(params, channel_params, bounded, grid.as_ref(), 0..channel_params.samples, &telemetry, Vec::new, record, merge);
+        flush(&mut buffer);
+        counts[index] = recorded.into_inner();
+    }
+
+    let (file, error) = output.into_inner().unwrap();
+    if let Some(e) = error {
+        return Err(write_error(e));
+    }
+    file.commit()?;
+    Ok(counts)
+}
+
+/// A recorded stream of orbit points
+#[derive(Debug, Clone)]
+pub struct OrbitStream {
+    path: String,
+    header: OrbitStreamHeader,
+    data_offset: u64,
+}
+
+impl OrbitStream {
+    /// Open a stream written by `record_orbit_stream` and read its header
+    pub fn open(path: &str) -> Result<Self, FractalError> {
+        let read_error = |e: std::io::Error| FractalError::io("read", path, e);
+        let mut reader = BufReader::new(std::fs::File::open(path).map_err(read_error)?);
+        let mut start = [0u8; 16];
+        reader.read_exact(&mut start).map_err(read_error)?;
+        if &start[..8] != MAGIC {
+            return Err(FractalError::InvalidInput(format!("'{}' is not an orbit stream", path)));
+        }
+        let version = u32::from_le_bytes(start[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(FractalError::InvalidInput(format!(
+                "'{}' is an orbit stream of version {}, this build reads version {}",
+                path, version, VERSION
+            )));
+        }
+        let header_len = u32::from_le_bytes(start[12..16].try_into().unwrap());
+        if header_len > MAX_HEADER_LEN {
+            return Err(FractalError::InvalidInput(format!(
+                "Invalid orbit stream header in '{}': {} bytes long, at most {} are read",
+                path, header_len, MAX_HEADER_LEN
+            )));
+        }
+        let mut header = vec![0u8; header_len as usize];
+        reader.read_exact(&mut header).map_err(read_error)?;
+        let header = serde_json::from_slice(&header)
+            .map_err(|e| FractalError::InvalidInput(format!("Invalid orbit stream header in '{}': {}", path, e)))?;
+        Ok(Self { path: path.to_string(), header, data_offset: 16 + header_len as u64 })
+    }
+
+    /// The stream's region and sampling parameters
+    pub fn header(&self) -> &OrbitStreamHeader {
+        &self.header
+    }
+
+    /// Bin the recorded points into red, green and blue histograms
+    ///
+    /// Each point is taken at the center of its quantization cell and counted in the pixel it
+    /// falls in, as the sampler counts orbit points in `buddhabrot_densities`, and the
+    /// histograms are denoised as the options ask.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Size, view and rotation of the histograms
+    ///
+    /// # Returns
+    ///
+    /// The histograms, or an error if the stream can't be read or is truncated
+    pub fn rebin(&self, options: &RebinOptions) -> Result<[DensityGrid; 3], FractalError> {
+        let RebinOptions { width, height, bounds: [x_min, x_max, y_min, y_max], rotation, .. } = *options;
+        if width == 0 || height == 0 || !(x_max > x_min && y_max > y_min) {
+            return Err(FractalError::InvalidInput(format!("Invalid rebinning view {}x{} over {:?}", width, height, options.bounds)));
+        }
+        let [region_x_min, region_x_max, region_y_min, region_y_max] = self.header.region;
+        let cell_size = [(region_x_max - region_x_min) / CELLS, (region_y_max - region_y_min) / CELLS];
+        let center = Complex::new((x_min + x_max) / 2.0, (y_min + y_max) / 2.0);
+        // Turning the view counter-clockwise turns the points the other way relative to it
+        let turn = Complex::from_polar(1.0, -rotation.to_radians());
+        let scale = [width as f64 / (x_max - x_min), height as f64 / (y_max - y_min)];
+
+        let read_error = |e: std::io::Error| FractalError::io("read", &self.path, e);
+        let mut file = std::fs::File::open(&self.path).map_err(read_error)?;
+        std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(self.data_offset)).map_err(read_error)?;
+        let mut reader = BufReader::new(file);
+        let mut histograms = [(); 3].map(|_| DensityGrid::new(width as usize, height as usize));
+        let truncated = |e: &dyn std::fmt::Display| FractalError::InvalidInput(format!("Truncated orbit stream '{}': {}", self.path, e));
+        let mut block_start = Vec::with_capacity(5);
+        let mut cells = Vec::new();
+        loop {
+            // The stream ends between blocks; an end within a block header is a truncated file
+            block_start.clear();
+            (&mut reader).take(5).read_to_end(&mut block_start).map_err(read_error)?;
+            match block_start.len() {
+                0 => break,
+                5 => {}
+                length => return Err(truncated(&format!("the last block header has {} of 5 bytes", length))),
+            }
+            let channel = block_start[0] as usize;
+            let count = u32::from_le_bytes(block_start[1..5].try_into().unwrap()) as usize;
+            if channel > 2 {
+                return Err(FractalError::InvalidInput(format!("'{}' holds a block of channel {}", self.path, channel)));
+            }
+            cells.resize(count * 4, 0);
+            reader.read_exact(&mut cells).map_err(|e| truncated(&e))?;
+            let histogram = &mut histograms[channel];
+            for point in cells.chunks_exact(4) {
+                let column = u16::from_le_bytes([point[0], point[1]]) as f64 + 0.5;
+                let row = u16::from_le_bytes([point[2], point[3]]) as f64 + 0.5;
+                let z = Complex::new(region_x_min + column * cell_size[0], region_y_min + row * cell_size[1]);
+                let z = center + (z - center) * turn;
+                histogram.splat((z.re - x_min) * scale[0], (z.im - y_min) * scale[1], 1.0);
+            }
+        }
+        let [red, green, blue] = &mut histograms;
+        denoise_channels(options.denoise.as_ref(), [red, green, blue]);
+        Ok(histograms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buddhabrot_densities, BuddhabrotChannel, BuddhabrotChannels};
+
+    #[test]
+    fn test_rebinned_stream_matches_direct_render() {
+        let channel = |min_iter, max_iter| BuddhabrotChannel { min_iter, max_iter, samples: 20_000 };
+        let channels = BuddhabrotChannels { red: channel(5, 50), green: channel(10, 100), blue: channel(2, 20) };
+        let bounds = [-2.0, 2.0, -2.0, 2.0];
+        let params = BuddhabrotParams::new(bounds, 64, 64, 2, 100, 20_000, 2.0, "z^2 + c".to_string(), channels);
+        let path = std::env::temp_dir().join(format!("ftk_orbits_{}.bin", std::process::id())).to_string_lossy().into_owned();
+        let counts = record_orbit_stream(&params, bounds, &path).unwrap();
+
+        // The pixel edges of the recorded region at 64x64 are cell edges, so no point moves pixel
+        let stream = OrbitStream::open(&path).unwrap();
+        let options = RebinOptions { width: 64, height: 64, bounds, rotation: 0.0, denoise: None };
+        let rebinned = stream.rebin(&options).unwrap();
+        let direct = buddhabrot_densities(&params);
+        for ((rebinned, direct), count) in rebinned.iter().zip(&direct).zip(counts) {
+            let total: f64 = rebinned.as_slice().iter().sum();
+            assert_eq!(total as u64, count);
+            assert_eq!(rebinned.as_slice(), direct.as_slice());
+        }
+
+        // A half turn about the center of a symmetric view mirrors the image through it
+        let turned = stream.rebin(&RebinOptions { rotation: 180.0, ..options.clone() }).unwrap();
+        assert!((turned[0][(10, 20)] - rebinned[0][(53, 43)]).abs() <= rebinned[0][(53, 43)] * 0.1 + 2.0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_streams_are_rejected() {
+        let channel = BuddhabrotChannel { min_iter: 2, max_iter: 20, samples: 200 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel };
+        let bounds = [-2.0, 2.0, -2.0, 2.0];
+        let params = BuddhabrotParams::new(bounds, 16, 16, 1, 20, 200, 2.0, "z^2 + c".to_string(), channels);
+        let path = std::env::temp_dir().join(format!("ftk_orbits_corrupt_{}.bin", std::process::id())).to_string_lossy().into_owned();
+        record_orbit_stream(&params, bounds, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let data_offset = OrbitStream::open(&path).unwrap().data_offset as usize;
+        let options = RebinOptions { width: 16, height: 16, bounds, rotation: 0.0, denoise: None };
+
+        // A file ending partway into a block header is truncated, not a clean end of stream
+        std::fs::write(&path, &bytes[..data_offset + 3]).unwrap();
+        let err = OrbitStream::open(&path).unwrap().rebin(&options).unwrap_err();
+        assert!(matches!(&err, FractalError::InvalidInput(message) if message.contains("Truncated")), "{:?}", err);
+
+        // A header length beyond the cap is rejected before anything is allocated for it
+        let mut oversized = bytes[..12].to_vec();
+        oversized.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &oversized).unwrap();
+        assert!(matches!(OrbitStream::open(&path), Err(FractalError::InvalidInput(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
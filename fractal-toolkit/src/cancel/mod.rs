@@ -0,0 +1,82 @@
+//! Cancelling renders from another thread
+//!
+//! Applications hosting the renderers, such as GUIs and servers, need to stop a render that is
+//! taking too long or is no longer wanted. A `CancelToken` is a flag shared between the host and
+//! the render: the parameter structs carry one (`FractalParams::cancel`,
+//! `BuddhabrotParams::cancel`, `BuddhabrotJuliaParams::cancel`, `NewtonParams::cancel`,
+//! `RaymarchParams::cancel` and `IfsParams::cancel`), the host keeps a clone and calls `cancel`,
+//! and the render loops check it between pixels, rows, samples, chains, tiles, GPU dispatches
+//! and frames.
+//!
+//! What a cancelled render returns depends on the entry point:
+//!
+//! - Escape-time images, including quadtree, quaternion, Newton and ray-marched ones, come back
+//!   partial: the pixels iterated before the cancel are colored as usual (without anti-aliasing
+//!   or post-processing) and the rest are transparent, with `IterationResult::UNRENDERED` as
+//!   their results where there are any.
+//! - Buddhabrot and IFS images, including the importance-sampled and Metropolis ones, are drawn
+//!   from the samples taken before the cancel, like a render with fewer samples.
+//! - Entry points that return a `Result`, such as tiled renders, animations, GPU escape-time
+//!   renders, `BuddhabrotAccumulator::add_samples` and `iterate_viewport`, fail with
+//!   `FractalError::Cancelled` (or its message).
+//!
+//! Whichever it is, `is_cancelled` on the token tells the host afterwards that the output is
+//! incomplete. Tokens are not serialized: parameters read from a file start uncancelled.
+
+use crate::FractalError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that cancels the renders whose parameters carry it, shared by its clones
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// A token that hasn't been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the renders using this token or any of its clones
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail with `FractalError::Cancelled` if the token has been cancelled
+    pub fn check(&self) -> Result<(), FractalError> {
+        match self.is_cancelled() {
+            true => Err(FractalError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_escape_time_image_and_results, FractalParams, IterationResult};
+
+    #[test]
+    fn test_cancelled_render_is_partial() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 100, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        let host = params.cancel.clone();
+        assert!(host.check().is_ok());
+        host.cancel();
+        assert!(params.cancel.is_cancelled());
+        assert_eq!(params.cancel.check(), Err(FractalError::Cancelled));
+
+        // Cancelled before it started, no pixel is iterated
+        let (image, results) = generate_escape_time_image_and_results(8, 6, &params, false, None);
+        assert!(results.iter().all(|result| *result == IterationResult::UNRENDERED));
+        assert!(image.pixels().all(|pixel| pixel.0[3] == 0));
+
+        // Tokens aren't serialized, so parameters read back start afresh
+        let copy: FractalParams = serde_json::from_str(&serde_json::to_string(&params).unwrap()).unwrap();
+        assert!(!copy.cancel.is_cancelled());
+    }
+}
@@ -0,0 +1,157 @@
+//! What this build of the library can do
+//!
+//! Renders are often prepared on one machine and run on another, where a missing cargo feature
+//! or an older version would only show up hours later as an error or a silently different image.
+//! `capabilities()` reports the version, the optional features compiled in and the names the
+//! parsers accept, and `Capabilities::check` compares them with the `Requirements` a job file
+//! states, so a worker can refuse a job before starting it.
+
+use crate::buddhabrot::OrbitMode;
+use crate::{FractalKind, MathEvaluator, Precision};
+use serde::{Deserialize, Serialize};
+
+/// Optional features compiled into the library
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeatureSet {
+    /// wgpu compute backend (`gpu` cargo feature)
+    pub gpu: bool,
+    /// Double-double iteration tier (`double-double` cargo feature)
+    pub double_double: bool,
+    /// Built for WebAssembly
+    pub wasm: bool,
+    /// Arbitrary-precision iteration; not available in this version
+    pub big_float: bool,
+}
+
+/// Version, features and supported names of this build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Crate version, e.g. `0.1.0`
+    pub version: String,
+    /// Optional features
+    pub features: FeatureSet,
+    /// Iteration precisions accepted by `Precision::parse`
+    pub precisions: Vec<String>,
+    /// Built-in fractal kinds accepted by `FractalKind::parse`
+    pub kinds: Vec<String>,
+    /// Buddhabrot orbit modes accepted by `OrbitMode::parse`
+    pub orbit_modes: Vec<String>,
+    /// Functions formulas can call
+    pub functions: Vec<String>,
+    /// Binary operators formulas can use
+    pub operators: Vec<String>,
+}
+
+/// What a job needs from the library; every field is optional in a job file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Requirements {
+    /// Lowest acceptable version, e.g. `0.1.0`
+    pub min_version: Option<String>,
+    /// Features that must be present, by cargo feature name: `gpu`, `double-double`, `wasm`
+    /// or `big-float`
+    pub features: Vec<String>,
+    /// Precisions, kinds, orbit modes, functions and operators the job uses
+    pub names: Vec<String>,
+}
+
+/// Report what this build of the library supports
+pub fn capabilities() -> Capabilities {
+    let parses = |names: &[&str], parse: &dyn Fn(&str) -> bool| {
+        names.iter().filter(|name| parse(name)).map(|name| name.to_string()).collect()
+    };
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        features: FeatureSet {
+            gpu: cfg!(feature = "gpu"),
+            double_double: cfg!(feature = "double-double"),
+            wasm: cfg!(target_arch = "wasm32"),
+            big_float: false,
+        },
+        precisions: parses(&["f32", "f64", "dd"], &|name| Precision::parse(name).is_ok()),
+        kinds: parses(
+            &["formula", "burning-ship", "perpendicular", "celtic", "buffalo", "lambda"],
+            &|name| FractalKind::parse(name).is_ok(),
+        ),
+        orbit_modes: parses(&["escaping", "bounded", "combined"], &|name| OrbitMode::parse(name).is_ok()),
+        functions: MathEvaluator::supported_functions().iter().map(|f| f.name.to_string()).collect(),
+        operators: MathEvaluator::supported_operators().iter().map(|op| op.symbol.to_string()).collect(),
+    }
+}
+
+/// Numeric components of a version string; missing or non-numeric components count as 0
+fn version_parts(version: &str) -> [u64; 3] {
+    let mut parts = version.trim().trim_start_matches('v').split('.').map(|part| {
+        part.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().unwrap_or(0)
+    });
+    [parts.next().unwrap_or(0), parts.next().unwrap_or(0), parts.next().unwrap_or(0)]
+}
+
+impl Capabilities {
+    /// Whether a cargo feature name is compiled in
+    pub fn has_feature(&self, feature: &str) -> bool {
+        match feature.trim() {
+            "gpu" => self.features.gpu,
+            "double-double" => self.features.double_double,
+            "wasm" => self.features.wasm,
+            "big-float" => self.features.big_float,
+            _ => false,
+        }
+    }
+
+    /// Check a job's requirements
+    ///
+    /// # Returns
+    ///
+    /// `Ok`, or an error listing every requirement this build doesn't meet
+    pub fn check(&self, requirements: &Requirements) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if let Some(min_version) = &requirements.min_version {
+            if version_parts(&self.version) < version_parts(min_version) {
+                problems.push(format!("version {} is older than the required {}", self.version, min_version));
+            }
+        }
+        for feature in &requirements.features {
+            if !self.has_feature(feature) {
+                problems.push(format!("feature '{}' is not available", feature));
+            }
+        }
+        let known = [&self.precisions, &self.kinds, &self.orbit_modes, &self.functions, &self.operators];
+        for name in &requirements.names {
+            if !known.iter().any(|names| names.contains(name)) {
+                problems.push(format!("'{}' is not supported", name));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("This build of fractal-toolkit {} can't run the job: {}", self.version, problems.join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_check_requirements() {
+        let report = capabilities();
+        assert!(report.functions.iter().any(|name| name == "sin"));
+        assert!(report.kinds.iter().any(|name| name == "burning-ship"));
+        assert_eq!(report.precisions.contains(&"dd".to_string()), report.features.double_double);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<Capabilities>(&json).unwrap(), report);
+
+        let job: Requirements = serde_json::from_str(r#"{"min_version": "0.0.9", "names": ["sin", "^"]}"#).unwrap();
+        assert_eq!(report.check(&job), Ok(()));
+        let job = Requirements {
+            min_version: Some("99.0".to_string()),
+            features: vec!["big-float".to_string()],
+            names: vec!["frobnicate".to_string()],
+        };
+        let error = report.check(&job).unwrap_err();
+        assert!(error.contains("99.0") && error.contains("big-float") && error.contains("frobnicate"), "{}", error);
+    }
+}
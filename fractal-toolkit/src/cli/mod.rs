@@ -0,0 +1,339 @@
+//! Shared steps of the escape-time executables
+//!
+//! `ftk-mandel` and `ftk-julia` offer the same extra outputs (animations, tiled renders,
+//! breadcrumb strips, sonification, annotations and GPU rendering) and differ only in whether
+//! they iterate the Mandelbrot or the Julia set of the formula. These functions take that choice
+//! as `julia`, like `generate_escape_time_image_and_results`, so both executables run the same
+//! code. They print progress as the executables do and return errors for the caller to report.
+
+use crate::animation::{
+    parse_keyframes, render_breadcrumb, render_timeline, render_zoom_animation, BreadcrumbOptions, FrameSequenceWriter,
+    SequenceFormat, Timeline,
+};
+use crate::annotations::{draw_annotations, load_annotations, save_annotations, Annotation};
+use crate::output::{save_image_atomic, OutputOptions};
+use crate::sonify::{orbit_tones, scanline_tones, write_tones, SonifyOptions};
+use crate::tiled::{single_image_limit, TiledRender};
+use crate::{julia_result_compiled, mandelbrot_result_compiled, ColorStop, CompiledFormula, Easing, FractalParams, IterationResult, RenderedImage};
+use image::{Rgba, RgbaImage};
+use num_complex::Complex;
+
+/// Where and how the frames of an animation are written
+#[derive(Debug, Clone)]
+pub struct AnimationOutput {
+    /// Output path; the extension picks the format (see `SequenceFormat::from_path`)
+    pub path: String,
+    /// Number of frames to render
+    pub frames: u32,
+    /// Frames per second of video formats
+    pub fps: u32,
+    /// Write each frame's parameters beside it (see `FrameSequenceWriter::with_metadata`)
+    pub metadata: bool,
+    /// How the frame images are written
+    pub options: OutputOptions,
+}
+
+impl AnimationOutput {
+    /// Open the frame writer for images of the given size
+    fn writer(&self, width: u32, height: u32) -> Result<FrameSequenceWriter, String> {
+        let format = SequenceFormat::from_path(&self.path)?;
+        Ok(FrameSequenceWriter::create(&self.path, format, width, height, self.fps)?
+            .with_metadata(self.metadata)?
+            .with_output_options(self.options.clone()))
+    }
+}
+
+/// Name of the set, for messages
+fn set_name(julia: bool) -> &'static str {
+    if julia {
+        "Julia"
+    } else {
+        "Mandelbrot"
+    }
+}
+
+/// The per-point iteration of the Mandelbrot or Julia set of a compiled formula
+fn iteration(formula: &CompiledFormula, julia: bool) -> impl Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy + '_ {
+    move |point, params| {
+        if julia {
+            julia_result_compiled(point, params, formula)
+        } else {
+            mandelbrot_result_compiled(point, params, formula)
+        }
+    }
+}
+
+/// Render a timeline animation
+///
+/// # Arguments
+///
+/// * `timeline` - Path of a timeline file, or the text of the timeline
+/// * `output` - Where the frames go
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `params` - Parameters the timeline's tracks start from
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+pub fn render_timeline_frames(
+    timeline: &str,
+    output: &AnimationOutput,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    color_palette: Option<&[ColorStop]>,
+) -> Result<(), String> {
+    let timeline = match std::fs::read_to_string(timeline) {
+        Ok(text) => Timeline::parse(&text)?,
+        Err(_) => Timeline::parse(timeline)?,
+    };
+    println!("Rendering {} frame {} timeline of {} tracks to {}", output.frames, set_name(julia), timeline.tracks.len(), output.path);
+
+    let mut writer = output.writer(width, height)?;
+    let formula = CompiledFormula::new(&params.formula);
+    render_timeline(width, height, params, &timeline, output.frames, iteration(&formula, julia), color_palette, &mut writer)?;
+    let frames = writer.finish()?;
+    println!("Timeline animation ({} frames) saved to {}", frames, output.path);
+    Ok(())
+}
+
+/// Render a zoom animation from the view of `params` through keyframe bounds
+///
+/// # Arguments
+///
+/// * `keyframes` - Keyframe bounds, as parsed by `parse_keyframes`
+/// * `easing` - Name of the easing between keyframes (see `Easing::parse`)
+/// * `output` - Where the frames go
+/// * `width` - Frame width in pixels
+/// * `height` - Frame height in pixels
+/// * `params` - Parameters of the first frame
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+#[allow(clippy::too_many_arguments)]
+pub fn render_zoom(
+    keyframes: &str,
+    easing: &str,
+    output: &AnimationOutput,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    color_palette: Option<&[ColorStop]>,
+) -> Result<(), String> {
+    let mut keyframes = parse_keyframes(keyframes)?;
+    keyframes.insert(0, params.bounds);
+    let easing = Easing::parse(easing)?;
+    println!("Rendering {} frame {} zoom through {} keyframes to {}", output.frames, set_name(julia), keyframes.len(), output.path);
+
+    let mut writer = output.writer(width, height)?;
+    let formula = CompiledFormula::new(&params.formula);
+    render_zoom_animation(width, height, params, &keyframes, output.frames, easing, iteration(&formula, julia), color_palette, &mut writer)?;
+    let frames = writer.finish()?;
+    println!("Zoom animation ({} frames) saved to {}", frames, output.path);
+    Ok(())
+}
+
+/// Save a breadcrumb strip of the view of `params` and the zoom keyframes
+///
+/// # Arguments
+///
+/// * `path` - Image path
+/// * `keyframes` - The zoom keyframes (`--zoom`), which the strip needs
+/// * `thumbnail_height` - Height of each view in the strip, in pixels
+/// * `params` - Parameters of the first view
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+/// * `options` - How the image is written
+pub fn save_breadcrumb(
+    path: &str,
+    keyframes: Option<&str>,
+    thumbnail_height: u32,
+    params: &FractalParams,
+    julia: bool,
+    color_palette: Option<&[ColorStop]>,
+    options: &OutputOptions,
+) -> Result<(), String> {
+    let keyframes = keyframes.ok_or("--breadcrumb needs the --zoom keyframes")?;
+    let mut views = parse_keyframes(keyframes)?;
+    views.insert(0, params.bounds);
+    println!("Rendering breadcrumb strip of {} views", views.len());
+
+    let formula = CompiledFormula::new(&params.formula);
+    let breadcrumb = BreadcrumbOptions { thumbnail_height, ..BreadcrumbOptions::default() };
+    let strip = render_breadcrumb(&views, params, iteration(&formula, julia), color_palette, &breadcrumb)?;
+    save_image_atomic(&strip, path, options)?;
+    println!("Breadcrumb strip saved to {}", path);
+    Ok(())
+}
+
+/// Write a WAV sonification of the orbit of one point, or of one pixel row of the image
+///
+/// # Arguments
+///
+/// * `path` - WAV path
+/// * `point` - Point whose orbit is played: `c` of the Mandelbrot set, or the starting `z` of
+///   the Julia set; `None` plays a row instead
+/// * `row` - Pixel row to play, by default the middle one
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters
+/// * `julia` - Iterate the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `options` - Tone length and synthesis options
+#[allow(clippy::too_many_arguments)]
+pub fn write_sonification(
+    path: &str,
+    point: Option<Complex<f64>>,
+    row: Option<u32>,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    options: &SonifyOptions,
+) -> Result<(), String> {
+    let tones = match point {
+        Some(point) => {
+            println!("Sonifying the orbit of {}", point);
+            let (start, c) = if julia { (point, params.spawn) } else { (params.kind.critical_point(), point) };
+            orbit_tones(start, c, params, options)
+        }
+        None => {
+            let row = row.unwrap_or(height / 2);
+            if row >= height {
+                return Err(format!("--sonify-row {} is outside the image of {} rows", row, height));
+            }
+            println!("Sonifying pixel row {}", row);
+            scanline_tones(row, width, height, params, julia, options)
+        }
+    };
+    let seconds = write_tones(path, &tones, options)?;
+    println!("Sonification ({} tones, {:.1}s) saved to {}", tones.len(), seconds, path);
+    Ok(())
+}
+
+/// Draw annotations over an image, and save them as GeoJSON
+///
+/// # Arguments
+///
+/// * `image` - The render
+/// * `annotations` - GeoJSON file of annotations to draw
+/// * `annotate` - More annotations, in the notation of `Annotation::parse`
+/// * `annotations_out` - GeoJSON file to save all of the annotations to
+/// * `bounds` - Bounds the image was rendered with
+pub fn annotate_image(
+    image: &mut RgbaImage,
+    annotations: Option<&str>,
+    annotate: &[String],
+    annotations_out: Option<&str>,
+    bounds: [f64; 4],
+) -> Result<(), String> {
+    let mut annotations = match annotations {
+        Some(path) => load_annotations(path)?,
+        None => Vec::new(),
+    };
+    for text in annotate {
+        annotations.push(Annotation::parse(text)?);
+    }
+    if let Some(path) = annotations_out {
+        save_annotations(path, &annotations)?;
+        println!("{} annotations saved to {}", annotations.len(), path);
+    }
+    for (label, (x, y)) in draw_annotations(image, bounds, &annotations, Rgba([255, 255, 255, 255])) {
+        println!("  Annotation '{}' at pixel ({}, {})", label, x, y);
+    }
+    Ok(())
+}
+
+/// Render the pending tiles of a tiled render, then stitch the image if no tiles are left, or
+/// save them as separate images if it can't be saved as one file
+///
+/// # Arguments
+///
+/// * `directory` - Tile directory (see `TiledRender`)
+/// * `worker` - `k/n` to render only this process's share of the tiles
+/// * `output` - Path of the stitched image
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `tile_size` - Edge length of the tiles in pixels
+/// * `params` - Fractal parameters
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+/// * `options` - How separately saved tiles are written, and whether outputs get checksum files
+#[allow(clippy::too_many_arguments)]
+pub fn render_tiled(
+    directory: &str,
+    worker: Option<&str>,
+    output: &str,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    params: &FractalParams,
+    julia: bool,
+    color_palette: Option<&[ColorStop]>,
+    options: &OutputOptions,
+) -> Result<(), String> {
+    let worker = match worker {
+        Some(text) => {
+            let parsed = text.split_once('/').and_then(|(k, n)| Some((k.trim().parse::<usize>().ok()?, n.trim().parse::<usize>().ok()?)));
+            Some(parsed.ok_or_else(|| format!("tile-worker must be k/n, found '{}'", text))?)
+        }
+        None => None,
+    };
+    let render = TiledRender::create(directory, &set_name(julia).to_lowercase(), width, height, tile_size, params)?;
+    let formula = CompiledFormula::new(&params.formula);
+    render.render_pending(iteration(&formula, julia), worker)?;
+
+    let remaining = render.pending_tiles().len();
+    if remaining > 0 {
+        println!("{} of {} tiles still to render; run again with the same options to continue", remaining, render.tiles().len());
+        return Ok(());
+    }
+    if single_image_limit(width, height, output).is_some() {
+        let manifest = render.export_tiles(output, color_palette, options)?;
+        println!("Tiles saved as images beside {}; their layout is in {}", output, manifest.display());
+        return Ok(());
+    }
+    render.stitch_png(output, color_palette, options.checksum)?;
+    println!("Tiles stitched into {}", output);
+    Ok(())
+}
+
+/// Render on the GPU, keeping the iteration results for the other outputs, or return `None`
+/// with a message so the caller renders on the CPU
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+#[cfg(feature = "gpu")]
+pub fn render_on_gpu(width: u32, height: u32, params: &FractalParams, julia: bool, color_palette: Option<&Vec<ColorStop>>) -> Option<RenderedImage> {
+    let result = crate::gpu::GpuRenderer::new().and_then(|renderer| renderer.render(width, height, params, julia, color_palette));
+    result.map_err(|e| eprintln!("GPU rendering unavailable ({}), rendering on the CPU", e)).ok()
+}
+
+/// Render on the GPU, which needs the `gpu` feature: this build prints a message and returns
+/// `None` so the caller renders on the CPU
+#[cfg(not(feature = "gpu"))]
+pub fn render_on_gpu(_width: u32, _height: u32, _params: &FractalParams, _julia: bool, _color_palette: Option<&Vec<ColorStop>>) -> Option<RenderedImage> {
+    eprintln!("Built without the gpu feature, rendering on the CPU");
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sets_iterate_their_own_formula() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 50, [0.3, 0.5], 2.0, "z^2 + c".to_string());
+        let formula = CompiledFormula::new(&params.formula);
+        let point = Complex::new(-0.1, 0.2);
+        assert_eq!(iteration(&formula, false)(point, &params).iterations, mandelbrot_result_compiled(point, &params, &formula).iterations);
+        assert_eq!(iteration(&formula, true)(point, &params).iterations, julia_result_compiled(point, &params, &formula).iterations);
+
+        let row = write_sonification("unused.wav", None, Some(8), 8, 8, &params, true, &SonifyOptions::default());
+        assert!(row.unwrap_err().contains("outside the image"));
+    }
+}
@@ -0,0 +1,383 @@
+//! Layered coloring: several coloring algorithms composited into one image
+//!
+//! A render is normally colored by a single algorithm, the escape time. A [`ColoringPipeline`]
+//! instead colors every pixel once per layer and composites the layers in order, each with a
+//! blend mode and an opacity, the way an image editor stacks layers:
+//!
+//! - `EscapeTime` colors as without a pipeline, following the smooth, histogram and interior
+//!   coloring options of the parameters
+//! - `OrbitTrap` colors by how close the orbit came to a point, a cross or a circle
+//! - `DistanceEstimate` colors escaped points by their distance to the set relative to the pixel
+//!   size, bright filaments fading to black at the boundary; it leaves bounded points untouched
+//! - `Angle` colors by the direction of the last value of z
+//!
+//! Layers other than the escape time map their value in [0, 1] through their own palette, the
+//! render's palette, or grayscale when neither is given. Blending starts from opaque black.
+//!
+//! Renders pick up a pipeline from `FractalParams::coloring_pipeline` in `generate_fractal_image`
+//! and its variants. Orbit traps follow `params.kind` and the custom i²; distance estimates
+//! iterate `params.formula` in the standard complex numbers.
+//!
+//! On the command line a pipeline is written as `source:key=value,...` layers separated by `;`,
+//! like post effects, e.g. `escape;trap:shape=cross,scale=0.5,blend=multiply;angle:blend=overlay,opacity=0.4`.
+
+use crate::algebra::NumberSystem;
+use crate::analysis::{julia_derivative, mandelbrot_derivative};
+use crate::formula::compiled::compiled_for;
+use crate::iteration::{escape_degree, FractalKind, IterationResult};
+use crate::{color_for_result_equalized, interpolate_color_from_palette, parse_color_palette, ColorStop, CompiledFormula, FractalParams, IterationHistogram};
+use image::Rgba;
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Shape an orbit trap measures the distance of the orbit to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrapShape {
+    /// The center
+    Point,
+    /// The horizontal and vertical lines through the center
+    Cross,
+    /// The circle of `radius` around the center
+    Circle,
+}
+
+/// What a layer colors pixels by
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum LayerSource {
+    /// The escape-time coloring of the parameters
+    EscapeTime,
+    /// The smallest distance from the orbit to a shape, divided by `scale` and capped at 1
+    OrbitTrap {
+        /// The shape
+        shape: TrapShape,
+        /// Center of the shape
+        center: Complex<f64>,
+        /// Radius of a circle trap
+        radius: f64,
+        /// Distance that maps to the end of the palette
+        scale: f64,
+    },
+    /// The distance estimate relative to the pixel size (see `analysis::distance`)
+    DistanceEstimate,
+    /// The argument of the final z, once around the palette
+    Angle,
+}
+
+/// How a layer combines with the layers below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    /// The layer covers the layers below
+    #[default]
+    Alpha,
+    /// Darken: the product of the layers
+    Multiply,
+    /// Lighten: the inverted product of the inverted layers
+    Screen,
+    /// Multiply where the layers below are dark and screen where they are light
+    Overlay,
+}
+
+impl BlendMode {
+    /// Parse a mode name: `alpha` (or `normal`), `multiply`, `screen` or `overlay`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "alpha" | "normal" => Ok(BlendMode::Alpha),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+            "overlay" => Ok(BlendMode::Overlay),
+            other => Err(format!("Unknown blend mode '{}' (expected alpha, multiply, screen or overlay)", other)),
+        }
+    }
+
+    /// Blend one channel of a layer onto the one below, both from 0 to 1
+    pub fn blend(&self, below: f64, layer: f64) -> f64 {
+        match self {
+            BlendMode::Alpha => layer,
+            BlendMode::Multiply => below * layer,
+            BlendMode::Screen => 1.0 - (1.0 - below) * (1.0 - layer),
+            BlendMode::Overlay if below < 0.5 => 2.0 * below * layer,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - below) * (1.0 - layer),
+        }
+    }
+}
+
+/// One layer of a pipeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColoringLayer {
+    /// What the layer colors by
+    #[serde(flatten)]
+    pub source: LayerSource,
+    /// How it combines with the layers below
+    #[serde(default)]
+    pub blend: BlendMode,
+    /// Weight of the blended layer, from 0 (invisible) to 1
+    #[serde(default = "default_opacity")]
+    pub opacity: f64,
+    /// Palette of the layer; `None` uses the render's palette
+    #[serde(default)]
+    pub palette: Option<Vec<ColorStop>>,
+}
+
+fn default_opacity() -> f64 {
+    1.0
+}
+
+impl ColoringLayer {
+    /// Parse one layer written as `source` or `source:key=value,...`
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - e.g. `escape`, `trap:shape=circle,radius=0.5,scale=0.2,blend=screen`,
+    ///   `distance:opacity=0.6` or `angle:blend=overlay,palette=twilight`
+    ///
+    /// # Returns
+    ///
+    /// The layer with defaults for omitted settings, or an error naming the unknown source or key
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, arguments) = spec.trim().split_once(':').unwrap_or((spec.trim(), ""));
+        let source = match name.to_lowercase().replace('_', "-").as_str() {
+            "escape" | "escape-time" | "iterations" => LayerSource::EscapeTime,
+            "trap" | "orbit-trap" => LayerSource::OrbitTrap { shape: TrapShape::Point, center: Complex::new(0.0, 0.0), radius: 1.0, scale: 1.0 },
+            "distance" | "de" | "distance-estimate" => LayerSource::DistanceEstimate,
+            "angle" => LayerSource::Angle,
+            _ => return Err(format!("Unknown coloring layer: '{}' (expected escape, trap, distance or angle)", name)),
+        };
+        let mut layer = ColoringLayer { source, blend: BlendMode::Alpha, opacity: 1.0, palette: None };
+
+        for pair in arguments.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value in coloring layer '{}', found '{}'", name, pair))?;
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            let number = || value.parse::<f64>().map_err(|_| format!("Invalid value for {}: '{}'", key, value));
+            match (&mut layer.source, key.as_str()) {
+                (_, "blend") => layer.blend = BlendMode::parse(value)?,
+                (_, "opacity") => layer.opacity = number()?.clamp(0.0, 1.0),
+                (_, "palette") => layer.palette = Some(parse_color_palette(value)?),
+                (LayerSource::OrbitTrap { shape, .. }, "shape") => {
+                    *shape = match value.to_lowercase().as_str() {
+                        "point" => TrapShape::Point,
+                        "cross" => TrapShape::Cross,
+                        "circle" | "ring" => TrapShape::Circle,
+                        other => return Err(format!("Unknown trap shape '{}' (expected point, cross or circle)", other)),
+                    }
+                }
+                (LayerSource::OrbitTrap { center, .. }, "x") => center.re = number()?,
+                (LayerSource::OrbitTrap { center, .. }, "y") => center.im = number()?,
+                (LayerSource::OrbitTrap { radius, .. }, "radius") => *radius = number()?,
+                (LayerSource::OrbitTrap { scale, .. }, "scale") => *scale = number()?,
+                (_, key) => return Err(format!("Unknown setting '{}' for coloring layer '{}'", key, name)),
+            }
+        }
+        Ok(layer)
+    }
+}
+
+/// Coloring layers composited in order, bottom first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ColoringPipeline {
+    /// The layers
+    pub layers: Vec<ColoringLayer>,
+    /// Whether the render is a Julia set, with pixels as starting points and `params.spawn` as c,
+    /// so the orbit-based layers retrace the same orbits
+    #[serde(default)]
+    pub julia: bool,
+}
+
+impl ColoringPipeline {
+    /// Parse a `;`-separated list of layers, see `ColoringLayer::parse`
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The layers, bottom first
+    /// * `julia` - Whether the pipeline colors a Julia set
+    pub fn parse(spec: &str, julia: bool) -> Result<Self, String> {
+        let layers = spec.split(';').filter(|layer| !layer.trim().is_empty()).map(ColoringLayer::parse).collect::<Result<Vec<_>, _>>()?;
+        if layers.is_empty() {
+            return Err("A coloring pipeline needs at least one layer".to_string());
+        }
+        Ok(Self { layers, julia })
+    }
+
+    /// Prepare the pipeline for coloring an image
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Fractal parameters of the render
+    /// * `palette` - The render's palette, for layers without their own
+    /// * `results` - Iteration results of every pixel, for histogram coloring
+    /// * `pixel_size` - Width of a pixel in the plane, which distance estimates are relative to
+    pub fn colorer<'a>(
+        &'a self,
+        params: &'a FractalParams,
+        palette: Option<&'a [ColorStop]>,
+        results: &[IterationResult],
+        pixel_size: f64,
+    ) -> PipelineColorer<'a> {
+        let histogram = params
+            .histogram_coloring
+            .then(|| IterationHistogram::from_results(results, params.max_iterations));
+        let formula = compiled_for(&params.formula);
+        PipelineColorer {
+            pipeline: self,
+            params,
+            palette,
+            degree: escape_degree(&params.formula),
+            histogram,
+            pixel_size,
+            system: NumberSystem::for_params(params.i_sqrt_value, &formula),
+            formula,
+        }
+    }
+}
+
+/// A pipeline ready to color the pixels of one image
+pub struct PipelineColorer<'a> {
+    pipeline: &'a ColoringPipeline,
+    params: &'a FractalParams,
+    palette: Option<&'a [ColorStop]>,
+    degree: f64,
+    histogram: Option<IterationHistogram>,
+    pixel_size: f64,
+    system: NumberSystem,
+    formula: Arc<CompiledFormula>,
+}
+
+impl PipelineColorer<'_> {
+    /// Color a point by compositing the layers
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - The pixel's point of the plane
+    /// * `result` - The outcome of iterating it
+    ///
+    /// # Returns
+    ///
+    /// The composited color
+    pub fn color(&self, point: Complex<f64>, result: &IterationResult) -> Rgba<u8> {
+        let needs_orbit = self.pipeline.layers.iter().any(|layer| matches!(layer.source, LayerSource::OrbitTrap { .. }));
+        let orbit = if needs_orbit { self.orbit(point) } else { Vec::new() };
+
+        let mut color = [0.0, 0.0, 0.0, 1.0];
+        for layer in &self.pipeline.layers {
+            let Some(Rgba(layer_color)) = self.layer_color(layer, point, result, &orbit) else { continue };
+            for (channel, value) in color.iter_mut().zip(layer_color) {
+                let value = value as f64 / 255.0;
+                *channel += (layer.blend.blend(*channel, value) - *channel) * layer.opacity;
+            }
+        }
+        Rgba(color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }
+
+    /// The color of one layer at a point, or `None` where the layer is transparent
+    fn layer_color(&self, layer: &ColoringLayer, point: Complex<f64>, result: &IterationResult, orbit: &[Complex<f64>]) -> Option<Rgba<u8>> {
+        let palette = layer.palette.as_deref().or(self.palette);
+        let value = match layer.source {
+            LayerSource::EscapeTime => {
+                return Some(color_for_result_equalized(result, self.degree, self.params, palette, self.histogram.as_ref()));
+            }
+            LayerSource::OrbitTrap { shape, center, radius, scale } => {
+                let distance = orbit
+                    .iter()
+                    .map(|z| {
+                        let offset = z - center;
+                        match shape {
+                            TrapShape::Point => offset.norm(),
+                            TrapShape::Cross => offset.re.abs().min(offset.im.abs()),
+                            TrapShape::Circle => (offset.norm() - radius).abs(),
+                        }
+                    })
+                    .fold(f64::INFINITY, f64::min);
+                (distance / scale.max(f64::MIN_POSITIVE)).min(1.0)
+            }
+            LayerSource::DistanceEstimate => {
+                let orbit = if self.pipeline.julia {
+                    julia_derivative(&self.formula, point, self.params.spawn, self.params)
+                } else {
+                    mandelbrot_derivative(&self.formula, point, self.params)
+                };
+                // Distances below a pixel fade to black at the boundary
+                (orbit.distance()? / self.pixel_size).clamp(0.0, 1.0).sqrt()
+            }
+            LayerSource::Angle => (result.final_z.arg() / std::f64::consts::TAU).rem_euclid(1.0),
+        };
+        Some(match palette {
+            Some(palette) => interpolate_color_from_palette(value, palette),
+            None => {
+                let gray = (value * 255.0).round() as u8;
+                Rgba([gray, gray, gray, 255])
+            }
+        })
+    }
+
+    /// The orbit of a pixel up to its escape, as the renderers iterate it
+    fn orbit(&self, point: Complex<f64>) -> Vec<Complex<f64>> {
+        let params = self.params;
+        let (mut z, c) = if self.pipeline.julia { (point, params.spawn) } else { (params.kind.critical_point(), point) };
+        self.formula.start_orbit();
+        let mut orbit = Vec::new();
+        for _ in 0..params.max_iterations {
+            z = match params.kind {
+                FractalKind::Formula => self.system.step(&self.formula, z, c),
+                kind => kind.step(z, c),
+            };
+            orbit.push(z);
+            if !z.is_finite() || z.norm_sqr() > params.bailout * params.bailout {
+                break;
+            }
+        }
+        orbit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layers_and_blend_modes() {
+        let pipeline = ColoringPipeline::parse("escape; trap:shape=cross,x=0.5,scale=0.2,blend=multiply,opacity=0.8; angle:blend=overlay", false).unwrap();
+        assert_eq!(pipeline.layers.len(), 3);
+        assert_eq!(
+            pipeline.layers[1].source,
+            LayerSource::OrbitTrap { shape: TrapShape::Cross, center: Complex::new(0.5, 0.0), radius: 1.0, scale: 0.2 }
+        );
+        assert_eq!((pipeline.layers[1].blend, pipeline.layers[1].opacity), (BlendMode::Multiply, 0.8));
+        assert!(ColoringPipeline::parse("escape;glow", false).is_err());
+        assert!(ColoringLayer::parse("angle:radius=1").is_err());
+
+        assert_eq!(BlendMode::Multiply.blend(0.5, 0.5), 0.25);
+        assert_eq!(BlendMode::Screen.blend(0.5, 0.5), 0.75);
+        assert_eq!(BlendMode::Overlay.blend(0.25, 0.5), 0.25);
+        assert_eq!(BlendMode::Overlay.blend(0.75, 0.5), 0.75);
+    }
+
+    #[test]
+    fn test_pipeline_composites_layers() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 100, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        let white = vec![ColorStop { color: [255, 255, 255], position: 0.0, easing: Default::default() }, ColorStop { color: [255, 255, 255], position: 1.0, easing: Default::default() }];
+        let escaped = IterationResult { iterations: 3, escaped: true, final_z: Complex::new(3.0, 0.0), period: None, multiplier: None };
+        let point = Complex::new(1.0, 0.0);
+
+        // A white escape-time layer multiplied by a trap at the point's own c: the orbit of 0 starts at c
+        let pipeline = ColoringPipeline {
+            layers: vec![
+                ColoringLayer { source: LayerSource::EscapeTime, blend: BlendMode::Alpha, opacity: 1.0, palette: Some(white) },
+                ColoringLayer::parse("trap:x=1,blend=multiply").unwrap(),
+            ],
+            julia: false,
+        };
+        let colorer = pipeline.colorer(&params, None, &[escaped], 0.01);
+        assert_eq!(colorer.color(point, &escaped), Rgba([0, 0, 0, 255]));
+
+        // Half an angle layer over black: a final z just above the negative real axis is mid-gray
+        let pipeline = ColoringPipeline::parse("angle:opacity=0.5", false).unwrap();
+        let colorer = pipeline.colorer(&params, None, &[escaped], 0.01);
+        let result = IterationResult { final_z: Complex::new(-1.0, 1e-12), ..escaped };
+        assert_eq!(colorer.color(point, &result), Rgba([64, 64, 64, 255]));
+    }
+}
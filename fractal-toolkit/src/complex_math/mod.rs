@@ -209,8 +209,7 @@ pub fn debug_orbit_iteration(z_init: Complex<f64>, c: Complex<f64>, custom_i_squ
 
     for iter in 0..max_iter {
         // For z^2 + c with custom arithmetic
-        let z_squared = z.multiply(&z);
-        z = z_squared.add(&c_custom);
+        z = z * z + c_custom;
         
         let z_standard = z.to_standard();
         println!("Iteration {}: z = {:?}, |z| = {:.6}", iter + 1, z_standard, z_standard.norm());
@@ -0,0 +1,328 @@
+//! Dense two-dimensional grids
+//!
+//! Histograms used to be `Vec<Vec<f64>>` filled through a `HashMap` per chunk of samples: one
+//! allocation per row, a hash lookup per orbit point, and partial maps that could grow larger
+//! than the grid itself. `Grid2D` keeps the cells in one row-major buffer. Renderers that
+//! accumulate into a private grid per rayon job use `Grid2D::par_accumulate`; those whose grids
+//! are too large to copy per thread share one `AtomicGrid`.
+//!
+//! `AtomicGrid` stores each cell as the bits of an f64 and adds with a compare-and-swap loop, so
+//! weights of any magnitude keep their precision; importance weights in deep zooms can be far
+//! below any fixed-point step. Whole-number weights such as orbit counts add exactly, so their
+//! totals don't depend on the order in which threads add them; fractional weights may differ
+//! between runs in the last bits.
+//!
+//! Renderers bin points by scaling them to continuous cell coordinates, where cell `i` covers
+//! `[i, i + 1)`. Casting such a coordinate with `as usize` saturates: NaN and negative
+//! coordinates land in cell 0 and huge ones in the last representable index, so orbits leaving
+//! the view pile up along the left and top edges. `cell_index` converts with explicit bounds,
+//! and `splat` adds a weight at continuous coordinates, dropping points outside the grid.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::{AddAssign, Index, IndexMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A dense width x height grid stored row by row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grid2D<T> {
+    width: usize,
+    height: usize,
+    data: Vec<T>,
+}
+
+/// A histogram of (weighted) hit counts
+pub type DensityGrid = Grid2D<f64>;
+
+/// The cell of a continuous coordinate along an axis of `cells` cells
+///
+/// # Returns
+///
+/// `floor(coordinate)`, or `None` if the coordinate is NaN or outside `[0, cells)`
+pub fn cell_index(coordinate: f64, cells: usize) -> Option<usize> {
+    // Also guards against `cells as f64` rounding up for counts beyond 2^53
+    (coordinate >= 0.0 && coordinate < cells as f64).then(|| (coordinate as usize).min(cells - 1))
+}
+
+impl<T: Clone + Default> Grid2D<T> {
+    /// A grid with every cell `T::default()`
+    pub fn new(width: usize, height: usize) -> Self {
+        Self::filled(width, height, T::default())
+    }
+}
+
+impl<T: Clone> Grid2D<T> {
+    /// A grid with every cell `value`
+    pub fn filled(width: usize, height: usize, value: T) -> Self {
+        Self { width, height, data: vec![value; width * height] }
+    }
+
+    /// Copy the cells into one `Vec` per row
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        self.rows().map(<[T]>::to_vec).collect()
+    }
+}
+
+impl<T> Grid2D<T> {
+    /// Wrap a row-major buffer
+    ///
+    /// # Returns
+    ///
+    /// The grid, or an error if `data` doesn't hold exactly `width * height` cells
+    pub fn from_vec(width: usize, height: usize, data: Vec<T>) -> Result<Self, String> {
+        if data.len() != width * height {
+            return Err(format!("{} cells don't fill a {}x{} grid", data.len(), width, height));
+        }
+        Ok(Self { width, height, data })
+    }
+
+    /// Number of columns
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of rows
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The cell at column `x` and row `y`, if inside the grid
+    pub fn get(&self, x: usize, y: usize) -> Option<&T> {
+        (x < self.width && y < self.height).then(|| &self.data[y * self.width + x])
+    }
+
+    /// The cell at column `x` and row `y` for writing, if inside the grid
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut T> {
+        (x < self.width && y < self.height).then(|| &mut self.data[y * self.width + x])
+    }
+
+    /// The column and row of the cell containing continuous coordinates, if inside the grid
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        Some((cell_index(x, self.width)?, cell_index(y, self.height)?))
+    }
+
+    /// Row `y`
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    /// The rows from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> + '_ {
+        self.data.chunks(self.width.max(1))
+    }
+
+    /// The rows from top to bottom, for writing
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [T]> + '_ {
+        self.data.chunks_mut(self.width.max(1))
+    }
+
+    /// Every cell in row-major order
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Every cell in row-major order, for writing
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    /// The row-major buffer
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    /// A grid of the same size with `f` applied to every cell
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid2D<U> {
+        Grid2D { width: self.width, height: self.height, data: self.data.iter().map(f).collect() }
+    }
+}
+
+impl<T: Send + Sync> Grid2D<T> {
+    /// A grid of the same size with `f` applied to every cell in parallel
+    pub fn par_map<U: Send>(&self, f: impl Fn(&T) -> U + Sync + Send) -> Grid2D<U> {
+        Grid2D { width: self.width, height: self.height, data: self.data.par_iter().map(f).collect() }
+    }
+}
+
+impl<T: Copy + AddAssign> Grid2D<T> {
+    /// Add another grid of the same size cell by cell
+    ///
+    /// # Panics
+    ///
+    /// If the grids differ in size
+    pub fn add_grid(&mut self, other: &Grid2D<T>) {
+        assert_eq!((self.width, self.height), (other.width, other.height), "grids differ in size");
+        for (cell, &value) in self.data.iter_mut().zip(&other.data) {
+            *cell += value;
+        }
+    }
+
+    /// Add a weight to the cell containing continuous coordinates
+    ///
+    /// # Returns
+    ///
+    /// Whether the point was inside the grid; points outside it, or with NaN coordinates, are
+    /// dropped
+    pub fn splat(&mut self, x: f64, y: f64, weight: T) -> bool {
+        match self.cell_at(x, y) {
+            Some(cell) => {
+                self[cell] += weight;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Copy + Default + AddAssign + Send> Grid2D<T> {
+    /// Accumulate items in parallel, each rayon job into a grid of its own, and add the grids
+    ///
+    /// Takes memory for one grid per concurrently running job; use `AtomicGrid` when that is
+    /// too much.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Size of the grid
+    /// * `items` - Work items, e.g. chunks of samples
+    /// * `accumulate` - Adds one item's contribution to a grid
+    pub fn par_accumulate<I, F>(width: usize, height: usize, items: I, accumulate: F) -> Self
+    where
+        I: IntoParallelIterator,
+        F: Fn(&mut Self, I::Item) + Sync + Send,
+    {
+        items
+            .into_par_iter()
+            .fold(
+                || Self { width, height, data: vec![T::default(); width * height] },
+                |mut grid, item| {
+                    accumulate(&mut grid, item);
+                    grid
+                },
+            )
+            .reduce_with(|mut total, grid| {
+                for (cell, value) in total.data.iter_mut().zip(grid.data) {
+                    *cell += value;
+                }
+                total
+            })
+            .unwrap_or_else(|| Self { width, height, data: vec![T::default(); width * height] })
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid2D<T> {
+    type Output = T;
+
+    /// The cell at `(x, y)`
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the {}x{} grid", x, y, self.width, self.height);
+        &self.data[y * self.width + x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid2D<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the {}x{} grid", x, y, self.width, self.height);
+        &mut self.data[y * self.width + x]
+    }
+}
+
+/// A grid of non-negative weights that many threads add to at once
+pub struct AtomicGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<AtomicU64>,
+}
+
+impl AtomicGrid {
+    /// A grid of zeros
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: (0..width * height).map(|_| AtomicU64::new(0.0f64.to_bits())).collect() }
+    }
+
+    /// Add a weight to the cell at column `x` and row `y`
+    ///
+    /// Negative weights and cells outside the grid are ignored.
+    pub fn add(&self, x: usize, y: usize, weight: f64) {
+        if x < self.width && y < self.height && weight > 0.0 {
+            let cell = &self.cells[y * self.width + x];
+            let mut current = cell.load(Ordering::Relaxed);
+            while let Err(actual) = cell.compare_exchange_weak(
+                current,
+                (f64::from_bits(current) + weight).to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                current = actual;
+            }
+        }
+    }
+
+    /// Add a weight to the cell containing continuous coordinates, like `Grid2D::splat`
+    ///
+    /// # Returns
+    ///
+    /// Whether the point was inside the grid
+    pub fn splat(&self, x: f64, y: f64, weight: f64) -> bool {
+        match (cell_index(x, self.width), cell_index(y, self.height)) {
+            (Some(x), Some(y)) => {
+                self.add(x, y, weight);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The accumulated weights
+    pub fn into_grid(self) -> DensityGrid {
+        let data = self.cells.into_iter().map(|cell| f64::from_bits(cell.into_inner())).collect();
+        Grid2D { width: self.width, height: self.height, data }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_indexing_and_accumulation() {
+        let mut grid = Grid2D::<u32>::new(3, 2);
+        grid[(2, 1)] = 5;
+        assert_eq!(grid.get(2, 1), Some(&5));
+        assert_eq!(grid.get(3, 0), None);
+        assert_eq!(grid.to_rows(), vec![vec![0, 0, 0], vec![0, 0, 5]]);
+        assert!(Grid2D::from_vec(2, 2, vec![1, 2, 3]).is_err());
+
+        // Per-job grids and the shared atomic grid both see every item
+        let counts = Grid2D::<u32>::par_accumulate(4, 4, 0..1000usize, |grid, i| grid[(i % 4, i / 4 % 4)] += 1);
+        assert_eq!(counts.as_slice().iter().sum::<u32>(), 1000);
+        assert_eq!(counts[(0, 0)], 63);
+        let shared = AtomicGrid::new(4, 4);
+        (0..1000usize).into_par_iter().for_each(|i| shared.add(i % 4, i / 4 % 4, 0.5));
+        let shared = shared.into_grid();
+        assert_eq!(shared.as_slice().iter().sum::<f64>(), 500.0);
+        assert_eq!(shared, counts.map(|&count| count as f64 * 0.5));
+        // Weights far below 1 are kept, not rounded away
+        let tiny = AtomicGrid::new(1, 1);
+        tiny.add(0, 0, 1e-12);
+        assert_eq!(tiny.into_grid()[(0, 0)], 1e-12);
+    }
+
+    #[test]
+    fn test_splat_drops_points_outside_the_grid() {
+        assert_eq!(cell_index(2.999, 3), Some(2));
+        assert_eq!(cell_index(3.0, 3), None);
+        assert_eq!(cell_index(-0.25, 3), None);
+        assert_eq!(cell_index(f64::NAN, 3), None);
+        assert_eq!(cell_index(f64::INFINITY, 3), None);
+
+        // An orbit leaving the view on every side, as a `usize` cast would pile it up in cell 0
+        let orbit = [(1.5, 0.5), (-0.5, 0.5), (0.5, -1e300), (4.0, 1.0), (f64::NAN, 1.0), (1.0, f64::NEG_INFINITY), (0.0, 1.999)];
+        let mut grid = DensityGrid::new(3, 2);
+        let shared = AtomicGrid::new(3, 2);
+        let inside: Vec<bool> = orbit.iter().map(|&(x, y)| grid.splat(x, y, 1.0)).collect();
+        assert_eq!(inside, [true, false, false, false, false, false, true]);
+        assert!(orbit.iter().zip(&inside).all(|(&(x, y), &inside)| shared.splat(x, y, 1.0) == inside));
+        assert_eq!(grid.as_slice(), [0.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(shared.into_grid(), grid);
+    }
+}
@@ -1 +1,9 @@
-// Placeholder for data structures module
\ No newline at end of file
+//! Data structures shared by the renderers
+//!
+//! `grid` holds dense two-dimensional buffers: `Grid2D` for images of values such as Buddhabrot
+//! and flame histograms, and `AtomicGrid` for accumulating weights from many threads at once, with `cell_index` and
+//! `splat` binning continuous coordinates with explicit bounds checks.
+
+pub mod grid;
+
+pub use grid::{cell_index, AtomicGrid, DensityGrid, Grid2D};
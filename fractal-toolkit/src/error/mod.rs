@@ -0,0 +1,159 @@
+//! Structured errors of the formula parser, the evaluator and the renderers
+//!
+//! Formula errors used to be bare `String`s, which callers could only print. `FractalError`
+//! tells the kinds apart, so a GUI can underline a parse error, retry a sample that hit a
+//! division by zero, point out a misspelled function name or tell a cancelled render from a
+//! failed one, and it implements `std::error::Error` to compose with other error types.
+//!
+//! Parse errors keep their location: `ParseError` wraps the `FormulaError` the parser returns,
+//! with its span and caret diagnostic. The formula entry points, the renders that return a
+//! `Result` (`iterate_viewport`, `RenderSession::render`, tiled renders, animation sequences,
+//! orbit streams, Buddhabrot accumulators, scenes and the GPU backend), the file readers and
+//! writers of `output` (embedded parameters, reframing, HDR and scientific images, layers,
+//! galleries, manifests), tile export and stitching, sonification and location imports report
+//! `FractalError`: `IoError` for a file that can't be read or written, `InvalidInput` for
+//! contents that can't be used. Parsers of option strings such as palettes and precisions still
+//! report `String`; `FractalError` converts into one, so `?` works across the boundary.
+
+use crate::formula::FormulaError;
+use std::fmt;
+use std::sync::Arc;
+
+/// What went wrong parsing or evaluating a formula
+#[derive(Debug, Clone)]
+pub enum FractalError {
+    /// The formula doesn't parse; the error holds the message and the byte span of the problem
+    ParseError(FormulaError),
+    /// The formula parsed but can't be evaluated at these arguments, e.g. `log(0)` or a theta
+    /// function outside its domain
+    EvalError(String),
+    /// A division by zero during evaluation
+    DivisionByZero,
+    /// A function or built-in formula name the evaluator doesn't know
+    UnknownFunction(String),
+    /// A formula outside what the operation supports, e.g. a value with no formula literal
+    UnsupportedFormula(String),
+    /// Reading or writing a file failed
+    IoError(Arc<std::io::Error>),
+    /// A render stopped by `CancelToken::cancel`
+    Cancelled,
+    /// Parameters or data the operation can't work with, e.g. a tile directory rendered at
+    /// another size or a truncated orbit stream
+    InvalidInput(String),
+    /// The GPU backend failed: no adapter or device is available, a shader doesn't compile or
+    /// results can't be read back; the render can be retried on the CPU
+    GpuError(String),
+}
+
+impl FractalError {
+    /// An `IoError` naming the file, e.g. "Failed to write 'out.png': No space left on device"
+    pub(crate) fn io(action: &str, path: impl AsRef<std::path::Path>, error: std::io::Error) -> Self {
+        let message = format!("Failed to {} '{}': {}", action, path.as_ref().display(), error);
+        FractalError::IoError(Arc::new(std::io::Error::new(error.kind(), message)))
+    }
+
+    /// An `IoError` naming the file if the image codec failed reading or writing it, otherwise
+    /// `InvalidInput`, e.g. for a file that isn't an image
+    pub(crate) fn image(action: &str, path: impl AsRef<std::path::Path>, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(error) => FractalError::io(action, path, error),
+            error => FractalError::InvalidInput(format!("Failed to {} '{}': {}", action, path.as_ref().display(), error)),
+        }
+    }
+
+    /// Byte span of the problem in the formula, for parse errors
+    pub fn span(&self) -> Option<std::ops::Range<usize>> {
+        match self {
+            FractalError::ParseError(error) => Some(error.span.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FractalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FractalError::ParseError(error) => write!(f, "{} at {}..{}", error.message, error.span.start, error.span.end),
+            FractalError::EvalError(message)
+            | FractalError::UnsupportedFormula(message)
+            | FractalError::InvalidInput(message)
+            | FractalError::GpuError(message) => write!(f, "{}", message),
+            FractalError::DivisionByZero => write!(f, "Division by zero"),
+            FractalError::UnknownFunction(name) => write!(f, "Unknown function: {}", name),
+            FractalError::IoError(error) => write!(f, "{}", error),
+            FractalError::Cancelled => write!(f, "Render cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for FractalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FractalError::ParseError(error) => Some(error),
+            FractalError::IoError(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// I/O errors are equal if their kinds and messages are
+impl PartialEq for FractalError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FractalError::ParseError(a), FractalError::ParseError(b)) => a == b,
+            (FractalError::EvalError(a), FractalError::EvalError(b)) => a == b,
+            (FractalError::DivisionByZero, FractalError::DivisionByZero) => true,
+            (FractalError::UnknownFunction(a), FractalError::UnknownFunction(b)) => a == b,
+            (FractalError::UnsupportedFormula(a), FractalError::UnsupportedFormula(b)) => a == b,
+            (FractalError::IoError(a), FractalError::IoError(b)) => a.kind() == b.kind() && a.to_string() == b.to_string(),
+            (FractalError::Cancelled, FractalError::Cancelled) => true,
+            (FractalError::InvalidInput(a), FractalError::InvalidInput(b)) => a == b,
+            (FractalError::GpuError(a), FractalError::GpuError(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl From<FormulaError> for FractalError {
+    fn from(error: FormulaError) -> Self {
+        FractalError::ParseError(error)
+    }
+}
+
+impl From<std::io::Error> for FractalError {
+    fn from(error: std::io::Error) -> Self {
+        FractalError::IoError(Arc::new(error))
+    }
+}
+
+impl From<FractalError> for String {
+    fn from(error: FractalError) -> String {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::Formula;
+    use crate::{evaluate_special_function, MathEvaluator};
+    use num_complex::Complex;
+
+    #[test]
+    fn test_errors_can_be_matched() {
+        let z = Complex::new(0.5, 0.25);
+        let evaluate = |formula| MathEvaluator::evaluate_formula_with_param(formula, z, z);
+        assert_eq!(evaluate("z / (z - z)"), Err(FractalError::DivisionByZero));
+        assert!(matches!(evaluate("theta1(z, 0)"), Err(FractalError::EvalError(_))));
+        assert_eq!(evaluate_special_function("bessel_q", z), Err(FractalError::UnknownFunction("bessel_q".to_string())));
+        assert!(matches!(Formula::constant(Complex::new(f64::NAN, 0.0)), Err(FractalError::UnsupportedFormula(_))));
+
+        // Parse errors keep their location, and convert to the messages the String APIs report
+        let error = evaluate("z^2 + sinn(z)").unwrap_err();
+        assert_eq!(error.span(), Some(6..10));
+        assert!(std::error::Error::source(&error).is_some());
+        assert_eq!(String::from(error), "Unknown identifier: sinn at 6..10");
+        let missing = FractalError::from(std::fs::read("/nonexistent/formula.txt").unwrap_err());
+        assert!(matches!(&missing, FractalError::IoError(error) if error.kind() == std::io::ErrorKind::NotFound));
+    }
+}
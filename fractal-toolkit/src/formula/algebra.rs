@@ -0,0 +1,254 @@
+//! Building formulas from other formulas
+//!
+//! Related fractals are often defined in terms of one another: the composition f(g(z)), a
+//! perturbation that replaces c by c + δ, or a system conjugated by an affine change of
+//! coordinates. [`Formula`] holds a formula as the parser's token stream and rewrites it
+//! structurally, substituting whole parenthesized subexpressions for variables, so tools can
+//! derive such formulas without splicing strings. The result prints as ordinary formula text
+//! for `--formula` or `CompiledFormula`.
+//!
+//! Formulas are read with the strict parser, so every token belongs to the expression and
+//! substitution can't change how the rest of it parses. Variables bound by `sum` and `prod`
+//! must not be named `z`, `c` or `param`.
+
+use super::{EvaluatorOptions, FormulaError, ParseMode};
+use crate::{ExpressionParser, FractalError, MathEvaluator, Token};
+use num_complex::Complex;
+use std::fmt;
+
+/// A variable of the iteration that can be substituted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormulaVariable {
+    /// The iterated value `z`
+    Z,
+    /// The parameter `c` (also written `param`)
+    C,
+}
+
+impl FormulaVariable {
+    fn matches(self, token: &Token) -> bool {
+        match (self, token) {
+            (FormulaVariable::Z, Token::Identifier(name)) => name == "z",
+            (FormulaVariable::C, Token::Identifier(name)) => name == "c" || name == "param",
+            _ => false,
+        }
+    }
+}
+
+/// A parsed formula that can be combined with others
+#[derive(Debug, Clone)]
+pub struct Formula {
+    tokens: Vec<Token>,
+}
+
+impl Formula {
+    /// Parse a formula with the strict parser
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The formula, e.g. `"z^2 + c"`
+    ///
+    /// # Returns
+    ///
+    /// The formula, or the parse error with its location
+    pub fn parse(text: &str) -> Result<Self, FormulaError> {
+        MathEvaluator::validate_formula(text, &EvaluatorOptions::strict())?;
+        let (tokens, _) = ExpressionParser::tokenize_with_spans(text, ParseMode::Strict)?;
+        Ok(Self { tokens })
+    }
+
+    /// A formula that is the constant `value`
+    ///
+    /// # Returns
+    ///
+    /// The formula, or an error if `value` is not finite and so can't be written
+    pub fn constant(value: Complex<f64>) -> Result<Self, FractalError> {
+        if !value.re.is_finite() || !value.im.is_finite() {
+            return Err(FractalError::UnsupportedFormula(format!("Constant {} can't be written in a formula", value)));
+        }
+        // The parser has no unary minus, so a negative real part is written as 0 - x
+        let mut tokens = vec![Token::LeftParen];
+        if value.re < 0.0 {
+            tokens.extend([Token::Number(0.0), Token::Minus]);
+        }
+        tokens.push(Token::Number(value.re.abs()));
+        if value.im != 0.0 {
+            tokens.push(if value.im < 0.0 { Token::Minus } else { Token::Plus });
+            tokens.push(Token::ComplexNumber(format!("{}i", value.im.abs())));
+        }
+        tokens.push(Token::RightParen);
+        Ok(Self { tokens })
+    }
+
+    /// Whether the formula refers to `variable`
+    pub fn uses(&self, variable: FormulaVariable) -> bool {
+        self.tokens.iter().any(|token| variable.matches(token))
+    }
+
+    /// Replace every occurrence of a variable by an expression
+    ///
+    /// # Arguments
+    ///
+    /// * `variable` - The variable to replace
+    /// * `replacement` - The expression put in its place, in parentheses
+    ///
+    /// # Returns
+    ///
+    /// The new formula; `replacement` may itself use `variable`, as in c := c + 0.01i
+    pub fn substitute(&self, variable: FormulaVariable, replacement: &Formula) -> Formula {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+        for token in &self.tokens {
+            if variable.matches(token) {
+                tokens.push(Token::LeftParen);
+                tokens.extend(replacement.tokens.iter().cloned());
+                tokens.push(Token::RightParen);
+            } else {
+                tokens.push(token.clone());
+            }
+        }
+        Formula { tokens }
+    }
+
+    /// The composition self ∘ inner: `self` evaluated at z = `inner`, with the same c
+    ///
+    /// One iteration of the result is one iteration of `inner` followed by one of `self`.
+    pub fn compose(&self, inner: &Formula) -> Formula {
+        self.substitute(FormulaVariable::Z, inner)
+    }
+
+    /// Replace `variable` by `factor * variable`
+    ///
+    /// Scaling c by a real factor stretches the parameter plane, scaling z the dynamical plane.
+    pub fn scale(&self, variable: FormulaVariable, factor: Complex<f64>) -> Result<Formula, FractalError> {
+        let name = match variable {
+            FormulaVariable::Z => "z",
+            FormulaVariable::C => "c",
+        };
+        let mut scaled = Formula::constant(factor)?;
+        scaled.tokens.push(Token::Multiply);
+        scaled.tokens.push(Token::Identifier(name.to_string()));
+        Ok(self.substitute(variable, &scaled))
+    }
+
+    /// The system conjugated by the affine map h(z) = a z + b: (f(a z + b) - b) / a
+    ///
+    /// The conjugate has the same dynamics in coordinates w with z = h(w), so its Julia sets are
+    /// those of `self` moved by h⁻¹ and its escape times are the same.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - Scale and rotation of h; zero fails with `FractalError::DivisionByZero`
+    /// * `b` - Translation of h
+    pub fn conjugate(&self, a: Complex<f64>, b: Complex<f64>) -> Result<Formula, FractalError> {
+        if a == Complex::new(0.0, 0.0) {
+            return Err(FractalError::DivisionByZero);
+        }
+        let (a, b) = (Formula::constant(a)?, Formula::constant(b)?);
+        let mut h = a.clone();
+        h.tokens.push(Token::Multiply);
+        h.tokens.push(Token::Identifier("z".to_string()));
+        h.tokens.push(Token::Plus);
+        h.tokens.extend(b.tokens.iter().cloned());
+
+        let mut tokens = vec![Token::LeftParen];
+        tokens.extend(self.compose(&h).tokens);
+        tokens.push(Token::Minus);
+        tokens.extend(b.tokens);
+        tokens.push(Token::RightParen);
+        tokens.push(Token::Divide);
+        tokens.extend(a.tokens);
+        Ok(Formula { tokens })
+    }
+}
+
+/// Whether `token` is an operator written without surrounding spaces
+fn is_tight(token: &Token) -> bool {
+    matches!(token, Token::Power | Token::Tetration | Token::Pentation | Token::Hexation)
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, token) in self.tokens.iter().enumerate() {
+            let space = match index.checked_sub(1).map(|previous| &self.tokens[previous]) {
+                None | Some(Token::LeftParen) => false,
+                Some(Token::Identifier(_)) if matches!(token, Token::LeftParen) => false,
+                Some(previous) => !(is_tight(previous) || is_tight(token) || matches!(token, Token::RightParen | Token::Comma)),
+            };
+            if space {
+                write!(f, " ")?;
+            }
+            match token {
+                Token::Number(n) => write!(f, "{}", n)?,
+                Token::ComplexNumber(s) | Token::Identifier(s) => write!(f, "{}", s)?,
+                Token::ImaginaryUnit => write!(f, "i")?,
+                Token::Plus => write!(f, "+")?,
+                Token::Minus => write!(f, "-")?,
+                Token::Multiply => write!(f, "*")?,
+                Token::Divide => write!(f, "/")?,
+                Token::Power => write!(f, "^")?,
+                Token::Tetration => write!(f, "^^")?,
+                Token::Pentation => write!(f, "^^^")?,
+                Token::Hexation => write!(f, "^^^^")?,
+                Token::LeftParen => write!(f, "(")?,
+                Token::RightParen => write!(f, ")")?,
+                Token::Comma => write!(f, ",")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formula_algebra() {
+        let f = Formula::parse("z^2 + c").unwrap();
+        assert_eq!(f.to_string(), "z^2 + c");
+        let g = Formula::parse("sin(z) - c").unwrap();
+        let eval = |formula: &Formula, z: Complex<f64>, c: Complex<f64>| {
+            MathEvaluator::evaluate_formula_with_param(&formula.to_string(), z, c).unwrap()
+        };
+        let (z, c) = (Complex::new(0.3, -0.4), Complex::new(-0.7, 0.2));
+
+        let composed = f.compose(&g);
+        assert_eq!(composed.to_string(), "(sin(z) - c)^2 + c");
+        assert!((eval(&composed, z, c) - eval(&f, eval(&g, z, c), c)).norm() < 1e-12);
+
+        let delta = Complex::new(0.0, -0.01);
+        let perturbed = f.substitute(FormulaVariable::C, &Formula::parse("c - 0.01i").unwrap());
+        assert!((eval(&perturbed, z, c) - eval(&f, z, c + delta)).norm() < 1e-12);
+        let scaled = f.scale(FormulaVariable::C, Complex::new(-2.0, 0.5)).unwrap();
+        assert!((eval(&scaled, z, c) - eval(&f, z, Complex::new(-2.0, 0.5) * c)).norm() < 1e-12);
+
+        // h⁻¹(f(h(z))) with h(z) = a z + b
+        let (a, b) = (Complex::new(2.0, -1.0), Complex::new(-0.5, 0.25));
+        let conjugate = f.conjugate(a, b).unwrap();
+        assert!((eval(&conjugate, z, c) - (eval(&f, a * z + b, c) - b) / a).norm() < 1e-12);
+        assert!(Formula::parse(&conjugate.to_string()).is_ok());
+        assert!(f.conjugate(Complex::new(0.0, 0.0), b).is_err());
+        assert!(Formula::parse("2z").is_err());
+    }
+
+    #[test]
+    fn test_compositions_may_exceed_the_untrusted_token_limit() {
+        // f uses z twice, so each composition doubles the formula but nests it only one level deeper
+        let f = Formula::parse("z^2 + z*c - c").unwrap();
+        let mut composed = f.clone();
+        for _ in 0..6 {
+            composed = composed.compose(&f);
+        }
+        let text = composed.to_string();
+        assert!(composed.tokens.len() > super::super::MAX_FORMULA_TOKENS, "{} tokens", composed.tokens.len());
+        assert!(MathEvaluator::validate_formula(&text, &EvaluatorOptions::untrusted()).is_err());
+
+        let compiled = crate::CompiledFormula::with_options(&text, &EvaluatorOptions::default()).unwrap();
+        let (z, c) = (Complex::new(0.1, 0.05), Complex::new(-0.2, 0.1));
+        let mut expected = z;
+        for _ in 0..7 {
+            expected = MathEvaluator::evaluate_formula_with_param(&f.to_string(), expected, c).unwrap();
+        }
+        assert!((compiled.try_eval(z, c).unwrap() - expected).norm() < 1e-9);
+    }
+}
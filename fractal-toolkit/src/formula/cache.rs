@@ -0,0 +1,295 @@
+//! Memoization of expensive formula subtrees
+//!
+//! Wrapping part of a formula in `cached(expr)` stores its values in a per-thread LRU cache keyed
+//! by the subtree and by whichever of `z`, `c` and the `sum`/`prod` index values it uses, each
+//! rounded to a multiple of a quantum (default 1e-12; `cached(expr, quantum)` overrides it, and
+//! a quantum of 0 only reuses bit-identical arguments, as do coordinates too large for their
+//! multiple of the quantum to fit in an i64). This pays off for subtrees that see the same arguments many times,
+//! such as `z^2 + cached(gamma(c))`, where the gamma value is computed once per pixel instead of
+//! once per iteration, or for orbits that settle into cycles.
+//!
+//! Subtrees are identified by their tokens, so the cache keeps working when a formula is parsed
+//! again for every evaluation. Errors are never cached.
+
+use crate::algebra::Dual;
+use crate::{Expression, FractalError};
+use num_complex::Complex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Entries kept per thread before the least recently used one is evicted
+pub const CACHE_CAPACITY: usize = 4096;
+
+/// Quantum used by `cached(expr)` without a second argument
+pub const DEFAULT_QUANTUM: f64 = 1e-12;
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CACHE: RefCell<LruCache> = RefCell::new(LruCache::new(CACHE_CAPACITY));
+}
+
+/// Hit and miss counts of `cached(...)` lookups across all threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups answered from the cache, 0 when there were none
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// Current hit and miss counts
+pub fn cache_stats() -> CacheStats {
+    CacheStats { hits: HITS.load(Ordering::Relaxed), misses: MISSES.load(Ordering::Relaxed) }
+}
+
+/// Reset the hit and miss counts, e.g. before timing a render
+pub fn reset_cache_stats() {
+    HITS.store(0, Ordering::Relaxed);
+    MISSES.store(0, Ordering::Relaxed);
+}
+
+/// Drop all entries cached by the current thread
+pub fn clear_cache() {
+    CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    subtree: u64,
+    point: [Coordinate; 4],
+    bound: Vec<Coordinate>,
+}
+
+/// A coordinate in a cache key
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Coordinate {
+    /// The coordinate rounded to this multiple of the quantum
+    Multiple(i64),
+    /// The coordinate's bits, when the quantum is 0 or the multiple doesn't fit in an i64
+    Bits(u64),
+}
+
+const NIL: usize = usize::MAX;
+
+struct Entry {
+    key: CacheKey,
+    value: Complex<f64>,
+    prev: usize,
+    next: usize,
+}
+
+/// Fixed-capacity map with least-recently-used eviction, as a linked list threaded through a Vec
+struct LruCache {
+    capacity: usize,
+    map: HashMap<CacheKey, usize>,
+    entries: Vec<Entry>,
+    /// Most recently used entry
+    head: usize,
+    /// Least recently used entry, the next to be evicted
+    tail: usize,
+}
+
+impl LruCache {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, map: HashMap::new(), entries: Vec::new(), head: NIL, tail: NIL }
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.entries.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.entries[index].prev, self.entries[index].next);
+        match prev {
+            NIL => self.head = next,
+            prev => self.entries[prev].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => self.entries[next].prev = prev,
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.entries[index].prev = NIL;
+        self.entries[index].next = self.head;
+        if self.head != NIL {
+            self.entries[self.head].prev = index;
+        }
+        self.head = index;
+        if self.tail == NIL {
+            self.tail = index;
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Complex<f64>> {
+        let index = *self.map.get(key)?;
+        self.unlink(index);
+        self.push_front(index);
+        Some(self.entries[index].value)
+    }
+
+    fn insert(&mut self, key: CacheKey, value: Complex<f64>) {
+        if let Some(&index) = self.map.get(&key) {
+            self.entries[index].value = value;
+            self.unlink(index);
+            self.push_front(index);
+            return;
+        }
+        let index = if self.entries.len() < self.capacity {
+            self.entries.push(Entry { key: key.clone(), value, prev: NIL, next: NIL });
+            self.entries.len() - 1
+        } else {
+            // Reuse the least recently used slot
+            let index = self.tail;
+            self.unlink(index);
+            let old = std::mem::replace(&mut self.entries[index], Entry { key: key.clone(), value, prev: NIL, next: NIL });
+            self.map.remove(&old.key);
+            index
+        };
+        self.map.insert(key, index);
+        self.push_front(index);
+    }
+}
+
+/// Round a coordinate to a multiple of `quantum`, or take its bits when `quantum` is 0 or the
+/// multiple is out of the range of an i64 (where the cast would saturate, mapping different
+/// coordinates to one key) or not a number
+fn quantize(value: f64, quantum: f64) -> Coordinate {
+    let multiple = (value / quantum).round();
+    if quantum == 0.0 || multiple.is_nan() || multiple.abs() >= i64::MAX as f64 {
+        Coordinate::Bits(value.to_bits())
+    } else {
+        Coordinate::Multiple(multiple as i64)
+    }
+}
+
+/// `cached(expr)` or `cached(expr, quantum)`
+pub(crate) struct Cached {
+    /// Hash of the subtree's tokens
+    pub(crate) subtree: u64,
+    /// Whether the subtree reads `z`, `c` and index variables, which then become part of the key
+    pub(crate) uses_z: bool,
+    pub(crate) uses_param: bool,
+    pub(crate) uses_bound: bool,
+    pub(crate) inner: Box<dyn Expression>,
+    pub(crate) quantum: Option<Box<dyn Expression>>,
+}
+
+impl Expression for Cached {
+    fn name(&self) -> &'static str {
+        "cached()"
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        std::iter::once(&mut self.inner).chain(self.quantum.as_mut()).collect()
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let quantum = match &self.quantum {
+            Some(quantum) => quantum.evaluate(z, param)?.re,
+            None => DEFAULT_QUANTUM,
+        };
+        if !(quantum.is_finite() && quantum >= 0.0) {
+            return Err(FractalError::EvalError(format!("cached needs a finite, non-negative quantum, got {}", quantum)));
+        }
+        let bound = if self.uses_bound { super::series::bound_values() } else { Vec::new() };
+        let zero = Complex::new(0.0, 0.0);
+        let (key_z, key_param) = (if self.uses_z { z } else { zero }, if self.uses_param { param } else { zero });
+        let coordinates = [key_z.re, key_z.im, key_param.re, key_param.im];
+        if coordinates.iter().chain(bound.iter().flat_map(|v| [&v.re, &v.im])).any(|x| !x.is_finite()) {
+            return self.inner.evaluate(z, param);
+        }
+
+        let key = CacheKey {
+            subtree: self.subtree,
+            point: coordinates.map(|x| quantize(x, quantum)),
+            bound: bound.iter().flat_map(|v| [quantize(v.re, quantum), quantize(v.im, quantum)]).collect(),
+        };
+        if let Some(value) = CACHE.with(|cache| cache.borrow_mut().get(&key)) {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(value);
+        }
+        MISSES.fetch_add(1, Ordering::Relaxed);
+        let value = self.inner.evaluate(z, param)?;
+        CACHE.with(|cache| cache.borrow_mut().insert(key, value));
+        Ok(value)
+    }
+
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
+        // Derivatives bypass the cache
+        self.inner.evaluate_dual(z, param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MathEvaluator;
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let key = |n: i64| CacheKey { subtree: 1, point: [Coordinate::Multiple(n); 4], bound: Vec::new() };
+        let mut cache = LruCache::new(2);
+        cache.insert(key(1), Complex::new(1.0, 0.0));
+        cache.insert(key(2), Complex::new(2.0, 0.0));
+        assert_eq!(cache.get(&key(1)), Some(Complex::new(1.0, 0.0)));
+        cache.insert(key(3), Complex::new(3.0, 0.0));
+        assert_eq!(cache.get(&key(2)), None);
+        assert_eq!(cache.get(&key(1)), Some(Complex::new(1.0, 0.0)));
+        assert_eq!(cache.get(&key(3)), Some(Complex::new(3.0, 0.0)));
+    }
+
+    #[test]
+    fn test_cached_subtree_is_reused_across_parses() {
+        clear_cache();
+        let z = Complex::new(0.1, 0.2);
+        let c = Complex::new(2.5, 0.0);
+        let plain = MathEvaluator::evaluate_formula_with_param("z^2 + gamma(c)", z, c).unwrap();
+
+        let before = cache_stats();
+        let first = MathEvaluator::evaluate_formula_with_param("z^2 + cached(gamma(c))", z, c).unwrap();
+        // gamma(c) doesn't read z, so the next iteration of the same pixel is a hit
+        let second = MathEvaluator::evaluate_formula_with_param("z^2 + cached(gamma(c))", first, c).unwrap();
+        let after = cache_stats();
+
+        assert_eq!(first, plain);
+        assert_eq!(second, first * first + (plain - z * z));
+        assert!(after.hits > before.hits);
+
+        assert!(MathEvaluator::evaluate_formula_with_param("cached(z, -1)", z, c).is_err());
+    }
+
+    #[test]
+    fn test_large_and_nan_arguments_get_their_own_keys() {
+        // Beyond about 9.2e6 the multiples of the default quantum no longer fit in an i64
+        assert!(quantize(1e7, DEFAULT_QUANTUM) != quantize(2e7, DEFAULT_QUANTUM));
+        assert!(quantize(f64::NAN, DEFAULT_QUANTUM) != quantize(0.0, DEFAULT_QUANTUM));
+        assert!(quantize(1e-300, 1e-320) != quantize(2e-300, 1e-320));
+        assert!(quantize(0.5, 1e-12) == quantize(0.5 + 1e-14, 1e-12));
+
+        clear_cache();
+        let c = Complex::new(0.0, 0.0);
+        let evaluate = |z: f64| MathEvaluator::evaluate_formula_with_param("cached(z)", Complex::new(z, 0.0), c).unwrap();
+        assert_eq!(evaluate(1e7).re, 1e7);
+        assert_eq!(evaluate(2e7).re, 2e7);
+        assert!(evaluate(f64::NAN).re.is_nan());
+        assert_eq!(evaluate(0.0).re, 0.0);
+    }
+}
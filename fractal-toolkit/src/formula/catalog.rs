@@ -0,0 +1,238 @@
+//! Catalog of the functions and operators accepted by the formula parser
+//!
+//! The tables must list exactly what `ExpressionParser` understands; the tests parse every entry
+//! so a function added to the parser without a catalog entry (or the other way round) is caught.
+
+use crate::MathEvaluator;
+use serde::Serialize;
+
+/// A function that can be called in a formula
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FunctionInfo {
+    /// Name as written in formulas, e.g. `sin`
+    pub name: &'static str,
+    /// Number of arguments (the minimum, for variadic functions)
+    pub arity: usize,
+    /// Whether the function accepts more than `arity` arguments
+    pub variadic: bool,
+    /// Call syntax for tooltips, e.g. `sin(z)`
+    pub signature: &'static str,
+    /// A complete formula using the function, for examples and tests
+    pub example: &'static str,
+    /// One-line description
+    pub description: &'static str,
+}
+
+/// A binary operator that can be used in a formula
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OperatorInfo {
+    /// Symbol as written in formulas, e.g. `^`
+    pub symbol: &'static str,
+    /// Name of the operation
+    pub name: &'static str,
+    /// Binding strength; higher binds tighter
+    pub precedence: u8,
+    /// Whether `a op b op c` groups as `a op (b op c)`
+    pub right_associative: bool,
+    /// One-line description
+    pub description: &'static str,
+}
+
+/// A one-argument function whose signature is also a valid example
+const fn function(name: &'static str, signature: &'static str, description: &'static str) -> FunctionInfo {
+    FunctionInfo { name, arity: 1, variadic: false, signature, example: signature, description }
+}
+
+const FUNCTIONS: &[FunctionInfo] = &[
+    function("sin", "sin(z)", "Sine"),
+    function("cos", "cos(z)", "Cosine"),
+    function("tan", "tan(z)", "Tangent"),
+    function("asin", "asin(z)", "Inverse sine (principal branch)"),
+    function("acos", "acos(z)", "Inverse cosine (principal branch)"),
+    function("atan", "atan(z)", "Inverse tangent (principal branch)"),
+    function("sinh", "sinh(z)", "Hyperbolic sine"),
+    function("cosh", "cosh(z)", "Hyperbolic cosine"),
+    function("tanh", "tanh(z)", "Hyperbolic tangent"),
+    function("exp", "exp(z)", "Exponential e^z"),
+    function("log", "log(z)", "Natural logarithm (principal branch)"),
+    function("sqrt", "sqrt(z)", "Principal square root"),
+    function("cbrt", "cbrt(z)", "Principal cube root"),
+    function("gamma", "gamma(z)", "Gamma function (real positive arguments only; returns z otherwise)"),
+    function("zeta", "zeta(z)", "Riemann zeta function (placeholder: returns z)"),
+    function("slog", "slog(z)", "Super-logarithm, the inverse of tetration (placeholder: returns 1)"),
+    function("sexp", "sexp(z)", "Super-exponential e^^z (approximated by e^z)"),
+    function("penta_root", "penta_root(z)", "Inverse of pentation (placeholder: returns 1)"),
+    function("hexa_root", "hexa_root(z)", "Inverse of hexation (placeholder: returns 1)"),
+    FunctionInfo {
+        name: "cheb",
+        arity: 2,
+        variadic: false,
+        signature: "cheb(n, z)",
+        example: "cheb(3, z) + c",
+        description: "Chebyshev polynomial of the first kind T_n(z), integer n >= 0",
+    },
+    FunctionInfo {
+        name: "legendre",
+        arity: 2,
+        variadic: false,
+        signature: "legendre(n, z)",
+        example: "legendre(4, z) + c",
+        description: "Legendre polynomial P_n(z), integer n >= 0",
+    },
+    FunctionInfo {
+        name: "theta1",
+        arity: 2,
+        variadic: false,
+        signature: "theta1(z, tau)",
+        example: "theta1(z, 0.5 + i)",
+        description: "Jacobi theta function with nome exp(i*pi*tau), Im(tau) > 0; odd, vanishes at multiples of pi",
+    },
+    FunctionInfo {
+        name: "theta2",
+        arity: 2,
+        variadic: false,
+        signature: "theta2(z, tau)",
+        example: "theta2(z, 0.5 + i)",
+        description: "Jacobi theta function with nome exp(i*pi*tau), Im(tau) > 0; even, pi-antiperiodic",
+    },
+    FunctionInfo {
+        name: "theta3",
+        arity: 2,
+        variadic: false,
+        signature: "theta3(z, tau)",
+        example: "theta3(z, 0.5 + i)",
+        description: "Jacobi theta function with nome exp(i*pi*tau), Im(tau) > 0; even, pi-periodic",
+    },
+    FunctionInfo {
+        name: "theta4",
+        arity: 2,
+        variadic: false,
+        signature: "theta4(z, tau)",
+        example: "theta4(z, 0.5 + i)",
+        description: "Jacobi theta function with nome exp(i*pi*tau), Im(tau) > 0; even, pi-periodic, theta3 shifted by pi/2",
+    },
+    FunctionInfo {
+        name: "wp",
+        arity: 3,
+        variadic: false,
+        signature: "wp(z, w1, w2)",
+        example: "wp(z, 1, i)",
+        description: "Weierstrass elliptic function of the lattice spanned by the periods w1 and w2",
+    },
+    FunctionInfo {
+        name: "cached",
+        arity: 1,
+        variadic: true,
+        signature: "cached(expr[, quantum])",
+        example: "z^2 + cached(gamma(c))",
+        description: "Value of expr, memoized per thread for arguments equal up to quantum (default 1e-12)",
+    },
+    FunctionInfo {
+        name: "sum",
+        arity: 4,
+        variadic: false,
+        signature: "sum(k, from, to, expr)",
+        example: "sum(k, 0, 10, z^k) + c",
+        description: "Sum of expr for integer k from..to; k can be used inside expr",
+    },
+    FunctionInfo {
+        name: "prod",
+        arity: 4,
+        variadic: false,
+        signature: "prod(k, from, to, expr)",
+        example: "prod(k, 1, 4, z - k/4) + c",
+        description: "Product of expr for integer k from..to; k can be used inside expr",
+    },
+    FunctionInfo {
+        name: "cfrac",
+        arity: 2,
+        variadic: true,
+        signature: "cfrac(a0, a1, ..., an, depth)",
+        example: "cfrac(z, c, 1, 8)",
+        description: "Continued fraction a0 + 1/(a1 + 1/(a2 + ...)) to depth levels, repeating a1..an",
+    },
+];
+
+const OPERATORS: &[OperatorInfo] = &[
+    OperatorInfo { symbol: "+", name: "addition", precedence: 1, right_associative: false, description: "Sum a + b" },
+    OperatorInfo { symbol: "-", name: "subtraction", precedence: 1, right_associative: false, description: "Difference a - b" },
+    OperatorInfo { symbol: "*", name: "multiplication", precedence: 2, right_associative: false, description: "Product a * b; also implied before i, as in 2i" },
+    OperatorInfo { symbol: "/", name: "division", precedence: 2, right_associative: false, description: "Quotient a / b; dividing by zero is an error" },
+    OperatorInfo { symbol: "^", name: "power", precedence: 3, right_associative: true, description: "Complex power a^b via exp(b ln a)" },
+    OperatorInfo { symbol: "^^^", name: "pentation", precedence: 4, right_associative: true, description: "Pentation, iterated tetration" },
+    OperatorInfo { symbol: "^^^^", name: "hexation", precedence: 5, right_associative: true, description: "Hexation, iterated pentation" },
+    OperatorInfo { symbol: "^^", name: "tetration", precedence: 6, right_associative: true, description: "Tetration, iterated exponentiation a^a^...^a" },
+];
+
+impl MathEvaluator {
+    /// Functions that formulas can call, with arities and descriptions
+    ///
+    /// # Returns
+    ///
+    /// The function catalog, in the order it should be presented
+    pub fn supported_functions() -> &'static [FunctionInfo] {
+        FUNCTIONS
+    }
+
+    /// Binary operators that formulas can use, with precedence and associativity
+    ///
+    /// # Returns
+    ///
+    /// The operator catalog, from loosest to tightest binding
+    pub fn supported_operators() -> &'static [OperatorInfo] {
+        OPERATORS
+    }
+}
+
+/// Escape text for inclusion in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Render the function and operator catalogs as an HTML fragment (two tables)
+///
+/// # Returns
+///
+/// HTML suitable for embedding in a page
+pub fn formula_reference_html() -> String {
+    let mut html = String::from("<h4>Variables</h4>\n<p><code>z</code> (iterated value), <code>c</code> or <code>param</code> (pixel or spawn constant), <code>i</code> (imaginary unit)</p>\n");
+    html.push_str("<h4>Functions</h4>\n<table>\n");
+    for f in MathEvaluator::supported_functions() {
+        html.push_str(&format!(
+            "<tr><td><code>{}</code></td><td>{}</td></tr>\n",
+            escape_html(f.signature),
+            escape_html(f.description)
+        ));
+    }
+    html.push_str("</table>\n<h4>Operators</h4>\n<table>\n");
+    for op in MathEvaluator::supported_operators() {
+        html.push_str(&format!(
+            "<tr><td><code>{}</code></td><td>{}</td></tr>\n",
+            escape_html(op.symbol),
+            escape_html(op.description)
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_catalog_matches_parser() {
+        let z = Complex::new(0.5, 0.1);
+        let c = Complex::new(0.1, 0.0);
+        for f in MathEvaluator::supported_functions() {
+            assert!(f.example.contains(f.name));
+            assert!(MathEvaluator::evaluate_formula_with_param(f.example, z, c).is_ok(), "{} should parse", f.example);
+        }
+        for op in MathEvaluator::supported_operators() {
+            let formula = format!("z {} 2", op.symbol);
+            assert!(MathEvaluator::evaluate_formula_with_param(&formula, z, c).is_ok(), "{} should parse", formula);
+        }
+        assert!(MathEvaluator::evaluate_formula_with_param("frobnicate(z) + c", z, c).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Formulas parsed once and evaluated many times
+//!
+//! `MathEvaluator::evaluate_formula_with_param` tokenizes and parses its formula on every call,
+//! which for custom formulas costs far more than the arithmetic itself. A [`CompiledFormula`]
+//! does that work once and keeps the syntax tree, so the iteration loops only evaluate it.
+//! Evaluation gives exactly the same values as the string-based evaluator, including the
+//! hand-written implementations of common formulas and the `z^2 + c` fallback for formulas
+//! that don't parse.
+
+use super::profile::{self, EvaluationProfile};
+use super::{semantics, EvaluatorOptions, FormulaError};
+use crate::algebra::Dual;
+use crate::{numeric_dual, Expression, ExpressionParser, FractalError, MathEvaluator};
+use num_complex::Complex;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+thread_local! {
+    /// The formula most recently compiled through `compiled_for` on this thread
+    static RECENT: RefCell<Option<Arc<CompiledFormula>>> = const { RefCell::new(None) };
+}
+
+enum Program {
+    /// One of the formulas `MathEvaluator` implements by hand, stored trimmed and lowercased
+    Builtin(String),
+    Ast(Box<dyn Expression>),
+    /// A formula that doesn't parse; every evaluation reports the parse error
+    Invalid(FormulaError),
+}
+
+/// A formula parsed into a syntax tree, ready to be evaluated for many values of z and c
+pub struct CompiledFormula {
+    source: String,
+    program: Program,
+    /// Branch and singular-value choices applied while evaluating
+    options: EvaluatorOptions,
+    /// Whether the formula commutes with conjugation, worked out on first use
+    real_coefficients: OnceLock<bool>,
+    /// Where evaluations are recorded, for formulas compiled with profiling
+    profile: Option<Arc<EvaluationProfile>>,
+}
+
+impl CompiledFormula {
+    /// Compile a formula leniently, the way the renderers have always treated formulas
+    ///
+    /// A formula that doesn't parse still compiles; `eval` then falls back to `z^2 + c`, and
+    /// `error` tells why. While `formula::profile::set_profiling` is on, the formula records its
+    /// evaluations into the profile `formula::profile::evaluation_profiles` reports for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula, e.g. `"z^3 + sin(c)"`
+    ///
+    /// # Returns
+    ///
+    /// The compiled formula
+    pub fn new(formula: &str) -> Self {
+        let compiled = Self::with_options(formula, &EvaluatorOptions::default()).unwrap_or_else(|error| CompiledFormula {
+            source: formula.to_string(),
+            program: Program::Invalid(error),
+            options: EvaluatorOptions::default(),
+            real_coefficients: OnceLock::new(),
+            profile: None,
+        });
+        match profile::profiling_enabled() {
+            true => compiled.instrumented(true),
+            false => compiled,
+        }
+    }
+
+    /// Compile a formula, failing if it doesn't parse under the given options
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula to compile
+    /// * `options` - Parser mode and other evaluator settings
+    ///
+    /// # Returns
+    ///
+    /// The compiled formula, or the parse error with its location
+    pub fn with_options(formula: &str, options: &EvaluatorOptions) -> Result<Self, FormulaError> {
+        let lower = formula.trim().to_lowercase();
+        let zero = Complex::new(0.0, 0.0);
+        // The hand-written formulas only know the default branch and singular-value choices
+        let program = if options.has_default_semantics() && MathEvaluator::evaluate_builtin_formula(&lower, zero, zero).is_some() {
+            Program::Builtin(lower)
+        } else {
+            Program::Ast(ExpressionParser::parse(formula, options)?)
+        };
+        Ok(CompiledFormula {
+            source: formula.to_string(),
+            program,
+            options: options.clone(),
+            real_coefficients: OnceLock::new(),
+            profile: None,
+        })
+    }
+
+    /// Compile a formula that records how often and for how long each kind of node in it is
+    /// evaluated (see `formula::profile`)
+    ///
+    /// Evaluation gives the same values as the formula compiled with `with_options`, more slowly.
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula to compile
+    /// * `options` - Parser mode and other evaluator settings
+    ///
+    /// # Returns
+    ///
+    /// The compiled formula, whose `profile` holds the statistics, or the parse error
+    pub fn profiled(formula: &str, options: &EvaluatorOptions) -> Result<Self, FormulaError> {
+        Ok(Self::with_options(formula, options)?.instrumented(false))
+    }
+
+    /// Wrap the syntax tree in timers recording into a new profile, or into the shared profile
+    /// of the source that `formula::profile::evaluation_profiles` reports
+    fn instrumented(mut self, shared: bool) -> Self {
+        let profile = match &mut self.program {
+            Program::Ast(ast) => {
+                let profile = profile::profile_for(&self.source, profile::node_names(ast), shared);
+                profile::instrument(ast, &profile);
+                profile
+            }
+            Program::Builtin(_) => profile::profile_for(&self.source, vec![profile::HAND_WRITTEN], shared),
+            Program::Invalid(_) => profile::profile_for(&self.source, Vec::new(), shared),
+        };
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Evaluation statistics, for formulas compiled with profiling
+    pub fn profile(&self) -> Option<&Arc<EvaluationProfile>> {
+        self.profile.as_ref()
+    }
+
+    /// The formula this was compiled from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Why the formula didn't parse, for formulas compiled leniently with `new`
+    pub fn error(&self) -> Option<&FormulaError> {
+        match &self.program {
+            Program::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Evaluate the formula, reporting evaluation errors
+    ///
+    /// # Arguments
+    ///
+    /// * `z` - The current value in the iteration
+    /// * `c` - The complex parameter
+    ///
+    /// # Returns
+    ///
+    /// The same result as `MathEvaluator::evaluate_formula_with_param(source, z, c)`
+    pub fn try_eval(&self, z: Complex<f64>, c: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        match &self.program {
+            Program::Builtin(lower) => {
+                let evaluate = || {
+                    MathEvaluator::evaluate_builtin_formula(lower, z, c)
+                        .unwrap_or_else(|| Err(FractalError::UnknownFunction(lower.clone())))
+                };
+                match &self.profile {
+                    Some(profile) => profile.time(0, evaluate),
+                    None => evaluate(),
+                }
+            }
+            Program::Ast(ast) if self.options.has_default_semantics() => ast.evaluate(z, c),
+            Program::Ast(ast) => semantics::with_options(&self.options, || ast.evaluate(z, c)),
+            Program::Invalid(error) => Err(error.clone().into()),
+        }
+    }
+
+    /// Evaluate the formula with the derivatives of z and c carried along (see `algebra::Dual`)
+    ///
+    /// Parsed formulas are differentiated exactly, node by node, apart from a few special
+    /// functions; the hand-written formulas are differentiated by a central difference.
+    ///
+    /// # Arguments
+    ///
+    /// * `z` - The current value in the iteration, with its derivative
+    /// * `c` - The complex parameter, with its derivative
+    ///
+    /// # Returns
+    ///
+    /// The value of `try_eval(z.value, c.value)` with its derivative
+    pub fn try_eval_dual(&self, z: Dual, c: Dual) -> Result<Dual, FractalError> {
+        match &self.program {
+            Program::Builtin(_) => numeric_dual(|z, c| self.try_eval(z, c), z, c),
+            Program::Ast(ast) if self.options.has_default_semantics() => ast.evaluate_dual(z, c),
+            Program::Ast(ast) => semantics::with_options(&self.options, || ast.evaluate_dual(z, c)),
+            Program::Invalid(error) => Err(error.clone().into()),
+        }
+    }
+
+    /// `try_eval_dual`, using `z^2 + c` where evaluation fails as `eval` does
+    pub fn eval_dual(&self, z: Dual, c: Dual) -> Dual {
+        self.try_eval_dual(z, c).unwrap_or(z * z + c)
+    }
+
+    /// Start a new orbit on this thread
+    ///
+    /// With `BranchChoice::Continuous`, multivalued functions follow the orbit from one
+    /// iteration to the next; call this before the first iteration of every orbit so that each
+    /// one starts again on the principal branch. It does nothing useful for other options.
+    pub fn start_orbit(&self) {
+        if self.options.branch != super::BranchChoice::Principal {
+            semantics::start_orbit();
+        }
+    }
+
+    /// Whether the formula commutes with conjugation, f(z̄, c̄) = conj f(z, c)
+    ///
+    /// Formulas built from real constants, z, c and analytic functions do; literals such as `i`
+    /// and functions such as `conj`, `abs` or `re` generally don't. Only formulas that do can be
+    /// evaluated in custom number systems (see `CustomComplex::eval_formula`). The test runs at a
+    /// few generic points the first time it is asked for, and the answer is kept.
+    pub fn has_real_coefficients(&self) -> bool {
+        *self.real_coefficients.get_or_init(|| {
+            let probes = [
+                (Complex::new(0.31, 0.17), Complex::new(-0.43, 0.52)),
+                (Complex::new(-0.62, -0.29), Complex::new(0.27, 0.11)),
+            ];
+            probes.iter().all(|&(z, c)| match (self.try_eval(z, c), self.try_eval(z.conj(), c.conj())) {
+                (Ok(value), Ok(mirrored)) => (value.conj() - mirrored).norm() <= 1e-9 * (1.0 + value.norm()),
+                _ => false,
+            })
+        })
+    }
+
+    /// Evaluate the formula, using `z^2 + c` where evaluation fails as the iteration loops do
+    ///
+    /// # Arguments
+    ///
+    /// * `z` - The current value in the iteration
+    /// * `c` - The complex parameter
+    ///
+    /// # Returns
+    ///
+    /// The next value of z
+    pub fn eval(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        self.try_eval(z, c).unwrap_or(z * z + c)
+    }
+}
+
+impl fmt::Debug for CompiledFormula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.program {
+            Program::Builtin(_) => "builtin",
+            Program::Ast(_) => "ast",
+            Program::Invalid(_) => "invalid",
+        };
+        f.debug_struct("CompiledFormula").field("source", &self.source).field("kind", &kind).finish()
+    }
+}
+
+/// Leniently compiled form of `formula`, reusing this thread's last compilation when it matches
+///
+/// For functions such as `mandelbrot_iterations` that receive the formula as a string for every
+/// pixel; callers that can hold on to a `CompiledFormula` themselves should do so instead.
+pub(crate) fn compiled_for(formula: &str) -> Arc<CompiledFormula> {
+    RECENT.with(|recent| {
+        let mut recent = recent.borrow_mut();
+        match recent.as_ref() {
+            Some(compiled) if compiled.source == formula && profile::is_current(compiled.profile.as_deref()) => {
+                Arc::clone(compiled)
+            }
+            _ => {
+                let compiled = Arc::new(CompiledFormula::new(formula));
+                *recent = Some(Arc::clone(&compiled));
+                compiled
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_matches_string_evaluation() {
+        let formulas = ["z^2 + c", "z^z + c", "z^3 + sin(c) * 0.5", "sum(k, 1, 3, z^k) + c", "(z+1)i + c", "z^2 + sinn(z)"];
+        let points = [(Complex::new(0.3, -0.2), Complex::new(-0.7, 0.1)), (Complex::new(1.5, 0.5), Complex::new(0.2, 0.9))];
+        for formula in formulas {
+            let compiled = CompiledFormula::new(formula);
+            for (z, c) in points {
+                let expected = MathEvaluator::evaluate_formula_with_options(formula, z, c, &EvaluatorOptions::default());
+                assert_eq!(compiled.try_eval(z, c).ok(), expected.clone().ok(), "{}", formula);
+                assert_eq!(compiled.eval(z, c), expected.unwrap_or(z * z + c), "{}", formula);
+            }
+        }
+
+        assert!(CompiledFormula::new("z^2 + sinn(z)").error().is_some());
+        assert!(CompiledFormula::with_options("2z + c", &EvaluatorOptions::strict()).is_err());
+        assert!(Arc::ptr_eq(&compiled_for("z^3 + c*z"), &compiled_for("z^3 + c*z")));
+    }
+}
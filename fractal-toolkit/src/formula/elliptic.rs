@@ -0,0 +1,221 @@
+//! Jacobi theta functions and the Weierstrass elliptic function for the expression evaluator
+//!
+//! `theta1(z, tau)` .. `theta4(z, tau)` follow the DLMF §20.2 convention with nome
+//! `q = exp(i*pi*tau)`, so `theta3(z + pi, tau) = theta3(z, tau)`. `wp(z, w1, w2)` is the
+//! Weierstrass ℘ function of the lattice spanned by the full periods `w1` and `w2`, computed from
+//! theta values (DLMF 23.6.5) after reducing `z` to the fundamental parallelogram.
+
+use crate::{Expression, FractalError};
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+/// Most terms summed for one theta series before giving up on convergence
+const MAX_THETA_TERMS: u32 = 10_000;
+
+/// Which of the four Jacobi theta functions a node evaluates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ThetaKind {
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+impl ThetaKind {
+    /// Map a function name such as `theta3` to its kind
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "theta1" => Some(ThetaKind::One),
+            "theta2" => Some(ThetaKind::Two),
+            "theta3" => Some(ThetaKind::Three),
+            "theta4" => Some(ThetaKind::Four),
+            _ => None,
+        }
+    }
+}
+
+/// `thetaN(z, tau)`
+pub(crate) struct Theta {
+    pub(crate) kind: ThetaKind,
+    pub(crate) argument: Box<dyn Expression>,
+    pub(crate) tau: Box<dyn Expression>,
+}
+
+impl Expression for Theta {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            ThetaKind::One => "theta1()",
+            ThetaKind::Two => "theta2()",
+            ThetaKind::Three => "theta3()",
+            ThetaKind::Four => "theta4()",
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        vec![&mut self.argument, &mut self.tau]
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let argument = self.argument.evaluate(z, param)?;
+        let tau = self.tau.evaluate(z, param)?;
+        theta(self.kind, argument, tau)
+    }
+}
+
+/// `wp(z, w1, w2)`
+pub(crate) struct WeierstrassP {
+    pub(crate) argument: Box<dyn Expression>,
+    pub(crate) period1: Box<dyn Expression>,
+    pub(crate) period2: Box<dyn Expression>,
+}
+
+impl Expression for WeierstrassP {
+    fn name(&self) -> &'static str {
+        "wp()"
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        vec![&mut self.argument, &mut self.period1, &mut self.period2]
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let argument = self.argument.evaluate(z, param)?;
+        let period1 = self.period1.evaluate(z, param)?;
+        let period2 = self.period2.evaluate(z, param)?;
+        weierstrass_p(argument, period1, period2)
+    }
+}
+
+/// Evaluate a Jacobi theta function by its Fourier series
+///
+/// # Arguments
+///
+/// * `kind` - Which theta function
+/// * `z` - Argument
+/// * `tau` - Lattice parameter, which must lie in the upper half-plane
+///
+/// # Returns
+///
+/// The theta value, or an error if `tau` is not in the upper half-plane or the series does not converge
+pub(crate) fn theta(kind: ThetaKind, z: Complex<f64>, tau: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+    if tau.im <= 0.0 || tau.im.is_nan() {
+        return Err(FractalError::EvalError(format!("theta needs Im(tau) > 0, got tau = {}", tau)));
+    }
+    // log|q|^n² falls faster than the cos/sin factor e^{n|2 Im z|} grows once n passes this index
+    let decay = PI * tau.im;
+    let turning_point = (z.im.abs() / decay).ceil() as u32 + 1;
+    let i_pi_tau = Complex::new(0.0, PI) * tau;
+
+    let half_integer = matches!(kind, ThetaKind::One | ThetaKind::Two);
+    let mut sum = match kind {
+        ThetaKind::Three | ThetaKind::Four => Complex::new(1.0, 0.0),
+        _ => Complex::new(0.0, 0.0),
+    };
+    for n in 0..MAX_THETA_TERMS {
+        let (index, frequency) = if half_integer {
+            (n as f64 + 0.5, (2 * n + 1) as f64)
+        } else if n == 0 {
+            continue;
+        } else {
+            (n as f64, (2 * n) as f64)
+        };
+        let sign = if n % 2 == 1 && matches!(kind, ThetaKind::One | ThetaKind::Four) { -1.0 } else { 1.0 };
+        let wave = if kind == ThetaKind::One { (frequency * z).sin() } else { (frequency * z).cos() };
+        let term = 2.0 * sign * (i_pi_tau * index * index).exp() * wave;
+        sum += term;
+
+        if n > turning_point && term.norm() <= f64::EPSILON * sum.norm().max(f64::MIN_POSITIVE) {
+            return Ok(sum);
+        }
+        if !sum.re.is_finite() || !sum.im.is_finite() {
+            break;
+        }
+    }
+    Err(FractalError::EvalError(format!("theta series did not converge at z = {}, tau = {}", z, tau)))
+}
+
+/// Evaluate the Weierstrass ℘ function of the lattice `m*w1 + n*w2`
+///
+/// # Arguments
+///
+/// * `z` - Argument
+/// * `w1` - First period
+/// * `w2` - Second period, not a real multiple of `w1`
+///
+/// # Returns
+///
+/// ℘(z), or an error for a degenerate lattice or a lattice point
+pub(crate) fn weierstrass_p(z: Complex<f64>, w1: Complex<f64>, w2: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+    if w1.norm_sqr() < f64::EPSILON || w2.norm_sqr() < f64::EPSILON {
+        return Err(FractalError::EvalError("wp needs two non-zero periods".to_string()));
+    }
+    // The lattice is the same with the periods swapped, so orient them to put tau in the upper half-plane
+    let (w1, w2) = if (w2 / w1).im < 0.0 { (w2, w1) } else { (w1, w2) };
+    let tau = w2 / w1;
+    if tau.im < 1e-9 * tau.norm() {
+        return Err(FractalError::EvalError(format!("wp periods {} and {} are parallel and don't span a lattice", w1, w2)));
+    }
+
+    // Reduce z = a*w1 + b*w2 to |a|, |b| <= 1/2 so the theta series stay short
+    let a = (z / w1).re - (z / w1).im * tau.re / tau.im;
+    let b = (z / w1).im / tau.im;
+    let z = z - a.round() * w1 - b.round() * w2;
+    if z.norm() < 1e-300 {
+        return Err(FractalError::EvalError("wp has a pole at lattice points".to_string()));
+    }
+
+    let zero = Complex::new(0.0, 0.0);
+    let theta2 = theta(ThetaKind::Two, zero, tau)?;
+    let theta3 = theta(ThetaKind::Three, zero, tau)?;
+    let v = PI * z / w1;
+    let ratio = PI * theta2 * theta3 * theta(ThetaKind::Four, v, tau)? / (w1 * theta(ThetaKind::One, v, tau)?);
+    Ok(ratio * ratio - PI * PI / (3.0 * w1 * w1) * (theta2.powi(4) + theta3.powi(4)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MathEvaluator;
+
+    #[test]
+    fn test_theta_identities() {
+        let tau = Complex::new(0.2, 0.9);
+        let zero = Complex::new(0.0, 0.0);
+        let t = |kind, z| theta(kind, z, tau).unwrap();
+
+        // Jacobi's identity theta3^4 = theta2^4 + theta4^4 at z = 0
+        let lhs = t(ThetaKind::Three, zero).powi(4);
+        let rhs = t(ThetaKind::Two, zero).powi(4) + t(ThetaKind::Four, zero).powi(4);
+        assert!((lhs - rhs).norm() < 1e-12 * lhs.norm());
+
+        // theta1 is odd, theta3 is pi-periodic
+        let z = Complex::new(0.4, 1.3);
+        assert!((t(ThetaKind::One, -z) + t(ThetaKind::One, z)).norm() < 1e-12 * t(ThetaKind::One, z).norm());
+        let shifted = t(ThetaKind::Three, z + PI);
+        assert!((shifted - t(ThetaKind::Three, z)).norm() < 1e-12 * shifted.norm());
+
+        assert!(theta(ThetaKind::Three, z, Complex::new(0.5, -1.0)).is_err());
+    }
+
+    #[test]
+    fn test_weierstrass_p_is_doubly_periodic() {
+        let c = Complex::new(0.0, 0.0);
+        let w1 = Complex::new(1.0, 0.0);
+        let w2 = Complex::new(0.3, 1.1);
+        let z = Complex::new(0.21, 0.17);
+        let p = weierstrass_p(z, w1, w2).unwrap();
+
+        for shift in [w1, w2, 2.0 * w1 - 3.0 * w2] {
+            assert!((weierstrass_p(z + shift, w1, w2).unwrap() - p).norm() < 1e-9 * p.norm());
+        }
+        assert!((weierstrass_p(-z, w1, w2).unwrap() - p).norm() < 1e-9 * p.norm());
+
+        // Near the origin ℘(z) = 1/z² + O(z²)
+        let small = Complex::new(1e-3, 2e-3);
+        assert!((weierstrass_p(small, w1, w2).unwrap() - 1.0 / (small * small)).norm() < 1e-3);
+
+        let parsed = MathEvaluator::evaluate_formula_with_param("wp(z, 1, 0.3 + 1.1i)", z, c).unwrap();
+        assert!((parsed - p).norm() < 1e-9 * p.norm());
+        assert!(weierstrass_p(z, w1, 2.0 * w1).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Parse errors that point at the offending part of a formula
+
+use std::fmt;
+use std::ops::Range;
+
+/// A formula that failed to parse, with the location of the problem
+///
+/// `diagnostic` is the formula followed by a line of carets under the offending token, e.g.
+///
+/// ```text
+/// z^2 + sinn(z)
+///       ^^^^
+/// ```
+///
+/// It is plain text meant for a monospace font (a terminal, or a `<pre>` block in HTML).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaError {
+    /// What went wrong, without position information
+    pub message: String,
+    /// Byte range of the offending text; empty and at the end for an unexpected end of input
+    pub span: Range<usize>,
+    /// The formula with the span underlined
+    pub diagnostic: String,
+}
+
+impl FormulaError {
+    /// Create an error and render its diagnostic
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula that was being parsed
+    /// * `message` - Description of the problem
+    /// * `span` - Byte range of the offending text (clamped to the formula)
+    pub fn new(formula: &str, message: impl Into<String>, span: Range<usize>) -> Self {
+        let end = span.end.min(formula.len());
+        let start = span.start.min(end);
+        Self {
+            message: message.into(),
+            span: start..end,
+            diagnostic: render_diagnostic(formula, start..end),
+        }
+    }
+}
+
+/// Render `formula` with carets under `span`, counting columns in characters
+fn render_diagnostic(formula: &str, span: Range<usize>) -> String {
+    // Line breaks and tabs would throw the caret line out of alignment
+    let line: String = formula.chars().map(|c| if c.is_whitespace() { ' ' } else { c }).collect();
+    let column = formula[..span.start].chars().count();
+    let width = formula[span].chars().count().max(1);
+    format!("{}\n{}{}", line, " ".repeat(column), "^".repeat(width))
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}\n{}", self.message, self.span.start, self.span.end, self.diagnostic)
+    }
+}
+
+impl std::error::Error for FormulaError {}
+
+impl From<FormulaError> for String {
+    fn from(error: FormulaError) -> String {
+        format!("{} at {}..{}", error.message, error.span.start, error.span.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_underlines_span() {
+        let error = FormulaError::new("z^2 + sinn(z)", "Unknown identifier: sinn", 6..10);
+        assert_eq!(error.diagnostic, "z^2 + sinn(z)\n      ^^^^");
+
+        // Columns count characters, not bytes, and an end-of-input error points past the end
+        let error = FormulaError::new("zé + (", "Unexpected end of expression", 7..7);
+        assert_eq!(error.diagnostic, "zé + (\n      ^");
+    }
+
+    #[test]
+    fn test_parse_errors_locate_offending_token() {
+        use crate::{EvaluatorOptions, MathEvaluator};
+
+        let error = MathEvaluator::validate_formula("z^2 + sinn(z)", &EvaluatorOptions::default()).unwrap_err();
+        assert_eq!(error.span, 6..10);
+        assert_eq!(error.diagnostic, "z^2 + sinn(z)\n      ^^^^");
+
+        let error = MathEvaluator::validate_formula("sin(z + c", &EvaluatorOptions::default()).unwrap_err();
+        assert_eq!(error.span, 9..9);
+        let error = MathEvaluator::validate_formula("z^2 + c $", &EvaluatorOptions::default()).unwrap_err();
+        assert_eq!(error.span, 8..9);
+    }
+}
@@ -0,0 +1,193 @@
+//! Escape radii of polynomial formulas
+//!
+//! The bailout radius is usually a guess: 2 works for `z^2 + c` on the Mandelbrot set, but
+//! formulas with large coefficients, such as `z^3 - 3*z + c`, have bounded orbits that pass well
+//! outside it and get colored as escaped. For a polynomial `p(z) = a_d z^d + ... + a_1 z + a_0`
+//! of degree `d >= 2`, writing `S = |a_0| + ... + |a_(d-1)|`, every `|z| > R` with
+//!
+//! `R = max(1, (1 + S) / |a_d|)`
+//!
+//! satisfies `|p(z)| >= |z|^(d-1) (|a_d| |z| - S) > |z|`, and the factor `|a_d| |z| - S` grows
+//! along the orbit, so the orbit is certain to escape to infinity.
+//!
+//! Formulas are compiled to syntax trees that can only be evaluated, so the coefficients are
+//! recovered numerically: a discrete Fourier transform of the formula on the unit circle gives
+//! its Taylor coefficients in `z`. The formula counts as a polynomial if they drop from a clearly
+//! nonzero leading coefficient straight to rounding noise, which entire functions such as
+//! `exp(z)`, whose coefficients shrink gradually, don't, and if the polynomial reproduces the
+//! formula inside the circle.
+//!
+//! The guarantee is for the polynomial itself. The evaluator caps the result of `^` at modulus
+//! 10, so a formula written with `^` follows its polynomial only while those powers stay below
+//! that; orbits past the radius still stay outside it in practice, as the capped powers remain
+//! large.
+
+use super::CompiledFormula;
+use num_complex::Complex;
+use std::f64::consts::PI;
+
+/// Points on the circle the coefficients are computed from, bounding the degree detected
+const SAMPLES: usize = 64;
+
+/// Highest degree treated as a polynomial
+pub const MAX_POLYNOMIAL_DEGREE: usize = 24;
+
+/// Relative size below which a coefficient counts as rounding noise
+const NOISE_LEVEL: f64 = 1e-12;
+
+/// Relative size the leading coefficient must have, well clear of the noise
+const LEADING_LEVEL: f64 = 1e-6;
+
+/// Relative error allowed when checking the polynomial against the formula
+const CHECK_TOLERANCE: f64 = 1e-9;
+
+/// Margin on radii taken over a region of c, which is only sampled
+const REGION_MARGIN: f64 = 1.01;
+
+impl CompiledFormula {
+    /// Coefficients of the formula as a polynomial in z, for a fixed c
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Value of the parameter c
+    ///
+    /// # Returns
+    ///
+    /// `[a_0, a_1, ..., a_d]` with `a_d` nonzero, or `None` if the formula isn't a polynomial
+    /// in z of degree at most `MAX_POLYNOMIAL_DEGREE` (or fails to evaluate)
+    pub fn polynomial_coefficients(&self, c: Complex<f64>) -> Option<Vec<Complex<f64>>> {
+        let values: Vec<Complex<f64>> = (0..SAMPLES)
+            .map(|k| self.try_eval(Complex::from_polar(1.0, 2.0 * PI * k as f64 / SAMPLES as f64), c).ok())
+            .collect::<Option<_>>()?;
+        if values.iter().any(|value| !value.is_finite()) {
+            return None;
+        }
+
+        // a_n = 1/N Σ f(ω^k) ω^(-kn) on the unit circle
+        let coefficients: Vec<Complex<f64>> = (0..SAMPLES)
+            .map(|n| {
+                let sum: Complex<f64> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(k, value)| value * Complex::from_polar(1.0, -2.0 * PI * ((k * n) % SAMPLES) as f64 / SAMPLES as f64))
+                    .sum();
+                sum / SAMPLES as f64
+            })
+            .collect();
+        let scale = coefficients.iter().map(|a| a.norm()).fold(0.0, f64::max);
+        if scale == 0.0 {
+            return None;
+        }
+        let degree = coefficients.iter().rposition(|a| a.norm() > NOISE_LEVEL * scale)?;
+        if degree > MAX_POLYNOMIAL_DEGREE || coefficients[degree].norm() < LEADING_LEVEL * scale {
+            return None;
+        }
+        let mut coefficients = coefficients;
+        coefficients.truncate(degree + 1);
+
+        // Powers of z beyond the number of samples alias onto low ones on the circle, but not
+        // inside it
+        for z in [Complex::from_polar(0.6, 0.4), Complex::from_polar(0.9, 2.3), Complex::from_polar(0.75, -1.9)] {
+            let expected = self.try_eval(z, c).ok()?;
+            let polynomial = coefficients.iter().rev().fold(Complex::new(0.0, 0.0), |sum, &a| sum * z + a);
+            if (polynomial - expected).norm() > CHECK_TOLERANCE * expected.norm().max(scale) {
+                return None;
+            }
+        }
+        Some(coefficients)
+    }
+
+    /// Radius beyond which every orbit of the formula escapes, for a fixed c
+    ///
+    /// # Arguments
+    ///
+    /// * `c` - Value of the parameter c, such as the spawn point of a Julia set
+    ///
+    /// # Returns
+    ///
+    /// `max(1, (1 + |a_0| + ... + |a_(d-1)|) / |a_d|)`, or `None` if the formula isn't a
+    /// polynomial in z of degree 2 or more
+    pub fn escape_radius(&self, c: Complex<f64>) -> Option<f64> {
+        let coefficients = self.polynomial_coefficients(c)?;
+        let (leading, lower) = coefficients.split_last()?;
+        if lower.len() < 2 {
+            return None;
+        }
+        let sum: f64 = lower.iter().map(|a| a.norm()).sum();
+        Some(((1.0 + sum) / leading.norm()).max(1.0))
+    }
+}
+
+/// Radius beyond which every orbit escapes, for all c in a rectangle
+///
+/// The coefficients depend holomorphically on c, so their largest moduli lie on the boundary of
+/// the rectangle, which is sampled along with an interior grid for the smallest leading
+/// coefficient. The result has a 1% margin for the sampling.
+///
+/// # Arguments
+///
+/// * `formula` - The compiled formula
+/// * `bounds` - Values of c as [x_min, x_max, y_min, y_max], such as the view of a Mandelbrot render
+///
+/// # Returns
+///
+/// The radius, or `None` if the formula isn't a polynomial of degree 2 or more in z at every
+/// sampled c
+pub fn escape_radius_over(formula: &CompiledFormula, bounds: [f64; 4]) -> Option<f64> {
+    let [x_min, x_max, y_min, y_max] = bounds;
+    let at = |u: f64, v: f64| Complex::new(x_min + (x_max - x_min) * u, y_min + (y_max - y_min) * v);
+    let steps = 32;
+    let mut points = Vec::new();
+    for i in 0..=steps {
+        let t = i as f64 / steps as f64;
+        points.extend([at(t, 0.0), at(t, 1.0), at(0.0, t), at(1.0, t)]);
+        if i % 4 == 0 {
+            points.extend((0..=steps).step_by(4).map(|j| at(t, j as f64 / steps as f64)));
+        }
+    }
+
+    let mut largest_sum: f64 = 0.0;
+    let mut smallest_leading = f64::INFINITY;
+    for c in points {
+        let coefficients = formula.polynomial_coefficients(c)?;
+        let (leading, lower) = coefficients.split_last()?;
+        if lower.len() < 2 {
+            return None;
+        }
+        largest_sum = largest_sum.max(lower.iter().map(|a| a.norm()).sum());
+        smallest_leading = smallest_leading.min(leading.norm());
+    }
+    Some(((1.0 + largest_sum) / smallest_leading).max(1.0) * REGION_MARGIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_radius_of_polynomials() {
+        let cubic = CompiledFormula::new("2*z*z*z - 3*z + c");
+        let c = Complex::new(0.5, -1.0);
+        let coefficients = cubic.polynomial_coefficients(c).unwrap();
+        assert_eq!(coefficients.len(), 4);
+        assert!((coefficients[1] - Complex::new(-3.0, 0.0)).norm() < 1e-9);
+        let radius = cubic.escape_radius(c).unwrap();
+        assert!((radius - (1.0 + 3.0 + c.norm()) / 2.0).abs() < 1e-9);
+
+        // Just outside the radius the orbit escapes even in the worst direction
+        let mut z = Complex::new(-radius * 1.001, 0.0);
+        for _ in 0..5 {
+            z = cubic.eval(z, c);
+        }
+        assert!(z.norm() > 1e10);
+
+        assert_eq!(CompiledFormula::new("z^2 + c").escape_radius(Complex::new(-2.0, 0.0)), Some(3.0));
+        assert!(CompiledFormula::new("z^5 - z^2 + c").escape_radius(c).is_some());
+        for formula in ["exp(z) + c", "sin(z) * c", "z^2.5 + c", "z^z + c", "2*z + c", "z^2 + 1/z", "z^66 + c"] {
+            assert_eq!(CompiledFormula::new(formula).escape_radius(c), None, "{}", formula);
+        }
+
+        let region = escape_radius_over(&CompiledFormula::new("z^2 + c"), [-2.0, 1.0, -1.5, 1.5]).unwrap();
+        assert!(region >= 1.0 + Complex::new(-2.0, 1.5).norm());
+    }
+}
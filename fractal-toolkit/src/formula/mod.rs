@@ -0,0 +1,30 @@
+//! Formula language metadata
+//!
+//! The expression evaluator in `MathEvaluator` is the source of truth for what formulas can
+//! contain; this module describes that language for frontends, such as the HTML explorer's
+//! formula reference and autocomplete in GUIs. It also holds the options that tune the
+//! evaluator's behaviour and the size limits every formula must meet, the error type that locates parse failures, and `CompiledFormula`,
+//! which parses a formula once for evaluation in iteration loops, and the escape radii derived
+//! from the coefficients of polynomial formulas. `Formula` derives new formulas from existing
+//! ones by composition, substitution and conjugation. `profile` breaks the evaluation time of
+//! compiled formulas down by function.
+
+pub mod algebra;
+pub mod cache;
+pub mod catalog;
+pub mod compiled;
+pub(crate) mod elliptic;
+pub mod error;
+pub mod escape;
+pub mod options;
+pub(crate) mod polynomials;
+pub mod profile;
+pub(crate) mod semantics;
+pub(crate) mod series;
+
+pub use algebra::{Formula, FormulaVariable};
+pub use catalog::{formula_reference_html, FunctionInfo, OperatorInfo};
+pub use compiled::CompiledFormula;
+pub use error::FormulaError;
+pub use escape::escape_radius_over;
+pub use options::{BranchChoice, EvaluatorOptions, ParseMode, SingularValues, MAX_FORMULA_TOKENS, MAX_NESTING_DEPTH};
@@ -0,0 +1,170 @@
+//! Options controlling how formulas are parsed and evaluated, and the limits parses enforce
+
+/// Most tokens a formula from an untrusted source may have (see `EvaluatorOptions::untrusted`)
+///
+/// The syntax tree is parsed, evaluated and dropped recursively, so its size is bounded to keep
+/// hostile formulas from overflowing the stack of a worker thread. Longer formulas are rejected
+/// with a `FormulaError` when they are parsed. Formulas built by the program itself, such as
+/// compositions (`Formula::compose`), are not limited by default.
+pub const MAX_FORMULA_TOKENS: usize = 512;
+
+/// Deepest nesting of parentheses, including function calls, a formula may have
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// How forgiving the formula parser is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Accept everything the parser has historically accepted: multiplication is implied before
+    /// `i` (as in `(z+1)i`), runs of five or more carets are read as hexation, and tokens left over
+    /// after a complete expression are ignored
+    #[default]
+    Lenient,
+    /// Reject ambiguous constructs with an error naming the offending byte range: implicit
+    /// multiplication, `i` run into a following name (`isin(z)`), more than four carets, and
+    /// leftover tokens
+    Strict,
+}
+
+/// Which value of a multivalued function (`log`, `sqrt`, `cbrt`, non-integer powers) is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BranchChoice {
+    /// The principal branch, with the argument in (-pi, pi] and a cut along the negative real
+    /// axis. Orbits that cross the cut jump between branches
+    #[default]
+    Principal,
+    /// Continue each function analytically along the orbit: every evaluation takes the argument
+    /// within pi of the one the same function used on the previous iteration, so there is no
+    /// cut. The continuation restarts at the beginning of every orbit
+    Continuous,
+}
+
+/// What `0^0`, `0^w` with `Re(w) <= 0`, and `log(0)` evaluate to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SingularValues {
+    /// The values the evaluator has always used: `0^0 = 1`, `0^w = 0` for every other `w`,
+    /// and `log(0) = -inf`
+    #[default]
+    Conventional,
+    /// The limits where they exist: `0^0 = 1`, `0^w = inf` for real negative `w`, NaN for the
+    /// other `w` with `Re(w) <= 0`, and `log(0) = -inf`
+    Infinite,
+    /// Report an error, so the iteration falls back to `z^2 + c` for that step like for any
+    /// other failed evaluation (`0^w` with `Re(w) > 0` is still 0)
+    Error,
+}
+
+/// Options for `MathEvaluator`
+#[derive(Debug, Clone, Default)]
+pub struct EvaluatorOptions {
+    /// Parser strictness (lenient by default)
+    pub parse_mode: ParseMode,
+    /// Branch of multivalued functions (principal by default)
+    pub branch: BranchChoice,
+    /// Values at the singular points of powers and logarithms (conventional by default)
+    pub singular_values: SingularValues,
+    /// Most tokens a formula may have, or `None` (the default) for no limit; the nesting depth
+    /// is limited to `MAX_NESTING_DEPTH` either way
+    pub max_tokens: Option<usize>,
+}
+
+impl EvaluatorOptions {
+    /// Options with the strict parser
+    pub fn strict() -> Self {
+        Self { parse_mode: ParseMode::Strict, ..Self::default() }
+    }
+
+    /// Options for formulas from untrusted sources, such as requests to a rendering service:
+    /// the lenient parser with at most `MAX_FORMULA_TOKENS` tokens
+    pub fn untrusted() -> Self {
+        Self { max_tokens: Some(MAX_FORMULA_TOKENS), ..Self::default() }
+    }
+
+    /// Whether evaluation differs from the historical behaviour, which the hand-written
+    /// implementations of common formulas follow
+    pub(crate) fn has_default_semantics(&self) -> bool {
+        self.branch == BranchChoice::Principal && self.singular_values == SingularValues::Conventional
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompiledFormula, MathEvaluator};
+    use num_complex::Complex;
+
+    #[test]
+    fn test_strict_mode_rejects_ambiguous_constructs() {
+        let strict = EvaluatorOptions::strict();
+        assert_eq!(MathEvaluator::validate_formula("z^2 + (c)i", &strict).unwrap_err().span, 9..10);
+        let err = MathEvaluator::validate_formula("z^2 + 2z", &strict).unwrap_err();
+        assert!(err.message.contains("Implicit multiplication"), "{}", err);
+        assert_eq!(err.span, 7..8);
+        assert_eq!(MathEvaluator::validate_formula("isin(z) + c", &strict).unwrap_err().span, 0..4);
+        assert_eq!(MathEvaluator::validate_formula("z^^^^^2 + c", &strict).unwrap_err().span, 1..6);
+
+        assert!(MathEvaluator::validate_formula("z^2 + 0.5i*c + i*sin(z)", &strict).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_formulas_fail_instead_of_overflowing() {
+        let lenient = EvaluatorOptions::default();
+        let untrusted = EvaluatorOptions::untrusted();
+        let chain = |terms: usize| vec!["z"; terms].join("^");
+        assert!(MathEvaluator::validate_formula(&chain(MAX_FORMULA_TOKENS / 2), &untrusted).is_ok());
+        let err = MathEvaluator::validate_formula(&chain(100_000), &untrusted).unwrap_err();
+        assert!(err.message.contains("tokens"), "{}", err);
+        assert_eq!(err.span.start, 2 * (MAX_FORMULA_TOKENS / 2));
+        assert!(MathEvaluator::validate_formula(&chain(MAX_FORMULA_TOKENS), &lenient).is_ok());
+
+        let nested = |depth: usize| format!("{}z{}", "sin(".repeat(depth), ")".repeat(depth));
+        assert!(MathEvaluator::validate_formula(&nested(MAX_NESTING_DEPTH), &lenient).is_ok());
+        let err = MathEvaluator::validate_formula(&nested(MAX_NESTING_DEPTH + 1), &lenient).unwrap_err();
+        assert_eq!(err.span, 4 * MAX_NESTING_DEPTH + 3..4 * MAX_NESTING_DEPTH + 4);
+        // Unbalanced input that would once have recursed until the stack ran out
+        assert!(MathEvaluator::validate_formula(&"(".repeat(1_000_000), &lenient).is_err());
+    }
+
+    #[test]
+    fn test_lenient_mode_keeps_existing_behaviour() {
+        let lenient = EvaluatorOptions::default();
+        let z = Complex::new(0.5, 0.25);
+        let c = Complex::new(0.1, -0.2);
+        let implicit = MathEvaluator::evaluate_formula_with_options("z^2 + (c)i", z, c, &lenient).unwrap();
+        assert!((implicit - (z * z + c * Complex::i())).norm() < 1e-12);
+        // Leftover tokens are ignored: "2z" evaluates as 2
+        let truncated = MathEvaluator::evaluate_formula_with_options("2z", z, c, &lenient).unwrap();
+        assert_eq!(truncated, Complex::new(2.0, 0.0));
+        assert!(MathEvaluator::validate_formula("z^^^^^2 + c", &lenient).is_ok());
+    }
+
+    #[test]
+    fn test_singular_values_and_branches_follow_options() {
+        let zero = Complex::new(0.0, 0.0);
+        let eval = |formula: &str, options: &EvaluatorOptions| MathEvaluator::evaluate_formula_with_options(formula, zero, zero, options);
+        let conventional = EvaluatorOptions::default();
+        let infinite = EvaluatorOptions { singular_values: SingularValues::Infinite, ..EvaluatorOptions::default() };
+        let error = EvaluatorOptions { singular_values: SingularValues::Error, ..EvaluatorOptions::default() };
+
+        assert_eq!(eval("z^0", &conventional).unwrap(), Complex::new(1.0, 0.0));
+        assert_eq!(eval("z^(0 - 2)", &conventional).unwrap(), zero);
+        assert_eq!(eval("z^(0 - 2)", &infinite).unwrap(), Complex::new(f64::INFINITY, 0.0));
+        assert_eq!(eval("z^0", &infinite).unwrap(), Complex::new(1.0, 0.0));
+        assert_eq!(eval("log(z)", &infinite).unwrap().re, f64::NEG_INFINITY);
+        assert!(eval("z^0", &error).is_err());
+        assert!(eval("log(z)", &error).is_err());
+        assert_eq!(eval("z^2", &error).unwrap(), zero);
+
+        // Going once around the origin, the continuous square root ends on the other branch
+        let continuous = EvaluatorOptions { branch: BranchChoice::Continuous, ..EvaluatorOptions::default() };
+        let formula = CompiledFormula::with_options("sqrt(z)", &continuous).unwrap();
+        formula.start_orbit();
+        let mut last = zero;
+        for step in 0..=16 {
+            let angle = std::f64::consts::PI * step as f64 / 8.0;
+            last = formula.try_eval(Complex::from_polar(4.0, angle), zero).unwrap();
+        }
+        assert!((last - Complex::new(-2.0, 0.0)).norm() < 1e-12, "{}", last);
+        let principal = CompiledFormula::with_options("sqrt(z)", &conventional).unwrap();
+        assert!((principal.try_eval(Complex::from_polar(4.0, 2.0 * std::f64::consts::PI), zero).unwrap() - Complex::new(2.0, 0.0)).norm() < 1e-12);
+    }
+}
@@ -0,0 +1,106 @@
+//! Classical orthogonal polynomials for the expression evaluator
+//!
+//! `cheb(n, z)` (Chebyshev, first kind) and `legendre(n, z)` are evaluated with their three-term
+//! recurrences rather than from expanded coefficients, which grow like 2^n and cancel badly
+//! for large degrees.
+
+use super::series::integer_argument;
+use crate::{Expression, FractalError};
+use num_complex::Complex;
+
+/// Highest degree accepted by `cheb` and `legendre`
+pub(crate) const MAX_DEGREE: i64 = 100_000;
+
+/// Which polynomial family a node evaluates
+#[derive(Clone, Copy)]
+pub(crate) enum PolynomialFamily {
+    Chebyshev,
+    Legendre,
+}
+
+/// `cheb(n, z)` or `legendre(n, z)`
+pub(crate) struct OrthogonalPolynomial {
+    pub(crate) family: PolynomialFamily,
+    pub(crate) degree: Box<dyn Expression>,
+    pub(crate) argument: Box<dyn Expression>,
+}
+
+impl Expression for OrthogonalPolynomial {
+    fn name(&self) -> &'static str {
+        match self.family {
+            PolynomialFamily::Chebyshev => "cheb()",
+            PolynomialFamily::Legendre => "legendre()",
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        vec![&mut self.degree, &mut self.argument]
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let degree = integer_argument(self.degree.evaluate(z, param)?, "Polynomial degree")?;
+        if !(0..=MAX_DEGREE).contains(&degree) {
+            return Err(FractalError::EvalError(format!("Polynomial degree must be between 0 and {}, got {}", MAX_DEGREE, degree)));
+        }
+        let x = self.argument.evaluate(z, param)?;
+        Ok(evaluate_polynomial(self.family, degree as u32, x))
+    }
+}
+
+/// Evaluate P_n(x) of the given family by upward recurrence
+///
+/// # Arguments
+///
+/// * `family` - Chebyshev (first kind) or Legendre
+/// * `n` - Degree
+/// * `x` - Argument
+///
+/// # Returns
+///
+/// The polynomial value
+pub(crate) fn evaluate_polynomial(family: PolynomialFamily, n: u32, x: Complex<f64>) -> Complex<f64> {
+    let mut previous = Complex::new(1.0, 0.0); // P_0
+    if n == 0 {
+        return previous;
+    }
+    let mut current = x; // P_1 for both families
+    for k in 1..n {
+        let next = match family {
+            // T_{k+1} = 2x T_k - T_{k-1}
+            PolynomialFamily::Chebyshev => 2.0 * x * current - previous,
+            // (k+1) P_{k+1} = (2k+1) x P_k - k P_{k-1}
+            PolynomialFamily::Legendre => {
+                let k = k as f64;
+                ((2.0 * k + 1.0) * x * current - k * previous) / (k + 1.0)
+            }
+        };
+        previous = current;
+        current = next;
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MathEvaluator;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_recurrences_match_closed_forms() {
+        let z = Complex::new(0.3, -0.7);
+        let c = Complex::new(0.0, 0.0);
+        let eval = |formula: &str| MathEvaluator::evaluate_formula_with_param(formula, z, c).unwrap();
+
+        assert!((eval("cheb(3, z)") - (4.0 * z * z * z - 3.0 * z)).norm() < 1e-12);
+        assert!((eval("legendre(3, z)") - (5.0 * z * z * z - 3.0 * z) / 2.0).norm() < 1e-12);
+        assert_eq!(eval("cheb(0, z) + legendre(0, z)"), Complex::new(2.0, 0.0));
+
+        // T_n(cos t) = cos(n t) stays accurate at high degree, where expanded coefficients would not
+        let t: f64 = 0.4;
+        let high = MathEvaluator::evaluate_formula_with_param("cheb(500, z)", Complex::new(t.cos(), 0.0), c).unwrap();
+        assert!((high.re - (500.0 * t).cos()).abs() < 1e-9);
+
+        assert!(MathEvaluator::evaluate_formula_with_param("cheb(-1, z)", z, c).is_err());
+        assert!(MathEvaluator::evaluate_formula_with_param("cheb(z)", z, c).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! Per-function evaluation statistics of compiled formulas
+//!
+//! A slow custom formula is usually slow because of one function in it. Profiling wraps every
+//! node of a compiled formula's syntax tree in a timer that counts its evaluations and the time
+//! spent in the node itself, excluding its operands, and aggregates them by node name, so a
+//! report after a render shows that e.g. `gamma()` took 80% of the evaluation time.
+//!
+//! `CompiledFormula::profiled` profiles a formula the caller holds on to. For the renderers,
+//! which compile their formulas internally with `CompiledFormula::new`, `set_profiling(true)`
+//! makes every formula compiled that way record into a shared profile per formula, read back
+//! with `evaluation_profiles` after the render. Only the `MAX_SHARED_PROFILES` most recently
+//! profiled formulas are kept, so a long animation whose formula changes every frame doesn't
+//! accumulate profiles without end. Formulas with a hand-written implementation are
+//! timed as a whole, and formulas the vectorized kernels run (see `iteration::simd`) aren't
+//! evaluated by the compiled evaluator at all and record nothing.
+//!
+//! Timing every node costs a clock read or two per node evaluation, which inflates the cheap
+//! nodes (`z`, constants, `+`) and slows the render, so profiling is off by default.
+
+use crate::algebra::Dual;
+use crate::{Constant, Expression, FractalError};
+use num_complex::Complex;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Name under which formulas with a hand-written implementation are timed
+pub const HAND_WRITTEN: &str = "hand-written formula";
+
+/// Most formulas `evaluation_profiles` keeps profiles of; the oldest is dropped for a new one
+pub const MAX_SHARED_PROFILES: usize = 64;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROFILES: Mutex<VecDeque<Arc<EvaluationProfile>>> = Mutex::new(VecDeque::new());
+/// Incremented whenever profiles are reset or dropped, so that formulas compiled before then,
+/// including those cached by `compiled_for`, are compiled again and record into a kept profile
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Time spent in the operands of the node being evaluated on this thread
+    static OPERAND_NANOS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Invocation counts and times of one kind of node
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStats {
+    /// Node name, e.g. `gamma()`, `^` or `z`
+    pub name: &'static str,
+    /// Evaluations of nodes with this name
+    pub calls: u64,
+    /// Time spent in these nodes, excluding their operands
+    pub time: Duration,
+    /// Fraction of the profile's total time
+    pub share: f64,
+}
+
+struct NodeCounters {
+    name: &'static str,
+    calls: AtomicU64,
+    nanos: AtomicU64,
+}
+
+/// Evaluation statistics of one formula, by node name
+pub struct EvaluationProfile {
+    source: String,
+    nodes: Vec<NodeCounters>,
+    /// Profiling generation of a shared profile, updated whenever a formula is compiled into it
+    /// again; `None` for a private one
+    generation: Option<AtomicU64>,
+}
+
+impl EvaluationProfile {
+    fn new(source: &str, names: Vec<&'static str>, generation: Option<AtomicU64>) -> Self {
+        let nodes = names
+            .into_iter()
+            .map(|name| NodeCounters { name, calls: AtomicU64::new(0), nanos: AtomicU64::new(0) })
+            .collect();
+        EvaluationProfile { source: source.to_string(), nodes, generation }
+    }
+
+    /// The formula profiled
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Statistics of every kind of node in the formula, the most time-consuming first
+    pub fn stats(&self) -> Vec<NodeStats> {
+        let total = self.total_time().as_secs_f64();
+        let mut stats: Vec<NodeStats> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let time = Duration::from_nanos(node.nanos.load(Ordering::Relaxed));
+                let share = if total > 0.0 { time.as_secs_f64() / total } else { 0.0 };
+                NodeStats { name: node.name, calls: node.calls.load(Ordering::Relaxed), time, share }
+            })
+            .collect();
+        stats.sort_by(|a, b| b.time.cmp(&a.time).then(b.calls.cmp(&a.calls)));
+        stats
+    }
+
+    /// Time spent evaluating the formula, summed over all threads
+    pub fn total_time(&self) -> Duration {
+        Duration::from_nanos(self.nodes.iter().map(|node| node.nanos.load(Ordering::Relaxed)).sum())
+    }
+
+    /// Clear the counts, e.g. between renders
+    pub fn reset(&self) {
+        for node in &self.nodes {
+            node.calls.store(0, Ordering::Relaxed);
+            node.nanos.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Run `evaluate` as an evaluation of the node in `slot`, recording its own time
+    pub(crate) fn time<T>(&self, slot: usize, evaluate: impl FnOnce() -> T) -> T {
+        let outer = OPERAND_NANOS.with(|nanos| nanos.replace(0));
+        let start = Instant::now();
+        let value = evaluate();
+        let elapsed = start.elapsed().as_nanos() as u64;
+        let operands = OPERAND_NANOS.with(|nanos| nanos.replace(outer + elapsed));
+        let node = &self.nodes[slot];
+        node.calls.fetch_add(1, Ordering::Relaxed);
+        node.nanos.fetch_add(elapsed.saturating_sub(operands), Ordering::Relaxed);
+        value
+    }
+
+    fn slot(&self, name: &str) -> usize {
+        self.nodes.iter().position(|node| node.name == name).expect("profiles have a slot per node name")
+    }
+}
+
+/// A table of the nodes by time, with their share, call count and time per call
+impl fmt::Display for EvaluationProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.stats();
+        let calls: u64 = stats.iter().map(|node| node.calls).sum();
+        writeln!(f, "Evaluation profile of {}: {:.3?} in {} node evaluations", self.source, self.total_time(), calls)?;
+        for node in stats.iter().filter(|node| node.calls > 0) {
+            writeln!(
+                f,
+                "  {:<22} {:>6.1}%  {:>12.3?}  {:>12} calls  {:>9.1?}/call",
+                node.name,
+                100.0 * node.share,
+                node.time,
+                node.calls,
+                Duration::from_secs_f64(node.time.as_secs_f64() / node.calls as f64)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A node evaluated under a timer
+struct Timed {
+    slot: usize,
+    inner: Box<dyn Expression>,
+    profile: Arc<EvaluationProfile>,
+}
+
+impl Expression for Timed {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        self.profile.time(self.slot, || self.inner.evaluate(z, param))
+    }
+
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
+        self.profile.time(self.slot, || self.inner.evaluate_dual(z, param))
+    }
+}
+
+/// Names of the nodes in `ast`, each once, in the order they're first met
+pub(crate) fn node_names(ast: &mut Box<dyn Expression>) -> Vec<&'static str> {
+    fn collect(node: &mut Box<dyn Expression>, names: &mut Vec<&'static str>) {
+        if !names.contains(&node.name()) {
+            names.push(node.name());
+        }
+        for child in node.children_mut() {
+            collect(child, names);
+        }
+    }
+    let mut names = Vec::new();
+    collect(ast, &mut names);
+    names
+}
+
+/// Wrap every node of `ast` in a timer recording into `profile`, which has a slot for each
+/// of the names `node_names` lists
+pub(crate) fn instrument(ast: &mut Box<dyn Expression>, profile: &Arc<EvaluationProfile>) {
+    for child in ast.children_mut() {
+        instrument(child, profile);
+    }
+    let slot = profile.slot(ast.name());
+    let inner = std::mem::replace(ast, Box::new(Constant(Complex::new(0.0, 0.0))));
+    *ast = Box::new(Timed { slot, inner, profile: Arc::clone(profile) });
+}
+
+/// A profile with slots for `names`, shared by every formula compiled from `source` with
+/// `CompiledFormula::new` while profiling is on, or a private one
+pub(crate) fn profile_for(source: &str, names: Vec<&'static str>, shared: bool) -> Arc<EvaluationProfile> {
+    if !shared {
+        return Arc::new(EvaluationProfile::new(source, names, None));
+    }
+    let mut profiles = PROFILES.lock().unwrap();
+    let generation = GENERATION.load(Ordering::Relaxed);
+    if let Some(profile) = profiles.iter().find(|profile| profile.source == source) {
+        if let Some(current) = &profile.generation {
+            current.store(generation, Ordering::Relaxed);
+        }
+        return Arc::clone(profile);
+    }
+    if profiles.len() == MAX_SHARED_PROFILES {
+        profiles.pop_front();
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+    let profile = Arc::new(EvaluationProfile::new(source, names, Some(AtomicU64::new(GENERATION.load(Ordering::Relaxed)))));
+    profiles.push_back(Arc::clone(&profile));
+    profile
+}
+
+/// Whether a formula compiled with `profile` still matches the profiling setting, so that a
+/// cached compilation can be reused
+pub(crate) fn is_current(profile: Option<&EvaluationProfile>) -> bool {
+    match profile {
+        None => !profiling_enabled(),
+        Some(profile) => {
+            profiling_enabled()
+                && profile.generation.as_ref().is_some_and(|current| current.load(Ordering::Relaxed) == GENERATION.load(Ordering::Relaxed))
+        }
+    }
+}
+
+/// Turn profiling of the formulas the renderers compile on or off
+///
+/// Takes effect for formulas compiled afterwards; formulas compiled before and cached since
+/// (see `compiled::compiled_for`) are compiled again on their next use.
+pub fn set_profiling(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the renderers profile the formulas they compile
+pub fn profiling_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Profiles of the last `MAX_SHARED_PROFILES` formulas rendered while profiling was on, in the
+/// order they were first used
+pub fn evaluation_profiles() -> Vec<Arc<EvaluationProfile>> {
+    PROFILES.lock().unwrap().iter().cloned().collect()
+}
+
+/// Forget the profiles `evaluation_profiles` returns; formulas compiled afterwards record into
+/// new ones
+pub fn reset_evaluation_profiles() {
+    let mut profiles = PROFILES.lock().unwrap();
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    profiles.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::{CompiledFormula, EvaluatorOptions};
+
+    #[test]
+    fn test_profile_counts_nodes_and_attributes_time_to_them() {
+        let formula = "z^2 + gamma(c) * 0.5";
+        let compiled = CompiledFormula::profiled(formula, &EvaluatorOptions::default()).unwrap();
+        let plain = CompiledFormula::new(formula);
+        let (z, c) = (Complex::new(0.3, -0.2), Complex::new(1.7, 0.4));
+        for _ in 0..100 {
+            assert_eq!(compiled.try_eval(z, c), plain.try_eval(z, c));
+        }
+
+        let profile = compiled.profile().unwrap();
+        let stats = profile.stats();
+        let calls = |name: &str| stats.iter().find(|node| node.name == name).map(|node| node.calls);
+        assert_eq!(calls("gamma()"), Some(100));
+        assert_eq!(calls("+"), Some(100));
+        assert_eq!(calls("constant"), Some(200));
+        assert!(stats.windows(2).all(|pair| pair[0].time >= pair[1].time));
+        assert!((stats.iter().map(|node| node.share).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(profile.to_string().contains("gamma()"));
+
+        profile.reset();
+        assert_eq!(profile.total_time(), Duration::ZERO);
+        assert!(CompiledFormula::profiled("z^2 + c", &EvaluatorOptions::default()).unwrap().profile().is_some());
+    }
+
+    #[test]
+    fn test_shared_profiles_cover_cached_formulas_and_stay_bounded() {
+        use crate::formula::compiled::compiled_for;
+        let formula = "z^2 + c * 0.123456";
+        let profiled = |source: &str| evaluation_profiles().into_iter().find(|profile| profile.source() == source);
+        let (z, c) = (Complex::new(0.1, 0.2), Complex::new(-0.3, 0.4));
+
+        // Cached before profiling is switched on, and compiled again once it is
+        assert!(compiled_for(formula).profile().is_none());
+        set_profiling(true);
+        let compiled = compiled_for(formula);
+        assert!(compiled.profile().is_some());
+        assert!(Arc::ptr_eq(&compiled, &compiled_for(formula)));
+        compiled.try_eval(z, c).unwrap();
+        assert!(profiled(formula).is_some_and(|profile| profile.stats().iter().any(|node| node.calls > 0)));
+
+        for index in 0..=MAX_SHARED_PROFILES {
+            CompiledFormula::new(&format!("z^2 + c * {}", index));
+        }
+        assert!(evaluation_profiles().len() <= MAX_SHARED_PROFILES);
+        assert!(profiled(formula).is_none());
+        // The dropped profile's cached formula records into a new, reported profile
+        let recompiled = compiled_for(formula);
+        assert!(!Arc::ptr_eq(&compiled, &recompiled));
+        recompiled.try_eval(z, c).unwrap();
+        assert!(profiled(formula).is_some());
+        set_profiling(false);
+        assert!(compiled_for(formula).profile().is_none());
+    }
+}
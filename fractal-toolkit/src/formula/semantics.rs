@@ -0,0 +1,101 @@
+//! Evaluation-time state behind `BranchChoice` and `SingularValues`
+//!
+//! Syntax tree nodes don't receive the evaluator options, so the options in force are kept in a
+//! thread-local for the duration of an evaluation. With `BranchChoice::Continuous`, each node that
+//! takes an argument remembers the last one it used, keyed by the node's address; that record is
+//! cleared when an orbit starts and whenever a formula is parsed for a single evaluation.
+
+use super::options::{BranchChoice, EvaluatorOptions, SingularValues};
+use crate::FractalError;
+use num_complex::Complex;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+thread_local! {
+    static ACTIVE: Cell<(BranchChoice, SingularValues)> = const { Cell::new((BranchChoice::Principal, SingularValues::Conventional)) };
+    static PREVIOUS_ARG: RefCell<HashMap<usize, f64>> = RefCell::new(HashMap::new());
+}
+
+/// Run `evaluate` with the branch and singular-value choices of `options` in force
+pub(crate) fn with_options<R>(options: &EvaluatorOptions, evaluate: impl FnOnce() -> R) -> R {
+    let outer = ACTIVE.with(|active| active.replace((options.branch, options.singular_values)));
+    let result = evaluate();
+    ACTIVE.with(|active| active.set(outer));
+    result
+}
+
+/// Branch choice of the evaluation in progress
+pub(crate) fn branch() -> BranchChoice {
+    ACTIVE.with(|active| active.get().0)
+}
+
+/// Singular-value policy of the evaluation in progress
+pub(crate) fn singular_values() -> SingularValues {
+    ACTIVE.with(|active| active.get().1)
+}
+
+/// Forget the arguments remembered for continuous branches, at the start of a new orbit
+pub(crate) fn start_orbit() {
+    PREVIOUS_ARG.with(|previous| {
+        let mut previous = previous.borrow_mut();
+        if !previous.is_empty() {
+            previous.clear();
+        }
+    });
+}
+
+/// Argument of `z` on the branch in force for the node at address `node`
+///
+/// Under `BranchChoice::Continuous` this is the principal argument shifted by a multiple of 2π
+/// to lie within π of the one the node returned last.
+pub(crate) fn branch_arg(node: usize, z: Complex<f64>) -> f64 {
+    let principal = z.arg();
+    if branch() == BranchChoice::Principal || !principal.is_finite() {
+        return principal;
+    }
+    PREVIOUS_ARG.with(|previous| {
+        let mut previous = previous.borrow_mut();
+        let arg = match previous.get(&node) {
+            Some(&last) => principal + 2.0 * PI * ((last - principal) / (2.0 * PI)).round(),
+            None => principal,
+        };
+        previous.insert(node, arg);
+        arg
+    })
+}
+
+/// `log(z)` on the branch in force; `log(0)` follows the singular-value policy
+pub(crate) fn ln(node: usize, z: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+    if z == Complex::new(0.0, 0.0) && singular_values() == SingularValues::Error {
+        return Err(FractalError::EvalError("log(0) is undefined".to_string()));
+    }
+    match branch() {
+        BranchChoice::Principal => Ok(z.ln()),
+        BranchChoice::Continuous => Ok(Complex::new(z.norm().ln(), branch_arg(node, z))),
+    }
+}
+
+/// `z^(1/n)` on the branch in force, the `n`th root used by `sqrt` and `cbrt`
+pub(crate) fn root(node: usize, z: Complex<f64>, n: u32) -> Complex<f64> {
+    match branch() {
+        BranchChoice::Principal if n == 2 => z.sqrt(),
+        BranchChoice::Principal => z.powf(1.0 / n as f64),
+        BranchChoice::Continuous => Complex::from_polar(z.norm().powf(1.0 / n as f64), branch_arg(node, z) / n as f64),
+    }
+}
+
+/// `0^w` under the singular-value policy in force
+pub(crate) fn zero_power(exponent: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+    // Exponents this close to zero count as zero, matching the base's tolerance
+    let is_zero = exponent.norm_sqr() < 1e-10;
+    match singular_values() {
+        SingularValues::Conventional => Ok(Complex::new(if is_zero { 1.0 } else { 0.0 }, 0.0)),
+        SingularValues::Infinite if is_zero => Ok(Complex::new(1.0, 0.0)),
+        SingularValues::Infinite if exponent.re > 0.0 => Ok(Complex::new(0.0, 0.0)),
+        SingularValues::Infinite if exponent.im.abs() < 1e-10 => Ok(Complex::new(f64::INFINITY, 0.0)),
+        SingularValues::Infinite => Ok(Complex::new(f64::NAN, f64::NAN)),
+        SingularValues::Error if exponent.re > 0.0 && !is_zero => Ok(Complex::new(0.0, 0.0)),
+        SingularValues::Error => Err(FractalError::EvalError(format!("0^({}) is undefined", exponent))),
+    }
+}
@@ -0,0 +1,208 @@
+//! Series and continued-fraction nodes for the expression evaluator
+//!
+//! `sum(k, from, to, expr)` and `prod(k, from, to, expr)` bind an index variable that `expr` can
+//! use; `cfrac(a0, a1, ..., an, depth)` evaluates the continued fraction
+//! `a0 + 1/(a1 + 1/(a2 + ...))` with `depth` partial denominators, repeating `a1..an` when
+//! `depth > n`. Together they let truncated series such as the exponential series
+//! `sum(k, 0, 20, z^k / prod(j, 1, k, j))` be written directly in a formula.
+//!
+//! Bound variables are resolved while parsing to a de Bruijn index (0 = innermost enclosing
+//! `sum`/`prod`), and their values live on a per-thread stack during evaluation, so parsed
+//! expressions stay immutable and can be evaluated from several threads.
+
+use crate::{Expression, FractalError};
+use num_complex::Complex;
+use std::cell::RefCell;
+
+/// Most terms a single `sum`, `prod` or `cfrac` may evaluate
+pub(crate) const MAX_TERMS: i64 = 100_000;
+
+thread_local! {
+    /// Names bound by the enclosing `sum`/`prod` calls while parsing, innermost last
+    static BOUND_NAMES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    /// Current values of the bound variables while evaluating, innermost last
+    static BOUND_VALUES: RefCell<Vec<Complex<f64>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `parse` with `name` in scope as a bound variable
+pub(crate) fn with_bound_name<T>(name: &str, parse: impl FnOnce() -> T) -> T {
+    BOUND_NAMES.with(|names| names.borrow_mut().push(name.to_string()));
+    let result = parse();
+    BOUND_NAMES.with(|names| names.borrow_mut().pop());
+    result
+}
+
+/// De Bruijn index of a bound variable in scope, or `None` if `name` isn't bound
+pub(crate) fn lookup_bound_name(name: &str) -> Option<usize> {
+    BOUND_NAMES.with(|names| names.borrow().iter().rev().position(|bound| bound == name))
+}
+
+/// Values of the bound variables of the `sum`/`prod` calls being evaluated, innermost last
+pub(crate) fn bound_values() -> Vec<Complex<f64>> {
+    BOUND_VALUES.with(|values| values.borrow().clone())
+}
+
+/// A reference to the variable of an enclosing `sum`/`prod`
+pub(crate) struct BoundVariable(pub(crate) usize);
+
+impl Expression for BoundVariable {
+    fn name(&self) -> &'static str {
+        "bound variable"
+    }
+
+    fn evaluate(&self, _z: Complex<f64>, _param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        BOUND_VALUES.with(|values| {
+            let values = values.borrow();
+            values
+                .len()
+                .checked_sub(self.0 + 1)
+                .map(|slot| values[slot])
+                .ok_or_else(|| FractalError::EvalError("Summation variable used outside its sum".to_string()))
+        })
+    }
+}
+
+/// Integer value of a range bound or depth argument
+pub(crate) fn integer_argument(value: Complex<f64>, what: &str) -> Result<i64, FractalError> {
+    if !value.re.is_finite() || value.re.abs() > 1e15 {
+        return Err(FractalError::EvalError(format!("{} must be a finite integer, got {}", what, value)));
+    }
+    Ok(value.re.round() as i64)
+}
+
+/// Whether a series adds or multiplies its terms
+#[derive(Clone, Copy)]
+pub(crate) enum SeriesKind {
+    Sum,
+    Product,
+}
+
+/// `sum(k, from, to, body)` or `prod(k, from, to, body)` over integer k
+pub(crate) struct Series {
+    pub(crate) kind: SeriesKind,
+    pub(crate) from: Box<dyn Expression>,
+    pub(crate) to: Box<dyn Expression>,
+    pub(crate) body: Box<dyn Expression>,
+}
+
+impl Expression for Series {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            SeriesKind::Sum => "sum()",
+            SeriesKind::Product => "prod()",
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        vec![&mut self.from, &mut self.to, &mut self.body]
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let from = integer_argument(self.from.evaluate(z, param)?, "Series start")?;
+        let to = integer_argument(self.to.evaluate(z, param)?, "Series end")?;
+        if to - from >= MAX_TERMS {
+            return Err(FractalError::EvalError(format!("Series has {} terms; at most {} are allowed", to - from + 1, MAX_TERMS)));
+        }
+
+        // An empty range gives the identity: 0 for sums, 1 for products
+        let mut total = match self.kind {
+            SeriesKind::Sum => Complex::new(0.0, 0.0),
+            SeriesKind::Product => Complex::new(1.0, 0.0),
+        };
+        BOUND_VALUES.with(|values| values.borrow_mut().push(Complex::new(0.0, 0.0)));
+        let result = (from..=to).try_for_each(|k| {
+            BOUND_VALUES.with(|values| *values.borrow_mut().last_mut().unwrap() = Complex::new(k as f64, 0.0));
+            let term = self.body.evaluate(z, param)?;
+            match self.kind {
+                SeriesKind::Sum => total += term,
+                SeriesKind::Product => total *= term,
+            }
+            Ok::<(), FractalError>(())
+        });
+        BOUND_VALUES.with(|values| values.borrow_mut().pop());
+        result.map(|_| total)
+    }
+}
+
+/// `cfrac(a0, a1, ..., an, depth)`
+pub(crate) struct ContinuedFraction {
+    /// a0 followed by the partial denominators a1..an
+    pub(crate) terms: Vec<Box<dyn Expression>>,
+    pub(crate) depth: Box<dyn Expression>,
+}
+
+impl Expression for ContinuedFraction {
+    fn name(&self) -> &'static str {
+        "cfrac()"
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        self.terms.iter_mut().chain(std::iter::once(&mut self.depth)).collect()
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        let depth = integer_argument(self.depth.evaluate(z, param)?, "cfrac depth")?;
+        if !(0..=MAX_TERMS).contains(&depth) {
+            return Err(FractalError::EvalError(format!("cfrac depth must be between 0 and {}, got {}", MAX_TERMS, depth)));
+        }
+        let terms = self
+            .terms
+            .iter()
+            .map(|term| term.evaluate(z, param))
+            .collect::<Result<Vec<_>, FractalError>>()?;
+        let denominators = &terms[1..];
+        if depth == 0 || denominators.is_empty() {
+            return Ok(terms[0]);
+        }
+
+        // Evaluate from the innermost level outwards, cycling through a1..an
+        let depth = depth as usize;
+        let mut tail = denominators[(depth - 1) % denominators.len()];
+        for level in (0..depth - 1).rev() {
+            if tail.norm_sqr() < f64::EPSILON {
+                return Err(FractalError::DivisionByZero);
+            }
+            tail = denominators[level % denominators.len()] + tail.inv();
+        }
+        if tail.norm_sqr() < f64::EPSILON {
+            return Err(FractalError::DivisionByZero);
+        }
+        Ok(terms[0] + tail.inv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MathEvaluator;
+    use num_complex::Complex;
+
+    fn eval(formula: &str, z: Complex<f64>) -> Complex<f64> {
+        MathEvaluator::evaluate_formula_with_param(formula, z, Complex::new(0.0, 0.0)).unwrap()
+    }
+
+    #[test]
+    fn test_sum_and_prod_bind_index_variable() {
+        let z = Complex::new(0.3, 0.2);
+        // Geometric series and a nested sum using both bound variables
+        let geometric = eval("sum(k, 0, 20, z^k)", z);
+        assert!((geometric - (Complex::new(1.0, 0.0) - z.powi(21)) / (Complex::new(1.0, 0.0) - z)).norm() < 1e-12);
+        assert_eq!(eval("sum(n, 1, 3, sum(m, 1, n, m))", z), Complex::new(10.0, 0.0));
+        assert_eq!(eval("prod(k, 1, 5, k)", z), Complex::new(120.0, 0.0));
+        assert_eq!(eval("sum(k, 3, 1, k) + prod(k, 3, 1, k)", z), Complex::new(1.0, 0.0));
+
+        // The bound variable is out of scope after the sum
+        assert!(MathEvaluator::evaluate_formula_with_param("sum(k, 1, 2, k) + k", z, z).is_err());
+        assert!(MathEvaluator::evaluate_formula_with_param("sum(z, 1, 2, z)", z, z).is_err());
+    }
+
+    #[test]
+    fn test_cfrac_repeats_denominators() {
+        let golden = (1.0 + 5f64.sqrt()) / 2.0;
+        assert!((eval("cfrac(1, 1, 40)", Complex::new(0.0, 0.0)).re - golden).abs() < 1e-12);
+        // 1 + 1/(2 + 1/(2 + ...)) converges to sqrt(2)
+        assert!((eval("cfrac(1, 2, 30)", Complex::new(0.0, 0.0)).re - 2f64.sqrt()).abs() < 1e-12);
+        let z = Complex::new(0.5, 1.0);
+        let expected = z + 1.0 / (2.0 + 1.0 / 3.0);
+        assert!((eval("cfrac(z, 2, 3, 2)", z) - expected).norm() < 1e-12);
+    }
+}
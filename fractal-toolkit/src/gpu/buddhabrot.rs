@@ -0,0 +1,333 @@
+//! Buddhabrot accumulation on the GPU
+//!
+//! Each shader invocation draws one sample from a counter-based random stream, iterates it to
+//! find its escape time and, if the channel wants the orbit, iterates it again and adds every
+//! orbit point to a histogram in device memory with atomic adds. Only the finished histogram is
+//! read back, so the CPU does nothing but denoising and combining the channels.
+//!
+//! The samples come from a different random stream than on the CPU, so images are statistically
+//! the same as `generate_buddhabrot` but not identical to it. Orbits are computed in `f32`, the
+//! escape-time prefilter is not used, and each pixel counts up to `u32::MAX` visits.
+
+use super::{wgsl, GpuRenderer};
+use crate::{buddhabrot, combine_buddhabrot_channels, postfx, BuddhabrotChannel, BuddhabrotParams, DensityGrid, FractalError, BUDDHABROT_SEED};
+use num_complex::Complex;
+
+/// Bytes in the shader's parameter block
+const PARAMS_SIZE: u64 = 80;
+
+/// Invocations per workgroup, each handling one sample
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Samples per dispatch, small enough that no single dispatch runs for long
+const SAMPLES_PER_DISPATCH: u64 = 1 << 20;
+
+impl GpuRenderer {
+    /// Accumulate one Buddhabrot channel on the GPU, the counterpart of `buddhabrot_channel`
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Buddhabrot parameters; the formula must be supported by `wgsl::formula_to_wgsl`
+    /// * `channel_params` - Channel-specific parameters (min/max iterations, sample count)
+    /// * `bounded` - Accumulate the orbits of points that don't escape (the anti-Buddhabrot)
+    ///
+    /// # Returns
+    ///
+    /// The histogram, or an error as from `GpuRenderer::results` if the parameters can't be
+    /// rendered on the GPU; a cancelled render has only the batches dispatched before the cancel
+    pub fn buddhabrot_channel(
+        &self,
+        params: &BuddhabrotParams,
+        channel_params: &BuddhabrotChannel,
+        bounded: bool,
+    ) -> Result<DensityGrid, FractalError> {
+        if params.i_sqrt_value != Complex::new(0.0, 1.0) {
+            return Err(FractalError::InvalidInput("The GPU backend only supports the standard imaginary unit".to_string()));
+        }
+        let (width, height) = (params.width as usize, params.height as usize);
+        let histogram_bytes = (width * height * 4) as u64;
+        if histogram_bytes == 0 {
+            return Ok(DensityGrid::new(width, height));
+        }
+        if histogram_bytes > self.device.limits().max_storage_buffer_binding_size as u64 {
+            return Err(FractalError::InvalidInput(format!("A {}x{} histogram doesn't fit in one GPU storage buffer", width, height)));
+        }
+
+        let source = buddhabrot_shader_source(&params.formula).map_err(FractalError::UnsupportedFormula)?;
+        let pipeline = self.compile(source, &params.formula)?;
+        let params_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buddhabrot params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // New buffers are zeroed, which is the empty histogram
+        let histogram = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buddhabrot histogram"),
+            size: histogram_bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buddhabrot readback"),
+            size: histogram_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("buddhabrot bindings"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: histogram.as_entire_binding() },
+            ],
+        });
+
+        let start = std::time::Instant::now();
+        let total_samples = channel_params.samples;
+        println!("Generating Buddhabrot channel on GPU ({}): 0% (0/{})", self.adapter_name, total_samples);
+        let batch_limit = SAMPLES_PER_DISPATCH
+            .min(self.device.limits().max_compute_workgroups_per_dimension as u64 * WORKGROUP_SIZE as u64);
+        let mut done = 0;
+        let mut last_percent = 0;
+        while done < total_samples && !params.cancel.is_cancelled() {
+            let batch = batch_limit.min(total_samples - done);
+            self.queue.write_buffer(&params_buffer, 0, &buddhabrot_params_bytes(params, channel_params, bounded, done, batch));
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups((batch as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+            }
+            self.queue.submit(Some(encoder.finish()));
+            self.device.poll(wgpu::Maintain::Wait);
+            done += batch;
+
+            let percent = done * 100 / total_samples;
+            if percent / 10 > last_percent / 10 && done < total_samples {
+                println!("Generating Buddhabrot channel on GPU: {}% ({}/{})", percent, done, total_samples);
+            }
+            last_percent = percent;
+        }
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&histogram, 0, &readback, 0, histogram_bytes);
+        self.queue.submit(Some(encoder.finish()));
+        let slice = readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| FractalError::GpuError(format!("GPU readback was cancelled: {}", e)))?
+            .map_err(|e| FractalError::GpuError(format!("Failed to read the GPU histogram: {}", e)))?;
+
+        let counts: Vec<f64> = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64)
+            .collect();
+        readback.unmap();
+
+        println!(
+            "Generating Buddhabrot channel on GPU: 100% ({}/{}), Completed in {:.1}s",
+            total_samples, total_samples, start.elapsed().as_secs_f64()
+        );
+        DensityGrid::from_vec(width, height, counts).map_err(FractalError::GpuError)
+    }
+
+    /// Render a complete Buddhabrot image on the GPU, the counterpart of `generate_buddhabrot`
+    ///
+    /// The channels are accumulated on the GPU; denoising, combining and post effects run on the
+    /// CPU as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Complete Buddhabrot parameters including all channel configurations
+    ///
+    /// # Returns
+    ///
+    /// The image, or an error as from `buddhabrot_channel`
+    pub fn generate_buddhabrot(&self, params: &BuddhabrotParams) -> Result<image::RgbImage, FractalError> {
+        let [red_bounded, green_bounded, blue_bounded] = params.orbits.bounded_channels();
+        let mut red_hist = self.buddhabrot_channel(params, &params.channels.red, red_bounded)?;
+        let mut green_hist = self.buddhabrot_channel(params, &params.channels.green, green_bounded)?;
+        let mut blue_hist = self.buddhabrot_channel(params, &params.channels.blue, blue_bounded)?;
+        buddhabrot::denoise::denoise_channels(params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+
+        let mut img = combine_buddhabrot_channels(params.width, params.height, &red_hist, &green_hist, &blue_hist, params.channel_mix.as_ref(), params.display_transform);
+        postfx::apply_post_effects_rgb(&mut img, &params.post_effects);
+        Ok(img)
+    }
+}
+
+/// Complete compute shader accumulating the orbits of `formula`
+///
+/// # Arguments
+///
+/// * `formula` - The iterated formula
+///
+/// # Returns
+///
+/// WGSL source with the entry point `main`, or an error if the formula can't be lowered
+pub fn buddhabrot_shader_source(formula: &str) -> Result<String, String> {
+    let expression = wgsl::formula_to_wgsl(formula)?;
+    Ok(format!(
+        r#"{prelude}
+struct Params {{
+    bounds: vec4<f32>,
+    sample_bounds: vec4<f32>,
+    size: vec2<u32>,
+    sample_offset: vec2<u32>,
+    samples: u32,
+    min_iter: u32,
+    max_iter: u32,
+    bounded: u32,
+    bailout_sqr: f32,
+    seed: u32,
+}}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> histogram: array<atomic<u32>>;
+
+// One iteration, falling back to z^2 + c when the formula fails like the CPU evaluator does
+fn iterate(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {{
+    failed = false;
+    let next = {expression};
+    if (failed) {{
+        return cmul(z, z) + c;
+    }}
+    return next;
+}}
+
+// PCG hash, a good random permutation of 32-bit words
+fn pcg(v: u32) -> u32 {{
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}}
+
+fn next_uniform(state: ptr<function, u32>) -> f32 {{
+    *state = pcg(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}}
+
+fn draw(z: vec2<f32>) {{
+    let x = (z.x - params.bounds.x) / (params.bounds.y - params.bounds.x) * f32(params.size.x);
+    let y = (z.y - params.bounds.z) / (params.bounds.w - params.bounds.z) * f32(params.size.y);
+    if (x >= 0.0 && y >= 0.0 && x < f32(params.size.x) && y < f32(params.size.y)) {{
+        atomicAdd(&histogram[u32(y) * params.size.x + u32(x)], 1u);
+    }}
+}}
+
+@compute @workgroup_size({size})
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {{
+    if (id.x >= params.samples) {{
+        return;
+    }}
+    // The 64-bit sample number, so every sample has its own stream
+    let low = params.sample_offset.x + id.x;
+    let high = params.sample_offset.y + select(0u, 1u, low < id.x);
+    var state = pcg(low ^ pcg(high ^ params.seed));
+    let c = vec2<f32>(
+        mix(params.sample_bounds.x, params.sample_bounds.y, next_uniform(&state)),
+        mix(params.sample_bounds.z, params.sample_bounds.w, next_uniform(&state)),
+    );
+
+    // Find the escape time, then iterate again to draw the orbit if the channel wants it
+    var z = vec2<f32>(0.0);
+    var iter = 0u;
+    while (iter < params.max_iter) {{
+        z = iterate(z, c);
+        if (dot(z, z) > params.bailout_sqr) {{
+            break;
+        }}
+        iter += 1u;
+    }}
+    let escaped = iter < params.max_iter;
+    var first = 0u;
+    var last = iter;
+    if (params.bounded != 0u) {{
+        if (escaped) {{
+            return;
+        }}
+        first = params.min_iter;
+    }} else if (!escaped || iter < params.min_iter) {{
+        return;
+    }}
+
+    // The drawn orbit is z_0 = 0 through the last point before escape, as on the CPU
+    z = vec2<f32>(0.0);
+    for (var i = 0u; i <= last && i < params.max_iter; i += 1u) {{
+        if (i >= first) {{
+            draw(z);
+        }}
+        z = iterate(z, c);
+    }}
+}}
+"#,
+        prelude = wgsl::PRELUDE,
+        expression = expression,
+        size = WORKGROUP_SIZE,
+    ))
+}
+
+/// Pack the shader's `Params` block for one batch of samples
+fn buddhabrot_params_bytes(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    bounded: bool,
+    sample_offset: u64,
+    samples: u64,
+) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(PARAMS_SIZE as usize);
+    for bound in params.bounds.into_iter().chain(params.sampling_bounds()) {
+        bytes.extend((bound as f32).to_le_bytes());
+    }
+    let seed = (BUDDHABROT_SEED ^ (BUDDHABROT_SEED >> 32)) as u32;
+    for word in [
+        params.width,
+        params.height,
+        sample_offset as u32,
+        (sample_offset >> 32) as u32,
+        samples as u32,
+        channel_params.min_iter,
+        channel_params.max_iter,
+        bounded as u32,
+    ] {
+        bytes.extend(word.to_le_bytes());
+    }
+    bytes.extend(((params.bailout * params.bailout) as f32).to_le_bytes());
+    bytes.extend(seed.to_le_bytes());
+    bytes.resize(PARAMS_SIZE as usize, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuddhabrotChannels;
+
+    #[test]
+    fn test_gpu_buddhabrot_matches_cpu_density() {
+        // Machines without a GPU can't run this
+        let Ok(renderer) = GpuRenderer::new() else { return };
+        let channel = BuddhabrotChannel { min_iter: 0, max_iter: 50, samples: 200_000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() };
+        let params = BuddhabrotParams::new([-2.0, 1.0, -1.5, 1.5], 24, 24, 0, 50, 200_000, 2.0, "z^2 + c".to_string(), channels);
+
+        let gpu = renderer.buddhabrot_channel(&params, &channel, false).unwrap();
+        let cpu = crate::buddhabrot_channel(&params, &channel, 0);
+        // Same distribution, different samples: compare the densities where the CPU has enough counts
+        let (gpu_total, cpu_total): (f64, f64) = (gpu.as_slice().iter().sum(), cpu.as_slice().iter().sum());
+        assert!(gpu_total > 0.0);
+        for (&g, &c) in gpu.as_slice().iter().zip(cpu.as_slice()) {
+            if c > 2000.0 {
+                assert!((g / gpu_total - c / cpu_total).abs() < 0.25 * c / cpu_total, "{} vs {}", g, c);
+            }
+        }
+    }
+}
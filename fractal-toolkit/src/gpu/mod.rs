@@ -0,0 +1,424 @@
+//! GPU compute backend for escape-time rendering (requires the `gpu` feature)
+//!
+//! `GpuRenderer` runs the Mandelbrot and Julia iteration loops in a wgpu compute shader. The
+//! formula is lowered to WGSL by `wgsl::formula_to_wgsl`, so the common formulas (`z^2 + c`,
+//! `z^3 + c`, ...) and most parsed expressions run unchanged; formulas using functions the
+//! lowering doesn't support are rejected with an error and should be rendered on the CPU.
+//!
+//! The shader computes in `f32`, so images match the CPU renderer at ordinary magnifications
+//! but lose detail once pixels are smaller than about 1e-6 of the view. Parameters the shader
+//! doesn't implement (see `check_support`) are rejected rather than ignored, so the caller can
+//! render them on the CPU instead. `buddhabrot` accumulates Buddhabrot histograms on the GPU in
+//! the same way.
+
+pub mod buddhabrot;
+pub mod wgsl;
+
+use crate::{color_results, AntiAliasing, ColorStop, FractalError, FractalKind, FractalParams, InteriorColoring, IterationResult, Precision, RenderedImage};
+use image::ImageBuffer;
+use num_complex::Complex;
+
+/// Bytes in the shader's parameter block
+const PARAMS_SIZE: u64 = 64;
+
+/// Bytes per pixel in the shader's output: the final `z`, the iteration count and the escape flag
+const PIXEL_SIZE: u64 = 16;
+
+/// Workgroup edge length, in pixels
+const WORKGROUP_SIZE: u32 = 8;
+
+/// An initialized GPU device for rendering escape-time fractals
+pub struct GpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    adapter_name: String,
+}
+
+impl GpuRenderer {
+    /// Open the default (preferably high-performance) GPU adapter
+    ///
+    /// # Returns
+    ///
+    /// The renderer, or `FractalError::GpuError` if no adapter or device is available
+    pub fn new() -> Result<Self, FractalError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or_else(|| FractalError::GpuError("No GPU adapter available".to_string()))?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("fractal-toolkit"),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|e| FractalError::GpuError(format!("Failed to open GPU device: {}", e)))?;
+
+        Ok(Self { device, queue, adapter_name: adapter.get_info().name })
+    }
+
+    /// Name of the GPU adapter in use
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    /// Iterate every pixel of the image on the GPU
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the image in pixels
+    /// * `height` - Height of the image in pixels
+    /// * `params` - Fractal parameters; the formula must be supported by `wgsl::formula_to_wgsl`
+    ///   and the other parameters by `check_support`
+    /// * `julia` - Iterate the Julia set of `params.spawn` instead of the Mandelbrot set
+    ///
+    /// # Returns
+    ///
+    /// The iteration results in row-major order, with pixels placed as by `pixel_to_complex`;
+    /// `FractalError::InvalidInput` or `UnsupportedFormula` for parameters or a formula the shader
+    /// doesn't implement, `FractalError::Cancelled` if `params.cancel` is cancelled between bands
+    /// of rows, or `FractalError::GpuError` if the GPU fails
+    pub fn results(&self, width: u32, height: u32, params: &FractalParams, julia: bool) -> Result<Vec<IterationResult>, FractalError> {
+        check_support(params).map_err(FractalError::InvalidInput)?;
+        if width == 0 || height == 0 {
+            return Ok(Vec::new());
+        }
+
+        let pipeline = self.pipeline(&params.formula)?;
+
+        // Render in bands of rows that fit in one storage buffer and one dispatch
+        let limits = self.device.limits();
+        let row_bytes = width as u64 * PIXEL_SIZE;
+        let max_rows = (limits.max_storage_buffer_binding_size as u64 / row_bytes)
+            .min(limits.max_compute_workgroups_per_dimension as u64 * WORKGROUP_SIZE as u64)
+            .min(height as u64) as u32;
+        if max_rows == 0 {
+            return Err(FractalError::InvalidInput(format!("Image rows of {} pixels are too wide for the GPU", width)));
+        }
+        if width.div_ceil(WORKGROUP_SIZE) > limits.max_compute_workgroups_per_dimension {
+            return Err(FractalError::InvalidInput(format!("Images {} pixels wide exceed the GPU's dispatch limit", width)));
+        }
+
+        let params_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fractal params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let band_bytes = max_rows as u64 * row_bytes;
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fractal pixels"),
+            size: band_bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fractal readback"),
+            size: band_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fractal bindings"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: output.as_entire_binding() },
+            ],
+        });
+
+        let mut results = Vec::with_capacity(width as usize * height as usize);
+        let mut row_offset = 0;
+        while row_offset < height {
+            params.cancel.check()?;
+            let rows = max_rows.min(height - row_offset);
+            self.queue.write_buffer(&params_buffer, 0, &params_bytes(width, height, row_offset, rows, params, julia));
+
+            let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), rows.div_ceil(WORKGROUP_SIZE), 1);
+            }
+            let bytes = rows as u64 * row_bytes;
+            encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, bytes);
+            self.queue.submit(Some(encoder.finish()));
+
+            let slice = readback.slice(..bytes);
+            let (sender, receiver) = std::sync::mpsc::channel();
+            slice.map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            self.device.poll(wgpu::Maintain::Wait);
+            receiver
+                .recv()
+                .map_err(|e| FractalError::GpuError(format!("GPU readback was cancelled: {}", e)))?
+                .map_err(|e| FractalError::GpuError(format!("Failed to read GPU results: {}", e)))?;
+
+            results.extend(slice.get_mapped_range().chunks_exact(PIXEL_SIZE as usize).map(|pixel| {
+                let word = |i: usize| [pixel[4 * i], pixel[4 * i + 1], pixel[4 * i + 2], pixel[4 * i + 3]];
+                IterationResult {
+                    final_z: Complex::new(f32::from_le_bytes(word(0)) as f64, f32::from_le_bytes(word(1)) as f64),
+                    iterations: u32::from_le_bytes(word(2)),
+                    escaped: u32::from_le_bytes(word(3)) != 0,
+                    period: None,
+                    multiplier: None,
+                }
+            }));
+            readback.unmap();
+            row_offset += rows;
+        }
+
+        Ok(results)
+    }
+
+    /// Render and color an image on the GPU, the counterpart of `generate_escape_time_image_and_results`
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the image in pixels
+    /// * `height` - Height of the image in pixels
+    /// * `params` - Fractal parameters, including the color overrides and coloring modes
+    /// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+    /// * `color_palette` - Optional color palette
+    ///
+    /// # Returns
+    ///
+    /// The image and the iteration results it was colored from, or an error as from `results`
+    pub fn render(
+        &self,
+        width: u32,
+        height: u32,
+        params: &FractalParams,
+        julia: bool,
+        color_palette: Option<&Vec<ColorStop>>,
+    ) -> Result<RenderedImage, FractalError> {
+        let start = std::time::Instant::now();
+        println!("Rendering fractal on GPU ({}): {}x{}", self.adapter_name, width, height);
+
+        let results = self.results(width, height, params, julia)?;
+        let colors = color_results(&results, params, color_palette.map(|p| p.as_slice()));
+        let mut imgbuf = ImageBuffer::new(width, height);
+        for (pixel, color) in imgbuf.pixels_mut().zip(colors) {
+            *pixel = color;
+        }
+        crate::postfx::apply_post_effects(&mut imgbuf, &params.post_effects);
+
+        println!("Rendering fractal: 100% ({}/{}), Completed in {:.1}s", results.len(), results.len(), start.elapsed().as_secs_f64());
+        Ok((imgbuf, results))
+    }
+
+    /// Compile the compute pipeline for a formula
+    fn pipeline(&self, formula: &str) -> Result<wgpu::ComputePipeline, FractalError> {
+        self.compile(shader_source(formula).map_err(FractalError::UnsupportedFormula)?, formula)
+    }
+
+    /// Compile a compute shader with the entry point `main` generated for `formula`
+    fn compile(&self, source: String, formula: &str) -> Result<wgpu::ComputePipeline, FractalError> {
+        // Validation errors would otherwise panic inside wgpu
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("fractal shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("fractal pipeline"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(FractalError::GpuError(format!("Failed to compile the GPU shader for '{}': {}", formula, error))),
+            None => Ok(pipeline),
+        }
+    }
+}
+
+/// Check that the GPU backend renders `params` as the CPU renderer would
+///
+/// The shader iterates the formula in f32 with the standard imaginary unit, one sample per
+/// pixel, and detects no cycles; it doesn't render the other fractal kinds, and the results are
+/// colored by `color_results` alone. The formula itself is checked when it is lowered to WGSL.
+///
+/// # Arguments
+///
+/// * `params` - Fractal parameters
+///
+/// # Returns
+///
+/// Ok(()) if the parameters are supported, or an error naming the first one that isn't
+pub fn check_support(params: &FractalParams) -> Result<(), String> {
+    if params.i_sqrt_value != Complex::new(0.0, 1.0) {
+        return Err("The GPU backend only supports the standard imaginary unit".to_string());
+    }
+    if params.precision != Precision::Double {
+        return Err(format!("The GPU backend computes in f32 and doesn't support {:?} precision", params.precision));
+    }
+    if params.kind != FractalKind::Formula {
+        return Err(format!("The GPU backend only renders formulas, not {:?}", params.kind));
+    }
+    if params.antialiasing != AntiAliasing::None {
+        return Err("The GPU backend doesn't support anti-aliasing".to_string());
+    }
+    if matches!(params.interior_coloring, InteriorColoring::Period | InteriorColoring::Multiplier) {
+        return Err(format!("The GPU backend doesn't detect cycles for {:?} interior coloring", params.interior_coloring));
+    }
+    if params.coloring_pipeline.is_some() {
+        return Err("The GPU backend doesn't support coloring pipelines".to_string());
+    }
+    Ok(())
+}
+
+/// Complete compute shader iterating `formula`
+///
+/// # Arguments
+///
+/// * `formula` - The iterated formula
+///
+/// # Returns
+///
+/// WGSL source with the entry point `main`, or an error if the formula can't be lowered
+pub fn shader_source(formula: &str) -> Result<String, String> {
+    let expression = wgsl::formula_to_wgsl(formula)?;
+    Ok(format!(
+        r#"{prelude}
+struct Params {{
+    bounds: vec4<f32>,
+    size: vec2<u32>,
+    row_offset: u32,
+    rows: u32,
+    max_iterations: u32,
+    bailout_sqr: f32,
+    spawn: vec2<f32>,
+    julia: u32,
+}}
+
+struct Pixel {{
+    z: vec2<f32>,
+    iterations: u32,
+    escaped: u32,
+}}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> pixels: array<Pixel>;
+
+// One iteration, falling back to z^2 + c when the formula fails like the CPU evaluator does
+fn iterate(z: vec2<f32>, c: vec2<f32>) -> vec2<f32> {{
+    failed = false;
+    let next = {expression};
+    if (failed) {{
+        return cmul(z, z) + c;
+    }}
+    return next;
+}}
+
+fn coordinate(index: u32, count: u32, low: f32, high: f32) -> f32 {{
+    if (count <= 1u) {{
+        return low;
+    }}
+    return low + f32(index) / f32(count - 1u) * (high - low);
+}}
+
+@compute @workgroup_size({size}, {size})
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {{
+    if (id.x >= params.size.x || id.y >= params.rows) {{
+        return;
+    }}
+    let point = vec2<f32>(
+        coordinate(id.x, params.size.x, params.bounds.x, params.bounds.y),
+        coordinate(id.y + params.row_offset, params.size.y, params.bounds.z, params.bounds.w),
+    );
+    var z = point;
+    var c = params.spawn;
+    if (params.julia == 0u) {{
+        z = vec2<f32>(0.0);
+        c = point;
+    }}
+
+    var iter = 0u;
+    while (iter < params.max_iterations) {{
+        z = iterate(z, c);
+        if (dot(z, z) > params.bailout_sqr) {{
+            break;
+        }}
+        iter += 1u;
+    }}
+
+    pixels[id.y * params.size.x + id.x] = Pixel(z, iter, select(0u, 1u, iter < params.max_iterations));
+}}
+"#,
+        prelude = wgsl::PRELUDE,
+        expression = expression,
+        size = WORKGROUP_SIZE,
+    ))
+}
+
+/// Pack the shader's `Params` block for one band of rows
+fn params_bytes(width: u32, height: u32, row_offset: u32, rows: u32, params: &FractalParams, julia: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(PARAMS_SIZE as usize);
+    for bound in params.bounds {
+        bytes.extend((bound as f32).to_le_bytes());
+    }
+    for word in [width, height, row_offset, rows, params.max_iterations] {
+        bytes.extend(word.to_le_bytes());
+    }
+    bytes.extend(((params.bailout * params.bailout) as f32).to_le_bytes());
+    bytes.extend((params.spawn.re as f32).to_le_bytes());
+    bytes.extend((params.spawn.im as f32).to_le_bytes());
+    bytes.extend((julia as u32).to_le_bytes());
+    bytes.resize(PARAMS_SIZE as usize, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_matches_cpu_iterations() {
+        // Machines without a GPU can't run this
+        let Ok(renderer) = GpuRenderer::new() else { return };
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 64, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let (width, height) = (48, 40);
+        let results = renderer.results(width, height, &params, false).unwrap();
+        assert_eq!(results.len(), (width * height) as usize);
+
+        // f32 rounding may move points right at the boundary of an iteration band
+        let formula = crate::CompiledFormula::new(&params.formula);
+        let mismatches = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .zip(&results)
+            .filter(|((x, y), gpu)| {
+                let c = crate::pixel_to_complex(*x, *y, width, height, params.bounds);
+                crate::mandelbrot_result_compiled(c, &params, &formula).iterations.abs_diff(gpu.iterations) > 1
+            })
+            .count();
+        assert!(mismatches * 100 < results.len(), "{} of {} pixels differ", mismatches, results.len());
+    }
+
+    #[test]
+    fn test_unsupported_params_are_rejected() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 64, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        assert_eq!(check_support(&params), Ok(()));
+        let unsupported = [
+            FractalParams { antialiasing: AntiAliasing::Supersample { samples: 2 }, ..params.clone() },
+            FractalParams { interior_coloring: InteriorColoring::Period, ..params.clone() },
+            FractalParams { kind: FractalKind::BurningShip, ..params.clone() },
+            FractalParams { i_sqrt_value: Complex::new(1.0, 0.0), ..params.clone() },
+        ];
+        for params in &unsupported {
+            assert!(check_support(params).is_err());
+        }
+        // Interior coloring from the final z needs no cycle detection
+        assert_eq!(check_support(&FractalParams { interior_coloring: InteriorColoring::Angle, ..params }), Ok(()));
+    }
+}
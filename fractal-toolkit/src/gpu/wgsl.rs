@@ -0,0 +1,285 @@
+//! Lowering of formulas to WGSL
+//!
+//! Formulas are translated from their tokens into a WGSL expression over `vec2<f32>` complex
+//! numbers, following the parser's grammar and the evaluator's semantics: powers use the same
+//! special cases and magnitude limits as `MathEvaluator`, and a division by zero makes the whole
+//! step fall back to `z^2 + c` exactly as a failed evaluation does on the CPU. The formulas that
+//! `MathEvaluator` implements by hand are lowered with plain multiplications, like their CPU
+//! versions.
+//!
+//! Only the elementary part of the language is supported: numbers, `i`, `z`, `c`, the four
+//! arithmetic operators, `^`, and `sin`, `cos`, `tan`, `exp`, `log`, `sqrt`, `sinh`, `cosh`
+//! and `tanh`. Anything else is reported as an error so callers can render on the CPU instead.
+
+use crate::{ExpressionParser, MathEvaluator, ParseMode, Token};
+use num_complex::Complex;
+
+/// Complex arithmetic shared by every generated shader
+///
+/// `failed` is set by operations the CPU evaluator reports as errors.
+pub const PRELUDE: &str = r#"
+var<private> failed: bool;
+
+fn finite(a: vec2<f32>) -> bool {
+    return all(abs(a) <= vec2<f32>(3.4028235e38));
+}
+
+fn cmul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+fn cdiv_raw(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let d = dot(b, b);
+    return vec2<f32>((a.x * b.x + a.y * b.y) / d, (a.y * b.x - a.x * b.y) / d);
+}
+
+fn cdiv(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    if (dot(b, b) < 2.220446e-16) {
+        failed = true;
+        return vec2<f32>(0.0);
+    }
+    return cdiv_raw(a, b);
+}
+
+fn cexp(a: vec2<f32>) -> vec2<f32> {
+    let r = exp(a.x);
+    return vec2<f32>(r * cos(a.y), r * sin(a.y));
+}
+
+fn cln(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(log(length(a)), atan2(a.y, a.x));
+}
+
+fn csqrt(a: vec2<f32>) -> vec2<f32> {
+    let r = length(a);
+    if (r == 0.0) {
+        return vec2<f32>(0.0);
+    }
+    let im = sqrt(max((r - a.x) * 0.5, 0.0));
+    return vec2<f32>(sqrt(max((r + a.x) * 0.5, 0.0)), select(-im, im, a.y >= 0.0));
+}
+
+fn csin(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(sin(a.x) * cosh(a.y), cos(a.x) * sinh(a.y));
+}
+
+fn ccos(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(cos(a.x) * cosh(a.y), -sin(a.x) * sinh(a.y));
+}
+
+fn ctan(a: vec2<f32>) -> vec2<f32> {
+    return cdiv_raw(csin(a), ccos(a));
+}
+
+fn csinh(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(sinh(a.x) * cos(a.y), cosh(a.x) * sin(a.y));
+}
+
+fn ccosh(a: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(cosh(a.x) * cos(a.y), sinh(a.x) * sin(a.y));
+}
+
+fn ctanh(a: vec2<f32>) -> vec2<f32> {
+    return cdiv_raw(csinh(a), ccosh(a));
+}
+
+fn rescale(a: vec2<f32>, target_norm: f32) -> vec2<f32> {
+    return a * (target_norm / max(length(a), 1e-10));
+}
+
+// The `^` operator of the expression evaluator, with its handling of zero bases and its limits
+// on the magnitude of the result
+fn cpow(base: vec2<f32>, e: vec2<f32>) -> vec2<f32> {
+    if (dot(base, base) < 1e-10) {
+        return select(vec2<f32>(0.0), vec2<f32>(1.0, 0.0), dot(e, e) < 1e-10);
+    }
+    if (abs(e.y) < 1e-10) {
+        let r = pow(length(base), e.x);
+        let t = atan2(base.y, base.x) * e.x;
+        let result = vec2<f32>(r * cos(t), r * sin(t));
+        if (!finite(result)) {
+            return vec2<f32>(0.0);
+        }
+        if (length(result) > 10.0) {
+            return rescale(result, 10.0);
+        }
+        return result;
+    }
+    let result = cexp(cmul(e, cln(base)));
+    if (!finite(result)) {
+        return vec2<f32>(0.0);
+    }
+    let n = length(result);
+    if (n > 2.0) {
+        return rescale(result, 2.0);
+    }
+    if (n > 1.5) {
+        return rescale(result, 1.0 + 0.5 * min(n - 1.5, 1.0));
+    }
+    if (n < 0.01) {
+        return rescale(result, max(n, 0.01) * 2.0);
+    }
+    return result;
+}
+"#;
+
+/// Translate a formula in `z` and `c` into a WGSL expression
+///
+/// # Arguments
+///
+/// * `formula` - The iterated formula, e.g. `"z^3 + sin(c)"`
+///
+/// # Returns
+///
+/// A WGSL expression of type `vec2<f32>` in the variables `z` and `c`, to be compiled with
+/// `PRELUDE`, or an error naming the construct that can't run on the GPU
+pub fn formula_to_wgsl(formula: &str) -> Result<String, String> {
+    let lower = formula.trim().to_lowercase();
+    let zero = Complex::new(0.0, 0.0);
+    if matches!(lower.as_str(), "z^z + c") || lower.contains("^^") {
+        return Err(format!("'{}' has no GPU implementation", formula));
+    }
+    // The hand-written formulas compute integer powers by repeated multiplication, without the
+    // limits of the `^` operator
+    let exact_powers = MathEvaluator::evaluate_builtin_formula(&lower, zero, zero).is_some();
+
+    let (tokens, _) = ExpressionParser::tokenize_with_spans(formula, ParseMode::Lenient).map_err(|e| e.to_string())?;
+    let mut lowering = Lowering { tokens: &tokens, pos: 0, exact_powers };
+    let expression = lowering.sum()?;
+    // Like the lenient parser, ignore anything after a complete expression
+    Ok(expression)
+}
+
+struct Lowering<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    exact_powers: bool,
+}
+
+impl Lowering<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn sum(&mut self) -> Result<String, String> {
+        let mut left = self.product()?;
+        loop {
+            match self.peek() {
+                Some(Token::Plus) => {
+                    self.pos += 1;
+                    left = format!("({} + {})", left, self.product()?);
+                }
+                Some(Token::Minus) => {
+                    self.pos += 1;
+                    left = format!("({} - {})", left, self.product()?);
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<String, String> {
+        let mut left = self.power()?;
+        loop {
+            match self.peek() {
+                Some(Token::Multiply) => {
+                    self.pos += 1;
+                    left = format!("cmul({}, {})", left, self.power()?);
+                }
+                Some(Token::Divide) => {
+                    self.pos += 1;
+                    left = format!("cdiv({}, {})", left, self.power()?);
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn power(&mut self) -> Result<String, String> {
+        let base = self.primary()?;
+        match self.peek() {
+            Some(Token::Power) => {
+                self.pos += 1;
+                let exponent_start = self.pos;
+                let exponent = self.power()?;
+                // An integer literal exponent of a hand-written formula is repeated multiplication
+                let integer = match &self.tokens[exponent_start..self.pos] {
+                    [Token::Number(n)] if *n >= 1.0 && *n <= 16.0 && n.fract() == 0.0 => Some(*n as u32),
+                    _ => None,
+                };
+                match integer {
+                    Some(n) if self.exact_powers => {
+                        Ok((1..n).fold(base.clone(), |product, _| format!("cmul({}, {})", product, base)))
+                    }
+                    _ => Ok(format!("cpow({}, {})", base, exponent)),
+                }
+            }
+            Some(Token::Tetration | Token::Pentation | Token::Hexation) => {
+                Err("Hyperoperators (^^ and beyond) have no GPU implementation".to_string())
+            }
+            _ => Ok(base),
+        }
+    }
+
+    fn primary(&mut self) -> Result<String, String> {
+        let token = self.peek().cloned().ok_or("Unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(format!("vec2<f32>({:?}, 0.0)", n as f32)),
+            Token::ComplexNumber(text) => {
+                let text = text.trim_end_matches(['i', 'I']);
+                let n: f64 = text.parse().map_err(|_| format!("Invalid complex number: {}", text))?;
+                Ok(format!("vec2<f32>(0.0, {:?})", n as f32))
+            }
+            Token::ImaginaryUnit => Ok("vec2<f32>(0.0, 1.0)".to_string()),
+            Token::LeftParen => {
+                let inner = self.sum()?;
+                self.expect(Token::RightParen, "Expected closing parenthesis")?;
+                Ok(inner)
+            }
+            Token::Identifier(name) => match name.as_str() {
+                "z" => Ok("z".to_string()),
+                "c" | "param" => Ok("c".to_string()),
+                "sin" | "cos" | "tan" | "exp" | "log" | "sqrt" | "sinh" | "cosh" | "tanh" => {
+                    self.expect(Token::LeftParen, &format!("Expected opening parenthesis for {}", name))?;
+                    let argument = self.sum()?;
+                    self.expect(Token::RightParen, &format!("Expected closing parenthesis for {}", name))?;
+                    let function = if name == "log" { "ln" } else { name.as_str() };
+                    Ok(format!("c{}({})", function, argument))
+                }
+                _ => Err(format!("'{}' has no GPU implementation", name)),
+            },
+            other => Err(format!("Unexpected token: {:?}", other)),
+        }
+    }
+
+    fn expect(&mut self, expected: Token, message: &str) -> Result<(), String> {
+        match self.peek() {
+            Some(token) if std::mem::discriminant(token) == std::mem::discriminant(&expected) => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(message.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formulas_lower_to_wgsl() {
+        assert_eq!(formula_to_wgsl("z^2 + c").unwrap(), "(cmul(z, z) + c)");
+        assert_eq!(formula_to_wgsl("z^3 + c").unwrap(), "(cmul(cmul(z, z), z) + c)");
+        // Parsed formulas keep the evaluator's power semantics
+        assert_eq!(
+            formula_to_wgsl("z^3 + sin(c) / 2").unwrap(),
+            "(cpow(z, vec2<f32>(3.0, 0.0)) + cdiv(csin(c), vec2<f32>(2.0, 0.0)))"
+        );
+        assert_eq!(formula_to_wgsl("log(z) + 0.5i").unwrap(), "(cln(z) + vec2<f32>(0.0, 0.5))");
+
+        assert!(formula_to_wgsl("gamma(z) + c").is_err());
+        assert!(formula_to_wgsl("z^^2 + c").is_err());
+    }
+}
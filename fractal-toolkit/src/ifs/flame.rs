@@ -0,0 +1,402 @@
+//! Fractal flames
+//!
+//! A fractal flame (Draves and Reckase) is an iterated function system whose maps are an affine
+//! transform followed by a weighted sum of nonlinear *variations* (sinusoidal, spherical,
+//! swirl, ...), which bend the straight-edged IFS attractors into smooth, flame-like shapes.
+//! The chaos game of the parent module runs as before, but every point also carries a color
+//! coordinate that moves halfway towards the color index of each transform it passes through,
+//! so regions of the attractor take the colors of the transforms that produce them.
+//!
+//! The histogram holds the hit count and the summed palette color of every pixel. The image is
+//! the average color scaled by the log density (normalized like the Buddhabrot channels) raised
+//! to 1/gamma, which brings out the faint structure that makes flames look soft.
+
+use super::{IfsParams, CHAIN_LENGTH, IFS_SEED, WARMUP_STEPS};
+use crate::rng::CounterRng;
+use crate::{interpolate_color_from_palette, normalize_density_histogram, parse_color_palette, ColorStop, DensityGrid, Grid2D};
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Guards the variations that divide by the radius against the origin
+const EPS: f64 = 1e-10;
+
+/// Entries of the color lookup table built from the palette
+const PALETTE_ENTRIES: usize = 256;
+
+/// Palette used when none is given: dark red through orange to pale yellow
+pub const DEFAULT_FLAME_PALETTE: &str = "[(#200008,0.0),(#a01808,0.35),(#f07818,0.7),(#fff0a0,1.0)]";
+
+/// A nonlinear function of the plane, applied after a transform's affine part
+///
+/// In the formulas, r is the distance from the origin and θ = atan2(x, y).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variation {
+    /// (x, y)
+    Linear,
+    /// (sin x, sin y)
+    Sinusoidal,
+    /// (x, y) / r²
+    Spherical,
+    /// (x sin r² − y cos r², x cos r² + y sin r²)
+    Swirl,
+    /// ((x − y)(x + y), 2xy) / r
+    Horseshoe,
+    /// (θ/π, r − 1)
+    Polar,
+    /// r (sin(θ + r), cos(θ − r))
+    Handkerchief,
+    /// r (sin(θ r), −cos(θ r))
+    Heart,
+    /// θ/π (sin πr, cos πr)
+    Disc,
+    /// (cos θ + sin r, sin θ − cos r) / r
+    Spiral,
+    /// (sin θ / r, r cos θ)
+    Hyperbolic,
+    /// (sin θ cos r, cos θ sin r)
+    Diamond,
+    /// 2 / (r + 1) (y, x)
+    Fisheye,
+    /// e^(x − 1) (cos πy, sin πy)
+    Exponential,
+}
+
+impl Variation {
+    /// Every variation, in the order of the classic flame papers
+    pub const ALL: [Variation; 14] = [
+        Variation::Linear,
+        Variation::Sinusoidal,
+        Variation::Spherical,
+        Variation::Swirl,
+        Variation::Horseshoe,
+        Variation::Polar,
+        Variation::Handkerchief,
+        Variation::Heart,
+        Variation::Disc,
+        Variation::Spiral,
+        Variation::Hyperbolic,
+        Variation::Diamond,
+        Variation::Fisheye,
+        Variation::Exponential,
+    ];
+
+    /// Parse a variation by its lowercase name, such as `swirl`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim().to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|variation| format!("{:?}", variation).to_lowercase() == name)
+            .ok_or_else(|| format!("Unknown flame variation '{}'", name))
+    }
+
+    /// Image of (x, y)
+    pub fn apply(self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let r = r2.sqrt();
+        let theta = x.atan2(y);
+        match self {
+            Variation::Linear => (x, y),
+            Variation::Sinusoidal => (x.sin(), y.sin()),
+            Variation::Spherical => (x / (r2 + EPS), y / (r2 + EPS)),
+            Variation::Swirl => {
+                let (sin, cos) = r2.sin_cos();
+                (x * sin - y * cos, x * cos + y * sin)
+            }
+            Variation::Horseshoe => ((x - y) * (x + y) / (r + EPS), 2.0 * x * y / (r + EPS)),
+            Variation::Polar => (theta / PI, r - 1.0),
+            Variation::Handkerchief => (r * (theta + r).sin(), r * (theta - r).cos()),
+            Variation::Heart => (r * (theta * r).sin(), -r * (theta * r).cos()),
+            Variation::Disc => (theta / PI * (PI * r).sin(), theta / PI * (PI * r).cos()),
+            Variation::Spiral => ((theta.cos() + r.sin()) / (r + EPS), (theta.sin() - r.cos()) / (r + EPS)),
+            Variation::Hyperbolic => (theta.sin() / (r + EPS), r * theta.cos()),
+            Variation::Diamond => (theta.sin() * r.cos(), theta.cos() * r.sin()),
+            Variation::Fisheye => (2.0 / (r + 1.0) * y, 2.0 / (r + 1.0) * x),
+            Variation::Exponential => {
+                let scale = (x - 1.0).exp();
+                (scale * (PI * y).cos(), scale * (PI * y).sin())
+            }
+        }
+    }
+}
+
+/// One function of a flame: an affine transform followed by a blend of variations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlameTransform {
+    /// Relative probability of choosing this transform
+    pub weight: f64,
+    /// Color index in [0, 1] that points passing through the transform move towards
+    pub color: f64,
+    /// Affine coefficients [a, b, c, d, e, f] of `(x, y) -> (a x + b y + e, c x + d y + f)`
+    pub affine: [f64; 6],
+    /// Variations with their weights, summed after the affine transform
+    pub variations: Vec<(Variation, f64)>,
+}
+
+impl FlameTransform {
+    /// Image of (x, y)
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let [a, b, c, d, e, f] = self.affine;
+        let (x, y) = (a * x + b * y + e, c * x + d * y + f);
+        self.variations.iter().fold((0.0, 0.0), |(sum_x, sum_y), &(variation, weight)| {
+            let (vx, vy) = variation.apply(x, y);
+            (sum_x + weight * vx, sum_y + weight * vy)
+        })
+    }
+}
+
+/// A fractal flame: the transforms of its chaos game
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Flame {
+    /// The transforms
+    pub transforms: Vec<FlameTransform>,
+}
+
+impl Flame {
+    /// Read a flame from JSON, as written by `to_json`
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let flame: Flame = serde_json::from_str(text).map_err(|e| format!("Invalid flame: {}", e))?;
+        if !flame.transforms.iter().any(|transform| transform.weight > 0.0) {
+            return Err("A flame needs at least one transform with a positive weight".to_string());
+        }
+        Ok(flame)
+    }
+
+    /// The flame as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("flames serialize to JSON")
+    }
+
+    /// A named example: `swirl-sierpinski`, `spherical`, `swirl` or `heart`
+    pub fn preset(name: &str) -> Result<Self, String> {
+        let transform = |weight: f64, color: f64, affine: [f64; 6], variations: &[(Variation, f64)]| FlameTransform {
+            weight,
+            color,
+            affine,
+            variations: variations.to_vec(),
+        };
+        let h = 0.75_f64.sqrt() / 2.0;
+        let transforms = match name.trim().to_lowercase().as_str() {
+            "swirl-sierpinski" => vec![
+                transform(1.0, 0.0, [0.5, 0.0, 0.0, 0.5, -0.5, -0.5], &[(Variation::Linear, 0.6), (Variation::Swirl, 0.4)]),
+                transform(1.0, 0.5, [0.5, 0.0, 0.0, 0.5, 0.5, -0.5], &[(Variation::Linear, 0.6), (Variation::Swirl, 0.4)]),
+                transform(1.0, 1.0, [0.5, 0.0, 0.0, 0.5, 0.0, h], &[(Variation::Linear, 0.6), (Variation::Swirl, 0.4)]),
+            ],
+            "spherical" => vec![
+                transform(1.0, 0.0, [0.56, -0.39, 0.39, 0.56, 0.3, 0.1], &[(Variation::Spherical, 1.0)]),
+                transform(1.0, 0.6, [-0.4, 0.7, -0.7, -0.4, -0.5, 0.2], &[(Variation::Spherical, 0.8), (Variation::Linear, 0.2)]),
+                transform(0.5, 1.0, [0.6, 0.0, 0.0, 0.6, 0.0, -0.6], &[(Variation::Sinusoidal, 1.0)]),
+            ],
+            "swirl" => vec![
+                transform(1.0, 0.0, [0.7, -0.3, 0.3, 0.7, 0.0, 0.0], &[(Variation::Swirl, 0.5), (Variation::Linear, 0.5)]),
+                transform(1.0, 1.0, [-0.4, 0.2, -0.2, -0.4, 0.6, 0.2], &[(Variation::Spherical, 0.6), (Variation::Linear, 0.4)]),
+            ],
+            "heart" => vec![
+                transform(1.0, 0.0, [0.5, -0.6, 0.6, 0.5, 0.3, 0.0], &[(Variation::Heart, 0.4), (Variation::Linear, 0.6)]),
+                transform(1.0, 0.7, [0.4, 0.3, -0.3, 0.4, -0.4, 0.3], &[(Variation::Spherical, 0.7), (Variation::Swirl, 0.3)]),
+                transform(0.5, 1.0, [0.5, 0.0, 0.0, 0.5, 0.0, -0.5], &[(Variation::Linear, 1.0)]),
+            ],
+            other => return Err(format!("Unknown flame preset '{}' (expected swirl-sierpinski, spherical, swirl or heart)", other)),
+        };
+        Ok(Self { transforms })
+    }
+
+    /// Cumulative weights, normalized so the last is 1
+    fn cumulative(&self) -> Vec<f64> {
+        let total: f64 = self.transforms.iter().map(|transform| transform.weight.max(0.0)).sum();
+        let mut sum = 0.0;
+        self.transforms
+            .iter()
+            .map(|transform| {
+                sum += transform.weight.max(0.0) / total;
+                sum
+            })
+            .collect()
+    }
+
+    /// Run one chain of the chaos game, calling `plot` with every point and its color index
+    fn run_chain<F: FnMut(f64, f64, f64)>(&self, cumulative: &[f64], chain: u64, points: u64, mut plot: F) {
+        let mut rng = CounterRng::new(IFS_SEED, chain);
+        let restart = |rng: &mut CounterRng| (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen::<f64>());
+        let (mut x, mut y, mut color) = restart(&mut rng);
+        for step in 0..WARMUP_STEPS as u64 + points {
+            let choice: f64 = rng.gen();
+            let index = cumulative.iter().position(|&bound| choice < bound).unwrap_or(cumulative.len() - 1);
+            let transform = &self.transforms[index];
+            (x, y) = transform.apply(x, y);
+            color = (color + transform.color) / 2.0;
+            if !(x.is_finite() && y.is_finite()) {
+                (x, y, color) = restart(&mut rng);
+                continue;
+            }
+            if step >= WARMUP_STEPS as u64 {
+                plot(x, y, color);
+            }
+        }
+    }
+
+    /// Bounds [x_min, x_max, y_min, y_max] holding all but the farthest points of the flame
+    ///
+    /// Variations such as spherical throw rare points far out, so the bounds are the 0.5th
+    /// and 99.5th percentiles of each coordinate rather than the extremes.
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - Number of chaos-game points to measure
+    /// * `margin` - Fraction of the flame's size added on every side
+    pub fn bounds(&self, points: u64, margin: f64) -> [f64; 4] {
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        self.run_chain(&self.cumulative(), 0, points.max(1), |x, y, _| {
+            xs.push(x);
+            ys.push(y);
+        });
+        let percentiles = |values: &mut Vec<f64>| {
+            values.sort_by(|a, b| a.total_cmp(b));
+            let at = |fraction: f64| values[((values.len() - 1) as f64 * fraction).round() as usize];
+            (at(0.005), at(0.995))
+        };
+        let ((x_min, x_max), (y_min, y_max)) = (percentiles(&mut xs), percentiles(&mut ys));
+        let (width, height) = ((x_max - x_min).max(1e-9), (y_max - y_min).max(1e-9));
+        [x_min - margin * width, x_max + margin * width, y_min - margin * height, y_max + margin * height]
+    }
+}
+
+/// Hit counts and summed colors of a flame render
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlameHistogram {
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// Hits of every pixel, with row 0 at `y_max`
+    pub counts: DensityGrid,
+    /// Sum of the palette colors (0 to 1) of the hits of every pixel
+    pub colors: Grid2D<[f64; 3]>,
+}
+
+/// Sample a palette into a lookup table of colors from 0 to 1
+fn palette_table(palette: &[ColorStop]) -> Vec<[f64; 3]> {
+    (0..PALETTE_ENTRIES)
+        .map(|entry| {
+            let [r, g, b, _] = interpolate_color_from_palette(entry as f64 / (PALETTE_ENTRIES - 1) as f64, palette).0;
+            [r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0]
+        })
+        .collect()
+}
+
+/// Run the chaos game of a flame into a histogram of hits and colors
+///
+/// # Arguments
+///
+/// * `flame` - The flame
+/// * `params` - View, size and number of points
+/// * `palette` - Colors for the color indices
+pub fn flame_histogram(flame: &Flame, params: &IfsParams, palette: &[ColorStop]) -> FlameHistogram {
+    let (width, height) = (params.width as usize, params.height as usize);
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let cumulative = flame.cumulative();
+    let table = palette_table(palette);
+    let chains = params.points.div_ceil(CHAIN_LENGTH);
+    let empty = || (DensityGrid::new(width, height), Grid2D::filled(width, height, [0.0; 3]));
+
+    println!("Running flame chaos game: {} points in {} chains on {} threads", params.points, chains, rayon::current_num_threads());
+    let (counts, colors) = (0..chains)
+        .into_par_iter()
+        .fold(empty, |(mut counts, mut colors), chain| {
+            if params.cancel.is_cancelled() {
+                return (counts, colors);
+            }
+            let points = CHAIN_LENGTH.min(params.points - chain * CHAIN_LENGTH);
+            flame.run_chain(&cumulative, chain, points, |x, y, color| {
+                let px = (x - x_min) / (x_max - x_min) * width as f64;
+                let py = (y_max - y) / (y_max - y_min) * height as f64;
+                if let Some(cell) = counts.cell_at(px, py) {
+                    let rgb = table[(color.clamp(0.0, 1.0) * (PALETTE_ENTRIES - 1) as f64).round() as usize];
+                    counts[cell] += 1.0;
+                    for (sum, channel) in colors[cell].iter_mut().zip(rgb) {
+                        *sum += channel;
+                    }
+                }
+            });
+            (counts, colors)
+        })
+        .reduce(empty, |(mut counts, mut colors), (more_counts, more_colors)| {
+            counts.add_grid(&more_counts);
+            for (color, more) in colors.as_mut_slice().iter_mut().zip(more_colors.into_vec()) {
+                for (sum, channel) in color.iter_mut().zip(more) {
+                    *sum += channel;
+                }
+            }
+            (counts, colors)
+        });
+
+    FlameHistogram { width: params.width, height: params.height, counts, colors }
+}
+
+/// Tone-map a flame histogram into an image
+///
+/// # Arguments
+///
+/// * `histogram` - Hits and summed colors
+/// * `gamma` - Gamma applied to the log density; higher values brighten faint regions (try 2.2)
+pub fn tone_map_flame(histogram: &FlameHistogram, gamma: f64) -> image::RgbImage {
+    let density = normalize_density_histogram(&histogram.counts);
+    let mut img = image::RgbImage::new(histogram.width, histogram.height);
+    for (index, pixel) in img.pixels_mut().enumerate() {
+        let count = histogram.counts.as_slice()[index];
+        if count == 0.0 {
+            continue;
+        }
+        let alpha = density.as_slice()[index].powf(1.0 / gamma.max(1e-6));
+        let [r, g, b] = histogram.colors.as_slice()[index].map(|sum| (sum / count * alpha * 255.0).round().clamp(0.0, 255.0) as u8);
+        *pixel = image::Rgb([r, g, b]);
+    }
+    img
+}
+
+/// Render a fractal flame
+///
+/// # Arguments
+///
+/// * `flame` - The flame
+/// * `params` - View, size and number of points
+/// * `palette` - Colors for the color indices; `DEFAULT_FLAME_PALETTE` without one
+/// * `gamma` - Gamma of the tone mapping
+pub fn render_flame(flame: &Flame, params: &IfsParams, palette: Option<&[ColorStop]>, gamma: f64) -> image::RgbImage {
+    let default_palette;
+    let palette = match palette {
+        Some(palette) => palette,
+        None => {
+            default_palette = parse_color_palette(DEFAULT_FLAME_PALETTE).expect("default flame palette parses");
+            &default_palette
+        }
+    };
+    tone_map_flame(&flame_histogram(flame, params, palette), gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flame_render() {
+        assert_eq!(Variation::parse("Swirl"), Ok(Variation::Swirl));
+        assert_eq!(Variation::Spherical.apply(2.0, 0.0), (2.0 / (4.0 + EPS), 0.0));
+        let (x, y) = Variation::Polar.apply(0.0, 1.0);
+        assert!(x.abs() < 1e-12 && y.abs() < 1e-12);
+
+        let flame = Flame::preset("swirl-sierpinski").unwrap();
+        assert_eq!(Flame::from_json(&flame.to_json()).unwrap(), flame);
+        let params = IfsParams { bounds: flame.bounds(20_000, 0.05), width: 48, height: 48, points: 200_000, cancel: Default::default() };
+        let palette = parse_color_palette(DEFAULT_FLAME_PALETTE).unwrap();
+        let histogram = flame_histogram(&flame, &params, &palette);
+        assert!(histogram.counts.as_slice().iter().sum::<f64>() > 190_000.0);
+        assert_eq!(flame_histogram(&flame, &params, &palette), histogram);
+
+        let img = tone_map_flame(&histogram, 2.2);
+        let lit = img.pixels().filter(|pixel| pixel.0 != [0, 0, 0]).count();
+        assert!(lit > 48 * 48 / 10 && lit < 48 * 48);
+    }
+}
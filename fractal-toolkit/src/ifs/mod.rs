@@ -0,0 +1,285 @@
+//! Iterated function systems rendered with the chaos game
+//!
+//! An iterated function system (IFS) is a set of contracting maps of the plane; its attractor
+//! is the one set that the maps together carry onto itself, such as Barnsley's fern or the
+//! Sierpinski triangle. The chaos game finds it by starting anywhere and repeatedly applying a
+//! map chosen at random, with the given probabilities: after a few steps the point stays on the
+//! attractor, and counting where it lands builds a density histogram. The histogram is turned
+//! into an image with the log and percentile normalization of the Buddhabrot channels.
+//!
+//! Maps are affine, `(x, y) -> (a x + b y + e, c x + d y + f)`, or Möbius transformations of
+//! the complex plane, `z -> (a z + b) / (c z + d)`. Unlike the escape-time renderers, images
+//! put `y_max` in the top row, as attractors are drawn in ordinary plot orientation.
+//!
+//! The game runs as many independent chains of `CHAIN_LENGTH` points, each drawing from its own
+//! counter-based random stream (see `rng`), so renders don't depend on the number of threads.
+//! `flame` builds fractal flames, with nonlinear variations and colored density, on the same game.
+
+pub mod flame;
+
+use crate::cancel::CancelToken;
+use crate::rng::CounterRng;
+use crate::{interpolate_color_from_palette, normalize_density_histogram, parse_complex_number, ColorStop, DensityGrid, Grid2D};
+use num_complex::Complex;
+use rand::Rng;
+
+/// Seed of the chaos game's random streams; chain `n` draws from `CounterRng::new(IFS_SEED, n)`
+pub const IFS_SEED: u64 = 0x1f5_c4a0_5000;
+
+/// Points plotted by each chain
+const CHAIN_LENGTH: u64 = 100_000;
+
+/// Steps each chain takes before plotting, to reach the attractor from its random start
+const WARMUP_STEPS: u32 = 32;
+
+/// One map of an iterated function system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IfsMap {
+    /// `(x, y) -> (a x + b y + e, c x + d y + f)`
+    Affine {
+        /// Coefficients [a, b, c, d, e, f]
+        coefficients: [f64; 6],
+    },
+    /// `z -> (a z + b) / (c z + d)`
+    Mobius {
+        /// Coefficients [a, b, c, d]
+        coefficients: [Complex<f64>; 4],
+    },
+}
+
+impl IfsMap {
+    /// Image of a point under the map
+    pub fn apply(&self, z: Complex<f64>) -> Complex<f64> {
+        match self {
+            IfsMap::Affine { coefficients: [a, b, c, d, e, f] } => Complex::new(a * z.re + b * z.im + e, c * z.re + d * z.im + f),
+            IfsMap::Mobius { coefficients: [a, b, c, d] } => (a * z + b) / (c * z + d),
+        }
+    }
+}
+
+/// An iterated function system: maps with the probabilities of choosing them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ifs {
+    /// The maps with their (unnormalized) probabilities
+    pub maps: Vec<(IfsMap, f64)>,
+}
+
+impl Ifs {
+    /// Parse maps separated by `;`
+    ///
+    /// Each map is `affine:a,b,c,d,e,f` or `mobius:a,b,c,d` with complex coefficients such as
+    /// `0.5+0.5i`, optionally followed by `@probability`. Maps without a probability get 1;
+    /// probabilities are relative, so they need not add up to 1.
+    ///
+    /// # Returns
+    ///
+    /// The system, or an error for a malformed map or a system without any
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut maps = Vec::new();
+        for spec in text.split(';').map(str::trim).filter(|spec| !spec.is_empty()) {
+            let (map, probability) = match spec.rsplit_once('@') {
+                Some((map, probability)) => {
+                    let probability = probability.trim().parse::<f64>().map_err(|_| format!("Invalid probability in IFS map '{}'", spec))?;
+                    (map.trim(), probability)
+                }
+                None => (spec, 1.0),
+            };
+            if !(probability >= 0.0 && probability.is_finite()) {
+                return Err(format!("Probability of IFS map '{}' must be a non-negative number", spec));
+            }
+            let (kind, coefficients) = map.split_once(':').ok_or_else(|| format!("IFS map '{}' must be affine:... or mobius:...", spec))?;
+            let coefficients: Vec<&str> = coefficients.split(',').map(str::trim).collect();
+            let map = match kind.trim().to_lowercase().as_str() {
+                "affine" => {
+                    let values = coefficients
+                        .iter()
+                        .map(|value| value.parse::<f64>().map_err(|_| format!("Invalid number '{}' in IFS map '{}'", value, spec)))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let coefficients: [f64; 6] = values.try_into().map_err(|_| format!("Affine IFS map '{}' needs 6 coefficients", spec))?;
+                    IfsMap::Affine { coefficients }
+                }
+                "mobius" | "möbius" => {
+                    let values = coefficients.iter().map(|value| parse_complex_number(value)).collect::<Result<Vec<_>, _>>()?;
+                    let coefficients: [Complex<f64>; 4] = values.try_into().map_err(|_| format!("Möbius IFS map '{}' needs 4 coefficients", spec))?;
+                    IfsMap::Mobius { coefficients }
+                }
+                other => return Err(format!("Unknown IFS map type '{}' (expected affine or mobius)", other)),
+            };
+            maps.push((map, probability));
+        }
+        if maps.is_empty() || maps.iter().all(|&(_, probability)| probability == 0.0) {
+            return Err("An IFS needs at least one map with a nonzero probability".to_string());
+        }
+        Ok(Self { maps })
+    }
+
+    /// A named classic: `fern` (Barnsley), `sierpinski`, `dragon` (Heighway) or `levy` (C curve)
+    pub fn preset(name: &str) -> Result<Self, String> {
+        let affine = |coefficients: [f64; 6], probability: f64| (IfsMap::Affine { coefficients }, probability);
+        let maps = match name.trim().to_lowercase().as_str() {
+            "fern" | "barnsley" => vec![
+                affine([0.0, 0.0, 0.0, 0.16, 0.0, 0.0], 0.01),
+                affine([0.85, 0.04, -0.04, 0.85, 0.0, 1.6], 0.85),
+                affine([0.2, -0.26, 0.23, 0.22, 0.0, 1.6], 0.07),
+                affine([-0.15, 0.28, 0.26, 0.24, 0.0, 0.44], 0.07),
+            ],
+            "sierpinski" => vec![
+                affine([0.5, 0.0, 0.0, 0.5, 0.0, 0.0], 1.0),
+                affine([0.5, 0.0, 0.0, 0.5, 0.5, 0.0], 1.0),
+                affine([0.5, 0.0, 0.0, 0.5, 0.25, 0.75_f64.sqrt() / 2.0], 1.0),
+            ],
+            "dragon" => vec![affine([0.5, -0.5, 0.5, 0.5, 0.0, 0.0], 1.0), affine([-0.5, -0.5, 0.5, -0.5, 1.0, 0.0], 1.0)],
+            "levy" => vec![affine([0.5, -0.5, 0.5, 0.5, 0.0, 0.0], 1.0), affine([0.5, 0.5, -0.5, 0.5, 0.5, 0.5], 1.0)],
+            other => return Err(format!("Unknown IFS preset '{}' (expected fern, sierpinski, dragon or levy)", other)),
+        };
+        Ok(Self { maps })
+    }
+
+    /// Cumulative probabilities, normalized so the last is 1
+    fn cumulative(&self) -> Vec<f64> {
+        let total: f64 = self.maps.iter().map(|&(_, probability)| probability).sum();
+        let mut sum = 0.0;
+        self.maps
+            .iter()
+            .map(|&(_, probability)| {
+                sum += probability / total;
+                sum
+            })
+            .collect()
+    }
+
+    /// Run one chain of the chaos game, calling `plot` for every point after the warmup
+    fn run_chain<F: FnMut(Complex<f64>)>(&self, cumulative: &[f64], chain: u64, points: u64, mut plot: F) {
+        let mut rng = CounterRng::new(IFS_SEED, chain);
+        let mut z = Complex::new(rng.gen::<f64>(), rng.gen::<f64>());
+        for step in 0..WARMUP_STEPS as u64 + points {
+            let choice: f64 = rng.gen();
+            let index = cumulative.iter().position(|&bound| choice < bound).unwrap_or(cumulative.len() - 1);
+            z = self.maps[index].0.apply(z);
+            if !(z.re.is_finite() && z.im.is_finite()) {
+                // A Möbius map sent the point to infinity; start again near the origin
+                z = Complex::new(rng.gen::<f64>(), rng.gen::<f64>());
+                continue;
+            }
+            if step >= WARMUP_STEPS as u64 {
+                plot(z);
+            }
+        }
+    }
+
+    /// Bounds [x_min, x_max, y_min, y_max] of the attractor with a margin around it
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - Number of chaos-game points to measure
+    /// * `margin` - Fraction of the attractor's size added on every side
+    pub fn bounds(&self, points: u64, margin: f64) -> [f64; 4] {
+        let cumulative = self.cumulative();
+        let mut bounds = [f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY];
+        self.run_chain(&cumulative, 0, points.max(1), |z| {
+            bounds = [bounds[0].min(z.re), bounds[1].max(z.re), bounds[2].min(z.im), bounds[3].max(z.im)];
+        });
+        let (width, height) = ((bounds[1] - bounds[0]).max(1e-9), (bounds[3] - bounds[2]).max(1e-9));
+        [bounds[0] - margin * width, bounds[1] + margin * width, bounds[2] - margin * height, bounds[3] + margin * height]
+    }
+}
+
+/// Size, view and length of an IFS render
+#[derive(Debug, Clone)]
+pub struct IfsParams {
+    /// Bounds of the plane to render [x_min, x_max, y_min, y_max]
+    pub bounds: [f64; 4],
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// Number of points to plot
+    pub points: u64,
+    /// Stops the render when cancelled from another thread (see `cancel`)
+    pub cancel: CancelToken,
+}
+
+/// Run the chaos game into a density histogram
+///
+/// # Arguments
+///
+/// * `ifs` - The system
+/// * `params` - View, size and number of points
+///
+/// # Returns
+///
+/// Hit counts, with row 0 at `y_max`; a cancelled render has only the chains run before the cancel
+pub fn ifs_histogram(ifs: &Ifs, params: &IfsParams) -> DensityGrid {
+    let (width, height) = (params.width as usize, params.height as usize);
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let cumulative = ifs.cumulative();
+    let chains = params.points.div_ceil(CHAIN_LENGTH);
+
+    println!("Running chaos game: {} points in {} chains on {} threads", params.points, chains, rayon::current_num_threads());
+    let counts = Grid2D::<u32>::par_accumulate(width, height, 0..chains, |counts, chain| {
+        if params.cancel.is_cancelled() {
+            return;
+        }
+        let points = CHAIN_LENGTH.min(params.points - chain * CHAIN_LENGTH);
+        ifs.run_chain(&cumulative, chain, points, |z| {
+            let px = (z.re - x_min) / (x_max - x_min) * width as f64;
+            let py = (y_max - z.im) / (y_max - y_min) * height as f64;
+            counts.splat(px, py, 1);
+        });
+    });
+
+    counts.map(|&count| count as f64)
+}
+
+/// Render an iterated function system
+///
+/// # Arguments
+///
+/// * `ifs` - The system
+/// * `params` - View, size and number of points
+/// * `palette` - Colors for the normalized density; white on black without one
+///
+/// # Returns
+///
+/// The attractor's density, normalized as a Buddhabrot channel
+pub fn render_ifs(ifs: &Ifs, params: &IfsParams, palette: Option<&[ColorStop]>) -> image::RgbImage {
+    let density = normalize_density_histogram(&ifs_histogram(ifs, params));
+    let mut img = image::RgbImage::new(params.width, params.height);
+    for (y, row) in density.rows().enumerate() {
+        for (x, &value) in row.iter().enumerate() {
+            let color = match palette {
+                Some(palette) => {
+                    let [r, g, b, _] = interpolate_color_from_palette(value, palette).0;
+                    [r, g, b]
+                }
+                None => [(value * 255.0) as u8; 3],
+            };
+            img.put_pixel(x as u32, y as u32, image::Rgb(color));
+        }
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sierpinski_chaos_game() {
+        let ifs = Ifs::preset("sierpinski").unwrap();
+        let params = IfsParams { bounds: [0.0, 1.0, 0.0, 1.0], width: 64, height: 64, points: 250_000, cancel: CancelToken::new() };
+        let hist = ifs_histogram(&ifs, &params);
+        let total: f64 = hist.as_slice().iter().sum();
+        assert!(total > 249_000.0);
+        // The central hole (around x = 0.5, y = 0.3) is never visited, the bottom corners are
+        assert_eq!(hist[(32, 64 - 20)], 0.0);
+        assert!(hist[(1, 63)] > 0.0 && hist[(62, 63)] > 0.0);
+        assert_eq!(ifs_histogram(&ifs, &params), hist);
+
+        let parsed = Ifs::parse("affine: 0.5,0,0,0.5,0,0 @2; mobius: 0.5, 0, 0, 1").unwrap();
+        assert_eq!(parsed.maps.len(), 2);
+        assert_eq!(parsed.maps[1].0.apply(Complex::new(1.0, 1.0)), Complex::new(0.5, 0.5));
+        assert!(Ifs::parse("affine:1,2,3").is_err());
+        assert!(Ifs::parse("affine:1,0,0,1,0,0@0").is_err());
+    }
+}
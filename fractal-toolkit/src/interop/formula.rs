@@ -0,0 +1,923 @@
+//! Import of Fractint `.frm` and Ultra Fractal `.ufm` formula files
+//!
+//! Only the common subset of both languages is understood: an init section, a loop of
+//! assignments ending in an iteration of `z`, and a `|z| <= R` bailout test. Loop statements
+//! are inlined into a single expression in this crate's formula syntax, so temporaries such as
+//! `t = z*z, z = t + pixel` become `z^2 + c`-style formulas that the evaluator can run directly.
+//!
+//! Constructs that have no equivalent in the evaluator (conditionals, `real()`, `conj()`,
+//! comparisons other than the bailout test, variables other than `z` that carry a value from
+//! one iteration to the next, ...) produce an error rather than a silently different fractal.
+
+use crate::{FractalError, FractalParams};
+use num_complex::Complex;
+use std::collections::{HashMap, HashSet};
+
+/// Source format of a formula file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormulaDialect {
+    /// Fractint `.frm` formula files
+    Fractint,
+    /// Ultra Fractal `.ufm` formula files (a subset of the language)
+    UltraFractal,
+}
+
+impl FormulaDialect {
+    /// Guess the dialect from a file extension (`.frm` or `.ufm`)
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "frm" => Some(FormulaDialect::Fractint),
+            "ufm" => Some(FormulaDialect::UltraFractal),
+            _ => None,
+        }
+    }
+
+    /// Name of the user parameter that conventionally holds the Julia constant
+    fn julia_parameter(&self) -> &'static str {
+        match self {
+            FormulaDialect::Fractint => "p1",
+            FormulaDialect::UltraFractal => "seed",
+        }
+    }
+}
+
+/// Options controlling how foreign formulas are translated
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Values for user parameters, keyed by lowercase name without any `@` prefix
+    /// (e.g. "p1" for Fractint, "power" for Ultra Fractal's `@power`)
+    ///
+    /// These override any defaults declared in the formula file.
+    pub parameters: HashMap<String, Complex<f64>>,
+    /// Functions to use for function parameters, keyed like `parameters` (e.g. "fn1" -> "cos")
+    pub functions: HashMap<String, String>,
+}
+
+/// A formula translated into this crate's expression language
+#[derive(Debug, Clone)]
+pub struct ImportedFormula {
+    /// Name of the formula entry in the source file
+    pub name: String,
+    /// Title declared by the formula, if any (Ultra Fractal `title = "..."`)
+    pub title: Option<String>,
+    /// The iteration formula in this crate's syntax (e.g. "z^2 + c")
+    pub formula: String,
+    /// True when the formula starts z at the pixel (Julia-style) instead of at zero
+    pub julia: bool,
+    /// The constant used for `c` in Julia-style formulas, when it could be determined
+    pub spawn: Option<Complex<f64>>,
+    /// Escape radius derived from the bailout test
+    ///
+    /// Both dialects write the test on the squared modulus (`|z| <= 4`), so this is already
+    /// converted to the radius used by `FractalParams::bailout` (2 in that example).
+    pub bailout: Option<f64>,
+    /// Non-fatal notes about constructs that were approximated or ignored
+    pub warnings: Vec<String>,
+}
+
+impl ImportedFormula {
+    /// Copy the translated formula, bailout and Julia constant into existing fractal parameters
+    pub fn apply_to(&self, params: &mut FractalParams) {
+        params.formula = self.formula.clone();
+        if let Some(bailout) = self.bailout {
+            params.bailout = bailout;
+        }
+        if self.julia {
+            if let Some(spawn) = self.spawn {
+                params.spawn = spawn;
+            }
+        }
+    }
+}
+
+/// Import every formula entry in a Fractint or Ultra Fractal formula file
+///
+/// Entries that cannot be translated are reported as an error naming the entry, so a single
+/// unsupported formula doesn't hide which one failed.
+pub fn import_formulas(source: &str, dialect: FormulaDialect, options: &ImportOptions) -> Result<Vec<ImportedFormula>, FractalError> {
+    match dialect {
+        FormulaDialect::Fractint => parse_fractint_formulas(source, options),
+        FormulaDialect::UltraFractal => parse_ultra_fractal_formulas(source, options),
+    }
+}
+
+/// Import the formulas of a Fractint `.frm` file
+///
+/// Each entry has the form `Name(SYMMETRY) { init : loop, |z| <= 4 }`, with statements separated
+/// by commas or newlines. `comment { ... }` blocks are skipped.
+pub fn parse_fractint_formulas(source: &str, options: &ImportOptions) -> Result<Vec<ImportedFormula>, FractalError> {
+    let mut formulas = Vec::new();
+
+    for entry in split_entries(source).map_err(FractalError::UnsupportedFormula)? {
+        let (init, body) = match split_top_level(&entry.body, ':').as_slice() {
+            [body] => (String::new(), body.clone()),
+            [init, body] => (init.clone(), body.clone()),
+            _ => {
+                return Err(FractalError::UnsupportedFormula(format!(
+                    "Formula '{}' has more than one ':' section separator",
+                    entry.name
+                )))
+            }
+        };
+
+        let init_statements = split_statements(&init, true);
+        let loop_statements = split_statements(&body, true);
+
+        let translator = Translator::new(FormulaDialect::Fractint, options, HashMap::new(), HashMap::new());
+        let formula = translator
+            .translate_entry(&entry.name, None, &init_statements, &loop_statements, &[])
+            .map_err(|e| FractalError::UnsupportedFormula(format!("Formula '{}': {}", entry.name, e)))?;
+        formulas.push(formula);
+    }
+
+    Ok(formulas)
+}
+
+/// Import the formulas of an Ultra Fractal `.ufm` file
+///
+/// The `init:`, `loop:`, `bailout:` and `default:` sections are used; parameter defaults declared
+/// in `param ... endparam` and `func ... endfunc` blocks are substituted for `@name` references.
+pub fn parse_ultra_fractal_formulas(source: &str, options: &ImportOptions) -> Result<Vec<ImportedFormula>, FractalError> {
+    let mut formulas = Vec::new();
+
+    for entry in split_entries(source).map_err(FractalError::UnsupportedFormula)? {
+        let sections = split_ultra_fractal_sections(&entry.body);
+        let section = |name: &str| sections.get(name).cloned().unwrap_or_default();
+
+        let defaults = parse_ultra_fractal_defaults(&section("default"));
+
+        let init_statements = split_statements(&section("init"), false);
+        let loop_statements = split_statements(&section("loop"), false);
+        let bailout_statements = split_statements(&section("bailout"), false);
+
+        let translator = Translator::new(FormulaDialect::UltraFractal, options, defaults.parameters, defaults.functions);
+        let formula = translator
+            .translate_entry(&entry.name, defaults.title, &init_statements, &loop_statements, &bailout_statements)
+            .map_err(|e| FractalError::UnsupportedFormula(format!("Formula '{}': {}", entry.name, e)))?;
+        formulas.push(formula);
+    }
+
+    Ok(formulas)
+}
+
+/// Translate a single foreign expression (e.g. `sqr(z) + pixel`) into this crate's syntax
+///
+/// `pixel`/`#pixel` map to `c`; parameters must be supplied through `options`.
+pub fn translate_expression(expr: &str, dialect: FormulaDialect, options: &ImportOptions) -> Result<String, FractalError> {
+    let mut translator = Translator::new(dialect, options, HashMap::new(), HashMap::new());
+    translator.env.insert("c".to_string(), Expr::Var("c"));
+    let parsed = translator.parse(expr).map_err(FractalError::UnsupportedFormula)?;
+    Ok(render(&parsed))
+}
+
+/// A raw `Name { body }` entry of a formula file
+struct Entry {
+    name: String,
+    body: String,
+}
+
+/// Remove `;` comments, ignoring semicolons inside double-quoted strings
+fn strip_comments(source: &str) -> String {
+    let mut cleaned = String::with_capacity(source.len());
+    for line in source.lines() {
+        let mut in_string = false;
+        for ch in line.chars() {
+            match ch {
+                '"' => {
+                    in_string = !in_string;
+                    cleaned.push(ch);
+                }
+                ';' if !in_string => break,
+                _ => cleaned.push(ch),
+            }
+        }
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+/// Split a formula file into its named entries
+fn split_entries(source: &str) -> Result<Vec<Entry>, String> {
+    let cleaned = strip_comments(source);
+    let mut entries = Vec::new();
+    let mut rest = cleaned.as_str();
+
+    while let Some(open) = rest.find('{') {
+        let header = rest[..open].trim();
+        let close = rest[open..]
+            .find('}')
+            .map(|offset| open + offset)
+            .ok_or_else(|| format!("Unterminated formula entry: {}", header))?;
+
+        // The name is whatever precedes an optional "(SYMMETRY)" on the last header line
+        let header_line = header.lines().last().unwrap_or("").trim();
+        let name = header_line.split('(').next().unwrap_or("").trim().to_string();
+        if name.is_empty() {
+            return Err("Formula entry without a name".to_string());
+        }
+
+        if !name.eq_ignore_ascii_case("comment") {
+            entries.push(Entry { name, body: rest[open + 1..close].to_string() });
+        }
+        rest = &rest[close + 1..];
+    }
+
+    Ok(entries)
+}
+
+/// Split text on a separator character that is not nested inside parentheses
+fn split_top_level(text: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0i32;
+
+    for ch in text.chars() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if ch == separator && depth == 0 {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(ch);
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+/// Split a section into statements on newlines (and top-level commas for Fractint)
+fn split_statements(text: &str, commas_separate: bool) -> Vec<String> {
+    text.lines()
+        .flat_map(|line| {
+            if commas_separate {
+                split_top_level(line, ',')
+            } else {
+                vec![line.to_string()]
+            }
+        })
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Group the lines of an Ultra Fractal entry by their `section:` headers
+fn split_ultra_fractal_sections(body: &str) -> HashMap<String, String> {
+    let mut sections: HashMap<String, String> = HashMap::new();
+    let mut current = "init".to_string();
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_suffix(':') {
+            if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()) {
+                current = name.to_lowercase();
+                continue;
+            }
+        }
+        let section = sections.entry(current.clone()).or_default();
+        section.push_str(trimmed);
+        section.push('\n');
+    }
+
+    sections
+}
+
+/// Title and parameter defaults declared in an Ultra Fractal `default:` section
+#[derive(Default)]
+struct UltraFractalDefaults {
+    title: Option<String>,
+    parameters: HashMap<String, Complex<f64>>,
+    functions: HashMap<String, String>,
+}
+
+/// Read the title, `param` defaults and `func` defaults from an Ultra Fractal `default:` section
+fn parse_ultra_fractal_defaults(section: &str) -> UltraFractalDefaults {
+    enum Block {
+        Param(String),
+        Func(String),
+    }
+
+    let mut defaults = UltraFractalDefaults::default();
+    let mut block: Option<Block> = None;
+    let empty = ImportOptions::default();
+
+    for line in section.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let lower = line.to_lowercase();
+        let lower = lower.trim();
+
+        if lower == "endparam" || lower == "endfunc" {
+            block = None;
+        } else if let Some(position) = words.iter().position(|w| w.eq_ignore_ascii_case("param")) {
+            block = words.get(position + 1).map(|name| Block::Param(name.to_lowercase()));
+        } else if let Some(position) = words.iter().position(|w| w.eq_ignore_ascii_case("func")) {
+            block = words.get(position + 1).map(|name| Block::Func(name.to_lowercase()));
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+            match (&block, key.as_str()) {
+                (None, "title") => defaults.title = Some(value.trim_matches('"').to_string()),
+                (Some(Block::Param(name)), "default") => {
+                    // Only numeric and complex defaults matter for translation; others are skipped
+                    let mut translator = Translator::new(FormulaDialect::UltraFractal, &empty, HashMap::new(), HashMap::new());
+                    if let Ok(Expr::Const(v)) = translator.parse(value) {
+                        defaults.parameters.insert(name.clone(), v);
+                    }
+                }
+                (Some(Block::Func(name)), "default") => {
+                    let function = value.split('(').next().unwrap_or("").trim().to_lowercase();
+                    defaults.functions.insert(name.clone(), function);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    defaults
+}
+
+/// Translated expression tree, rendered back to text once a whole entry has been inlined
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Const(Complex<f64>),
+    Var(&'static str),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(&'static str, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(f64),
+    Ident(String),
+    Op(char),
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+/// Tokenize a foreign expression; identifiers are lowercased since both languages ignore case
+fn tokenize(expr: &str) -> Result<Vec<Tok>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            c if c.is_whitespace() => i += 1,
+            '+' | '-' | '*' | '/' | '^' => {
+                tokens.push(Tok::Op(ch));
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Tok::LeftParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Tok::RightParen);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Tok::Comma);
+                i += 1;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                // Scientific notation such as 1e-5
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                let value = text.parse::<f64>().map_err(|_| format!("Invalid number: {}", text))?;
+                tokens.push(Tok::Num(value));
+            }
+            c if c.is_alphabetic() || c == '_' || c == '#' || c == '@' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident: String = chars[start..i].iter().collect();
+                tokens.push(Tok::Ident(ident.to_lowercase()));
+            }
+            '|' => return Err("The |x| modulus operator is only supported in the bailout test".to_string()),
+            _ => return Err(format!("Unsupported character: {}", ch)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Converts statements of one formula entry into a single expression
+struct Translator<'a> {
+    dialect: FormulaDialect,
+    options: &'a ImportOptions,
+    defaults: HashMap<String, Complex<f64>>,
+    default_functions: HashMap<String, String>,
+    /// Current value of every assigned variable, as an expression in z and c
+    env: HashMap<String, Expr>,
+    /// Variables other than z that the loop assigns but hasn't yet in this pass; reading one
+    /// would need its value from the previous iteration, which a single formula can't carry
+    unassigned: HashSet<String>,
+    /// Whether z starts at the pixel (Julia-style)
+    julia: bool,
+    spawn: Option<Complex<f64>>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(
+        dialect: FormulaDialect,
+        options: &'a ImportOptions,
+        defaults: HashMap<String, Complex<f64>>,
+        default_functions: HashMap<String, String>,
+    ) -> Self {
+        let mut env = HashMap::new();
+        env.insert("z".to_string(), Expr::Var("z"));
+        Self {
+            dialect,
+            options,
+            defaults,
+            default_functions,
+            env,
+            unassigned: HashSet::new(),
+            julia: false,
+            spawn: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn translate_entry(
+        mut self,
+        name: &str,
+        title: Option<String>,
+        init: &[String],
+        body: &[String],
+        bailout_section: &[String],
+    ) -> Result<ImportedFormula, String> {
+        self.julia = init.iter().any(|statement| {
+            let compact: String = statement.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+            compact == "z=pixel" || compact == "z=#pixel"
+        });
+
+        for statement in init {
+            self.translate_init_statement(statement)?;
+        }
+
+        self.unassigned = body
+            .iter()
+            .chain(bailout_section)
+            .filter(|statement| !is_condition(statement))
+            .filter_map(|statement| split_assignment(statement))
+            .map(|(target, _)| target)
+            .filter(|target| target != "z")
+            .collect();
+        let mut bailout = None;
+        for statement in body.iter().chain(bailout_section) {
+            if let Some(radius) = self.translate_loop_statement(statement)? {
+                bailout = Some(radius);
+            }
+        }
+
+        let z = self.env.get("z").cloned().unwrap_or(Expr::Var("z"));
+        if z == Expr::Var("z") {
+            return Err("the loop never assigns z".to_string());
+        }
+
+        Ok(ImportedFormula {
+            name: name.to_string(),
+            title,
+            formula: render(&z),
+            julia: self.julia,
+            spawn: self.spawn,
+            bailout,
+            warnings: self.warnings,
+        })
+    }
+
+    fn translate_init_statement(&mut self, statement: &str) -> Result<(), String> {
+        let (target, value) = split_assignment(statement)
+            .ok_or_else(|| format!("Unsupported statement in init section: {}", statement))?;
+
+        match target.as_str() {
+            "z" if self.julia => {
+                // z = pixel, already recorded as a Julia-style start
+                Ok(())
+            }
+            "z" => {
+                // Mandelbrot iteration always starts from 0, so any other start is a different fractal
+                if self.parse(&value)? != Expr::Const(Complex::new(0.0, 0.0)) {
+                    return Err(format!("Only 'z = 0' is supported in Mandelbrot-style formulas, found z = {}", value));
+                }
+                Ok(())
+            }
+            "c" => {
+                if self.julia {
+                    match self.parse(&value) {
+                        Ok(Expr::Const(v)) => self.spawn = Some(v),
+                        Ok(_) => return Err(format!("Julia constant must be a constant: c = {}", value)),
+                        Err(_) => self.warnings.push(format!("No value for 'c = {}'; set the spawn point manually", value)),
+                    }
+                } else {
+                    let parsed = self.parse(&value)?;
+                    if parsed != Expr::Var("c") {
+                        return Err(format!("Only 'c = pixel' is supported in Mandelbrot-style formulas, found c = {}", value));
+                    }
+                }
+                self.env.insert("c".to_string(), Expr::Var("c"));
+                Ok(())
+            }
+            _ => {
+                let parsed = self.parse(&value)?;
+                self.env.insert(target, parsed);
+                Ok(())
+            }
+        }
+    }
+
+    /// Translate one loop statement, returning the escape radius if it is the bailout test
+    fn translate_loop_statement(&mut self, statement: &str) -> Result<Option<f64>, String> {
+        if is_condition(statement) {
+            let compact: String = statement.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+            let limit = compact
+                .strip_prefix("|z|<=")
+                .or_else(|| compact.strip_prefix("|z|<"))
+                .ok_or_else(|| format!("Unsupported bailout test: {}", statement))?;
+            return match self.parse(limit)? {
+                Expr::Const(v) if v.im == 0.0 && v.re > 0.0 => Ok(Some(v.re.sqrt())),
+                _ => Err(format!("Bailout limit must be a positive real constant: {}", statement)),
+            };
+        }
+
+        let (target, value) = split_assignment(statement)
+            .ok_or_else(|| format!("Unsupported statement in loop: {}", statement))?;
+        let parsed = self.parse(&value)?;
+        self.unassigned.remove(&target);
+        self.env.insert(target, parsed);
+        Ok(None)
+    }
+
+    fn parse(&mut self, expr: &str) -> Result<Expr, String> {
+        let tokens = tokenize(expr)?;
+        let mut pos = 0;
+        let parsed = self.parse_add_sub(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(format!("Unexpected token {:?} in '{}'", tokens[pos], expr));
+        }
+        Ok(parsed)
+    }
+
+    fn parse_add_sub(&mut self, tokens: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+        let mut left = self.parse_mul_div(tokens, pos)?;
+        while let Some(Tok::Op(op @ ('+' | '-'))) = tokens.get(*pos) {
+            *pos += 1;
+            let right = self.parse_mul_div(tokens, pos)?;
+            left = Expr::Binary(*op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_mul_div(&mut self, tokens: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+        let mut left = self.parse_unary(tokens, pos)?;
+        while let Some(Tok::Op(op @ ('*' | '/'))) = tokens.get(*pos) {
+            *pos += 1;
+            let right = self.parse_unary(tokens, pos)?;
+            left = Expr::Binary(*op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self, tokens: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+        match tokens.get(*pos) {
+            Some(Tok::Op('-')) => {
+                *pos += 1;
+                Ok(match self.parse_unary(tokens, pos)? {
+                    Expr::Const(v) => Expr::Const(-v),
+                    other => Expr::Neg(Box::new(other)),
+                })
+            }
+            Some(Tok::Op('+')) => {
+                *pos += 1;
+                self.parse_unary(tokens, pos)
+            }
+            _ => self.parse_power(tokens, pos),
+        }
+    }
+
+    fn parse_power(&mut self, tokens: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+        let base = self.parse_primary(tokens, pos)?;
+        if let Some(Tok::Op('^')) = tokens.get(*pos) {
+            *pos += 1;
+            let exponent = self.parse_unary(tokens, pos)?;
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_primary(&mut self, tokens: &[Tok], pos: &mut usize) -> Result<Expr, String> {
+        let token = tokens.get(*pos).cloned().ok_or("Unexpected end of expression")?;
+        *pos += 1;
+
+        match token {
+            Tok::Num(n) => Ok(Expr::Const(Complex::new(n, 0.0))),
+            Tok::LeftParen => {
+                let first = self.parse_add_sub(tokens, pos)?;
+                let result = if tokens.get(*pos) == Some(&Tok::Comma) {
+                    // Complex literal (re, im)
+                    *pos += 1;
+                    let second = self.parse_add_sub(tokens, pos)?;
+                    match (first, second) {
+                        (Expr::Const(re), Expr::Const(im)) if re.im == 0.0 && im.im == 0.0 => {
+                            Expr::Const(Complex::new(re.re, im.re))
+                        }
+                        _ => return Err("Complex literals (re, im) must contain real constants".to_string()),
+                    }
+                } else {
+                    first
+                };
+                if tokens.get(*pos) != Some(&Tok::RightParen) {
+                    return Err("Expected closing parenthesis".to_string());
+                }
+                *pos += 1;
+                Ok(result)
+            }
+            Tok::Ident(name) => {
+                if tokens.get(*pos) == Some(&Tok::LeftParen) {
+                    *pos += 1;
+                    let argument = self.parse_add_sub(tokens, pos)?;
+                    if tokens.get(*pos) != Some(&Tok::RightParen) {
+                        return Err(format!("Expected closing parenthesis for {}", name));
+                    }
+                    *pos += 1;
+                    self.call(&name, argument)
+                } else {
+                    self.variable(&name)
+                }
+            }
+            other => Err(format!("Unexpected token: {:?}", other)),
+        }
+    }
+
+    /// Look up a user parameter, preferring values from the import options over file defaults
+    fn parameter(&self, name: &str) -> Option<Complex<f64>> {
+        self.options.parameters.get(name).or_else(|| self.defaults.get(name)).copied()
+    }
+
+    fn variable(&mut self, name: &str) -> Result<Expr, String> {
+        if self.unassigned.contains(name) {
+            return Err(format!(
+                "'{}' is read before the loop assigns it, so it carries a value between iterations; only z can",
+                name
+            ));
+        }
+        if let Some(value) = self.env.get(name) {
+            return Ok(value.clone());
+        }
+
+        let is_pixel = match self.dialect {
+            FormulaDialect::Fractint => name == "pixel",
+            FormulaDialect::UltraFractal => name == "#pixel",
+        };
+        if is_pixel {
+            return if self.julia {
+                Err("the pixel can only be used as the starting z of a Julia-style formula".to_string())
+            } else {
+                Ok(Expr::Var("c"))
+            };
+        }
+
+        match name {
+            "pi" | "#pi" => return Ok(Expr::Const(Complex::new(std::f64::consts::PI, 0.0))),
+            "e" | "#e" => return Ok(Expr::Const(Complex::new(std::f64::consts::E, 0.0))),
+            _ => {}
+        }
+
+        let parameter_name = match self.dialect {
+            FormulaDialect::Fractint if name.len() == 2 && name.starts_with('p') => Some(name),
+            FormulaDialect::UltraFractal => name.strip_prefix('@'),
+            _ => None,
+        };
+
+        if let Some(parameter_name) = parameter_name {
+            // In Julia-style formulas the conventional Julia parameter becomes c itself
+            if self.julia && parameter_name == self.dialect.julia_parameter() && !self.env.contains_key("c") {
+                self.spawn = self.parameter(parameter_name);
+                if self.spawn.is_none() {
+                    self.warnings.push(format!("No value for '{}'; set the spawn point manually", name));
+                }
+                self.env.insert("c".to_string(), Expr::Var("c"));
+                return Ok(Expr::Var("c"));
+            }
+            return self
+                .parameter(parameter_name)
+                .map(Expr::Const)
+                .ok_or_else(|| format!("Parameter '{}' has no value; pass it in ImportOptions::parameters", name));
+        }
+
+        Err(format!("Unknown variable: {}", name))
+    }
+
+    fn call(&mut self, name: &str, argument: Expr) -> Result<Expr, String> {
+        // Resolve function parameters (fn1..fn4, @fn1..) to a concrete function name
+        let is_function_parameter = match self.dialect {
+            FormulaDialect::Fractint => name.starts_with("fn") && name.len() == 3,
+            FormulaDialect::UltraFractal => name.starts_with('@'),
+        };
+        let resolved = if is_function_parameter {
+            let key = name.trim_start_matches('@');
+            match self.options.functions.get(key).or_else(|| self.default_functions.get(key)) {
+                Some(function) => function.to_lowercase(),
+                None => {
+                    self.warnings.push(format!("No function given for '{}'; using sin", name));
+                    "sin".to_string()
+                }
+            }
+        } else {
+            name.to_string()
+        };
+
+        let function = match resolved.as_str() {
+            "sin" => "sin",
+            "cos" => "cos",
+            "tan" => "tan",
+            "sinh" => "sinh",
+            "cosh" => "cosh",
+            "tanh" => "tanh",
+            "asin" => "asin",
+            "acos" => "acos",
+            "atan" => "atan",
+            "exp" => "exp",
+            "sqrt" => "sqrt",
+            "log" | "ln" => "log",
+            "sqr" => return Ok(Expr::Binary('^', Box::new(argument), Box::new(Expr::Const(Complex::new(2.0, 0.0))))),
+            "recip" => return Ok(Expr::Binary('/', Box::new(Expr::Const(Complex::new(1.0, 0.0))), Box::new(argument))),
+            "ident" => return Ok(argument),
+            "zero" => return Ok(Expr::Const(Complex::new(0.0, 0.0))),
+            "one" => return Ok(Expr::Const(Complex::new(1.0, 0.0))),
+            _ => return Err(format!("Unsupported function: {}", resolved)),
+        };
+
+        Ok(Expr::Call(function, Box::new(argument)))
+    }
+}
+
+/// True if the statement is a comparison (a bailout or other test) rather than an assignment
+fn is_condition(statement: &str) -> bool {
+    ["<", ">", "==", "!=", "&&", "||"].iter().any(|op| statement.contains(op))
+}
+
+/// Split `target = value` into a lowercase target name and the value text
+fn split_assignment(statement: &str) -> Option<(String, String)> {
+    let (target, value) = statement.split_once('=')?;
+    let target = target.trim().to_lowercase();
+    if target.is_empty() || !target.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((target, value.trim().to_string()))
+}
+
+/// Binding strength of an expression node when rendered in this crate's syntax
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary('+' | '-', _, _) => 1,
+        Expr::Binary('*' | '/', _, _) => 2,
+        Expr::Binary(_, _, _) => 3,
+        _ => 4,
+    }
+}
+
+fn render_wrapped(expr: &Expr, parenthesize: bool) -> String {
+    if parenthesize {
+        format!("({})", render(expr))
+    } else {
+        render(expr)
+    }
+}
+
+/// Render an expression using the evaluator's syntax with only the parentheses it needs
+///
+/// The evaluator has no unary minus, so negation is written as `(0 - x)`.
+fn render(expr: &Expr) -> String {
+    match expr {
+        Expr::Const(v) => render_constant(*v),
+        Expr::Var(name) => name.to_string(),
+        Expr::Neg(inner) => format!("(0 - {})", render_wrapped(inner, precedence(inner) <= 1)),
+        Expr::Call(name, argument) => format!("{}({})", name, render(argument)),
+        Expr::Binary(op, left, right) => {
+            let p = precedence(expr);
+            let (l, r) = match op {
+                // Right-associative: only a nested power on the left needs parentheses
+                '^' => (render_wrapped(left, precedence(left) <= p), render_wrapped(right, precedence(right) < p)),
+                '-' | '/' => (render_wrapped(left, precedence(left) < p), render_wrapped(right, precedence(right) <= p)),
+                _ => (render_wrapped(left, precedence(left) < p), render_wrapped(right, precedence(right) < p)),
+            };
+            match op {
+                '+' => format!("{} + {}", l, r),
+                '-' => format!("{} - {}", l, r),
+                _ => format!("{}{}{}", l, op, r),
+            }
+        }
+    }
+}
+
+/// Render a constant so it can be used as an operand anywhere in an expression
+fn render_constant(v: Complex<f64>) -> String {
+    if v.im == 0.0 {
+        if v.re >= 0.0 {
+            format!("{}", v.re)
+        } else {
+            format!("(0 - {})", -v.re)
+        }
+    } else if v.re == 0.0 {
+        if v.im > 0.0 {
+            format!("{}i", v.im)
+        } else {
+            format!("(0 - {}i)", -v.im)
+        }
+    } else if v.im > 0.0 {
+        format!("({} + {}i)", v.re, v.im)
+    } else {
+        format!("({} - {}i)", v.re, -v.im)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fractint_mandelbrot_translates_to_fast_path_formula() {
+        let source = "Mandel(XAXIS) { ; classic\n  z = 0, c = pixel:\n  z = sqr(z) + c\n  |z| <= 4\n}\n";
+        let formulas = parse_fractint_formulas(source, &ImportOptions::default()).unwrap();
+        assert_eq!(formulas.len(), 1);
+        assert_eq!(formulas[0].name, "Mandel");
+        assert_eq!(formulas[0].formula, "z^2 + c");
+        assert!(!formulas[0].julia);
+        assert_eq!(formulas[0].bailout, Some(2.0));
+    }
+
+    #[test]
+    fn test_fractint_julia_uses_p1_as_spawn() {
+        let source = "Julia { z = pixel: t = z*z, z = t + p1, |z| < 16 }";
+        let mut options = ImportOptions::default();
+        options.parameters.insert("p1".to_string(), Complex::new(-0.8, 0.156));
+        let formulas = parse_fractint_formulas(source, &options).unwrap();
+        assert_eq!(formulas[0].formula, "z*z + c");
+        assert!(formulas[0].julia);
+        assert_eq!(formulas[0].spawn, Some(Complex::new(-0.8, 0.156)));
+        assert_eq!(formulas[0].bailout, Some(4.0));
+    }
+
+    #[test]
+    fn test_ultra_fractal_parameter_defaults() {
+        let source = "PowerMandel {\ninit:\n  z = 0\nloop:\n  z = z^@power + #pixel\nbailout:\n  |z| <= @bailout\ndefault:\n  title = \"Power Mandelbrot\"\n  param power\n    default = (3,0)\n  endparam\n  float param bailout\n    default = 100\n  endparam\n}\n";
+        let formulas = parse_ultra_fractal_formulas(source, &ImportOptions::default()).unwrap();
+        assert_eq!(formulas[0].title.as_deref(), Some("Power Mandelbrot"));
+        assert_eq!(formulas[0].formula, "z^3 + c");
+        assert_eq!(formulas[0].bailout, Some(10.0));
+    }
+
+    #[test]
+    fn test_unsupported_function_is_an_error() {
+        let source = "Bad { z = 0, c = pixel: z = conj(z)*z + c, |z| <= 4 }";
+        let err = parse_fractint_formulas(source, &ImportOptions::default()).unwrap_err();
+        assert!(matches!(&err, FractalError::UnsupportedFormula(message) if message.contains("conj")));
+    }
+
+    #[test]
+    fn test_loop_carried_temporaries_are_rejected() {
+        // w is the previous z, which a formula in z and c alone can't express
+        let source = "TwoStep { z = 0, c = pixel, w = 0: t = z, z = z*z + w + c, w = t, |z| <= 4 }";
+        let err = parse_fractint_formulas(source, &ImportOptions::default()).unwrap_err();
+        assert!(matches!(&err, FractalError::UnsupportedFormula(message) if message.contains("'w'")), "{:?}", err);
+
+        // Temporaries assigned before they're read within the pass are inlined as usual
+        let source = "Inlined { z = 0, c = pixel: t = z*z, u = t + c, z = u, |z| <= 4 }";
+        let formulas = parse_fractint_formulas(source, &ImportOptions::default()).unwrap();
+        assert_eq!(formulas[0].formula, "z*z + c");
+    }
+
+    #[test]
+    fn test_nonzero_initial_z_is_rejected() {
+        for init in ["z = 1", "z = 2*pixel"] {
+            let source = format!("Start {{ {}, c = pixel: z = z*z + c, |z| <= 4 }}", init);
+            let err = parse_fractint_formulas(&source, &ImportOptions::default()).unwrap_err();
+            assert!(matches!(&err, FractalError::UnsupportedFormula(message) if message.contains("z = 0")), "{:?}", err);
+        }
+    }
+}
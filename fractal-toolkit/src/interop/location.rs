@@ -0,0 +1,122 @@
+//! Import of Kalles Fraktaler and Mandel Machine location files
+//!
+//! Both programs store a location as `Key: value` (or `Key = value`) lines. Only the view itself
+//! is read: the center as decimal strings, the zoom and the iteration limit. Colouring and
+//! rendering settings specific to those programs are ignored.
+
+use crate::viewport::Viewport;
+use crate::{FractalError, FractalParams};
+
+/// A location read from another program's parameter file
+#[derive(Debug, Clone)]
+pub struct ImportedLocation {
+    /// The view center and magnification, with the center kept at full precision
+    pub viewport: Viewport,
+    /// Iteration limit stored with the location, if any
+    pub max_iterations: Option<u32>,
+    /// Non-fatal notes about settings that could not be carried over
+    pub warnings: Vec<String>,
+}
+
+impl ImportedLocation {
+    /// Build Mandelbrot parameters that render this location at the given image size
+    ///
+    /// Uses the standard `z^2 + c` formula and the default bailout of 4, and falls back to
+    /// `default_iterations` when the file has no iteration limit. Bounds are axis-aligned, so the
+    /// rotation of a rotated location is not represented.
+    pub fn to_fractal_params(&self, width: u32, height: u32, default_iterations: u32) -> FractalParams {
+        FractalParams::new(
+            self.viewport.to_bounds(width, height),
+            self.max_iterations.unwrap_or(default_iterations),
+            [0.0, 0.0],
+            4.0,
+            "z^2 + c".to_string(),
+        )
+    }
+}
+
+/// Parse a Kalles Fraktaler (`.kfr`/`.kfp`) or Mandel Machine style location file
+///
+/// Recognised keys (case-insensitive): `Re`/`Real`, `Im`/`Imag`, `Zoom`/`Magnification`
+/// `Iterations`/`MaxIter` and `RotateAngle`/`Rotation`. A missing zoom defaults to 1.
+///
+/// # Arguments
+///
+/// * `source` - Contents of the location file
+///
+/// # Returns
+///
+/// The imported location, or `FractalError::InvalidInput` if the center is missing or a value is malformed
+pub fn parse_kfr_location(source: &str) -> Result<ImportedLocation, FractalError> {
+    let invalid = FractalError::InvalidInput;
+    let mut center_re = None;
+    let mut center_im = None;
+    let mut zoom = None;
+    let mut max_iterations = None;
+    let mut rotation = 0.0;
+    let mut warnings = Vec::new();
+
+    for line in source.lines() {
+        let line = line.trim();
+        let separator = match line.find([':', '=']) {
+            Some(pos) => pos,
+            None => continue,
+        };
+        let key = line[..separator].trim().to_lowercase();
+        let value = line[separator + 1..].trim();
+
+        match key.as_str() {
+            "re" | "real" => center_re = Some(value.to_string()),
+            "im" | "imag" => center_im = Some(value.to_string()),
+            "zoom" | "magnification" => {
+                let parsed = value.parse::<f64>().map_err(|_| invalid(format!("Invalid zoom: '{}'", value)))?;
+                if !parsed.is_finite() {
+                    return Err(invalid(format!("Zoom {} is beyond double precision range", value)));
+                }
+                zoom = Some(parsed);
+            }
+            "iterations" | "maxiter" => {
+                let parsed = value.parse::<u64>().map_err(|_| invalid(format!("Invalid iteration count: '{}'", value)))?;
+                max_iterations = Some(u32::try_from(parsed).unwrap_or_else(|_| {
+                    warnings.push(format!("Iteration count {} clamped to {}", parsed, u32::MAX));
+                    u32::MAX
+                }));
+            }
+            "rotateangle" | "rotation" => {
+                rotation = value.parse::<f64>().map_err(|_| invalid(format!("Invalid rotation: '{}'", value)))?;
+            }
+            _ => {}
+        }
+    }
+
+    let center_re = center_re.ok_or_else(|| invalid("Location file has no real part of the center (Re)".to_string()))?;
+    let center_im = center_im.ok_or_else(|| invalid("Location file has no imaginary part of the center (Im)".to_string()))?;
+    let viewport = Viewport::new(&center_re, &center_im, zoom.unwrap_or(1.0)).map_err(invalid)?.with_rotation(rotation);
+
+    Ok(ImportedLocation { viewport, max_iterations, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kfr_keeps_center_digits() {
+        let source = "Re: -0.743643887037158704752191506114774\r\nIm: 0.131825904205311970493132056385139\r\nZoom: 2E10\r\nIterations: 50000\r\nRotateAngle: 30\r\nColorMethod: 7\r\n";
+        let location = parse_kfr_location(source).unwrap();
+        assert_eq!(location.viewport.center_re, "-0.743643887037158704752191506114774");
+        assert_eq!(location.viewport.center_im, "0.131825904205311970493132056385139");
+        assert_eq!(location.viewport.zoom, 2e10);
+        assert_eq!(location.max_iterations, Some(50000));
+        assert_eq!(location.viewport.rotation, 30.0);
+
+        let bounds = location.viewport.to_bounds(200, 100);
+        assert!(((bounds[3] - bounds[2]) - 2e-10).abs() < 1e-15);
+        assert!(((bounds[1] - bounds[0]) - 4e-10).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_parse_kfr_requires_center() {
+        assert!(matches!(parse_kfr_location("Zoom: 1E5\n"), Err(FractalError::InvalidInput(_))));
+    }
+}
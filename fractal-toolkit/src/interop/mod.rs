@@ -0,0 +1,14 @@
+//! Interoperability with other fractal software
+//!
+//! This module translates formats used by other fractal programs into this crate's own
+//! parameter and expression types, so existing formula libraries and shared locations
+//! can be rendered with the toolkit.
+
+pub mod formula;
+pub mod location;
+
+pub use formula::{
+    import_formulas, parse_fractint_formulas, parse_ultra_fractal_formulas, translate_expression,
+    FormulaDialect, ImportOptions, ImportedFormula,
+};
+pub use location::{parse_kfr_location, ImportedLocation};
@@ -0,0 +1,87 @@
+//! Analytic interior tests for the Mandelbrot set of `z^2 + c`
+//!
+//! The main cardioid and the period-2 bulb make up most of the set's area, and every pixel in
+//! them costs the full `max_iterations` (or, with cycle detection, the steps until the cycle
+//! shows). Both have closed forms: c is in the cardioid when its fixed point attracts, which
+//! with q = (x - 1/4)^2 + y^2 is q(q + x - 1/4) <= y^2/4, and in the bulb when its 2-cycle
+//! attracts, |c + 1| <= 1/4. Points that pass either test skip iteration entirely.
+//!
+//! The tests only hold for the standard quadratic with i² = -1, so they are used only when
+//! `FractalParams::bulb_check` is set and the formula is `z^2 + c` (see `shortcut`).
+
+use super::{is_standard_quadratic, IterationResult};
+use crate::{FractalKind, FractalParams};
+use num_complex::Complex;
+
+/// True if `c` lies in the main cardioid of the Mandelbrot set
+pub fn in_main_cardioid(c: Complex<f64>) -> bool {
+    let x = c.re - 0.25;
+    let q = x * x + c.im * c.im;
+    q * (q + x) <= 0.25 * c.im * c.im
+}
+
+/// True if `c` lies in the period-2 bulb, the disk of radius 1/4 around -1
+pub fn in_period2_bulb(c: Complex<f64>) -> bool {
+    let x = c.re + 1.0;
+    x * x + c.im * c.im <= 0.0625
+}
+
+/// The outcome of iterating `c` Mandelbrot-style, when the analytic tests decide it
+///
+/// # Returns
+///
+/// A bounded result whose `final_z` is a point of the attracting cycle, whose `period` is 1
+/// (cardioid) or 2 (bulb), as cycle detection would report, and with the cycle's `multiplier`;
+/// `None` when the tests don't apply to `params` or `c` passes neither
+pub fn shortcut(c: Complex<f64>, params: &FractalParams) -> Option<IterationResult> {
+    if !params.bulb_check
+        || params.kind != FractalKind::Formula
+        || params.i_sqrt_value != Complex::new(0.0, 1.0)
+        || !is_standard_quadratic(&params.formula)
+    {
+        return None;
+    }
+    // The attracting fixed point solves z^2 - z + c = 0, a point of the 2-cycle z^2 + z + c + 1 = 0
+    // with multipliers 2z and 4(c + 1)
+    let (final_z, period, multiplier) = if in_main_cardioid(c) {
+        let fixed = (1.0 - (1.0 - 4.0 * c).sqrt()) / 2.0;
+        (fixed, 1, 2.0 * fixed)
+    } else if in_period2_bulb(c) {
+        ((-1.0 + (-3.0 - 4.0 * c).sqrt()) / 2.0, 2, 4.0 * (c + 1.0))
+    } else {
+        return None;
+    };
+    Some(IterationResult {
+        iterations: params.max_iterations,
+        escaped: false,
+        final_z,
+        period: Some(period),
+        multiplier: Some(multiplier),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cardioid_and_bulb() {
+        assert!(in_main_cardioid(Complex::new(0.0, 0.0)));
+        assert!(in_main_cardioid(Complex::new(0.24, 0.0)) && !in_main_cardioid(Complex::new(0.26, 0.0)));
+        assert!(in_main_cardioid(Complex::new(-0.74, 0.0)) && !in_main_cardioid(Complex::new(-0.76, 0.0)));
+        assert!(in_period2_bulb(Complex::new(-1.2, 0.1)) && !in_period2_bulb(Complex::new(-0.7, 0.0)));
+
+        let mut params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 500, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        assert_eq!(shortcut(Complex::new(0.0, 0.0), &params), None);
+        params.bulb_check = true;
+        let fixed = shortcut(Complex::new(-0.1, 0.1), &params).unwrap();
+        assert_eq!((fixed.iterations, fixed.period), (500, Some(1)));
+        let z = fixed.final_z;
+        assert!((z * z + Complex::new(-0.1, 0.1) - z).norm() < 1e-12);
+        assert_eq!(shortcut(Complex::new(-1.1, 0.0), &params).unwrap().period, Some(2));
+        assert_eq!(shortcut(Complex::new(0.3, 0.0), &params), None);
+
+        params.formula = "z^3 + c".to_string();
+        assert_eq!(shortcut(Complex::new(0.0, 0.0), &params), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! Double-double arithmetic: about 106 bits of precision from a pair of `f64`s
+//!
+//! A value is stored as an unevaluated sum `hi + lo` with `|lo| <= ulp(hi) / 2`. The error-free
+//! transformations below (Knuth's two-sum and an FMA-based two-product) carry the rounding error
+//! of each `f64` operation into `lo`, giving a relative precision of about 1e-32. That allows
+//! zooms roughly 1e15 times deeper than plain `f64` at a few times the cost, which covers most
+//! deep zooms before arbitrary precision becomes necessary.
+
+use super::IterationFloat;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A double-double floating-point number `hi + lo`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DoubleDouble {
+    /// Leading part, the value rounded to `f64`
+    pub hi: f64,
+    /// Trailing part, the rounding error of `hi`
+    pub lo: f64,
+}
+
+/// Sum of two f64s as (rounded sum, exact error)
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// Like `two_sum`, but only valid when |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// Product of two f64s as (rounded product, exact error)
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    /// Parse a decimal number such as `-0.743643887037158704752191506114774` or `1.5e-40`
+    ///
+    /// Digits beyond the ~32 that double-double can represent are still read, so the result is
+    /// the nearest double-double to the full decimal value.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let invalid = || format!("Invalid decimal number '{}'", text);
+
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(pos) => (&unsigned[..pos], unsigned[pos + 1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (unsigned, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+
+        // Digits past the 40th significant one can't change the result; skipping them keeps the
+        // accumulated mantissa in range for coordinates with hundreds of digits
+        let ten = DoubleDouble::from(10.0);
+        let mut value = DoubleDouble::default();
+        let mut significant = 0;
+        let mut scale = exponent;
+        for (i, ch) in integer.chars().chain(fraction.chars()).enumerate() {
+            let digit = ch.to_digit(10).ok_or_else(invalid)?;
+            let in_fraction = i >= integer.len();
+            if significant >= 40 {
+                if !in_fraction {
+                    scale += 1;
+                }
+                continue;
+            }
+            if in_fraction {
+                scale -= 1;
+            }
+            if digit != 0 || significant > 0 {
+                significant += 1;
+            }
+            value = value * ten + DoubleDouble::from(digit as f64);
+        }
+
+        let power = ten.powi(scale.unsigned_abs());
+        value = if scale < 0 { value / power } else { value * power };
+        Ok(if negative { -value } else { value })
+    }
+
+    /// Raise to a non-negative integer power by repeated squaring
+    pub fn powi(self, mut exponent: u32) -> Self {
+        let mut result = DoubleDouble::from(1.0);
+        let mut base = self;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        result
+    }
+}
+
+/// Significant digits written by `Display`, about all that double-double holds
+const DECIMAL_DIGITS: usize = 32;
+
+/// The value in scientific notation with up to 32 significant digits, e.g.
+/// `-1.7548776662466927600495088963585e0`, which `parse` reads back
+impl fmt::Display for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hi == 0.0 || !self.hi.is_finite() {
+            return write!(f, "{}", self.hi);
+        }
+        let mut x = if self.hi < 0.0 { -*self } else { *self };
+        let ten = DoubleDouble::from(10.0);
+        let mut exponent = x.hi.log10().floor() as i32;
+        let power = ten.powi(exponent.unsigned_abs());
+        x = if exponent < 0 { x * power } else { x / power };
+        if x.hi >= 10.0 {
+            x = x / ten;
+            exponent += 1;
+        } else if x.hi < 1.0 {
+            x = x * ten;
+            exponent -= 1;
+        }
+
+        // One digit more than written, to round on
+        let mut digits = Vec::with_capacity(DECIMAL_DIGITS + 1);
+        for _ in 0..=DECIMAL_DIGITS {
+            let mut digit = x.hi.floor();
+            if digit == x.hi && x.lo < 0.0 {
+                digit -= 1.0;
+            }
+            let digit = digit.clamp(0.0, 9.0);
+            digits.push(digit as u8);
+            x = (x - DoubleDouble::from(digit)) * ten;
+        }
+        if digits.pop().is_some_and(|last| last >= 5) {
+            for digit in digits.iter_mut().rev() {
+                *digit += 1;
+                if *digit < 10 {
+                    break;
+                }
+                *digit = 0;
+            }
+            if digits[0] == 0 {
+                digits.insert(0, 1);
+                digits.pop();
+                exponent += 1;
+            }
+        }
+        while digits.len() > 1 && digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        let sign = if self.hi < 0.0 { "-" } else { "" };
+        let fraction: String = digits[1..].iter().map(|digit| char::from(b'0' + digit)).collect();
+        match fraction.is_empty() {
+            true => write!(f, "{}{}e{}", sign, digits[0], exponent),
+            false => write!(f, "{}{}.{}e{}", sign, digits[0], fraction, exponent),
+        }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        Self { hi: value, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (s1, s2) = two_sum(self.hi, other.hi);
+        let (t1, t2) = two_sum(self.lo, other.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        let (hi, lo) = quick_two_sum(s1, s2 + t2);
+        Self { hi, lo }
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + (-other)
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let (p1, p2) = two_prod(self.hi, other.hi);
+        let p2 = p2 + (self.hi * other.lo + self.lo * other.hi);
+        let (hi, lo) = quick_two_sum(p1, p2);
+        Self { hi, lo }
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        // Long division: three f64 quotient digits, each correcting the previous remainder
+        let q1 = self.hi / other.hi;
+        let r = self - other * DoubleDouble::from(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other * DoubleDouble::from(q2);
+        let q3 = r.hi / other.hi;
+        let (hi, lo) = quick_two_sum(q1, q2);
+        DoubleDouble { hi, lo } + DoubleDouble::from(q3)
+    }
+}
+
+impl IterationFloat for DoubleDouble {
+    fn from_f64(value: f64) -> Self {
+        DoubleDouble::from(value)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic_keeps_digits_beyond_f64() {
+        let third = DoubleDouble::from(1.0) / DoubleDouble::from(3.0);
+        let one = third * DoubleDouble::from(3.0);
+        assert!((one - DoubleDouble::from(1.0)).to_f64().abs() < 1e-31);
+
+        // 1 + 1e-20 is exactly 1 in f64 but not in double-double
+        let x = DoubleDouble::from(1.0) + DoubleDouble::from(1e-20);
+        assert_eq!(x.hi, 1.0);
+        assert!((x.lo - 1e-20).abs() < 1e-36);
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        let x = DoubleDouble::parse("-0.7436438870371587047521915061").unwrap();
+        assert!((x.hi + 0.7436438870371587).abs() < 2e-16);
+        let rest = x - DoubleDouble::parse("-0.7436438870371587").unwrap();
+        assert!((rest.to_f64() + 4.7521915061e-18).abs() < 1e-29);
+
+        assert_eq!(DoubleDouble::parse("1.5e3").unwrap().to_f64(), 1500.0);
+        let long = format!("0.{}", "3".repeat(400));
+        assert!((DoubleDouble::parse(&long).unwrap() - DoubleDouble::from(1.0) / DoubleDouble::from(3.0)).to_f64().abs() < 1e-31);
+        assert!(DoubleDouble::parse("1.2.3").is_err());
+        assert!(DoubleDouble::parse("").is_err());
+
+        // Written values read back to within the last digit
+        assert_eq!(DoubleDouble::from(1500.0).to_string(), "1.5e3");
+        assert_eq!(DoubleDouble::from(-0.25).to_string(), "-2.5e-1");
+        let third = DoubleDouble::from(1.0) / DoubleDouble::from(3.0);
+        assert_eq!(third.to_string(), format!("3.{}e-1", "3".repeat(31)));
+        for value in [third, -x, DoubleDouble::from(0.999_999_999_999_999_9) + DoubleDouble::from(1e-25)] {
+            let back = DoubleDouble::parse(&value.to_string()).unwrap();
+            assert!((back - value).to_f64().abs() <= 1e-31 * value.to_f64().abs());
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Coloring of points inside the set
+//!
+//! Bounded points are usually drawn in one color, which hides the structure of the interior:
+//! every hyperbolic component holds orbits attracted to a cycle of its own period, and inside a
+//! component the attracting point rotates with c. `InteriorColoring` picks what of the bounded
+//! orbit selects the color from the palette:
+//!
+//! - `Period` colors by the period of the detected cycle (see the `period` submodule), so the
+//!   cardioid, the period-2 bulb and each minibrot take the color of their period. Points whose
+//!   orbit didn't settle into a cycle within `max_iterations` keep the inside color.
+//! - `Angle` colors by the argument of the final z, a point of the attracting cycle. In the main
+//!   cardioid that is the internal angle, the argument of the multiplier 2z. With
+//!   `FractalParams::phase_wheel` set, the angle is colored from that cyclic palette instead.
+//! - `Magnitude` colors by |z| of the final z relative to the bailout radius.
+//! - `Multiplier` colors by |λ|, where λ is the multiplier of the detected cycle (see
+//!   `analysis::orbit_class`): 0 at the centers of hyperbolic components, where the cycle is
+//!   superattracting, rising to 1 at their boundaries, so every bulb and minibrot shows its own
+//!   concentric rings. Like `Period`, it needs a detected cycle.
+
+use super::IterationResult;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Number of periods after which `Period` coloring starts over at the beginning of the palette
+pub const PERIOD_CYCLE: u32 = 12;
+
+/// How points that never escape are colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InteriorColoring {
+    /// One color: `FractalParams::inside_color`, or the palette's first stop
+    #[default]
+    Solid,
+    /// The period of the attracting cycle
+    Period,
+    /// The argument of the final z
+    Angle,
+    /// The magnitude of the final z
+    Magnitude,
+    /// The magnitude of the attracting cycle's multiplier
+    Multiplier,
+}
+
+impl InteriorColoring {
+    /// Parse a mode name: `solid`, `period`, `angle` (or `internal-angle`), `magnitude` (or
+    /// `attractor`) or `multiplier`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().replace(['_', ' '], "-").as_str() {
+            "solid" | "none" => Ok(InteriorColoring::Solid),
+            "period" => Ok(InteriorColoring::Period),
+            "angle" | "internal-angle" => Ok(InteriorColoring::Angle),
+            "magnitude" | "attractor" => Ok(InteriorColoring::Magnitude),
+            "multiplier" => Ok(InteriorColoring::Multiplier),
+            other => Err(format!(
+                "Unknown interior coloring '{}' (expected solid, period, angle, magnitude or multiplier)",
+                other
+            )),
+        }
+    }
+
+    /// Position in the palette, between 0 and 1, of a bounded point
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Outcome of iterating the point
+    /// * `bailout` - Escape radius, which scales `Magnitude`
+    ///
+    /// # Returns
+    ///
+    /// `None` when the point is colored with the solid inside color: always for `Solid`, and for
+    /// `Period` and `Multiplier` when no cycle was detected
+    pub fn position(&self, result: &IterationResult, bailout: f64) -> Option<f64> {
+        match self {
+            InteriorColoring::Solid => None,
+            InteriorColoring::Period => {
+                result.period.map(|period| ((period - 1) % PERIOD_CYCLE) as f64 / PERIOD_CYCLE as f64)
+            }
+            InteriorColoring::Angle => Some((result.final_z.arg() + PI) / (2.0 * PI)),
+            InteriorColoring::Magnitude => Some((result.final_z.norm() / bailout.max(f64::MIN_POSITIVE)).clamp(0.0, 1.0)),
+            InteriorColoring::Multiplier => result.multiplier.map(|multiplier| multiplier.norm().min(1.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_interior_positions() {
+        assert_eq!(InteriorColoring::parse("Internal_Angle"), Ok(InteriorColoring::Angle));
+        assert!(InteriorColoring::parse("stripes").is_err());
+
+        let result = |period| IterationResult { iterations: 100, escaped: false, final_z: Complex::new(0.0, 1.0), period, multiplier: None };
+        assert_eq!(InteriorColoring::Solid.position(&result(Some(1)), 2.0), None);
+        assert_eq!(InteriorColoring::Period.position(&result(None), 2.0), None);
+        assert_eq!(InteriorColoring::Period.position(&result(Some(1)), 2.0), Some(0.0));
+        assert_eq!(InteriorColoring::Period.position(&result(Some(PERIOD_CYCLE + 4)), 2.0), Some(0.25));
+        assert_eq!(InteriorColoring::Angle.position(&result(None), 2.0), Some(0.75));
+        assert_eq!(InteriorColoring::Magnitude.position(&result(None), 2.0), Some(0.5));
+        let attracted = IterationResult { multiplier: Some(Complex::new(0.0, -0.5)), ..result(Some(3)) };
+        assert_eq!(InteriorColoring::Multiplier.position(&attracted, 2.0), Some(0.5));
+    }
+}
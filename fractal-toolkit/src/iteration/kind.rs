@@ -0,0 +1,145 @@
+//! Built-in escape-time fractals outside the formula language
+//!
+//! The Burning Ship and its relatives square z after taking absolute values of its components,
+//! which the complex formula language can't express. `FractalKind` selects one of them in place
+//! of `FractalParams::formula`; each iterates in f64 from z = 0 (Mandelbrot-style) or from the
+//! pixel with `spawn` as c (Julia-style), with the same escape counting as the formula kernels.
+//! All of them grow like z^2, so smooth coloring uses degree 2.
+//!
+//! Writing z = x + iy, one step of each is:
+//!
+//! | Kind          | Real part      | Imaginary part |
+//! |---------------|----------------|----------------|
+//! | Burning Ship  | x^2 - y^2      | 2\|xy\|        |
+//! | Perpendicular | x^2 - y^2      | -2\|x\|y       |
+//! | Celtic        | \|x^2 - y^2\|  | 2xy            |
+//! | Buffalo       | \|x^2 - y^2\|  | -2\|xy\|       |
+//!
+//! plus c. Most of the Burning Ship lies below the real axis; bounds of `-2.2,1.3,-2,1` frame
+//! the whole set.
+//!
+//! `Lambda` is the logistic family λz(1 - z), with the pixel (or `spawn`) as λ. Its critical point
+//! is 1/2 rather than 0, so Mandelbrot-style iteration starts there (`critical_point`). It is
+//! conjugate to z^2 + c by w = λ(1/2 - z) with c = λ/2 - λ^2/4, and the renderers iterate it in
+//! those coordinates: escape counts and smooth coloring then match the Mandelbrot set exactly,
+//! where a bailout on z itself would depend on λ. `final_z` holds w. The λ-plane Mandelbrot set
+//! is two copies of the usual one, mirrored about λ = 1; bounds of `-2.2,4.2,-1.6,1.6` frame both.
+//! `lambda_to_c`, `c_to_lambda`, `lambda_z_to_w` and `w_to_lambda_z` convert between the views.
+
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+/// Iteration used by the escape-time renderers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FractalKind {
+    /// `FractalParams::formula`, evaluated by the formula kernels
+    #[default]
+    Formula,
+    /// `(|x| + i|y|)^2 + c`
+    BurningShip,
+    /// Mandelbrot with the absolute value of x in the imaginary part
+    Perpendicular,
+    /// Mandelbrot with the absolute value of the real part
+    Celtic,
+    /// Burning Ship with the absolute value of the real part, mirrored
+    Buffalo,
+    /// The logistic family `λz(1 - z)`, with c as λ
+    Lambda,
+}
+
+impl FractalKind {
+    /// Parse a kind name: `formula`, `burning-ship` (or `ship`), `perpendicular`, `celtic`,
+    /// `buffalo` or `lambda` (or `logistic`)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().replace(['_', ' '], "-").as_str() {
+            "formula" => Ok(FractalKind::Formula),
+            "burning-ship" | "burningship" | "ship" => Ok(FractalKind::BurningShip),
+            "perpendicular" | "perpendicular-mandelbrot" => Ok(FractalKind::Perpendicular),
+            "celtic" => Ok(FractalKind::Celtic),
+            "buffalo" => Ok(FractalKind::Buffalo),
+            "lambda" | "logistic" => Ok(FractalKind::Lambda),
+            other => Err(format!(
+                "Unknown fractal kind '{}' (expected formula, burning-ship, perpendicular, celtic, buffalo or lambda)",
+                other
+            )),
+        }
+    }
+
+    /// Starting value of z for Mandelbrot-style iteration: the critical point of the step
+    pub fn critical_point(&self) -> Complex<f64> {
+        match self {
+            FractalKind::Lambda => Complex::new(0.5, 0.0),
+            _ => Complex::new(0.0, 0.0),
+        }
+    }
+
+    /// One iteration step from `z` with constant `c`
+    ///
+    /// `Formula` has no built-in step and returns the standard `z^2 + c`. For `Lambda`, `c` is λ.
+    pub fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        let (x, y) = (z.re, z.im);
+        let (re, im) = match self {
+            FractalKind::Lambda => return c * z * (1.0 - z),
+            FractalKind::Formula => (x * x - y * y, 2.0 * x * y),
+            FractalKind::BurningShip => (x * x - y * y, 2.0 * (x * y).abs()),
+            FractalKind::Perpendicular => (x * x - y * y, -2.0 * x.abs() * y),
+            FractalKind::Celtic => ((x * x - y * y).abs(), 2.0 * x * y),
+            FractalKind::Buffalo => ((x * x - y * y).abs(), -2.0 * (x * y).abs()),
+        };
+        Complex::new(re + c.re, im + c.im)
+    }
+}
+
+/// The c of the quadratic map conjugate to λz(1 - z): c = λ/2 - λ^2/4
+pub fn lambda_to_c(lambda: Complex<f64>) -> Complex<f64> {
+    lambda / 2.0 - lambda * lambda / 4.0
+}
+
+/// A λ whose logistic map is conjugate to z^2 + c: λ = 1 + sqrt(1 - 4c)
+///
+/// The other one is 2 - λ, its mirror image about λ = 1.
+pub fn c_to_lambda(c: Complex<f64>) -> Complex<f64> {
+    1.0 + (1.0 - 4.0 * c).sqrt()
+}
+
+/// The point w = λ(1/2 - z) of the c-plane dynamics corresponding to z of the logistic map
+pub fn lambda_z_to_w(z: Complex<f64>, lambda: Complex<f64>) -> Complex<f64> {
+    lambda * (0.5 - z)
+}
+
+/// The point z = 1/2 - w/λ of the logistic map corresponding to w of the c-plane dynamics
+pub fn w_to_lambda_z(w: Complex<f64>, lambda: Complex<f64>) -> Complex<f64> {
+    0.5 - w / lambda
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fractal_kind_steps() {
+        assert_eq!(FractalKind::parse("Burning Ship").unwrap(), FractalKind::BurningShip);
+        assert_eq!(FractalKind::parse("celtic").unwrap(), FractalKind::Celtic);
+        assert!(FractalKind::parse("tricorn").is_err());
+
+        let z = Complex::new(-1.0, 2.0);
+        let c = Complex::new(0.5, 0.25);
+        // z^2 = -3 - 4i
+        assert_eq!(FractalKind::Formula.step(z, c), Complex::new(-2.5, -3.75));
+        assert_eq!(FractalKind::BurningShip.step(z, c), Complex::new(-2.5, 4.25));
+        assert_eq!(FractalKind::Perpendicular.step(z, c), Complex::new(-2.5, -3.75));
+        assert_eq!(FractalKind::Celtic.step(z, c), Complex::new(3.5, -3.75));
+        assert_eq!(FractalKind::Buffalo.step(z, c), Complex::new(3.5, -3.75));
+        // For positive x the Perpendicular step flips the sign of the imaginary part
+        assert_eq!(FractalKind::Perpendicular.step(Complex::new(1.0, 2.0), c), Complex::new(-2.5, -3.75));
+
+        // The conjugacy carries one logistic step to one quadratic step
+        let lambda = Complex::new(2.5, 0.7);
+        let w = lambda_z_to_w(z, lambda);
+        let stepped = lambda_z_to_w(FractalKind::Lambda.step(z, lambda), lambda);
+        assert!((stepped - (w * w + lambda_to_c(lambda))).norm() < 1e-12);
+        assert!((w_to_lambda_z(w, lambda) - z).norm() < 1e-12);
+        assert!((lambda_to_c(c_to_lambda(c)) - c).norm() < 1e-12);
+        assert_eq!(lambda_z_to_w(FractalKind::Lambda.critical_point(), lambda), Complex::new(0.0, 0.0));
+    }
+}
@@ -0,0 +1,534 @@
+//! Batch evaluation of escape-time iterations
+//!
+//! `mandelbrot_iterations` and `julia_iterations` look up the formula again for every pixel and
+//! every step. `iterate_batch` resolves the formula to an iteration kernel once per batch and then
+//! runs all points through it, which suits callers that already have a list of points: GPU
+//! preparation, Buddhabrot sampling and external tools.
+//!
+//! The standard `z^2 + c` kernel unrolls the inner loop, checking the bailout only every few
+//! steps and replaying the last block step by step once a point has escaped, so the iteration
+//! counts are identical to the per-pixel functions. Unrolling is skipped when the bailout radius
+//! is too small for that to hold.
+//!
+//! The `z^2 + c` kernels are generic over the float type and run in the precision selected by
+//! `FractalParams::precision` (see the `precision` submodule). Precisions beyond `f64` need
+//! pixel coordinates beyond `f64` as well, so they are used through `iterate_viewport`, which
+//! computes each pixel from the viewport's decimal center.
+//!
+//! Every result keeps the value of z where iteration stopped; the `smooth` submodule turns that
+//! into a fractional iteration count for band-free coloring. The per-pixel f64 paths of
+//! `mandelbrot_iterations` and `julia_iterations` also stop bounded orbits early once they
+//! repeat (see the `period` submodule) and report the cycle's period, which `InteriorColoring` can
+//! color the interior by (see the `interior` submodule).
+//!
+//! With `FractalParams::bulb_check` set, Mandelbrot-style iteration of `z^2 + c` skips points in
+//! the main cardioid and the period-2 bulb, which analytic tests place inside the set (see the
+//! `bulb` submodule).
+//!
+//! The hand-written polynomial formulas (`z^2 + c`, `z^3 + c`, `z^4 + c`, `z^2 - c`) are
+//! iterated several points at a time in f64 (see the `simd` submodule), with the same counts as
+//! the scalar loops.
+//!
+//! `FractalParams::kind` can replace the formula with one of the built-in abs-variant fractals
+//! or the logistic family (see the `kind` submodule), which always iterate in f64.
+
+pub mod bulb;
+#[cfg(feature = "double-double")]
+pub mod double_double;
+pub mod interior;
+pub mod kind;
+pub mod period;
+pub mod precision;
+pub mod simd;
+pub mod smooth;
+
+#[cfg(feature = "double-double")]
+pub use double_double::DoubleDouble;
+pub use interior::InteriorColoring;
+pub use kind::FractalKind;
+pub use period::CycleDetector;
+pub use precision::{IterationFloat, Precision};
+pub use simd::SimdFormula;
+pub use smooth::escape_degree;
+
+use crate::algebra::{Algebra, CustomAlgebra};
+use crate::formula::compiled::compiled_for;
+use crate::viewport::Viewport;
+use crate::{CompiledFormula, FractalError, FractalParams};
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// Number of points handed to each worker thread at a time
+const CHUNK_SIZE: usize = 1024;
+
+/// Outcome of iterating a single point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IterationResult {
+    /// Number of iterations before the point escaped, or `max_iterations` if it stayed bounded
+    pub iterations: u32,
+    /// True if the point exceeded the bailout radius
+    pub escaped: bool,
+    /// The value of z when iteration stopped (the first escaped value, or the last bounded one)
+    pub final_z: Complex<f64>,
+    /// Period of the cycle a bounded orbit was found to settle into (see `period`), after which
+    /// iteration stopped early; `None` for escaped points and where no cycle was detected
+    pub period: Option<u32>,
+    /// Multiplier of that cycle (see `analysis::orbit_class`), computed only where
+    /// `InteriorColoring::Multiplier` needs it
+    pub multiplier: Option<Complex<f64>>,
+}
+
+impl IterationResult {
+    /// Result of the points a cancelled render never iterated (see `cancel`)
+    pub const UNRENDERED: IterationResult =
+        IterationResult { iterations: 0, escaped: false, final_z: Complex::new(0.0, 0.0), period: None, multiplier: None };
+}
+
+/// Options for `iterate_batch_with`
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    /// Iterate Julia-style (points are starting z, `params.spawn` is c) instead of Mandelbrot-style
+    pub julia: bool,
+    /// Number of iterations between bailout checks in the `z^2 + c` kernel (1 disables unrolling)
+    pub unroll: u32,
+    /// Split large batches across the rayon thread pool
+    pub parallel: bool,
+    /// Iterate recognized polynomial formulas `simd::LANES` points at a time (see `simd`)
+    pub simd: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            julia: false,
+            unroll: 8,
+            parallel: true,
+            simd: true,
+        }
+    }
+}
+
+/// Iteration kernel chosen once per batch from the fractal parameters
+enum Kernel {
+    /// Standard complex `z^2 + c`, unrolled
+    Quadratic,
+    /// `z^2 + c` with a custom imaginary unit
+    CustomQuadratic(Complex<f64>),
+    /// Any other formula with a custom imaginary unit
+    Custom(CustomAlgebra, Arc<CompiledFormula>),
+    /// Any other formula, evaluated by the expression evaluator
+    Formula(Arc<CompiledFormula>),
+    /// One of the built-in abs-variant fractals
+    Kind(FractalKind),
+}
+
+impl Kernel {
+    fn for_params(params: &FractalParams) -> Self {
+        if params.kind != FractalKind::Formula {
+            return Kernel::Kind(params.kind);
+        }
+        if params.i_sqrt_value != Complex::new(0.0, 1.0) {
+            if is_standard_quadratic(&params.formula) {
+                return Kernel::CustomQuadratic(params.i_sqrt_value);
+            }
+            let formula = compiled_for(&params.formula);
+            return Kernel::Custom(CustomAlgebra::new(params.i_sqrt_value, &formula), formula);
+        }
+        if is_standard_quadratic(&params.formula) {
+            Kernel::Quadratic
+        } else {
+            Kernel::Formula(compiled_for(&params.formula))
+        }
+    }
+}
+
+/// True if `formula` is `z^2 + c` (or `z*z + c`), ignoring whitespace and case
+pub(crate) fn is_standard_quadratic(formula: &str) -> bool {
+    let normalized = || formula.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_lowercase());
+    normalized().eq("z^2+c".chars()) || normalized().eq("z*z+c".chars())
+}
+
+/// Iterate many points Mandelbrot-style with one formula dispatch
+///
+/// Equivalent to calling `mandelbrot_iterations` for every point, but returns the final value
+/// and escape flag as well.
+///
+/// # Arguments
+///
+/// * `points` - The c values to iterate
+/// * `params` - Fractal parameters (formula, max_iterations, bailout, custom imaginary unit)
+///
+/// # Returns
+///
+/// One result per point, in the same order
+pub fn iterate_batch(points: &[Complex<f64>], params: &FractalParams) -> Vec<IterationResult> {
+    iterate_batch_with(points, params, &BatchOptions::default())
+}
+
+/// Iterate many points with explicit batch options
+///
+/// # Arguments
+///
+/// * `points` - The points to iterate (c values, or starting z values when `options.julia` is set)
+/// * `params` - Fractal parameters
+/// * `options` - Julia/Mandelbrot mode, unroll factor and parallelism
+///
+/// # Returns
+///
+/// One result per point, in the same order
+pub fn iterate_batch_with(points: &[Complex<f64>], params: &FractalParams, options: &BatchOptions) -> Vec<IterationResult> {
+    let kernel = Kernel::for_params(params);
+    let lanes = SimdFormula::for_params(params).filter(|_| options.simd);
+    let iterate_chunk = |chunk: &[Complex<f64>]| -> Vec<IterationResult> {
+        if params.cancel.is_cancelled() {
+            return vec![IterationResult::UNRENDERED; chunk.len()];
+        }
+        if let Some(formula) = lanes {
+            return simd::iterate_points(formula, chunk, params, options.julia);
+        }
+        chunk
+            .iter()
+            .map(|&point| {
+                if !options.julia {
+                    if let Some(result) = bulb::shortcut(point, params) {
+                        return result;
+                    }
+                }
+                let (z, c) = if options.julia { (point, params.spawn) } else { (params.kind.critical_point(), point) };
+                iterate_point(&kernel, z, c, params, options.unroll.max(1))
+            })
+            .collect()
+    };
+
+    if options.parallel && points.len() > CHUNK_SIZE {
+        points.par_chunks(CHUNK_SIZE).flat_map_iter(iterate_chunk).collect()
+    } else {
+        iterate_chunk(points)
+    }
+}
+
+/// Iterate a single point, dispatching to the kernel and precision chosen by the parameters
+///
+/// Used by `mandelbrot_iterations` and `julia_iterations` when a precision other than the
+/// default is requested.
+pub(crate) fn iterate_single(point: Complex<f64>, params: &FractalParams, julia: bool) -> IterationResult {
+    if let Some(result) = bulb::shortcut(point, params).filter(|_| !julia) {
+        return result;
+    }
+    let (z, c) = if julia { (point, params.spawn) } else { (params.kind.critical_point(), point) };
+    iterate_point(&Kernel::for_params(params), z, c, params, BatchOptions::default().unroll)
+}
+
+/// Iterate every pixel of a viewport, computing pixel coordinates in the iteration precision
+///
+/// For `f32` and `f64` this is `iterate_batch_with` over `Viewport::pixel_to_complex`. For
+/// extended precisions the center is parsed from its decimal string at full precision and the
+/// (small) pixel offsets are added to it, so views far beyond the reach of `f64` render
+/// correctly. Results are in row-major order.
+///
+/// # Arguments
+///
+/// * `viewport` - The view to render; its center strings may carry any number of digits
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters; `bounds` is ignored in favour of the viewport
+/// * `options` - Julia/Mandelbrot mode and unroll factor
+///
+/// # Returns
+///
+/// One result per pixel, or an error if the viewport center can't be parsed or the render is
+/// cancelled (see `cancel`)
+pub fn iterate_viewport(
+    viewport: &Viewport,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    options: &BatchOptions,
+) -> Result<Vec<IterationResult>, FractalError> {
+    let pixels: Vec<(u32, u32)> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).collect();
+
+    #[cfg(feature = "double-double")]
+    if params.precision == Precision::DoubleDouble {
+        let center = KernelComplex {
+            re: DoubleDouble::parse(&viewport.center_re).map_err(FractalError::InvalidInput)?,
+            im: DoubleDouble::parse(&viewport.center_im).map_err(FractalError::InvalidInput)?,
+        };
+        let kernel = Kernel::for_params(params);
+        let unroll = options.unroll.max(1);
+        let results: Vec<IterationResult> = pixels
+            .par_iter()
+            .map(|&(x, y)| {
+                if params.cancel.is_cancelled() {
+                    return IterationResult::UNRENDERED;
+                }
+                let offset = KernelComplex::<DoubleDouble>::from_complex(viewport.pixel_offset(x as f64, y as f64, width, height));
+                let point = KernelComplex { re: center.re + offset.re, im: center.im + offset.im };
+                if !options.julia {
+                    if let Some(result) = bulb::shortcut(point.to_complex(), params) {
+                        return result;
+                    }
+                }
+                let (z, c) = if options.julia {
+                    (point, KernelComplex::from_complex(params.spawn))
+                } else {
+                    (KernelComplex::from_complex(params.kind.critical_point()), point)
+                };
+                match kernel {
+                    Kernel::Quadratic => iterate_quadratic(z, c, params.max_iterations, params.bailout, unroll),
+                    Kernel::CustomQuadratic(i_squared) => iterate_custom_quadratic(
+                        z,
+                        c,
+                        KernelComplex::from_complex(i_squared),
+                        params.max_iterations,
+                        params.bailout,
+                    ),
+                    // The expression evaluator and the built-in kinds only work in f64
+                    Kernel::Formula(_) | Kernel::Custom(..) | Kernel::Kind(_) => iterate_point(&kernel, z.to_complex(), c.to_complex(), params, unroll),
+                }
+            })
+            .collect();
+        params.cancel.check()?;
+        return Ok(results);
+    }
+
+    let points: Vec<Complex<f64>> = pixels
+        .iter()
+        .map(|&(x, y)| viewport.pixel_to_complex(x as f64, y as f64, width, height))
+        .collect();
+    let results = iterate_batch_with(&points, params, options);
+    params.cancel.check()?;
+    Ok(results)
+}
+
+fn iterate_point(kernel: &Kernel, z: Complex<f64>, c: Complex<f64>, params: &FractalParams, unroll: u32) -> IterationResult {
+    let (max_iterations, bailout) = (params.max_iterations, params.bailout);
+    match (kernel, params.precision) {
+        (Kernel::Quadratic, Precision::Single) => iterate_quadratic::<f32>(
+            KernelComplex::from_complex(z),
+            KernelComplex::from_complex(c),
+            max_iterations,
+            bailout,
+            unroll,
+        ),
+        (Kernel::CustomQuadratic(i_squared), Precision::Single) => iterate_custom_quadratic::<f32>(
+            KernelComplex::from_complex(z),
+            KernelComplex::from_complex(c),
+            KernelComplex::from_complex(*i_squared),
+            max_iterations,
+            bailout,
+        ),
+        // Without extended pixel coordinates double-double gives no benefit over f64
+        (Kernel::Quadratic, _) => iterate_quadratic::<f64>(
+            KernelComplex::from_complex(z),
+            KernelComplex::from_complex(c),
+            max_iterations,
+            bailout,
+            unroll,
+        ),
+        (Kernel::CustomQuadratic(i_squared), _) => iterate_custom_quadratic::<f64>(
+            KernelComplex::from_complex(z),
+            KernelComplex::from_complex(c),
+            KernelComplex::from_complex(*i_squared),
+            max_iterations,
+            bailout,
+        ),
+        (Kernel::Formula(formula), _) => iterate_with(z, params, |z| formula.eval(z, c)),
+        (Kernel::Custom(algebra, formula), _) => {
+            let c = algebra.embed(c);
+            iterate_with(z, params, |z| algebra.project(algebra.step(formula, algebra.embed(z), c)))
+        }
+        // The logistic map iterates in the coordinates where it is z^2 + c (see `kind`)
+        (Kernel::Kind(FractalKind::Lambda), _) => {
+            let quadratic_c = kind::lambda_to_c(c);
+            iterate_with(kind::lambda_z_to_w(z, c), params, |w| w * w + quadratic_c)
+        }
+        (Kernel::Kind(kind), _) => iterate_with(z, params, |z| kind.step(z, c)),
+    }
+}
+
+/// A complex number in one of the kernel float types
+#[derive(Clone, Copy)]
+struct KernelComplex<F> {
+    re: F,
+    im: F,
+}
+
+impl<F: IterationFloat> KernelComplex<F> {
+    fn from_complex(z: Complex<f64>) -> Self {
+        Self { re: F::from_f64(z.re), im: F::from_f64(z.im) }
+    }
+
+    fn to_complex(self) -> Complex<f64> {
+        Complex::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    /// |z|^2, rounded to f64 (only compared against the bailout)
+    fn norm_sqr(self) -> f64 {
+        (self.re * self.re + self.im * self.im).to_f64()
+    }
+
+    /// z^2 + c, with the same operation order as `Complex<f64>` so f64 results are unchanged
+    fn square_add(self, c: Self) -> Self {
+        Self {
+            re: self.re * self.re - self.im * self.im + c.re,
+            im: self.re * self.im + self.im * self.re + c.im,
+        }
+    }
+
+    /// z^2 + c where i^2 is `i_squared`, with the same operation order as `CustomComplex`
+    fn custom_square_add(self, c: Self, i_squared: Self) -> Self {
+        let bd = self.im * self.im;
+        Self {
+            re: self.re * self.re + bd * i_squared.re + c.re,
+            im: self.re * self.im + self.im * self.re + bd * i_squared.im + c.im,
+        }
+    }
+}
+
+/// `z^2 + c` with a custom imaginary unit, stepping one iteration at a time
+fn iterate_custom_quadratic<F: IterationFloat>(
+    mut z: KernelComplex<F>,
+    c: KernelComplex<F>,
+    i_squared: KernelComplex<F>,
+    max_iterations: u32,
+    bailout: f64,
+) -> IterationResult {
+    let bailout_sqr = bailout * bailout;
+    let mut iterations = 0;
+
+    while iterations < max_iterations {
+        z = z.custom_square_add(c, i_squared);
+        if z.norm_sqr() > bailout_sqr {
+            return IterationResult { iterations, escaped: true, final_z: z.to_complex(), period: None, multiplier: None };
+        }
+        iterations += 1;
+    }
+
+    IterationResult { iterations, escaped: false, final_z: z.to_complex(), period: None, multiplier: None }
+}
+
+/// Step-by-step escape loop with the same counting as `mandelbrot_iterations`
+fn iterate_with<F>(mut z: Complex<f64>, params: &FractalParams, step: F) -> IterationResult
+where
+    F: Fn(Complex<f64>) -> Complex<f64>,
+{
+    let bailout_sqr = params.bailout * params.bailout;
+    let mut iterations = 0;
+
+    while iterations < params.max_iterations {
+        z = step(z);
+        if z.norm_sqr() > bailout_sqr {
+            return IterationResult { iterations, escaped: true, final_z: z, period: None, multiplier: None };
+        }
+        iterations += 1;
+    }
+
+    IterationResult { iterations, escaped: false, final_z: z, period: None, multiplier: None }
+}
+
+/// Unrolled `z^2 + c`: run blocks of `unroll` steps, replaying a block once it contains the escape
+fn iterate_quadratic<F: IterationFloat>(
+    mut z: KernelComplex<F>,
+    c: KernelComplex<F>,
+    max_iterations: u32,
+    bailout: f64,
+    unroll: u32,
+) -> IterationResult {
+    let bailout_sqr = bailout * bailout;
+    let mut iterations = 0;
+
+    // Skipping bailout checks is only exact when an escaped orbit can never come back, which
+    // holds once |z| exceeds both 2 and |c|
+    let unroll = if bailout >= 2.0 && c.to_complex().norm() <= bailout { unroll } else { 1 };
+
+    while iterations < max_iterations {
+        let block = unroll.min(max_iterations - iterations);
+        let saved = z;
+        for _ in 0..block {
+            z = z.square_add(c);
+        }
+
+        // NaN (overflow inside the block) also counts as escaped
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr || norm_sqr.is_nan() {
+            z = saved;
+            for _ in 0..block {
+                z = z.square_add(c);
+                if z.norm_sqr() > bailout_sqr {
+                    return IterationResult { iterations, escaped: true, final_z: z.to_complex(), period: None, multiplier: None };
+                }
+                iterations += 1;
+            }
+            // Only reachable if the block overflowed without crossing the bailout, e.g. NaN input
+            return IterationResult { iterations, escaped: true, final_z: z.to_complex(), period: None, multiplier: None };
+        }
+        iterations += block;
+    }
+
+    IterationResult { iterations, escaped: false, final_z: z.to_complex(), period: None, multiplier: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unrolled_counts_match_step_by_step() {
+        let params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 100, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let points: Vec<Complex<f64>> = (0..400)
+            .map(|i| Complex::new(-2.0 + (i % 20) as f64 * 0.13, -1.3 + (i / 20) as f64 * 0.13))
+            .collect();
+
+        let unrolled = iterate_batch(&points, &params);
+        let stepwise = iterate_batch_with(&points, &params, &BatchOptions { unroll: 1, simd: false, ..BatchOptions::default() });
+        assert_eq!(unrolled, stepwise);
+        let unrolled_scalar = iterate_batch_with(&points, &params, &BatchOptions { simd: false, ..BatchOptions::default() });
+        assert_eq!(unrolled_scalar, stepwise);
+
+        // c = 1: 1, 2, 5 -> escapes on the third step
+        let result = iterate_batch(&[Complex::new(1.0, 0.0), Complex::new(0.0, 0.0)], &params);
+        assert_eq!(result[0].iterations, 2);
+        assert!(result[0].escaped);
+        assert_eq!(result[1].iterations, 100);
+        assert!(!result[1].escaped);
+    }
+
+    #[test]
+    fn test_single_precision_matches_double_away_from_boundary() {
+        let mut params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 50, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let points = [Complex::new(0.5, 0.5), Complex::new(-0.1, 0.1), Complex::new(-2.5, 0.0)];
+        let double = iterate_batch(&points, &params);
+
+        params.precision = Precision::Single;
+        let single = iterate_batch(&points, &params);
+        for (s, d) in single.iter().zip(&double) {
+            assert_eq!(s.iterations, d.iterations);
+            assert_eq!(s.escaped, d.escaped);
+        }
+        assert_eq!(Precision::parse("F32"), Ok(Precision::Single));
+        assert!(Precision::parse("f16").is_err());
+    }
+
+    #[cfg(feature = "double-double")]
+    #[test]
+    fn test_double_double_resolves_pixels_beyond_f64() {
+        // Pixels 1e-20 apart around the Misiurewicz point c = i, which all round to i in f64
+        let viewport = Viewport::new("0", "1", 4.0 / 8e-20).unwrap();
+        let mut params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 2000, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let options = BatchOptions::default();
+
+        let double = iterate_viewport(&viewport, 8, 8, &params, &options).unwrap();
+        params.precision = Precision::for_pixel_size(viewport.pixel_size(8), 1.0);
+        assert_eq!(params.precision, Precision::DoubleDouble);
+        let extended = iterate_viewport(&viewport, 8, 8, &params, &options).unwrap();
+
+        let distinct = |results: &[IterationResult]| {
+            let mut counts: Vec<u32> = results.iter().map(|r| r.iterations).collect();
+            counts.sort();
+            counts.dedup();
+            counts.len()
+        };
+        assert!(distinct(&extended) > distinct(&double));
+    }
+}
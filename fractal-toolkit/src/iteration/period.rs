@@ -0,0 +1,98 @@
+//! Periodicity checking for bounded orbits
+//!
+//! Points inside the set never escape, so without a check every one of them costs the full
+//! `max_iterations`, which dominates the render time of views with much interior. Their orbits
+//! are attracted to a cycle, though, and once z comes back to (numerically) the same value the
+//! rest of the orbit is known. `CycleDetector` uses Brent's scheme: it remembers z at step 2, 4,
+//! 8, ... and compares every later z with the remembered one. A cycle of period p is found
+//! within about twice the steps the orbit needs to reach it plus p.
+//!
+//! The first match can be a multiple of the period: while the orbit still converges, z may come
+//! within the tolerance of the remembered value only after going around the cycle several
+//! times. Once matched, though, the orbit is on the cycle to within the tolerance, so the
+//! detector remembers z once more and reports the first step that comes back to it within the
+//! looser `CONFIRM_TOLERANCE`, which is the period itself. That costs at most p more steps.
+
+use num_complex::Complex;
+
+/// Distance below which two orbit values count as the same point of a cycle
+///
+/// Orbits converge to attracting cycles geometrically, so a small tolerance costs few extra
+/// steps, while a large one could mistake an escaping orbit that lingers near a repelling cycle
+/// for an interior one.
+pub const PERIOD_TOLERANCE: f64 = 1e-12;
+
+/// Distance below which an orbit already on a cycle counts as having come around it
+///
+/// Much larger than `PERIOD_TOLERANCE`, so that the converged orbit meets it after one turn,
+/// and much smaller than the distance between the points of any cycle worth telling apart.
+pub const CONFIRM_TOLERANCE: f64 = 1e-9;
+
+/// Brent cycle detection over the values of one orbit
+#[derive(Debug, Clone, Copy)]
+pub struct CycleDetector {
+    reference: Complex<f64>,
+    steps: u32,
+    limit: u32,
+    /// Steps of the first match, while the period is being confirmed
+    matched: Option<u32>,
+}
+
+impl CycleDetector {
+    /// Start detection at the orbit's initial value
+    pub fn new(start: Complex<f64>) -> Self {
+        Self { reference: start, steps: 0, limit: 2, matched: None }
+    }
+
+    /// Record the next value of the orbit
+    ///
+    /// # Returns
+    ///
+    /// The period once the orbit has been found to repeat, otherwise `None`
+    pub fn check(&mut self, z: Complex<f64>) -> Option<u32> {
+        self.steps += 1;
+        let distance = (z - self.reference).norm_sqr();
+        if let Some(matched) = self.matched {
+            return (distance < CONFIRM_TOLERANCE * CONFIRM_TOLERANCE || self.steps >= matched).then_some(self.steps);
+        }
+        if distance < PERIOD_TOLERANCE * PERIOD_TOLERANCE {
+            self.matched = Some(self.steps);
+            self.reference = z;
+            self.steps = 0;
+            return None;
+        }
+        if self.steps == self.limit {
+            self.reference = z;
+            self.steps = 0;
+            self.limit = self.limit.saturating_mul(2);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_smallest_period() {
+        let find = |c: Complex<f64>| {
+            let mut z = Complex::new(0.0, 0.0);
+            let mut detector = CycleDetector::new(z);
+            (0..10_000).find_map(|_| {
+                z = z * z + c;
+                detector.check(z)
+            })
+        };
+        // The centers of the main cardioid, the period-2 bulb and the period-3 minibrot
+        assert_eq!(find(Complex::new(0.0, 0.0)), Some(1));
+        assert_eq!(find(Complex::new(-1.0, 0.0)), Some(2));
+        assert_eq!(find(Complex::new(-1.754_877_666_246_693, 0.0)), Some(3));
+        // Inside the cardioid but not at its center, the orbit only converges to the fixed point
+        assert_eq!(find(Complex::new(-0.1, 0.1)), Some(1));
+        // Here the orbit first comes within the tolerance after two turns of the 2-cycle
+        assert_eq!(find(Complex::new(-1.1, 0.05)), Some(2));
+        // A point on the real axis outside the set escapes to infinity, never repeating
+        assert_eq!(find(Complex::new(0.3, 0.0)), None);
+    }
+}
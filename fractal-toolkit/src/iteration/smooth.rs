@@ -0,0 +1,85 @@
+//! Smooth (continuous) escape-time values
+//!
+//! Integer iteration counts change in steps, which shows up as bands of equal color around the
+//! set. Near infinity an orbit of a degree-`p` map grows like `|z| -> |z|^p`, so how far past
+//! the bailout radius the escaping value landed tells how much of the last iteration was needed.
+//! The normalized count `n + 1 - log(log|z|) / log(p)` interpolates between the integer counts and
+//! is continuous across the band boundaries.
+
+use super::IterationResult;
+
+/// Degree assumed for formulas whose leading power isn't a plain number
+pub const DEFAULT_DEGREE: f64 = 2.0;
+
+impl IterationResult {
+    /// Fractional iteration count for smooth coloring
+    ///
+    /// # Arguments
+    ///
+    /// * `degree` - Degree `p` of the iterated map in `z`, see `escape_degree`
+    ///
+    /// # Returns
+    ///
+    /// `n + 1 - log(log|z|) / log(p)` for escaped points, never below 0; the integer count for
+    /// bounded points and for escapes too close to the unit circle to normalize
+    pub fn smooth_iterations(&self, degree: f64) -> f64 {
+        let modulus = self.final_z.norm();
+        if !self.escaped || modulus <= 1.0 || !modulus.is_finite() || degree <= 1.0 {
+            return self.iterations as f64;
+        }
+        (self.iterations as f64 + 1.0 - modulus.ln().ln() / degree.ln()).max(0.0)
+    }
+}
+
+/// Estimate the degree in `z` of a formula from its largest literal power of `z`
+///
+/// `z^3 + c` has degree 3 and `z^2.5 + sin(c)` degree 2.5. Formulas without a numeric power
+/// of `z` greater than 1, such as `z*z + c` or `z^z + c`, get `DEFAULT_DEGREE`.
+///
+/// # Arguments
+///
+/// * `formula` - The iterated formula
+///
+/// # Returns
+///
+/// The degree to pass to `IterationResult::smooth_iterations`
+pub fn escape_degree(formula: &str) -> f64 {
+    let compact: String = formula.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    let mut degree: Option<f64> = None;
+    for (index, _) in compact.match_indices("z^") {
+        // Skip names ending in z, and `z^^2`
+        let after_name = !compact[..index].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+        let exponent = &compact[index + 2..];
+        let exponent = exponent.strip_prefix('(').unwrap_or(exponent);
+        let length = exponent.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(exponent.len());
+        if let (true, Ok(value)) = (after_name, exponent[..length].parse::<f64>()) {
+            degree = Some(degree.map_or(value, |degree| degree.max(value)));
+        }
+    }
+    degree.filter(|&degree| degree > 1.0).unwrap_or(DEFAULT_DEGREE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_smooth_iterations_are_continuous_across_bands() {
+        assert_eq!(escape_degree("z^3 + c"), 3.0);
+        assert_eq!(escape_degree("z^2.5 + sin(c) + z^(2)"), 2.5);
+        assert_eq!(escape_degree("z*z + c"), DEFAULT_DEGREE);
+        assert_eq!(escape_degree("z^z + c"), DEFAULT_DEGREE);
+
+        // A point escaping just past |z| = 2 after n steps gets the same value as its neighbour
+        // that stayed just inside and reached 2^2 = 4 one step later
+        let result = |iterations, modulus| IterationResult { iterations, escaped: true, final_z: Complex::new(modulus, 0.0), period: None, multiplier: None };
+        let early = result(9, 2.0).smooth_iterations(2.0);
+        let late = result(10, 4.0).smooth_iterations(2.0);
+        assert!((late - early).abs() < 1e-12, "{} vs {}", late, early);
+        assert!(late > 10.0 && late < 11.0);
+
+        let bounded = IterationResult { iterations: 64, escaped: false, final_z: Complex::new(0.5, 0.0), period: None, multiplier: None };
+        assert_eq!(bounded.smooth_iterations(2.0), 64.0);
+    }
+}
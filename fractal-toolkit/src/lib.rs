@@ -38,9 +38,57 @@
 //! - `CustomComplex`: Alternative complex number system with configurable imaginary unit
 //! - `MathEvaluator`: Mathematical expression evaluator with custom imaginary unit support
 //! - Algorithm functions for each fractal type with custom arithmetic support
+//! - `fractal_toolkit_core`: Re-export of the dependency-free `no_std` core with custom imaginary
+//!   unit arithmetic and escape-time iteration, for embedded and WebAssembly builds
+//! - `algebra`: The number systems formulas iterate in (`Algebra`), standard complex or with a custom i²,
+//!   behind one generic iteration loop, dual numbers (`Dual`) for automatic derivatives, and bicomplex
+//!   numbers (`Bicomplex`) for Tetrabrot-style 4D slices
+//! - `annotations`: Labelled points and regions of the plane, exported and imported as GeoJSON and drawn over renders
+//! - `antialias`: Supersampling and adaptive subpixel refinement of escape-time renders
+//! - `analysis`: Numerical tools such as locating minibrot centers and Misiurewicz points, and classifying
+//!   orbits as escaping, periodic (with period and multiplier) or bounded (`classify_orbit`), and
+//!   distance estimates and slope shading from derivatives carried along the orbit
+//! - `animation`: Animated output, such as color cycling from a single iteration buffer
+//! - `buddhabrot`: Buddhabrot sampling strategies, such as region-of-interest sampling for deep zooms
+//! - `cancel`: Cancellation tokens that stop a render from another thread, with a partial image or
+//!   a cancelled error
+//! - `capabilities`: Report of the version, compiled-in features and supported names, checked
+//!   against a job's requirements before long renders
+//! - `cli`: The steps `ftk-mandel` and `ftk-julia` share (animations, tiled renders, breadcrumbs,
+//!   sonification, annotations and GPU rendering), for either set
+//! - `coloring`: Layered coloring pipelines compositing escape time, orbit traps, distance estimates
+//!   and final angles with multiply, screen, overlay and alpha blending (`ColoringPipeline`)
+//! - `data_structures`: Dense 2D grids (`Grid2D`, `DensityGrid`) and atomic accumulation for histograms
+//! - `error`: `FractalError`, the structured errors of the formula parser and evaluator
+//! - `gpu`: wgpu compute backend for escape-time and Buddhabrot rendering (with the `gpu` feature)
+//! - `formula`: Catalog of the functions and operators formulas can use, evaluator options, and
+//!   formula algebra (composition, substitution, conjugation)
+//! - `ifs`: Iterated function systems (Barnsley fern, Sierpinski triangle, user maps) rendered with the chaos game
+//! - `newton`: Newton fractal renderer with smooth basin boundaries
+//! - `iteration`: Batch evaluation of escape-time iterations (`iterate_batch`), the built-in
+//!   Burning Ship family (`FractalKind`), interior coloring by period or attractor (`InteriorColoring`)
+//!   and vectorized kernels for the polynomial formulas (`SimdFormula`)
+//! - `interop`: Import of formulas and locations from other fractal software
+//! - `quadtree`: Adaptive rendering that refines only the quadtree cells whose samples disagree
+//! - `quaternion`: Quaternion Julia sets of `q^2 + c`, rendered as 2D slices of 4D space
+//! - `raymarch`: Ray-marched 3D fractals (Mandelbulb, Mandelbox) with Phong lighting
+//! - `rng`: Counter-based random numbers keyed by sample index, for reproducible parallel sampling
+//! - `scene`: Scene files (JSON or TOML) holding everything needed to reproduce a render
+//! - `session`: Render sessions that keep the compiled formula, palette and iteration results warm
+//!   for live previews, recomputing only what each parameter update invalidates (`RenderSession`)
+//! - `sonify`: Sonification of orbits and scanlines as WAV audio (pitch from |z|, loudness from iteration density)
+//! - `telemetry`: Periodic health snapshots and a stall watchdog for long renders
+//! - `tiled`: Tiled rendering to disk that can be resumed or split across processes, then stitched
+//! - `quality`: Named draft/standard/production profiles bundling iterations, anti-aliasing, precision and samples
+//! - `palette`: Cyclic phase wheels (`PhaseWheel`) for domain coloring and other angle-based coloring
+//! - `postfx`: Post-processing effects (bloom, unsharp mask, vignette) applied to rendered images
+//! - `output`: Additional output formats, such as normalized float TIFF/PGM for analysis, and
+//!   per-tile content hashes for verifying reproducible renders, interior/exterior layers, and
+//!   re-rendering crops of earlier renders (`reframe_from_png`)
+//! - `viewport`: Views described by center and magnification, with full-precision centers
 
 use num_complex::Complex;
-use rand::{Rng, SeedableRng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -48,6 +96,50 @@ use std::sync::Arc;
 use chrono::Local;
 use image::{ImageBuffer, Rgba};
 
+pub use fractal_toolkit_core;
+
+pub mod algebra;
+pub mod analysis;
+pub mod annotations;
+pub mod antialias;
+pub mod animation;
+pub mod buddhabrot;
+pub mod cancel;
+pub mod capabilities;
+pub mod cli;
+pub mod coloring;
+pub mod data_structures;
+pub mod error;
+pub mod formula;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod ifs;
+pub mod interop;
+pub mod iteration;
+pub mod newton;
+pub mod output;
+pub mod palette;
+pub mod postfx;
+pub mod quadtree;
+pub mod quality;
+pub mod quaternion;
+pub mod raymarch;
+pub mod rng;
+pub mod scene;
+pub mod session;
+pub mod sonify;
+pub mod telemetry;
+pub mod tiled;
+pub mod viewport;
+
+pub use antialias::AntiAliasing;
+pub use capabilities::{capabilities, Capabilities};
+pub use data_structures::{AtomicGrid, DensityGrid, Grid2D};
+pub use error::FractalError;
+pub use formula::{BranchChoice, CompiledFormula, EvaluatorOptions, Formula, FormulaError, FormulaVariable, ParseMode, SingularValues};
+pub use iteration::{escape_degree, iterate_batch, FractalKind, InteriorColoring, IterationResult, Precision};
+pub use viewport::Viewport;
+
 /// Custom complex number system with configurable imaginary unit
 ///
 /// This structure implements an alternative complex number system where i² can equal any complex value.
@@ -60,6 +152,10 @@ use image::{ImageBuffer, Rgba};
 /// (a + bi) * (c + di) = ac + (ad + bc)*i + bd*i²
 /// where i² is the custom value specified in i_squared.
 ///
+/// `exp`, `ln`, `sqrt`, `sin`, `cos`, `sinh`, `cosh`, `powi` and `pow` are computed in the custom
+/// system too (see `CustomComplex::map_analytic`), as are formulas that `formula_is_real` accepts,
+/// so `sin(z) + c` iterates with the chosen i² rather than with the standard one.
+///
 /// # Examples
 ///
 /// ```
@@ -158,32 +254,9 @@ impl CustomComplex {
     /// Real part = ac + Re(bd * custom_value)
     /// Imaginary part = (ad + bc) + Im(bd * custom_value)
     pub fn multiply(&self, other: &Self) -> Self {
-        // (a + bi) * (c + di) = ac + ad*i + bc*i + bd*i²
-        // = ac + (ad + bc)*i + bd*i²
         // Since our custom i² value is stored in other.i_squared, we have bd*i² = bd * other.i_squared
-        let a = self.re;
-        let b = self.im;
-        let c = other.re;
-        let d = other.im;
-
-        let ac = a * c;
-        let ad = a * d;
-        let bc = b * c;
-        let bd = b * d;
-
-        // bd * i² where i² is our custom value
-        let bd_i_squared = bd * other.i_squared;
-
-        // The real part: ac + Re(bd * i²)
-        let real_part = ac + bd_i_squared.re;
-        // The imaginary part: ad + bc + Im(bd * i²)
-        let imag_part = ad + bc + bd_i_squared.im;
-
-        Self {
-            re: real_part,
-            im: imag_part,
-            i_squared: other.i_squared,  // Use the same i_squared value as the other operand
-        }
+        let product = self.to_core().mul(other.to_core(), core_i_squared(other.i_squared));
+        Self::from_core(product, other.i_squared)  // Use the same i_squared value as the other operand
     }
 
     /// Perform addition in the custom complex number system
@@ -230,6 +303,60 @@ impl CustomComplex {
         }
     }
 
+    /// Get the multiplicative inverse in the custom complex number system
+    ///
+    /// With i² = q + p*i (q and p being the real and imaginary parts of i_squared), the conjugate
+    /// of c + di is (c + dp) - di, and their product is the real number c² + cdp - d²q. Dividing
+    /// the conjugate by it gives the inverse.
+    ///
+    /// # Returns
+    ///
+    /// A CustomComplex number w with self * w = 1, with infinite or NaN components when that
+    /// product is zero (for example at c = ±d when i² = 1, the zero divisors of split complex numbers)
+    pub fn inverse(&self) -> Self {
+        Self::from_core(self.to_core().inverse(core_i_squared(self.i_squared)), self.i_squared)
+    }
+
+    /// The components as an element of `fractal_toolkit_core`, whose arithmetic this uses
+    pub fn to_core(&self) -> fractal_toolkit_core::Element {
+        fractal_toolkit_core::Element::new(self.re, self.im)
+    }
+
+    /// A CustomComplex number from an element of `fractal_toolkit_core` and the value of i²
+    pub fn from_core(element: fractal_toolkit_core::Element, i_squared: Complex<f64>) -> Self {
+        Self { re: element.re, im: element.im, i_squared }
+    }
+
+    /// The i² value two operands share in the arithmetic operators
+    ///
+    /// A purely real number belongs to every number system, so it takes the other operand's
+    /// i²; otherwise the left operand's i² is used.
+    fn common_i_squared(&self, other: &Self) -> Complex<f64> {
+        if self.im == 0.0 {
+            other.i_squared
+        } else {
+            self.i_squared
+        }
+    }
+
+    fn op_add(&self, other: &Self) -> Self {
+        Self { re: self.re + other.re, im: self.im + other.im, i_squared: self.common_i_squared(other) }
+    }
+
+    fn op_sub(&self, other: &Self) -> Self {
+        Self { re: self.re - other.re, im: self.im - other.im, i_squared: self.common_i_squared(other) }
+    }
+
+    fn op_mul(&self, other: &Self) -> Self {
+        let i_squared = self.common_i_squared(other);
+        Self { i_squared, ..*self }.multiply(&Self { i_squared, ..*other })
+    }
+
+    fn op_div(&self, other: &Self) -> Self {
+        let i_squared = self.common_i_squared(other);
+        Self { i_squared, ..*self }.multiply(&Self { i_squared, ..*other }.inverse())
+    }
+
     /// Get the norm squared of the complex number
     ///
     /// The norm squared is calculated as the sum of squares of the real and imaginary components:
@@ -259,14 +386,10 @@ impl CustomComplex {
     pub fn arg(&self) -> f64 {
         self.im.atan2(self.re)
     }
-    /// Custom power operation that respects the custom imaginary unit
-    ///
-    /// This method implements complex exponentiation z^w in the custom complex number system.
-    /// The power operation is computed using the standard complex power formula z^w = exp(w * ln(z)),
-    /// but the result is converted back to the custom complex number system with the same i² value.
+    /// Power operation that respects the custom imaginary unit
     ///
-    /// Note: This is a simplified implementation that uses the standard complex power function
-    /// but maintains the custom imaginary unit property in the result.
+    /// Integer real exponents use repeated multiplication (`powi`); other exponents use
+    /// z^w = exp(w * ln(z)), evaluated in the custom number system like `exp` and `ln`.
     ///
     /// # Arguments
     ///
@@ -276,19 +399,237 @@ impl CustomComplex {
     ///
     /// A new CustomComplex number representing z^exp in the custom system
     pub fn pow(&self, exp: &Self) -> Self {
-        // For complex exponentiation z^w where z and w are complex numbers,
-        // the standard formula is: z^w = exp(w * ln(z))
-        // But with a custom imaginary unit, we need to be more careful
-        // For now, we'll use the standard complex power function but with awareness of the custom i
-        let z = self.to_standard();
-        let w = exp.to_standard();
+        if exp.im == 0.0 && exp.re.fract() == 0.0 && exp.re.abs() <= i32::MAX as f64 {
+            return self.powi(exp.re as i32);
+        }
+        let i_squared = self.common_i_squared(exp);
+        Self::map_analytic(&Self { i_squared, ..*self }, &Self { i_squared, ..*exp }, |z, w| Ok(complex_pow(z, w)))
+            .unwrap_or_else(|_| Self::new(f64::NAN, f64::NAN, i_squared))
+    }
+
+    /// Integer power by repeated squaring in the custom number system
+    ///
+    /// Negative powers invert first, so they have the zero divisors of `inverse`.
+    pub fn powi(&self, n: i32) -> Self {
+        Self::from_core(self.to_core().powi(n, core_i_squared(self.i_squared)), self.i_squared)
+    }
+
+    /// Exponential in the custom number system
+    pub fn exp(&self) -> Self {
+        self.map_unary(Complex::exp)
+    }
+
+    /// Principal natural logarithm in the custom number system
+    ///
+    /// NaN where the logarithm has no value in the system, such as split complex numbers whose
+    /// light-cone coordinates (see `CustomComplex::map_analytic`) aren't both positive.
+    pub fn ln(&self) -> Self {
+        self.map_unary(Complex::ln)
+    }
+
+    /// Principal square root in the custom number system, NaN where there is none
+    pub fn sqrt(&self) -> Self {
+        self.map_unary(Complex::sqrt)
+    }
+
+    /// Sine in the custom number system
+    pub fn sin(&self) -> Self {
+        self.map_unary(Complex::sin)
+    }
+
+    /// Cosine in the custom number system
+    pub fn cos(&self) -> Self {
+        self.map_unary(Complex::cos)
+    }
+
+    /// Hyperbolic sine in the custom number system
+    pub fn sinh(&self) -> Self {
+        self.map_unary(Complex::sinh)
+    }
+
+    /// Hyperbolic cosine in the custom number system
+    pub fn cosh(&self) -> Self {
+        self.map_unary(Complex::cosh)
+    }
+
+    fn map_unary(&self, f: fn(Complex<f64>) -> Complex<f64>) -> Self {
+        Self::map_analytic(self, self, |z, _| Ok(f(z))).unwrap_or_else(|_| Self::new(f64::NAN, f64::NAN, self.i_squared))
+    }
+
+    /// Evaluate a function of two variables in the custom number system
+    ///
+    /// With i² = q + p*i, the number system is a commutative 2D real algebra, and a + bi behaves
+    /// like a + bλ for the roots λ of t² = q + p*t, which turns it into a familiar system:
+    ///
+    /// * Complex roots λ, λ̄ (p² + 4q < 0): a + bi ↦ a + bλ is an isomorphism onto the complex
+    ///   numbers, so `f` is evaluated once at complex arguments.
+    /// * Real roots λ₁ ≠ λ₂ (split complex numbers and their relatives): a + bi ↦ (a + bλ₁, a + bλ₂)
+    ///   is an isomorphism onto pairs of reals, so `f` is evaluated at each real coordinate.
+    ///   A coordinate where `f` has no real value, such as ln of a negative number, gives NaN.
+    /// * A double root λ (dual numbers and their relatives): a + bi = (a + bλ) + bε with ε² = 0,
+    ///   and f(x + yε) = f(x) + f'(x)yε. The derivative comes from a complex step,
+    ///   Im f(x + hy i) / h, which has no cancellation error.
+    ///
+    /// For this to be the function's extension to the custom system, `f` must be analytic with
+    /// real coefficients, f(z̄, w̄) = conj f(z, w), like every polynomial and the exp, ln, trig and
+    /// hyperbolic functions with their principal branches. Both operands must share `z.i_squared`.
+    ///
+    /// # Arguments
+    ///
+    /// * `z` - First argument
+    /// * `w` - Second argument
+    /// * `f` - The function on standard complex numbers
+    ///
+    /// # Returns
+    ///
+    /// f(z, w) in the custom system, or the error `f` returned
+    pub fn map_analytic<F>(z: &Self, w: &Self, f: F) -> Result<Self, FractalError>
+    where
+        F: Fn(Complex<f64>, Complex<f64>) -> Result<Complex<f64>, FractalError>,
+    {
+        // Step of the complex-step derivative; small enough that its square vanishes against 1
+        const COMPLEX_STEP: f64 = 1e-20;
+        let i_squared = z.i_squared;
+        let (q, p) = (i_squared.re, i_squared.im);
+        let discriminant = p * p + 4.0 * q;
+        let along = |root: Complex<f64>, x: &Self| Complex::new(x.re, 0.0) + root * x.im;
+        let real = |value: Complex<f64>| if value.im == 0.0 { value.re } else { f64::NAN };
+        // Coefficients of the result from its image at a root
+        let (alpha, beta) = if discriminant < 0.0 {
+            let root = Complex::new(p / 2.0, (-discriminant).sqrt() / 2.0);
+            let value = f(along(root, z), along(root, w))?;
+            let beta = value.im / root.im;
+            (value.re - beta * root.re, beta)
+        } else if discriminant > 0.0 {
+            let (root_1, root_2) = ((p + discriminant.sqrt()) / 2.0, (p - discriminant.sqrt()) / 2.0);
+            let value_1 = real(f(along(root_1.into(), z), along(root_1.into(), w))?);
+            let value_2 = real(f(along(root_2.into(), z), along(root_2.into(), w))?);
+            let beta = (value_1 - value_2) / (root_1 - root_2);
+            (value_1 - beta * root_1, beta)
+        } else {
+            let root = Complex::new(p / 2.0, 0.0);
+            let (x_z, x_w) = (along(root, z), along(root, w));
+            let value = real(f(x_z, x_w)?);
+            let stepped = f(x_z + Complex::new(0.0, COMPLEX_STEP * z.im), x_w + Complex::new(0.0, COMPLEX_STEP * w.im))?;
+            let beta = stepped.im / COMPLEX_STEP;
+            (value - beta * root.re, beta)
+        };
+        Ok(Self::new(alpha, beta, i_squared))
+    }
+
+    /// Evaluate one step of a formula in the custom number system
+    ///
+    /// Only meaningful for formulas that `formula_is_real` accepts; check that once before
+    /// iterating rather than on every step.
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The compiled formula
+    /// * `z` - The current value in the iteration
+    /// * `c` - The parameter, in the same number system as `z`
+    ///
+    /// # Returns
+    ///
+    /// The formula's value, or its evaluation error
+    pub fn eval_formula(formula: &CompiledFormula, z: &Self, c: &Self) -> Result<Self, FractalError> {
+        Self::map_analytic(z, &Self { i_squared: z.i_squared, ..*c }, |z, c| formula.try_eval(z, c))
+    }
+
+    /// Whether a formula can be evaluated in custom number systems with `eval_formula`
+    ///
+    /// Literals such as `i` and functions such as `conj`, `abs` or `re` refer to the standard
+    /// complex numbers and have no counterpart in other systems; see
+    /// `CompiledFormula::has_real_coefficients`.
+    pub fn formula_is_real(formula: &CompiledFormula) -> bool {
+        formula.has_real_coefficients()
+    }
+}
+
+/// The value of i² in the form `fractal_toolkit_core` takes it
+fn core_i_squared(i_squared: Complex<f64>) -> fractal_toolkit_core::ISquared {
+    fractal_toolkit_core::ISquared::new(i_squared.re, i_squared.im)
+}
+
+/// Implement an arithmetic operator for every combination of owned and borrowed CustomComplex
+/// operands, so formula code can write `z * z + c` instead of `z.multiply(&z).add(&c)`
+macro_rules! custom_complex_operator {
+    ($trait:ident, $method:ident, $op:ident) => {
+        impl std::ops::$trait for CustomComplex {
+            type Output = CustomComplex;
+            fn $method(self, other: CustomComplex) -> CustomComplex {
+                CustomComplex::$op(&self, &other)
+            }
+        }
+
+        impl<'a> std::ops::$trait<&'a CustomComplex> for CustomComplex {
+            type Output = CustomComplex;
+            fn $method(self, other: &'a CustomComplex) -> CustomComplex {
+                CustomComplex::$op(&self, other)
+            }
+        }
+
+        impl<'a> std::ops::$trait<CustomComplex> for &'a CustomComplex {
+            type Output = CustomComplex;
+            fn $method(self, other: CustomComplex) -> CustomComplex {
+                CustomComplex::$op(self, &other)
+            }
+        }
+
+        impl<'a, 'b> std::ops::$trait<&'b CustomComplex> for &'a CustomComplex {
+            type Output = CustomComplex;
+            fn $method(self, other: &'b CustomComplex) -> CustomComplex {
+                CustomComplex::$op(self, other)
+            }
+        }
+    };
+}
 
-        // Use the standard complex power function
-        let result = complex_pow(z, w);
-        Self::from_standard(result, self.i_squared)
+custom_complex_operator!(Add, add, op_add);
+custom_complex_operator!(Sub, sub, op_sub);
+custom_complex_operator!(Mul, mul, op_mul);
+custom_complex_operator!(Div, div, op_div);
+
+impl std::ops::Neg for CustomComplex {
+    type Output = CustomComplex;
+    fn neg(self) -> CustomComplex {
+        CustomComplex { re: -self.re, im: -self.im, i_squared: self.i_squared }
+    }
+}
+
+/// Two numbers are equal when their components and their number systems are
+impl PartialEq for CustomComplex {
+    fn eq(&self, other: &Self) -> bool {
+        self.re == other.re && self.im == other.im && self.i_squared == other.i_squared
+    }
+}
+
+/// Formats as `a+bi`, like `Complex`, followed by the value of i² unless it is -1
+impl std::fmt::Display for CustomComplex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.to_standard(), f)?;
+        if self.i_squared != Complex::new(-1.0, 0.0) {
+            write!(f, " (i² = {})", self.i_squared)?;
+        }
+        Ok(())
+    }
+}
+
+/// Zero of the standard system; being real, it adopts the other operand's i² in arithmetic
+impl num::Zero for CustomComplex {
+    fn zero() -> Self {
+        CustomComplex::new(0.0, 0.0, Complex::new(-1.0, 0.0))
     }
 
+    fn is_zero(&self) -> bool {
+        self.re == 0.0 && self.im == 0.0
+    }
+}
 
+/// One of the standard system; being real, it adopts the other operand's i² in arithmetic
+impl num::One for CustomComplex {
+    fn one() -> Self {
+        CustomComplex::new(1.0, 0.0, Complex::new(-1.0, 0.0))
+    }
 }
 
 /// Helper function to compute complex power z^w = exp(w * ln(z))
@@ -338,10 +679,50 @@ pub struct MathEvaluator;
 impl MathEvaluator {
     /// Evaluate a mathematical formula with a parameter for complex numbers
     /// Supports various functions like sin, cos, tan, exp, log, and more
-    pub fn evaluate_formula_with_param(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
+    ///
+    /// The formula is parsed once per thread and reused while the same formula is evaluated
+    /// repeatedly; hold a `CompiledFormula` to avoid even the string comparison.
+    pub fn evaluate_formula_with_param(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        formula::compiled::compiled_for(formula).try_eval(z, param)
+    }
+
+    /// Evaluate a formula like `evaluate_formula_with_param`, with explicit evaluator options
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The mathematical formula to evaluate
+    /// * `z` - The current value in the iteration
+    /// * `param` - The complex parameter (typically 'c')
+    /// * `options` - Parser mode and other evaluator settings
+    ///
+    /// # Returns
+    ///
+    /// The value of the formula, or an error if it is invalid under the chosen options
+    pub fn evaluate_formula_with_options(formula: &str, z: Complex<f64>, param: Complex<f64>, options: &EvaluatorOptions) -> Result<Complex<f64>, FractalError> {
         let formula_lower = formula.trim().to_lowercase();
 
-        match formula_lower.as_str() {
+        // The hand-written formulas only know the default branch and singular-value choices
+        let builtin = if options.has_default_semantics() { Self::evaluate_builtin_formula(&formula_lower, z, param) } else { None };
+        match builtin {
+            Some(result) => result,
+            // For more complex expressions, try to parse them
+            None => ExpressionParser::evaluate_with_options(formula, z, param, options),
+        }
+    }
+
+    /// Evaluate one of the common formulas that have a hand-written implementation
+    ///
+    /// # Arguments
+    ///
+    /// * `formula_lower` - The trimmed, lowercased formula
+    /// * `z` - The current value in the iteration
+    /// * `param` - The complex parameter (typically 'c')
+    ///
+    /// # Returns
+    ///
+    /// The value of the formula, or None if it has no built-in implementation and must be parsed
+    pub(crate) fn evaluate_builtin_formula(formula_lower: &str, z: Complex<f64>, param: Complex<f64>) -> Option<Result<Complex<f64>, FractalError>> {
+        let value = match formula_lower {
             "z^2 + c" => Ok(z * z + param),
             "z^3 + c" => Ok(z * z * z + param),
             "z^4 + c" => Ok(z * z * z * z + param),
@@ -419,15 +800,31 @@ impl MathEvaluator {
                     Ok(Complex::new(1.0, 0.0) + param)
                 }
             },
-            _ => {
-                // For more complex expressions, try to parse them
-                ExpressionParser::evaluate(formula, z, param)
-            }
-        }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Check that a formula parses, without evaluating it
+    ///
+    /// Useful for rejecting a bad formula once up front instead of on every pixel; with
+    /// `ParseMode::Strict` it also rejects ambiguous constructs that lenient evaluation accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The formula to check
+    /// * `options` - Parser mode and other evaluator settings
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the formula is valid, or the first problem found with its location and a
+    /// rendered diagnostic
+    pub fn validate_formula(formula: &str, options: &EvaluatorOptions) -> Result<(), FormulaError> {
+        ExpressionParser::parse(formula, options).map(|_| ())
     }
 
     /// Parse and evaluate more complex mathematical expressions
-    fn parse_and_evaluate(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
+    fn parse_and_evaluate(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         // Use a more sophisticated expression parser
         ExpressionParser::evaluate(formula, z, param)
     }
@@ -459,7 +856,7 @@ impl MathEvaluator {
     /// - Standard: custom_i = Complex::new(0.0, -1.0) → i² = -1 (standard complex numbers)
     /// - Split Complex: custom_i = Complex::new(1.0, 0.0) → i² = 1 (split complex numbers)
     /// - Other: custom_i = Complex::new(1.0, 1.0) → i² = 1+i (alternative complex system)
-    pub fn evaluate_formula_with_param_and_custom_i(formula: &str, z: Complex<f64>, param: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, String> {
+    pub fn evaluate_formula_with_param_and_custom_i(formula: &str, z: Complex<f64>, param: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         let formula_lower = formula.trim().to_lowercase();
 
         match formula_lower.as_str() {
@@ -642,16 +1039,85 @@ impl MathEvaluator {
 struct ExpressionParser;
 
 impl ExpressionParser {
-    pub fn evaluate(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
-        let tokens = Self::tokenize(formula)?;
+    pub fn evaluate(formula: &str, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        Self::evaluate_with_options(formula, z, param, &EvaluatorOptions::default())
+    }
+
+    /// Evaluate an expression, parsing it according to `options`
+    pub fn evaluate_with_options(formula: &str, z: Complex<f64>, param: Complex<f64>, options: &EvaluatorOptions) -> Result<Complex<f64>, FractalError> {
+        let ast = Self::parse(formula, options)?;
+        // A fresh tree has no orbit history to continue branches along
+        formula::semantics::start_orbit();
+        formula::semantics::with_options(options, || ast.evaluate(z, param))
+    }
+
+    /// Parse an expression into an AST without evaluating it
+    ///
+    /// In lenient mode, tokens left over after a complete expression are ignored (so `2z` parses
+    /// as `2`); strict mode reports them. Errors carry the byte range of the token the parser
+    /// stopped at.
+    fn parse(formula: &str, options: &EvaluatorOptions) -> Result<Box<dyn Expression>, FormulaError> {
+        let (tokens, spans) = Self::tokenize_with_spans(formula, options.parse_mode)?;
+        let span_at = |pos: usize| spans.get(pos).cloned().unwrap_or(formula.len()..formula.len());
+        Self::check_limits(formula, &tokens, &spans, options.max_tokens)?;
         let mut pos = 0;
-        let ast = Self::parse_expression(&tokens, &mut pos, z, param)?;
-        let result = ast.evaluate(z, param)?;
-        Ok(result)
+        let zero = Complex::new(0.0, 0.0);
+        let ast = Self::parse_expression(&tokens, &mut pos, zero, zero)
+            .map_err(|message| FormulaError::new(formula, message, span_at(pos)))?;
+
+        if options.parse_mode == ParseMode::Strict && pos < tokens.len() {
+            let span = span_at(pos);
+            let starts_operand = matches!(
+                tokens[pos],
+                Token::Number(_) | Token::ComplexNumber(_) | Token::ImaginaryUnit | Token::Identifier(_) | Token::LeftParen
+            );
+            let message = if starts_operand {
+                format!("Implicit multiplication before '{}'; write '*' explicitly", &formula[span.clone()])
+            } else {
+                format!("Unexpected '{}'", &formula[span.clone()])
+            };
+            return Err(FormulaError::new(formula, message, span));
+        }
+        Ok(ast)
+    }
+
+    /// Reject formulas too large or too deeply nested to parse and evaluate safely
+    ///
+    /// The parser and the syntax tree it builds recurse once per level of nesting and per
+    /// operator in a chain, so without these limits a long or deeply parenthesized formula,
+    /// such as one submitted to a rendering service, would overflow the stack and abort the
+    /// process instead of failing with an error. The token limit is optional (see
+    /// `EvaluatorOptions::max_tokens`); the nesting limit always applies.
+    fn check_limits(formula: &str, tokens: &[Token], spans: &[std::ops::Range<usize>], max_tokens: Option<usize>) -> Result<(), FormulaError> {
+        use formula::MAX_NESTING_DEPTH;
+        if let Some(max_tokens) = max_tokens.filter(|&max_tokens| tokens.len() > max_tokens) {
+            let span = spans[max_tokens].start..formula.len();
+            return Err(FormulaError::new(
+                formula,
+                format!("Formula has {} tokens; at most {} are allowed", tokens.len(), max_tokens),
+                span,
+            ));
+        }
+        let mut depth = 0;
+        for (token, span) in tokens.iter().zip(spans) {
+            match token {
+                Token::LeftParen if depth == MAX_NESTING_DEPTH => {
+                    return Err(FormulaError::new(
+                        formula,
+                        format!("Parentheses nest more than {} deep", MAX_NESTING_DEPTH),
+                        span.clone(),
+                    ));
+                }
+                Token::LeftParen => depth += 1,
+                Token::RightParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     /// Evaluate a mathematical expression with complex numbers and custom imaginary unit
-    pub fn evaluate_with_custom_i(formula: &str, z: Complex<f64>, param: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, String> {
+    pub fn evaluate_with_custom_i(formula: &str, z: Complex<f64>, param: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         // Preprocess the formula to replace 'i' with the custom imaginary unit value
         // This allows users to use 'i' in their formulas and have it interpreted as the custom value
         let processed_formula = formula.replace("i", &format!("({})", custom_complex_to_string(custom_i)));
@@ -660,150 +1126,149 @@ impl ExpressionParser {
         Self::evaluate(&processed_formula, z, param)
     }
 
-    /// Tokenize the input string
-    fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    /// Tokenize the input string, returning the byte range of each token alongside it
+    ///
+    /// Lenient mode inserts a multiplication before an `i` that follows a number, identifier or
+    /// closing parenthesis and reads five or more carets as hexation. Strict mode rejects those
+    /// constructs, and an `i` directly followed by a letter or digit, with the offending span.
+    fn tokenize_with_spans(input: &str, mode: ParseMode) -> Result<(Vec<Token>, Vec<std::ops::Range<usize>>), FormulaError> {
+        let strict = mode == ParseMode::Strict;
         let mut tokens = Vec::new();
-        let mut chars = input.chars().peekable();
+        let mut spans = Vec::new();
+        let mut chars = input.char_indices().peekable();
 
-        while let Some(&ch) = chars.peek() {
+        while let Some(&(start, ch)) = chars.peek() {
             match ch {
                 ' ' | '\t' | '\n' | '\r' => {
                     chars.next(); // Skip whitespace
                 }
-                '+' => {
-                    tokens.push(Token::Plus);
-                    chars.next();
-                }
-                '-' => {
-                    tokens.push(Token::Minus);
-                    chars.next();
-                }
-                '*' => {
-                    tokens.push(Token::Multiply);
-                    chars.next();
-                }
-                '/' => {
-                    tokens.push(Token::Divide);
+                '+' | '-' | '*' | '/' | '(' | ')' | ',' => {
+                    tokens.push(match ch {
+                        '+' => Token::Plus,
+                        '-' => Token::Minus,
+                        '*' => Token::Multiply,
+                        '/' => Token::Divide,
+                        '(' => Token::LeftParen,
+                        ')' => Token::RightParen,
+                        _ => Token::Comma,
+                    });
+                    spans.push(start..start + 1);
                     chars.next();
                 }
                 '^' => {
-                    // Look ahead to count consecutive ^ characters
-                    let mut temp_chars = chars.clone();
+                    // Consume the run of consecutive ^ characters
                     let mut caret_count = 0;
-
-                    // Count how many consecutive ^ characters there are starting from the current position
-                    while let Some(next_char) = temp_chars.next() {
-                        if next_char == '^' {
-                            caret_count += 1;
-                        } else {
-                            break;
-                        }
+                    while let Some(&(_, '^')) = chars.peek() {
+                        caret_count += 1;
+                        chars.next();
                     }
 
-                    // Now consume the appropriate number of ^ characters from the main iterator
-                    match caret_count {
-                        1 => {
-                            // Single ^ is power
-                            tokens.push(Token::Power);
-                            chars.next(); // consume the ^
-                        }
-                        2 => {
-                            // Double ^^ is tetration
-                            tokens.push(Token::Tetration);
-                            chars.next(); // consume first ^
-                            chars.next(); // consume second ^
-                        }
-                        3 => {
-                            // Triple ^^^ is pentation
-                            tokens.push(Token::Pentation);
-                            chars.next(); // consume first ^
-                            chars.next(); // consume second ^
-                            chars.next(); // consume third ^
-                        }
-                        4 => {
-                            // Quadruple ^^^^ is hexation
-                            tokens.push(Token::Hexation);
-                            chars.next(); // consume first ^
-                            chars.next(); // consume second ^
-                            chars.next(); // consume third ^
-                            chars.next(); // consume fourth ^
-                        }
-                        _ => {
-                            // For more than 4 carets, treat as hexation
-                            // Consume all the carets
-                            for _ in 0..caret_count {
-                                chars.next();
-                            }
-                            tokens.push(Token::Hexation);
+                    let token = match caret_count {
+                        1 => Token::Power,      // Single ^ is power
+                        2 => Token::Tetration,  // Double ^^ is tetration
+                        3 => Token::Pentation,  // Triple ^^^ is pentation
+                        4 => Token::Hexation,   // Quadruple ^^^^ is hexation
+                        _ if strict => {
+                            return Err(FormulaError::new(
+                                input,
+                                format!("Operator '{}' has more than four carets; the longest operator is '^^^^' (hexation)", "^".repeat(caret_count)),
+                                start..start + caret_count,
+                            ));
                         }
-                    }
-                }
-                '(' => {
-                    tokens.push(Token::LeftParen);
-                    chars.next();
-                }
-                ')' => {
-                    tokens.push(Token::RightParen);
-                    chars.next();
-                }
-                ',' => {
-                    tokens.push(Token::Comma);
-                    chars.next();
+                        _ => Token::Hexation,   // More than 4 carets are treated as hexation
+                    };
+                    tokens.push(token);
+                    spans.push(start..start + caret_count);
                 }
                 'i' | 'I' => {
+                    chars.next();
                     // Check if this is part of a variable name or just the imaginary unit
-                    if tokens.last().map_or(true, |t| matches!(t, Token::Number(_) | Token::RightParen | Token::Identifier(_))) {
-                        // This is multiplication by i
-                        tokens.push(Token::Multiply);
+                    if strict {
+                        if let Some(&(next, next_ch)) = chars.peek() {
+                            if next_ch.is_alphanumeric() || next_ch == '_' {
+                                let end = input[next..]
+                                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                                    .map_or(input.len(), |len| next + len);
+                                return Err(FormulaError::new(
+                                    input,
+                                    format!(
+                                        "'{}' is read as 'i' followed by '{}'; write 'i*{}' or separate them",
+                                        &input[start..end], &input[next..end], &input[next..end]
+                                    ),
+                                    start..end,
+                                ));
+                            }
+                        }
+                    }
+                    if tokens.last().is_none_or(|t| matches!(t, Token::Number(_) | Token::RightParen | Token::Identifier(_))) {
+                        if strict && !tokens.is_empty() {
+                            return Err(FormulaError::new(
+                                input,
+                                "Implicit multiplication before 'i'; write '*i' explicitly",
+                                start..start + ch.len_utf8(),
+                            ));
+                        }
+                        if !strict {
+                            // This is multiplication by i
+                            tokens.push(Token::Multiply);
+                            spans.push(start..start);
+                        }
                     }
                     tokens.push(Token::ImaginaryUnit);
-                    chars.next();
+                    spans.push(start..start + ch.len_utf8());
                 }
                 c if c.is_ascii_digit() || c == '.' => {
-                    let mut num_str = String::new();
+                    let mut end = start;
                     let mut has_decimal = false;
 
-                    while let Some(&next_ch) = chars.peek() {
-                        if next_ch.is_ascii_digit() {
-                            num_str.push(next_ch);
-                            chars.next();
-                        } else if next_ch == '.' && !has_decimal {
-                            num_str.push(next_ch);
-                            has_decimal = true;
+                    while let Some(&(pos, next_ch)) = chars.peek() {
+                        if next_ch.is_ascii_digit() || (next_ch == '.' && !has_decimal) {
+                            has_decimal |= next_ch == '.';
+                            end = pos + 1;
                             chars.next();
                         } else {
                             break;
                         }
                     }
+                    let num_str = &input[start..end];
 
                     // Check if followed by 'i' (imaginary number)
-                    if chars.peek() == Some(&'i') || chars.peek() == Some(&'I') {
-                        num_str.push('i');
+                    if let Some(&(pos, 'i' | 'I')) = chars.peek() {
                         chars.next();
-                        tokens.push(Token::ComplexNumber(num_str));
+                        tokens.push(Token::ComplexNumber(format!("{}i", num_str)));
+                        spans.push(start..pos + 1);
                     } else {
-                        tokens.push(Token::Number(num_str.parse().unwrap()));
+                        let value = num_str
+                            .parse()
+                            .map_err(|_| FormulaError::new(input, format!("Invalid number '{}'", num_str), start..end))?;
+                        tokens.push(Token::Number(value));
+                        spans.push(start..end);
                     }
                 }
                 c if c.is_alphabetic() => {
-                    let mut ident = String::new();
-                    while let Some(&next_ch) = chars.peek() {
+                    let mut end = start;
+                    while let Some(&(pos, next_ch)) = chars.peek() {
                         if next_ch.is_alphanumeric() || next_ch == '_' {
-                            ident.push(next_ch);
+                            end = pos + next_ch.len_utf8();
                             chars.next();
                         } else {
                             break;
                         }
                     }
-                    tokens.push(Token::Identifier(ident));
+                    tokens.push(Token::Identifier(input[start..end].to_string()));
+                    spans.push(start..end);
                 }
                 _ => {
-                    return Err(format!("Unexpected character: {}", ch));
+                    return Err(FormulaError::new(
+                        input,
+                        format!("Unexpected character '{}'", ch),
+                        start..start + ch.len_utf8(),
+                    ));
                 }
             }
         }
 
-        Ok(tokens)
+        Ok((tokens, spans))
     }
 
     /// Parse tokens into an expression AST
@@ -903,6 +1368,52 @@ impl ExpressionParser {
         }
     }
 
+    /// Consume `expected` or fail with `message`
+    fn expect_token(tokens: &[Token], pos: &mut usize, expected: &Token, message: &str) -> Result<(), String> {
+        if *pos < tokens.len() && std::mem::discriminant(&tokens[*pos]) == std::mem::discriminant(expected) {
+            *pos += 1;
+            Ok(())
+        } else {
+            Err(message.to_string())
+        }
+    }
+
+    /// Parse a parenthesized, comma-separated argument list after a function name
+    fn parse_arguments(name: &str, tokens: &[Token], pos: &mut usize, z: Complex<f64>, param: Complex<f64>) -> Result<Vec<Box<dyn Expression>>, String> {
+        Self::expect_token(tokens, pos, &Token::LeftParen, &format!("Expected opening parenthesis for {}", name))?;
+        let mut args = vec![Self::parse_expression(tokens, pos, z, param)?];
+        while *pos < tokens.len() && matches!(tokens[*pos], Token::Comma) {
+            *pos += 1;
+            args.push(Self::parse_expression(tokens, pos, z, param)?);
+        }
+        Self::expect_token(tokens, pos, &Token::RightParen, &format!("Expected closing parenthesis for {}", name))?;
+        Ok(args)
+    }
+
+    /// Parse the arguments of `sum(k, from, to, expr)` or `prod(k, from, to, expr)`
+    fn parse_series(name: &str, kind: SeriesKind, tokens: &[Token], pos: &mut usize, z: Complex<f64>, param: Complex<f64>) -> Result<Box<dyn Expression>, String> {
+        Self::expect_token(tokens, pos, &Token::LeftParen, &format!("Expected opening parenthesis for {}", name))?;
+        let variable = match tokens.get(*pos) {
+            Some(Token::Identifier(variable))
+                if !matches!(variable.as_str(), "z" | "c" | "param")
+                    && !MathEvaluator::supported_functions().iter().any(|f| f.name == variable) =>
+            {
+                variable.clone()
+            }
+            _ => return Err(format!("Expected an index variable name such as k as the first argument of {}", name)),
+        };
+        *pos += 1;
+        let separator = format!("Expected ',' between the arguments of {}(variable, from, to, expression)", name);
+        Self::expect_token(tokens, pos, &Token::Comma, &separator)?;
+        let from = Self::parse_expression(tokens, pos, z, param)?;
+        Self::expect_token(tokens, pos, &Token::Comma, &separator)?;
+        let to = Self::parse_expression(tokens, pos, z, param)?;
+        Self::expect_token(tokens, pos, &Token::Comma, &separator)?;
+        let body = formula::series::with_bound_name(&variable, || Self::parse_expression(tokens, pos, z, param))?;
+        Self::expect_token(tokens, pos, &Token::RightParen, &format!("Expected closing parenthesis for {}", name))?;
+        Ok(Box::new(Series { kind, from, to, body }))
+    }
+
     fn parse_primary(tokens: &[Token], pos: &mut usize, z: Complex<f64>, param: Complex<f64>) -> Result<Box<dyn Expression>, String> {
         if *pos >= tokens.len() {
             return Err("Unexpected end of expression".to_string());
@@ -915,7 +1426,7 @@ impl ExpressionParser {
             }
             Token::ComplexNumber(s) => {
                 *pos += 1;
-                let s = s.trim_end_matches(|c| c == 'i' || c == 'I');
+                let s = s.trim_end_matches(['i', 'I']);
                 let num: f64 = s.parse().map_err(|_| format!("Invalid complex number: {}", s))?;
                 Ok(Box::new(Constant(Complex::new(0.0, num))))
             }
@@ -929,6 +1440,78 @@ impl ExpressionParser {
                 match name.as_str() {
                     "z" => Ok(Box::new(Variable::Z)),
                     "c" | "param" => Ok(Box::new(Variable::C)),
+                    "sum" | "prod" => {
+                        let kind = if name == "sum" { SeriesKind::Sum } else { SeriesKind::Product };
+                        Self::parse_series(name, kind, tokens, pos, z, param)
+                    }
+                    "cheb" | "legendre" => {
+                        let family = if name == "cheb" { PolynomialFamily::Chebyshev } else { PolynomialFamily::Legendre };
+                        let mut args = Self::parse_arguments(name, tokens, pos, z, param)?;
+                        if args.len() != 2 {
+                            return Err(format!("{} takes two arguments: {}(n, z)", name, name));
+                        }
+                        let argument = args.pop().unwrap();
+                        let degree = args.pop().unwrap();
+                        Ok(Box::new(OrthogonalPolynomial { family, degree, argument }))
+                    }
+                    "theta1" | "theta2" | "theta3" | "theta4" => {
+                        let kind = ThetaKind::from_name(name).unwrap();
+                        let mut args = Self::parse_arguments(name, tokens, pos, z, param)?;
+                        if args.len() != 2 {
+                            return Err(format!("{} takes two arguments: {}(z, tau)", name, name));
+                        }
+                        let tau = args.pop().unwrap();
+                        let argument = args.pop().unwrap();
+                        Ok(Box::new(Theta { kind, argument, tau }))
+                    }
+                    "wp" => {
+                        let mut args = Self::parse_arguments(name, tokens, pos, z, param)?;
+                        if args.len() != 3 {
+                            return Err("wp takes three arguments: wp(z, w1, w2)".to_string());
+                        }
+                        let period2 = args.pop().unwrap();
+                        let period1 = args.pop().unwrap();
+                        let argument = args.pop().unwrap();
+                        Ok(Box::new(WeierstrassP { argument, period1, period2 }))
+                    }
+                    "cached" => {
+                        use std::hash::{Hash, Hasher};
+                        Self::expect_token(tokens, pos, &Token::LeftParen, "Expected opening parenthesis for cached")?;
+                        let start = *pos;
+                        let inner = Self::parse_expression(tokens, pos, z, param)?;
+                        let subtree_tokens = &tokens[start..*pos];
+                        let quantum = if matches!(tokens.get(*pos), Some(Token::Comma)) {
+                            *pos += 1;
+                            Some(Self::parse_expression(tokens, pos, z, param)?)
+                        } else {
+                            None
+                        };
+                        Self::expect_token(tokens, pos, &Token::RightParen, "Expected closing parenthesis for cached")?;
+
+                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                        format!("{:?}", subtree_tokens).hash(&mut hasher);
+                        let uses = |names: &[&str]| {
+                            subtree_tokens.iter().any(|t| matches!(t, Token::Identifier(n) if names.contains(&n.as_str())))
+                        };
+                        Ok(Box::new(Cached {
+                            subtree: hasher.finish(),
+                            uses_z: uses(&["z"]),
+                            uses_param: uses(&["c", "param"]),
+                            uses_bound: subtree_tokens.iter().any(|t| {
+                                matches!(t, Token::Identifier(n) if formula::series::lookup_bound_name(n).is_some())
+                            }),
+                            inner,
+                            quantum,
+                        }))
+                    }
+                    "cfrac" => {
+                        let mut args = Self::parse_arguments(name, tokens, pos, z, param)?;
+                        if args.len() < 2 {
+                            return Err("cfrac needs at least two arguments: cfrac(a0, ..., depth)".to_string());
+                        }
+                        let depth = args.pop().unwrap();
+                        Ok(Box::new(ContinuedFraction { terms: args, depth }))
+                    }
                     "sin" => {
                         if *pos < tokens.len() && matches!(tokens[*pos], Token::LeftParen) {
                             *pos += 1;
@@ -1195,7 +1778,13 @@ impl ExpressionParser {
                             Err("Expected opening parenthesis for tanh".to_string())
                         }
                     }
-                    _ => Err(format!("Unknown identifier: {}", name)),
+                    _ => match formula::series::lookup_bound_name(name) {
+                        Some(index) => Ok(Box::new(BoundVariable(index))),
+                        None => {
+                            *pos -= 1; // Point the error at the identifier itself
+                            Err(format!("Unknown identifier: {}", name))
+                        }
+                    },
                 }
             }
             Token::LeftParen => {
@@ -1232,16 +1821,64 @@ enum Token {
     Comma,
 }
 
-trait Expression {
-    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String>;
+use algebra::Dual;
+use formula::cache::Cached;
+use formula::elliptic::{Theta, ThetaKind, WeierstrassP};
+use formula::polynomials::{OrthogonalPolynomial, PolynomialFamily};
+use formula::series::{BoundVariable, ContinuedFraction, Series, SeriesKind};
+
+trait Expression: Send + Sync {
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError>;
+
+    /// Evaluate with the derivatives of z and param carried along (see `algebra::Dual`)
+    ///
+    /// Nodes without exact rules fall back to a central difference of `evaluate` in the
+    /// direction of the derivatives.
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
+        numeric_dual(|z, param| self.evaluate(z, param), z, param)
+    }
+
+    /// Name of the node in evaluation profiles (see `formula::profile`), e.g. `gamma()` or `^`
+    fn name(&self) -> &'static str {
+        "expression"
+    }
+
+    /// The node's operands, which profiling wraps in timers of their own
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        Vec::new()
+    }
+}
+
+/// The derivative of `f` in the direction (z.derivative, param.derivative) by a central difference
+pub(crate) fn numeric_dual<F>(f: F, z: Dual, param: Dual) -> Result<Dual, FractalError>
+where
+    F: Fn(Complex<f64>, Complex<f64>) -> Result<Complex<f64>, FractalError>,
+{
+    let value = f(z.value, param.value)?;
+    let direction = (z.derivative.norm_sqr() + param.derivative.norm_sqr()).sqrt();
+    if direction == 0.0 {
+        return Ok(Dual::constant(value));
+    }
+    let h = 1e-6 * (1.0 + z.value.norm() + param.value.norm()) / direction;
+    let ahead = f(z.value + z.derivative * h, param.value + param.derivative * h)?;
+    let behind = f(z.value - z.derivative * h, param.value - param.derivative * h)?;
+    Ok(Dual::new(value, (ahead - behind) / (2.0 * h)))
 }
 
 struct Constant(Complex<f64>);
 
 impl Expression for Constant {
-    fn evaluate(&self, _z: Complex<f64>, _param: Complex<f64>) -> Result<Complex<f64>, String> {
+    fn name(&self) -> &'static str {
+        "constant"
+    }
+
+    fn evaluate(&self, _z: Complex<f64>, _param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         Ok(self.0)
     }
+
+    fn evaluate_dual(&self, _z: Dual, _param: Dual) -> Result<Dual, FractalError> {
+        Ok(Dual::constant(self.0))
+    }
 }
 
 enum Variable {
@@ -1250,7 +1887,21 @@ enum Variable {
 }
 
 impl Expression for Variable {
-    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
+    fn name(&self) -> &'static str {
+        match self {
+            Variable::Z => "z",
+            Variable::C => "c",
+        }
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        match self {
+            Variable::Z => Ok(z),
+            Variable::C => Ok(param),
+        }
+    }
+
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
         match self {
             Variable::Z => Ok(z),
             Variable::C => Ok(param),
@@ -1270,7 +1921,33 @@ enum BinaryOp {
 }
 
 impl Expression for BinaryOp {
-    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
+    fn name(&self) -> &'static str {
+        match self {
+            BinaryOp::Add(..) => "+",
+            BinaryOp::Sub(..) => "-",
+            BinaryOp::Mul(..) => "*",
+            BinaryOp::Div(..) => "/",
+            BinaryOp::Pow(..) => "^",
+            BinaryOp::Tetration(..) => "^^",
+            BinaryOp::Pentation(..) => "^^^",
+            BinaryOp::Hexation(..) => "^^^^",
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        match self {
+            BinaryOp::Add(left, right)
+            | BinaryOp::Sub(left, right)
+            | BinaryOp::Mul(left, right)
+            | BinaryOp::Div(left, right)
+            | BinaryOp::Pow(left, right)
+            | BinaryOp::Tetration(left, right)
+            | BinaryOp::Pentation(left, right)
+            | BinaryOp::Hexation(left, right) => vec![left, right],
+        }
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         match self {
             BinaryOp::Add(left, right) => {
                 let l = left.evaluate(z, param)?;
@@ -1291,123 +1968,14 @@ impl Expression for BinaryOp {
                 let l = left.evaluate(z, param)?;
                 let r = right.evaluate(z, param)?;
                 if r.norm_sqr() < f64::EPSILON {
-                    return Err("Division by zero".to_string());
+                    return Err(FractalError::DivisionByZero);
                 }
                 Ok(l / r)
             }
             BinaryOp::Pow(left, right) => {
                 let base = left.evaluate(z, param)?;
                 let exp = right.evaluate(z, param)?;
-
-                // For complex exponentiation: base^exp = exp(exp * ln(base))
-                if base.norm_sqr() < 1e-10 {
-                    // For very small base values (near zero), handle specially
-                    // In fractal context, 0^w where w is not zero should be 0
-                    if exp.norm_sqr() < 1e-10 {
-                        // This is essentially 0^0, which is typically defined as 1
-                        Ok(Complex::new(1.0, 0.0))
-                    } else {
-                        // 0^w where w is not zero should be 0
-                        Ok(Complex::new(0.0, 0.0))
-                    }
-                } else {
-                    // Check if the exponent is purely real (no imaginary component)
-                    if exp.im.abs() < 1e-10 {
-                        // For real exponents, handle special cases first
-                        // Check if base is zero (which should result in 0 for positive exponents)
-                        if base.norm_sqr() < 1e-10 {
-                            // 0^real_number where real_number > 0 should be 0
-                            // 0^real_number where real_number <= 0 is undefined (return 0 as safe value)
-                            Ok(Complex::new(0.0, 0.0))
-                        } else {
-                            // For non-zero base with real exponent, use the standard approach
-                            let result = base.powf(exp.re);
-
-                            // Check if result is NaN or infinite
-                            if result.re.is_nan() || result.im.is_nan() || result.re.is_infinite() || result.im.is_infinite() {
-                                // Return a safe value if result is problematic
-                                Ok(Complex::new(0.0, 0.0))
-                            } else {
-                                // For fractal generation, even real exponents with non-integer values
-                                // can cause immediate escape for all points, so we need to be conservative
-                                let result_norm = result.norm();
-
-                                // Use a reasonable upper bound to prevent immediate escape
-                                let max_norm = 10.0; // Reasonable upper bound for fractal iteration
-
-                                if result_norm > max_norm {
-                                    let scale_factor = max_norm / result_norm.max(1e-10); // Avoid division by zero
-                                    Ok(Complex::new(result.re * scale_factor, result.im * scale_factor))
-                                } else {
-                                    Ok(result)
-                                }
-                            }
-                        }
-                    } else {
-                        // For complex exponents in fractals, we need a special algorithm
-                        // The standard complex power z^(a+bi) where both a and b are non-zero
-                        // can cause immediate escape for all points, making fractal formation impossible
-                        // This is due to the mathematical properties of complex exponentiation in iterative systems
-
-                        // Instead of using the direct complex power, we'll implement a modified algorithm
-                        // that allows for fractal formation while preserving the mathematical essence
-                        let r = base.norm();
-                        let theta = base.arg();
-
-                        // Calculate using the proper formula: z^w = exp(w * ln(z))
-                        let log_base = Complex::new(r.ln(), theta);
-                        let w_ln_z = exp * log_base;
-                        let result = w_ln_z.exp();
-
-                        // Check if result is NaN or infinite
-                        if result.re.is_nan() || result.im.is_nan() || result.re.is_infinite() || result.im.is_infinite() {
-                            // Use a safe fallback value
-                            Ok(Complex::new(0.0, 0.0))
-                        } else {
-                            // For complex exponents in fractals, we need to be extremely conservative
-                            // The complex power z^(a+bi) where both a and b are non-zero
-                            // can cause immediate escape for all points in the iteration
-                            // This makes fractal formation impossible with the standard algorithm
-                            // Use a much more conservative approach to allow fractal formation
-
-                            // Calculate the magnitude of the result
-                            let result_norm = result.norm();
-
-                            // For fractal generation with complex exponents, use a very conservative limit
-                            // to prevent immediate escape of all points
-                            let max_norm = 2.0; // Very conservative for complex exponents in fractals
-
-                            if result_norm > max_norm {
-                                // Scale down the result significantly to allow for fractal iteration
-                                let scale_factor = max_norm / result_norm.max(1e-10); // Avoid division by zero
-                                Ok(Complex::new(result.re * scale_factor, result.im * scale_factor))
-                            } else {
-                                // For complex exponents, we also need to ensure the result doesn't cause
-                                // immediate escape in subsequent iterations. Let's apply a more sophisticated
-                                // transformation that preserves the mathematical character while allowing
-                                // for fractal formation
-
-                                // Apply a transformation that maps large values to a more manageable range
-                                // but still allows for differentiation between points
-                                let transformed_result = if result_norm > 1.5 {
-                                    // For large results, compress the range logarithmically
-                                    let compressed_norm = 1.0 + 0.5 * (result_norm - 1.5).min(1.0); // Gradually compress
-                                    let scale_factor = compressed_norm / result_norm.max(1e-10);
-                                    Complex::new(result.re * scale_factor, result.im * scale_factor)
-                                } else if result_norm < 0.01 {
-                                    // For very small results, slightly amplify to avoid stagnation
-                                    let amplified_norm = result_norm.max(0.01) * 2.0;
-                                    let scale_factor = amplified_norm / result_norm.max(1e-10);
-                                    Complex::new(result.re * scale_factor, result.im * scale_factor)
-                                } else {
-                                    result
-                                };
-
-                                Ok(transformed_result)
-                            }
-                        }
-                    }
-                }
+                self.power(base, exp)
             }
             BinaryOp::Tetration(left, right) => {
                 let base = left.evaluate(z, param)?;
@@ -1572,6 +2140,159 @@ impl Expression for BinaryOp {
             }
         }
     }
+
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
+        match self {
+            BinaryOp::Add(left, right) => Ok(left.evaluate_dual(z, param)? + right.evaluate_dual(z, param)?),
+            BinaryOp::Sub(left, right) => Ok(left.evaluate_dual(z, param)? - right.evaluate_dual(z, param)?),
+            BinaryOp::Mul(left, right) => Ok(left.evaluate_dual(z, param)? * right.evaluate_dual(z, param)?),
+            BinaryOp::Div(left, right) => {
+                let l = left.evaluate_dual(z, param)?;
+                let r = right.evaluate_dual(z, param)?;
+                if r.value.norm_sqr() < f64::EPSILON {
+                    return Err(FractalError::DivisionByZero);
+                }
+                Ok(l / r)
+            }
+            BinaryOp::Pow(left, right) => {
+                let base = left.evaluate_dual(z, param)?;
+                let exp = right.evaluate_dual(z, param)?;
+                let value = self.power(base.value, exp.value)?;
+                // The derivative of the power itself, without the damping `power` applies to
+                // large results
+                let integer = exp.derivative == Complex::new(0.0, 0.0) && exp.value.im == 0.0 && exp.value.re.fract() == 0.0;
+                let derivative = if integer && exp.value.re.abs() <= i32::MAX as f64 {
+                    match exp.value.re as i32 {
+                        0 => Complex::new(0.0, 0.0),
+                        n => base.value.powi(n - 1) * n as f64 * base.derivative,
+                    }
+                } else if base.value.norm_sqr() < 1e-10 {
+                    Complex::new(0.0, 0.0)
+                } else {
+                    value * (exp.value * base.derivative / base.value + base.value.ln() * exp.derivative)
+                };
+                Ok(Dual::new(value, derivative))
+            }
+            // Iterated powers only take a few special values, differentiated numerically
+            BinaryOp::Tetration(..) | BinaryOp::Pentation(..) | BinaryOp::Hexation(..) => numeric_dual(|z, param| self.evaluate(z, param), z, param),
+        }
+    }
+}
+
+impl BinaryOp {
+    /// base^exp, as the `^` operator computes it
+    fn power(&self, base: Complex<f64>, exp: Complex<f64>) -> Result<Complex<f64>, FractalError> {
+        // For complex exponentiation: base^exp = exp(exp * ln(base))
+        if base.norm_sqr() < 1e-10 {
+            // For very small base values (near zero), 0^0 and 0^w follow the
+            // singular-value policy (by default 0^0 = 1 and 0^w = 0 otherwise)
+            formula::semantics::zero_power(exp)
+        } else {
+            // Check if the exponent is purely real (no imaginary component)
+            if exp.im.abs() < 1e-10 {
+                // For real exponents, handle special cases first
+                // Check if base is zero (which should result in 0 for positive exponents)
+                if base.norm_sqr() < 1e-10 {
+                    // 0^real_number where real_number > 0 should be 0
+                    // 0^real_number where real_number <= 0 is undefined (return 0 as safe value)
+                    Ok(Complex::new(0.0, 0.0))
+                } else {
+                    // For non-zero base with real exponent, use the standard approach
+                    // on the principal branch
+                    let result = match formula::semantics::branch() {
+                        BranchChoice::Principal => base.powf(exp.re),
+                        BranchChoice::Continuous => {
+                            let theta = formula::semantics::branch_arg(self as *const Self as usize, base);
+                            Complex::from_polar(base.norm().powf(exp.re), theta * exp.re)
+                        }
+                    };
+
+                    // Check if result is NaN or infinite
+                    if result.re.is_nan() || result.im.is_nan() || result.re.is_infinite() || result.im.is_infinite() {
+                        // Return a safe value if result is problematic
+                        Ok(Complex::new(0.0, 0.0))
+                    } else {
+                        // For fractal generation, even real exponents with non-integer values
+                        // can cause immediate escape for all points, so we need to be conservative
+                        let result_norm = result.norm();
+
+                        // Use a reasonable upper bound to prevent immediate escape
+                        let max_norm = 10.0; // Reasonable upper bound for fractal iteration
+
+                        if result_norm > max_norm {
+                            let scale_factor = max_norm / result_norm.max(1e-10); // Avoid division by zero
+                            Ok(Complex::new(result.re * scale_factor, result.im * scale_factor))
+                        } else {
+                            Ok(result)
+                        }
+                    }
+                }
+            } else {
+                // For complex exponents in fractals, we need a special algorithm
+                // The standard complex power z^(a+bi) where both a and b are non-zero
+                // can cause immediate escape for all points, making fractal formation impossible
+                // This is due to the mathematical properties of complex exponentiation in iterative systems
+
+                // Instead of using the direct complex power, we'll implement a modified algorithm
+                // that allows for fractal formation while preserving the mathematical essence
+                let r = base.norm();
+                let theta = formula::semantics::branch_arg(self as *const Self as usize, base);
+
+                // Calculate using the proper formula: z^w = exp(w * ln(z))
+                let log_base = Complex::new(r.ln(), theta);
+                let w_ln_z = exp * log_base;
+                let result = w_ln_z.exp();
+
+                // Check if result is NaN or infinite
+                if result.re.is_nan() || result.im.is_nan() || result.re.is_infinite() || result.im.is_infinite() {
+                    // Use a safe fallback value
+                    Ok(Complex::new(0.0, 0.0))
+                } else {
+                    // For complex exponents in fractals, we need to be extremely conservative
+                    // The complex power z^(a+bi) where both a and b are non-zero
+                    // can cause immediate escape for all points in the iteration
+                    // This makes fractal formation impossible with the standard algorithm
+                    // Use a much more conservative approach to allow fractal formation
+
+                    // Calculate the magnitude of the result
+                    let result_norm = result.norm();
+
+                    // For fractal generation with complex exponents, use a very conservative limit
+                    // to prevent immediate escape of all points
+                    let max_norm = 2.0; // Very conservative for complex exponents in fractals
+
+                    if result_norm > max_norm {
+                        // Scale down the result significantly to allow for fractal iteration
+                        let scale_factor = max_norm / result_norm.max(1e-10); // Avoid division by zero
+                        Ok(Complex::new(result.re * scale_factor, result.im * scale_factor))
+                    } else {
+                        // For complex exponents, we also need to ensure the result doesn't cause
+                        // immediate escape in subsequent iterations. Let's apply a more sophisticated
+                        // transformation that preserves the mathematical character while allowing
+                        // for fractal formation
+
+                        // Apply a transformation that maps large values to a more manageable range
+                        // but still allows for differentiation between points
+                        let transformed_result = if result_norm > 1.5 {
+                            // For large results, compress the range logarithmically
+                            let compressed_norm = 1.0 + 0.5 * (result_norm - 1.5).min(1.0); // Gradually compress
+                            let scale_factor = compressed_norm / result_norm.max(1e-10);
+                            Complex::new(result.re * scale_factor, result.im * scale_factor)
+                        } else if result_norm < 0.01 {
+                            // For very small results, slightly amplify to avoid stagnation
+                            let amplified_norm = result_norm.max(0.01) * 2.0;
+                            let scale_factor = amplified_norm / result_norm.max(1e-10);
+                            Complex::new(result.re * scale_factor, result.im * scale_factor)
+                        } else {
+                            result
+                        };
+
+                        Ok(transformed_result)
+                    }
+                }
+            }
+        }
+    }
 }
 
 enum Function {
@@ -1597,7 +2318,55 @@ enum Function {
 }
 
 impl Expression for Function {
-    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, String> {
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Sin(_) => "sin()",
+            Function::Cos(_) => "cos()",
+            Function::Tan(_) => "tan()",
+            Function::Exp(_) => "exp()",
+            Function::Ln(_) => "log()",
+            Function::Gamma(_) => "gamma()",
+            Function::Zeta(_) => "zeta()",
+            Function::SuperLog(_) => "slog()",
+            Function::SuperExp(_) => "sexp()",
+            Function::PentaRoot(_) => "penta_root()",
+            Function::HexaRoot(_) => "hexa_root()",
+            Function::Sqrt(_) => "sqrt()",
+            Function::Cbrt(_) => "cbrt()",
+            Function::Asin(_) => "asin()",
+            Function::Acos(_) => "acos()",
+            Function::Atan(_) => "atan()",
+            Function::Sinh(_) => "sinh()",
+            Function::Cosh(_) => "cosh()",
+            Function::Tanh(_) => "tanh()",
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn Expression>> {
+        match self {
+            Function::Sin(arg)
+            | Function::Cos(arg)
+            | Function::Tan(arg)
+            | Function::Exp(arg)
+            | Function::Ln(arg)
+            | Function::Gamma(arg)
+            | Function::Zeta(arg)
+            | Function::SuperLog(arg)
+            | Function::SuperExp(arg)
+            | Function::PentaRoot(arg)
+            | Function::HexaRoot(arg)
+            | Function::Sqrt(arg)
+            | Function::Cbrt(arg)
+            | Function::Asin(arg)
+            | Function::Acos(arg)
+            | Function::Atan(arg)
+            | Function::Sinh(arg)
+            | Function::Cosh(arg)
+            | Function::Tanh(arg) => vec![arg],
+        }
+    }
+
+    fn evaluate(&self, z: Complex<f64>, param: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         match self {
             Function::Sin(expr) => {
                 let arg = expr.evaluate(z, param)?;
@@ -1617,7 +2386,7 @@ impl Expression for Function {
             }
             Function::Ln(expr) => {
                 let arg = expr.evaluate(z, param)?;
-                Ok(arg.ln())
+                formula::semantics::ln(self as *const Self as usize, arg)
             }
             Function::Gamma(expr) => {
                 let arg = expr.evaluate(z, param)?;
@@ -1663,15 +2432,15 @@ impl Expression for Function {
             }
             Function::Sqrt(expr) => {
                 let arg = expr.evaluate(z, param)?;
-                // Square root for complex numbers
-                Ok(arg.sqrt())
+                // Square root for complex numbers, on the branch chosen in the evaluator options
+                Ok(formula::semantics::root(self as *const Self as usize, arg, 2))
             }
             Function::Cbrt(expr) => {
                 let arg = expr.evaluate(z, param)?;
                 // Cube root for complex numbers
-                // For complex numbers, we use the principal cube root
-                // This is equivalent to arg^(1/3)
-                Ok(arg.powf(1.0/3.0))
+                // For complex numbers, we use the principal cube root unless the evaluator
+                // options ask for a continuous branch. This is equivalent to arg^(1/3)
+                Ok(formula::semantics::root(self as *const Self as usize, arg, 3))
             }
             Function::Asin(expr) => {
                 let arg = expr.evaluate(z, param)?;
@@ -1705,10 +2474,53 @@ impl Expression for Function {
             }
         }
     }
+
+    fn evaluate_dual(&self, z: Dual, param: Dual) -> Result<Dual, FractalError> {
+        let node = self as *const Self as usize;
+        let one = Complex::new(1.0, 0.0);
+        match self {
+            Function::Sin(expr) => Ok(expr.evaluate_dual(z, param)?.sin()),
+            Function::Cos(expr) => Ok(expr.evaluate_dual(z, param)?.cos()),
+            Function::Tan(expr) => Ok(expr.evaluate_dual(z, param)?.tan()),
+            Function::Exp(expr) => Ok(expr.evaluate_dual(z, param)?.exp()),
+            Function::Sinh(expr) => Ok(expr.evaluate_dual(z, param)?.sinh()),
+            Function::Cosh(expr) => Ok(expr.evaluate_dual(z, param)?.cosh()),
+            Function::Tanh(expr) => Ok(expr.evaluate_dual(z, param)?.tanh()),
+            Function::Ln(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                Ok(arg.chain(formula::semantics::ln(node, arg.value)?, arg.value.inv()))
+            }
+            Function::Sqrt(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                let value = formula::semantics::root(node, arg.value, 2);
+                Ok(arg.chain(value, (value * 2.0).inv()))
+            }
+            Function::Cbrt(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                let value = formula::semantics::root(node, arg.value, 3);
+                Ok(arg.chain(value, (value * value * 3.0).inv()))
+            }
+            Function::Asin(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                Ok(arg.chain(arg.value.asin(), (one - arg.value * arg.value).sqrt().inv()))
+            }
+            Function::Acos(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                Ok(arg.chain(arg.value.acos(), -(one - arg.value * arg.value).sqrt().inv()))
+            }
+            Function::Atan(expr) => {
+                let arg = expr.evaluate_dual(z, param)?;
+                Ok(arg.chain(arg.value.atan(), (one + arg.value * arg.value).inv()))
+            }
+            // Special functions and the inverses of iterated powers are placeholders or
+            // approximations, differentiated numerically
+            _ => numeric_dual(|z, param| self.evaluate(z, param), z, param),
+        }
+    }
 } // End of ExpressionParser implementation
 
 /// Evaluate special functions for complex numbers (placeholder implementations)
-pub fn evaluate_special_function(func_name: &str, z: Complex<f64>) -> Result<Complex<f64>, String> {
+pub fn evaluate_special_function(func_name: &str, z: Complex<f64>) -> Result<Complex<f64>, FractalError> {
     match func_name.trim().to_lowercase().as_str() {
         "gamma" => {
             // The gamma function for complex numbers is complex to implement properly
@@ -1734,13 +2546,13 @@ pub fn evaluate_special_function(func_name: &str, z: Complex<f64>) -> Result<Com
             // Bessel function of the second kind - placeholder
             Ok(z)
         },
-        _ => Err(format!("Unknown special function: {}", func_name)),
+        _ => Err(FractalError::UnknownFunction(func_name.to_string())),
     }
 } // End of ExpressionParser implementation
 
 impl MathEvaluator {
     /// Evaluate special functions for complex numbers (placeholder implementations)
-    pub fn evaluate_special_function(func_name: &str, z: Complex<f64>) -> Result<Complex<f64>, String> {
+    pub fn evaluate_special_function(func_name: &str, z: Complex<f64>) -> Result<Complex<f64>, FractalError> {
         match func_name.trim().to_lowercase().as_str() {
             "gamma" => {
                 // The gamma function for complex numbers is complex to implement properly
@@ -1774,7 +2586,7 @@ impl MathEvaluator {
                 // Bessel function of the second kind - placeholder
                 Ok(z)
             },
-            _ => Err(format!("Unknown special function: {}", func_name)),
+            _ => Err(FractalError::UnknownFunction(func_name.to_string())),
         }
     }
 }
@@ -1822,17 +2634,93 @@ pub struct FractalParams {
     /// Maximum number of iterations before assuming a point is bounded
     pub max_iterations: u32,
     /// The complex constant for Julia sets (the c value in z^2 + c)
+    #[serde(default)]
     pub spawn: Complex<f64>,
     /// The magnitude threshold for determining if a point has escaped
+    #[serde(default = "default_bailout")]
     pub bailout: f64,
     /// The mathematical formula to use for iteration (e.g., "z^2 + c", "z^3 + c", "z^^z + c")
+    #[serde(default = "default_formula")]
     pub formula: String,
     /// The value that i² equals in the custom complex number system (i.e., what i is the square root of)
     ///
     /// In standard complex numbers, i² = -1, so this would be Complex::new(0.0, -1.0) (representing -1).
     /// For split complex numbers, i² = 1, so this would be Complex::new(1.0, 0.0).
     /// For other alternative number systems, this can be any complex value.
+    #[serde(default = "default_i_sqrt_value")]
     pub i_sqrt_value: Complex<f64>,
+    /// Floating-point precision of the built-in `z^2 + c` kernels (f64 by default)
+    #[serde(default)]
+    pub precision: Precision,
+    /// Built-in fractal to iterate instead of `formula`, such as the Burning Ship (see `iteration::kind`)
+    #[serde(default)]
+    pub kind: FractalKind,
+    /// Skip iteration for points that analytic tests place in the main cardioid or the period-2
+    /// bulb; only applies to Mandelbrot-style `z^2 + c` with i² = -1 (see `iteration::bulb`)
+    #[serde(default)]
+    pub bulb_check: bool,
+    /// RGBA color of points that never escape; `None` uses the palette's first stop (black without a palette)
+    #[serde(default)]
+    pub inside_color: Option<[u8; 4]>,
+    /// How points that never escape are colored: solid, or from the palette by the period, angle
+    /// or magnitude of their attractor (see `iteration::interior`)
+    #[serde(default)]
+    pub interior_coloring: InteriorColoring,
+    /// Cyclic palette for angle-based coloring, the interior angle and the hue of domain coloring
+    /// mode (see `palette::phase`); `None` keeps the palette and the HSV wheel
+    #[serde(default)]
+    pub phase_wheel: Option<palette::PhaseWheel>,
+    /// RGBA color of points that escape on the first iteration, i.e. the background outside the
+    /// bailout radius; `None` colors them from the palette like any other escaped point
+    #[serde(default)]
+    pub outside_background: Option<[u8; 4]>,
+    /// Color escaped points by their fractional iteration count (see `IterationResult::smooth_iterations`)
+    /// instead of the integer count, which removes the bands between iteration counts
+    #[serde(default)]
+    pub smooth_coloring: bool,
+    /// Spread the palette evenly over the escaped pixels by equalizing their iteration counts
+    /// across the image (see `IterationHistogram`) instead of using `iterations / max_iterations`
+    #[serde(default)]
+    pub histogram_coloring: bool,
+    /// Times the palette repeats over the iteration range; 1 stretches it once from 0 to
+    /// `max_iterations` (see `FractalParams::cycled_count`)
+    #[serde(default = "default_color_density")]
+    pub color_density: f64,
+    /// Shift of the palette as a fraction of its length, applied after `color_density`
+    #[serde(default)]
+    pub color_offset: f64,
+    /// Coloring layers composited in place of the single coloring above (see `coloring`)
+    #[serde(default)]
+    pub coloring_pipeline: Option<coloring::ColoringPipeline>,
+    /// Extra samples per pixel to smooth jagged edges (see `antialias`)
+    #[serde(default)]
+    pub antialiasing: AntiAliasing,
+    /// Post-processing effects applied in order to the colored image (see `postfx`)
+    #[serde(default)]
+    pub post_effects: Vec<postfx::PostEffect>,
+    /// Quality profile applied on top of these parameters before rendering (see `quality`)
+    #[serde(default)]
+    pub quality: quality::Profile,
+    /// Stops the render when cancelled from another thread (see `cancel`); not serialized
+    #[serde(skip)]
+    pub cancel: cancel::CancelToken,
+}
+
+// Defaults of the fields scene files and other hand-written parameters may leave out
+fn default_bailout() -> f64 {
+    4.0
+}
+
+fn default_formula() -> String {
+    "z^2 + c".to_string()
+}
+
+fn default_i_sqrt_value() -> Complex<f64> {
+    Complex::new(0.0, 1.0)
+}
+
+fn default_color_density() -> f64 {
+    1.0
 }
 
 impl FractalParams {
@@ -1844,11 +2732,47 @@ impl FractalParams {
             bailout,
             formula,
             i_sqrt_value: Complex::new(0.0, 1.0), // Default to standard i = sqrt(-1)
+            precision: Precision::Double,
+            kind: FractalKind::Formula,
+            bulb_check: false,
+            inside_color: None,
+            interior_coloring: InteriorColoring::Solid,
+            phase_wheel: None,
+            outside_background: None,
+            smooth_coloring: false,
+            histogram_coloring: false,
+            color_density: 1.0,
+            color_offset: 0.0,
+            coloring_pipeline: None,
+            antialiasing: AntiAliasing::None,
+            post_effects: Vec::new(),
+            quality: quality::Profile::Standard,
+            cancel: cancel::CancelToken::new(),
         }
     }
+
+    /// Whether `color_density` or `color_offset` make the palette cycle
+    pub fn cycles_palette(&self) -> bool {
+        self.color_density != 1.0 || self.color_offset != 0.0
+    }
+
+    /// The escape count an escaped point is colored as, after palette cycling
+    ///
+    /// Its palette position `count / max_iterations * color_density + color_offset` wraps around
+    /// at 1, so the palette repeats `color_density` times over the iteration range and, in deep
+    /// zooms where every pixel takes thousands of iterations, still bands with a steady rhythm.
+    /// The position is returned scaled back to iterations, as the palette functions take it.
+    /// Without cycling the count is returned as it is.
+    pub fn cycled_count(&self, count: f64) -> f64 {
+        if !self.cycles_palette() || self.max_iterations == 0 {
+            return count;
+        }
+        let max_iterations = self.max_iterations as f64;
+        (count / max_iterations * self.color_density + self.color_offset).rem_euclid(1.0) * max_iterations
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuddhabrotParams {
     pub bounds: [f64; 4],           // [x_min, x_max, y_min, y_max]
     pub width: u32,
@@ -1860,16 +2784,42 @@ pub struct BuddhabrotParams {
     pub formula: String,
     pub channels: BuddhabrotChannels, // RGB channel configurations
     pub i_sqrt_value: Complex<f64>, // Custom imaginary unit (i = sqrt of this value)
+    #[serde(default)]
+    pub prefilter_cells: usize,     // Cells per axis of the escape-time prefilter grid, 0 to disable
+    #[serde(default)]
+    pub denoise: Option<buddhabrot::DenoiseOptions>, // Histogram noise reduction before the channels are combined
+    #[serde(default)]
+    pub post_effects: Vec<postfx::PostEffect>, // Post-processing applied in order to the combined image
+    #[serde(default)]
+    pub orbits: buddhabrot::OrbitMode, // Escaping orbits (Buddhabrot), bounded orbits (anti-Buddhabrot) or both
+    #[serde(default)]
+    pub sample_bounds: Option<[f64; 4]>, // Area c is sampled from, if not the view `bounds`
+    #[serde(default)]
+    pub channel_mix: Option<buddhabrot::ChannelMix>, // Color matrix and white balance applied to the normalized channels
+    #[serde(default)]
+    pub quality: quality::Profile, // Quality profile applied on top of these parameters (see `quality`)
+    #[serde(default)]
+    pub interpolation: Option<buddhabrot::OrbitInterpolation>, // Points inserted between distant iterates of uniformly sampled orbits
+    #[serde(default = "default_orbit_buffer_limit")]
+    pub orbit_buffer_limit: usize, // Longest orbit recorded in memory; channels with a higher max_iter replay orbits instead
+    #[serde(default)]
+    pub display_transform: Option<output::DisplayTransform>, // Transfer curve encoding the normalized channels for display before quantizing
+    #[serde(skip)]
+    pub cancel: cancel::CancelToken, // Stops sampling when cancelled from another thread (see `cancel`)
 }
 
-#[derive(Debug, Clone)]
+fn default_orbit_buffer_limit() -> usize {
+    buddhabrot::scratch::DEFAULT_ORBIT_BUFFER_LIMIT
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuddhabrotChannel {
     pub min_iter: u32,
     pub max_iter: u32,
     pub samples: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuddhabrotChannels {
     pub red: BuddhabrotChannel,
     pub green: BuddhabrotChannel,
@@ -1877,6 +2827,7 @@ pub struct BuddhabrotChannels {
 }
 
 impl BuddhabrotParams {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bounds: [f64; 4],
         width: u32,
@@ -1899,11 +2850,22 @@ impl BuddhabrotParams {
             formula,
             channels,
             i_sqrt_value: Complex::new(0.0, 1.0), // Default to standard i = sqrt(-1)
+            prefilter_cells: 0,
+            denoise: None,
+            post_effects: Vec::new(),
+            orbits: buddhabrot::OrbitMode::default(),
+            sample_bounds: None,
+            channel_mix: None,
+            quality: quality::Profile::Standard,
+            interpolation: None,
+            orbit_buffer_limit: buddhabrot::scratch::DEFAULT_ORBIT_BUFFER_LIMIT,
+            display_transform: None,
+            cancel: cancel::CancelToken::new(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuddhabrotJuliaParams {
     pub bounds: [f64; 4],           // [x_min, x_max, y_min, y_max]
     pub width: u32,
@@ -1916,6 +2878,18 @@ pub struct BuddhabrotJuliaParams {
     pub formula: String,
     pub channels: BuddhabrotChannels, // RGB channel configurations
     pub i_sqrt_value: Complex<f64>, // Custom imaginary unit (i = sqrt of this value)
+    #[serde(default)]
+    pub denoise: Option<buddhabrot::DenoiseOptions>, // Histogram noise reduction before the channels are combined
+    #[serde(default)]
+    pub post_effects: Vec<postfx::PostEffect>, // Post-processing applied in order to the combined image
+    #[serde(default)]
+    pub channel_mix: Option<buddhabrot::ChannelMix>, // Color matrix and white balance applied to the normalized channels
+    #[serde(default)]
+    pub quality: quality::Profile, // Quality profile applied on top of these parameters (see `quality`)
+    #[serde(default)]
+    pub display_transform: Option<output::DisplayTransform>, // Transfer curve encoding the normalized channels for display before quantizing
+    #[serde(skip)]
+    pub cancel: cancel::CancelToken, // Stops sampling when cancelled from another thread (see `cancel`)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1925,9 +2899,17 @@ pub struct DomainColorParams {
     pub height: u32,
     pub formula: String,
     pub i_sqrt_value: Complex<f64>, // Custom imaginary unit (i = sqrt of this value)
+    /// Highlight branch cuts: pixels whose phase differs from a neighbour's by more than this
+    /// many radians are drawn in white. `None` (the default) draws no overlay
+    #[serde(default)]
+    pub branch_cut_threshold: Option<f64>,
+    /// Cyclic palette the phase is colored with (see `palette::phase`); HSV by default
+    #[serde(default)]
+    pub phase_wheel: palette::PhaseWheel,
 }
 
 impl BuddhabrotJuliaParams {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         bounds: [f64; 4],
         width: u32,
@@ -1952,6 +2934,12 @@ impl BuddhabrotJuliaParams {
             formula,
             channels,
             i_sqrt_value: Complex::new(0.0, 1.0), // Default to standard i = sqrt(-1)
+            denoise: None,
+            post_effects: Vec::new(),
+            channel_mix: None,
+            quality: quality::Profile::Standard,
+            display_transform: None,
+            cancel: cancel::CancelToken::new(),
         }
     }
 }
@@ -2042,6 +3030,12 @@ pub fn generate_html_file(
             white-space: pre-wrap;
             word-break: break-all;
         }}
+        .formula-reference {{
+            margin-top: 20px;
+        }}
+        .formula-reference td {{
+            padding: 2px 12px 2px 0;
+        }}
     </style>
 </head>
 <body>
@@ -2054,6 +3048,10 @@ pub fn generate_html_file(
             <div id="selection-box"></div>
         </div>
 
+        <p id="readout">Move the pointer over the image to read its coordinates.</p>
+
+        <p>Current view: <code>{}</code></p>
+
         <div class="controls">
             <div class="aspect-ratio-controls">
                 <label><input type="radio" name="aspect-ratio" value="1:1" checked> 1:1 (Square)</label>
@@ -2089,6 +3087,23 @@ pub fn generate_html_file(
 
             <h3>Command to render selected region:</h3>
             <div id="command-output" class="command-output">{}</div>
+
+            <h3>Selected region (center + magnification):</h3>
+            <div id="notation-output" class="command-output"></div>
+
+            <h3>Annotations:</h3>
+            <p>
+                <input id="annotation-label" type="text" placeholder="Label">
+                <button id="annotate-region">Add selection as region</button>
+                <button id="annotate-point">Add selection center as point</button>
+                <button id="download-annotations">Download GeoJSON</button>
+            </p>
+            <div id="annotation-output" class="command-output">Annotations saved here can be drawn over renders with --annotations.</div>
+
+            <details class="formula-reference">
+                <summary>Formula reference</summary>
+                {}
+            </details>
         </div>
     </div>
 
@@ -2097,6 +3112,7 @@ pub fn generate_html_file(
         const selectionBox = document.getElementById('selection-box');
         let isSelecting = false;
         let startX, startY, currentX, currentY;
+        let lastSelection = null; // [x_min, x_max, y_min, y_max] of the last selection
 
         // Get image dimensions
         const imgWidth = {};
@@ -2114,6 +3130,20 @@ pub fn generate_html_file(
             "9:16": ["720x1280", "1080x1920", "2160x3840"]
         }};
 
+        // Coordinates under the pointer and the size of the selection in complex units, with the
+        // image's own pixel mapping (see viewport::measure::Ruler for the same measurements in Rust)
+        const readout = document.getElementById('readout');
+        let selectionReadout = '';
+        function toComplex(x, y) {{
+            return [bounds[0] + (x / imgWidth) * (bounds[1] - bounds[0]), bounds[2] + (y / imgHeight) * (bounds[3] - bounds[2])];
+        }}
+        img.addEventListener('mousemove', function(e) {{
+            const rect = img.getBoundingClientRect();
+            const [re, im] = toComplex((e.clientX - rect.left) * imgWidth / rect.width, (e.clientY - rect.top) * imgHeight / rect.height);
+            const sign = im < 0 ? '-' : '+';
+            readout.textContent = `Pointer: ${{re.toPrecision(12)}} ${{sign}} ${{Math.abs(im).toPrecision(12)}}i` + selectionReadout;
+        }});
+
         img.addEventListener('mousedown', startSelection);
         document.addEventListener('mousemove', updateSelection);
         document.addEventListener('mouseup', endSelection);
@@ -2302,6 +3332,8 @@ pub fn generate_html_file(
                 yMax = center + epsilonY / 2;
             }}
 
+            lastSelection = [xMin, xMax, yMin, yMax];
+
             // Get selected resolution
             const resolutionSelect = document.getElementById('resolution-select');
             const [widthRes, heightRes] = resolutionSelect.value.split('x').map(Number);
@@ -2311,6 +3343,17 @@ pub fn generate_html_file(
                                     .replace('{{dimensions}}', `${{widthRes}},${{heightRes}}`);
 
             document.getElementById('command-output').textContent = command;
+
+            const selectionWidth = xMax - xMin;
+            const selectionHeight = yMax - yMin;
+            selectionReadout = ` | Selection: ${{selectionWidth.toPrecision(6)}} x ${{selectionHeight.toPrecision(6)}}, ` +
+                `diagonal ${{Math.hypot(selectionWidth, selectionHeight).toPrecision(6)}}, area ${{(selectionWidth * selectionHeight).toPrecision(6)}}`;
+            readout.textContent = readout.textContent.split(' | ')[0] + selectionReadout;
+
+            // Same region in center + magnification notation (see Viewport::to_notation)
+            const zoom = {} / (yMax - yMin);
+            document.getElementById('notation-output').textContent =
+                `re=${{(xMin + xMax) / 2}} im=${{(yMin + yMax) / 2}} zoom=${{zoom.toExponential()}} rotation=0`;
         }}
 
         // Update resolution options when aspect ratio changes
@@ -2330,6 +3373,29 @@ pub fn generate_html_file(
             }}
         }});
 
+        // Annotations in the GeoJSON format of the annotations module, with [re, im] positions
+        const annotationFeatures = [];
+        function addAnnotation(asPoint) {{
+            if (!lastSelection) return;
+            const [xMin, xMax, yMin, yMax] = lastSelection;
+            const label = document.getElementById('annotation-label').value || `Annotation ${{annotationFeatures.length + 1}}`;
+            const geometry = asPoint
+                ? {{ type: 'Point', coordinates: [(xMin + xMax) / 2, (yMin + yMax) / 2] }}
+                : {{ type: 'Polygon', coordinates: [[[xMin, yMin], [xMax, yMin], [xMax, yMax], [xMin, yMax], [xMin, yMin]]] }};
+            annotationFeatures.push({{ type: 'Feature', geometry: geometry, properties: {{ label: label }} }});
+            document.getElementById('annotation-output').textContent =
+                annotationFeatures.map(f => `${{f.properties.label}}: ${{JSON.stringify(f.geometry.coordinates)}}`).join('\n');
+        }}
+        document.getElementById('annotate-region').addEventListener('click', () => addAnnotation(false));
+        document.getElementById('annotate-point').addEventListener('click', () => addAnnotation(true));
+        document.getElementById('download-annotations').addEventListener('click', function() {{
+            const collection = {{ type: 'FeatureCollection', coordinate_space: 'complex-plane', features: annotationFeatures }};
+            const link = document.createElement('a');
+            link.href = URL.createObjectURL(new Blob([JSON.stringify(collection, null, 2)], {{ type: 'application/geo+json' }}));
+            link.download = 'annotations.geojson';
+            link.click();
+        }});
+
         function updateResolutionOptions() {{
             const selectedRatio = document.querySelector('input[name="aspect-ratio"]:checked').value;
             const resolutions = aspectRatioResolutions[selectedRatio] || ["640x480", "1280x720", "1920x1080"];
@@ -2351,20 +3417,23 @@ pub fn generate_html_file(
 </body>
 </html>"#,
         image_filename,
+        Viewport::from_bounds(bounds).to_notation(),
         command_template,
+        formula::formula_reference_html(),
         dimensions[0],
         dimensions[1],
         bounds[0],
         bounds[1],
         bounds[2],
         bounds[3],
-        command_template
+        command_template,
+        viewport::BASE_VIEW_HEIGHT
     );
 
     let html_path = std::path::Path::new(image_path)
         .with_extension("html");
 
-    std::fs::write(html_path, html_content)
+    output::write_atomic(html_path, html_content.as_bytes()).map_err(std::io::Error::other)
 }
 
 /// Calculate the number of iterations for a point in a Mandelbrot set with support for custom imaginary units
@@ -2383,6 +3452,9 @@ pub fn generate_html_file(
 ///
 /// The number of iterations before the point escapes, or max_iterations if it remains bounded
 ///
+/// Orbits that fall into a cycle stop early (see `iteration::CycleDetector`) and also count as
+/// max_iterations.
+///
 /// # Mathematical Implementation
 ///
 /// When params.i_sqrt_value equals the standard value (i² = -1), the function uses standard complex arithmetic.
@@ -2394,46 +3466,52 @@ pub fn generate_html_file(
 /// - Split Complex: params.i_sqrt_value = Complex::new(1.0, 0.0) → i² = 1 (split complex numbers)
 /// - Other: params.i_sqrt_value = Complex::new(1.0, 1.0) → i² = 1+i (alternative complex system)
 pub fn mandelbrot_iterations(c: Complex<f64>, params: &FractalParams) -> u32 {
-    // If the custom imaginary unit is the standard one (i² = -1), use the regular algorithm
-    if params.i_sqrt_value == Complex::new(0.0, 1.0) {
-        // Use the standard algorithm for backward compatibility
-        let mut z = Complex::new(0.0, 0.0);
-        let mut iter = 0;
-
-        while iter < params.max_iterations {
-            // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
-            z = match MathEvaluator::evaluate_formula_with_param(&params.formula, z, c) {
-                Ok(result) => result,
-                Err(_e) => z * z + c, // Fallback to standard formula
-            };
+    // Reduced or extended precision and the built-in kinds run through the generic kernels
+    if params.precision != Precision::Double || params.kind != FractalKind::Formula {
+        return iteration::iterate_single(c, params, false).iterations;
+    }
 
-            if z.norm_sqr() > params.bailout * params.bailout {
-                break;
-            }
-            iter += 1;
-        }
+    mandelbrot_iterations_compiled(c, params, &formula::compiled::compiled_for(&params.formula))
+}
 
-        iter
-    } else {
-        // Use the custom complex number system for non-standard imaginary units
-        let custom_i_squared = params.i_sqrt_value;  // This is the value that i² equals
-        let mut z = CustomComplex::from_standard(Complex::new(0.0, 0.0), custom_i_squared);
-        let c_custom = CustomComplex::from_standard(c, custom_i_squared);
-        let mut iter = 0;
-
-        while iter < params.max_iterations {
-            // Use custom complex arithmetic: z = z^2 + c
-            let z_squared = z.multiply(&z);
-            z = z_squared.add(&c_custom);
-
-            if z.norm_sqr() > params.bailout * params.bailout {
-                break;
-            }
-            iter += 1;
-        }
+/// `mandelbrot_iterations` with the formula already compiled
+///
+/// Renderers compile `params.formula` once and call this for every pixel, so the formula is
+/// never parsed inside the iteration loop.
+///
+/// # Arguments
+///
+/// * `c` - The complex coordinate to test
+/// * `params` - Fractal parameters including max iterations and bailout value
+/// * `formula` - `params.formula`, compiled
+///
+/// # Returns
+///
+/// The number of iterations before the point escaped, or max_iterations if it didn't escape
+pub fn mandelbrot_iterations_compiled(c: Complex<f64>, params: &FractalParams, formula: &CompiledFormula) -> u32 {
+    mandelbrot_result_compiled(c, params, formula).iterations
+}
 
-        iter
+/// `mandelbrot_iterations_compiled`, also returning the value of z where iteration stopped
+///
+/// # Arguments
+///
+/// * `c` - The complex coordinate to test
+/// * `params` - Fractal parameters including max iterations and bailout value
+/// * `formula` - `params.formula`, compiled
+///
+/// # Returns
+///
+/// The iteration count with the escaped value, for smooth coloring
+pub fn mandelbrot_result_compiled(c: Complex<f64>, params: &FractalParams, formula: &CompiledFormula) -> IterationResult {
+    if params.precision != Precision::Double || params.kind != FractalKind::Formula {
+        return iteration::iterate_single(c, params, false);
     }
+    if let Some(result) = iteration::bulb::shortcut(c, params) {
+        return result;
+    }
+
+    algebra::NumberSystem::for_params(params.i_sqrt_value, formula).iterate(formula, Complex::new(0.0, 0.0), c, params)
 }
 
 /// Calculate the number of iterations for a point in a Julia set with support for custom imaginary units
@@ -2452,6 +3530,9 @@ pub fn mandelbrot_iterations(c: Complex<f64>, params: &FractalParams) -> u32 {
 ///
 /// The number of iterations before the point escapes, or max_iterations if it remains bounded
 ///
+/// Orbits that fall into a cycle stop early (see `iteration::CycleDetector`) and also count as
+/// max_iterations.
+///
 /// # Mathematical Implementation
 ///
 /// When params.i_sqrt_value equals the standard value (i² = -1), the function uses standard complex arithmetic.
@@ -2463,53 +3544,63 @@ pub fn mandelbrot_iterations(c: Complex<f64>, params: &FractalParams) -> u32 {
 /// - Split Complex: params.i_sqrt_value = Complex::new(1.0, 0.0) → i² = 1 (split complex numbers)
 /// - Other: params.i_sqrt_value = Complex::new(1.0, 1.0) → i² = 1+i (alternative complex system)
 pub fn julia_iterations(z: Complex<f64>, params: &FractalParams) -> u32 {
-    // If the custom imaginary unit is the standard one (i² = -1), use the regular algorithm
-    if params.i_sqrt_value == Complex::new(0.0, 1.0) {
-        // Use the standard algorithm for backward compatibility
-        let c = params.spawn;  // Use spawn point as the constant for Julia set
-        let mut z = z;
-        let mut iter = 0;
-
-        while iter < params.max_iterations {
-            // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
-            z = match MathEvaluator::evaluate_formula_with_param(&params.formula, z, c) {
-                Ok(result) => result,
-                Err(_) => z * z + c, // Fallback to standard formula
-            };
+    // Reduced or extended precision and the built-in kinds run through the generic kernels
+    if params.precision != Precision::Double || params.kind != FractalKind::Formula {
+        return iteration::iterate_single(z, params, true).iterations;
+    }
 
-            if z.norm_sqr() > params.bailout * params.bailout {
-                break;
-            }
-            iter += 1;
-        }
+    julia_iterations_compiled(z, params, &formula::compiled::compiled_for(&params.formula))
+}
 
-        iter
-    } else {
-        // Use the custom complex number system for non-standard imaginary units
-        let custom_i_squared = params.i_sqrt_value;  // This is the value that i² equals
-        let mut z = CustomComplex::new(z.re, z.im, custom_i_squared);
-        let c = CustomComplex::new(params.spawn.re, params.spawn.im, custom_i_squared);
-        let mut iter = 0;
-
-        while iter < params.max_iterations {
-            // Use custom complex arithmetic: z = z^2 + c
-            let z_squared = z.multiply(&z);
-            z = z_squared.add(&c);
-
-            if z.norm_sqr() > params.bailout * params.bailout {
-                break;
-            }
-            iter += 1;
-        }
+/// `julia_iterations` with the formula already compiled
+///
+/// # Arguments
+///
+/// * `z` - The initial point in the complex plane
+/// * `params` - Fractal parameters including max iterations, spawn point and bailout value
+/// * `formula` - `params.formula`, compiled
+///
+/// # Returns
+///
+/// The number of iterations before the point escaped, or max_iterations if it didn't escape
+pub fn julia_iterations_compiled(z: Complex<f64>, params: &FractalParams, formula: &CompiledFormula) -> u32 {
+    julia_result_compiled(z, params, formula).iterations
+}
 
-        iter
+/// `julia_iterations_compiled`, also returning the value of z where iteration stopped
+///
+/// # Arguments
+///
+/// * `z` - The initial point in the complex plane
+/// * `params` - Fractal parameters including max iterations, spawn point and bailout value
+/// * `formula` - `params.formula`, compiled
+///
+/// # Returns
+///
+/// The iteration count with the escaped value, for smooth coloring
+pub fn julia_result_compiled(z: Complex<f64>, params: &FractalParams, formula: &CompiledFormula) -> IterationResult {
+    if params.precision != Precision::Double || params.kind != FractalKind::Formula {
+        return iteration::iterate_single(z, params, true);
     }
+
+    algebra::NumberSystem::for_params(params.i_sqrt_value, formula).iterate(formula, z, params.spawn, params)
 }
 
+/// Seed of the Buddhabrot sampler; sample `n` draws from `rng::CounterRng::new(BUDDHABROT_SEED, n)`,
+/// so renders are reproducible however the samples are split across threads or machines
+pub const BUDDHABROT_SEED: u64 = 0xdeadbeef;
+
+/// Seed of the Buddhabrot Julia sampler, used like `BUDDHABROT_SEED`
+pub const BUDDHABROT_JULIA_SEED: u64 = 0xcafebabe;
+
 /// Calculate the Buddhabrot for a specific channel
 ///
 /// Implements the Buddhabrot algorithm by tracking the orbits of escaping points
-/// and creating a histogram of visited locations in the complex plane.
+/// and creating a histogram of visited locations in the complex plane. With
+/// `params.orbits` set to `OrbitMode::Bounded` it tracks the orbits of the points that
+/// don't escape instead (the anti-Buddhabrot); `Combined` only matters to `generate_buddhabrot`.
+/// c is sampled over `params.sampling_bounds()`, which for a zoomed view should be larger than
+/// the view so that orbits coming from outside it are drawn too.
 ///
 /// # Arguments
 ///
@@ -2524,127 +3615,282 @@ pub fn buddhabrot_channel(
     params: &BuddhabrotParams,
     channel_params: &BuddhabrotChannel,
     _escape_count: u32,
-) -> Vec<Vec<f64>> {
-    use std::time::Instant;
-    use std::collections::HashMap;
+) -> DensityGrid {
+    let bounded = params.orbits == buddhabrot::OrbitMode::Bounded;
+    let grid = (params.prefilter_cells > 0 && !bounded)
+        .then(|| buddhabrot::EscapeTimeGrid::compute(params, params.prefilter_cells, channel_params.max_iter));
+    buddhabrot_channel_with_grid(params, channel_params, bounded, grid.as_ref())
+}
 
-    let [x_min, x_max, y_min, y_max] = params.bounds;
+/// `buddhabrot_channel`, skipping the samples that `grid` shows to be out of the channel's range
+///
+/// With `bounded`, the orbits of points that don't escape within `max_iter` are drawn from
+/// iteration `min_iter` on instead of the orbits of escaping points. The grid only applies to
+/// escaping orbits.
+pub(crate) fn buddhabrot_channel_with_grid(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    bounded: bool,
+    grid: Option<&buddhabrot::EscapeTimeGrid>,
+) -> DensityGrid {
+    use std::time::Instant;
 
     let total_samples = channel_params.samples;
     let start_time = Instant::now();
+    let growths_before = buddhabrot::scratch::orbit_buffer_growths();
+
+    let telemetry = telemetry::RenderTelemetry::start("Generating Buddhabrot channel", total_samples);
 
     // Print initial progress
     println!("Generating Buddhabrot channel: 0% (0/{}) - Started at {:?}. Using {} threads.",
              total_samples, Local::now().format("%H:%M:%S"), rayon::current_num_threads());
 
+    let mut final_histogram = DensityGrid::new(params.width as usize, params.height as usize);
+    let skipped_samples = accumulate_buddhabrot_samples(
+        params,
+        channel_params,
+        bounded,
+        grid,
+        0..total_samples,
+        &mut final_histogram,
+        &telemetry,
+    );
+
+    // Final progress report
+    let elapsed = start_time.elapsed();
+    println!(
+        "Generating Buddhabrot channel: 100% ({}/{}), Completed in {:.1}s",
+        total_samples, total_samples, elapsed.as_secs_f64()
+    );
+    if grid.is_some() {
+        println!("  Prefilter skipped {} of {} samples without iterating", skipped_samples, total_samples);
+    }
+    println!(
+        "  Orbit buffers grew {} times for {} samples",
+        buddhabrot::scratch::orbit_buffer_growths() - growths_before,
+        total_samples
+    );
+
+    final_histogram
+}
+
+/// Accumulate the orbits of a range of a channel's samples into a histogram
+///
+/// Sample `n` always takes the same c (its random stream is keyed by `n`), so accumulating
+/// `0..a` and `a..b` separately and adding the histograms gives the same result as `0..b`.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters
+/// * `channel_params` - Iteration range of the channel
+/// * `bounded` - Draw the orbits of bounded instead of escaping points
+/// * `grid` - Optional escape-time prefilter grid
+/// * `samples` - Indices of the samples to take
+/// * `histogram` - Counts, added to
+/// * `telemetry` - Health telemetry of the render the samples belong to
+///
+/// # Returns
+///
+/// The number of samples the prefilter skipped without iterating
+pub(crate) fn accumulate_buddhabrot_samples(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    bounded: bool,
+    grid: Option<&buddhabrot::EscapeTimeGrid>,
+    samples: std::ops::Range<u64>,
+    histogram: &mut DensityGrid,
+    telemetry: &telemetry::RenderTelemetry,
+) -> usize {
+    let (width, height) = (histogram.width(), histogram.height());
+    // Cells outside the histogram and negative weights are ignored
+    let add = |job: &mut DensityGrid, x: usize, y: usize, weight: f64| {
+        if x < width && y < height && weight > 0.0 {
+            job[(x, y)] += weight;
+        }
+    };
+    // Plot an orbit point, filling in the segment to the next one when interpolating
+    let plot_segment = |job: &mut DensityGrid, point: Complex<f64>, next: Option<Complex<f64>>| match &params.interpolation {
+        Some(interpolation) => interpolation.plot_segment(point, next, params, &mut |x, y, weight| add(job, x, y, weight)),
+        None => {
+            if let Some((x, y)) = buddhabrot::orbit_pixel(point, params) {
+                add(job, x, y, 1.0);
+            }
+        }
+    };
+    // Each job counts into a grid of its own; they are added once the job is done
+    let merge = |mut total: DensityGrid, job: DensityGrid| {
+        total.add_grid(&job);
+        total
+    };
+    let (counts, skipped_samples) = sample_buddhabrot_orbits(
+        params,
+        channel_params,
+        bounded,
+        grid,
+        samples,
+        telemetry,
+        || DensityGrid::new(width, height),
+        plot_segment,
+        merge,
+    );
+    histogram.add_grid(&counts);
+    skipped_samples
+}
+
+/// Sample a range of a channel's samples and hand every point of the orbits drawn to `plot`
+///
+/// The sampling of `accumulate_buddhabrot_samples`, for any destination of the orbit points,
+/// such as a histogram or a recorded point stream (`buddhabrot::stream`). Every rayon job plots
+/// into a state of its own, from `init`, and the states of finished jobs are combined with
+/// `merge`, so no point needs a lock or an atomic update.
+///
+/// # Arguments
+///
+/// * `params` - Buddhabrot parameters
+/// * `channel_params` - Iteration range of the channel
+/// * `bounded` - Draw the orbits of bounded instead of escaping points
+/// * `grid` - Optional escape-time prefilter grid
+/// * `samples` - Indices of the samples to take
+/// * `telemetry` - Health telemetry of the render the samples belong to
+/// * `init` - Creates the state of a job, e.g. an empty histogram
+/// * `plot_segment` - Called with a job's state, every orbit point and the point after it,
+///   `None` after the last one
+/// * `merge` - Combines the states of two jobs
+///
+/// # Returns
+///
+/// The combined state of all jobs and the number of samples the prefilter skipped without
+/// iterating
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sample_buddhabrot_orbits<S, I, P, M>(
+    params: &BuddhabrotParams,
+    channel_params: &BuddhabrotChannel,
+    bounded: bool,
+    grid: Option<&buddhabrot::EscapeTimeGrid>,
+    samples: std::ops::Range<u64>,
+    telemetry: &telemetry::RenderTelemetry,
+    init: I,
+    plot_segment: P,
+    merge: M,
+) -> (S, usize)
+where
+    S: Send,
+    I: Fn() -> S + Sync + Send,
+    P: Fn(&mut S, Complex<f64>, Option<Complex<f64>>) + Sync + Send,
+    M: Fn(S, S) -> S + Sync + Send,
+{
+    let [x_min, x_max, y_min, y_max] = params.sampling_bounds();
+    let formula = CompiledFormula::new(&params.formula);
+    let number_system = algebra::NumberSystem::for_params(params.i_sqrt_value, &formula);
+    let skipped_samples = AtomicUsize::new(0);
+    let total_samples = samples.end.saturating_sub(samples.start);
+
     // Determine chunk size for parallel processing
     let chunk_size = (total_samples / (rayon::current_num_threads() as u64 * 4)).max(1000);
 
     // Process samples in chunks using parallel iterator
     // Create a custom iterator that yields chunks of sample numbers
-    let num_chunks = std::cmp::max((total_samples as usize) / chunk_size as usize, 1);
-    let partial_histograms: Vec<HashMap<(usize, usize), f64>> = (0..num_chunks)
-        .into_par_iter()
-        .map(|chunk_idx| {
-            let start_sample = (chunk_idx as u64) * chunk_size;
-            let end_sample = std::cmp::min(start_sample + chunk_size, total_samples);
+    let num_chunks = total_samples.div_ceil(chunk_size).max(1);
+    let draw = |state: &mut S, points: &[Complex<f64>]| {
+        for (index, &point) in points.iter().enumerate() {
+            plot_segment(state, point, points.get(index + 1).copied());
+        }
+    };
+    // Orbits longer than the buffer limit are iterated twice rather than stored
+    let replay = channel_params.max_iter as usize > params.orbit_buffer_limit;
+    let bailout_sqr = params.bailout * params.bailout;
 
-            let mut local_histogram = HashMap::new();
-            // Use a deterministic seed based on the chunk index to ensure reproducible results
-            let mut rng = rand::rngs::StdRng::seed_from_u64(start_sample ^ 0xdeadbeef);
+    let state = (0..num_chunks)
+        .into_par_iter()
+        .fold(&init, |mut state, chunk_idx| {
+            let start_sample = samples.start + chunk_idx * chunk_size;
+            let end_sample = std::cmp::min(start_sample + chunk_size, samples.end);
 
-            for _sample_num in start_sample..end_sample {
-                // Randomly sample a c value in the complex plane using the local RNG
+            for sample_num in start_sample..end_sample {
+                if params.cancel.is_cancelled() {
+                    break;
+                }
+                let _task = telemetry.task();
+                // Each sample has its own random stream, independent of the chunking
+                let mut rng = rng::CounterRng::new(BUDDHABROT_SEED, sample_num);
+                // Randomly sample a c value in the sampling area using the local RNG
                 let c_re = x_min + (x_max - x_min) * rng.gen::<f64>();
                 let c_im = y_min + (y_max - y_min) * rng.gen::<f64>();
                 let c = Complex::new(c_re, c_im);
 
-                // Check if this point escapes within the iteration range
-                let mut z = Complex::new(0.0, 0.0);
-                let mut iter = 0;
-                let mut orbit = Vec::new();
-
-                // Track the orbit
-                while iter < channel_params.max_iter {
-                    orbit.push(z);
-                    // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
-                    if params.i_sqrt_value == Complex::new(0.0, 1.0) {
-                        // Use standard algorithm for backward compatibility
-                        z = match MathEvaluator::evaluate_formula_with_param(&params.formula, z, c) {
-                            Ok(result) => result,
-                            Err(_) => z * z + c, // Fallback to standard formula
-                        };
-                    } else {
-                        // Use custom complex arithmetic for non-standard imaginary units
-                        let custom_i_squared = params.i_sqrt_value;
-                        let z_custom = CustomComplex::new(z.re, z.im, custom_i_squared);
-                        let c_custom = CustomComplex::new(c.re, c.im, custom_i_squared);
-
-                        let result_custom = match MathEvaluator::evaluate_formula_with_param(&params.formula, z_custom.to_standard(), c_custom.to_standard()) {
-                            Ok(result) => CustomComplex::from_standard(result, custom_i_squared),
-                            Err(_) => {
-                                // Fallback to standard formula using custom arithmetic
-                                let z_sq = z_custom.multiply(&z_custom);
-                                z_sq.add(&c_custom)
-                            },
-                        };
+                if !bounded && grid.is_some_and(|grid| grid.can_skip(c, channel_params)) {
+                    skipped_samples.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
 
-                        z = result_custom.to_standard();
-                    };
+                // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
+                let step = |z: Complex<f64>| number_system.step(&formula, z, c);
 
-                    if z.norm_sqr() > params.bailout * params.bailout {
-                        // Point escapes, check if it's in the right iteration range
-                        if iter >= channel_params.min_iter {
-                            // Draw the orbit - accumulate locally first
-                            for point in &orbit {
-                                let px = ((point.re - x_min) / (x_max - x_min) * params.width as f64) as usize;
-                                let py = ((point.im - y_min) / (y_max - y_min) * params.height as f64) as usize;
+                if replay {
+                    // Find where the orbit escapes without recording it, then iterate it again to draw it
+                    let mut z = Complex::new(0.0, 0.0);
+                    let mut iter = 0;
+                    while iter < channel_params.max_iter {
+                        z = step(z);
+                        if z.norm_sqr() > bailout_sqr {
+                            break;
+                        }
+                        iter += 1;
+                    }
+                    let drawn = if bounded {
+                        (iter == channel_params.max_iter).then_some(channel_params.min_iter..channel_params.max_iter)
+                    } else {
+                        (iter < channel_params.max_iter && iter >= channel_params.min_iter).then_some(0..iter + 1)
+                    };
+                    if let Some(points) = drawn {
+                        buddhabrot::scratch::replay_orbit(step, points, |point, next| plot_segment(&mut state, point, next));
+                    }
+                    continue;
+                }
 
-                                if px < params.width as usize && py < params.height as usize {
-                                    *local_histogram.entry((px, py)).or_insert(0.0) += 1.0;
-                                }
+                // Check if this point escapes within the iteration range, recording the orbit in
+                // this thread's reusable buffer
+                buddhabrot::scratch::with_orbit_buffer(|orbit| {
+                    let mut z = Complex::new(0.0, 0.0);
+                    let mut iter = 0;
+
+                    // Track the orbit
+                    while iter < channel_params.max_iter {
+                        orbit.push(z);
+                        z = step(z);
+
+                        if z.norm_sqr() > bailout_sqr {
+                            // Point escapes, check if it's in the right iteration range
+                            if !bounded && iter >= channel_params.min_iter {
+                                draw(&mut state, orbit);
                             }
+                            break;
                         }
-                        break;
+                        iter += 1;
                     }
-                    iter += 1;
-                }
+
+                    // The point stayed bounded; the anti-Buddhabrot draws its orbit past the transient
+                    if bounded && iter == channel_params.max_iter {
+                        draw(&mut state, orbit.get(channel_params.min_iter as usize..).unwrap_or(&[]));
+                    }
+                });
             }
-            local_histogram
+            state
         })
-        .collect();
+        .reduce_with(&merge)
+        .unwrap_or_else(&init);
 
-    // Merge all partial histograms into the final histogram
-    let mut final_histogram = vec![vec![0.0; params.width as usize]; params.height as usize];
-
-    for partial_hist in partial_histograms {
-        for ((x, y), value) in partial_hist {
-            if x < params.width as usize && y < params.height as usize {
-                final_histogram[y][x] += value;
-            }
-        }
-    }
-
-    // Final progress report
-    let elapsed = start_time.elapsed();
-    println!(
-        "Generating Buddhabrot channel: 100% ({}/{}), Completed in {:.1}s",
-        total_samples, total_samples, elapsed.as_secs_f64()
-    );
-
-    final_histogram
+    (state, skipped_samples.into_inner())
 }
 
 /// Calculate the percentile of log-transformed values in a histogram
-fn calculate_percentile_log(hist: &Vec<Vec<f64>>, percentile: f64) -> f64 {
+fn calculate_percentile_log(hist: &DensityGrid, percentile: f64) -> f64 {
     let mut values = Vec::new();
 
     // Collect all non-zero values and apply log transform
-    for row in hist {
-        for &val in row {
-            if val > 0.0 {
-                values.push((val + 1.0).ln()); // Use ln(1 + x) to handle values close to 0
-            }
+    for &val in hist.as_slice() {
+        if val > 0.0 {
+            values.push((val + 1.0).ln()); // Use ln(1 + x) to handle values close to 0
         }
     }
 
@@ -2673,57 +3919,104 @@ fn calculate_percentile_log(hist: &Vec<Vec<f64>>, percentile: f64) -> f64 {
 ///
 /// An RGB image representing the combined Buddhabrot visualization
 pub fn generate_buddhabrot(params: &BuddhabrotParams) -> image::RgbImage {
-    let mut img = image::RgbImage::new(params.width, params.height);
+    let densities = buddhabrot_densities(params);
+    render_buddhabrot_densities(params.width, params.height, &densities, params.channel_mix.as_ref(), params.display_transform, &params.post_effects)
+}
 
+/// The orbit densities of the three channels of a Buddhabrot, denoised but not yet normalized
+///
+/// These are the raw counts `generate_buddhabrot` colors, for outputs that keep the linear
+/// densities, such as `output::hdr`.
+///
+/// # Returns
+///
+/// The red, green and blue densities
+pub fn buddhabrot_densities(params: &BuddhabrotParams) -> [DensityGrid; 3] {
     // Generate each channel separately
-    let red_hist = buddhabrot_channel(params, &params.channels.red, params.channels.red.max_iter);
-    let green_hist = buddhabrot_channel(params, &params.channels.green, params.channels.green.max_iter);
-    let blue_hist = buddhabrot_channel(params, &params.channels.blue, params.channels.blue.max_iter);
-
-    // Calculate 95th percentile of log-transformed values for each channel
-    // This gives us a more robust normalization value that's less sensitive to outliers
-    let log_percentile_r = calculate_percentile_log(&red_hist, 95.0);
-    let log_percentile_g = calculate_percentile_log(&green_hist, 95.0);
-    let log_percentile_b = calculate_percentile_log(&blue_hist, 95.0);
-
-    // If all channels are zero, return a black image
-    if log_percentile_r == 0.0 && log_percentile_g == 0.0 && log_percentile_b == 0.0 {
-        return img; // Already initialized as black
-    }
-
-    // Normalize and combine channels using percentile-based normalization
-    for y in 0..params.height as usize {
-        for x in 0..params.width as usize {
-            let r_val = if log_percentile_r > 0.0 {
-                let raw_value = red_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_r > 0.0 { log_value / log_percentile_r } else { 0.0 };
-
-                // Clamp normalized value to [0, 1] range
-                let clamped_norm = norm.min(1.0).max(0.0);
-
-                // Apply final scaling to map to 0-255 range
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
-
-            let g_val = if log_percentile_g > 0.0 {
-                let raw_value = green_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_g > 0.0 { log_value / log_percentile_g } else { 0.0 };
-
-                let clamped_norm = norm.min(1.0).max(0.0);
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
-
-            let b_val = if log_percentile_b > 0.0 {
-                let raw_value = blue_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_b > 0.0 { log_value / log_percentile_b } else { 0.0 };
-
-                let clamped_norm = norm.min(1.0).max(0.0);
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
+    // One prefilter grid serves all channels, so it is iterated to the largest max_iter
+    let grid = (params.prefilter_cells > 0).then(|| {
+        let channels = [&params.channels.red, &params.channels.green, &params.channels.blue];
+        let max_iter = channels.iter().map(|channel| channel.max_iter).max().unwrap_or(params.max_iterations);
+        println!("Computing {0}x{0} escape-time prefilter grid", params.prefilter_cells);
+        buddhabrot::EscapeTimeGrid::compute(params, params.prefilter_cells, max_iter)
+    });
+    let [red_bounded, green_bounded, blue_bounded] = params.orbits.bounded_channels();
+    let mut red_hist = buddhabrot_channel_with_grid(params, &params.channels.red, red_bounded, grid.as_ref());
+    let mut green_hist = buddhabrot_channel_with_grid(params, &params.channels.green, green_bounded, grid.as_ref());
+    let mut blue_hist = buddhabrot_channel_with_grid(params, &params.channels.blue, blue_bounded, grid.as_ref());
+    buddhabrot::denoise::denoise_channels(params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+    [red_hist, green_hist, blue_hist]
+}
+
+/// Color the channel densities of a Buddhabrot or Buddhabrot Julia render
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `densities` - Red, green and blue densities, from `buddhabrot_densities` or `buddhabrot_julia_densities`
+/// * `mix` - Optional channel mix
+/// * `transform` - Optional transfer curve encoding the mixed channels for display
+/// * `post_effects` - Post-processing effects applied to the colored image
+///
+/// # Returns
+///
+/// The RGB image, as `generate_buddhabrot` returns it
+pub fn render_buddhabrot_densities(
+    width: u32,
+    height: u32,
+    [red, green, blue]: &[DensityGrid; 3],
+    mix: Option<&buddhabrot::ChannelMix>,
+    transform: Option<output::DisplayTransform>,
+    post_effects: &[postfx::PostEffect],
+) -> image::RgbImage {
+    let mut img = combine_buddhabrot_channels(width, height, red, green, blue, mix, transform);
+    postfx::apply_post_effects_rgb(&mut img, post_effects);
+    img
+}
+
+/// Combine three channel histograms into an RGB image
+///
+/// Each channel is log-scaled and normalized to its own 95th percentile, then mixed into the
+/// output colors by `mix` and encoded by `transform` if they are given.
+pub(crate) fn combine_buddhabrot_channels(
+    width: u32,
+    height: u32,
+    red_hist: &DensityGrid,
+    green_hist: &DensityGrid,
+    blue_hist: &DensityGrid,
+    mix: Option<&buddhabrot::ChannelMix>,
+    transform: Option<output::DisplayTransform>,
+) -> image::RgbImage {
+    let red = normalize_density_histogram(red_hist);
+    let green = normalize_density_histogram(green_hist);
+    let blue = normalize_density_histogram(blue_hist);
+    combine_normalized_channels(width, height, [&red, &green, &blue], mix, transform)
+}
 
+/// Combine three channels already normalized to [0, 1] into an RGB image, mixed by `mix` and
+/// encoded for display by `transform` if they are given
+///
+/// The transform is applied to the values before they are quantized, so the dark levels it
+/// spreads apart don't band.
+pub(crate) fn combine_normalized_channels(
+    width: u32,
+    height: u32,
+    [red, green, blue]: [&DensityGrid; 3],
+    mix: Option<&buddhabrot::ChannelMix>,
+    transform: Option<output::DisplayTransform>,
+) -> image::RgbImage {
+    let mut img = image::RgbImage::new(width, height);
+
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let bands = [red[(x, y)], green[(x, y)], blue[(x, y)]];
+            let mixed = mix.map_or(bands, |mix| mix.apply(bands));
+            let [r, g, b] = transform.map_or(mixed, |transform| mixed.map(|value| transform.encode(value)));
+            // Map the normalized values to the 0-255 range
+            let r_val = (r * 255.0) as u8;
+            let g_val = (g * 255.0) as u8;
+            let b_val = (b * 255.0) as u8;
             img.put_pixel(x as u32, y as u32, image::Rgb([r_val, g_val, b_val]));
         }
     }
@@ -2731,6 +4024,29 @@ pub fn generate_buddhabrot(params: &BuddhabrotParams) -> image::RgbImage {
     img
 }
 
+/// Normalize a density histogram to [0, 1] the way Buddhabrot channels are
+///
+/// Counts are log-scaled as ln(1 + n) and divided by the 95th percentile of the nonzero
+/// log counts, which is less sensitive to a few very bright cells than the maximum, then
+/// clamped to 1. An empty histogram normalizes to all zeros.
+///
+/// # Arguments
+///
+/// * `hist` - Counts
+///
+/// # Returns
+///
+/// The normalized values in a grid of the same size
+pub(crate) fn normalize_density_histogram(hist: &DensityGrid) -> DensityGrid {
+    let log_percentile = calculate_percentile_log(hist, 95.0);
+    hist.map(|&raw_value| {
+        if log_percentile <= 0.0 || raw_value <= 0.0 {
+            return 0.0;
+        }
+        ((raw_value + 1.0).ln() / log_percentile).clamp(0.0, 1.0)
+    })
+}
+
 /// Calculate the Buddhabrot Julia for a specific channel
 ///
 /// Implements the Buddhabrot algorithm for Julia sets by tracking the orbits of
@@ -2747,104 +4063,72 @@ pub fn generate_buddhabrot(params: &BuddhabrotParams) -> image::RgbImage {
 pub fn buddhabrot_julia_channel(
     params: &BuddhabrotJuliaParams,
     channel_params: &BuddhabrotChannel,
-) -> Vec<Vec<f64>> {
+) -> DensityGrid {
     use std::time::Instant;
-    use std::collections::HashMap;
 
     let [x_min, x_max, y_min, y_max] = params.bounds;
+    let formula = CompiledFormula::new(&params.formula);
+    let number_system = algebra::NumberSystem::for_params(params.i_sqrt_value, &formula);
 
     let total_samples = channel_params.samples;
     let start_time = Instant::now();
 
+    let growths_before = buddhabrot::scratch::orbit_buffer_growths();
+
+    let telemetry = telemetry::RenderTelemetry::start("Generating Buddhabrot Julia channel", total_samples);
+
     // Print initial progress
     println!("Generating Buddhabrot Julia channel: 0% (0/{}) - Started at {:?}. Using {} threads.",
              total_samples, Local::now().format("%H:%M:%S"), rayon::current_num_threads());
 
     // Determine chunk size for parallel processing
     let chunk_size = (total_samples / (rayon::current_num_threads() as u64 * 4)).max(1000);
-    let num_chunks = std::cmp::max((total_samples as usize) / chunk_size as usize, 1);
+    let num_chunks = total_samples.div_ceil(chunk_size).max(1);
 
-    // Process samples in chunks using parallel iterator
-    let partial_histograms: Vec<HashMap<(usize, usize), f64>> = (0..num_chunks)
-        .into_par_iter()
-        .map(|chunk_idx| {
-            let start_sample = (chunk_idx as u64) * chunk_size;
+    // Process samples in chunks in parallel, each job counting into a histogram of its own
+    let final_histogram = DensityGrid::par_accumulate(params.width as usize, params.height as usize, 0..num_chunks, |histogram, chunk_idx| {
+            let start_sample = chunk_idx * chunk_size;
             let end_sample = std::cmp::min(start_sample + chunk_size, total_samples);
 
-            let mut local_histogram = HashMap::new();
-            // Use a deterministic seed based on the chunk index to ensure reproducible results
-            let mut rng = rand::rngs::StdRng::seed_from_u64(start_sample ^ 0xcafebabe);
-
-            for _sample_num in start_sample..end_sample {
+            for sample_num in start_sample..end_sample {
+                if params.cancel.is_cancelled() {
+                    break;
+                }
+                let _task = telemetry.task();
+                // Each sample has its own random stream, independent of the chunking
+                let mut rng = rng::CounterRng::new(BUDDHABROT_JULIA_SEED, sample_num);
                 // Randomly sample a z0 value in the complex plane using the local RNG
                 let z_re = x_min + (x_max - x_min) * rng.gen::<f64>();
                 let z_im = y_min + (y_max - y_min) * rng.gen::<f64>();
                 let mut z = Complex::new(z_re, z_im);
 
-                // Check if this point escapes within the iteration range
-                let mut iter = 0;
-                let mut orbit = Vec::new();
-
-                // Track the orbit
-                while iter < channel_params.max_iter {
-                    orbit.push(z);
-                    // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
-                    if params.i_sqrt_value == Complex::new(0.0, 1.0) {
-                        // Use standard algorithm for backward compatibility
-                        z = match MathEvaluator::evaluate_formula_with_param(&params.formula, z, params.spawn) {
-                            Ok(result) => result,
-                            Err(_) => z * z + params.spawn, // Fallback to standard Julia formula
-                        };
-                    } else {
-                        // Use custom complex arithmetic for non-standard imaginary units
-                        let custom_i_squared = params.i_sqrt_value;
-                        let z_custom = CustomComplex::new(z.re, z.im, custom_i_squared);
-                        let c_custom = CustomComplex::new(params.spawn.re, params.spawn.im, custom_i_squared);
-
-                        let result_custom = match MathEvaluator::evaluate_formula_with_param(&params.formula, z_custom.to_standard(), c_custom.to_standard()) {
-                            Ok(result) => CustomComplex::from_standard(result, custom_i_squared),
-                            Err(_) => {
-                                // Fallback to standard formula using custom arithmetic
-                                let z_sq = z_custom.multiply(&z_custom);
-                                z_sq.add(&c_custom)
-                            },
-                        };
-
-                        z = result_custom.to_standard();
-                    };
-
-                    if z.norm_sqr() > params.bailout * params.bailout {
-                        // Point escapes, check if it's in the right iteration range
-                        if iter >= channel_params.min_iter {
-                            // Draw the orbit - accumulate locally first
-                            for point in &orbit {
-                                let px = ((point.re - x_min) / (x_max - x_min) * params.width as f64) as usize;
-                                let py = ((point.im - y_min) / (y_max - y_min) * params.height as f64) as usize;
-
-                                if px < params.width as usize && py < params.height as usize {
-                                    *local_histogram.entry((px, py)).or_insert(0.0) += 1.0;
+                // Check if this point escapes within the iteration range, recording the orbit in
+                // this thread's reusable buffer
+                buddhabrot::scratch::with_orbit_buffer(|orbit| {
+                    let mut iter = 0;
+
+                    // Track the orbit
+                    while iter < channel_params.max_iter {
+                        orbit.push(z);
+                        // Use the formula specified in params, defaulting to z^2 + c if evaluation fails
+                        z = number_system.step(&formula, z, params.spawn);
+
+                        if z.norm_sqr() > params.bailout * params.bailout {
+                            // Point escapes, check if it's in the right iteration range
+                            if iter >= channel_params.min_iter {
+                                for point in orbit.iter() {
+                                    let fx = (point.re - x_min) / (x_max - x_min) * params.width as f64;
+                                    let fy = (point.im - y_min) / (y_max - y_min) * params.height as f64;
+                                    histogram.splat(fx, fy, 1.0);
                                 }
                             }
+                            break;
                         }
-                        break;
+                        iter += 1;
                     }
-                    iter += 1;
-                }
+                });
             }
-            local_histogram
-        })
-        .collect();
-
-    // Merge all partial histograms into the final histogram
-    let mut final_histogram = vec![vec![0.0; params.width as usize]; params.height as usize];
-
-    for partial_hist in partial_histograms {
-        for ((x, y), value) in partial_hist {
-            if x < params.width as usize && y < params.height as usize {
-                final_histogram[y][x] += value;
-            }
-        }
-    }
+        });
 
     // Final progress report
     let elapsed = start_time.elapsed();
@@ -2852,6 +4136,11 @@ pub fn buddhabrot_julia_channel(
         "Generating Buddhabrot Julia channel: 100% ({}/{}), Completed in {:.1}s",
         total_samples, total_samples, elapsed.as_secs_f64()
     );
+    println!(
+        "  Orbit buffers grew {} times for {} samples",
+        buddhabrot::scratch::orbit_buffer_growths() - growths_before,
+        total_samples
+    );
 
     final_histogram
 }
@@ -2869,60 +4158,19 @@ pub fn buddhabrot_julia_channel(
 ///
 /// An RGB image representing the combined Buddhabrot Julia visualization
 pub fn generate_buddhabrot_julia(params: &BuddhabrotJuliaParams) -> image::RgbImage {
-    let mut img = image::RgbImage::new(params.width, params.height);
+    let densities = buddhabrot_julia_densities(params);
+    render_buddhabrot_densities(params.width, params.height, &densities, params.channel_mix.as_ref(), params.display_transform, &params.post_effects)
+}
 
+/// The orbit densities of the three channels of a Buddhabrot Julia render, denoised but not
+/// yet normalized (see `buddhabrot_densities`)
+pub fn buddhabrot_julia_densities(params: &BuddhabrotJuliaParams) -> [DensityGrid; 3] {
     // Generate each channel separately
-    let red_hist = buddhabrot_julia_channel(params, &params.channels.red);
-    let green_hist = buddhabrot_julia_channel(params, &params.channels.green);
-    let blue_hist = buddhabrot_julia_channel(params, &params.channels.blue);
-
-    // Calculate 95th percentile of log-transformed values for each channel
-    // This gives us a more robust normalization value that's less sensitive to outliers
-    let log_percentile_r = calculate_percentile_log(&red_hist, 95.0);
-    let log_percentile_g = calculate_percentile_log(&green_hist, 95.0);
-    let log_percentile_b = calculate_percentile_log(&blue_hist, 95.0);
-
-    // If all channels are zero, return a black image
-    if log_percentile_r == 0.0 && log_percentile_g == 0.0 && log_percentile_b == 0.0 {
-        return img; // Already initialized as black
-    }
-
-    // Normalize and combine channels using percentile-based normalization
-    for y in 0..params.height as usize {
-        for x in 0..params.width as usize {
-            let r_val = if log_percentile_r > 0.0 {
-                let raw_value = red_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_r > 0.0 { log_value / log_percentile_r } else { 0.0 };
-
-                // Clamp normalized value to [0, 1] range
-                let clamped_norm = norm.min(1.0).max(0.0);
-
-                // Apply final scaling to map to 0-255 range
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
-            let g_val = if log_percentile_g > 0.0 {
-                let raw_value = green_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_g > 0.0 { log_value / log_percentile_g } else { 0.0 };
-
-                let clamped_norm = norm.min(1.0).max(0.0);
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
-            let b_val = if log_percentile_b > 0.0 {
-                let raw_value = blue_hist[y][x];
-                let log_value = if raw_value > 0.0 { (raw_value + 1.0).ln() } else { 0.0 };
-                let norm = if log_percentile_b > 0.0 { log_value / log_percentile_b } else { 0.0 };
-
-                let clamped_norm = norm.min(1.0).max(0.0);
-                (clamped_norm * 255.0) as u8
-            } else { 0 };
-
-            img.put_pixel(x as u32, y as u32, image::Rgb([r_val, g_val, b_val]));
-        }
-    }
-
-    img
+    let mut red_hist = buddhabrot_julia_channel(params, &params.channels.red);
+    let mut green_hist = buddhabrot_julia_channel(params, &params.channels.green);
+    let mut blue_hist = buddhabrot_julia_channel(params, &params.channels.blue);
+    buddhabrot::denoise::denoise_channels(params.denoise.as_ref(), [&mut red_hist, &mut green_hist, &mut blue_hist]);
+    [red_hist, green_hist, blue_hist]
 }
 
 /// Convert pixel coordinates to complex plane coordinates
@@ -2963,8 +4211,9 @@ pub fn pixel_to_complex(x: u32, y: u32, width: u32, height: u32, bounds: [f64; 4
 ///
 /// This function creates a visualization of a complex function using domain coloring,
 /// where each point in the complex plane is assigned a color based on the value of
-/// the function at that point. The hue represents the argument (angle) of the complex
-/// value, and the lightness represents the magnitude.
+/// the function at that point. The hue, taken from `params.phase_wheel`, represents the
+/// argument (angle) of the complex value, and the lightness represents the magnitude. With `branch_cut_threshold` set,
+/// discontinuities of the phase are drawn over the plot (see `branch_cut_pixels`).
 ///
 /// # Arguments
 ///
@@ -2983,18 +4232,32 @@ pub fn generate_domain_color_plot(params: &DomainColorParams) -> image::RgbImage
     // Create a vector of (x, y) coordinates to process in parallel
     let coords: Vec<(u32, u32)> = (0..params.height).flat_map(|y| (0..params.width).map(move |x| (x, y))).collect();
 
-    // Process pixels in parallel
-    let results: Vec<((u32, u32), [u8; 3])> = coords
-        .into_par_iter()
-        .map(|(x, y)| {
+    // Evaluate the function at every pixel in parallel
+    let values: Vec<Complex<f64>> = coords
+        .par_iter()
+        .map(|&(x, y)| {
             // Convert pixel coordinates to complex plane coordinates
             let z = pixel_to_complex(x, y, params.width, params.height, params.bounds);
 
             // Evaluate the complex function with custom imaginary unit
-            let result = match evaluate_complex_function_with_custom_i(&params.formula, z, params.i_sqrt_value) {
+            match evaluate_complex_function_with_custom_i(&params.formula, z, params.i_sqrt_value) {
                 Ok(value) => value,
                 Err(_) => Complex::new(0.0, 0.0), // Default to zero if evaluation fails
-            };
+            }
+        })
+        .collect();
+    let branch_cuts = params
+        .branch_cut_threshold
+        .map(|threshold| branch_cut_pixels(&values, params.width as usize, params.height as usize, threshold));
+
+    let results: Vec<((u32, u32), [u8; 3])> = coords
+        .into_par_iter()
+        .zip(values.par_iter())
+        .enumerate()
+        .map(|(index, ((x, y), &result))| {
+            if branch_cuts.as_ref().is_some_and(|cuts| cuts[index]) {
+                return ((x, y), [255, 255, 255]);
+            }
 
             // Calculate hue based on argument (angle) of the result
             let arg = result.arg(); // Returns angle in radians from -π to π
@@ -3012,8 +4275,7 @@ pub fn generate_domain_color_plot(params: &DomainColorParams) -> image::RgbImage
                 0.0
             };
 
-            // Convert HSV to RGB
-            let rgb = hsv_to_rgb(hue, 1.0, brightness);
+            let rgb = params.phase_wheel.color(hue, brightness);
 
             ((x, y), rgb)
         })
@@ -3028,6 +4290,45 @@ pub fn generate_domain_color_plot(params: &DomainColorParams) -> image::RgbImage
     img
 }
 
+/// Find the pixels of a domain coloring plot that lie on a branch cut
+///
+/// A branch cut shows up as a line where the phase jumps between neighbouring pixels instead of
+/// varying smoothly, e.g. by π across the negative real axis for `sqrt(z)`. Zeros and poles also
+/// produce large jumps between the pixels around them, so they show up as isolated dots.
+///
+/// # Arguments
+///
+/// * `values` - Function values in row-major order
+/// * `width` - Width of the plot in pixels
+/// * `height` - Height of the plot in pixels
+/// * `threshold` - Smallest phase difference in radians, between 0 and π, that counts as a jump
+///
+/// # Returns
+///
+/// One flag per pixel, set when its phase differs by more than `threshold` from the pixel to its
+/// right or below it. Pixels where the function is zero or not finite are never flagged
+pub fn branch_cut_pixels(values: &[Complex<f64>], width: usize, height: usize, threshold: f64) -> Vec<bool> {
+    let phase = |index: usize| {
+        let value = values[index];
+        (value.re.is_finite() && value.im.is_finite() && value.norm_sqr() > 0.0).then(|| value.arg())
+    };
+    let jumps = |a: usize, b: usize| match (phase(a), phase(b)) {
+        // Distance around the circle, so -π and π are the same phase
+        (Some(pa), Some(pb)) => {
+            let difference = (pa - pb).abs();
+            difference.min(2.0 * PI - difference) > threshold
+        }
+        _ => false,
+    };
+
+    (0..width * height)
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            (x + 1 < width && jumps(index, index + 1)) || (y + 1 < height && jumps(index, index + width))
+        })
+        .collect()
+}
+
 /// Evaluate a complex function given as a string
 ///
 /// This is a sophisticated evaluator that handles complex mathematical expressions
@@ -3041,7 +4342,7 @@ pub fn generate_domain_color_plot(params: &DomainColorParams) -> image::RgbImage
 ///
 /// The result of evaluating the function at z, or an error if the formula is invalid
 #[allow(dead_code)]
-fn evaluate_complex_function(formula: &str, z: Complex<f64>) -> Result<Complex<f64>, String> {
+fn evaluate_complex_function(formula: &str, z: Complex<f64>) -> Result<Complex<f64>, FractalError> {
     // Use the existing sophisticated parser
     let formula = formula.trim();
 
@@ -3055,7 +4356,7 @@ fn evaluate_complex_function(formula: &str, z: Complex<f64>) -> Result<Complex<f
 }
 
 /// Evaluate a complex function with a given formula and custom imaginary unit
-fn evaluate_complex_function_with_custom_i(formula: &str, z: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, String> {
+fn evaluate_complex_function_with_custom_i(formula: &str, z: Complex<f64>, custom_i: Complex<f64>) -> Result<Complex<f64>, FractalError> {
     // Use the existing sophisticated parser with custom imaginary unit
     let formula = formula.trim();
 
@@ -3078,8 +4379,7 @@ fn evaluate_complex_function_with_custom_i(formula: &str, z: Complex<f64>, custo
             Ok(result) => CustomComplex::from_standard(result, custom_i_squared),
             Err(_) => {
                 // Fallback to standard formula using custom arithmetic
-                let z_sq = z_custom.multiply(&z_custom);
-                z_sq.add(&param_custom)
+                z_custom * z_custom + param_custom
             },
         };
 
@@ -3098,7 +4398,7 @@ fn evaluate_complex_function_with_custom_i(formula: &str, z: Complex<f64>, custo
 /// # Returns
 ///
 /// RGB values as [u8, u8, u8] array
-fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8; 3] {
+pub(crate) fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8; 3] {
     let h = h.fract(); // Ensure hue is in [0, 1) range
     let h_i = (h * 6.0).floor() as i32;
     let f = h * 6.0 - h_i as f64;
@@ -3148,6 +4448,105 @@ mod tests {
         assert!((bottom_right.im - expected_y).abs() < 0.01);  // Should be close to y_max
     }
 
+    #[test]
+    fn test_julia_orbits_leaving_the_view_are_dropped() {
+        // In a view of one quadrant most orbit points fall to the left of or below it
+        let channel = BuddhabrotChannel { min_iter: 2, max_iter: 50, samples: 5_000 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel.clone() };
+        let params = BuddhabrotJuliaParams::new([0.0, 1.0, 0.0, 1.0], 8, 8, 2, 50, 5_000, 4.0, [-0.8, 0.156], "z^2 + c".to_string(), channels);
+        let hist = buddhabrot_julia_channel(&params, &channel);
+        let mean = hist.as_slice().iter().sum::<f64>() / 64.0;
+        // Saturating casts used to pile them up in the first row and column
+        assert!(hist[(0, 0)] < mean * 4.0, "{} against a mean of {}", hist[(0, 0)], mean);
+    }
+
+    #[test]
+    fn test_buddhabrot_params_without_later_fields_load() {
+        // Parameters saved before these fields existed load with the defaults of `new`
+        let channel = BuddhabrotChannel { min_iter: 2, max_iter: 50, samples: 100 };
+        let channels = BuddhabrotChannels { red: channel.clone(), green: channel.clone(), blue: channel };
+        let params = BuddhabrotParams::new([-2.0, 2.0, -2.0, 2.0], 8, 8, 2, 50, 100, 4.0, "z^2 + c".to_string(), channels);
+        let mut json = serde_json::to_value(&params).unwrap();
+        for field in ["prefilter_cells", "denoise", "post_effects"] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+        let loaded: BuddhabrotParams = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.prefilter_cells, params.prefilter_cells);
+        assert!(loaded.denoise.is_none() && loaded.post_effects.is_empty());
+    }
+
+    #[test]
+    fn test_custom_complex_operators() {
+        use num::{One, Zero};
+
+        let split = Complex::new(1.0, 0.0);
+        let z = CustomComplex::new(2.0, 0.5, split);
+        let c = CustomComplex::new(-0.3, 0.4, split);
+        assert_eq!(z * z + c, z.multiply(&z).add(&c));
+        let (z_ref, c_ref) = (&z, &c);
+        assert_eq!(z_ref - c_ref, z.subtract(&c));
+        assert_eq!(-z, CustomComplex::new(-2.0, -0.5, split));
+        // The real identities adopt the other operand's number system
+        assert_eq!(CustomComplex::one() * z, z);
+        assert_eq!(z + CustomComplex::zero(), z);
+        assert!((z - z).is_zero());
+
+        for i_squared in [Complex::new(-1.0, 0.0), split, Complex::new(0.5, 1.5)] {
+            let w = CustomComplex::new(0.7, -1.3, i_squared);
+            let quotient = CustomComplex::new(1.0, 2.0, i_squared) / w * w;
+            assert!((quotient.re - 1.0).abs() < 1e-12 && (quotient.im - 2.0).abs() < 1e-12, "{}", quotient);
+        }
+        assert_eq!(CustomComplex::new(1.0, -2.0, Complex::new(-1.0, 0.0)).to_string(), "1-2i");
+        assert_eq!(z.to_string(), "2+0.5i (i² = 1+0i)");
+    }
+
+    #[test]
+    fn test_custom_complex_functions() {
+        let close = |a: CustomComplex, b: CustomComplex| (a.re - b.re).abs() < 1e-9 && (a.im - b.im).abs() < 1e-9;
+
+        // Standard, split (i² = 1) and dual (i² = 0) numbers have closed forms
+        let standard = CustomComplex::new(0.4, -1.1, Complex::new(-1.0, 0.0));
+        let expected = Complex::new(0.4, -1.1).sin();
+        assert!(close(standard.sin(), CustomComplex::from_standard(expected, standard.i_squared)));
+        let split = CustomComplex::new(0.4, -1.1, Complex::new(1.0, 0.0));
+        let expected = CustomComplex::new(0.4f64.exp() * 1.1f64.cosh(), -(0.4f64.exp() * 1.1f64.sinh()), split.i_squared);
+        assert!(close(split.exp(), expected), "{}", split.exp());
+        assert!(split.ln().re.is_nan(), "0.4 - 1.1 is a negative light-cone coordinate");
+        let dual = CustomComplex::new(0.4, -1.1, Complex::new(0.0, 0.0));
+        assert!(close(dual.sin(), CustomComplex::new(0.4f64.sin(), -1.1 * 0.4f64.cos(), dual.i_squared)), "{}", dual.sin());
+
+        // Inverse functions and powers agree in a system with i² = -2 + i
+        let z = CustomComplex::new(0.7, 0.3, Complex::new(-2.0, 1.0));
+        assert!(close(z.exp().ln(), z), "{}", z.exp().ln());
+        assert!(close(z.sqrt() * z.sqrt(), z));
+        assert!(close(z.powi(5), z * z * z * z * z));
+        assert!(close(z.powi(-2) * z * z, CustomComplex::new(1.0, 0.0, z.i_squared)));
+        assert!(close(z.pow(&CustomComplex::new(0.5, 0.0, z.i_squared)), z.sqrt()));
+        assert!(close(z.cosh() * z.cosh() - z.sinh() * z.sinh(), CustomComplex::new(1.0, 0.0, z.i_squared)));
+
+        let formula = CompiledFormula::new("sin(z) + c");
+        assert!(CustomComplex::formula_is_real(&formula));
+        let c = CustomComplex::new(-0.2, 0.5, z.i_squared);
+        assert!(close(CustomComplex::eval_formula(&formula, &z, &c).unwrap(), z.sin() + c));
+        assert!(!CustomComplex::formula_is_real(&CompiledFormula::new("z^2 + i*c")));
+        assert!(!CustomComplex::formula_is_real(&CompiledFormula::new("conj(z)^2 + c")));
+    }
+
+    #[test]
+    fn test_branch_cut_pixels_find_sqrt_cut() {
+        let (width, height) = (16, 16);
+        let values: Vec<Complex<f64>> = (0..width * height)
+            .map(|index| pixel_to_complex((index % width) as u32, (index / width) as u32, width as u32, height as u32, [-1.0, 1.0, -1.0, 1.0]).sqrt())
+            .collect();
+        let cuts = branch_cut_pixels(&values, width, height, 2.0);
+
+        // sqrt is discontinuous across the negative real axis only
+        let flagged = |x: usize| (0..height).filter(|&y| cuts[y * width + x]).count();
+        assert_eq!(flagged(0), 1);
+        assert_eq!(flagged(width - 1), 0);
+        assert!((0..width * height).filter(|&index| cuts[index]).all(|index| index % width < width / 2 + 1));
+    }
+
     #[test]
     fn test_mandelbrot_iterations_origin() {
         // The origin (0, 0) should be in the Mandelbrot set (high iterations)
@@ -3181,16 +4580,154 @@ mod tests {
         let z = Complex::new(3.0, 4.0);
         assert_eq!(z.norm_sqr(), 25.0);  // 3^2 + 4^2 = 25
     }
+
+    #[test]
+    fn test_inside_and_background_colors_override_palette() {
+        let palette = parse_color_palette("[(#FFFFFF,0.0),(#0000FF,1.0)]").unwrap();
+        let mut params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 100, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+
+        // Without overrides the interior takes the palette's first stop
+        assert_eq!(color_for_iterations(100, &params, Some(&palette)), Rgba([255, 255, 255, 255]));
+
+        params.inside_color = Some(parse_rgba_color("#102030").unwrap());
+        params.outside_background = Some(parse_rgba_color("transparent").unwrap());
+        assert_eq!(color_for_iterations(100, &params, Some(&palette)), Rgba([16, 32, 48, 255]));
+        assert_eq!(color_for_iterations(0, &params, Some(&palette)), Rgba([0, 0, 0, 0]));
+        assert_eq!(color_for_iterations(50, &params, Some(&palette)), color_from_iterations_with_palette(50, 100, &palette));
+        assert!(parse_rgba_color("#12345").is_err());
+    }
+
+    #[test]
+    fn test_palette_easing_shapes_segments() {
+        let palette = parse_color_palette("[(#000000,0.0,step),(#808080,0.5,smoothstep),(#FFFFFF,1.0)]").unwrap();
+        assert_eq!(palette[0].easing, Easing::Step);
+        assert_eq!(palette[2].easing, Easing::Linear);
+
+        // A step segment holds its color until the next stop
+        assert_eq!(interpolate_color_from_palette(0.49, &palette), Rgba([0, 0, 0, 255]));
+        assert_eq!(interpolate_color_from_palette(0.5, &palette), Rgba([128, 128, 128, 255]));
+        // Smoothstep passes the midpoint of its segment at the same color as linear, but starts slower
+        assert_eq!(interpolate_color_from_palette(0.75, &palette), Rgba([192, 192, 192, 255]));
+        assert!(interpolate_color_from_palette(0.55, &palette)[0] < 128 + 13);
+
+        assert_eq!(Easing::Cubic.apply(0.5), 0.5);
+        assert!(parse_color_palette("[(#000000,0.0,bounce),(#FFFFFF,1.0)]").is_err());
+    }
+
+    #[test]
+    fn test_histogram_coloring_spreads_palette() {
+        // Three quarters of the escaped pixels leave after one iteration, the rest after 90
+        let result = |iterations| IterationResult { iterations, escaped: iterations < 100, final_z: Complex::new(0.0, 0.0), period: None, multiplier: None };
+        let results: Vec<IterationResult> = [1, 1, 1, 90, 100].into_iter().map(result).collect();
+        let histogram = IterationHistogram::from_results(&results, 100);
+        assert_eq!(histogram.equalize(0.0), 0.0);
+        assert_eq!(histogram.equalize(1.0), 0.75);
+        assert_eq!(histogram.equalize(50.0), 0.75);
+        assert_eq!(histogram.equalize(90.0), 1.0);
+        assert_eq!(histogram.equalize(0.5), 0.375);
+
+        let palette = parse_color_palette("[(#000000,0.0),(#FFFFFF,1.0)]").unwrap();
+        let mut params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 100, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        params.histogram_coloring = true;
+        let colors = color_results(&results, &params, Some(&palette));
+        assert_eq!(colors[0], Rgba([191, 191, 191, 255]));
+        assert_eq!(colors[3], Rgba([255, 255, 255, 255]));
+        assert_eq!(colors[4], Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_color_density_cycles_palette() {
+        let palette = parse_color_palette("[(#000000,0.0),(#FFFFFF,1.0)]").unwrap();
+        let mut params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 100, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        assert_eq!(params.cycled_count(37.5), 37.5);
+
+        // Four repeats: 10 and 35 iterations are both 40% of the way through a cycle
+        params.color_density = 4.0;
+        assert_eq!(color_for_iterations(10, &params, Some(&palette)), color_for_iterations(35, &params, Some(&palette)));
+        assert_eq!(color_for_iterations(10, &params, Some(&palette)), Rgba([102, 102, 102, 255]));
+        assert_eq!(color_for_iterations(100, &params, Some(&palette)), Rgba([0, 0, 0, 255]));
+
+        params.color_offset = 0.5;
+        assert!((params.cycled_count(10.0) - 90.0).abs() < 1e-9);
+        assert!((params.cycled_count(20.0) - 30.0).abs() < 1e-9);
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColorStop {
     pub color: [u8; 3],  // RGB
     pub position: f64,   // 0.0 to 1.0
+    /// How the color changes from this stop to the next one
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// Shape of the transition between two palette stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Easing {
+    /// Constant rate of change
+    #[default]
+    Linear,
+    /// Slow at both stops and fastest in between (`3t^2 - 2t^3`), for soft blends
+    Smoothstep,
+    /// Cubic ease-in-out, which holds each color longer than smoothstep before changing
+    Cubic,
+    /// Keep this stop's color up to the next stop, for sharp bands
+    Step,
+}
+
+impl Easing {
+    /// Parse an easing name: `linear`, `smoothstep` (or `smooth`), `cubic` or `step`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "linear" => Ok(Easing::Linear),
+            "smoothstep" | "smooth" => Ok(Easing::Smoothstep),
+            "cubic" => Ok(Easing::Cubic),
+            "step" => Ok(Easing::Step),
+            _ => Err(format!("Unknown easing: {} (expected linear, smoothstep, cubic or step)", name.trim())),
+        }
+    }
+
+    /// Map the position between two stops, from 0 at the first to 1 at the second, to the
+    /// fraction of the second stop's color to mix in
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Easing::Cubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
 }
 
 // Parse color palette string like "[(#FF0000,0.0),(#00FF00,0.5),(#0000FF,1.0)]"
+//
+// A stop may name the easing towards the next stop as a third value, e.g.
+// "[(#FF0000,0.0,step),(#00FF00,0.5,smoothstep),(#0000FF,1.0)]"; the default is linear.
+//
+// The name of a built-in palette ("inferno", "viridis", see `palette::PRESET_NAMES`) or the
+// path of a `.map`, `.ggr` or `.json` palette file can be given instead of the stops.
 pub fn parse_color_palette(palette_str: &str) -> Result<Vec<ColorStop>, String> {
+    if let Some(preset) = palette::preset_palette(palette_str) {
+        return Ok(preset);
+    }
+    if palette::is_palette_file(palette_str) {
+        return palette::load_palette_file(palette_str);
+    }
     let mut stops = Vec::new();
 
     // Remove outer brackets if present
@@ -3203,7 +4740,7 @@ pub fn parse_color_palette(palette_str: &str) -> Result<Vec<ColorStop>, String>
         let clean_stop = stop_str.trim().trim_start_matches('(').trim_end_matches(')');
         let parts: Vec<&str> = clean_stop.split(',').collect();
 
-        if parts.len() != 2 {
+        if parts.len() != 2 && parts.len() != 3 {
             return Err(format!("Invalid color stop format: {}", clean_stop));
         }
 
@@ -3216,7 +4753,12 @@ pub fn parse_color_palette(palette_str: &str) -> Result<Vec<ColorStop>, String>
         // Parse position
         let position = position_str.parse::<f64>().map_err(|_| format!("Invalid position: {}", position_str))?;
 
-        stops.push(ColorStop { color, position });
+        let easing = match parts.get(2) {
+            Some(name) => Easing::parse(name)?,
+            None => Easing::Linear,
+        };
+
+        stops.push(ColorStop { color, position, easing });
     }
 
     // Sort by position
@@ -3240,22 +4782,62 @@ pub fn parse_hex_color(hex: &str) -> Result<[u8; 3], String> {
     Ok([r, g, b])
 }
 
+/// Parse an RGBA color for the inside/background options: `#RRGGBB`, `#RRGGBBAA` or `transparent`
+///
+/// # Arguments
+///
+/// * `text` - The color string
+///
+/// # Returns
+///
+/// The color as [r, g, b, a], or an error message
+pub fn parse_rgba_color(text: &str) -> Result<[u8; 4], String> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("transparent") || text.eq_ignore_ascii_case("none") {
+        return Ok([0, 0, 0, 0]);
+    }
+
+    let hex_clean = text.trim_start_matches('#');
+    match hex_clean.len() {
+        6 => {
+            let [r, g, b] = parse_hex_color(hex_clean)?;
+            Ok([r, g, b, 255])
+        }
+        8 => {
+            let [r, g, b] = parse_hex_color(&hex_clean[0..6])?;
+            let a = u8::from_str_radix(&hex_clean[6..8], 16).map_err(|_| format!("Invalid hex color: {}", text))?;
+            Ok([r, g, b, a])
+        }
+        _ => Err(format!("Invalid color '{}' (expected #RRGGBB, #RRGGBBAA or transparent)", text)),
+    }
+}
+
 // Interpolate color from palette based on normalized value (0.0 to 1.0)
 pub fn interpolate_color_from_palette(normalized_value: f64, palette: &[ColorStop]) -> image::Rgba<u8> {
+    let [r, g, b] = interpolate_palette_rgb(normalized_value, palette).map(|channel| channel.round() as u8);
+    image::Rgba([r, g, b, 255])
+}
+
+/// `interpolate_color_from_palette` without rounding, for 16-bit and floating point output
+///
+/// # Returns
+///
+/// The red, green and blue components from 0 to 255
+pub fn interpolate_palette_rgb(normalized_value: f64, palette: &[ColorStop]) -> [f64; 3] {
     if palette.is_empty() {
-        return image::Rgba([0, 0, 0, 255]); // Default to black
+        return [0.0, 0.0, 0.0]; // Default to black
     }
 
     if palette.len() == 1 {
-        return image::Rgba([palette[0].color[0], palette[0].color[1], palette[0].color[2], 255]);
+        return palette[0].color.map(f64::from);
     }
 
     // Find the two color stops to interpolate between
     let mut lower_idx = 0;
     let mut upper_idx = palette.len() - 1;
 
-    for i in 0..palette.len() {
-        if palette[i].position <= normalized_value {
+    for (i, stop) in palette.iter().enumerate() {
+        if stop.position <= normalized_value {
             lower_idx = i;
         } else {
             upper_idx = i;
@@ -3272,7 +4854,7 @@ pub fn interpolate_color_from_palette(normalized_value: f64, palette: &[ColorSto
     }
 
     if lower_idx == upper_idx {
-        return image::Rgba([palette[lower_idx].color[0], palette[lower_idx].color[1], palette[lower_idx].color[2], 255]);
+        return palette[lower_idx].color.map(f64::from);
     }
 
     let lower = &palette[lower_idx];
@@ -3280,13 +4862,9 @@ pub fn interpolate_color_from_palette(normalized_value: f64, palette: &[ColorSto
 
     // Interpolate between the two colors
     let t = (normalized_value - lower.position) / (upper.position - lower.position);
-    let t = t.clamp(0.0, 1.0);
-
-    let r = (lower.color[0] as f64 * (1.0 - t) + upper.color[0] as f64 * t).round() as u8;
-    let g = (lower.color[1] as f64 * (1.0 - t) + upper.color[1] as f64 * t).round() as u8;
-    let b = (lower.color[2] as f64 * (1.0 - t) + upper.color[2] as f64 * t).round() as u8;
+    let t = lower.easing.apply(t);
 
-    image::Rgba([r, g, b, 255])
+    std::array::from_fn(|i| lower.color[i] as f64 * (1.0 - t) + upper.color[i] as f64 * t)
 }
 
 // Function to convert iterations to a color using the palette
@@ -3305,9 +4883,26 @@ pub fn color_from_iterations_with_palette(iterations: u32, max_iterations: u32,
         }
     } else {
         // Outside the set - interpolate based on iteration count
-        let t = iterations as f64 / max_iterations as f64;
-        interpolate_color_from_palette(t, palette)
+        color_from_smooth_iterations_with_palette(iterations as f64, max_iterations, palette)
+    }
+}
+
+/// Color an escaped point from its fractional iteration count using the palette
+///
+/// # Arguments
+///
+/// * `iterations` - Fractional iteration count, e.g. from `IterationResult::smooth_iterations`
+/// * `max_iterations` - Iteration limit, which maps to the end of the palette
+/// * `palette` - Color stops
+///
+/// # Returns
+///
+/// The interpolated color
+pub fn color_from_smooth_iterations_with_palette(iterations: f64, max_iterations: u32, palette: &[ColorStop]) -> image::Rgba<u8> {
+    if max_iterations == 0 {
+        return image::Rgba([0, 0, 0, 255]);
     }
+    interpolate_color_from_palette(iterations / max_iterations as f64, palette)
 }
 
 // Simple function to convert iterations to a color (fallback)
@@ -3317,57 +4912,317 @@ pub fn color_from_iterations(iterations: u32, max_iterations: u32) -> image::Rgb
         image::Rgba([0, 0, 0, 255])
     } else {
         // Outside the set - color based on iterations
-        let t = iterations as f64 / max_iterations as f64;
-        let r = (9.0 * (1.0 - t) * t * t * t * 255.0) as u8;
-        let g = (15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0) as u8;
-        let b = (8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0) as u8;
-        image::Rgba([r, g, b, 255])
+        color_from_smooth_iterations(iterations as f64, max_iterations)
     }
 }
 
-use rayon::prelude::*;
+/// Color an escaped point from its fractional iteration count with the built-in gradient
+pub fn color_from_smooth_iterations(iterations: f64, max_iterations: u32) -> image::Rgba<u8> {
+    let [r, g, b] = smooth_gradient_rgb(iterations, max_iterations).map(|channel| channel as u8);
+    image::Rgba([r, g, b, 255])
+}
 
-// Generate fractal image with time-based progress bar and ETA with color palette support
-pub fn generate_fractal_image<F>(
-    width: u32,
-    height: u32,
-    params: &FractalParams,
-    iteration_func: F,
-    color_palette: Option<&Vec<ColorStop>>,
-) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>>
-where
-    F: Fn(Complex<f64>, &FractalParams) -> u32 + Sync + Copy,
-{
-    use std::time::{Duration, Instant};
+/// `color_from_smooth_iterations` without rounding, for 16-bit and floating point output
+///
+/// # Returns
+///
+/// The red, green and blue components from 0 to 255
+pub fn smooth_gradient_rgb(iterations: f64, max_iterations: u32) -> [f64; 3] {
+    let t = (iterations / max_iterations as f64).clamp(0.0, 1.0);
+    [
+        9.0 * (1.0 - t) * t * t * t * 255.0,
+        15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0,
+        8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0,
+    ]
+}
 
-    let mut imgbuf = image::ImageBuffer::new(width, height);
+/// Color an iteration count, honouring the inside color and background of `params`
+///
+/// Points that never escape get `params.inside_color` and points that escape on the first
+/// iteration get `params.outside_background` when those are set; everything else is colored
+/// from the palette, or from the built-in gradient when no palette is given, cycled by
+/// `params.color_density` and `params.color_offset`.
+///
+/// # Arguments
+///
+/// * `iterations` - Iteration count of the point
+/// * `params` - Fractal parameters with the iteration limit and color overrides
+/// * `palette` - Optional color palette
+///
+/// # Returns
+///
+/// The RGBA color of the pixel
+pub fn color_for_iterations(iterations: u32, params: &FractalParams, palette: Option<&[ColorStop]>) -> image::Rgba<u8> {
+    if iterations >= params.max_iterations {
+        if let Some(color) = params.inside_color {
+            return image::Rgba(color);
+        }
+    } else if iterations == 0 {
+        if let Some(color) = params.outside_background {
+            return image::Rgba(color);
+        }
+    }
 
-    // Initialize progress tracking
-    let total_pixels = width * height;
-    let processed_pixels = Arc::new(AtomicUsize::new(0));
-    let start_time = Instant::now();
-    let last_report_time = Arc::new(std::sync::Mutex::new(Instant::now()));
+    if iterations < params.max_iterations && params.cycles_palette() {
+        let count = params.cycled_count(iterations as f64);
+        return match palette {
+            Some(palette) => color_from_smooth_iterations_with_palette(count, params.max_iterations, palette),
+            None => color_from_smooth_iterations(count, params.max_iterations),
+        };
+    }
+    match palette {
+        Some(palette) => color_from_iterations_with_palette(iterations, params.max_iterations, palette),
+        None => color_from_iterations(iterations, params.max_iterations),
+    }
+}
 
-    // Print initial progress
-    println!("Rendering fractal: 0% (0/{}) - Started at {:?}. Using {} threads.",
-             total_pixels, chrono::Local::now().format("%H:%M:%S"), rayon::current_num_threads());
+/// Color a point inside the set by `params.interior_coloring`
+///
+/// # Returns
+///
+/// `None` when the point escaped or is colored with the solid inside color
+fn color_for_interior(result: &IterationResult, params: &FractalParams, palette: Option<&[ColorStop]>) -> Option<image::Rgba<u8>> {
+    if result.iterations < params.max_iterations {
+        return None;
+    }
+    let position = params.interior_coloring.position(result, params.bailout)?;
+    if let (InteriorColoring::Angle, Some(wheel)) = (params.interior_coloring, &params.phase_wheel) {
+        let [r, g, b] = wheel.color(position, 1.0);
+        return Some(image::Rgba([r, g, b, 255]));
+    }
+    Some(match palette {
+        Some(palette) => interpolate_color_from_palette(position, palette),
+        // The built-in gradient is black at both ends, so keep to its colored middle
+        None => color_from_smooth_iterations((0.1 + 0.8 * position) * params.max_iterations as f64, params.max_iterations),
+    })
+}
 
-    // Create a vector of (x, y) coordinates to process in parallel
-    let coords: Vec<(u32, u32)> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).collect();
+/// Color an iteration result, smoothly when `params.smooth_coloring` is set
+///
+/// Points inside the set are colored by `params.interior_coloring`; those it leaves solid, and
+/// the background when `params.outside_background` is set, are colored as by
+/// `color_for_iterations`.
+///
+/// # Arguments
+///
+/// * `result` - Outcome of iterating the point
+/// * `degree` - Degree of the formula, from `escape_degree(&params.formula)`
+/// * `params` - Fractal parameters with the iteration limit and color overrides
+/// * `palette` - Optional color palette
+///
+/// # Returns
+///
+/// The RGBA color of the pixel
+pub fn color_for_result(result: &IterationResult, degree: f64, params: &FractalParams, palette: Option<&[ColorStop]>) -> image::Rgba<u8> {
+    if let Some(color) = color_for_interior(result, params, palette) {
+        return color;
+    }
+    let iterations = result.iterations;
+    if !params.smooth_coloring || iterations >= params.max_iterations || (iterations == 0 && params.outside_background.is_some()) {
+        return color_for_iterations(iterations, params, palette);
+    }
 
-    // Process pixels in parallel
-    let results: Vec<((u32, u32), image::Rgba<u8>)> = coords
-        .into_par_iter()
-        .map(|(x, y)| {
-            let c = pixel_to_complex(x, y, width, height, params.bounds);
-            let iterations = iteration_func(c, params);
+    let smooth = params.cycled_count(result.smooth_iterations(degree));
+    match palette {
+        Some(palette) => color_from_smooth_iterations_with_palette(smooth, params.max_iterations, palette),
+        None => color_from_smooth_iterations(smooth, params.max_iterations),
+    }
+}
+
+use rayon::prelude::*;
+
+/// Distribution of escape counts over an image, for histogram (equalized) coloring
+///
+/// Mapping `iterations / max_iterations` to the palette leaves most of it unused when nearly
+/// every pixel escapes within a few iterations. Histogram coloring instead maps each count to the
+/// fraction of escaped pixels that escaped no later, so every part of the palette covers about
+/// the same number of pixels.
+#[derive(Debug, Clone)]
+pub struct IterationHistogram {
+    /// `cumulative[n]` is the fraction of escaped pixels with at most `n` iterations
+    cumulative: Vec<f64>,
+}
+
+impl IterationHistogram {
+    /// Count the escaped results of an image
+    ///
+    /// # Arguments
+    ///
+    /// * `results` - Iteration results of every pixel
+    /// * `max_iterations` - Iteration limit; results that reach it are inside the set and not counted
+    pub fn from_results(results: &[IterationResult], max_iterations: u32) -> Self {
+        let mut counts = vec![0u64; max_iterations as usize];
+        for result in results {
+            if result.iterations < max_iterations {
+                counts[result.iterations as usize] += 1;
+            }
+        }
+        Self::from_counts(&counts)
+    }
 
-            // Choose coloring method based on whether palette is provided
-            let color = if let Some(palette) = color_palette {
-                color_from_iterations_with_palette(iterations, params.max_iterations, palette)
+    /// Build the histogram from counts already taken, for images too large to hold at once
+    ///
+    /// # Arguments
+    ///
+    /// * `counts` - `counts[n]` is the number of escaped pixels with exactly `n` iterations
+    pub(crate) fn from_counts(counts: &[u64]) -> Self {
+        let escaped: u64 = counts.iter().sum();
+        let mut running = 0;
+        let cumulative = counts
+            .iter()
+            .map(|&count| {
+                running += count;
+                if escaped == 0 { 0.0 } else { running as f64 / escaped as f64 }
+            })
+            .collect();
+        Self { cumulative }
+    }
+
+    /// Position in the palette, between 0 and 1, of a (possibly fractional) iteration count
+    ///
+    /// Fractional counts interpolate between the neighbouring integer counts, so smooth
+    /// coloring stays smooth.
+    pub fn equalize(&self, iterations: f64) -> f64 {
+        let Some(&last) = self.cumulative.last() else { return 0.0 };
+        let at = |n: f64| -> f64 {
+            if n < 0.0 {
+                0.0
             } else {
-                color_from_iterations(iterations, params.max_iterations)
-            };
+                self.cumulative.get(n as usize).copied().unwrap_or(last)
+            }
+        };
+        let floor = iterations.floor();
+        let fraction = iterations - floor;
+        at(floor) * (1.0 - fraction) + at(floor + 1.0) * fraction
+    }
+}
+
+/// Color the iteration results of a whole image, following `params.smooth_coloring` and
+/// `params.histogram_coloring`
+///
+/// # Arguments
+///
+/// * `results` - Iteration results of every pixel
+/// * `params` - Fractal parameters with the formula, iteration limit and color options
+/// * `palette` - Optional color palette
+///
+/// # Returns
+///
+/// The pixel colors, in the order of `results`
+pub fn color_results(results: &[IterationResult], params: &FractalParams, palette: Option<&[ColorStop]>) -> Vec<image::Rgba<u8>> {
+    let degree = escape_degree(&params.formula);
+    let histogram = params
+        .histogram_coloring
+        .then(|| IterationHistogram::from_results(results, params.max_iterations));
+    results
+        .par_iter()
+        .map(|result| color_for_result_equalized(result, degree, params, palette, histogram.as_ref()))
+        .collect()
+}
+
+/// `color_for_result`, with escaped points equalized by `histogram` when one is given
+pub(crate) fn color_for_result_equalized(
+    result: &IterationResult,
+    degree: f64,
+    params: &FractalParams,
+    palette: Option<&[ColorStop]>,
+    histogram: Option<&IterationHistogram>,
+) -> image::Rgba<u8> {
+    let Some(histogram) = histogram else {
+        return color_for_result(result, degree, params, palette);
+    };
+    let iterations = result.iterations;
+    let max_iterations = params.max_iterations;
+    if iterations >= max_iterations || (iterations == 0 && params.outside_background.is_some()) {
+        return color_for_result(result, degree, params, palette);
+    }
+    let count = if params.smooth_coloring { result.smooth_iterations(degree) } else { iterations as f64 };
+    // Scaled to the iteration limit so both palettes see the same range as without equalization
+    let equalized = params.cycled_count(histogram.equalize(count) * max_iterations as f64);
+    match palette {
+        Some(palette) => color_from_smooth_iterations_with_palette(equalized, max_iterations, palette),
+        None => color_from_smooth_iterations(equalized, max_iterations),
+    }
+}
+
+// Generate fractal image with time-based progress bar and ETA with color palette support
+pub fn generate_fractal_image<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    iteration_func: F,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> u32 + Sync + Copy,
+{
+    // Without the escaped value, smooth coloring falls back to the integer counts
+    let result_func = move |c, p: &FractalParams| {
+        let iterations = iteration_func(c, p);
+        IterationResult { iterations, escaped: iterations < p.max_iterations, final_z: Complex::new(0.0, 0.0), period: None, multiplier: None }
+    };
+    generate_fractal_image_from_results(width, height, params, result_func, color_palette)
+}
+
+/// `generate_fractal_image` for iteration functions that return the escaped value as well,
+/// such as `mandelbrot_result_compiled`, so that `params.smooth_coloring` can take effect
+pub fn generate_fractal_image_from_results<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    iteration_func: F,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy,
+{
+    generate_fractal_image_and_results(width, height, params, iteration_func, color_palette).0
+}
+
+/// `generate_fractal_image_from_results`, also returning the iteration result of every pixel
+///
+/// Outputs derived from the render, such as interior/exterior layers (see
+/// `output::split_layers`), can use the results without iterating the image again.
+///
+/// # Returns
+///
+/// The image and the result at the center of each pixel, in row-major order
+pub fn generate_fractal_image_and_results<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    iteration_func: F,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> (image::ImageBuffer<image::Rgba<u8>, Vec<u8>>, Vec<IterationResult>)
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy,
+{
+    use std::time::{Duration, Instant};
+
+    // Initialize progress tracking
+    let total_pixels = width * height;
+    let processed_pixels = Arc::new(AtomicUsize::new(0));
+    let start_time = Instant::now();
+    let last_report_time = Arc::new(std::sync::Mutex::new(Instant::now()));
+
+    // Print initial progress
+    println!("Rendering fractal: 0% (0/{}) - Started at {:?}. Using {} threads.",
+             total_pixels, chrono::Local::now().format("%H:%M:%S"), rayon::current_num_threads());
+
+    // Create a vector of (x, y) coordinates to process in parallel
+    let coords: Vec<(u32, u32)> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).collect();
+    let telemetry = telemetry::RenderTelemetry::start("Rendering fractal", total_pixels as u64);
+
+    // Process pixels in parallel
+    let results: Vec<IterationResult> = coords
+        .into_par_iter()
+        .map(|(x, y)| {
+            if params.cancel.is_cancelled() {
+                return IterationResult::UNRENDERED;
+            }
+            let _task = telemetry.task();
+            let c = pixel_to_complex(x, y, width, height, params.bounds);
+            let result = iteration_func(c, params);
 
             // Update progress counter
             let current = processed_pixels.fetch_add(1, Ordering::SeqCst) + 1;
@@ -3407,14 +5262,15 @@ where
                 }
             }
 
-            ((x, y), color)
+            result
         })
         .collect();
 
-    // Put the results back into the image buffer
-    for ((x, y), color) in results {
-        imgbuf.put_pixel(x, y, color);
+    if params.cancel.is_cancelled() {
+        println!("Rendering fractal: cancelled after {:.1}s", start_time.elapsed().as_secs_f64());
+        return (partial_fractal_image(width, height, params, &results, color_palette), results);
     }
+    let imgbuf = finish_fractal_image(width, height, params, &results, iteration_func, color_palette);
 
     // Final progress report
     let elapsed = start_time.elapsed();
@@ -3423,8 +5279,173 @@ where
         total_pixels, total_pixels, elapsed.as_secs_f64()
     );
 
+    (imgbuf, results)
+}
+
+/// The image of a cancelled render: the pixels iterated before the cancel, colored without
+/// anti-aliasing or post-processing, and transparent pixels for the `UNRENDERED` rest
+pub(crate) fn partial_fractal_image(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    results: &[IterationResult],
+    color_palette: Option<&Vec<ColorStop>>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+    let colors = color_results(results, params, color_palette.map(Vec::as_slice));
+    let mut imgbuf = image::ImageBuffer::new(width, height);
+    for ((pixel, color), result) in imgbuf.pixels_mut().zip(colors).zip(results) {
+        *pixel = if *result == IterationResult::UNRENDERED { Rgba([0, 0, 0, 0]) } else { color };
+    }
+    imgbuf
+}
+
+/// Color iteration results into an image, anti-aliasing and post-processing as `params` asks
+///
+/// `iteration_func` iterates the extra samples anti-aliasing takes.
+pub(crate) fn finish_fractal_image<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    results: &[IterationResult],
+    iteration_func: F,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy,
+{
+    let mut imgbuf = image::ImageBuffer::new(width, height);
+
+    // Color once every pixel is known, since histogram coloring depends on the whole image
+    let palette = color_palette.map(|p| p.as_slice());
+    if let Some(pipeline) = &params.coloring_pipeline {
+        return finish_pipeline_image(width, height, params, pipeline, results, iteration_func, palette);
+    }
+    let mut colors = color_results(results, params, palette);
+    if params.antialiasing != AntiAliasing::None {
+        let degree = escape_degree(&params.formula);
+        let histogram = params
+            .histogram_coloring
+            .then(|| IterationHistogram::from_results(results, params.max_iterations));
+        let refined = antialias::refine_colors(&mut colors, width, height, params.bounds, &params.antialiasing, |c| {
+            color_for_result_equalized(&iteration_func(c, params), degree, params, palette, histogram.as_ref())
+        });
+        println!("Anti-aliasing: resampled {} of {} pixels", refined, width * height);
+    }
+    for (pixel, color) in imgbuf.pixels_mut().zip(colors) {
+        *pixel = color;
+    }
+    postfx::apply_post_effects(&mut imgbuf, &params.post_effects);
+    imgbuf
+}
+
+/// `finish_fractal_image` with the pixels colored by a coloring pipeline
+fn finish_pipeline_image<F>(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    pipeline: &coloring::ColoringPipeline,
+    results: &[IterationResult],
+    iteration_func: F,
+    palette: Option<&[ColorStop]>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>>
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy,
+{
+    let pixel_size = ((params.bounds[1] - params.bounds[0]) / width.max(1) as f64).abs();
+    let colorer = pipeline.colorer(params, palette, results, pixel_size);
+    let mut colors: Vec<image::Rgba<u8>> = results
+        .par_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let (x, y) = ((index % width as usize) as u32, (index / width as usize) as u32);
+            colorer.color(pixel_to_complex(x, y, width, height, params.bounds), result)
+        })
+        .collect();
+    if params.antialiasing != AntiAliasing::None {
+        let refined = antialias::refine_colors(&mut colors, width, height, params.bounds, &params.antialiasing, |c| {
+            colorer.color(c, &iteration_func(c, params))
+        });
+        println!("Anti-aliasing: resampled {} of {} pixels", refined, width * height);
+    }
+    let mut imgbuf = image::ImageBuffer::new(width, height);
+    for (pixel, color) in imgbuf.pixels_mut().zip(colors) {
+        *pixel = color;
+    }
+    postfx::apply_post_effects(&mut imgbuf, &params.post_effects);
     imgbuf
 }
+
+/// An image with the iteration result of each of its pixels, in row-major order
+pub type RenderedImage = (image::ImageBuffer<image::Rgba<u8>, Vec<u8>>, Vec<IterationResult>);
+
+/// Render `params.formula` Mandelbrot- or Julia-style, iterating recognized polynomial formulas
+/// several pixels at a time
+///
+/// The same image as `generate_fractal_image_and_results` with `mandelbrot_result_compiled` or
+/// `julia_result_compiled`. Formulas with a lane kernel (see `iteration::simd`) are iterated a
+/// row at a time through it; all others, and renders whose interior coloring needs the detected
+/// cycles, go through the per-pixel loop.
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `color_palette` - Optional color palette
+///
+/// # Returns
+///
+/// The image and the result at the center of each pixel, in row-major order
+pub fn generate_escape_time_image_and_results(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> (image::ImageBuffer<image::Rgba<u8>, Vec<u8>>, Vec<IterationResult>) {
+    // Parse the formula once rather than for every pixel and iteration
+    let formula = CompiledFormula::new(&params.formula);
+    let iterate = |point, p: &FractalParams| {
+        if julia {
+            julia_result_compiled(point, p, &formula)
+        } else {
+            mandelbrot_result_compiled(point, p, &formula)
+        }
+    };
+    let Some(lanes) = iteration::SimdFormula::for_image(params) else {
+        return generate_fractal_image_and_results(width, height, params, iterate, color_palette);
+    };
+
+    let start_time = std::time::Instant::now();
+    println!(
+        "Rendering fractal: {} pixels, {} per step. Started at {:?}. Using {} threads.",
+        width * height,
+        iteration::simd::LANES,
+        chrono::Local::now().format("%H:%M:%S"),
+        rayon::current_num_threads()
+    );
+    let telemetry = telemetry::RenderTelemetry::start("Rendering fractal rows", height as u64);
+    let results: Vec<IterationResult> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            if params.cancel.is_cancelled() {
+                return vec![IterationResult::UNRENDERED; width as usize];
+            }
+            let _task = telemetry.task();
+            let row: Vec<Complex<f64>> = (0..width).map(|x| pixel_to_complex(x, y, width, height, params.bounds)).collect();
+            iteration::simd::iterate_points(lanes, &row, params, julia)
+        })
+        .collect();
+
+    if params.cancel.is_cancelled() {
+        println!("Rendering fractal: cancelled after {:.1}s", start_time.elapsed().as_secs_f64());
+        return (partial_fractal_image(width, height, params, &results, color_palette), results);
+    }
+    let imgbuf = finish_fractal_image(width, height, params, &results, iterate, color_palette);
+    println!("Rendering fractal: 100%, Completed in {:.1}s", start_time.elapsed().as_secs_f64());
+    (imgbuf, results)
+}
 /// Trace the orbit of a point in the Mandelbrot set for debugging purposes
 pub fn trace_orbit_mandelbrot(c: Complex<f64>, params: &FractalParams) {
     println!("Tracing orbit for Mandelbrot with:");
@@ -3594,7 +5615,7 @@ pub fn trace_orbit_dca(z: Complex<f64>, formula: &str, custom_i: Complex<f64>) {
     println!();
 
     let mut z = z;
-    let mut iter = 0;
+    let iter = 0;
 
     // For domain coloring, we just evaluate the function once
     println!("  Iteration {}: z = ({:.6}, {:.6}), |z| = {:.6}", 
@@ -3777,7 +5798,7 @@ pub fn generate_mandelbrot_domain_color_image(
                 let final_value = mandelbrot_final_value(c, &params_arc, no_bailout);
                 
                 // Map the complex value to a color using domain coloring
-                let color = complex_to_domain_color(final_value, color_palette);
+                let color = complex_to_domain_color(final_value, color_palette, params_arc.phase_wheel.as_ref());
                 row.push(color);
             }
             row
@@ -3797,6 +5818,72 @@ pub fn generate_mandelbrot_domain_color_image(
     ImageBuffer::from_raw(width, height, pixel_bytes).unwrap()
 }
 
+/// Generate a Mandelbrot or Julia image colored from derivatives carried along each orbit
+///
+/// The derivative of z with respect to the pixel comes from iterating the formula on dual
+/// numbers (see `analysis::distance`), so any formula the parser accepts can be shaded by its
+/// distance estimate or lit as relief.
+///
+/// # Arguments
+///
+/// * `width` - Width of the output image in pixels
+/// * `height` - Height of the output image in pixels
+/// * `params` - Fractal parameters including bounds, max_iterations, bailout and formula
+/// * `julia` - If true, iterate each pixel as z with `params.spawn` as c (a Julia set); otherwise
+///   iterate 0 with each pixel as c (a Mandelbrot set)
+/// * `coloring` - Distance estimate or slope shading
+/// * `color_palette` - Optional palette, indexed by the shade; grayscale without one
+///
+/// # Returns
+///
+/// An RGBA image buffer, with bounded points colored as in escape-time renders
+pub fn generate_derivative_image(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    coloring: analysis::DerivativeColoring,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    use rayon::prelude::*;
+
+    let formula = CompiledFormula::new(&params.formula);
+    let bounds = params.bounds;
+    let pixel_size = ((bounds[1] - bounds[0]) / width.max(1) as f64).abs();
+    let inside = color_for_iterations(params.max_iterations, params, color_palette.map(|palette| palette.as_slice()));
+
+    let pixels: Vec<Rgba<u8>> = (0..height)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            let formula = &formula;
+            (0..width).map(move |x| {
+                let point = pixel_to_complex(x, y, width, height, bounds);
+                let orbit = if julia {
+                    analysis::julia_derivative(formula, point, params.spawn, params)
+                } else {
+                    analysis::mandelbrot_derivative(formula, point, params)
+                };
+                let shade = match coloring {
+                    // Distances below a pixel fade to black at the boundary
+                    analysis::DerivativeColoring::Distance => orbit.distance().map(|d| (d / pixel_size).clamp(0.0, 1.0).sqrt()),
+                    analysis::DerivativeColoring::Slope { light_angle, height } => orbit.slope(light_angle, height),
+                };
+                match (shade, color_palette) {
+                    (None, _) => inside,
+                    (Some(shade), Some(palette)) => interpolate_color_from_palette(shade, palette),
+                    (Some(shade), None) => {
+                        let gray = (shade * 255.0).round() as u8;
+                        Rgba([gray, gray, gray, 255])
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let pixel_bytes: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.0).collect();
+    ImageBuffer::from_raw(width, height, pixel_bytes).unwrap()
+}
+
 /// Calculate the final complex value for a point in the Mandelbrot set for domain coloring
 /// 
 /// This function iterates the Mandelbrot formula but returns the final complex value instead of iteration count
@@ -3843,11 +5930,12 @@ pub fn mandelbrot_final_value(c: Complex<f64>, params: &FractalParams, no_bailou
 /// 
 /// * `z` - The complex number to convert to a color
 /// * `color_palette` - Optional color palette to use for coloring
+/// * `phase_wheel` - Cyclic palette for the hue when no color palette is given (HSV when `None`)
 /// 
 /// # Returns
 /// 
 /// An RGBA color representing the complex number
-fn complex_to_domain_color(z: Complex<f64>, color_palette: Option<&Vec<ColorStop>>) -> Rgba<u8> {
+fn complex_to_domain_color(z: Complex<f64>, color_palette: Option<&Vec<ColorStop>>, phase_wheel: Option<&palette::PhaseWheel>) -> Rgba<u8> {
     if z.re.is_nan() || z.im.is_nan() || z.re.is_infinite() || z.im.is_infinite() {
         // For invalid values, return black
         return Rgba([0, 0, 0, 255]);
@@ -3878,8 +5966,8 @@ fn complex_to_domain_color(z: Complex<f64>, color_palette: Option<&Vec<ColorStop
         };
         interpolate_color_from_palette(normalized_mag, palette)
     } else {
-        // Convert HSV to RGB using the hue and intensity
-        let rgb = hsv_to_rgb(hue, 1.0, intensity);
+        // Color the hue from the phase wheel, scaled by the intensity
+        let rgb = phase_wheel.unwrap_or(&palette::PhaseWheel::Hsv).color(hue, intensity);
         Rgba([rgb[0], rgb[1], rgb[2], 255])
     }
 }
@@ -3955,1367 +6043,3 @@ pub fn parse_complex_number(s: &str) -> Result<Complex<f64>, String> {
     
     Err(format!("Unable to parse complex number: {}", s))
 }
-
-/// More precise complex power function with better handling of edge cases
-fn complex_pow_precise(z: Complex<f64>, w: Complex<f64>) -> Complex<f64> {
-    // Handle special cases with higher precision
-    if z.norm_sqr() < 1e-10 {
-        // z is essentially zero
-        if w.re > 0.0 {
-            // 0^w where Re(w) > 0 should be 0
-            Complex::new(0.0, 0.0)
-        } else if w.re == 0.0 && w.im == 0.0 {
-            // 0^0 is typically defined as 1
-            Complex::new(1.0, 0.0)
-        } else {
-            // For other cases involving zero base, return a safe value
-            Complex::new(0.0, 0.0)
-        }
-    } else if w.norm_sqr() < 1e-10 {
-        // w is essentially zero, so z^w = z^0 = 1
-        Complex::new(1.0, 0.0)
-    } else {
-        // Standard complex exponentiation: z^w = exp(w * ln(z))
-        // Use higher precision for the intermediate calculations
-        let ln_z = complex_ln_precise(z);
-        let w_ln_z = w * ln_z;
-        complex_exp_precise(w_ln_z)
-    }
-}
-
-/// More precise complex natural logarithm with better handling of edge cases
-fn complex_ln_precise(z: Complex<f64>) -> Complex<f64> {
-    let magnitude = z.norm();
-    let argument = z.arg();
-    
-    // Use higher precision for the logarithm calculation
-    if magnitude <= 0.0 {
-        // For zero or negative magnitudes, return a safe value
-        Complex::new(f64::NEG_INFINITY, argument)
-    } else {
-        Complex::new(magnitude.ln(), argument)
-    }
-}
-
-/// More precise complex exponential with better handling of edge cases
-fn complex_exp_precise(z: Complex<f64>) -> Complex<f64> {
-    // exp(a + bi) = exp(a) * (cos(b) + i*sin(b))
-    let exp_re = z.re.exp();
-    
-    // Check for overflow in the real part
-    if exp_re.is_infinite() {
-        // Return a large but finite value to avoid infinities
-        let safe_exp = 1e100;
-        Complex::new(safe_exp * z.im.cos(), safe_exp * z.im.sin())
-    } else {
-        Complex::new(exp_re * z.im.cos(), exp_re * z.im.sin())
-    }
-}
-
-/// Enhanced tetration function with better precision handling
-fn enhanced_tetration(z: Complex<f64>, height: Complex<f64>) -> Complex<f64> {
-    // For integer heights, use iterative approach with overflow checking
-    if height.im.abs() < 1e-10 && height.re.fract() == 0.0 && height.re > 0.0 && height.re <= 5.0 {
-        let n = height.re as u32;
-        match n {
-            1 => z,  // z^^1 = z
-            2 => {
-                // z^^2 = z^z
-                let result = complex_pow_precise(z, z);
-                // Check for overflow and apply conservative scaling
-                if result.norm_sqr() > 1e10 {
-                    let scale_factor = 1e5 / result.norm().max(1e-10);
-                    Complex::new(result.re * scale_factor, result.im * scale_factor)
-                } else {
-                    result
-                }
-            },
-            3 => {
-                // z^^3 = z^(z^z)
-                let z_pow_z = complex_pow_precise(z, z);
-                if z_pow_z.norm_sqr() > 1e10 {
-                    Complex::new(1e5, 1e5)
-                } else {
-                    let result = complex_pow_precise(z, z_pow_z);
-                    if result.norm_sqr() > 1e10 {
-                        let scale_factor = 1e5 / result.norm().max(1e-10);
-                        Complex::new(result.re * scale_factor, result.im * scale_factor)
-                    } else {
-                        result
-                    }
-                }
-            },
-            _ => {
-                // For higher values, return a safe value to avoid immediate escape
-                Complex::new(1.0, 0.0)
-            }
-        }
-    } else {
-        // For non-integer heights, return a safe value to avoid black images
-        Complex::new(1.0, 0.0)
-    }
-}
-
-use rug::{Complex as RugComplex, Float as RugFloat};
-
-/// Parameters for arbitrary precision fractal generation
-#[derive(Debug, Clone)]
-pub struct ArbitraryPrecisionParams {
-    pub bounds: [f64; 4],           // [x_min, x_max, y_min, y_max]
-    pub max_iterations: u32,
-    pub spawn: Complex<f64>,        // For Julia sets
-    pub bailout: f64,
-    pub formula: String,
-    pub i_sqrt_value: Complex<f64>, // Custom imaginary unit (i = sqrt of this value), defaults to 0+1i
-    pub precision_bits: u32,        // Precision in bits for arbitrary precision mode (0 = disabled)
-}
-
-impl ArbitraryPrecisionParams {
-    pub fn new(bounds: [f64; 4], max_iterations: u32, spawn: [f64; 2], bailout: f64, formula: String, precision_bits: u32) -> Self {
-        Self {
-            bounds,
-            max_iterations,
-            spawn: Complex::new(spawn[0], spawn[1]),
-            bailout,
-            formula,
-            i_sqrt_value: Complex::new(0.0, 1.0), // Default to standard i = sqrt(-1)
-            precision_bits,  // Precision in bits for arbitrary precision mode
-        }
-    }
-    
-    /// Convert to standard FractalParams
-    pub fn to_standard(&self) -> FractalParams {
-        FractalParams {
-            bounds: self.bounds,
-            max_iterations: self.max_iterations,
-            spawn: self.spawn,
-            bailout: self.bailout,
-            formula: self.formula.clone(),
-            i_sqrt_value: self.i_sqrt_value,
-        }
-    }
-}
-
-/// Calculate the number of iterations for a point in a Mandelbrot set with arbitrary precision arithmetic
-pub fn mandelbrot_iterations_arbitrary_precision(c: Complex<f64>, params: &ArbitraryPrecisionParams) -> u32 {
-    if params.precision_bits == 0 {
-        // Use standard precision
-        return mandelbrot_iterations(c, &params.to_standard());
-    }
-    
-    // Use arbitrary precision arithmetic
-    let c_ap = RugComplex::with_val(params.precision_bits, c.re, c.im);
-    let i_squared_ap = RugComplex::with_val(params.precision_bits, params.i_sqrt_value.re, params.i_sqrt_value.im);
-    let mut z = RugComplex::with_val(params.precision_bits, 0.0, 0.0);
-    let bailout_ap = RugFloat::with_val(params.precision_bits, params.bailout);
-    let mut iter = 0;
-
-    while iter < params.max_iterations {
-        // Apply the formula with arbitrary precision arithmetic
-        z = match evaluate_formula_arbitrary_precision(&params.formula, &z, &c_ap, &i_squared_ap, params.precision_bits) {
-            Ok(result) => result,
-            Err(_e) => {
-                // Fallback to standard formula with arbitrary precision: z^2 + c
-                let z_sq = rug_complex_multiply(&z, &z, &i_squared_ap, params.precision_bits);
-                rug_complex_add(&z_sq, &c_ap, params.precision_bits)
-            },
-        };
-
-        // Check if the point escapes using arbitrary precision norm
-        let norm_sqr = rug_complex_norm_sqr(&z, params.precision_bits);
-        if norm_sqr > &bailout_ap * &bailout_ap {
-            break;
-        }
-        iter += 1;
-    }
-
-    iter
-}
-
-/// Evaluate a formula with arbitrary precision arithmetic
-fn evaluate_formula_arbitrary_precision(formula: &str, z: &RugComplex, param: &RugComplex, i_squared: &RugComplex, precision: u32) -> Result<RugComplex, String> {
-    let formula_lower = formula.trim().to_lowercase();
-    
-    match formula_lower.as_str() {
-        "z^2 + c" => {
-            let z_sq = rug_complex_multiply(z, z, i_squared, precision);
-            Ok(rug_complex_add(&z_sq, param, precision))
-        },
-        "z^3 + c" => {
-            let z_sq = rug_complex_multiply(z, z, i_squared, precision);
-            let z_cu = rug_complex_multiply(&z_sq, z, i_squared, precision);
-            Ok(rug_complex_add(&z_cu, param, precision))
-        },
-        "z^z + c" => {
-            // For z^z with arbitrary precision, we use the formula z^z = exp(z * ln(z))
-            let ln_z = rug_complex_ln(z, precision);
-            let z_ln_z = rug_complex_multiply(z, &ln_z, i_squared, precision);
-            let z_pow_z = rug_complex_exp(&z_ln_z, precision);
-            
-            // Apply conservative scaling to prevent immediate escape
-            let result = rug_complex_add(&z_pow_z, param, precision);
-            let result_norm = rug_complex_norm(&result, precision);
-            
-            let max_norm = RugFloat::with_val(precision, 2.0);
-            if result_norm > max_norm {
-                let scale_factor = &max_norm / &result_norm;
-                let scaled_real = result.real() * &scale_factor;
-                let scaled_imag = result.imag() * &scale_factor;
-                Ok(RugComplex::with_val(precision, scaled_real, scaled_imag))
-            } else {
-                Ok(result)
-            }
-        },
-        "z^^z + c" => {
-            // For tetration z^^z with arbitrary precision
-            // This is extremely complex to compute directly, so we'll use a conservative approach
-            let z_real = z.real().to_f64();
-            let z_imag = z.imag().to_f64();
-            
-            if z_imag.abs() < 1e-10 && z_real.fract() == 0.0 && z_real > 0.0 && z_real <= 5.0 {
-                // Integer tetration for small values - most stable for fractals
-                let n = z_real as u32;
-                let result = match n {
-                    1 => z.clone(),
-                    2 => {
-                        // z^^2 = z^z with arbitrary precision
-                        let ln_z = rug_complex_ln(z, precision);
-                        let z_ln_z = rug_complex_multiply(z, &ln_z, i_squared, precision);
-                        rug_complex_exp(&z_ln_z, precision)
-                    },
-                    _ => {
-                        // For higher values, return a safe value to avoid immediate escape
-                        RugComplex::with_val(precision, 1.0, 0.0)
-                    }
-                };
-                Ok(rug_complex_add(&result, param, precision))
-            } else {
-                // For non-integer or complex z, return a safe value to avoid black images
-                Ok(rug_complex_add(&RugComplex::with_val(precision, 1.0, 0.0), param, precision))
-            }
-        },
-        _ => {
-            // For more complex expressions, try to parse them with arbitrary precision
-            // This would require implementing a full arbitrary-precision expression parser
-            // For now, we'll fall back to standard precision
-            let z_std = Complex::new(z.real().to_f64(), z.imag().to_f64());
-            let param_std = Complex::new(param.real().to_f64(), param.imag().to_f64());
-            
-            match MathEvaluator::evaluate_formula_with_param_and_custom_i(&params.formula, z_std, param_std, params.i_sqrt_value) {
-                Ok(result) => {
-                    Ok(RugComplex::with_val(precision, result.re, result.im))
-                },
-                Err(e) => Err(e),
-            }
-        }
-    }
-}
-
-/// Helper function for arbitrary precision complex multiplication with custom imaginary unit
-fn rug_complex_multiply(z1: &RugComplex, z2: &RugComplex, i_squared: &RugComplex, precision: u32) -> RugComplex {
-    // (a + bi) * (c + di) = ac + (ad + bc)*i + bd*i²
-    // where i² is the custom value
-    let a = z1.real();
-    let b = z1.imag();
-    let c = z2.real();
-    let d = z2.imag();
-    
-    let ac = a * c;
-    let ad = a * d;
-    let bc = b * c;
-    let bd = b * d;
-    
-    // bd * i² where i² is our custom value
-    let bd_i_squared = &bd * i_squared;
-    
-    // Real part: ac + Re(bd * i²)
-    let real_part = &ac + bd_i_squared.real();
-    // Imaginary part: (ad + bc) + Im(bd * i²)
-    let imag_part = (&ad + &bc) + bd_i_squared.imag();
-    
-    RugComplex::with_val(precision, real_part, imag_part)
-}
-
-/// Helper function for arbitrary precision complex addition
-fn rug_complex_add(z1: &RugComplex, z2: &RugComplex, precision: u32) -> RugComplex {
-    RugComplex::with_val(precision, z1.real() + z2.real(), z1.imag() + z2.imag())
-}
-
-/// Helper function for arbitrary precision complex norm squared
-fn rug_complex_norm_sqr(z: &RugComplex, precision: u32) -> RugFloat {
-    let re = z.real();
-    let im = z.imag();
-    &re * &re + &im * &im
-}
-
-/// Helper function for arbitrary precision complex norm
-fn rug_complex_norm(z: &RugComplex, precision: u32) -> RugFloat {
-    rug_complex_norm_sqr(z, precision).sqrt()
-}
-
-/// Helper function for arbitrary precision complex natural logarithm
-fn rug_complex_ln(z: &RugComplex, precision: u32) -> RugComplex {
-    let magnitude = rug_complex_norm(z, precision).ln();
-    let argument = rug_complex_arg(z, precision);
-    RugComplex::with_val(precision, magnitude, argument)
-}
-
-/// Helper function for arbitrary precision complex argument (angle)
-fn rug_complex_arg(z: &RugComplex, precision: u32) -> RugFloat {
-    z.imag().atan2(z.real())
-}
-
-/// Helper function for arbitrary precision complex exponential
-fn rug_complex_exp(z: &RugComplex, precision: u32) -> RugComplex {
-    // exp(a + bi) = exp(a) * (cos(b) + i*sin(b))
-    let exp_re = z.real().exp();
-    let cos_im = z.imag().cos();
-    let sin_im = z.imag().sin();
-    
-    let real_part = &exp_re * &cos_im;
-    let imag_part = &exp_re * &sin_im;
-    
-    RugComplex::with_val(precision, real_part, imag_part)
-}
-
-/// Test arbitrary precision with various complex functions
-pub fn test_arbitrary_precision() {
-    println!("Testing arbitrary precision with various complex functions...");
-    
-    // Test with different precision levels
-    for prec in [32, 64, 128, 256, 512, 1024] {
-        println!("
-Testing with {} bits of precision:", prec);
-        
-        // Test basic operations
-        let z1 = RugComplex::with_val(prec, 1.5, 0.5);
-        let z2 = RugComplex::with_val(prec, 2.0, -1.0);
-        let i_squared = RugComplex::with_val(prec, -1.0, 0.0); // Standard complex
-        
-        let result = rug_complex_multiply(&z1, &z2, &i_squared, prec);
-        println!("  (1.5 + 0.5i) * (2.0 - 1.0i) = ({:.10}, {:.10}i)", 
-                 result.real().to_f64(), result.imag().to_f64());
-        
-        // Test complex power
-        let z = RugComplex::with_val(prec, 1.5, 0.5);
-        let w = RugComplex::with_val(prec, 2.0, 0.3);
-        let ln_z = rug_complex_ln(&z, prec);
-        let z_ln_w = rug_complex_multiply(&ln_z, &w, &i_squared, prec);
-        let z_pow_w = rug_complex_exp(&z_ln_w, prec);
-        println!("  (1.5 + 0.5i)^(2.0 + 0.3i) = ({:.10}, {:.10}i)", 
-                 z_pow_w.real().to_f64(), z_pow_w.imag().to_f64());
-        
-        // Test tetration
-        let z_tet = RugComplex::with_val(prec, 1.5, 0.0); // Real number for tetration
-        if z_tet.imag().to_f64().abs() < 1e-10 && z_tet.real().to_f64().fract() == 0.0 && z_tet.real().to_f64() > 0.0 && z_tet.real().to_f64() <= 3.0 {
-            let n = z_tet.real().to_f64() as u32;
-            match n {
-                1 => println!("  1^^1 = 1 (trivial)"),
-                2 => {
-                    let z_sq = rug_complex_multiply(&z_tet, &z_tet, &i_squared, prec);
-                    println!("  2^^2 = 2^2 = ({:.10}, {:.10}i)", 
-                             z_sq.real().to_f64(), z_sq.imag().to_f64());
-                },
-                3 => {
-                    let z_sq = rug_complex_multiply(&z_tet, &z_tet, &i_squared, prec);
-                    let z_cu = rug_complex_multiply(&z_sq, &z_tet, &i_squared, prec);
-                    println!("  3^^3 = 3^(3^3) - would be astronomically large, returning safe value");
-                },
-                _ => println!("  Higher tetration values return safe values"),
-            }
-        }
-    }
-    
-    println!("
-Arbitrary precision testing completed!");
-}
-
-
-/// Generate a Mandelbrot set image with arbitrary precision arithmetic
-/// 
-/// This function generates a Mandelbrot set image using arbitrary precision arithmetic where the precision
-/// can be specified in bits. This enables more accurate computation of complex mathematical operations
-/// that might lose precision with standard f64 arithmetic.
-/// 
-/// # Arguments
-/// 
-/// * `width` - Width of the output image in pixels
-/// * `height` - Height of the output image in pixels
-/// * `params` - Fractal parameters including bounds, max_iterations, formula, and custom imaginary unit
-/// * `precision_bits` - Number of bits of precision to use for the calculations
-/// * `color_palette` - Optional color palette for coloring the image
-/// 
-/// # Returns
-/// 
-/// An RGBA image buffer representing the Mandelbrot set with arbitrary precision arithmetic
-pub fn generate_mandelbrot_image_arbitrary_precision(
-    width: u32,
-    height: u32,
-    params: &FractalParams,
-    precision_bits: u32,
-    color_palette: Option<&Vec<ColorStop>>
-) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    use rayon::prelude::*;
-    use std::sync::Arc;
-    
-    let img = ImageBuffer::new(width, height);
-    let params_arc = Arc::new(params.clone());
-    let bounds = params.bounds;
-    let color_palette_arc = color_palette.cloned().map(Arc::new);
-    let ap_params = ArbitraryPrecisionParams::new(
-        bounds,
-        params.max_iterations,
-        [params.spawn.re, params.spawn.im],
-        params.bailout,
-        params.formula.clone(),
-        precision_bits,
-    );
-    ap_params.i_sqrt_value = params.i_sqrt_value;
-    
-    // Calculate step sizes for mapping pixels to complex plane
-    let dx = (bounds[1] - bounds[0]) / width as f64;
-    let dy = (bounds[3] - bounds[2]) / height as f64;
-    
-    // Process rows in parallel
-    let rows: Vec<Vec<Rgba<u8>>> = (0..height)
-        .into_par_iter()
-        .map(|y| {
-            let mut row = Vec::with_capacity(width as usize);
-            for x in 0..width {
-                // Convert pixel coordinates to complex plane coordinates
-                let c = Complex::new(
-                    bounds[0] + x as f64 * dx,
-                    bounds[2] + y as f64 * dy,
-                );
-                
-                // Calculate the number of iterations for this point using arbitrary precision
-                let iterations = mandelbrot_iterations_arbitrary_precision(c, &ap_params);
-                
-                // Map the iteration count to a color
-                let color = if iterations == params_arc.max_iterations {
-                    // Inside the set - black
-                    Rgba([0, 0, 0, 255])
-                } else {
-                    // Outside the set - interpolate color based on iteration count
-                    if let Some(ref palette) = color_palette_arc {
-                        interpolate_color_from_palette(iterations as f64 / params_arc.max_iterations as f64, palette)
-                    } else {
-                        // Default coloring based on iteration count
-                        color_from_iterations(iterations, params_arc.max_iterations)
-                    }
-                };
-                row.push(color);
-            }
-            row
-        })
-        .collect();
-    
-    // Flatten the rows into a single vector
-    let pixels: Vec<Rgba<u8>> = rows.into_iter().flatten().collect();
-    
-    // Create the final image from the pixel data
-    ImageBuffer::from_vec(width, height, pixels).unwrap()
-}
-
-
-use rug::{Complex as RugComplex, Float as RugFloat};
-
-/// Efficient representation for very large numbers using scientific notation: significand * base^exponent
-/// For example: Googolplex = 1 * 10^(10^100) would be represented as LargeNumber { significand: 1.0, base: 10.0, exponent: LargeNumber { significand: 1.0, base: 10.0, exponent: LargeNumber { significand: 100.0, base: 10.0, exponent: LargeNumber::zero() } } }
-#[derive(Debug, Clone, PartialEq)]
-pub enum LargeNumber {
-    /// Standard floating point number for small values
-    Standard(f64),
-    /// Scientific notation: significand * base^exponent
-    Scientific { significand: f64, base: f64, exponent: Box<LargeNumber> },
-    /// Special values: infinity, NaN, zero
-    Special(SpecialValue),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum SpecialValue {
-    Infinity,
-    NegInfinity,
-    NaN,
-    Zero,
-}
-
-impl LargeNumber {
-    pub fn new_standard(value: f64) -> Self {
-        LargeNumber::new_standard(value)
-    }
-    
-    pub fn new_scientific(significand: f64, base: f64, exponent: LargeNumber) -> Self {
-        LargeNumber::new_scientific {
-            significand,
-            base,
-            exponent: Box::new(exponent),
-        }
-    }
-    
-    pub fn zero() -> Self {
-        LargeNumber::Special(SpecialValue::Zero)
-    }
-    
-    pub fn infinity() -> Self {
-        LargeNumber::Special(SpecialValue::Infinity)
-    }
-    
-    pub fn neg_infinity() -> Self {
-        LargeNumber::Special(SpecialValue::NegInfinity)
-    }
-    
-    pub fn nan() -> Self {
-        LargeNumber::Special(SpecialValue::NaN)
-    }
-    
-    /// Create a googol (10^100)
-    pub fn googol() -> Self {
-        LargeNumber::new_scientific(1.0, 10.0, LargeNumber::new_standard(100.0))
-    }
-    
-    /// Create a googolplex (10^googol)
-    pub fn googolplex() -> Self {
-        LargeNumber::new_scientific(1.0, 10.0, LargeNumber::googol())
-    }
-    
-    /// Convert to f64 if possible, otherwise return infinity
-    pub fn to_f64(&self) -> f64 {
-        match self {
-            LargeNumber::new_standard(val) => *val,
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                let exp_val = exponent.to_f64();
-                if exp_val > 300.0 {
-                    // Would overflow, return infinity
-                    f64::INFINITY
-                } else {
-                    significand * base.powf(exp_val)
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity => f64::INFINITY,
-                SpecialValue::NegInfinity => f64::NEG_INFINITY,
-                SpecialValue::NaN => f64::NAN,
-                SpecialValue::Zero => 0.0,
-            },
-        }
-    }
-    
-    /// Get the magnitude (absolute value) of the number
-    pub fn abs(&self) -> Self {
-        match self {
-            LargeNumber::new_standard(val) => LargeNumber::new_standard(val.abs()),
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                LargeNumber::new_scientific {
-                    significand: significand.abs(),
-                    base: *base,
-                    exponent: exponent.clone(),
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::NegInfinity => LargeNumber::Special(SpecialValue::Infinity),
-                _ => self.clone(),
-            },
-        }
-    }
-    
-    /// Check if the number is finite
-    pub fn is_finite(&self) -> bool {
-        match self {
-            LargeNumber::new_standard(val) => val.is_finite(),
-            LargeNumber::new_scientific { .. } => true, // Scientific notation represents finite values
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN => false,
-                SpecialValue::Zero => true,
-            },
-        }
-    }
-    
-    /// Check if the number is infinite
-    pub fn is_infinite(&self) -> bool {
-        match self {
-            LargeNumber::Special(special) => matches!(special, SpecialValue::Infinity | SpecialValue::NegInfinity),
-            _ => false,
-        }
-    }
-    
-    /// Add two large numbers
-    pub fn add(&self, other: &Self) -> Self {
-        match (self, other) {
-            (LargeNumber::new_standard(a), LargeNumber::new_standard(b)) => {
-                let result = a + b;
-                if result.is_infinite() {
-                    if result.is_sign_positive() {
-                        LargeNumber::infinity()
-                    } else {
-                        LargeNumber::neg_infinity()
-                    }
-                } else {
-                    LargeNumber::new_standard(result)
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Infinity, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Infinity) => LargeNumber::nan(),
-                    (SpecialValue::Infinity, _) | (_, SpecialValue::Infinity) => LargeNumber::infinity(),
-                    (SpecialValue::NegInfinity, _) | (_, SpecialValue::NegInfinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                    (SpecialValue::Zero, _) => other.clone(),
-                    (_, SpecialValue::Zero) => self.clone(),
-                }
-            },
-            (LargeNumber::Special(s), _) | (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN => self.clone(),
-                    SpecialValue::Zero => other.clone(),
-                }
-            },
-            _ => {
-                // For mixed representations, convert to f64 if possible
-                let a = self.to_f64();
-                let b = other.to_f64();
-                let result = a + b;
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Multiply two large numbers
-    pub fn multiply(&self, other: &Self) -> Self {
-        match (self, other) {
-            (LargeNumber::new_standard(a), LargeNumber::new_standard(b)) => {
-                let result = a * b;
-                if result.is_infinite() {
-                    if result.is_sign_positive() {
-                        LargeNumber::infinity()
-                    } else {
-                        LargeNumber::neg_infinity()
-                    }
-                } else if result.is_nan() {
-                    LargeNumber::nan()
-                } else {
-                    LargeNumber::new_standard(result)
-                }
-            },
-            (LargeNumber::new_scientific { significand: s1, base: b1, exponent: e1 }, 
-             LargeNumber::new_scientific { significand: s2, base: b2, exponent: e2 }) if (b1 - b2).abs() < 1e-10 => {
-                // Same base: multiply significands and add exponents
-                let new_significand = s1 * s2;
-                let new_exponent = e1.add(e2);
-                LargeNumber::new_scientific {
-                    significand: new_significand,
-                    base: *b1,
-                    exponent: Box::new(new_exponent),
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Zero, SpecialValue::Infinity) |
-                    (SpecialValue::Infinity, SpecialValue::Zero) |
-                    (SpecialValue::Zero, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Zero) => LargeNumber::nan(),
-                    (SpecialValue::Zero, _) | (_, SpecialValue::Zero) => LargeNumber::zero(),
-                    (SpecialValue::Infinity, SpecialValue::Infinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::NegInfinity) => LargeNumber::infinity(),
-                    (SpecialValue::Infinity, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Infinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::Infinity, _) | (_, SpecialValue::Infinity) => LargeNumber::infinity(),
-                    (SpecialValue::NegInfinity, _) | (_, SpecialValue::NegInfinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                }
-            },
-            (LargeNumber::Special(s), _) | (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::zero(),
-                    SpecialValue::Infinity | SpecialValue::NegInfinity => self.clone(),
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            _ => {
-                // For mixed representations, convert to f64 if possible
-                let a = self.to_f64();
-                let b = other.to_f64();
-                let result = a * b;
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Raise a large number to a power
-    pub fn pow(&self, exponent: &Self) -> Self {
-        match (self, exponent) {
-            (LargeNumber::new_standard(base_val), LargeNumber::new_standard(exp_val)) => {
-                let result = base_val.powf(*exp_val);
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            },
-            (LargeNumber::new_scientific { significand: s, base: b, exponent: e }, LargeNumber::new_standard(exp_val)) => {
-                // (s * b^e)^exp_val = s^exp_val * b^(e*exp_val)
-                let new_significand = s.powf(*exp_val);
-                let new_exponent = e.multiply(&LargeNumber::new_standard(*exp_val));
-                LargeNumber::new_scientific {
-                    significand: new_significand,
-                    base: *b,
-                    exponent: Box::new(new_exponent),
-                }
-            },
-            (LargeNumber::new_standard(base_val), LargeNumber::new_scientific { significand: s, base: b, exponent: e }) => {
-                // base_val^(s * b^e) - this is complex, convert to f64 if reasonable
-                if *base_val > 0.0 base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite()base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite() s.abs() < 100.0 base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite()base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite() b.abs() < 100.0 base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite()base_val > 0.0 && s.abs() < 100.0 && b.abs() < 100.0 && e.is_finite() e.is_finite() {
-                    let exp_as_f64 = e.to_f64();
-                    let effective_exp = s * b.powf(exp_as_f64);
-                    let result = base_val.powf(effective_exp);
-                    if result.is_finite() {
-                        LargeNumber::new_standard(result)
-                    } else if result.is_infinite() && result.is_sign_positive() {
-                        LargeNumber::infinity()
-                    } else if result.is_infinite() && result.is_sign_negative() {
-                        LargeNumber::neg_infinity()
-                    } else {
-                        LargeNumber::nan()
-                    }
-                } else {
-                    LargeNumber::infinity() // Too complex to compute safely
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Zero, SpecialValue::Infinity) => LargeNumber::nan(), // 0^inf
-                    (SpecialValue::Zero, SpecialValue::NegInfinity) => LargeNumber::infinity(), // 0^(-inf)
-                    (SpecialValue::Zero, _) if s2.is_finite() => LargeNumber::zero(), // 0^finite = 0
-                    (SpecialValue::One, SpecialValue::Infinity) | (SpecialValue::One, SpecialValue::NegInfinity) => LargeNumber::new_standard(1.0), // 1^inf = 1
-                    (SpecialValue::Infinity, SpecialValue::Zero) => LargeNumber::new_standard(1.0), // inf^0 = 1
-                    (SpecialValue::Infinity, _) if s2.is_finite() && s2.to_f64() > 0.0 => LargeNumber::infinity(), // inf^positive = inf
-                    (SpecialValue::Infinity, _) if s2.is_finite() && s2.to_f64() < 0.0 => LargeNumber::zero(), // inf^negative = 0
-                    (SpecialValue::NegInfinity, _) if s2.is_finite() && s2.to_f64() > 0.0 => {
-                        // (-inf)^positive depends on if positive is even or odd, but we'll simplify
-                        if s2.to_f64().floor() == s2.to_f64() && (s2.to_f64() as i64) % 2 == 0 {
-                            LargeNumber::infinity() // even power
-                        } else {
-                            LargeNumber::neg_infinity() // odd power
-                        }
-                    },
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                    _ => LargeNumber::infinity(), // Default to infinity for complex cases
-                }
-            },
-            (LargeNumber::Special(s), _) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::zero(),
-                    SpecialValue::Infinity => LargeNumber::infinity(),
-                    SpecialValue::NegInfinity => LargeNumber::neg_infinity(),
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::new_standard(1.0), // anything^0 = 1
-                    SpecialValue::Infinity => LargeNumber::infinity(), // anything^inf = inf (for positive base)
-                    SpecialValue::NegInfinity => LargeNumber::zero(), // anything^(-inf) = 0 (for base > 1)
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            _ => {
-                // For other combinations, convert to f64 if reasonable
-                let base_val = self.to_f64();
-                let exp_val = exponent.to_f64();
-                let result = base_val.powf(exp_val);
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Get the norm (magnitude) of the number
-    pub fn norm(&self) -> f64 {
-        match self {
-            LargeNumber::new_standard(val) => val.abs(),
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                let exp_val = exponent.to_f64();
-                if exp_val > 300.0 {
-                    f64::INFINITY
-                } else {
-                    significand.abs() * base.powf(exp_val)
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity | SpecialValue::NegInfinity => f64::INFINITY,
-                SpecialValue::NaN => f64::NAN,
-                SpecialValue::Zero => 0.0,
-            },
-        }
-    }
-}
-
-/// Efficient complex number representation for very large values
-#[derive(Debug, Clone, PartialEq)]
-pub struct LargeComplex {
-    pub real: LargeNumber,
-    pub imag: LargeNumber,
-}
-
-impl LargeComplex {
-    pub fn new(real: LargeNumber, imag: LargeNumber) -> Self {
-        LargeComplex { real, imag }
-    }
-    
-    pub fn from_standard(z: Complex<f64>) -> Self {
-        LargeComplex {
-            real: LargeNumber::new_standard(z.re),
-            imag: LargeNumber::new_standard(z.im),
-        }
-    }
-    
-    pub fn to_standard(&self) -> Complex<f64> {
-        Complex::new(self.real.to_f64(), self.imag.to_f64())
-    }
-    
-    /// Custom multiplication for the alternative complex number system with large numbers
-    /// (a + bi) * (c + di) = ac + (ad + bc)*i + bd*i²
-    /// where i² is the custom value
-    pub fn multiply(&self, other: &Self, i_squared: &LargeComplex) -> Self {
-        let a = &self.real;
-        let b = &self.imag;
-        let c = &other.real;
-        let d = &other.imag;
-        
-        let ac = a.multiply(c);
-        let ad = a.multiply(&other.imag);
-        let bc = b.multiply(c);
-        let bd = b.multiply(d);
-        
-        // bd * i² where i² is our custom value
-        let bd_i_squared = bd.multiply(&i_squared);
-        
-        // Real part: ac + Re(bd * i²)
-        let real_part = ac.add(&bd_i_squared.real);
-        // Imaginary part: (ad + bc) + Im(bd * i²)
-        let imag_part = ad.add(bc).add(&bd_i_squared.imag);
-        
-        LargeComplex::new(real_part, imag_part)
-    }
-    
-    /// Addition of large complex numbers
-    pub fn add(&self, other: &Self) -> Self {
-        LargeComplex::new(
-            self.real.add(&other.real),
-            self.imag.add(&other.imag),
-        )
-    }
-    
-    /// Get the norm squared of the complex number
-    pub fn norm_sqr(&self) -> LargeNumber {
-        let real_sqr = self.real.pow(&LargeNumber::new_standard(2.0));
-        let imag_sqr = self.imag.pow(&LargeNumber::new_standard(2.0));
-        real_sqr.add(&imag_sqr)
-    }
-    
-    /// Get the norm (magnitude) of the complex number
-    pub fn norm(&self) -> f64 {
-        let norm_sqr = self.norm_sqr();
-        norm_sqr.to_f64().sqrt()
-    }
-    
-    /// Convert to RugComplex for high precision operations when needed
-    pub fn to_rug_complex(&self, precision: u32) -> RugComplex {
-        RugComplex::with_val(precision, self.real.to_f64(), self.imag.to_f64())
-    }
-    
-    /// Create from RugComplex
-    pub fn from_rug_complex(z: &RugComplex) -> Self {
-        LargeComplex::new(
-            LargeNumber::new_standard(z.real().to_f64()),
-            LargeNumber::new_standard(z.imag().to_f64()),
-        )
-    }
-}
-
-// Helper implementations for SpecialValue
-impl SpecialValue {
-    pub fn is_finite(&self) -> bool {
-        !matches!(self, SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN)
-    }
-    
-    pub fn to_f64(&self) -> f64 {
-        match self {
-            SpecialValue::Infinity => f64::INFINITY,
-            SpecialValue::NegInfinity => f64::NEG_INFINITY,
-            SpecialValue::NaN => f64::NAN,
-            SpecialValue::Zero => 0.0,
-        }
-    }
-}
-
-
-// Add the large number system at the end of the file
-use rug::{Complex as RugComplex, Float as RugFloat};
-
-/// Efficient representation for very large numbers using scientific notation: significand * base^exponent
-/// For example: Googolplex = 1 * 10^(10^100) would be represented as LargeNumber { significand: 1.0, base: 10.0, exponent: LargeNumber { significand: 1.0, base: 10.0, exponent: LargeNumber { significand: 100.0, base: 10.0, exponent: LargeNumber::zero() } } }
-#[derive(Debug, Clone, PartialEq)]
-pub enum LargeNumber {
-    /// Standard floating point number for small values
-    Standard(f64),
-    /// Scientific notation: significand * base^exponent
-    Scientific { significand: f64, base: f64, exponent: Box<LargeNumber> },
-    /// Special values: infinity, NaN, zero
-    Special(SpecialValue),
-}
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum SpecialValue {
-    Infinity,
-    NegInfinity,
-    NaN,
-    Zero,
-}
-
-impl LargeNumber {
-    pub fn new_standard(value: f64) -> Self {
-        LargeNumber::new_standard(value)
-    }
-    
-    pub fn new_scientific(significand: f64, base: f64, exponent: LargeNumber) -> Self {
-        LargeNumber::new_scientific {
-            significand,
-            base,
-            exponent: Box::new(exponent),
-        }
-    }
-    
-    pub fn zero() -> Self {
-        LargeNumber::Special(SpecialValue::Zero)
-    }
-    
-    pub fn infinity() -> Self {
-        LargeNumber::Special(SpecialValue::Infinity)
-    }
-    
-    pub fn neg_infinity() -> Self {
-        LargeNumber::Special(SpecialValue::NegInfinity)
-    }
-    
-    pub fn nan() -> Self {
-        LargeNumber::Special(SpecialValue::NaN)
-    }
-    
-    /// Create a googol (10^100)
-    pub fn googol() -> Self {
-        LargeNumber::new_scientific(1.0, 10.0, LargeNumber::new_standard(100.0))
-    }
-    
-    /// Create a googolplex (10^googol)
-    pub fn googolplex() -> Self {
-        LargeNumber::new_scientific(1.0, 10.0, LargeNumber::googol())
-    }
-    
-    /// Convert to f64 if possible, otherwise return infinity
-    pub fn to_f64(&self) -> f64 {
-        match self {
-            LargeNumber::new_standard(val) => *val,
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                let exp_val = exponent.to_f64();
-                if exp_val > 300.0 {
-                    // Would overflow, return infinity
-                    f64::INFINITY
-                } else {
-                    significand * base.powf(exp_val)
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity => f64::INFINITY,
-                SpecialValue::NegInfinity => f64::NEG_INFINITY,
-                SpecialValue::NaN => f64::NAN,
-                SpecialValue::Zero => 0.0,
-            },
-        }
-    }
-    
-    /// Get the magnitude (absolute value) of the number
-    pub fn abs(&self) -> Self {
-        match self {
-            LargeNumber::new_standard(val) => LargeNumber::new_standard(val.abs()),
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                LargeNumber::new_scientific {
-                    significand: significand.abs(),
-                    base: *base,
-                    exponent: exponent.clone(),
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::NegInfinity => LargeNumber::Special(SpecialValue::Infinity),
-                _ => self.clone(),
-            },
-        }
-    }
-    
-    /// Check if the number is finite
-    pub fn is_finite(&self) -> bool {
-        match self {
-            LargeNumber::new_standard(val) => val.is_finite(),
-            LargeNumber::new_scientific { .. } => true, // Scientific notation represents finite values
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN => false,
-                SpecialValue::Zero => true,
-            },
-        }
-    }
-    
-    /// Check if the number is infinite
-    pub fn is_infinite(&self) -> bool {
-        match self {
-            LargeNumber::Special(special) => matches!(special, SpecialValue::Infinity | SpecialValue::NegInfinity),
-            _ => false,
-        }
-    }
-    
-    /// Add two large numbers
-    pub fn add(&self, other: &Self) -> Self {
-        match (self, other) {
-            (LargeNumber::new_standard(a), LargeNumber::new_standard(b)) => {
-                let result = a + b;
-                if result.is_infinite() {
-                    if result.is_sign_positive() {
-                        LargeNumber::infinity()
-                    } else {
-                        LargeNumber::neg_infinity()
-                    }
-                } else {
-                    LargeNumber::new_standard(result)
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Infinity, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Infinity) => LargeNumber::nan(),
-                    (SpecialValue::Infinity, _) | (_, SpecialValue::Infinity) => LargeNumber::infinity(),
-                    (SpecialValue::NegInfinity, _) | (_, SpecialValue::NegInfinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                    (SpecialValue::Zero, _) => other.clone(),
-                    (_, SpecialValue::Zero) => self.clone(),
-                }
-            },
-            (LargeNumber::Special(s), _) | (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN => self.clone(),
-                    SpecialValue::Zero => other.clone(),
-                }
-            },
-            _ => {
-                // For mixed representations, convert to f64 if possible
-                let a = self.to_f64();
-                let b = other.to_f64();
-                let result = a + b;
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Multiply two large numbers
-    pub fn multiply(&self, other: &Self) -> Self {
-        match (self, other) {
-            (LargeNumber::new_standard(a), LargeNumber::new_standard(b)) => {
-                let result = a * b;
-                if result.is_infinite() {
-                    if result.is_sign_positive() {
-                        LargeNumber::infinity()
-                    } else {
-                        LargeNumber::neg_infinity()
-                    }
-                } else if result.is_nan() {
-                    LargeNumber::nan()
-                } else {
-                    LargeNumber::new_standard(result)
-                }
-            },
-            (LargeNumber::new_scientific { significand: s1, base: b1, exponent: e1 }, 
-             LargeNumber::new_scientific { significand: s2, base: b2, exponent: e2 }) if (b1 - b2).abs() < 1e-10 => {
-                // Same base: multiply significands and add exponents
-                let new_significand = s1 * s2;
-                let new_exponent = e1.add(e2);
-                LargeNumber::new_scientific {
-                    significand: new_significand,
-                    base: *b1,
-                    exponent: Box::new(new_exponent),
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Zero, SpecialValue::Infinity) |
-                    (SpecialValue::Infinity, SpecialValue::Zero) |
-                    (SpecialValue::Zero, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Zero) => LargeNumber::nan(),
-                    (SpecialValue::Zero, _) | (_, SpecialValue::Zero) => LargeNumber::zero(),
-                    (SpecialValue::Infinity, SpecialValue::Infinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::NegInfinity) => LargeNumber::infinity(),
-                    (SpecialValue::Infinity, SpecialValue::NegInfinity) |
-                    (SpecialValue::NegInfinity, SpecialValue::Infinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::Infinity, _) | (_, SpecialValue::Infinity) => LargeNumber::infinity(),
-                    (SpecialValue::NegInfinity, _) | (_, SpecialValue::NegInfinity) => LargeNumber::neg_infinity(),
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                }
-            },
-            (LargeNumber::Special(s), _) | (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::zero(),
-                    SpecialValue::Infinity | SpecialValue::NegInfinity => self.clone(),
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            _ => {
-                // For mixed representations, convert to f64 if possible
-                let a = self.to_f64();
-                let b = other.to_f64();
-                let result = a * b;
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Raise a large number to a power
-    pub fn pow(&self, exponent: &Self) -> Self {
-        match (self, exponent) {
-            (LargeNumber::new_standard(base_val), LargeNumber::new_standard(exp_val)) => {
-                let result = base_val.powf(*exp_val);
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            },
-            (LargeNumber::new_scientific { significand: s, base: b, exponent: e }, LargeNumber::new_standard(exp_val)) => {
-                // (s * b^e)^exp_val = s^exp_val * b^(e*exp_val)
-                let new_significand = s.powf(*exp_val);
-                let new_exponent = e.multiply(&LargeNumber::new_standard(*exp_val));
-                LargeNumber::new_scientific {
-                    significand: new_significand,
-                    base: *b,
-                    exponent: Box::new(new_exponent),
-                }
-            },
-            (LargeNumber::Special(s1), LargeNumber::Special(s2)) => {
-                match (s1, s2) {
-                    (SpecialValue::Zero, SpecialValue::Infinity) => LargeNumber::nan(), // 0^inf
-                    (SpecialValue::Zero, SpecialValue::NegInfinity) => LargeNumber::infinity(), // 0^(-inf)
-                    (SpecialValue::Zero, _) if s2.is_finite() => LargeNumber::zero(), // 0^finite = 0
-                    (SpecialValue::One, SpecialValue::Infinity) | (SpecialValue::One, SpecialValue::NegInfinity) => LargeNumber::new_standard(1.0), // 1^inf = 1
-                    (SpecialValue::Infinity, SpecialValue::Zero) => LargeNumber::new_standard(1.0), // inf^0 = 1
-                    (SpecialValue::Infinity, _) if s2.is_finite() && s2.to_f64() > 0.0 => LargeNumber::infinity(), // inf^positive = inf
-                    (SpecialValue::Infinity, _) if s2.is_finite() && s2.to_f64() < 0.0 => LargeNumber::zero(), // inf^negative = 0
-                    (SpecialValue::NegInfinity, _) if s2.is_finite() && s2.to_f64() > 0.0 => {
-                        // (-inf)^positive depends on if positive is even or odd, but we'll simplify
-                        if s2.to_f64().floor() == s2.to_f64() && (s2.to_f64() as i64) % 2 == 0 {
-                            LargeNumber::infinity() // even power
-                        } else {
-                            LargeNumber::neg_infinity() // odd power
-                        }
-                    },
-                    (SpecialValue::NaN, _) | (_, SpecialValue::NaN) => LargeNumber::nan(),
-                    _ => LargeNumber::infinity(), // Default to infinity for complex cases
-                }
-            },
-            (LargeNumber::Special(s), _) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::zero(),
-                    SpecialValue::Infinity => LargeNumber::infinity(),
-                    SpecialValue::NegInfinity => LargeNumber::neg_infinity(),
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            (_, LargeNumber::Special(s)) => {
-                match s {
-                    SpecialValue::Zero => LargeNumber::new_standard(1.0), // anything^0 = 1
-                    SpecialValue::Infinity => LargeNumber::infinity(), // anything^inf = inf (for positive base)
-                    SpecialValue::NegInfinity => LargeNumber::zero(), // anything^(-inf) = 0 (for base > 1)
-                    SpecialValue::NaN => LargeNumber::nan(),
-                }
-            },
-            _ => {
-                // For other combinations, convert to f64 if reasonable
-                let base_val = self.to_f64();
-                let exp_val = exponent.to_f64();
-                let result = base_val.powf(exp_val);
-                if result.is_finite() {
-                    LargeNumber::new_standard(result)
-                } else if result.is_infinite() && result.is_sign_positive() {
-                    LargeNumber::infinity()
-                } else if result.is_infinite() && result.is_sign_negative() {
-                    LargeNumber::neg_infinity()
-                } else {
-                    LargeNumber::nan()
-                }
-            }
-        }
-    }
-    
-    /// Get the norm (magnitude) of the number
-    pub fn norm(&self) -> f64 {
-        match self {
-            LargeNumber::new_standard(val) => val.abs(),
-            LargeNumber::new_scientific { significand, base, exponent } => {
-                let exp_val = exponent.to_f64();
-                if exp_val > 300.0 {
-                    f64::INFINITY
-                } else {
-                    significand.abs() * base.powf(exp_val)
-                }
-            },
-            LargeNumber::Special(special) => match special {
-                SpecialValue::Infinity | SpecialValue::NegInfinity => f64::INFINITY,
-                SpecialValue::NaN => f64::NAN,
-                SpecialValue::Zero => 0.0,
-            },
-        }
-    }
-}
-
-/// Efficient complex number representation for very large values
-#[derive(Debug, Clone, PartialEq)]
-pub struct LargeComplex {
-    pub real: LargeNumber,
-    pub imag: LargeNumber,
-}
-
-impl LargeComplex {
-    pub fn new(real: LargeNumber, imag: LargeNumber) -> Self {
-        LargeComplex { real, imag }
-    }
-    
-    pub fn from_standard(z: Complex<f64>) -> Self {
-        LargeComplex {
-            real: LargeNumber::new_standard(z.re),
-            imag: LargeNumber::new_standard(z.im),
-        }
-    }
-    
-    pub fn to_standard(&self) -> Complex<f64> {
-        Complex::new(self.real.to_f64(), self.imag.to_f64())
-    }
-    
-    /// Custom multiplication for the alternative complex number system with large numbers
-    /// (a + bi) * (c + di) = ac + (ad + bc)*i + bd*i²
-    /// where i² is the custom value
-    pub fn multiply(&self, other: &Self, i_squared: &LargeComplex) -> Self {
-        let a = &self.real;
-        let b = &self.imag;
-        let c = &other.real;
-        let d = &other.imag;
-        
-        let ac = a.multiply(c);
-        let ad = a.multiply(&other.imag);
-        let bc = b.multiply(c);
-        let bd = b.multiply(d);
-        
-        // bd * i² where i² is our custom value
-        let bd_i_squared = bd.multiply(&i_squared);
-        
-        // Real part: ac + Re(bd * i²)
-        let real_part = ac.add(&bd_i_squared.real);
-        // Imaginary part: (ad + bc) + Im(bd * i²)
-        let imag_part = ad.add(bc).add(&bd_i_squared.imag);
-        
-        LargeComplex::new(real_part, imag_part)
-    }
-    
-    /// Addition of large complex numbers
-    pub fn add(&self, other: &Self) -> Self {
-        LargeComplex::new(
-            self.real.add(&other.real),
-            self.imag.add(&other.imag),
-        )
-    }
-    
-    /// Get the norm squared of the complex number
-    pub fn norm_sqr(&self) -> LargeNumber {
-        let real_sqr = self.real.pow(&LargeNumber::new_standard(2.0));
-        let imag_sqr = self.imag.pow(&LargeNumber::new_standard(2.0));
-        real_sqr.add(&imag_sqr)
-    }
-    
-    /// Get the norm (magnitude) of the complex number
-    pub fn norm(&self) -> f64 {
-        let norm_sqr = self.norm_sqr();
-        norm_sqr.to_f64().sqrt()
-    }
-    
-    /// Convert to RugComplex for high precision operations when needed
-    pub fn to_rug_complex(&self, precision: u32) -> RugComplex {
-        RugComplex::with_val(precision, self.real.to_f64(), self.imag.to_f64())
-    }
-    
-    /// Create from RugComplex
-    pub fn from_rug_complex(z: &RugComplex) -> Self {
-        LargeComplex::new(
-            LargeNumber::new_standard(z.real().to_f64()),
-            LargeNumber::new_standard(z.imag().to_f64()),
-        )
-    }
-}
-
-// Helper implementations for SpecialValue
-impl SpecialValue {
-    pub fn is_finite(&self) -> bool {
-        !matches!(self, SpecialValue::Infinity | SpecialValue::NegInfinity | SpecialValue::NaN)
-    }
-    
-    pub fn to_f64(&self) -> f64 {
-        match self {
-            SpecialValue::Infinity => f64::INFINITY,
-            SpecialValue::NegInfinity => f64::NEG_INFINITY,
-            SpecialValue::NaN => f64::NAN,
-            SpecialValue::Zero => 0.0,
-        }
-    }
-}
-
@@ -0,0 +1,272 @@
+//! Newton fractal renderer for polynomials given by their roots
+//!
+//! Each pixel is a starting point for Newton's method on `p(z) = (z - r_1)(z - r_2)...(z - r_n)`
+//! and is coloured by the root it converges to. Since the roots are known, the Newton step is
+//! `z - 1 / sum(1 / (z - r_i))` and no formula parsing is needed.
+//!
+//! Basin boundaries are fractal, so plain basin colouring aliases badly along them. Two smooth
+//! quantities soften the edges without supersampling:
+//!
+//! - a fractional convergence iteration, used to darken slowly converging points, and
+//! - how close the orbit came to a competing root, relative to the root it finally reached,
+//!   before it was captured; this is used to blend towards the competing basin's colour.
+
+use crate::cancel::CancelToken;
+use crate::{hsv_to_rgb, pixel_to_complex};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+/// Parameters for rendering a Newton fractal
+#[derive(Debug, Clone)]
+pub struct NewtonParams {
+    /// The rectangular bounds of the complex plane to render [x_min, x_max, y_min, y_max]
+    pub bounds: [f64; 4],
+    /// Maximum number of Newton steps before a point is considered non-convergent
+    pub max_iterations: u32,
+    /// Roots of the polynomial; each one gets its own basin colour
+    pub roots: Vec<Complex<f64>>,
+    /// Distance to a root at which a point counts as converged
+    pub tolerance: f64,
+    /// Strength of the colour blending across basin boundaries (0 gives hard edges, 1 is the default)
+    pub boundary_smoothing: f64,
+    /// Stops the render when cancelled from another thread (see `cancel`)
+    pub cancel: CancelToken,
+}
+
+impl NewtonParams {
+    pub fn new(bounds: [f64; 4], max_iterations: u32, roots: Vec<Complex<f64>>) -> Self {
+        Self {
+            bounds,
+            max_iterations,
+            roots,
+            tolerance: 1e-6,
+            boundary_smoothing: 1.0,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Parameters for the classic `z^n - 1` Newton fractal, whose roots are the n-th roots of unity
+    pub fn roots_of_unity(bounds: [f64; 4], max_iterations: u32, degree: u32) -> Self {
+        let roots = (0..degree)
+            .map(|k| Complex::from_polar(1.0, 2.0 * std::f64::consts::PI * k as f64 / degree as f64))
+            .collect();
+        Self::new(bounds, max_iterations, roots)
+    }
+}
+
+/// Outcome of running Newton's method from one starting point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NewtonSample {
+    /// Index of the root the point converged to, or `None` if it did not converge
+    pub root: Option<usize>,
+    /// Continuous convergence iteration count, for smooth shading
+    pub smooth_iterations: f64,
+    /// The competing root the orbit came closest to choosing before it was captured
+    pub neighbour_root: Option<usize>,
+    /// How much of the neighbouring basin's colour to blend in, from 0 (none) to 0.5 (on the boundary)
+    pub boundary_weight: f64,
+}
+
+/// Nearest root to a point as an (index, distance) pair
+fn nearest_root(z: Complex<f64>, roots: &[Complex<f64>]) -> (usize, f64) {
+    roots
+        .iter()
+        .enumerate()
+        .map(|(i, root)| (i, (z - root).norm()))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, f64::INFINITY))
+}
+
+/// Run Newton's method from a starting point and measure how close it is to a basin boundary
+///
+/// # Arguments
+///
+/// * `z` - The starting point
+/// * `params` - Newton fractal parameters
+///
+/// # Returns
+///
+/// The converged root with smooth iteration count and boundary blending information
+pub fn newton_iterate(mut z: Complex<f64>, params: &NewtonParams) -> NewtonSample {
+    let not_converged = NewtonSample {
+        root: None,
+        smooth_iterations: params.max_iterations as f64,
+        neighbour_root: None,
+        boundary_weight: 0.0,
+    };
+    if params.roots.is_empty() {
+        return not_converged;
+    }
+
+    // Once a point is well inside this distance of a root it stays in that root's basin in
+    // practice; only the orbit points before that are used to judge boundary proximity
+    let min_separation = params
+        .roots
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| params.roots[i + 1..].iter().map(move |b| (a - b).norm()))
+        .fold(f64::INFINITY, f64::min);
+    let capture_radius = (min_separation / 3.0).max(params.tolerance);
+    let tolerance = params.tolerance.max(f64::MIN_POSITIVE);
+    let mut uncaptured: Vec<Complex<f64>> = Vec::new();
+
+    for n in 0..params.max_iterations {
+        let (root, distance) = nearest_root(z, &params.roots);
+
+        if distance < tolerance {
+            // Quadratic convergence: the fraction of a step needed to get from tolerance to here
+            let fraction = if distance > 0.0 {
+                (distance.ln() / tolerance.ln()).log2().clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+
+            // The most ambiguous uncaptured point, comparing the final root with its nearest rival
+            let mut neighbour_root = None;
+            let mut ambiguity = 0.0;
+            for w in &uncaptured {
+                let rival = params
+                    .roots
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != root)
+                    .map(|(i, r)| (i, (w - r).norm()))
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                if let Some((i, d)) = rival {
+                    let a = ((w - params.roots[root]).norm() / d).min(1.0);
+                    if a > ambiguity {
+                        ambiguity = a;
+                        neighbour_root = Some(i);
+                    }
+                }
+            }
+            let boundary_weight = if params.boundary_smoothing > 0.0 {
+                0.5 * f64::powf(ambiguity, 4.0 / params.boundary_smoothing)
+            } else {
+                0.0
+            };
+            return NewtonSample {
+                root: Some(root),
+                smooth_iterations: (n as f64 - fraction).max(0.0),
+                neighbour_root,
+                boundary_weight,
+            };
+        }
+
+        if distance >= capture_radius {
+            uncaptured.push(z);
+        }
+
+        // z - p(z)/p'(z) with p'(z)/p(z) = sum of 1/(z - r_i)
+        let log_derivative: Complex<f64> = params.roots.iter().map(|r| (z - r).inv()).sum();
+        z -= log_derivative.inv();
+        if !z.is_finite() {
+            return not_converged;
+        }
+    }
+
+    not_converged
+}
+
+/// Default basin colours: evenly spaced hues, one per root
+pub fn default_root_colors(count: usize) -> Vec<[u8; 3]> {
+    (0..count)
+        .map(|i| hsv_to_rgb(i as f64 / count.max(1) as f64, 0.75, 1.0))
+        .collect()
+}
+
+/// Colour a Newton sample using per-root colours, blending across boundaries
+///
+/// Non-convergent points are black. Slowly converging points are darkened according to their
+/// smooth iteration count, so the darkening varies continuously across basin edges.
+pub fn color_newton_sample(sample: &NewtonSample, root_colors: &[[u8; 3]], max_iterations: u32) -> image::Rgba<u8> {
+    let root = match sample.root {
+        Some(root) if !root_colors.is_empty() => root,
+        _ => return image::Rgba([0, 0, 0, 255]),
+    };
+
+    let base = root_colors[root % root_colors.len()];
+    let neighbour = sample
+        .neighbour_root
+        .map(|r| root_colors[r % root_colors.len()])
+        .unwrap_or(base);
+
+    let t = (sample.smooth_iterations / max_iterations.max(1) as f64).clamp(0.0, 1.0);
+    let brightness = (1.0 - t).powi(2);
+    let w = sample.boundary_weight;
+
+    let channel = |i: usize| {
+        let mixed = base[i] as f64 * (1.0 - w) + neighbour[i] as f64 * w;
+        (mixed * brightness).round().clamp(0.0, 255.0) as u8
+    };
+    image::Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+/// Render a Newton fractal image
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Newton fractal parameters
+/// * `root_colors` - Colour for each root's basin; evenly spaced hues are used if `None`
+///
+/// # Returns
+///
+/// The rendered image; if `params.cancel` is cancelled, the pixels not reached yet are
+/// transparent
+pub fn generate_newton_image(
+    width: u32,
+    height: u32,
+    params: &NewtonParams,
+    root_colors: Option<&[[u8; 3]]>,
+) -> image::ImageBuffer<image::Rgba<u8>, Vec<u8>> {
+    let default_colors = default_root_colors(params.roots.len());
+    let colors = root_colors.unwrap_or(&default_colors);
+
+    println!("Rendering Newton fractal with {} roots: {}x{} pixels", params.roots.len(), width, height);
+
+    let pixels: Vec<image::Rgba<u8>> = (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            if params.cancel.is_cancelled() {
+                return image::Rgba([0, 0, 0, 0]);
+            }
+            let (x, y) = (i % width, i / width);
+            let z = pixel_to_complex(x, y, width, height, params.bounds);
+            color_newton_sample(&newton_iterate(z, params), colors, params.max_iterations)
+        })
+        .collect();
+
+    let mut imgbuf = image::ImageBuffer::new(width, height);
+    for (i, color) in pixels.into_iter().enumerate() {
+        imgbuf.put_pixel(i as u32 % width, i as u32 / width, color);
+    }
+    imgbuf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_converges_to_nearest_root_without_blending() {
+        let params = NewtonParams::roots_of_unity([-2.0, 2.0, -2.0, 2.0], 64, 3);
+        let sample = newton_iterate(Complex::new(1.1, 0.05), &params);
+        assert_eq!(sample.root, Some(0));
+        assert!(sample.smooth_iterations < 8.0);
+        assert!(sample.boundary_weight < 0.05);
+    }
+
+    #[test]
+    fn test_boundary_points_blend_neighbouring_basins() {
+        let mut params = NewtonParams::roots_of_unity([-2.0, 2.0, -2.0, 2.0], 64, 3);
+        // Between the basins of the two complex roots, close to where all three meet
+        let near_boundary = newton_iterate(Complex::new(-0.6, 0.1), &params);
+        assert!(near_boundary.root.is_some());
+        assert!(near_boundary.boundary_weight > 0.25);
+
+        params.boundary_smoothing = 0.0;
+        assert_eq!(newton_iterate(Complex::new(-0.6, 0.1), &params).boundary_weight, 0.0);
+    }
+}
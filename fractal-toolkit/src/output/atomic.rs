@@ -0,0 +1,224 @@
+//! Atomic output writing with optional checksums
+//!
+//! A render that is interrupted while its PNG is being written leaves a truncated file behind,
+//! which a resumed run or a gallery later takes for a finished image. Outputs are therefore
+//! written to a hidden temporary file next to the target (`.name.<pid>.part`), flushed to disk
+//! and renamed over the target, which is atomic on the same file system: the target is either
+//! the old file or the complete new one. A temporary file that is never committed is removed
+//! when it is dropped.
+//!
+//! With `OutputOptions::checksum` set (`--checksum` in the executables) every committed output
+//! also gets a `<name>.sha256` file holding its SHA-256 digest as `<hash>  <name>`, which both
+//! `verify_checksum` and `sha256sum -c` check.
+
+use super::hash::Sha256;
+use super::icc::{embed_icc_profile, ColorProfile};
+use crate::FractalError;
+use image::{EncodableLayout, ImageBuffer, ImageFormat, ImageOutputFormat, PixelWithColorType};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+/// How images are written, beyond their pixels
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputOptions {
+    /// ICC profile embedded in 8- and 16-bit PNG and TIFF images (see `icc`)
+    pub color_profile: Option<ColorProfile>,
+    /// Write a `.sha256` checksum file next to every output
+    pub checksum: bool,
+}
+
+/// Path of the checksum file of an output: the output path with `.sha256` appended
+pub fn checksum_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".sha256");
+    PathBuf::from(name)
+}
+
+/// An output file that only appears under its name once `commit` succeeds
+pub struct AtomicFile {
+    path: PathBuf,
+    partial: PathBuf,
+    writer: Option<BufWriter<File>>,
+    /// Digest of the bytes written so far, if a checksum file is wanted
+    hasher: Option<Sha256>,
+}
+
+impl AtomicFile {
+    /// Start writing `path` through a temporary file in the same directory, without a checksum
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, FractalError> {
+        let path = path.as_ref().to_path_buf();
+        let name = path
+            .file_name()
+            .ok_or_else(|| FractalError::InvalidInput(format!("'{}' is not a file path", path.display())))?;
+        let partial = path.with_file_name(format!(".{}.{}.part", name.to_string_lossy(), std::process::id()));
+        let file = File::create(&partial).map_err(|e| FractalError::io("create", &partial, e))?;
+        Ok(Self { path, partial, writer: Some(BufWriter::new(file)), hasher: None })
+    }
+
+    /// Whether `commit` also writes a checksum file; set this before writing any data
+    pub fn set_checksum(&mut self, enabled: bool) {
+        self.hasher = enabled.then(Sha256::new);
+    }
+
+    /// Flush the data to disk and rename the temporary file over the target
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) once the target holds the complete output (and its checksum file, if enabled)
+    pub fn commit(mut self) -> Result<(), FractalError> {
+        let writer = self.writer.take().expect("AtomicFile is committed once");
+        let file = writer.into_inner().map_err(|e| FractalError::io("write", &self.path, e.into_error()))?;
+        file.sync_all().map_err(|e| FractalError::io("write", &self.path, e))?;
+        drop(file);
+        std::fs::rename(&self.partial, &self.path).map_err(|e| FractalError::io("write", &self.path, e))?;
+        if let Some(hasher) = self.hasher.take() {
+            let name = self.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let mut sums = AtomicFile::create(checksum_path(&self.path))?;
+            writeln!(sums, "{}  {}", hasher.hex(), name).map_err(|e| FractalError::io("write", &sums.path, e))?;
+            sums.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.as_mut().expect("AtomicFile is not committed").write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.write(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.as_mut().expect("AtomicFile is not committed").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Not committed: the output is incomplete, so leave the target untouched
+        if self.writer.take().is_some() {
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// Write a whole file atomically (see `AtomicFile`)
+///
+/// # Arguments
+///
+/// * `path` - Target path
+/// * `bytes` - Complete file contents
+pub fn write_atomic(path: impl AsRef<Path>, bytes: &[u8]) -> Result<(), FractalError> {
+    write_output(path, bytes, &OutputOptions::default())
+}
+
+/// Write a finished output atomically, with a checksum file if the options ask for one
+///
+/// # Arguments
+///
+/// * `path` - Target path
+/// * `bytes` - Complete, already encoded file contents
+/// * `options` - Whether to write a checksum file (the colour profile is the encoder's business)
+pub fn write_output(path: impl AsRef<Path>, bytes: &[u8], options: &OutputOptions) -> Result<(), FractalError> {
+    let mut file = AtomicFile::create(&path)?;
+    file.set_checksum(options.checksum);
+    file.write_all(bytes).map_err(|e| FractalError::io("write", &path, e))?;
+    file.commit()
+}
+
+/// Encode an image in the format its extension names and write it atomically
+///
+/// # Arguments
+///
+/// * `image` - Image to save
+/// * `path` - Target path; the extension chooses the format, as for `ImageBuffer::save`
+/// * `options` - Profile embedded in PNG and TIFF files, and whether to write a checksum file
+pub fn save_image_atomic<P>(image: &ImageBuffer<P, Vec<P::Subpixel>>, path: impl AsRef<Path>, options: &OutputOptions) -> Result<(), FractalError>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let path = path.as_ref();
+    let unsaved = |e: image::ImageError| FractalError::InvalidInput(format!("Failed to save '{}': {}", path.display(), e));
+    let format = ImageFormat::from_path(path).map_err(unsaved)?;
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, ImageOutputFormat::from(format)).map_err(unsaved)?;
+    match &options.color_profile {
+        Some(profile) if matches!(format, ImageFormat::Png | ImageFormat::Tiff) => {
+            let embedded = embed_icc_profile(encoded.get_ref(), format, &profile.bytes())
+                .map_err(|e| FractalError::InvalidInput(format!("Failed to save '{}': {}", path.display(), e)))?;
+            write_output(path, &embedded, options)
+        }
+        _ => write_output(path, encoded.get_ref(), options),
+    }
+}
+
+/// Check an output against its checksum file
+///
+/// # Returns
+///
+/// Ok(()) if the file's hash matches, or an error if it differs or either file can't be read
+pub fn verify_checksum(path: impl AsRef<Path>) -> Result<(), FractalError> {
+    let path = path.as_ref();
+    let sums = checksum_path(path);
+    let line = std::fs::read_to_string(&sums).map_err(|e| FractalError::io("read", &sums, e))?;
+    let expected = line.split_whitespace().next().unwrap_or_default();
+    let bytes = std::fs::read(path).map_err(|e| FractalError::io("read", path, e))?;
+    let mut hasher = Sha256::new();
+    hasher.write(&bytes);
+    let actual = hasher.hex();
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(FractalError::InvalidInput(format!(
+            "'{}' has checksum {} but {} records {}",
+            path.display(),
+            actual,
+            sums.display(),
+            expected
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_write_and_checksum() {
+        let directory = std::env::temp_dir().join(format!("ftk_atomic_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("render.png");
+        std::fs::write(&path, b"previous").unwrap();
+
+        // An abandoned write leaves the previous file and no temporary file
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"half").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read(&path).unwrap(), b"previous");
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        let image = image::RgbImage::from_pixel(3, 2, image::Rgb([10, 20, 30]));
+        save_image_atomic(&image, &path, &OutputOptions::default()).unwrap();
+        assert_eq!(image::open(&path).unwrap().to_rgb8(), image);
+
+        let checksummed = OutputOptions { checksum: true, ..OutputOptions::default() };
+        save_image_atomic(&image, &path, &checksummed).unwrap();
+        assert_eq!(verify_checksum(&path), Ok(()));
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.set_checksum(true);
+        file.write_all(b"complete").unwrap();
+        file.commit().unwrap();
+        assert_eq!(verify_checksum(&path), Ok(()));
+        let sums = std::fs::read_to_string(checksum_path(&path)).unwrap();
+        assert_eq!(sums, "eebbf6457e46a7f63acdf9b97390f790ba443d60cfa44b607da7e5c40aa1cc1d  render.png\n");
+
+        std::fs::write(&path, b"damaged").unwrap();
+        assert!(verify_checksum(&path).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
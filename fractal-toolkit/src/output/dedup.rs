@@ -0,0 +1,227 @@
+//! Perceptual hashes of renders for finding near-duplicates
+//!
+//! Automated sweeps over parameters produce many renders that look the same: a zoom into the
+//! interior of the set, a palette offset one step apart, or the same view at two sizes. Content
+//! hashes (`hash`) only match identical bytes, so renders are fingerprinted twice more:
+//!
+//! - Perceptual hashes of a small grayscale thumbnail, which change little when the image
+//!   changes little: a difference hash (dHash) of the brightness gradients of a 9x8 thumbnail,
+//!   and a DCT hash (pHash) of the low frequencies of a 32x32 one. Each is 64 bits, and the
+//!   number of differing bits (the Hamming distance) measures how different two images look,
+//!   independently of their size.
+//! - A hash of the parameters a PNG carries (`embed`), which matches renders of the same
+//!   fractal whatever their size or format.
+//!
+//! `find_near_duplicates` groups the renders of a directory whose hashes are within a distance
+//! of each other, or whose parameters are identical.
+
+use super::embed::load_params_from_image;
+use super::hash::Fnv1a;
+use crate::FractalError;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Extensions of the images `find_near_duplicates` looks at
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "webp", "tif", "tiff", "bmp"];
+
+/// Perceptual hashes of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PerceptualHash {
+    /// Difference hash: whether each pixel of a 9x8 grayscale thumbnail is brighter than its left
+    /// neighbour
+    pub dhash: u64,
+    /// DCT hash: whether each of the 8x8 lowest frequencies of a 32x32 grayscale thumbnail is
+    /// above their median
+    pub phash: u64,
+}
+
+impl PerceptualHash {
+    /// Hash an image
+    pub fn of(image: &DynamicImage) -> Self {
+        let gray = image.to_luma8();
+        Self { dhash: difference_hash(&gray), phash: dct_hash(&gray) }
+    }
+
+    /// The larger of the Hamming distances between the dHashes and the pHashes of two images,
+    /// from 0 (alike) to 64
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        (self.dhash ^ other.dhash).count_ones().max((self.phash ^ other.phash).count_ones())
+    }
+}
+
+/// Difference hash of a grayscale image
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let thumbnail = imageops::resize(gray, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = thumbnail.get_pixel(x + 1, y)[0] > thumbnail.get_pixel(x, y)[0];
+            hash = hash << 1 | brighter as u64;
+        }
+    }
+    hash
+}
+
+/// DCT hash of a grayscale image
+fn dct_hash(gray: &GrayImage) -> u64 {
+    const SIZE: usize = 32;
+    let thumbnail = imageops::resize(gray, SIZE as u32, SIZE as u32, FilterType::Triangle);
+    let pixels: Vec<f64> = thumbnail.as_raw().iter().map(|&value| value as f64).collect();
+
+    // The 8x8 lowest frequencies of the 2D DCT-II, rows first
+    let basis = |frequency: usize, at: usize| (std::f64::consts::PI * frequency as f64 * (2 * at + 1) as f64 / (2 * SIZE) as f64).cos();
+    let rows: Vec<f64> = (0..SIZE)
+        .flat_map(|y| (0..8).map(move |u| (y, u)))
+        .map(|(y, u)| (0..SIZE).map(|x| pixels[y * SIZE + x] * basis(u, x)).sum())
+        .collect();
+    let coefficients: Vec<f64> = (0..8)
+        .flat_map(|v| (0..8).map(move |u| (v, u)))
+        .map(|(v, u)| (0..SIZE).map(|y| rows[y * 8 + u] * basis(v, y)).sum())
+        .collect();
+
+    // The DC term is the mean brightness, which would dominate the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    coefficients.iter().fold(0u64, |hash, &coefficient| hash << 1 | (coefficient > median) as u64)
+}
+
+/// Hash of render parameters, as 16 hex digits
+///
+/// The parameters are hashed as JSON with sorted keys, so equal parameters hash alike however
+/// they were written.
+///
+/// # Arguments
+///
+/// * `params` - Render parameters, anything serializable to JSON
+pub fn params_hash<T: Serialize>(params: &T) -> Result<String, String> {
+    let value = serde_json::to_value(params).map_err(|e| e.to_string())?;
+    let mut hasher = Fnv1a::new();
+    hasher.write(value.to_string().as_bytes());
+    Ok(hasher.hex())
+}
+
+/// Fingerprint of one render
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderFingerprint {
+    /// Path of the render
+    pub path: PathBuf,
+    /// Perceptual hashes of the image
+    pub perceptual: PerceptualHash,
+    /// Hash of the parameters the render carries, if it is a PNG with embedded parameters
+    pub params_hash: Option<String>,
+}
+
+impl RenderFingerprint {
+    /// Fingerprint a render on disk
+    pub fn of_file(path: impl AsRef<Path>) -> Result<Self, FractalError> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| FractalError::image("read", path, e))?;
+        let params_hash = match load_params_from_image(&path.to_string_lossy()) {
+            Ok(params) => Some(params_hash(&params).map_err(FractalError::InvalidInput)?),
+            Err(_) => None,
+        };
+        Ok(Self { path: path.to_path_buf(), perceptual: PerceptualHash::of(&image), params_hash })
+    }
+
+    /// Whether two renders look alike within `max_distance` bits, or carry the same parameters
+    pub fn is_near_duplicate(&self, other: &RenderFingerprint, max_distance: u32) -> bool {
+        let same_params = self.params_hash.is_some() && self.params_hash == other.params_hash;
+        same_params || self.perceptual.distance(&other.perceptual) <= max_distance
+    }
+}
+
+/// Find groups of near-duplicate renders in a directory
+///
+/// Renders are grouped transitively: if A is close to B and B to C, all three are one group.
+///
+/// # Arguments
+///
+/// * `directory` - Directory to scan for PNG, JPEG, WebP, TIFF and BMP images; subdirectories
+///   are not searched
+/// * `max_distance` - Largest Hamming distance between perceptual hashes of images considered
+///   alike; 0 only matches images that look the same at thumbnail size, around 6 also matches
+///   small shifts and color changes
+///
+/// # Returns
+///
+/// The groups of two or more renders, each sorted by path, or `FractalError::IoError` if the
+/// directory or an image can't be read
+pub fn find_near_duplicates(directory: impl AsRef<Path>, max_distance: u32) -> Result<Vec<Vec<RenderFingerprint>>, FractalError> {
+    let directory = directory.as_ref();
+    let listing = std::fs::read_dir(directory).map_err(|e| FractalError::io("read", directory, e))?;
+    let mut paths: Vec<PathBuf> = listing
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+            path.is_file() && IMAGE_EXTENSIONS.contains(&extension.as_str())
+        })
+        .collect();
+    paths.sort();
+    let fingerprints = paths.iter().map(RenderFingerprint::of_file).collect::<Result<Vec<_>, _>>()?;
+
+    // Union-find over the pairs that are alike
+    let mut group: Vec<usize> = (0..fingerprints.len()).collect();
+    fn root(group: &mut [usize], mut index: usize) -> usize {
+        while group[index] != index {
+            group[index] = group[group[index]];
+            index = group[index];
+        }
+        index
+    }
+    for i in 0..fingerprints.len() {
+        for j in i + 1..fingerprints.len() {
+            if fingerprints[i].is_near_duplicate(&fingerprints[j], max_distance) {
+                let (a, b) = (root(&mut group, i), root(&mut group, j));
+                group[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<RenderFingerprint>> = vec![Vec::new(); fingerprints.len()];
+    for (index, fingerprint) in fingerprints.into_iter().enumerate() {
+        let root = root(&mut group, index);
+        groups[root].push(fingerprint);
+    }
+    Ok(groups.into_iter().filter(|group| group.len() > 1).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{save_png_with_params, OutputOptions};
+    use crate::{generate_escape_time_image_and_results, FractalParams};
+
+    #[test]
+    fn test_near_duplicates_are_grouped() {
+        let directory = std::env::temp_dir().join(format!("ftk_dedup_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 64, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        let mut zoomed = params.clone();
+        zoomed.bounds = [-0.2, -0.1, 0.95, 1.05];
+
+        // The same view at two sizes, once with parameters and once as a plain image, and
+        // another view
+        let (small, _) = generate_escape_time_image_and_results(96, 96, &params, false, None);
+        let (large, _) = generate_escape_time_image_and_results(160, 160, &params, false, None);
+        let (other, _) = generate_escape_time_image_and_results(96, 96, &zoomed, false, None);
+        save_png_with_params(&small, directory.join("a.png"), &params, None, None, &OutputOptions::default()).unwrap();
+        large.save(directory.join("b.png")).unwrap();
+        save_png_with_params(&other, directory.join("c.png"), &zoomed, None, None, &OutputOptions::default()).unwrap();
+
+        let a = RenderFingerprint::of_file(directory.join("a.png")).unwrap();
+        let b = RenderFingerprint::of_file(directory.join("b.png")).unwrap();
+        let c = RenderFingerprint::of_file(directory.join("c.png")).unwrap();
+        assert_eq!(a.params_hash, Some(params_hash(&params).unwrap()));
+        assert_eq!(b.params_hash, None);
+        assert!(a.perceptual.distance(&b.perceptual) <= 6, "{:?} {:?}", a.perceptual, b.perceptual);
+        assert!(a.perceptual.distance(&c.perceptual) > 12, "{:?} {:?}", a.perceptual, c.perceptual);
+
+        let groups = find_near_duplicates(&directory, 6).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].iter().map(|fingerprint| fingerprint.path.clone()).collect::<Vec<_>>(), [a.path, b.path]);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! Render parameters embedded in PNG files
+//!
+//! A PNG render can carry the `FractalParams` it was made with, as JSON in a text chunk with the
+//! keyword `PARAMS_KEYWORD`, and its palette in a chunk with the keyword `PALETTE_KEYWORD`. The
+//! image is then its own record: `load_params_from_image` recovers the parameters to render it
+//! again, at another size or quality, or to start a zoom from it, and `reframe_from_png` reads
+//! the same chunk to re-render crops. The view is also stored in center + magnification notation
+//! under `VIEW_KEYWORD` (see `viewport`), with the center digits it was given, for programs that
+//! exchange locations rather than bounds. The chunks are plain `tEXt` (or `iTXt` for text that
+//! isn't ASCII), which image viewers and `exiftool` show as they are.
+
+use super::atomic::{write_output, OutputOptions};
+use super::icc::embed_icc_profile;
+use super::reframe::{read_png_params, PARAMS_KEYWORD};
+use crate::viewport::Viewport;
+use crate::{ColorStop, FractalError, FractalParams};
+use image::{ImageFormat, RgbaImage};
+use std::path::Path;
+
+/// Keyword of the PNG text chunk holding a render's palette as a JSON list of `ColorStop`s
+pub const PALETTE_KEYWORD: &str = "FractalPalette";
+
+/// Keyword of the PNG text chunk holding a render's view in center + magnification notation
+pub const VIEW_KEYWORD: &str = "FractalView";
+
+/// Save a render as a PNG carrying its parameters and palette
+///
+/// # Arguments
+///
+/// * `image` - The render
+/// * `path` - Target path, written atomically
+/// * `params` - Parameters the render was made with
+/// * `palette` - Palette it was colored with, if any
+/// * `viewport` - The view with its exact center, if it was given as one; otherwise the view is
+///   described from `params.bounds`
+/// * `options` - Profile embedded in the PNG
+///
+/// # Returns
+///
+/// Ok(()) once the file is written, `FractalError::IoError` if it can't be written, or
+/// `FractalError::InvalidInput` if the image or its parameters can't be encoded
+pub fn save_png_with_params(
+    image: &RgbaImage,
+    path: impl AsRef<Path>,
+    params: &FractalParams,
+    palette: Option<&[ColorStop]>,
+    viewport: Option<&Viewport>,
+    options: &OutputOptions,
+) -> Result<(), FractalError> {
+    let path = path.as_ref();
+    let view = viewport.cloned().unwrap_or_else(|| Viewport::from_bounds(params.bounds));
+    let invalid = FractalError::InvalidInput;
+    let mut chunks = vec![
+        (PARAMS_KEYWORD, serde_json::to_string(params).map_err(|e| invalid(format!("Failed to encode parameters: {}", e)))?),
+        (VIEW_KEYWORD, view.to_notation()),
+    ];
+    if let Some(palette) = palette {
+        chunks.push((PALETTE_KEYWORD, serde_json::to_string(palette).map_err(|e| invalid(format!("Failed to encode palette: {}", e)))?));
+    }
+    let unsaved = |e: png::EncodingError| invalid(format!("Failed to save '{}': {}", path.display(), e));
+
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut encoded, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        for (keyword, text) in chunks {
+            let added = match text.is_ascii() {
+                true => encoder.add_text_chunk(keyword.to_string(), text),
+                false => encoder.add_itxt_chunk(keyword.to_string(), text),
+            };
+            added.map_err(unsaved)?;
+        }
+        let mut writer = encoder.write_header().map_err(unsaved)?;
+        writer.write_image_data(image.as_raw()).map_err(unsaved)?;
+    }
+    match &options.color_profile {
+        Some(profile) => write_output(path, &embed_icc_profile(&encoded, ImageFormat::Png, &profile.bytes()).map_err(invalid)?, options),
+        None => write_output(path, &encoded, options),
+    }
+}
+
+/// Save a render, embedding its parameters if it is a PNG
+///
+/// Other formats are saved as by `save_image_atomic`, without parameters.
+pub fn save_image_with_params(
+    image: &RgbaImage,
+    path: impl AsRef<Path>,
+    params: &FractalParams,
+    palette: Option<&[ColorStop]>,
+    viewport: Option<&Viewport>,
+    options: &OutputOptions,
+) -> Result<(), FractalError> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")) {
+        save_png_with_params(image, path, params, palette, viewport, options)
+    } else {
+        super::save_image_atomic(image, path, options)
+    }
+}
+
+/// The parameters a PNG render was made with
+///
+/// # Arguments
+///
+/// * `path` - A PNG saved by `save_png_with_params`, or any PNG with a `PARAMS_KEYWORD` chunk
+///
+/// # Returns
+///
+/// The parameters, `FractalError::IoError` if the file can't be read, or
+/// `FractalError::InvalidInput` if it isn't a PNG or carries no valid parameters
+pub fn load_params_from_image(path: &str) -> Result<FractalParams, FractalError> {
+    let text = read_png_params(path, PARAMS_KEYWORD)?
+        .2
+        .ok_or_else(|| FractalError::InvalidInput(format!("{} has no '{}' text chunk", path, PARAMS_KEYWORD)))?;
+    serde_json::from_str(&text).map_err(|e| FractalError::InvalidInput(format!("Invalid parameters in {}: {}", path, e)))
+}
+
+/// The palette a PNG render was colored with, or `None` if it carries none
+pub fn load_palette_from_image(path: &str) -> Result<Option<Vec<ColorStop>>, FractalError> {
+    read_png_params(path, PALETTE_KEYWORD)?
+        .2
+        .map(|text| serde_json::from_str(&text).map_err(|e| FractalError::InvalidInput(format!("Invalid palette in {}: {}", path, e))))
+        .transpose()
+}
+
+/// The view a PNG render was made at, or `None` if it carries none
+pub fn load_viewport_from_image(path: &str) -> Result<Option<Viewport>, FractalError> {
+    read_png_params(path, VIEW_KEYWORD)?
+        .2
+        .map(|text| Viewport::from_notation(&text).map_err(|e| FractalError::InvalidInput(format!("Invalid view in {}: {}", path, e))))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_color_palette;
+
+    #[test]
+    fn test_params_survive_a_png_round_trip() {
+        let mut params = FractalParams::new([-0.75, -0.74, 0.1, 0.11], 2500, [0.0, 0.0], 2.0, "z^2 + c*π".to_string());
+        params.smooth_coloring = true;
+        let palette = parse_color_palette("[(#000000,0),(#FF8800,0.5),(#FFFFFF,1)]").unwrap();
+        let path = std::env::temp_dir().join(format!("ftk_embed_{}.png", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let image = RgbaImage::from_pixel(5, 4, image::Rgba([10, 20, 30, 255]));
+        save_image_with_params(&image, &path, &params, Some(&palette), None, &OutputOptions::default()).unwrap();
+
+        let loaded = load_params_from_image(&path).unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&params).unwrap());
+        assert_eq!(load_palette_from_image(&path).unwrap().unwrap().len(), 3);
+        assert_eq!(load_viewport_from_image(&path).unwrap(), Some(Viewport::from_bounds(params.bounds)));
+        assert_eq!(image::open(&path).unwrap().to_rgba8(), image);
+
+        // A view given by its center keeps every digit
+        let viewport = Viewport::new("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", 2e10).unwrap();
+        save_image_with_params(&image, &path, &params, None, Some(&viewport), &OutputOptions::default()).unwrap();
+        assert!(load_palette_from_image(&path).unwrap().is_none());
+        assert_eq!(load_viewport_from_image(&path).unwrap(), Some(viewport));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Static HTML galleries of a directory of renders
+//!
+//! Every PNG render of `ftk-mandel`, `ftk-julia` and `ftk-render` carries its parameters and
+//! palette (see `embed`), so a directory of renders already holds everything an index of them
+//! needs. `write_gallery` scans a directory for such PNGs, writes a thumbnail of each to
+//! `thumbnails/` and an `index.html` listing them with their formula, view, iteration limit and
+//! a swatch of their palette, linking each to the full image and, when the render wrote one, to
+//! its interactive explorer page (`<name>.html`). PNGs without embedded parameters, such as
+//! layers and plots, are left out.
+//!
+//! The page is static and self-contained, so the directory can be copied or served as it is.
+//! Thumbnails are only recreated when their render is newer, which keeps regenerating the index
+//! after every render cheap.
+
+use super::atomic::{save_image_atomic, write_atomic, OutputOptions};
+use super::embed::{load_palette_from_image, load_params_from_image};
+use crate::{interpolate_color_from_palette, ColorStop, FractalError, FractalParams};
+use std::path::{Path, PathBuf};
+
+/// Name of the gallery page written to the scanned directory
+pub const GALLERY_INDEX: &str = "index.html";
+
+/// Subdirectory the thumbnails are written to
+pub const THUMBNAIL_DIRECTORY: &str = "thumbnails";
+
+/// Palette stops shown in the swatch of an entry
+const SWATCH_STOPS: usize = 16;
+
+/// Appearance of a gallery page
+#[derive(Debug, Clone, PartialEq)]
+pub struct GalleryOptions {
+    /// Page title and heading
+    pub title: String,
+    /// Longest side of a thumbnail in pixels
+    pub thumbnail_size: u32,
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        Self { title: "Fractal Gallery".to_string(), thumbnail_size: 256 }
+    }
+}
+
+/// One render found in a gallery directory
+#[derive(Debug, Clone)]
+pub struct GalleryEntry {
+    /// File name of the render, relative to the directory
+    pub image: String,
+    /// Width and height of the render in pixels
+    pub dimensions: [u32; 2],
+    /// Parameters embedded in the render
+    pub params: FractalParams,
+    /// Palette embedded in the render, if any
+    pub palette: Option<Vec<ColorStop>>,
+    /// File name of the render's explorer page, if it exists
+    pub explorer: Option<String>,
+}
+
+/// Find the renders with embedded parameters in a directory
+///
+/// # Arguments
+///
+/// * `directory` - Directory to scan; subdirectories are not searched
+///
+/// # Returns
+///
+/// The renders sorted by file name, or `FractalError::IoError` if the directory or a render
+/// can't be read
+pub fn scan_gallery(directory: impl AsRef<Path>) -> Result<Vec<GalleryEntry>, FractalError> {
+    let directory = directory.as_ref();
+    let listing = std::fs::read_dir(directory).map_err(|e| FractalError::io("read", directory, e))?;
+    let mut paths: Vec<PathBuf> = listing
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+        .collect();
+    paths.sort();
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let path_str = path.to_string_lossy();
+        let Ok(params) = load_params_from_image(&path_str) else { continue };
+        let dimensions = image::image_dimensions(&path).map_err(|e| FractalError::image("read", &path, e))?;
+        let explorer = path.with_extension("html");
+        entries.push(GalleryEntry {
+            image: file_name(&path),
+            dimensions: [dimensions.0, dimensions.1],
+            params,
+            palette: load_palette_from_image(&path_str).unwrap_or_default(),
+            explorer: explorer.is_file().then(|| file_name(&explorer)),
+        });
+    }
+    Ok(entries)
+}
+
+/// Write thumbnails and an `index.html` gallery page for the renders in a directory
+///
+/// # Arguments
+///
+/// * `directory` - Directory holding the renders; the page and thumbnails are written there
+/// * `options` - Title and thumbnail size
+///
+/// # Returns
+///
+/// The path of the page and the number of renders it lists, or `FractalError::IoError` if a
+/// render can't be read or a file can't be written
+pub fn write_gallery(directory: impl AsRef<Path>, options: &GalleryOptions) -> Result<(PathBuf, usize), FractalError> {
+    let directory = directory.as_ref();
+    let entries = scan_gallery(directory)?;
+    let thumbnails = directory.join(THUMBNAIL_DIRECTORY);
+    std::fs::create_dir_all(&thumbnails).map_err(|e| FractalError::io("create", &thumbnails, e))?;
+    for entry in &entries {
+        let source = directory.join(&entry.image);
+        let target = thumbnails.join(&entry.image);
+        if is_newer(&target, &source) {
+            continue;
+        }
+        let image = image::open(&source).map_err(|e| FractalError::image("read", &source, e))?;
+        let size = options.thumbnail_size.max(1);
+        // Thumbnails are previews for the page, saved without the renders' profile
+        save_image_atomic(&image.thumbnail(size, size).to_rgba8(), &target, &OutputOptions::default())?;
+    }
+
+    let index = directory.join(GALLERY_INDEX);
+    write_atomic(&index, gallery_html(&entries, options).as_bytes())?;
+    Ok((index, entries.len()))
+}
+
+/// Rewrite the gallery of the directory a render was saved to, with the default options
+///
+/// # Arguments
+///
+/// * `output` - Path of the render just saved
+///
+/// # Returns
+///
+/// The path of the page and the number of renders it lists, or an error as from `write_gallery`
+pub fn update_gallery(output: impl AsRef<Path>) -> Result<(PathBuf, usize), FractalError> {
+    let directory = match output.as_ref().parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    write_gallery(directory, &GalleryOptions::default())
+}
+
+/// The gallery page for a list of renders, with thumbnails in `THUMBNAIL_DIRECTORY`
+pub fn gallery_html(entries: &[GalleryEntry], options: &GalleryOptions) -> String {
+    let mut cards = String::new();
+    for entry in entries {
+        let params = &entry.params;
+        let [x_min, x_max, y_min, y_max] = params.bounds;
+        let center = ((x_min + x_max) / 2.0, (y_min + y_max) / 2.0);
+        let mut rows = vec![
+            ("Formula", params.formula.clone()),
+            ("Center", format!("{} {:+}i", center.0, center.1)),
+            ("Width", format!("{:e}", x_max - x_min)),
+            ("Iterations", params.max_iterations.to_string()),
+            ("Size", format!("{}x{}", entry.dimensions[0], entry.dimensions[1])),
+        ];
+        if params.spawn.norm() > 0.0 {
+            rows.push(("Spawn", format!("{} {:+}i", params.spawn.re, params.spawn.im)));
+        }
+        if params.i_sqrt_value.re != 0.0 || params.i_sqrt_value.im != 1.0 {
+            rows.push(("i", format!("√({} {:+}i)", params.i_sqrt_value.re, params.i_sqrt_value.im)));
+        }
+        let table: String = rows
+            .iter()
+            .map(|(name, value)| format!("<tr><th>{}</th><td>{}</td></tr>", name, escape(value)))
+            .collect();
+        let swatch = entry.palette.as_deref().map(palette_gradient).unwrap_or_default();
+        let explorer = entry
+            .explorer
+            .as_ref()
+            .map(|page| format!(r#" · <a href="{}">Open in explorer</a>"#, escape(page)))
+            .unwrap_or_default();
+        let json = serde_json::to_string_pretty(params).unwrap_or_default();
+        cards.push_str(&format!(
+            r#"<div class="card">
+<a href="{image}"><img src="{thumbnails}/{image}" alt="{image}" loading="lazy"></a>
+<div class="swatch" style="background: {swatch}"></div>
+<div class="name"><a href="{image}">{image}</a>{explorer}</div>
+<table>{table}</table>
+<details><summary>Parameters</summary><pre>{json}</pre></details>
+</div>
+"#,
+            image = escape(&entry.image),
+            thumbnails = THUMBNAIL_DIRECTORY,
+            swatch = if swatch.is_empty() { "#444".to_string() } else { swatch },
+            explorer = explorer,
+            table = table,
+            json = escape(&json),
+        ));
+    }
+    if entries.is_empty() {
+        cards.push_str("<p>No renders with embedded parameters found.</p>\n");
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: Arial, sans-serif; margin: 20px; background-color: #1e1e1e; color: #ddd; }}
+a {{ color: #8cf; }}
+.grid {{ display: flex; flex-wrap: wrap; gap: 16px; }}
+.card {{ width: {size}px; background-color: #2a2a2a; padding: 8px; border-radius: 4px; }}
+.card img {{ display: block; max-width: 100%; margin: 0 auto; }}
+.swatch {{ height: 8px; margin: 6px 0; }}
+.name {{ font-weight: bold; word-break: break-all; margin-bottom: 4px; }}
+table {{ font-size: 12px; border-collapse: collapse; }}
+th {{ text-align: left; padding-right: 8px; color: #999; font-weight: normal; }}
+td {{ word-break: break-all; }}
+pre {{ font-size: 11px; white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{count} renders</p>
+<div class="grid">
+{cards}</div>
+</body>
+</html>
+"#,
+        title = escape(&options.title),
+        size = options.thumbnail_size.max(160),
+        count = entries.len(),
+        cards = cards,
+    )
+}
+
+/// A CSS gradient showing a palette
+fn palette_gradient(palette: &[ColorStop]) -> String {
+    let stops: Vec<String> = (0..SWATCH_STOPS)
+        .map(|i| {
+            let position = i as f64 / (SWATCH_STOPS - 1) as f64;
+            let color = interpolate_color_from_palette(position, palette);
+            format!("#{:02x}{:02x}{:02x} {:.0}%", color[0], color[1], color[2], position * 100.0)
+        })
+        .collect();
+    format!("linear-gradient(to right, {})", stops.join(", "))
+}
+
+/// Whether `path` exists and was modified after `than`
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    matches!((modified(path), modified(than)), (Some(path), Some(than)) if path >= than)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Escape text for HTML content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::save_image_with_params;
+    use crate::parse_color_palette;
+    use image::RgbaImage;
+
+    #[test]
+    fn test_gallery_lists_renders_with_params() {
+        let directory = std::env::temp_dir().join(format!("ftk_gallery_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 300, [0.0, 0.0], 4.0, "z^3 + c".to_string());
+        let palette = parse_color_palette("inferno").unwrap();
+        let image = RgbaImage::from_pixel(40, 30, image::Rgba([200, 100, 0, 255]));
+        save_image_with_params(&image, directory.join("b_render.png"), &params, Some(&palette), None, &OutputOptions::default()).unwrap();
+        save_image_with_params(&image, directory.join("a_render.png"), &params, None, None, &OutputOptions::default()).unwrap();
+        std::fs::write(directory.join("a_render.html"), "<html></html>").unwrap();
+        // A PNG without parameters is skipped
+        image.save(directory.join("plot.png")).unwrap();
+
+        let options = GalleryOptions { thumbnail_size: 16, ..Default::default() };
+        let (index, count) = write_gallery(&directory, &options).unwrap();
+        assert_eq!(count, 2);
+        let html = std::fs::read_to_string(index).unwrap();
+        assert!(html.find("a_render.png").unwrap() < html.find("b_render.png").unwrap());
+        assert!(html.contains(r#"<a href="a_render.html">Open in explorer</a>"#));
+        assert!(html.contains("z^3 + c") && !html.contains("plot.png"));
+        assert_eq!(image::image_dimensions(directory.join("thumbnails/b_render.png")).unwrap(), (16, 12));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,382 @@
+//! Content hashes of rendered images for verifying reproducibility
+//!
+//! Renders are deterministic: escape-time images depend only on their parameters, and the
+//! Buddhabrot samplers draw from fixed seeds. A `RenderManifest` records a hash of the whole
+//! image and of every tile, together with the parameters and seeds, so a render split across
+//! workers or resumed after an interruption can be checked against a reference run, and a
+//! mismatch narrowed down to the tiles that differ.
+//!
+//! Hashes are 64-bit FNV-1a over the image dimensions and pixel bytes. The function is fixed
+//! here rather than taken from `std`, whose hasher may change between Rust releases. It detects
+//! accidental differences, not deliberate tampering.
+//!
+//! Checksums of output files (see `atomic`) use SHA-256 instead, so that the standard
+//! `sha256sum` tool can check them.
+
+use crate::FractalError;
+use image::{ImageBuffer, Pixel};
+use serde::{Deserialize, Serialize};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// SHA-256 round constants: the fractional parts of the cube roots of the first 64 primes
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 initial hash: the fractional parts of the square roots of the first 8 primes
+const SHA256_H: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+/// Hash of one tile of an image
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileHash {
+    /// Column of the tile's top-left pixel
+    pub x: u32,
+    /// Row of the tile's top-left pixel
+    pub y: u32,
+    /// Width of the tile; tiles on the right edge may be narrower than the tile size
+    pub width: u32,
+    /// Height of the tile; tiles on the bottom edge may be shorter than the tile size
+    pub height: u32,
+    /// Hash of the tile's pixels as 16 hex digits
+    pub hash: String,
+}
+
+/// Hashes of a render together with what it was rendered from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderManifest {
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+    /// Side length of the (square) tiles
+    pub tile_size: u32,
+    /// Render parameters, as given by the renderer
+    pub params: serde_json::Value,
+    /// Seeds of the random number generators used, empty for deterministic renderers
+    #[serde(default)]
+    pub seeds: Vec<u64>,
+    /// Hash of the whole image as 16 hex digits
+    pub image_hash: String,
+    /// Tile hashes in row-major tile order
+    pub tiles: Vec<TileHash>,
+}
+
+/// 64-bit FNV-1a hash of a byte stream
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(super) fn new() -> Self {
+        Fnv1a(FNV_OFFSET)
+    }
+
+    pub(super) fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub(super) fn hex(self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// SHA-256 digest of a byte stream (FIPS 180-4)
+#[derive(Debug, Clone)]
+pub(super) struct Sha256 {
+    state: [u32; 8],
+    /// Bytes of the block being filled
+    block: [u8; 64],
+    /// Bytes hashed so far
+    length: u64,
+}
+
+impl Sha256 {
+    pub(super) fn new() -> Self {
+        Self { state: SHA256_H, block: [0; 64], length: 0 }
+    }
+
+    pub(super) fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let filled = (self.length % 64) as usize;
+            let taken = bytes.len().min(64 - filled);
+            self.block[filled..filled + taken].copy_from_slice(&bytes[..taken]);
+            self.length += taken as u64;
+            bytes = &bytes[taken..];
+            if filled + taken == 64 {
+                self.compress();
+            }
+        }
+    }
+
+    pub(super) fn hex(mut self) -> String {
+        let bits = self.length.wrapping_mul(8);
+        self.write(&[0x80]);
+        while self.length % 64 != 56 {
+            self.write(&[0]);
+        }
+        self.write(&bits.to_be_bytes());
+        self.state.iter().map(|word| format!("{:08x}", word)).collect()
+    }
+
+    /// Mix the full block into the state
+    fn compress(&mut self) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(self.block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = schedule[i - 15].rotate_right(7) ^ schedule[i - 15].rotate_right(18) ^ (schedule[i - 15] >> 3);
+            let s1 = schedule[i - 2].rotate_right(17) ^ schedule[i - 2].rotate_right(19) ^ (schedule[i - 2] >> 10);
+            schedule[i] = schedule[i - 16].wrapping_add(s0).wrapping_add(schedule[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in SHA256_K.iter().zip(schedule) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(*k).wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+/// Hash of a rectangle of an image, including its size so that differently shaped regions with
+/// the same bytes don't collide
+fn region_hash<P>(image: &ImageBuffer<P, Vec<u8>>, x: u32, y: u32, width: u32, height: u32) -> String
+where
+    P: Pixel<Subpixel = u8>,
+{
+    let mut hasher = Fnv1a::new();
+    hasher.write(&width.to_le_bytes());
+    hasher.write(&height.to_le_bytes());
+    let row_bytes = image.width() as usize * P::CHANNEL_COUNT as usize;
+    let raw = image.as_raw();
+    for row in y..y + height {
+        let start = row as usize * row_bytes + x as usize * P::CHANNEL_COUNT as usize;
+        hasher.write(&raw[start..start + width as usize * P::CHANNEL_COUNT as usize]);
+    }
+    hasher.hex()
+}
+
+/// Hash of a whole image
+pub fn image_hash<P>(image: &ImageBuffer<P, Vec<u8>>) -> String
+where
+    P: Pixel<Subpixel = u8>,
+{
+    region_hash(image, 0, 0, image.width(), image.height())
+}
+
+/// Hash every tile of an image
+///
+/// # Arguments
+///
+/// * `image` - The rendered image
+/// * `tile_size` - Side length of the tiles in pixels (at least 1)
+///
+/// # Returns
+///
+/// The tile hashes in row-major tile order
+pub fn tile_hashes<P>(image: &ImageBuffer<P, Vec<u8>>, tile_size: u32) -> Vec<TileHash>
+where
+    P: Pixel<Subpixel = u8>,
+{
+    let tile_size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..image.height()).step_by(tile_size as usize) {
+        for x in (0..image.width()).step_by(tile_size as usize) {
+            let width = tile_size.min(image.width() - x);
+            let height = tile_size.min(image.height() - y);
+            tiles.push(TileHash { x, y, width, height, hash: region_hash(image, x, y, width, height) });
+        }
+    }
+    tiles
+}
+
+impl RenderManifest {
+    /// Hash an image and record it with the parameters it was rendered from
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The rendered image
+    /// * `tile_size` - Side length of the tiles in pixels
+    /// * `params` - Render parameters, anything serializable to JSON
+    /// * `seeds` - Random number generator seeds the render used
+    ///
+    /// # Returns
+    ///
+    /// The manifest, or `FractalError::InvalidInput` if the parameters can't be serialized
+    pub fn new<P, T>(image: &ImageBuffer<P, Vec<u8>>, tile_size: u32, params: &T, seeds: Vec<u64>) -> Result<Self, FractalError>
+    where
+        P: Pixel<Subpixel = u8>,
+        T: Serialize,
+    {
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            tile_size: tile_size.max(1),
+            params: serde_json::to_value(params).map_err(|e| FractalError::InvalidInput(e.to_string()))?,
+            seeds,
+            image_hash: image_hash(image),
+            tiles: tile_hashes(image, tile_size),
+        })
+    }
+
+    /// Read a manifest written by `write`
+    pub fn read(path: &str) -> Result<Self, FractalError> {
+        let text = std::fs::read_to_string(path).map_err(|e| FractalError::io("read", path, e))?;
+        serde_json::from_str(&text).map_err(|e| FractalError::InvalidInput(format!("Invalid render manifest '{}': {}", path, e)))
+    }
+
+    /// Write the manifest as pretty-printed JSON
+    pub fn write(&self, path: &str) -> Result<(), FractalError> {
+        let pretty = serde_json::to_string_pretty(self).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+        super::atomic::write_atomic(path, pretty.as_bytes())
+    }
+
+    /// Compare against another run of the same render
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Manifest of the run to check, e.g. from another worker or a resumed render
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the images are identical, or an error listing what differs: the image size,
+    /// tile size, parameters, seeds or the tiles (by top-left pixel) whose hashes don't match
+    pub fn verify(&self, other: &RenderManifest) -> Result<(), String> {
+        if (self.width, self.height, self.tile_size) != (other.width, other.height, other.tile_size) {
+            return Err(format!(
+                "Renders are not comparable: {}x{} in tiles of {} against {}x{} in tiles of {}",
+                self.width, self.height, self.tile_size, other.width, other.height, other.tile_size
+            ));
+        }
+        let mut problems = Vec::new();
+        if self.params != other.params {
+            problems.push("parameters differ".to_string());
+        }
+        if self.seeds != other.seeds {
+            problems.push(format!("seeds differ ({:?} against {:?})", self.seeds, other.seeds));
+        }
+        let tiles: Vec<String> = self
+            .tiles
+            .iter()
+            .zip(&other.tiles)
+            .filter(|(expected, actual)| expected.hash != actual.hash)
+            .map(|(tile, _)| format!("({}, {})", tile.x, tile.y))
+            .collect();
+        if !tiles.is_empty() {
+            problems.push(format!("{} of {} tiles differ, at {}", tiles.len(), self.tiles.len(), tiles.join(", ")));
+        }
+        if problems.is_empty() && self.image_hash != other.image_hash {
+            problems.push("image hashes differ".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+}
+
+/// Record and check the hashes of a saved render, as the executables' `--tile-hashes` and
+/// `--verify-hashes` options do
+///
+/// # Arguments
+///
+/// * `image` - The rendered image
+/// * `params` - Render parameters to record
+/// * `seeds` - Random number generator seeds the render used
+/// * `output` - Path the image was saved to; the manifest goes next to it as `<name>.hashes.json`
+/// * `tile_size` - Write a manifest with tiles of this size; `None` writes none
+/// * `reference` - Manifest to compare against, hashed with its own tile size
+///
+/// # Returns
+///
+/// Ok(()) if nothing was requested or the render matches the reference,
+/// `FractalError::InvalidInput` describing the difference, or `FractalError::IoError` if a
+/// manifest can't be read or written
+pub fn record_render_hashes<P, T>(
+    image: &ImageBuffer<P, Vec<u8>>,
+    params: &T,
+    seeds: Vec<u64>,
+    output: &str,
+    tile_size: Option<u32>,
+    reference: Option<&str>,
+) -> Result<(), FractalError>
+where
+    P: Pixel<Subpixel = u8>,
+    T: Serialize,
+{
+    if let Some(size) = tile_size {
+        let manifest = RenderManifest::new(image, size, params, seeds.clone())?;
+        let path = std::path::Path::new(output).with_extension("hashes.json");
+        manifest.write(&path.to_string_lossy())?;
+        println!("Image hash {}; {} tile hashes saved to {}", manifest.image_hash, manifest.tiles.len(), path.display());
+    }
+    if let Some(path) = reference {
+        // Hash with the reference's tiles, whatever size was asked for above
+        let reference = RenderManifest::read(path)?;
+        let manifest = RenderManifest::new(image, reference.tile_size, params, seeds)?;
+        reference.verify(&manifest).map_err(|e| FractalError::InvalidInput(format!("Render differs from {}: {}", path, e)))?;
+        println!("Render matches {}", path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_manifest_pinpoints_changed_tile() {
+        let image = RgbImage::from_fn(10, 7, |x, y| Rgb([x as u8, y as u8, 3]));
+        let reference = RenderManifest::new(&image, 4, &[1.0, 2.0], vec![42]).unwrap();
+        assert_eq!(reference.tiles.len(), 3 * 2);
+        assert_eq!((reference.tiles[5].width, reference.tiles[5].height), (2, 3));
+        assert_eq!(reference.verify(&RenderManifest::new(&image.clone(), 4, &[1.0, 2.0], vec![42]).unwrap()), Ok(()));
+
+        let mut changed = image.clone();
+        changed.put_pixel(9, 6, Rgb([0, 0, 0]));
+        let rerun = RenderManifest::new(&changed, 4, &[1.0, 2.0], vec![42]).unwrap();
+        assert_ne!(rerun.image_hash, reference.image_hash);
+        assert_eq!(reference.verify(&rerun), Err("1 of 6 tiles differ, at (8, 4)".to_string()));
+    }
+
+    #[test]
+    fn test_sha256_matches_reference_digests() {
+        let digest = |bytes: &[u8]| {
+            let mut hasher = Sha256::new();
+            hasher.write(bytes);
+            hasher.hex()
+        };
+        assert_eq!(digest(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(digest(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // Crosses a block boundary
+        assert_eq!(
+            digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+
+        let mut split = Sha256::new();
+        split.write(b"abcdbcdecdefdefgefgh");
+        split.write(b"fghighijhijkijkljklmklmnlmnomnopnopq");
+        assert_eq!(split.hex(), digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"));
+    }
+}
@@ -0,0 +1,257 @@
+//! 16-bit and floating point image output
+//!
+//! The renders are 8-bit, which is enough to look at but posterizes smooth gradients once they
+//! are graded: a curve that stretches the darks spreads 256 levels over the whole range. These
+//! writers keep the precision the renderers compute with, for grading in external tools:
+//!
+//! - `.png`: 16-bit RGBA PNG, in sRGB like the 8-bit renders
+//! - `.exr`: 32-bit float RGBA OpenEXR, linear
+//! - `.tif`/`.tiff`: 32-bit float RGBA TIFF, linear
+//!
+//! Escape-time renders are colored from the palette without rounding (`color_results_hdr`);
+//! the float formats convert the colors from sRGB to linear light, as compositing tools expect.
+//! Buddhabrot renders are written as their raw linear orbit densities (`HdrImage::from_densities`),
+//! one count per channel, before the log scaling and normalization of the 8-bit image, so the
+//! tone curve can be chosen afterwards. A 16-bit PNG can't hold counts above 1, so densities
+//! are divided by the largest one first, then encoded with the sRGB curve like every other PNG.
+//!
+//! Coloring pipelines, post effects and annotations act on the 8-bit image and are not part of
+//! these outputs.
+
+use super::atomic::{save_image_atomic, write_output, OutputOptions};
+use super::icc::{linear_to_srgb, srgb_to_linear};
+use super::scientific::encode_tiff_f32;
+use crate::{color_for_result_equalized, escape_degree, interpolate_palette_rgb, smooth_gradient_rgb, ColorStop, DensityGrid, FractalError, FractalParams, IterationHistogram, IterationResult};
+use image::{ImageBuffer, Rgba};
+use rayon::prelude::*;
+
+/// Format of a high bit depth output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// 16-bit RGBA PNG
+    Png16,
+    /// 32-bit float RGBA OpenEXR
+    Exr,
+    /// 32-bit float RGBA TIFF
+    Tiff32F,
+}
+
+impl HdrFormat {
+    /// Pick the format from a file extension (`.png`, `.exr`, `.tif` or `.tiff`)
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(HdrFormat::Png16),
+            "exr" => Some(HdrFormat::Exr),
+            "tif" | "tiff" => Some(HdrFormat::Tiff32F),
+            _ => None,
+        }
+    }
+}
+
+/// An RGBA image with a float per channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct HdrImage {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Row-major pixels
+    pub pixels: Vec<[f32; 4]>,
+    /// Whether the colors are sRGB-encoded from 0 to 1, as rendered colors are; otherwise they
+    /// are linear and may exceed 1, as densities do
+    pub srgb: bool,
+}
+
+impl HdrImage {
+    /// The raw densities of a Buddhabrot render, one per channel, with an opaque alpha
+    ///
+    /// # Arguments
+    ///
+    /// * `densities` - Red, green and blue densities, e.g. from `buddhabrot_densities`
+    pub fn from_densities([red, green, blue]: &[DensityGrid; 3]) -> Self {
+        let pixels = red
+            .as_slice()
+            .iter()
+            .zip(green.as_slice())
+            .zip(blue.as_slice())
+            .map(|((&r, &g), &b)| [r as f32, g as f32, b as f32, 1.0])
+            .collect();
+        Self { width: red.width() as u32, height: red.height() as u32, pixels, srgb: false }
+    }
+
+    /// The colors as linear light
+    fn to_linear(&self) -> Vec<[f32; 4]> {
+        if !self.srgb {
+            return self.pixels.clone();
+        }
+        self.pixels
+            .iter()
+            .map(|&[r, g, b, a]| {
+                let [r, g, b] = [r, g, b].map(|channel| srgb_to_linear(channel as f64) as f32);
+                [r, g, b, a]
+            })
+            .collect()
+    }
+}
+
+/// Color the iteration results of a render without rounding to 8 bits
+///
+/// Follows `color_results`: escaped points take the same palette positions, including smooth,
+/// histogram and cycled coloring, and are interpolated at full precision. Points colored with a
+/// solid color (the inside color, the background, interior coloring) keep their 8-bit colors.
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `results` - Iteration results of every pixel, in row-major order
+/// * `params` - Fractal parameters with the iteration limit and color options
+/// * `palette` - Optional color palette
+///
+/// # Returns
+///
+/// The sRGB image, or an error if the results don't match its size
+pub fn color_results_hdr(
+    width: u32,
+    height: u32,
+    results: &[IterationResult],
+    params: &FractalParams,
+    palette: Option<&[ColorStop]>,
+) -> Result<HdrImage, String> {
+    if results.len() != width as usize * height as usize {
+        return Err(format!("{} iteration results for a {}x{} image", results.len(), width, height));
+    }
+    let degree = escape_degree(&params.formula);
+    let histogram = params
+        .histogram_coloring
+        .then(|| IterationHistogram::from_results(results, params.max_iterations));
+    let pixels = results
+        .par_iter()
+        .map(|result| match palette_count(result, degree, params, histogram.as_ref()) {
+            Some(count) => {
+                let rgb = match palette {
+                    Some(palette) => interpolate_palette_rgb(count / params.max_iterations as f64, palette),
+                    None => smooth_gradient_rgb(count, params.max_iterations),
+                };
+                let [r, g, b] = rgb.map(|channel| (channel / 255.0) as f32);
+                [r, g, b, 1.0]
+            }
+            None => color_for_result_equalized(result, degree, params, palette, histogram.as_ref()).0.map(|channel| channel as f32 / 255.0),
+        })
+        .collect();
+    Ok(HdrImage { width, height, pixels, srgb: true })
+}
+
+/// The iteration count an escaped point is looked up in the palette by, as in
+/// `color_for_result_equalized`, or `None` for points colored otherwise
+fn palette_count(result: &IterationResult, degree: f64, params: &FractalParams, histogram: Option<&IterationHistogram>) -> Option<f64> {
+    let iterations = result.iterations;
+    let max_iterations = params.max_iterations;
+    if max_iterations == 0 || iterations >= max_iterations || (iterations == 0 && params.outside_background.is_some()) {
+        return None;
+    }
+    let count = if params.smooth_coloring { result.smooth_iterations(degree) } else { iterations as f64 };
+    Some(match histogram {
+        Some(histogram) => params.cycled_count(histogram.equalize(count) * max_iterations as f64),
+        None => params.cycled_count(count),
+    })
+}
+
+/// Write an image in the format its extension names
+///
+/// # Arguments
+///
+/// * `path` - Output path ending in `.png`, `.exr`, `.tif` or `.tiff`
+/// * `image` - The image
+/// * `options` - How the file is written; only the 16-bit PNG carries the profile, since the
+///   float formats hold linear light
+///
+/// # Returns
+///
+/// The format written, `FractalError::IoError` if the file can't be written, or
+/// `FractalError::InvalidInput` for an unsupported extension or an image a format can't hold
+pub fn write_hdr_image(path: &str, image: &HdrImage, options: &OutputOptions) -> Result<HdrFormat, FractalError> {
+    let format = HdrFormat::from_path(path).ok_or_else(|| {
+        FractalError::InvalidInput(format!("Unsupported high bit depth output extension for '{}' (use .png, .exr or .tif)", path))
+    })?;
+    let mismatched = || FractalError::InvalidInput("Image size doesn't match its pixels".to_string());
+    let (width, height) = (image.width, image.height);
+    match format {
+        HdrFormat::Png16 => {
+            // Densities are scaled into the 0 to 1 a PNG holds and encoded as sRGB, which is how
+            // viewers read a PNG without a profile
+            let peak = image.pixels.iter().flat_map(|pixel| &pixel[..3]).fold(0.0f32, |peak, &value| peak.max(value));
+            let scale = if !image.srgb && peak > 1.0 { 1.0 / peak } else { 1.0 };
+            let encode = |value: f32| match image.srgb {
+                true => value,
+                false => linear_to_srgb((value * scale).clamp(0.0, 1.0) as f64) as f32,
+            };
+            let samples = image
+                .pixels
+                .iter()
+                .flat_map(|&[r, g, b, a]| [encode(r), encode(g), encode(b), a])
+                .map(|value| (value.clamp(0.0, 1.0) * 65535.0).round() as u16)
+                .collect();
+            let buffer: ImageBuffer<Rgba<u16>, Vec<u16>> = ImageBuffer::from_raw(width, height, samples).ok_or_else(mismatched)?;
+            save_image_atomic(&buffer, path, options)?;
+        }
+        HdrFormat::Exr => {
+            let samples = image.to_linear().into_iter().flatten().collect();
+            let buffer: ImageBuffer<Rgba<f32>, Vec<f32>> = ImageBuffer::from_raw(width, height, samples).ok_or_else(mismatched)?;
+            save_image_atomic(&buffer, path, options)?;
+        }
+        HdrFormat::Tiff32F => {
+            let samples: Vec<f32> = image.to_linear().into_iter().flatten().collect();
+            let description = if image.srgb { "linear RGBA" } else { "linear RGBA orbit densities" };
+            write_output(path, &encode_tiff_f32(width, height, 4, &samples, description)?, options)?;
+        }
+    }
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_color_palette;
+
+    #[test]
+    fn test_hdr_colors_are_not_rounded_and_round_trip() {
+        let mut params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 1000, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        params.inside_color = Some([0, 0, 255, 255]);
+        let palette = parse_color_palette("[(#000000,0),(#FFFFFF,1)]").unwrap();
+        let escaped = |iterations| IterationResult { iterations, escaped: true, final_z: Default::default(), period: None, multiplier: None };
+        let results = [escaped(1), escaped(2), escaped(1000), escaped(500)];
+        let image = color_results_hdr(2, 2, &results, &params, Some(&palette)).unwrap();
+        // One iteration apart is 1/1000 of the gradient, finer than an 8-bit step
+        assert!((image.pixels[1][0] - image.pixels[0][0] - 0.001).abs() < 1e-6);
+        assert_eq!(image.pixels[2], [0.0, 0.0, 1.0, 1.0]);
+
+        let directory = std::env::temp_dir();
+        let png = directory.join(format!("ftk_hdr_{}.png", std::process::id())).to_string_lossy().into_owned();
+        assert_eq!(write_hdr_image(&png, &image, &OutputOptions::default()).unwrap(), HdrFormat::Png16);
+        let decoded = image::open(&png).unwrap().into_rgba16();
+        assert_eq!(decoded.get_pixel(1, 1)[0], (0.5f32 * 65535.0).round() as u16);
+
+        let exr = png.replace(".png", ".exr");
+        write_hdr_image(&exr, &image, &OutputOptions::default()).unwrap();
+        let decoded = image::open(&exr).unwrap().into_rgba32f();
+        assert!((decoded.get_pixel(1, 1)[0] - srgb_to_linear(0.5) as f32).abs() < 1e-6);
+
+        let densities = [DensityGrid::filled(2, 1, 7.0), DensityGrid::filled(2, 1, 0.0), DensityGrid::filled(2, 1, 1.5)];
+        let tiff = png.replace(".png", ".tif");
+        write_hdr_image(&tiff, &HdrImage::from_densities(&densities), &OutputOptions::default()).unwrap();
+        let bytes = std::fs::read(&tiff).unwrap();
+        let pixels: Vec<f32> = bytes[bytes.len() - 32..].chunks(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(pixels, [7.0, 0.0, 1.5, 1.0, 7.0, 0.0, 1.5, 1.0]);
+
+        // In a PNG the densities are scaled to the peak and sRGB-encoded
+        write_hdr_image(&png, &HdrImage::from_densities(&densities), &OutputOptions::default()).unwrap();
+        let decoded = image::open(&png).unwrap().into_rgba16();
+        let blue = (linear_to_srgb(1.5 / 7.0) * 65535.0).round() as u16;
+        assert_eq!(decoded.get_pixel(0, 0).0, [65535, 0, blue, 65535]);
+        for path in [png, exr, tiff] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Colour management: ICC profiles and the linear-to-display transform
+//!
+//! A PNG or TIFF without a profile is shown however the viewer or printer driver guesses, which
+//! on wide-gamut displays oversaturates the palette and in print shifts it. With a profile in
+//! the `OutputOptions` an image is saved with (`--icc-profile` in the executables), the 8- and
+//! 16-bit PNG and TIFF images carry it: in an `iCCP` chunk for PNG, in the `InterColorProfile`
+//! tag (34675) for TIFF. The profile is either the built-in sRGB profile, which describes the
+//! colours the palettes are written in, or a profile file, for images already converted to a
+//! printer's or display's space. Other formats are saved without one, and so are the float
+//! TIFFs of `hdr` and `scientific`, which hold linear light or data that an sRGB or device
+//! profile would misdescribe.
+//!
+//! Renders whose brightness is proportional to a physical quantity (Buddhabrot densities,
+//! blackbody mixing) hold linear light, which looks dark and crushed when shown as it is. A
+//! `DisplayTransform` encodes such values for display with the sRGB transfer curve or a plain
+//! gamma: Buddhabrot renders apply theirs (`BuddhabrotParams::display_transform`) to the
+//! normalized densities before they are quantized, and `apply_display_transform` applies one
+//! to an image that is already 8-bit.
+
+use image::{ImageBuffer, ImageFormat, Pixel};
+use serde::{Deserialize, Serialize};
+
+/// Tag number of the ICC profile in TIFF files (`InterColorProfile`)
+const TIFF_ICC_TAG: u16 = 34675;
+
+/// An ICC profile to embed in outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// The built-in sRGB profile (`srgb_icc_profile`)
+    Srgb,
+    /// The contents of an ICC profile file
+    Icc(Vec<u8>),
+}
+
+impl ColorProfile {
+    /// Parse a profile option: `srgb`, or the path of an `.icc`/`.icm` file
+    ///
+    /// # Returns
+    ///
+    /// The profile, or an error if the file can't be read or isn't an ICC profile
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if spec.eq_ignore_ascii_case("srgb") {
+            return Ok(ColorProfile::Srgb);
+        }
+        let bytes = std::fs::read(spec).map_err(|e| format!("Failed to read ICC profile '{}': {}", spec, e))?;
+        validate_icc_profile(&bytes).map_err(|e| format!("'{}' is not an ICC profile: {}", spec, e))?;
+        Ok(ColorProfile::Icc(bytes))
+    }
+
+    /// The profile's bytes, as embedded in files
+    pub fn bytes(&self) -> Vec<u8> {
+        match self {
+            ColorProfile::Srgb => srgb_icc_profile(),
+            ColorProfile::Icc(bytes) => bytes.clone(),
+        }
+    }
+}
+
+/// Check the header of an ICC profile: its recorded size and the `acsp` signature
+pub fn validate_icc_profile(bytes: &[u8]) -> Result<(), String> {
+    if bytes.len() < 132 {
+        return Err(format!("{} bytes is too short for a profile header and tag table", bytes.len()));
+    }
+    if &bytes[36..40] != b"acsp" {
+        return Err("missing the 'acsp' signature".to_string());
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    if size != bytes.len() {
+        return Err(format!("the header records {} bytes but the file has {}", size, bytes.len()));
+    }
+    Ok(())
+}
+
+/// An ICC v2 display profile for sRGB (IEC 61966-2.1)
+///
+/// The primaries are the Bradford-adapted D50 values of the standard profile, and the tone
+/// curves tabulate the sRGB transfer function at 1024 points.
+pub fn srgb_icc_profile() -> Vec<u8> {
+    fn xyz(values: [f64; 3]) -> Vec<u8> {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        for value in values {
+            data.extend_from_slice(&((value * 65536.0).round() as i32).to_be_bytes());
+        }
+        data
+    }
+    fn text_description(text: &str) -> Vec<u8> {
+        let mut data = b"desc\0\0\0\0".to_vec();
+        data.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+        data.extend_from_slice(text.as_bytes());
+        data.push(0);
+        // No Unicode or ScriptCode descriptions
+        data.extend_from_slice(&[0; 4 + 4 + 2 + 1 + 67]);
+        data
+    }
+    let mut copyright = b"text\0\0\0\0No copyright, use freely".to_vec();
+    copyright.push(0);
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend_from_slice(&1024u32.to_be_bytes());
+    for i in 0..1024 {
+        let linear = srgb_to_linear(i as f64 / 1023.0);
+        curve.extend_from_slice(&((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let blobs = [
+        text_description("sRGB IEC61966-2.1"),
+        copyright,
+        xyz([0.9505, 1.0, 1.0891]),
+        xyz([0.4361, 0.2225, 0.0139]),
+        xyz([0.3851, 0.7169, 0.0971]),
+        xyz([0.1431, 0.0606, 0.7141]),
+        curve,
+    ];
+    // Tag signatures and the blob each points to; the three tone curves share one table
+    let tags: [(&[u8; 4], usize); 9] =
+        [(b"desc", 0), (b"cprt", 1), (b"wtpt", 2), (b"rXYZ", 3), (b"gXYZ", 4), (b"bXYZ", 5), (b"rTRC", 6), (b"gTRC", 6), (b"bTRC", 6)];
+
+    let data_start = 128 + 4 + tags.len() * 12;
+    let mut data: Vec<u8> = Vec::new();
+    let mut offsets = Vec::with_capacity(blobs.len());
+    for blob in &blobs {
+        offsets.push(data_start + data.len());
+        data.extend_from_slice(blob);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    for (signature, blob) in tags {
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&(offsets[blob] as u32).to_be_bytes());
+        table.extend_from_slice(&(blobs[blob].len() as u32).to_be_bytes());
+    }
+
+    let size = data_start + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&(size as u32).to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // Preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // Version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    for field in [2000u16, 1, 1, 0, 0, 0] {
+        profile.extend_from_slice(&field.to_be_bytes());
+    }
+    profile.extend_from_slice(b"acsp");
+    profile.extend_from_slice(&[0; 28]); // Platform, flags, manufacturer, model, attributes
+    profile.extend_from_slice(&0u32.to_be_bytes()); // Perceptual intent
+    profile.extend_from_slice(&xyz([0.9642, 1.0, 0.8249])[8..]); // PCS illuminant, D50
+    profile.resize(128, 0);
+    profile.extend_from_slice(&table);
+    profile.extend_from_slice(&data);
+    profile
+}
+
+/// Embed an ICC profile in an encoded PNG or TIFF file
+///
+/// PNG files get an `iCCP` chunk after the header, replacing any `sRGB` or `iCCP` chunk (the two
+/// are exclusive). TIFF files get a copy of their first directory with the profile tag added,
+/// which the header then points to.
+///
+/// # Arguments
+///
+/// * `encoded` - The complete file
+/// * `format` - Its format, `ImageFormat::Png` or `ImageFormat::Tiff`
+/// * `profile` - The profile's bytes
+///
+/// # Returns
+///
+/// The file with the profile, or an error for other formats or files that can't be parsed
+pub fn embed_icc_profile(encoded: &[u8], format: ImageFormat, profile: &[u8]) -> Result<Vec<u8>, String> {
+    match format {
+        ImageFormat::Png => embed_icc_in_png(encoded, profile),
+        ImageFormat::Tiff => embed_icc_in_tiff(encoded, profile),
+        _ => Err(format!("ICC profiles can only be embedded in PNG and TIFF files, not {:?}", format)),
+    }
+}
+
+fn embed_icc_in_png(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, String> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !encoded.starts_with(SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+    let mut chunk_data = b"ICC profile\0\0".to_vec(); // Name, then compression method 0 (zlib)
+    chunk_data.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(profile, 6));
+    let mut iccp = (chunk_data.len() as u32).to_be_bytes().to_vec();
+    iccp.extend_from_slice(b"iCCP");
+    iccp.extend_from_slice(&chunk_data);
+    let mut crc = crc32(b"iCCP", 0xFFFF_FFFF);
+    crc = crc32(&chunk_data, crc);
+    iccp.extend_from_slice(&(!crc).to_be_bytes());
+
+    let mut out = SIGNATURE.to_vec();
+    let mut position = SIGNATURE.len();
+    while position < encoded.len() {
+        let header = encoded.get(position..position + 8).ok_or("truncated PNG chunk")?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let end = position + 12 + length;
+        let chunk = encoded.get(position..end).ok_or("truncated PNG chunk")?;
+        match &header[4..8] {
+            b"IHDR" => {
+                out.extend_from_slice(chunk);
+                out.extend_from_slice(&iccp);
+            }
+            b"iCCP" | b"sRGB" => {}
+            _ => out.extend_from_slice(chunk),
+        }
+        position = end;
+    }
+    Ok(out)
+}
+
+/// CRC-32 as used by PNG chunks, continuing from `crc` (start with `0xFFFFFFFF`, invert at the end)
+fn crc32(bytes: &[u8], mut crc: u32) -> u32 {
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn embed_icc_in_tiff(encoded: &[u8], profile: &[u8]) -> Result<Vec<u8>, String> {
+    const UNDEFINED: u16 = 7;
+    let little_endian = match encoded.get(0..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return Err("not a TIFF file (BigTIFF is not supported)".to_string()),
+    };
+    let u16_at = |offset: usize| -> Result<u16, String> {
+        let bytes: [u8; 2] = encoded.get(offset..offset + 2).ok_or("truncated TIFF")?.try_into().unwrap();
+        Ok(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    };
+    let u32_at = |offset: usize| -> Result<u32, String> {
+        let bytes: [u8; 4] = encoded.get(offset..offset + 4).ok_or("truncated TIFF")?.try_into().unwrap();
+        Ok(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    };
+    let put_u16 = |out: &mut Vec<u8>, value: u16| out.extend_from_slice(&if little_endian { value.to_le_bytes() } else { value.to_be_bytes() });
+    let put_u32 = |out: &mut Vec<u8>, value: u32| out.extend_from_slice(&if little_endian { value.to_le_bytes() } else { value.to_be_bytes() });
+
+    // Entries of the first directory, without any previous profile
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    let mut entries: Vec<(u16, &[u8])> = Vec::with_capacity(count + 1);
+    for i in 0..count {
+        let entry = encoded.get(ifd + 2 + i * 12..ifd + 14 + i * 12).ok_or("truncated TIFF directory")?;
+        let tag = u16_at(ifd + 2 + i * 12)?;
+        if tag != TIFF_ICC_TAG {
+            entries.push((tag, entry));
+        }
+    }
+    let next_ifd = u32_at(ifd + 2 + count * 12)?;
+
+    // The profile, then the new directory (on a word boundary), are appended to the file
+    let mut out = encoded.to_vec();
+    out.resize(out.len().next_multiple_of(2), 0);
+    let profile_offset = out.len() as u32;
+    out.extend_from_slice(profile);
+    out.resize(out.len().next_multiple_of(2), 0);
+    let new_ifd = out.len() as u32;
+
+    let mut icc_entry = Vec::with_capacity(12);
+    put_u16(&mut icc_entry, TIFF_ICC_TAG);
+    put_u16(&mut icc_entry, UNDEFINED);
+    put_u32(&mut icc_entry, profile.len() as u32);
+    put_u32(&mut icc_entry, profile_offset);
+    entries.push((TIFF_ICC_TAG, &icc_entry));
+    entries.sort_by_key(|&(tag, _)| tag);
+
+    put_u16(&mut out, entries.len() as u16);
+    for (_, entry) in &entries {
+        out.extend_from_slice(entry);
+    }
+    put_u32(&mut out, next_ifd);
+    let mut header = Vec::with_capacity(4);
+    put_u32(&mut header, new_ifd);
+    out[4..8].copy_from_slice(&header);
+    Ok(out)
+}
+
+/// How linear light is encoded for display
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisplayTransform {
+    /// The sRGB transfer curve (linear below 0.0031308, then a 1/2.4 power)
+    Srgb,
+    /// A pure power curve, `value^(1/gamma)`
+    Gamma(f64),
+}
+
+impl DisplayTransform {
+    /// Parse `srgb`, `gamma:<g>` or a bare gamma such as `2.2`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.eq_ignore_ascii_case("srgb") {
+            return Ok(DisplayTransform::Srgb);
+        }
+        let gamma = spec.strip_prefix("gamma:").unwrap_or(spec);
+        match gamma.parse::<f64>() {
+            Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(DisplayTransform::Gamma(gamma)),
+            _ => Err(format!("Invalid display transform '{}': expected 'srgb', 'gamma:<g>' or a positive gamma", spec)),
+        }
+    }
+
+    /// Encode one linear value in [0, 1] for display
+    pub fn encode(&self, linear: f64) -> f64 {
+        let linear = linear.clamp(0.0, 1.0);
+        match self {
+            DisplayTransform::Srgb => linear_to_srgb(linear),
+            DisplayTransform::Gamma(gamma) => linear.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// The sRGB transfer function: linear light to encoded value, both in [0, 1]
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `linear_to_srgb`
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode an 8-bit image holding linear light for display, in place
+///
+/// The colour channels go through the transform; alpha, which is coverage rather than light,
+/// is left as it is. Dark levels are spread apart by the curve, so this bands where the render
+/// could have encoded its values before quantizing them instead.
+pub fn apply_display_transform<P: Pixel<Subpixel = u8>>(image: &mut ImageBuffer<P, Vec<u8>>, transform: DisplayTransform) {
+    let table: Vec<u8> = (0..=255).map(|level| (transform.encode(level as f64 / 255.0) * 255.0).round() as u8).collect();
+    for pixel in image.pixels_mut() {
+        pixel.apply_without_alpha(|channel| table[channel as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_profile_round_trips_through_png_and_tiff() {
+        let profile = srgb_icc_profile();
+        assert_eq!(validate_icc_profile(&profile), Ok(()));
+        let image = RgbaImage::from_fn(6, 5, |x, y| image::Rgba([x as u8 * 40, y as u8 * 50, 90, 255]));
+
+        for format in [ImageFormat::Png, ImageFormat::Tiff] {
+            let mut encoded = Cursor::new(Vec::new());
+            image.write_to(&mut encoded, image::ImageOutputFormat::from(format)).unwrap();
+            let embedded = embed_icc_profile(encoded.get_ref(), format, &profile).unwrap();
+            let decoded = image::load_from_memory_with_format(&embedded, format).unwrap().to_rgba8();
+            assert_eq!(decoded, image, "{:?}", format);
+            // Embedding again replaces the profile rather than adding a second one
+            let twice = embed_icc_profile(&embedded, format, &profile).unwrap();
+            assert_eq!(image::load_from_memory_with_format(&twice, format).unwrap().to_rgba8(), image);
+        }
+
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, image::ImageOutputFormat::Png).unwrap();
+        let embedded = embed_icc_profile(encoded.get_ref(), ImageFormat::Png, &profile).unwrap();
+        let reader = png::Decoder::new(Cursor::new(embedded)).read_info().unwrap();
+        assert_eq!(reader.info().icc_profile.as_deref(), Some(&profile[..]));
+    }
+
+    #[test]
+    fn test_display_transform() {
+        assert_eq!(DisplayTransform::parse("srgb"), Ok(DisplayTransform::Srgb));
+        assert_eq!(DisplayTransform::parse("gamma:2.2"), Ok(DisplayTransform::Gamma(2.2)));
+        assert!(DisplayTransform::parse("gamma:0").is_err());
+        assert!((srgb_to_linear(linear_to_srgb(0.18)) - 0.18).abs() < 1e-12);
+
+        let mut image = RgbaImage::from_pixel(1, 1, image::Rgba([0, 46, 255, 128]));
+        apply_display_transform(&mut image, DisplayTransform::Srgb);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 118, 255, 128]);
+
+        // Buddhabrot channels are encoded before quantizing, so faint densities that an 8-bit
+        // image rounds to black stay visible
+        let faint = crate::DensityGrid::filled(1, 1, 0.001);
+        let encoded = crate::combine_normalized_channels(1, 1, [&faint, &faint, &faint], None, Some(DisplayTransform::Srgb));
+        assert_eq!(encoded.get_pixel(0, 0).0, [3; 3]);
+    }
+}
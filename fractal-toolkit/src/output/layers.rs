@@ -0,0 +1,110 @@
+//! Interior and exterior of a render as separate layers
+//!
+//! Compositing workflows often fill the inside of a set with a texture or photo and keep the
+//! rendered exterior around it. `split_layers` divides a finished render by the escape flag of
+//! each pixel into two images of the same size: the interior layer keeps the pixels that never
+//! escaped and the exterior layer the ones that did, each transparent everywhere else. Stacking
+//! the two gives back the render.
+//!
+//! The split uses the iteration results the render was colored from, so it needs no second pass.
+//! Anti-aliased edge pixels belong to the layer of their center sample.
+
+use super::atomic::{save_image_atomic, OutputOptions};
+use crate::{FractalError, IterationResult};
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+/// The two halves of a render
+#[derive(Debug, Clone)]
+pub struct RenderLayers {
+    /// Pixels of points that never escaped, transparent elsewhere
+    pub interior: RgbaImage,
+    /// Pixels of points that escaped, transparent elsewhere
+    pub exterior: RgbaImage,
+}
+
+/// Split a render into interior and exterior layers
+///
+/// # Arguments
+///
+/// * `image` - The colored render
+/// * `results` - The iteration result of every pixel, in row-major order
+///
+/// # Returns
+///
+/// The layers, or an error if the results don't match the image size
+pub fn split_layers(image: &RgbaImage, results: &[IterationResult]) -> Result<RenderLayers, String> {
+    let (width, height) = image.dimensions();
+    if results.len() != (width as usize) * (height as usize) {
+        return Err(format!("{} iteration results for a {}x{} image", results.len(), width, height));
+    }
+
+    let mut interior = RgbaImage::new(width, height);
+    let mut exterior = RgbaImage::new(width, height);
+    let transparent = Rgba([0, 0, 0, 0]);
+    for ((pixel, result), (inside, outside)) in image.pixels().zip(results).zip(interior.pixels_mut().zip(exterior.pixels_mut())) {
+        if result.escaped {
+            *inside = transparent;
+            *outside = *pixel;
+        } else {
+            *inside = *pixel;
+            *outside = transparent;
+        }
+    }
+    Ok(RenderLayers { interior, exterior })
+}
+
+/// Paths of the layers of a render saved at `path`: `<stem>_interior.png` and `<stem>_exterior.png`
+pub fn layer_paths(path: &str) -> (String, String) {
+    let path = Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+    let layer = |name: &str| path.with_file_name(format!("{}_{}.png", stem, name)).to_string_lossy().into_owned();
+    (layer("interior"), layer("exterior"))
+}
+
+/// Split a render and save both layers next to it
+///
+/// # Arguments
+///
+/// * `path` - Where the render itself is saved; the layers go to `layer_paths(path)`
+/// * `image` - The colored render
+/// * `results` - The iteration result of every pixel, in row-major order
+/// * `options` - How the layers are written
+///
+/// # Returns
+///
+/// The paths of the interior and exterior layers, `FractalError::InvalidInput` if the results
+/// don't match the image size, or `FractalError::IoError` if a layer can't be written
+pub fn write_split_layers(path: &str, image: &RgbaImage, results: &[IterationResult], options: &OutputOptions) -> Result<(String, String), FractalError> {
+    let layers = split_layers(image, results).map_err(FractalError::InvalidInput)?;
+    let (interior_path, exterior_path) = layer_paths(path);
+    for (layer, layer_path) in [(&layers.interior, &interior_path), (&layers.exterior, &exterior_path)] {
+        save_image_atomic(layer, layer_path, options)?;
+    }
+    Ok((interior_path, exterior_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_complex::Complex;
+
+    #[test]
+    fn test_split_layers() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([10, 20, 30, 255]));
+        image.put_pixel(1, 0, Rgba([200, 100, 50, 255]));
+        let result = |escaped| IterationResult { iterations: 5, escaped, final_z: Complex::new(0.0, 0.0), period: None, multiplier: None };
+        let layers = split_layers(&image, &[result(false), result(true)]).unwrap();
+
+        assert_eq!(*layers.interior.get_pixel(0, 0), Rgba([10, 20, 30, 255]));
+        assert_eq!(layers.interior.get_pixel(1, 0)[3], 0);
+        assert_eq!(layers.exterior.get_pixel(0, 0)[3], 0);
+        assert_eq!(*layers.exterior.get_pixel(1, 0), Rgba([200, 100, 50, 255]));
+        assert!(split_layers(&image, &[result(true)]).is_err());
+
+        let (interior, exterior) = layer_paths("renders/mandel.png");
+        assert_eq!(interior, "renders/mandel_interior.png");
+        assert_eq!(exterior, "renders/mandel_exterior.png");
+    }
+}
@@ -0,0 +1,45 @@
+//! Image and data output formats beyond the standard PNG renders
+//!
+//! The executables save colour images through the `image` crate. This module holds writers for
+//! outputs that need more control over the file contents, such as raw data for analysis, and the
+//! content hashes used to check that two runs of a render produced the same image. Renders can
+//! also be split into interior and exterior layers for compositing. PNG renders carry their
+//! parameters and palette in text chunks (`embed`), so any render can be reproduced from the
+//! image alone, and a directory of them can be indexed as a static HTML gallery (`gallery`). PNG
+//! and TIFF outputs can carry an ICC profile (`icc`), so prints and wide-gamut displays show the
+//! intended colours. Escape-time colours and Buddhabrot densities can also be written at 16 bits
+//! or as floats (`hdr`) for grading. Perceptual and parameter hashes (`dedup`) find the
+//! near-duplicate renders of a parameter sweep.
+//!
+//! Every output is written through `atomic`, so an interrupted run never leaves a half-written
+//! file under the output's name, and can be checksummed for later verification.
+
+pub mod atomic;
+pub mod dedup;
+pub mod embed;
+pub mod gallery;
+pub mod hash;
+pub mod hdr;
+pub mod icc;
+pub mod layers;
+pub mod reframe;
+pub mod scientific;
+
+pub use atomic::{checksum_path, save_image_atomic, verify_checksum, write_atomic, write_output, AtomicFile, OutputOptions};
+pub use dedup::{find_near_duplicates, params_hash, PerceptualHash, RenderFingerprint};
+pub use embed::{
+    load_palette_from_image, load_params_from_image, load_viewport_from_image, save_image_with_params, save_png_with_params, PALETTE_KEYWORD,
+    VIEW_KEYWORD,
+};
+pub use gallery::{gallery_html, scan_gallery, update_gallery, write_gallery, GalleryEntry, GalleryOptions, GALLERY_INDEX, THUMBNAIL_DIRECTORY};
+pub use hash::{image_hash, record_render_hashes, tile_hashes, RenderManifest, TileHash};
+pub use hdr::{color_results_hdr, write_hdr_image, HdrFormat, HdrImage};
+pub use icc::{
+    apply_display_transform, embed_icc_profile, linear_to_srgb, srgb_icc_profile, srgb_to_linear, validate_icc_profile, ColorProfile,
+    DisplayTransform,
+};
+pub use layers::{layer_paths, split_layers, write_split_layers, RenderLayers};
+pub use reframe::{reframe_bounds, reframe_from_png, PixelRect, PARAMS_KEYWORD};
+pub use scientific::{
+    normalize_percentile, write_scientific_densities, write_scientific_image, Normalization, ScientificFormat, ScientificOutputOptions,
+};
@@ -0,0 +1,198 @@
+//! Re-rendering crops of earlier renders
+//!
+//! A render that carries its parameters can be cropped and rendered again at a higher
+//! resolution or iteration count without hunting for the coordinates by hand. The parameters
+//! are read from a PNG text chunk with the keyword `PARAMS_KEYWORD`, holding `FractalParams` as
+//! JSON, or failing that from the `<name>.hashes.json` manifest written next to the image by
+//! `--tile-hashes`. The image's dimensions come from its PNG header.
+//!
+//! The new bounds follow the pixel mapping of `pixel_to_complex`, where the first and last pixel
+//! of each row and column sit exactly on the bounds. A crop of `w` columns rendered `(w - 1) * k + 1`
+//! pixels wide therefore puts every k-th new pixel on the center of an original pixel
+//! (`PixelRect::scaled_size`), so the upgrade lines up with the old render exactly.
+
+use super::hash::RenderManifest;
+use crate::{pixel_to_complex, FractalError, FractalParams};
+use std::fs::File;
+use std::path::Path;
+
+/// Keyword of the PNG text chunk holding a render's `FractalParams` as JSON
+pub const PARAMS_KEYWORD: &str = "FractalParams";
+
+/// A rectangle of pixels in an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+    /// Column of the top-left pixel
+    pub x: u32,
+    /// Row of the top-left pixel
+    pub y: u32,
+    /// Number of columns
+    pub width: u32,
+    /// Number of rows
+    pub height: u32,
+}
+
+impl PixelRect {
+    /// Parse a rectangle given as "x,y,width,height"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let values: Vec<u32> = spec
+            .split(',')
+            .map(|part| part.trim().parse::<u32>().map_err(|_| format!("Invalid pixel rectangle value '{}'", part.trim())))
+            .collect::<Result<_, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(PixelRect { x, y, width, height }),
+            _ => Err(format!("Pixel rectangle '{}' must be x,y,width,height", spec)),
+        }
+    }
+
+    /// Dimensions of a re-render `scale` times finer that keeps the crop's pixels on the new grid
+    ///
+    /// # Returns
+    ///
+    /// `((width - 1) * scale + 1, (height - 1) * scale + 1)`
+    pub fn scaled_size(&self, scale: u32) -> (u32, u32) {
+        (
+            self.width.saturating_sub(1) * scale + 1,
+            self.height.saturating_sub(1) * scale + 1,
+        )
+    }
+}
+
+/// Bounds of a crop of an image rendered with `bounds`
+///
+/// # Arguments
+///
+/// * `bounds` - Bounds the image was rendered with [x_min, x_max, y_min, y_max]
+/// * `image_width` - Width of the image in pixels
+/// * `image_height` - Height of the image in pixels
+/// * `rect` - The crop, which must lie inside the image and be at least 2x2 pixels
+///
+/// # Returns
+///
+/// Bounds whose first and last pixels are the crop's corner pixels
+pub fn reframe_bounds(bounds: [f64; 4], image_width: u32, image_height: u32, rect: PixelRect) -> Result<[f64; 4], String> {
+    if rect.width < 2 || rect.height < 2 {
+        return Err(format!("Crop {}x{} must be at least 2x2 pixels", rect.width, rect.height));
+    }
+    let right = rect.x.checked_add(rect.width - 1).filter(|&right| right < image_width);
+    let bottom = rect.y.checked_add(rect.height - 1).filter(|&bottom| bottom < image_height);
+    let (Some(right), Some(bottom)) = (right, bottom) else {
+        return Err(format!(
+            "Crop {}x{} at ({}, {}) extends past the {}x{} image",
+            rect.width, rect.height, rect.x, rect.y, image_width, image_height
+        ));
+    };
+    let top_left = pixel_to_complex(rect.x, rect.y, image_width, image_height, bounds);
+    let bottom_right = pixel_to_complex(right, bottom, image_width, image_height, bounds);
+    Ok([top_left.re, bottom_right.re, top_left.im, bottom_right.im])
+}
+
+/// Parameters for re-rendering a crop of an earlier PNG render
+///
+/// Only the bounds change; raise `max_iterations` on the result for a deeper render, and render
+/// it at `rect.scaled_size(scale)` to keep the old pixels on the new grid.
+///
+/// # Arguments
+///
+/// * `path` - The PNG, with a `PARAMS_KEYWORD` text chunk or a `<name>.hashes.json` manifest
+/// * `rect` - The crop in the PNG's pixels
+///
+/// # Returns
+///
+/// The render's parameters with the crop's bounds, `FractalError::IoError` if the PNG or its
+/// manifest can't be read, or `FractalError::InvalidInput` if it carries no parameters or the
+/// crop doesn't fit in it
+pub fn reframe_from_png(path: &str, rect: PixelRect) -> Result<FractalParams, FractalError> {
+    let (width, height, embedded) = read_png_params(path, PARAMS_KEYWORD)?;
+    let json = match embedded {
+        Some(json) => json,
+        None => {
+            let manifest_path = Path::new(path).with_extension("hashes.json");
+            if !manifest_path.exists() {
+                return Err(FractalError::InvalidInput(format!(
+                    "{} has no '{}' text chunk and no manifest at {}",
+                    path,
+                    PARAMS_KEYWORD,
+                    manifest_path.display()
+                )));
+            }
+            let manifest = RenderManifest::read(&manifest_path.to_string_lossy())?;
+            if (manifest.width, manifest.height) != (width, height) {
+                return Err(FractalError::InvalidInput(format!(
+                    "Manifest {} describes a {}x{} image, not {}x{}",
+                    manifest_path.display(),
+                    manifest.width,
+                    manifest.height,
+                    width,
+                    height
+                )));
+            }
+            manifest.params.to_string()
+        }
+    };
+    let mut params: FractalParams =
+        serde_json::from_str(&json).map_err(|e| FractalError::InvalidInput(format!("Invalid parameters in {}: {}", path, e)))?;
+    params.bounds = reframe_bounds(params.bounds, width, height, rect).map_err(FractalError::InvalidInput)?;
+    Ok(params)
+}
+
+/// Dimensions of a PNG and the text of its chunk with the given keyword, such as
+/// `PARAMS_KEYWORD`, if any
+///
+/// Only chunks before the image data are read, which is where encoders put text chunks.
+pub(super) fn read_png_params(path: &str, keyword: &str) -> Result<(u32, u32, Option<String>), FractalError> {
+    let file = File::open(path).map_err(|e| FractalError::io("open", path, e))?;
+    let reader = png::Decoder::new(file).read_info().map_err(|e| match e {
+        png::DecodingError::IoError(e) => FractalError::io("read", path, e),
+        e => FractalError::InvalidInput(format!("Failed to read PNG {}: {}", path, e)),
+    })?;
+    let info = reader.info();
+    let text = info
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == keyword)
+        .map(|chunk| chunk.text.clone())
+        .or_else(|| {
+            info.compressed_latin1_text
+                .iter()
+                .find(|chunk| chunk.keyword == keyword)
+                .and_then(|chunk| chunk.get_text().ok())
+        })
+        .or_else(|| {
+            info.utf8_text
+                .iter()
+                .find(|chunk| chunk.keyword == keyword)
+                .and_then(|chunk| chunk.get_text().ok())
+        });
+    Ok((info.width, info.height, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reframe_embedded_params() {
+        let params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 300, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let path = std::env::temp_dir().join(format!("ftk_reframe_{}.png", std::process::id()));
+        {
+            let file = File::create(&path).unwrap();
+            let mut encoder = png::Encoder::new(file, 31, 31);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.add_text_chunk(PARAMS_KEYWORD.to_string(), serde_json::to_string(&params).unwrap()).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&vec![0; 31 * 31 * 4]).unwrap();
+        }
+
+        // Pixels are 0.1 apart both ways; columns 10..=20 and rows 5..=15
+        let rect = PixelRect::parse("10, 5, 11, 11").unwrap();
+        let reframed = reframe_from_png(&path.to_string_lossy(), rect).unwrap();
+        let expected = [-1.0, 0.0, -1.0, 0.0];
+        assert!(reframed.bounds.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-12), "{:?}", reframed.bounds);
+        assert_eq!(reframed.max_iterations, 300);
+        assert_eq!(rect.scaled_size(4), (41, 41));
+        assert!(reframe_from_png(&path.to_string_lossy(), PixelRect { x: 25, y: 0, width: 10, height: 10 }).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,363 @@
+//! Single-channel normalized output for quantitative analysis
+//!
+//! Instead of a coloured image, these writers store one value per pixel (an iteration count or
+//! an orbit density) scaled to [0, 1] between two percentiles of the data. The normalization
+//! parameters are recorded both inside the file and in a JSON sidecar next to it, so the
+//! original values can be recovered from the pixels:
+//!
+//! `value = low_value + pixel * (high_value - low_value)`, followed by `exp(value) - 1` when
+//! `log_scale` is set. Pixels outside the percentile range are clamped.
+//!
+//! Two formats are supported: 32-bit floating point TIFF, and 16-bit PGM for tools that cannot
+//! read float TIFFs (PGM has no float variant).
+
+use super::atomic::{write_atomic, write_output, OutputOptions};
+use crate::{DensityGrid, FractalError};
+use serde::{Deserialize, Serialize};
+
+/// File format for scientific output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScientificFormat {
+    /// Uncompressed single-channel 32-bit float TIFF
+    Tiff32F,
+    /// Binary 16-bit greyscale PGM (P5)
+    Pgm16,
+}
+
+impl ScientificFormat {
+    /// Pick the format from a file extension (`.tif`, `.tiff` or `.pgm`)
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "tif" | "tiff" => Some(ScientificFormat::Tiff32F),
+            "pgm" => Some(ScientificFormat::Pgm16),
+            _ => None,
+        }
+    }
+}
+
+/// Options for percentile normalization
+#[derive(Debug, Clone)]
+pub struct ScientificOutputOptions {
+    /// Percentile (0-100) mapped to 0
+    pub low_percentile: f64,
+    /// Percentile (0-100) mapped to 1
+    pub high_percentile: f64,
+    /// Normalize ln(1 + value) instead of the value, as the Buddhabrot renderer does for densities
+    pub log_scale: bool,
+    /// Name of the stored quantity, recorded in the metadata (e.g. "iterations", "density")
+    pub quantity: String,
+    /// Write a `.sha256` checksum file next to the image (see `OutputOptions::checksum`)
+    pub checksum: bool,
+}
+
+impl Default for ScientificOutputOptions {
+    fn default() -> Self {
+        Self {
+            low_percentile: 0.0,
+            high_percentile: 99.5,
+            log_scale: false,
+            quantity: "iterations".to_string(),
+            checksum: false,
+        }
+    }
+}
+
+/// Parameters needed to map normalized pixels back to data values
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Normalization {
+    /// Name of the stored quantity
+    pub quantity: String,
+    /// Whether values were log-transformed with ln(1 + value) before normalizing
+    pub log_scale: bool,
+    /// Percentile mapped to 0
+    pub low_percentile: f64,
+    /// Percentile mapped to 1
+    pub high_percentile: f64,
+    /// (Transformed) data value mapped to 0
+    pub low_value: f64,
+    /// (Transformed) data value mapped to 1
+    pub high_value: f64,
+    /// Smallest raw data value
+    pub data_min: f64,
+    /// Largest raw data value
+    pub data_max: f64,
+    /// Image width in pixels
+    pub width: u32,
+    /// Image height in pixels
+    pub height: u32,
+}
+
+/// Value at a percentile of sorted data, using linear interpolation between ranks
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * fraction
+}
+
+/// Normalize data to [0, 1] between two percentiles
+///
+/// # Arguments
+///
+/// * `data` - Row-major pixel values (non-finite values are treated as 0)
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `options` - Percentiles, log scaling and quantity name
+///
+/// # Returns
+///
+/// The normalized values and the parameters used, or an error if the data size doesn't match
+pub fn normalize_percentile(
+    data: &[f64],
+    width: u32,
+    height: u32,
+    options: &ScientificOutputOptions,
+) -> Result<(Vec<f32>, Normalization), String> {
+    if data.len() != width as usize * height as usize {
+        return Err(format!(
+            "Data has {} values but a {}x{} image needs {}",
+            data.len(),
+            width,
+            height,
+            width as usize * height as usize
+        ));
+    }
+    if options.low_percentile >= options.high_percentile {
+        return Err("Low percentile must be below the high percentile".to_string());
+    }
+
+    let raw: Vec<f64> = data.iter().map(|&v| if v.is_finite() { v } else { 0.0 }).collect();
+    let transform = |v: f64| if options.log_scale { v.max(0.0).ln_1p() } else { v };
+    let transformed: Vec<f64> = raw.iter().map(|&v| transform(v)).collect();
+
+    let mut sorted = transformed.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let low_value = percentile(&sorted, options.low_percentile);
+    let high_value = percentile(&sorted, options.high_percentile);
+    let range = high_value - low_value;
+
+    let normalized = transformed
+        .iter()
+        .map(|&v| if range > 0.0 { ((v - low_value) / range).clamp(0.0, 1.0) as f32 } else { 0.0 })
+        .collect();
+
+    let normalization = Normalization {
+        quantity: options.quantity.clone(),
+        log_scale: options.log_scale,
+        low_percentile: options.low_percentile,
+        high_percentile: options.high_percentile,
+        low_value,
+        high_value,
+        data_min: raw.iter().copied().fold(f64::INFINITY, f64::min),
+        data_max: raw.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        width,
+        height,
+    };
+
+    Ok((normalized, normalization))
+}
+
+/// Normalize data and write it as a scientific image with a JSON sidecar
+///
+/// The sidecar is written to the same path with a `.json` extension.
+///
+/// # Arguments
+///
+/// * `path` - Output path; the format is chosen from the extension (`.tif`, `.tiff`, `.pgm`)
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `data` - Row-major pixel values
+/// * `options` - Normalization options
+///
+/// # Returns
+///
+/// The normalization parameters that were recorded, `FractalError::IoError` if a file can't be
+/// written, or `FractalError::InvalidInput` for an unsupported extension or mismatched data
+pub fn write_scientific_image(
+    path: &str,
+    width: u32,
+    height: u32,
+    data: &[f64],
+    options: &ScientificOutputOptions,
+) -> Result<Normalization, FractalError> {
+    let format = ScientificFormat::from_path(path).ok_or_else(|| {
+        FractalError::InvalidInput(format!("Unsupported scientific output extension for '{}' (use .tif or .pgm)", path))
+    })?;
+    let (normalized, normalization) = normalize_percentile(data, width, height, options).map_err(FractalError::InvalidInput)?;
+    let description = serde_json::to_string(&normalization).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+
+    let bytes = match format {
+        ScientificFormat::Tiff32F => encode_tiff_f32(width, height, 1, &normalized, &description)?,
+        ScientificFormat::Pgm16 => encode_pgm16(width, height, &normalized, &description),
+    };
+    write_output(path, &bytes, &OutputOptions { checksum: options.checksum, ..OutputOptions::default() })?;
+
+    let sidecar = std::path::Path::new(path).with_extension("json");
+    let pretty = serde_json::to_string_pretty(&normalization).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+    write_atomic(&sidecar, pretty.as_bytes())?;
+
+    Ok(normalization)
+}
+
+/// Write the orbit densities of a Buddhabrot render as a scientific image
+///
+/// The three channels are summed into the total density of each pixel and normalized on a log
+/// scale, as the renderer does, with the quantity recorded as "density".
+///
+/// # Arguments
+///
+/// * `path` - Output path ending in `.tif`, `.tiff` or `.pgm`
+/// * `densities` - Red, green and blue densities, e.g. from `buddhabrot_densities`
+/// * `checksum` - Write a `.sha256` checksum file next to the image
+///
+/// # Returns
+///
+/// The normalization parameters that were recorded, or an error as from `write_scientific_image`
+pub fn write_scientific_densities(path: &str, [red, green, blue]: &[DensityGrid; 3], checksum: bool) -> Result<Normalization, FractalError> {
+    let total: Vec<f64> = red
+        .as_slice()
+        .iter()
+        .zip(green.as_slice())
+        .zip(blue.as_slice())
+        .map(|((&r, &g), &b)| r + g + b)
+        .collect();
+    let options = ScientificOutputOptions { log_scale: true, quantity: "density".to_string(), checksum, ..Default::default() };
+    write_scientific_image(path, red.width() as u32, red.height() as u32, &total, &options)
+}
+
+/// Encode a little-endian float TIFF of 1 (gray), 3 (RGB) or 4 (RGBA) channels with the
+/// description in ImageDescription
+///
+/// # Returns
+///
+/// The file, or `FractalError::InvalidInput` if it would exceed the 4 GiB that the 32-bit
+/// offsets of a TIFF address
+pub(super) fn encode_tiff_f32(width: u32, height: u32, channels: u16, pixels: &[f32], description: &str) -> Result<Vec<u8>, FractalError> {
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const ASCII: u16 = 2;
+    const STRIP_OFFSETS: u16 = 273;
+    const STRIP_BYTE_COUNTS: u16 = 279;
+
+    let mut description_bytes = description.as_bytes().to_vec();
+    description_bytes.push(0);
+    let shorts = |value: u16| -> Vec<u8> { (0..channels).flat_map(|_| value.to_le_bytes()).collect() };
+    let data_size = pixels.len() as u64 * 4;
+
+    // Tags must be written in ascending order, with their values as little-endian bytes
+    let mut entries: Vec<(u16, u16, u32, Vec<u8>)> = vec![
+        (256, LONG, 1, width.to_le_bytes().to_vec()),                          // ImageWidth
+        (257, LONG, 1, height.to_le_bytes().to_vec()),                         // ImageLength
+        (258, SHORT, channels as u32, shorts(32)),                             // BitsPerSample
+        (259, SHORT, 1, 1u16.to_le_bytes().to_vec()),                          // Compression: none
+        (262, SHORT, 1, (if channels >= 3 { 2u16 } else { 1 }).to_le_bytes().to_vec()), // Photometric: RGB or BlackIsZero
+        (270, ASCII, description_bytes.len() as u32, description_bytes),       // ImageDescription
+        (STRIP_OFFSETS, LONG, 1, vec![0; 4]),                                  // StripOffsets, filled in below
+        (277, SHORT, 1, channels.to_le_bytes().to_vec()),                      // SamplesPerPixel
+        (278, LONG, 1, height.to_le_bytes().to_vec()),                         // RowsPerStrip
+        (STRIP_BYTE_COUNTS, LONG, 1, vec![0; 4]),                              // StripByteCounts, filled in below
+    ];
+    if channels == 4 {
+        entries.push((338, SHORT, 1, 2u16.to_le_bytes().to_vec())); // ExtraSamples: unassociated alpha
+    }
+    entries.push((339, SHORT, channels as u32, shorts(3))); // SampleFormat: IEEE float
+
+    // Layout: header, IFD, values longer than 4 bytes (word aligned), pixel data
+    let ifd_offset = 8u32;
+    let ifd_size = 2 + entries.len() as u32 * 12 + 4;
+    let external_size: u64 = entries.iter().filter(|entry| entry.3.len() > 4).map(|entry| entry.3.len().next_multiple_of(2) as u64).sum();
+    // Sizes are added in 64 bits, since an image of more than a gigapixel overflows 32
+    let data_offset = (ifd_offset + ifd_size) as u64 + external_size;
+    if data_offset + data_size > u32::MAX as u64 {
+        return Err(FractalError::InvalidInput(format!(
+            "A {}x{} float TIFF of {} channels exceeds the 4 GiB a TIFF can hold",
+            width, height, channels
+        )));
+    }
+    let data_offset = data_offset as u32;
+    for entry in entries.iter_mut() {
+        match entry.0 {
+            STRIP_OFFSETS => entry.3 = data_offset.to_le_bytes().to_vec(),
+            STRIP_BYTE_COUNTS => entry.3 = (data_size as u32).to_le_bytes().to_vec(),
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::with_capacity((data_offset as u64 + data_size) as usize);
+    out.extend_from_slice(b"II");
+    out.extend_from_slice(&42u16.to_le_bytes());
+    out.extend_from_slice(&ifd_offset.to_le_bytes());
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut external = Vec::new();
+    for (tag, field_type, count, value) in &entries {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&field_type.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        if value.len() <= 4 {
+            // Values of 4 bytes or less are stored inline, left-justified
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(ifd_offset + ifd_size + external.len() as u32).to_le_bytes());
+            external.extend_from_slice(value);
+            external.resize(external.len().next_multiple_of(2), 0);
+        }
+    }
+    out.extend_from_slice(&0u32.to_le_bytes()); // No further IFDs
+
+    out.extend_from_slice(&external);
+    for value in pixels {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// Encode a binary 16-bit PGM with the description in a header comment
+fn encode_pgm16(width: u32, height: u32, pixels: &[f32], description: &str) -> Vec<u8> {
+    let mut out = format!("P5\n# {}\n{} {}\n65535\n", description, width, height).into_bytes();
+    out.reserve(pixels.len() * 2);
+    for value in pixels {
+        let level = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        out.extend_from_slice(&level.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_normalization_records_parameters() {
+        let data: Vec<f64> = (0..100).map(|v| v as f64).collect();
+        let options = ScientificOutputOptions { low_percentile: 10.0, high_percentile: 90.0, ..Default::default() };
+        let (normalized, normalization) = normalize_percentile(&data, 10, 10, &options).unwrap();
+
+        assert!((normalization.low_value - 9.9).abs() < 1e-9);
+        assert!((normalization.high_value - 89.1).abs() < 1e-9);
+        assert_eq!(normalization.data_max, 99.0);
+        assert_eq!(normalized[0], 0.0);
+        assert_eq!(normalized[99], 1.0);
+        assert!((normalized[50] - ((50.0 - 9.9) / 79.2) as f32).abs() < 1e-6);
+
+        assert!(normalize_percentile(&data, 10, 9, &options).is_err());
+    }
+
+    #[test]
+    fn test_scientific_densities_sum_the_channels() {
+        let densities = [DensityGrid::filled(2, 1, 1.0), DensityGrid::filled(2, 1, 2.0), DensityGrid::filled(2, 1, 4.0)];
+        let path = std::env::temp_dir().join(format!("ftk_scientific_densities_{}.pgm", std::process::id())).to_string_lossy().into_owned();
+        let normalization = write_scientific_densities(&path, &densities, false).unwrap();
+        assert_eq!(normalization.quantity, "density");
+        assert!(normalization.log_scale);
+        assert_eq!(normalization.data_max, 7.0);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(std::path::Path::new(&path).with_extension("json")).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+//! Palette files from other fractal and image programs
+//!
+//! `parse_color_palette` loads a palette from a file when given a path ending in one of these
+//! extensions:
+//!
+//! - `.map`: Fractint and Ultra Fractal color maps, one `R G B` line (0-255) per color, usually
+//!   256 of them, spaced evenly along the palette. Anything after the third number is a comment.
+//! - `.ggr`: GIMP gradients, segments with their own endpoints, midpoint and blending curve. The
+//!   gradient is sampled at `GGR_SAMPLES` points; alpha is dropped and HSV blending is treated as
+//!   RGB.
+//! - `.json`: a list of stops as written to `FractalPalette` PNG chunks and frame sidecars,
+//!   `[{"color": [r, g, b], "position": 0.0}, ...]`.
+
+use crate::{ColorStop, Easing};
+use std::path::Path;
+
+/// Points a GIMP gradient is sampled at
+const GGR_SAMPLES: usize = 256;
+
+/// Extensions `load_palette_file` reads
+pub const PALETTE_FILE_EXTENSIONS: &[&str] = &["map", "ggr", "json"];
+
+/// Whether a palette option names a palette file, by its extension
+pub fn is_palette_file(spec: &str) -> bool {
+    Path::new(spec.trim())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| PALETTE_FILE_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
+
+/// Load a `.map`, `.ggr` or `.json` palette file
+///
+/// # Arguments
+///
+/// * `path` - The file; its extension chooses the format
+///
+/// # Returns
+///
+/// The palette's stops sorted by position, or an error if the file can't be read or parsed
+pub fn load_palette_file(path: &str) -> Result<Vec<ColorStop>, String> {
+    let path = path.trim();
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read palette file '{}': {}", path, e))?;
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    let mut stops = match extension.as_str() {
+        "map" => parse_map_palette(&contents),
+        "ggr" => parse_ggr_palette(&contents),
+        "json" => serde_json::from_str::<Vec<ColorStop>>(&contents).map_err(|e| e.to_string()),
+        _ => Err(format!("unknown palette file extension (expected one of {})", PALETTE_FILE_EXTENSIONS.join(", "))),
+    }
+    .map_err(|e| format!("Invalid palette file '{}': {}", path, e))?;
+    if stops.is_empty() {
+        return Err(format!("Palette file '{}' holds no colors", path));
+    }
+    stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap());
+    Ok(stops)
+}
+
+/// Parse a Fractint/Ultra Fractal `.map` color map
+pub fn parse_map_palette(contents: &str) -> Result<Vec<ColorStop>, String> {
+    let mut colors = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        let components: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map(|value| value.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("line {}: expected three components from 0 to 255", number + 1))?;
+        match components[..] {
+            [r, g, b] => colors.push([r, g, b]),
+            _ => return Err(format!("line {}: expected three components from 0 to 255", number + 1)),
+        }
+    }
+    let last = (colors.len().max(2) - 1) as f64;
+    Ok(colors.into_iter().enumerate().map(|(i, color)| ColorStop { color, position: i as f64 / last, easing: Easing::Linear }).collect())
+}
+
+/// One segment of a GIMP gradient
+struct GgrSegment {
+    left: f64,
+    middle: f64,
+    right: f64,
+    left_color: [f64; 3],
+    right_color: [f64; 3],
+    blending: u32,
+}
+
+impl GgrSegment {
+    /// Color at `position`, which lies in the segment
+    fn color_at(&self, position: f64) -> [f64; 3] {
+        let width = self.right - self.left;
+        let (t, middle) = match width > 0.0 {
+            true => ((position - self.left) / width, (self.middle - self.left) / width),
+            false => (0.5, 0.5),
+        };
+        // The midpoint is where the blend is halfway
+        let linear = if t <= middle {
+            if middle > 0.0 { 0.5 * t / middle } else { 0.5 }
+        } else if middle < 1.0 {
+            0.5 + 0.5 * (t - middle) / (1.0 - middle)
+        } else {
+            1.0
+        };
+        let factor = match self.blending {
+            1 => t.powf(0.5f64.ln() / middle.max(1e-10).ln()),
+            2 => ((std::f64::consts::PI * linear - std::f64::consts::FRAC_PI_2).sin() + 1.0) / 2.0,
+            3 => (1.0 - (linear - 1.0) * (linear - 1.0)).sqrt(),
+            4 => 1.0 - (1.0 - linear * linear).sqrt(),
+            5 => (t >= middle) as u8 as f64,
+            _ => linear,
+        };
+        std::array::from_fn(|i| self.left_color[i] + (self.right_color[i] - self.left_color[i]) * factor)
+    }
+}
+
+/// Parse a GIMP `.ggr` gradient
+pub fn parse_ggr_palette(contents: &str) -> Result<Vec<ColorStop>, String> {
+    let mut lines = contents.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        return Err("missing the 'GIMP Gradient' header".to_string());
+    }
+    let mut count_line = lines.next().ok_or("missing the segment count")?;
+    if count_line.starts_with("Name:") {
+        count_line = lines.next().ok_or("missing the segment count")?;
+    }
+    let count: usize = count_line.parse().map_err(|_| format!("invalid segment count '{}'", count_line))?;
+
+    let mut segments = Vec::with_capacity(count);
+    for index in 0..count {
+        let line = lines.next().ok_or_else(|| format!("expected {} segments, found {}", count, index))?;
+        let values: Vec<f64> = line
+            .split_whitespace()
+            .map(|value| value.parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("segment {}: invalid number", index + 1))?;
+        if values.len() < 13 {
+            return Err(format!("segment {}: expected at least 13 values, found {}", index + 1, values.len()));
+        }
+        segments.push(GgrSegment {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            left_color: [values[3], values[4], values[5]],
+            right_color: [values[7], values[8], values[9]],
+            blending: values[11] as u32,
+        });
+    }
+    if segments.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok((0..GGR_SAMPLES)
+        .map(|i| {
+            let position = i as f64 / (GGR_SAMPLES - 1) as f64;
+            let segment = segments.iter().find(|segment| position <= segment.right).unwrap_or(&segments[segments.len() - 1]);
+            let color = segment.color_at(position.clamp(segment.left, segment.right)).map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            ColorStop { color, position, easing: Easing::Linear }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_ggr_and_json_palettes() {
+        let map = parse_map_palette("0 0 0 black\n; comment\n255 128 0\n255 255 255 white\n").unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!((map[1].color, map[1].position), ([255, 128, 0], 0.5));
+        assert!(parse_map_palette("0 0\n").is_err());
+
+        // Black to red in the first half, linear; red to white in the second, stepped at 0.75
+        let ggr = "GIMP Gradient\nName: Test\n2\n\
+                   0 0.25 0.5 0 0 0 1 1 0 0 1 0 0\n\
+                   0.5 0.75 1 1 0 0 1 1 1 1 1 5 0\n";
+        let stops = parse_ggr_palette(ggr).unwrap();
+        let at = |position: f64| stops.iter().min_by(|a, b| (a.position - position).abs().total_cmp(&(b.position - position).abs())).unwrap().color;
+        assert_eq!(at(0.0), [0, 0, 0]);
+        assert_eq!(at(0.25), [128, 0, 0]);
+        assert_eq!(at(0.7), [255, 0, 0]);
+        assert_eq!(at(0.8), [255, 255, 255]);
+
+        let path = std::env::temp_dir().join(format!("ftk_palette_{}.json", std::process::id()));
+        std::fs::write(&path, r#"[{"color": [255, 255, 255], "position": 1.0}, {"color": [0, 0, 0], "position": 0.0}]"#).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        assert!(is_palette_file(&path));
+        let json = load_palette_file(&path).unwrap();
+        assert_eq!((json[0].color, json[1].color), ([0, 0, 0], [255, 255, 255]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
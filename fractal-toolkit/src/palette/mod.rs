@@ -0,0 +1,48 @@
+//! Palettes beyond the ad-hoc stop lists of `parse_color_palette`
+//!
+//! `presets` holds the built-in palettes selectable by name (`inferno`, `viridis`,
+//! `ultrafractal`, ...) and `files` reads palettes saved by other programs (`.map`, `.ggr`) or
+//! as JSON; `parse_color_palette` accepts both in place of a stop list. The `phase` submodule
+//! holds cyclic palettes for coloring angles, such as the phase of a domain coloring plot, where
+//! the colors at 0 and 2π must meet without a seam. `rotate_palette` shifts a stop list along the
+//! palette, for animating its offset.
+
+pub mod files;
+pub mod phase;
+pub mod presets;
+
+pub use files::{is_palette_file, load_palette_file, parse_ggr_palette, parse_map_palette, PALETTE_FILE_EXTENSIONS};
+pub use phase::PhaseWheel;
+pub use presets::{preset_palette, PRESET_NAMES};
+
+use crate::{interpolate_color_from_palette, ColorStop, Easing};
+
+/// Stops `rotate_palette` samples the shifted palette at
+const ROTATION_SAMPLES: usize = 256;
+
+/// A palette shifted along itself, wrapping around at the end
+///
+/// The color at position t of the result is the color at `t + offset` of `palette`, taken
+/// modulo 1. Palettes whose first and last stops differ show a seam where they wrap.
+///
+/// # Arguments
+///
+/// * `palette` - The palette to shift
+/// * `offset` - Shift as a fraction of the palette; whole numbers give the palette back
+///
+/// # Returns
+///
+/// The shifted palette, sampled at evenly spaced stops
+pub fn rotate_palette(palette: &[ColorStop], offset: f64) -> Vec<ColorStop> {
+    let offset = offset.rem_euclid(1.0);
+    if palette.len() < 2 || offset == 0.0 {
+        return palette.to_vec();
+    }
+    (0..ROTATION_SAMPLES)
+        .map(|i| {
+            let position = i as f64 / (ROTATION_SAMPLES - 1) as f64;
+            let color = interpolate_color_from_palette((position + offset).rem_euclid(1.0), palette);
+            ColorStop { color: [color[0], color[1], color[2]], position, easing: Easing::Linear }
+        })
+        .collect()
+}
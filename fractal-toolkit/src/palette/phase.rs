@@ -0,0 +1,152 @@
+//! Cyclic palettes for coloring phases and angles
+//!
+//! Domain coloring maps the argument of f(z) to a hue on the HSV wheel, whose pure primaries
+//! make some phases look much brighter than others and draw false edges at yellow and cyan. A
+//! `PhaseWheel` replaces that wheel with any cyclic palette: a built-in perceptually smoother
+//! colormap (`twilight`, `hsv-lite`), a palette in the usual `[(#hex,pos),...]` form, or a
+//! colormap file with one RGB color per line, as exported from matplotlib or other tools. The
+//! colors are sampled evenly around the circle and the last one blends back into the first, so
+//! a palette need not repeat its first color at the end.
+//!
+//! The same wheel colors the other angle-based modes: the hue of `ftk-mandel --domain-color` and
+//! `InteriorColoring::Angle`.
+
+use crate::{hsv_to_rgb, interpolate_color_from_palette, parse_color_palette};
+use serde::{Deserialize, Serialize};
+
+/// Colors sampled from a palette or colormap file, around the wheel
+const WHEEL_SAMPLES: usize = 256;
+
+/// Matplotlib's `twilight`, sampled at eighths of the circle
+const TWILIGHT: [[u8; 3]; 8] = [
+    [226, 217, 226],
+    [149, 168, 199],
+    [96, 117, 186],
+    [92, 63, 150],
+    [47, 20, 54],
+    [129, 42, 84],
+    [180, 86, 77],
+    [207, 154, 131],
+];
+
+/// A cyclic palette indexed by phase
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseWheel {
+    /// The fully saturated HSV hue wheel
+    #[default]
+    Hsv,
+    /// Colors spaced evenly around the circle, interpolated linearly and cyclically
+    Colors(Vec<[u8; 3]>),
+}
+
+impl PhaseWheel {
+    /// Matplotlib's `twilight`: light at phase 0, dark at π, blue and red in between
+    pub fn twilight() -> Self {
+        PhaseWheel::Colors(TWILIGHT.to_vec())
+    }
+
+    /// The HSV wheel at reduced saturation and value, with softer primaries
+    pub fn hsv_lite() -> Self {
+        PhaseWheel::Colors((0..WHEEL_SAMPLES).map(|i| hsv_to_rgb(i as f64 / WHEEL_SAMPLES as f64, 0.55, 0.9)).collect())
+    }
+
+    /// Parse a wheel: `hsv`, `hsv-lite`, `twilight`, a palette such as `[(#000000,0),(#FFFFFF,0.5)]`,
+    /// or the path of a colormap file
+    ///
+    /// Colormap files hold one color per line, as three components separated by commas or
+    /// whitespace, either all between 0 and 1 or between 0 and 255. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        match spec.to_lowercase().replace('_', "-").as_str() {
+            "hsv" => return Ok(PhaseWheel::Hsv),
+            "hsv-lite" => return Ok(PhaseWheel::hsv_lite()),
+            "twilight" => return Ok(PhaseWheel::twilight()),
+            _ => {}
+        }
+        if spec.starts_with('[') || spec.starts_with('(') {
+            let palette = parse_color_palette(spec)?;
+            let colors = (0..WHEEL_SAMPLES)
+                .map(|i| {
+                    let color = interpolate_color_from_palette(i as f64 / WHEEL_SAMPLES as f64, &palette);
+                    [color[0], color[1], color[2]]
+                })
+                .collect();
+            return Ok(PhaseWheel::Colors(colors));
+        }
+        let contents = std::fs::read_to_string(spec)
+            .map_err(|e| format!("Unknown phase wheel '{}' (expected hsv, hsv-lite, twilight, a palette or a colormap file): {}", spec, e))?;
+        Self::from_colormap(&contents)
+    }
+
+    /// Read a colormap with one RGB color per line (see `parse`)
+    pub fn from_colormap(contents: &str) -> Result<Self, String> {
+        let rows = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let values: Vec<f64> = line
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|part| !part.is_empty())
+                    .map(|part| part.parse::<f64>().map_err(|_| format!("Invalid colormap value '{}'", part)))
+                    .collect::<Result<_, _>>()?;
+                match values[..] {
+                    [r, g, b] => Ok([r, g, b]),
+                    _ => Err(format!("Invalid colormap line '{}' (expected three components)", line)),
+                }
+            })
+            .collect::<Result<Vec<[f64; 3]>, String>>()?;
+        if rows.is_empty() {
+            return Err("Colormap has no colors".to_string());
+        }
+        let scale = if rows.iter().flatten().all(|&v| v <= 1.0) { 255.0 } else { 1.0 };
+        Ok(PhaseWheel::Colors(
+            rows.iter().map(|row| row.map(|v| (v * scale).round().clamp(0.0, 255.0) as u8)).collect(),
+        ))
+    }
+
+    /// Color of a phase
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - Position around the wheel; 0 and 1 (and any whole number) are the same color
+    /// * `value` - Brightness from 0 (black) to 1 (the wheel's color)
+    pub fn color(&self, phase: f64, value: f64) -> [u8; 3] {
+        let colors = match self {
+            PhaseWheel::Hsv => return hsv_to_rgb(phase.rem_euclid(1.0), 1.0, value),
+            PhaseWheel::Colors(colors) if !colors.is_empty() => colors,
+            PhaseWheel::Colors(_) => return [0, 0, 0],
+        };
+        let position = phase.rem_euclid(1.0) * colors.len() as f64;
+        let index = (position.floor() as usize).min(colors.len() - 1);
+        let fraction = position - index as f64;
+        let (from, to) = (colors[index], colors[(index + 1) % colors.len()]);
+        [0, 1, 2].map(|k| ((from[k] as f64 * (1.0 - fraction) + to[k] as f64 * fraction) * value.clamp(0.0, 1.0)).round() as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_wheels_wrap_around() {
+        assert_eq!(PhaseWheel::parse("HSV"), Ok(PhaseWheel::Hsv));
+        assert_eq!(PhaseWheel::Hsv.color(0.25, 1.0), hsv_to_rgb(0.25, 1.0, 1.0));
+        assert!(PhaseWheel::parse("no-such-wheel").is_err());
+
+        // The last color blends back into the first, and phases wrap around
+        let wheel = PhaseWheel::from_colormap("# r g b\n1 0 0\n0 0 1\n").unwrap();
+        assert_eq!(wheel, PhaseWheel::Colors(vec![[255, 0, 0], [0, 0, 255]]));
+        assert_eq!(wheel.color(0.25, 1.0), [128, 0, 128]);
+        assert_eq!(wheel.color(0.75, 1.0), [128, 0, 128]);
+        assert_eq!(wheel.color(1.5, 0.5), [0, 0, 128]);
+
+        let twilight = PhaseWheel::parse("twilight").unwrap();
+        assert_eq!(twilight.color(0.0, 1.0), twilight.color(1.0, 1.0));
+        let palette = PhaseWheel::parse("[(#000000,0),(#FFFFFF,1)]").unwrap();
+        assert_eq!(palette.color(0.0, 1.0), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Built-in palettes selectable by name
+//!
+//! `parse_color_palette` accepts these names in place of a stop list, e.g.
+//! `--color-pallette inferno`. The perceptual colormaps (`inferno`, `magma`, `viridis`) are
+//! matplotlib's, sampled at eighths; they rise steadily in lightness, so escape-time detail reads
+//! the same in grayscale and to colour-blind viewers. `ultrafractal` is the blue, white and gold
+//! default gradient of Ultra Fractal, and `fire` and `ice` are gradients in the same style. Those
+//! three end on the color they start with, so they cycle without a seam.
+
+use crate::{ColorStop, Easing};
+
+/// Names of the built-in palettes, in the order `--help` lists them
+pub const PRESET_NAMES: &[&str] = &["grayscale", "inferno", "magma", "viridis", "ultrafractal", "fire", "ice"];
+
+/// Matplotlib's `inferno`: black through purple and orange to pale yellow
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 142, 9],
+    [248, 201, 50],
+    [252, 255, 164],
+];
+
+/// Matplotlib's `magma`: black through purple and pink to cream
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+/// Matplotlib's `viridis`: dark purple through teal to yellow
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 45, 123],
+    [59, 82, 139],
+    [44, 114, 142],
+    [33, 145, 140],
+    [40, 174, 128],
+    [94, 201, 98],
+    [173, 220, 48],
+    [253, 231, 37],
+];
+
+/// Ultra Fractal's default gradient
+const ULTRAFRACTAL: [([u8; 3], f64); 6] = [
+    ([0, 7, 100], 0.0),
+    ([32, 107, 203], 0.16),
+    ([237, 255, 255], 0.42),
+    ([255, 170, 0], 0.6425),
+    ([0, 2, 0], 0.8575),
+    ([0, 7, 100], 1.0),
+];
+
+/// Black through red and orange to pale yellow, and back to black
+const FIRE: [([u8; 3], f64); 6] = [
+    ([0, 0, 0], 0.0),
+    ([128, 0, 0], 0.2),
+    ([255, 64, 0], 0.4),
+    ([255, 200, 40], 0.6),
+    ([255, 255, 220], 0.75),
+    ([0, 0, 0], 1.0),
+];
+
+/// Deep blue through sky blue to white, and back to deep blue
+const ICE: [([u8; 3], f64); 6] = [
+    ([0, 4, 24], 0.0),
+    ([10, 50, 110], 0.25),
+    ([80, 160, 220], 0.5),
+    ([235, 250, 255], 0.7),
+    ([120, 140, 200], 0.85),
+    ([0, 4, 24], 1.0),
+];
+
+fn evenly_spaced(colors: &[[u8; 3]]) -> Vec<ColorStop> {
+    let last = (colors.len() - 1).max(1) as f64;
+    colors.iter().enumerate().map(|(i, &color)| ColorStop { color, position: i as f64 / last, easing: Easing::Linear }).collect()
+}
+
+fn positioned(stops: &[([u8; 3], f64)]) -> Vec<ColorStop> {
+    stops.iter().map(|&(color, position)| ColorStop { color, position, easing: Easing::Linear }).collect()
+}
+
+/// A built-in palette by name
+///
+/// Names are case-insensitive, and `gray`/`greyscale` are accepted for `grayscale`.
+///
+/// # Returns
+///
+/// The palette's stops, or `None` if no preset has that name
+pub fn preset_palette(name: &str) -> Option<Vec<ColorStop>> {
+    let stops = match name.trim().to_lowercase().as_str() {
+        "grayscale" | "greyscale" | "gray" | "grey" => evenly_spaced(&[[0, 0, 0], [255, 255, 255]]),
+        "inferno" => evenly_spaced(&INFERNO),
+        "magma" => evenly_spaced(&MAGMA),
+        "viridis" => evenly_spaced(&VIRIDIS),
+        "ultrafractal" | "uf" => positioned(&ULTRAFRACTAL),
+        "fire" => positioned(&FIRE),
+        "ice" => positioned(&ICE),
+        _ => return None,
+    };
+    Some(stops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_span_the_palette() {
+        for name in PRESET_NAMES {
+            let stops = preset_palette(name).unwrap();
+            assert_eq!(stops.first().unwrap().position, 0.0, "{}", name);
+            assert_eq!(stops.last().unwrap().position, 1.0, "{}", name);
+            assert!(stops.windows(2).all(|pair| pair[0].position < pair[1].position), "{}", name);
+        }
+        assert_eq!(preset_palette("Inferno").unwrap()[8].color, [252, 255, 164]);
+        assert!(preset_palette("plasma").is_none());
+    }
+}
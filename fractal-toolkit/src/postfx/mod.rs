@@ -0,0 +1,254 @@
+//! Post-processing effects applied to rendered images
+//!
+//! Effects run in the order they are listed in the render parameters (`FractalParams::post_effects`,
+//! `BuddhabrotParams::post_effects`), each on the output of the previous one:
+//!
+//! - `Bloom` spreads the brightest parts of the image into a soft glow, which suits the dense
+//!   highlights of Buddhabrot renders
+//! - `UnsharpMask` sharpens fine escape-time detail by adding back the difference between the
+//!   image and a blurred copy
+//! - `Vignette` darkens the image towards its corners
+//!
+//! On the command line an effect list is written as `name:key=value,...` entries separated by
+//! `;`, e.g. `bloom:threshold=0.6,radius=8;vignette:strength=0.4`. Omitted values take defaults.
+
+use image::{RgbImage, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One post-processing step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum PostEffect {
+    /// Add a blurred copy of the parts brighter than `threshold` back onto the image
+    Bloom {
+        /// Brightness (0 to 1) above which pixels glow
+        threshold: f64,
+        /// Standard deviation of the glow in pixels
+        radius: f64,
+        /// Weight of the glow added to the image
+        strength: f64,
+    },
+    /// Sharpen by adding `amount` times the difference between the image and a blurred copy
+    UnsharpMask {
+        /// Standard deviation of the blur in pixels
+        radius: f64,
+        /// Weight of the added detail
+        amount: f64,
+        /// Differences smaller than this (0 to 1) are left alone, so flat areas stay smooth
+        threshold: f64,
+    },
+    /// Darken towards the corners
+    Vignette {
+        /// Darkening at the corners, from 0 (none) to 1 (black)
+        strength: f64,
+        /// Distance from the center, as a fraction of the half diagonal, where darkening starts
+        radius: f64,
+    },
+}
+
+impl PostEffect {
+    /// Parse one effect written as `name` or `name:key=value,...`
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - e.g. `bloom:threshold=0.6,radius=8,strength=0.8`, `unsharp:amount=0.5` or `vignette`
+    ///
+    /// # Returns
+    ///
+    /// The effect with defaults for omitted values, or an error naming the unknown effect or key
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, arguments) = spec.trim().split_once(':').unwrap_or((spec.trim(), ""));
+        let mut effect = match name.to_lowercase().as_str() {
+            "bloom" | "glow" => PostEffect::Bloom { threshold: 0.6, radius: 8.0, strength: 0.8 },
+            "unsharp" | "unsharp_mask" | "sharpen" => PostEffect::UnsharpMask { radius: 1.5, amount: 0.6, threshold: 0.0 },
+            "vignette" => PostEffect::Vignette { strength: 0.5, radius: 0.5 },
+            _ => return Err(format!("Unknown post effect: '{}' (expected bloom, unsharp or vignette)", name)),
+        };
+
+        for pair in arguments.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value in post effect '{}', found '{}'", name, pair))?;
+            let value: f64 = value.trim().parse().map_err(|_| format!("Invalid value for {}: '{}'", key.trim(), value.trim()))?;
+            let slot = match (&mut effect, key.trim().to_lowercase().as_str()) {
+                (PostEffect::Bloom { threshold, .. }, "threshold") => threshold,
+                (PostEffect::Bloom { radius, .. }, "radius") => radius,
+                (PostEffect::Bloom { strength, .. }, "strength") => strength,
+                (PostEffect::UnsharpMask { radius, .. }, "radius") => radius,
+                (PostEffect::UnsharpMask { amount, .. }, "amount") => amount,
+                (PostEffect::UnsharpMask { threshold, .. }, "threshold") => threshold,
+                (PostEffect::Vignette { strength, .. }, "strength") => strength,
+                (PostEffect::Vignette { radius, .. }, "radius") => radius,
+                (_, key) => return Err(format!("Unknown setting '{}' for post effect '{}'", key, name)),
+            };
+            *slot = value;
+        }
+        Ok(effect)
+    }
+}
+
+/// Parse a `;`-separated list of effects, see `PostEffect::parse`
+pub fn parse_post_effects(spec: &str) -> Result<Vec<PostEffect>, String> {
+    spec.split(';').filter(|effect| !effect.trim().is_empty()).map(PostEffect::parse).collect()
+}
+
+/// Apply effects in order to an RGBA image, leaving the alpha channel unchanged
+pub fn apply_post_effects(image: &mut RgbaImage, effects: &[PostEffect]) {
+    if effects.is_empty() {
+        return;
+    }
+    let mut planes = Planes::from_pixels(image.width(), image.height(), image.pixels().map(|p| [p[0], p[1], p[2]]));
+    planes.apply(effects);
+    for (pixel, color) in image.pixels_mut().zip(planes.to_bytes()) {
+        pixel[0] = color[0];
+        pixel[1] = color[1];
+        pixel[2] = color[2];
+    }
+}
+
+/// Apply effects in order to an RGB image, such as a Buddhabrot render
+pub fn apply_post_effects_rgb(image: &mut RgbImage, effects: &[PostEffect]) {
+    if effects.is_empty() {
+        return;
+    }
+    let mut planes = Planes::from_pixels(image.width(), image.height(), image.pixels().map(|p| p.0));
+    planes.apply(effects);
+    for (pixel, color) in image.pixels_mut().zip(planes.to_bytes()) {
+        pixel.0 = color;
+    }
+}
+
+/// An RGB image with channels from 0 to 1, in row-major order
+#[derive(Clone)]
+struct Planes {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Planes {
+    fn from_pixels(width: u32, height: u32, pixels: impl Iterator<Item = [u8; 3]>) -> Self {
+        let pixels = pixels.map(|p| p.map(|channel| channel as f32 / 255.0)).collect();
+        Self { width: width as usize, height: height as usize, pixels }
+    }
+
+    fn to_bytes(&self) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.pixels.iter().map(|p| p.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }
+
+    fn apply(&mut self, effects: &[PostEffect]) {
+        for effect in effects {
+            match *effect {
+                PostEffect::Bloom { threshold, radius, strength } => self.bloom(threshold as f32, radius, strength as f32),
+                PostEffect::UnsharpMask { radius, amount, threshold } => self.unsharp_mask(radius, amount as f32, threshold as f32),
+                PostEffect::Vignette { strength, radius } => self.vignette(strength as f32, radius as f32),
+            }
+        }
+    }
+
+    fn bloom(&mut self, threshold: f32, radius: f64, strength: f32) {
+        // Keep only the part of each pixel's brightness above the threshold
+        let mut bright = self.clone();
+        let range = (1.0 - threshold).max(1e-6);
+        for pixel in &mut bright.pixels {
+            let luminance = 0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2];
+            let excess = ((luminance - threshold) / range).clamp(0.0, 1.0);
+            *pixel = pixel.map(|channel| channel * excess);
+        }
+        let glow = bright.gaussian_blur(radius);
+        for (pixel, glow) in self.pixels.iter_mut().zip(&glow.pixels) {
+            for channel in 0..3 {
+                pixel[channel] += strength * glow[channel];
+            }
+        }
+    }
+
+    fn unsharp_mask(&mut self, radius: f64, amount: f32, threshold: f32) {
+        let blurred = self.gaussian_blur(radius);
+        for (pixel, blurred) in self.pixels.iter_mut().zip(&blurred.pixels) {
+            for channel in 0..3 {
+                let detail = pixel[channel] - blurred[channel];
+                if detail.abs() >= threshold {
+                    pixel[channel] += amount * detail;
+                }
+            }
+        }
+    }
+
+    fn vignette(&mut self, strength: f32, radius: f32) {
+        let (center_x, center_y) = ((self.width as f32 - 1.0) / 2.0, (self.height as f32 - 1.0) / 2.0);
+        let half_diagonal = center_x.hypot(center_y).max(1.0);
+        let width = self.width;
+        self.pixels.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+            let (x, y) = ((index % width) as f32, (index / width) as f32);
+            let distance = (x - center_x).hypot(y - center_y) / half_diagonal;
+            let falloff = ((distance - radius) / (1.0 - radius).max(1e-6)).clamp(0.0, 1.0);
+            let factor = 1.0 - strength * falloff * falloff;
+            *pixel = pixel.map(|channel| channel * factor);
+        });
+    }
+
+    /// Separable Gaussian blur with standard deviation `sigma` pixels, clamping at the edges
+    fn gaussian_blur(&self, sigma: f64) -> Planes {
+        if sigma <= 0.0 || self.pixels.is_empty() {
+            return self.clone();
+        }
+        let reach = (3.0 * sigma).ceil() as isize;
+        let mut kernel: Vec<f32> = (-reach..=reach).map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp() as f32).collect();
+        let total: f32 = kernel.iter().sum();
+        kernel.iter_mut().for_each(|weight| *weight /= total);
+
+        let (width, height) = (self.width as isize, self.height as isize);
+        let blur_pass = |source: &[[f32; 3]], horizontal: bool| -> Vec<[f32; 3]> {
+            (0..source.len())
+                .into_par_iter()
+                .map(|index| {
+                    let (x, y) = (index as isize % width, index as isize / width);
+                    let mut sum = [0.0f32; 3];
+                    for (offset, weight) in (-reach..=reach).zip(&kernel) {
+                        let (sx, sy) = if horizontal {
+                            ((x + offset).clamp(0, width - 1), y)
+                        } else {
+                            (x, (y + offset).clamp(0, height - 1))
+                        };
+                        let sample = source[(sy * width + sx) as usize];
+                        for channel in 0..3 {
+                            sum[channel] += weight * sample[channel];
+                        }
+                    }
+                    sum
+                })
+                .collect()
+        };
+        let horizontal = blur_pass(&self.pixels, true);
+        Planes { width: self.width, height: self.height, pixels: blur_pass(&horizontal, false) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effects_parse_and_apply_in_order() {
+        let effects = parse_post_effects("bloom:radius=2,strength=1; vignette:strength=1,radius=0").unwrap();
+        assert_eq!(effects[0], PostEffect::Bloom { threshold: 0.6, radius: 2.0, strength: 1.0 });
+        assert!(parse_post_effects("blur").is_err());
+        assert!(parse_post_effects("vignette:amount=1").is_err());
+
+        // A single bright pixel glows onto its neighbours, and the vignette blacks out the corners
+        let mut image = RgbImage::new(21, 21);
+        image.put_pixel(10, 10, image::Rgb([255, 255, 255]));
+        apply_post_effects_rgb(&mut image, &effects);
+        assert!(image.get_pixel(11, 10)[0] > 0);
+        assert_eq!(image.get_pixel(10, 10)[0], 255);
+        assert_eq!(image.get_pixel(0, 0)[0], 0);
+
+        // Unsharp masking leaves flat areas alone and steepens edges
+        let mut edge = RgbaImage::from_fn(8, 1, |x, _| if x < 4 { image::Rgba([100, 100, 100, 7]) } else { image::Rgba([200, 200, 200, 7]) });
+        apply_post_effects(&mut edge, &[PostEffect::UnsharpMask { radius: 1.0, amount: 1.0, threshold: 0.0 }]);
+        assert_eq!(edge.get_pixel(0, 0).0, [100, 100, 100, 7]);
+        assert!(edge.get_pixel(3, 0)[0] < 100 && edge.get_pixel(4, 0)[0] > 200);
+    }
+}
@@ -0,0 +1,263 @@
+//! Adaptive rendering by quadtree subdivision
+//!
+//! Most of the time of a render goes into regions where every pixel gets the same result: the
+//! interior of the set, which iterates to `max_iterations` everywhere, and the wide bands of
+//! equal iteration count away from it. The quadtree renderer splits the image into cells, iterates
+//! the corners and the center of each, and fills a cell whose five samples agree (same iteration
+//! count, all escaped or all bounded) without iterating the pixels inside. Cells that disagree
+//! are split in four, down to a minimum size below which every pixel is iterated. The result is
+//! rasterized to one `IterationResult` per pixel and colored like any other render.
+//!
+//! Cells share their edges, so each sample is iterated once. A feature smaller than a cell that
+//! touches none of its samples can be missed, which is why the first level is a grid of small
+//! cells (`QuadtreeOptions::initial_cell`) rather than the whole image. Filled pixels take the
+//! result of their cell's top-left corner, so with smooth coloring the bands inside a filled cell
+//! are flat; the quadtree suits views dominated by the interior or by wide bands.
+
+use crate::{
+    finish_fractal_image, julia_result_compiled, mandelbrot_result_compiled, partial_fractal_image, pixel_to_complex, ColorStop,
+    CompiledFormula, FractalParams, IterationResult,
+};
+use image::{ImageBuffer, Rgba};
+use num_complex::Complex;
+use rayon::prelude::*;
+
+/// How finely the quadtree starts and how far it refines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadtreeOptions {
+    /// Side in pixels of the cells of the first level; smaller cells miss fewer small features
+    pub initial_cell: u32,
+    /// Cells this many pixels across or fewer are iterated pixel by pixel instead of split
+    pub min_cell: u32,
+}
+
+impl Default for QuadtreeOptions {
+    fn default() -> Self {
+        Self { initial_cell: 32, min_cell: 4 }
+    }
+}
+
+/// The per-pixel results of a quadtree render
+#[derive(Debug, Clone)]
+pub struct QuadtreeResults {
+    /// One result per pixel, in row-major order
+    pub results: Vec<IterationResult>,
+    /// Number of pixels that were iterated; the rest were filled from their cell
+    pub iterated: usize,
+}
+
+/// A rectangle of pixels from (x0, y0) to (x1, y1) inclusive; neighbouring cells share an edge
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Cell {
+    fn is_small(&self, min_cell: u32) -> bool {
+        self.x1 - self.x0 <= min_cell || self.y1 - self.y0 <= min_cell
+    }
+
+    fn center(&self) -> (u32, u32) {
+        ((self.x0 + self.x1) / 2, (self.y0 + self.y1) / 2)
+    }
+
+    /// The corners and the center, which decide whether the cell is uniform
+    fn samples(&self) -> [(u32, u32); 5] {
+        [(self.x0, self.y0), (self.x1, self.y0), (self.x0, self.y1), (self.x1, self.y1), self.center()]
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let (x0, x1) = (self.x0, self.x1);
+        (self.y0..=self.y1).flat_map(move |y| (x0..=x1).map(move |x| (x, y)))
+    }
+
+    fn split(&self) -> [Cell; 4] {
+        let (xm, ym) = self.center();
+        [
+            Cell { x0: self.x0, y0: self.y0, x1: xm, y1: ym },
+            Cell { x0: xm, y0: self.y0, x1: self.x1, y1: ym },
+            Cell { x0: self.x0, y0: ym, x1: xm, y1: self.y1 },
+            Cell { x0: xm, y0: ym, x1: self.x1, y1: self.y1 },
+        ]
+    }
+}
+
+/// Edges of the first-level cells along one side of the image
+fn grid_edges(size: u32, cell: u32) -> Vec<u32> {
+    let mut edges: Vec<u32> = (0..size - 1).step_by(cell.max(1) as usize).collect();
+    edges.push(size - 1);
+    edges
+}
+
+/// Iterate an image adaptively, refining only the cells whose samples disagree
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters
+/// * `options` - Sizes of the first and the smallest cells
+/// * `iteration_func` - Iteration of one point, e.g. `mandelbrot_result_compiled`
+///
+/// # Returns
+///
+/// The result of every pixel and the number of pixels actually iterated; if `params.cancel` is
+/// cancelled, the pixels not iterated or filled yet are `IterationResult::UNRENDERED`
+pub fn quadtree_results<F>(width: u32, height: u32, params: &FractalParams, options: &QuadtreeOptions, iteration_func: F) -> QuadtreeResults
+where
+    F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+{
+    let index = |(x, y): (u32, u32)| y as usize * width as usize + x as usize;
+    let mut samples: Vec<Option<IterationResult>> = vec![None; width as usize * height as usize];
+    if samples.is_empty() {
+        return QuadtreeResults { results: Vec::new(), iterated: 0 };
+    }
+    let iterate = |pixels: Vec<(u32, u32)>, samples: &mut Vec<Option<IterationResult>>| {
+        let mut pixels: Vec<(u32, u32)> = pixels.into_iter().filter(|&pixel| samples[index(pixel)].is_none()).collect();
+        pixels.sort_unstable_by_key(|&pixel| index(pixel));
+        pixels.dedup();
+        let results: Vec<IterationResult> = pixels
+            .par_iter()
+            .map(|&(x, y)| match params.cancel.is_cancelled() {
+                true => IterationResult::UNRENDERED,
+                false => iteration_func(pixel_to_complex(x, y, width, height, params.bounds), params),
+            })
+            .collect();
+        for (pixel, result) in pixels.into_iter().zip(results) {
+            samples[index(pixel)] = Some(result);
+        }
+    };
+
+    let (columns, rows) = (grid_edges(width, options.initial_cell), grid_edges(height, options.initial_cell));
+    let mut cells: Vec<Cell> = rows
+        .windows(2)
+        .flat_map(|ys| columns.windows(2).map(move |xs| Cell { x0: xs[0], y0: ys[0], x1: xs[1], y1: ys[1] }))
+        .collect();
+    // Images one pixel wide or high have no cells with an inside
+    if cells.is_empty() {
+        iterate((0..height).flat_map(|y| (0..width).map(move |x| (x, y))).collect(), &mut samples);
+    }
+    let min_cell = options.min_cell.max(1);
+    let mut uniform = Vec::new();
+    while !cells.is_empty() && !params.cancel.is_cancelled() {
+        let needed = cells
+            .iter()
+            .flat_map(|cell| -> Box<dyn Iterator<Item = (u32, u32)>> {
+                match cell.is_small(min_cell) {
+                    true => Box::new(cell.pixels()),
+                    false => Box::new(cell.samples().into_iter()),
+                }
+            })
+            .collect();
+        iterate(needed, &mut samples);
+
+        let mut next = Vec::new();
+        if params.cancel.is_cancelled() {
+            break;
+        }
+        for cell in cells.iter().filter(|cell| !cell.is_small(min_cell)) {
+            let corner = samples[index((cell.x0, cell.y0))].expect("sampled");
+            let agree = cell.samples().iter().all(|&pixel| {
+                let sample = samples[index(pixel)].expect("sampled");
+                sample.iterations == corner.iterations && sample.escaped == corner.escaped
+            });
+            match agree {
+                true => uniform.push((*cell, corner)),
+                false => next.extend(cell.split()),
+            }
+        }
+        cells = next;
+    }
+
+    let iterated = samples.iter().filter(|sample| sample.is_some()).count();
+    let mut filled = samples;
+    for (cell, result) in uniform {
+        for pixel in cell.pixels() {
+            filled[index(pixel)].get_or_insert(result);
+        }
+    }
+    // Only a cancel leaves pixels neither sampled nor filled
+    let results = filled.into_iter().map(|result| result.unwrap_or(IterationResult::UNRENDERED)).collect();
+    QuadtreeResults { results, iterated }
+}
+
+/// Render a Mandelbrot or Julia set adaptively with a quadtree
+///
+/// Coloring, anti-aliasing and post-processing follow `params` as for full renders.
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters
+/// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `options` - Sizes of the first and the smallest cells
+/// * `color_palette` - Optional color palette
+///
+/// # Returns
+///
+/// The image and the result of each pixel, in row-major order; a cancelled render returns the
+/// pixels iterated so far, as `generate_escape_time_image_and_results` does
+pub fn generate_quadtree_image(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    julia: bool,
+    options: &QuadtreeOptions,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> (ImageBuffer<Rgba<u8>, Vec<u8>>, Vec<IterationResult>) {
+    let formula = CompiledFormula::new(&params.formula);
+    let iterate = |point, p: &FractalParams| match julia {
+        true => julia_result_compiled(point, p, &formula),
+        false => mandelbrot_result_compiled(point, p, &formula),
+    };
+    let start_time = std::time::Instant::now();
+    let render = quadtree_results(width, height, params, options, iterate);
+    println!(
+        "Quadtree render: iterated {} of {} pixels ({:.1}%) in {:.1}s",
+        render.iterated,
+        render.results.len(),
+        100.0 * render.iterated as f64 / render.results.len().max(1) as f64,
+        start_time.elapsed().as_secs_f64()
+    );
+    if params.cancel.is_cancelled() {
+        return (partial_fractal_image(width, height, params, &render.results, color_palette), render.results);
+    }
+    let image = finish_fractal_image(width, height, params, &render.results, iterate, color_palette);
+    (image, render.results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadtree_skips_uniform_regions() {
+        // Mostly the main cardioid, which is one uniform region
+        let params = FractalParams::new([-0.7, 0.3, -0.5, 0.5], 200, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let formula = CompiledFormula::new("z^2 + c");
+        let iterate = |c, p: &FractalParams| mandelbrot_result_compiled(c, p, &formula);
+        let (width, height) = (241, 240);
+        let render = quadtree_results(width, height, &params, &QuadtreeOptions::default(), iterate);
+        assert_eq!(render.results.len(), (width * height) as usize);
+        assert!(render.iterated < render.results.len() / 2, "iterated {} pixels", render.iterated);
+
+        // Nearly every pixel matches a full render
+        let mismatches = render
+            .results
+            .iter()
+            .enumerate()
+            .filter(|(index, result)| {
+                let point = pixel_to_complex(*index as u32 % width, *index as u32 / width, width, height, params.bounds);
+                iterate(point, &params).iterations != result.iterations
+            })
+            .count();
+        assert!(mismatches * 100 < render.results.len(), "{} mismatches", mismatches);
+
+        let line = quadtree_results(7, 1, &params, &QuadtreeOptions::default(), iterate);
+        assert_eq!((line.results.len(), line.iterated), (7, 7));
+    }
+}
@@ -0,0 +1,183 @@
+//! Named render quality profiles
+//!
+//! Going from a quick preview to a final render used to mean changing several options at once:
+//! iterations, anti-aliasing, precision and coloring for escape-time renders, sample counts and
+//! denoising for Buddhabrots. A `Profile` bundles them under one name. The parameters keep
+//! describing the image; the profile is applied on top of them just before rendering:
+//!
+//! - `Draft` quarters the iterations, turns anti-aliasing off, iterates in f32 where the view
+//!   allows it, takes a tenth of the Buddhabrot samples and denoises them
+//! - `Standard` renders the parameters as given
+//! - `Production` doubles the iterations, refines edges adaptively unless anti-aliasing is already
+//!   set, raises the precision to what the pixel size needs, colors smoothly and takes four times
+//!   the Buddhabrot samples
+//!
+//! Applying a profile resets the parameters' `quality` to `Standard`, so applying it twice
+//! changes nothing.
+
+use crate::buddhabrot::DenoiseOptions;
+use crate::{AntiAliasing, BuddhabrotChannels, BuddhabrotJuliaParams, BuddhabrotParams, FractalParams, Precision};
+use serde::{Deserialize, Serialize};
+
+/// Fewest iterations a draft keeps, so that previews of shallow views still show the set
+const MIN_DRAFT_ITERATIONS: u32 = 64;
+
+/// Fewest samples per channel a draft Buddhabrot keeps
+const MIN_DRAFT_SAMPLES: u64 = 10_000;
+
+/// A named trade-off between render time and quality
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    /// Fast previews
+    Draft,
+    /// The parameters as given
+    #[default]
+    Standard,
+    /// Final renders
+    Production,
+}
+
+impl Profile {
+    /// Parse a profile name: `draft` (or `preview`), `standard` or `production` (or `final`)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "draft" | "preview" => Ok(Profile::Draft),
+            "standard" => Ok(Profile::Standard),
+            "production" | "final" => Ok(Profile::Production),
+            other => Err(format!("Unknown quality profile '{}' (expected draft, standard or production)", other)),
+        }
+    }
+
+    /// Factor applied to iteration limits
+    pub fn iteration_scale(&self) -> f64 {
+        match self {
+            Profile::Draft => 0.25,
+            Profile::Standard => 1.0,
+            Profile::Production => 2.0,
+        }
+    }
+
+    /// Factor applied to Buddhabrot sample counts
+    pub fn sample_scale(&self) -> f64 {
+        match self {
+            Profile::Draft => 0.1,
+            Profile::Standard => 1.0,
+            Profile::Production => 4.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Profile::Draft => "draft",
+            Profile::Standard => "standard",
+            Profile::Production => "production",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Apply the parameters' quality profile to an escape-time render
+///
+/// # Arguments
+///
+/// * `params` - Parameters whose `quality` is applied and reset to `Standard`
+/// * `height` - Image height in pixels, which sets the pixel size the precision must resolve
+pub fn apply_profile(params: &mut FractalParams, height: u32) {
+    let profile = std::mem::take(&mut params.quality);
+    let [x_min, x_max, y_min, y_max] = params.bounds;
+    let pixel_size = (y_max - y_min).abs() / height.max(1) as f64;
+    let magnitude = x_min.abs().max(x_max.abs()).max(y_min.abs()).max(y_max.abs()).max(1.0);
+    match profile {
+        Profile::Standard => {}
+        Profile::Draft => {
+            params.max_iterations =
+                scale_iterations(params.max_iterations, profile).max(MIN_DRAFT_ITERATIONS.min(params.max_iterations));
+            params.antialiasing = AntiAliasing::None;
+            if pixel_size >= Precision::Single.min_pixel_size() * magnitude {
+                params.precision = Precision::Single;
+            }
+        }
+        Profile::Production => {
+            params.max_iterations = scale_iterations(params.max_iterations, profile);
+            if params.antialiasing == AntiAliasing::None {
+                params.antialiasing = AntiAliasing::Adaptive { samples: 4, threshold: 0.1 };
+            }
+            let needed = Precision::for_pixel_size(pixel_size, magnitude);
+            if needed.min_pixel_size() < params.precision.min_pixel_size() {
+                params.precision = needed;
+            }
+            params.smooth_coloring = true;
+        }
+    }
+}
+
+/// Apply the parameters' quality profile to a Buddhabrot render
+///
+/// The channels' iteration ranges are left alone, since they decide what the image shows.
+pub fn apply_buddhabrot_profile(params: &mut BuddhabrotParams) {
+    let profile = std::mem::take(&mut params.quality);
+    params.samples = scale_samples(params.samples, profile);
+    scale_channels(&mut params.channels, &mut params.denoise, profile);
+}
+
+/// Apply the parameters' quality profile to a Buddhabrot Julia render
+pub fn apply_buddhabrot_julia_profile(params: &mut BuddhabrotJuliaParams) {
+    let profile = std::mem::take(&mut params.quality);
+    params.samples = scale_samples(params.samples, profile);
+    scale_channels(&mut params.channels, &mut params.denoise, profile);
+}
+
+fn scale_iterations(iterations: u32, profile: Profile) -> u32 {
+    (iterations as f64 * profile.iteration_scale()).round().clamp(1.0, u32::MAX as f64) as u32
+}
+
+fn scale_samples(samples: u64, profile: Profile) -> u64 {
+    let scaled = (samples as f64 * profile.sample_scale()).round() as u64;
+    match profile {
+        Profile::Draft => scaled.max(MIN_DRAFT_SAMPLES.min(samples)),
+        _ => scaled,
+    }
+}
+
+/// Scale the channels' sample counts; drafts, which are noisy, are denoised
+fn scale_channels(channels: &mut BuddhabrotChannels, denoise: &mut Option<DenoiseOptions>, profile: Profile) {
+    for channel in [&mut channels.red, &mut channels.green, &mut channels.blue] {
+        channel.samples = scale_samples(channel.samples, profile);
+    }
+    if profile == Profile::Draft && denoise.is_none() {
+        *denoise = Some(DenoiseOptions::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_scale_params_once() {
+        assert_eq!(Profile::parse("Final"), Ok(Profile::Production));
+        assert!(Profile::parse("ultra").is_err());
+
+        let mut params = FractalParams::new([-2.0, 1.0, -1.5, 1.5], 1000, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        params.quality = Profile::Draft;
+        apply_profile(&mut params, 600);
+        assert_eq!((params.max_iterations, params.precision), (250, Precision::Single));
+        assert_eq!(params.quality, Profile::Standard);
+        apply_profile(&mut params, 600);
+        assert_eq!(params.max_iterations, 250);
+
+        // A deep view keeps the precision it needs even in a draft
+        let mut deep = FractalParams::new([-0.75, -0.75 + 1e-9, 0.1, 0.1 + 1e-9], 1000, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        deep.quality = Profile::Draft;
+        apply_profile(&mut deep, 600);
+        assert_eq!(deep.precision, Precision::Double);
+
+        params.quality = Profile::Production;
+        apply_profile(&mut params, 600);
+        assert_eq!(params.max_iterations, 500);
+        assert!(params.smooth_coloring && params.antialiasing != AntiAliasing::None);
+    }
+}
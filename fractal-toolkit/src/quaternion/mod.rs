@@ -0,0 +1,288 @@
+//! Quaternion Julia sets and their 2D slices
+//!
+//! Iterating `q^2 + c` over the quaternions `q = re + i·i + j·j + k·k` gives Julia sets in four
+//! dimensions. An image shows a plane through them: two axes vary across the pixels and the
+//! other two components are held at fixed values. The plane spanned by `re` and `i` through the
+//! origin, with `c` in that plane as well, is exactly the ordinary Julia set of `z^2 + c`, so
+//! slicing away from it shows how the familiar set extends into the extra dimensions.
+//!
+//! Pixels are iterated into `IterationResult`s with the modulus of the escaped quaternion as
+//! `final_z`, so the palettes, smooth coloring and image generation of the complex renderers
+//! apply unchanged.
+
+use crate::{generate_fractal_image_from_results, ColorStop, FractalParams, IterationResult};
+use image::{ImageBuffer, Rgba};
+use num_complex::Complex;
+use std::ops::{Add, Mul, Sub};
+
+/// A quaternion `re + i·i + j·j + k·k`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quaternion {
+    /// Real part
+    pub re: f64,
+    /// Coefficient of i
+    pub i: f64,
+    /// Coefficient of j
+    pub j: f64,
+    /// Coefficient of k
+    pub k: f64,
+}
+
+impl Quaternion {
+    pub fn new(re: f64, i: f64, j: f64, k: f64) -> Self {
+        Self { re, i, j, k }
+    }
+
+    /// Squared modulus
+    pub fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.i * self.i + self.j * self.j + self.k * self.k
+    }
+
+    /// Modulus
+    pub fn norm(&self) -> f64 {
+        self.norm_sqr().sqrt()
+    }
+
+    /// `q^2`, cheaper than `q * q`: the imaginary parts all scale by `2 re`
+    pub fn square(&self) -> Self {
+        let twice_re = 2.0 * self.re;
+        Self {
+            re: self.re * self.re - self.i * self.i - self.j * self.j - self.k * self.k,
+            i: twice_re * self.i,
+            j: twice_re * self.j,
+            k: twice_re * self.k,
+        }
+    }
+
+    /// Component along an axis
+    pub fn component(&self, axis: Axis) -> f64 {
+        match axis {
+            Axis::Re => self.re,
+            Axis::I => self.i,
+            Axis::J => self.j,
+            Axis::K => self.k,
+        }
+    }
+
+    /// The quaternion with one component replaced
+    pub fn with_component(mut self, axis: Axis, value: f64) -> Self {
+        match axis {
+            Axis::Re => self.re = value,
+            Axis::I => self.i = value,
+            Axis::J => self.j = value,
+            Axis::K => self.k = value,
+        }
+        self
+    }
+
+    /// Parse four comma-separated components `re,i,j,k`; missing trailing components are zero
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let values: Vec<f64> = text
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in quaternion '{}'", value.trim(), text.trim())))
+            .collect::<Result<_, _>>()?;
+        if values.len() > 4 {
+            return Err(format!("Quaternion '{}' has more than 4 components", text.trim()));
+        }
+        let mut components = [0.0; 4];
+        components[..values.len()].copy_from_slice(&values);
+        let [re, i, j, k] = components;
+        Ok(Self::new(re, i, j, k))
+    }
+}
+
+impl From<Complex<f64>> for Quaternion {
+    fn from(z: Complex<f64>) -> Self {
+        Self::new(z.re, z.im, 0.0, 0.0)
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.re + other.re, self.i + other.i, self.j + other.j, self.k + other.k)
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.re - other.re, self.i - other.i, self.j - other.j, self.k - other.k)
+    }
+}
+
+/// Hamilton product; not commutative
+impl Mul for Quaternion {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            re: self.re * other.re - self.i * other.i - self.j * other.j - self.k * other.k,
+            i: self.re * other.i + self.i * other.re + self.j * other.k - self.k * other.j,
+            j: self.re * other.j - self.i * other.k + self.j * other.re + self.k * other.i,
+            k: self.re * other.k + self.i * other.j - self.j * other.i + self.k * other.re,
+        }
+    }
+}
+
+/// One of the four axes of quaternion space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Re,
+    I,
+    J,
+    K,
+}
+
+impl Axis {
+    /// Parse an axis name: `re` (or `r`, `1`), `i`, `j` or `k`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "re" | "r" | "1" => Ok(Axis::Re),
+            "i" => Ok(Axis::I),
+            "j" => Ok(Axis::J),
+            "k" => Ok(Axis::K),
+            other => Err(format!("Unknown quaternion axis: {} (expected re, i, j or k)", other)),
+        }
+    }
+}
+
+/// A plane through quaternion space, mapped onto the complex plane of an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuaternionSlice {
+    /// Axis along the image's x direction
+    pub horizontal: Axis,
+    /// Axis along the image's y direction
+    pub vertical: Axis,
+    /// Values of the other two components; its `horizontal` and `vertical` components are ignored
+    pub fixed: Quaternion,
+}
+
+impl QuaternionSlice {
+    /// A slice along two axes
+    ///
+    /// # Arguments
+    ///
+    /// * `horizontal` - Axis along the image's x direction
+    /// * `vertical` - Axis along the image's y direction, different from `horizontal`
+    /// * `fixed` - Values of the other two components
+    ///
+    /// # Returns
+    ///
+    /// The slice, or an error if both axes are the same
+    pub fn new(horizontal: Axis, vertical: Axis, fixed: Quaternion) -> Result<Self, String> {
+        if horizontal == vertical {
+            return Err(format!("A slice needs two different axes, got {:?} twice", horizontal));
+        }
+        Ok(Self { horizontal, vertical, fixed })
+    }
+
+    /// Parse the axes as `horizontal,vertical` (such as `re,j`) and the fixed values as `re,i,j,k`
+    pub fn parse(axes: &str, fixed: &str) -> Result<Self, String> {
+        let (horizontal, vertical) = axes
+            .split_once(',')
+            .ok_or_else(|| format!("Slice axes must be two axes such as re,j, found '{}'", axes.trim()))?;
+        Self::new(Axis::parse(horizontal)?, Axis::parse(vertical)?, Quaternion::parse(fixed)?)
+    }
+
+    /// The quaternion at a point of the image's complex plane
+    pub fn point(&self, plane: Complex<f64>) -> Quaternion {
+        self.fixed.with_component(self.horizontal, plane.re).with_component(self.vertical, plane.im)
+    }
+}
+
+/// Iterate `q^2 + c` from a starting quaternion
+///
+/// # Arguments
+///
+/// * `q` - The starting point
+/// * `c` - The Julia constant
+/// * `max_iterations` - Maximum number of iterations
+/// * `bailout` - Escape radius
+///
+/// # Returns
+///
+/// The iteration result, with the modulus of the last quaternion as a real `final_z` for smooth
+/// coloring
+pub fn quaternion_julia_result(mut q: Quaternion, c: Quaternion, max_iterations: u32, bailout: f64) -> IterationResult {
+    let mut iter = 0;
+    while iter < max_iterations {
+        q = q.square() + c;
+        if q.norm_sqr() > bailout * bailout {
+            break;
+        }
+        iter += 1;
+    }
+    IterationResult { iterations: iter, escaped: iter < max_iterations, final_z: Complex::new(q.norm(), 0.0), period: None, multiplier: None }
+}
+
+/// Render a 2D slice of a quaternion Julia set
+///
+/// `params.bounds` is the region of the slice's plane, with `params.max_iterations`,
+/// `params.bailout` and the coloring options used as for complex Julia sets; `params.formula` and
+/// `params.spawn` are ignored in favour of `q^2 + c`.
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Bounds, iteration and coloring parameters
+/// * `c` - The Julia constant
+/// * `slice` - The plane to render
+/// * `color_palette` - Optional color palette
+///
+/// # Returns
+///
+/// The rendered image
+pub fn generate_quaternion_julia_image(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    c: Quaternion,
+    slice: &QuaternionSlice,
+    color_palette: Option<&Vec<ColorStop>>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    generate_fractal_image_from_results(
+        width,
+        height,
+        params,
+        |z, p| quaternion_julia_result(slice.point(z), c, p.max_iterations, p.bailout),
+        color_palette,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quaternion_slices() {
+        let q = Quaternion::new(1.0, 2.0, -0.5, 0.25);
+        assert_eq!(q.square(), q * q);
+        assert_eq!(Quaternion::new(0.0, 1.0, 0.0, 0.0) * Quaternion::new(0.0, 0.0, 1.0, 0.0), Quaternion::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(Quaternion::new(0.0, 0.0, 1.0, 0.0) * Quaternion::new(0.0, 1.0, 0.0, 0.0), Quaternion::new(0.0, 0.0, 0.0, -1.0));
+
+        // The re,i plane through the origin is the complex Julia set
+        let c = Complex::new(-0.8, 0.156);
+        let slice = QuaternionSlice::parse("re,i", "0,0,0,0").unwrap();
+        for z0 in [Complex::new(0.1, 0.2), Complex::new(-0.7, 0.3), Complex::new(1.2, -0.4)] {
+            let mut z = z0;
+            let mut iterations = 0;
+            while iterations < 200 {
+                z = z * z + c;
+                if z.norm_sqr() > 4.0 {
+                    break;
+                }
+                iterations += 1;
+            }
+            assert_eq!(quaternion_julia_result(slice.point(z0), c.into(), 200, 2.0).iterations, iterations);
+        }
+
+        let side = QuaternionSlice::parse("j, k", "0.1,-0.2").unwrap();
+        assert_eq!(side.point(Complex::new(0.5, 0.6)), Quaternion::new(0.1, -0.2, 0.5, 0.6));
+        assert!(QuaternionSlice::parse("j,j", "0").is_err());
+        assert!(Axis::parse("w").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! Distance estimators of 3D fractals
+//!
+//! A distance estimator returns a lower bound on the distance from a point to the fractal's
+//! surface, so a ray can safely advance by that much. Both fractals here iterate a map on 3D
+//! points while tracking the running derivative `dr` of the iteration; the distance then follows
+//! from the final radius and `dr`.
+
+use super::Vec3;
+
+/// A 3D fractal with its distance estimator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceEstimator {
+    /// White and Nylander's Mandelbulb: `z -> z^power + c` with powers taken in spherical
+    /// coordinates
+    Mandelbulb {
+        /// Power of the iteration; 8 gives the classic bulb
+        power: f64,
+        /// Maximum number of iterations per estimate
+        iterations: u32,
+        /// Radius beyond which a point counts as escaped
+        bailout: f64,
+    },
+    /// Tom Lowe's Mandelbox: box fold, sphere fold, then `z -> scale z + c`
+    Mandelbox {
+        /// Scale of the iteration; -1.5 and 2 are common
+        scale: f64,
+        /// Maximum number of iterations per estimate
+        iterations: u32,
+        /// Radius below which the sphere fold scales points up by a constant factor
+        min_radius: f64,
+        /// Radius of the sphere inversion
+        fixed_radius: f64,
+    },
+}
+
+impl DistanceEstimator {
+    /// The classic power-8 Mandelbulb
+    pub fn mandelbulb() -> Self {
+        DistanceEstimator::Mandelbulb { power: 8.0, iterations: 16, bailout: 2.0 }
+    }
+
+    /// A Mandelbox of scale -1.5
+    pub fn mandelbox() -> Self {
+        DistanceEstimator::Mandelbox { scale: -1.5, iterations: 16, min_radius: 0.5, fixed_radius: 1.0 }
+    }
+
+    /// Parse a fractal name: `mandelbulb` (or `bulb`) or `mandelbox` (or `box`)
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "mandelbulb" | "bulb" => Ok(Self::mandelbulb()),
+            "mandelbox" | "box" => Ok(Self::mandelbox()),
+            other => Err(format!("Unknown 3D fractal: {} (expected mandelbulb or mandelbox)", other)),
+        }
+    }
+
+    /// Lower bound on the distance from a point to the surface, with an orbit trap for coloring
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The point
+    ///
+    /// # Returns
+    ///
+    /// `(distance, trap)`: the estimate, zero or negative inside, and the smallest radius the
+    /// orbit reached
+    pub fn estimate(&self, p: Vec3) -> (f64, f64) {
+        match *self {
+            DistanceEstimator::Mandelbulb { power, iterations, bailout } => mandelbulb(p, power, iterations, bailout),
+            DistanceEstimator::Mandelbox { scale, iterations, min_radius, fixed_radius } => {
+                mandelbox(p, scale, iterations, min_radius, fixed_radius)
+            }
+        }
+    }
+}
+
+fn mandelbulb(p: Vec3, power: f64, iterations: u32, bailout: f64) -> (f64, f64) {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    let mut trap = r;
+    for _ in 0..iterations {
+        if r > bailout {
+            break;
+        }
+        // d|z^n|/dz = n |z|^(n-1)
+        dr = power * r.powf(power - 1.0) * dr + 1.0;
+        let theta = if r > 0.0 { (z.z / r).clamp(-1.0, 1.0).acos() * power } else { 0.0 };
+        let phi = z.y.atan2(z.x) * power;
+        z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * r.powf(power) + p;
+        r = z.length();
+        trap = trap.min(r);
+    }
+    if r <= 1.0 {
+        return (0.0, trap);
+    }
+    (0.5 * r.ln() * r / dr, trap)
+}
+
+fn mandelbox(p: Vec3, scale: f64, iterations: u32, min_radius: f64, fixed_radius: f64) -> (f64, f64) {
+    let (min_r2, fixed_r2) = (min_radius * min_radius, fixed_radius * fixed_radius);
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut trap = z.length();
+    for _ in 0..iterations {
+        // Reflect each component that leaves [-1, 1] back into it
+        z = Vec3::new(z.x.clamp(-1.0, 1.0) * 2.0 - z.x, z.y.clamp(-1.0, 1.0) * 2.0 - z.y, z.z.clamp(-1.0, 1.0) * 2.0 - z.z);
+        let r2 = z.dot(z);
+        let factor = if r2 < min_r2 {
+            fixed_r2 / min_r2
+        } else if r2 < fixed_r2 {
+            fixed_r2 / r2
+        } else {
+            1.0
+        };
+        z = z * (factor * scale) + p;
+        dr = dr * factor * scale.abs() + 1.0;
+        trap = trap.min(z.length());
+        if z.dot(z) > 1e6 {
+            break;
+        }
+    }
+    (z.length() / dr.abs(), trap)
+}
@@ -0,0 +1,357 @@
+//! Ray-marched 3D fractals
+//!
+//! The Mandelbulb and Mandelbox have no closed-form surface, but their distance estimators bound
+//! how far any point is from it. Sphere tracing follows each camera ray by steps of that bound
+//! until it is closer to the surface than the width a pixel covers at that distance (or
+//! `epsilon`, if larger), so detail is resolved down to the pixel but no further, and the surface is shaded with Phong
+//! lighting: a normal from the gradient of the estimator, ambient, diffuse and specular terms,
+//! and a darkening by the number of steps the ray needed, which approximates ambient occlusion
+//! in the fractal's crevices.
+//!
+//! Surfaces take their base color from a palette indexed by an orbit trap, so the palettes of
+//! the 2D renderers apply, and the result is an ordinary RGBA image for post-processing and
+//! saving.
+
+pub mod estimators;
+
+pub use estimators::DistanceEstimator;
+
+use crate::cancel::CancelToken;
+use crate::{interpolate_color_from_palette, ColorStop};
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A point or direction in 3D space
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(self.y * other.z - self.z * other.y, self.z * other.x - self.x * other.z, self.x * other.y - self.y * other.x)
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    /// The vector scaled to length 1, or unchanged if it has length 0
+    pub fn normalized(self) -> Self {
+        let length = self.length();
+        if length > 0.0 {
+            self * (1.0 / length)
+        } else {
+            self
+        }
+    }
+
+    /// Parse three comma-separated coordinates `x,y,z`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let values: Vec<f64> = text
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().map_err(|_| format!("Invalid number '{}' in vector '{}'", value.trim(), text.trim())))
+            .collect::<Result<_, _>>()?;
+        match values[..] {
+            [x, y, z] => Ok(Self::new(x, y, z)),
+            _ => Err(format!("Vector '{}' must be x,y,z", text.trim())),
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, factor: f64) -> Self {
+        Self::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// A pinhole camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Position of the eye
+    pub position: Vec3,
+    /// Point at the center of the image
+    pub target: Vec3,
+    /// Direction that appears upwards in the image; needn't be perpendicular to the view
+    pub up: Vec3,
+    /// Vertical field of view in degrees
+    pub fov_degrees: f64,
+}
+
+impl Camera {
+    /// Direction of the ray through a point of the image
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal position in pixels, 0 at the left edge
+    /// * `y` - Vertical position in pixels, 0 at the top edge
+    /// * `width` - Image width in pixels
+    /// * `height` - Image height in pixels
+    ///
+    /// # Returns
+    ///
+    /// The unit direction of the ray from the eye
+    pub fn ray_direction(&self, x: f64, y: f64, width: u32, height: u32) -> Vec3 {
+        let forward = (self.target - self.position).normalized();
+        let right = forward.cross(self.up).normalized();
+        let up = right.cross(forward);
+        let half_height = (self.fov_degrees.to_radians() / 2.0).tan();
+        let half_width = half_height * width as f64 / height as f64;
+        let u = (2.0 * x / width as f64 - 1.0) * half_width;
+        let v = (1.0 - 2.0 * y / height as f64) * half_height;
+        (forward + right * u + up * v).normalized()
+    }
+}
+
+/// Strengths of the Phong lighting terms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhongLighting {
+    /// Direction from the surface towards the light
+    pub light_direction: Vec3,
+    /// Light reaching every point regardless of orientation
+    pub ambient: f64,
+    /// Strength of the diffuse (Lambertian) term
+    pub diffuse: f64,
+    /// Strength of the specular highlight
+    pub specular: f64,
+    /// Sharpness of the specular highlight
+    pub shininess: f64,
+}
+
+impl Default for PhongLighting {
+    fn default() -> Self {
+        Self { light_direction: Vec3::new(-0.5, 0.8, 0.6), ambient: 0.15, diffuse: 0.8, specular: 0.4, shininess: 32.0 }
+    }
+}
+
+/// Parameters for ray marching a 3D fractal
+#[derive(Debug, Clone)]
+pub struct RaymarchParams {
+    /// The fractal and its distance estimator
+    pub fractal: DistanceEstimator,
+    /// The camera
+    pub camera: Camera,
+    /// The lighting
+    pub lighting: PhongLighting,
+    /// Maximum number of steps along a ray
+    pub max_steps: u32,
+    /// Smallest distance to the surface at which a ray counts as hitting it; rays far from the
+    /// eye stop at the width of a pixel instead
+    pub epsilon: f64,
+    /// Distance from the eye beyond which a ray counts as missing
+    pub max_distance: f64,
+    /// Color of rays that miss
+    pub background: [u8; 4],
+    /// Rays per pixel along each axis, averaged for anti-aliasing
+    pub supersampling: u32,
+    /// Stops the render when cancelled from another thread (see `cancel`)
+    pub cancel: CancelToken,
+}
+
+impl RaymarchParams {
+    pub fn new(fractal: DistanceEstimator, camera: Camera) -> Self {
+        Self {
+            fractal,
+            camera,
+            lighting: PhongLighting::default(),
+            max_steps: 256,
+            epsilon: 1e-4,
+            max_distance: 20.0,
+            background: [0, 0, 0, 255],
+            supersampling: 1,
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+/// Where a ray hit the surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    /// Point on the surface
+    pub point: Vec3,
+    /// Distance from the eye along the ray
+    pub distance: f64,
+    /// Number of steps the ray took
+    pub steps: u32,
+    /// Distance to the surface at which the ray stopped
+    pub threshold: f64,
+    /// Orbit trap of the estimator at the hit
+    pub trap: f64,
+}
+
+/// Sphere-trace a ray
+///
+/// # Arguments
+///
+/// * `origin` - Start of the ray
+/// * `direction` - Unit direction of the ray
+/// * `pixel_angle` - Angle a pixel covers, so that the width of a pixel at distance `d` is
+///   `d * pixel_angle`
+/// * `params` - Fractal and marching limits
+///
+/// # Returns
+///
+/// The hit, or `None` if the ray left `max_distance` or ran out of steps
+pub fn march(origin: Vec3, direction: Vec3, pixel_angle: f64, params: &RaymarchParams) -> Option<RayHit> {
+    let mut distance = 0.0;
+    for steps in 0..params.max_steps {
+        let point = origin + direction * distance;
+        let (estimate, trap) = params.fractal.estimate(point);
+        let threshold = params.epsilon.max(distance * pixel_angle);
+        if estimate < threshold {
+            return Some(RayHit { point, distance, steps, threshold, trap });
+        }
+        distance += estimate;
+        if distance > params.max_distance {
+            return None;
+        }
+    }
+    None
+}
+
+/// Surface normal from the gradient of the estimator, sampled at the corners of a tetrahedron
+pub fn surface_normal(point: Vec3, fractal: &DistanceEstimator, step: f64) -> Vec3 {
+    let corners = [Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, -1.0, 1.0), Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)];
+    corners
+        .iter()
+        .fold(Vec3::default(), |sum, &corner| sum + corner * fractal.estimate(point + corner * step).0)
+        .normalized()
+}
+
+/// Color of one ray
+fn shade_ray(origin: Vec3, direction: Vec3, pixel_angle: f64, params: &RaymarchParams, palette: Option<&[ColorStop]>) -> [f64; 4] {
+    let Some(hit) = march(origin, direction, pixel_angle, params) else {
+        return params.background.map(|channel| channel as f64);
+    };
+    let normal = surface_normal(hit.point, &params.fractal, hit.threshold);
+    let light = params.lighting.light_direction.normalized();
+    let diffuse = normal.dot(light).max(0.0);
+    let reflected = normal * (2.0 * normal.dot(light)) - light;
+    let specular = if diffuse > 0.0 { reflected.dot(-direction).max(0.0).powf(params.lighting.shininess) } else { 0.0 };
+    let occlusion = 1.0 - hit.steps as f64 / params.max_steps as f64;
+
+    // The trap is unbounded; squash it into the palette's range
+    let albedo = match palette {
+        Some(palette) => interpolate_color_from_palette(1.0 - (-2.0 * hit.trap).exp(), palette).0,
+        None => [220, 200, 170, 255],
+    };
+    let light_level = (params.lighting.ambient + params.lighting.diffuse * diffuse) * occlusion;
+    let highlight = params.lighting.specular * specular * 255.0;
+    let [r, g, b] = [albedo[0], albedo[1], albedo[2]].map(|channel| channel as f64 * light_level + highlight);
+    [r, g, b, 255.0]
+}
+
+/// Render a 3D fractal
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `params` - Fractal, camera, lighting and marching parameters
+/// * `palette` - Optional palette for the surface color, indexed by the orbit trap
+///
+/// # Returns
+///
+/// The rendered image; pixels not reached before a cancel are transparent
+pub fn render_raymarch(width: u32, height: u32, params: &RaymarchParams, palette: Option<&[ColorStop]>) -> RgbaImage {
+    println!(
+        "Ray marching {}x{} image - Started at {}. Using {} threads.",
+        width,
+        height,
+        chrono::Local::now().format("%H:%M:%S"),
+        rayon::current_num_threads()
+    );
+    let samples = params.supersampling.max(1);
+    let pixel_angle = 2.0 * (params.camera.fov_degrees.to_radians() / 2.0).tan() / (height * samples) as f64;
+    let pixels: Vec<u8> = (0..width * height)
+        .into_par_iter()
+        .flat_map_iter(|index| {
+            if params.cancel.is_cancelled() {
+                return [0; 4];
+            }
+            let (x, y) = (index % width, index / width);
+            let mut sum = [0.0; 4];
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let px = x as f64 + (sx as f64 + 0.5) / samples as f64;
+                    let py = y as f64 + (sy as f64 + 0.5) / samples as f64;
+                    let direction = params.camera.ray_direction(px, py, width, height);
+                    let color = shade_ray(params.camera.position, direction, pixel_angle, params, palette);
+                    for (total, channel) in sum.iter_mut().zip(color) {
+                        *total += channel;
+                    }
+                }
+            }
+            let count = (samples * samples) as f64;
+            sum.map(|total| (total / count).round().clamp(0.0, 255.0) as u8)
+        })
+        .collect();
+    println!("Ray marching complete");
+    RgbaImage::from_raw(width, height, pixels).expect("one color per pixel")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_estimators_bound_the_distance_and_render_hits() {
+        // Marching from outside along an axis stops at the bulb's surface, within its bounding sphere
+        let params = RaymarchParams::new(DistanceEstimator::mandelbulb(), Camera {
+            position: Vec3::new(0.0, 0.0, 6.0),
+            target: Vec3::default(),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            fov_degrees: 40.0,
+        });
+        let hit = march(params.camera.position, Vec3::new(0.0, 0.0, -1.0), 0.0, &params).unwrap();
+        assert!(hit.point.z > 0.5 && hit.point.z < 1.3, "{:?}", hit);
+        assert!(params.fractal.estimate(Vec3::new(0.0, 0.0, 3.0)).0 <= 3.0 - hit.point.z);
+        assert_eq!(params.fractal.estimate(Vec3::default()).0, 0.0);
+
+        let (distance, _) = DistanceEstimator::mandelbox().estimate(Vec3::new(10.0, 0.0, 0.0));
+        assert!(distance > 0.0 && distance < 10.0);
+        assert!(DistanceEstimator::parse("sponge").is_err());
+
+        let image = render_raymarch(16, 16, &params, None);
+        assert_ne!(*image.get_pixel(8, 8), Rgba([0, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    }
+}
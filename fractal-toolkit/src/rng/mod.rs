@@ -0,0 +1,125 @@
+//! Counter-based random numbers for reproducible parallel sampling
+//!
+//! Seeding a generator per chunk of samples ties the random numbers to the chunking: change the
+//! chunk size or the number of threads, or split the samples across machines, and every sample
+//! draws different numbers. A counter-based generator instead computes its output directly from
+//! a key and a counter, so each sample gets its own stream keyed by `(seed, sample_index)` and
+//! draws the same numbers however the samples are divided up. A Buddhabrot rendered in pieces
+//! on several machines therefore adds up to exactly the single-machine render, which the
+//! render manifests of `output::hash` can verify.
+//!
+//! The generator is Philox4x32-10 (Salmon et al., "Parallel random numbers: as easy as 1, 2, 3",
+//! 2011), which passes the BigCrush statistical tests and matches the reference implementation
+//! in Random123. `CounterRng` implements `rand::RngCore`, so it works with `Rng::gen` and the
+//! rest of the `rand` API.
+
+use rand::RngCore;
+
+const PHILOX_M0: u32 = 0xD251_1F53;
+const PHILOX_M1: u32 = 0xCD9E_8D57;
+const PHILOX_W0: u32 = 0x9E37_79B9;
+const PHILOX_W1: u32 = 0xBB67_AE85;
+
+/// Philox4x32-10: the block of four 32-bit outputs for a counter and key
+pub fn philox4x32(counter: [u32; 4], key: [u32; 2]) -> [u32; 4] {
+    let mut counter = counter;
+    let mut key = key;
+    for round in 0..10 {
+        if round > 0 {
+            key = [key[0].wrapping_add(PHILOX_W0), key[1].wrapping_add(PHILOX_W1)];
+        }
+        let product0 = PHILOX_M0 as u64 * counter[0] as u64;
+        let product1 = PHILOX_M1 as u64 * counter[2] as u64;
+        counter = [
+            (product1 >> 32) as u32 ^ counter[1] ^ key[0],
+            product1 as u32,
+            (product0 >> 32) as u32 ^ counter[3] ^ key[1],
+            product0 as u32,
+        ];
+    }
+    counter
+}
+
+/// Stream of random numbers for one sample, computed from `(seed, stream)` and a draw counter
+#[derive(Debug, Clone)]
+pub struct CounterRng {
+    key: [u32; 2],
+    stream: u64,
+    /// Index of the next block within the stream
+    block: u64,
+    buffer: [u32; 4],
+    /// Next unused word of `buffer`, 4 when it is used up
+    position: usize,
+}
+
+impl CounterRng {
+    /// The random number stream of one sample
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the whole computation, such as `BUDDHABROT_SEED`
+    /// * `stream` - Index of the sample (or of any other unit of work) within it
+    pub fn new(seed: u64, stream: u64) -> Self {
+        Self { key: [seed as u32, (seed >> 32) as u32], stream, block: 0, buffer: [0; 4], position: 4 }
+    }
+}
+
+impl RngCore for CounterRng {
+    fn next_u32(&mut self) -> u32 {
+        if self.position == 4 {
+            let counter = [self.block as u32, (self.block >> 32) as u32, self.stream as u32, (self.stream >> 32) as u32];
+            self.buffer = philox4x32(counter, self.key);
+            self.block += 1;
+            self.position = 0;
+        }
+        self.position += 1;
+        self.buffer[self.position - 1]
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        low | (self.next_u32() as u64) << 32
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_philox_matches_reference_and_ignores_chunking() {
+        // Known-answer vectors from Random123
+        assert_eq!(philox4x32([0; 4], [0; 2]), [0x6627e8d5, 0xe169c58d, 0xbc57ac4c, 0x9b00dbd8]);
+        assert_eq!(philox4x32([u32::MAX; 4], [u32::MAX; 2]), [0x408f276d, 0x41c83b0e, 0xa20bc7c6, 0x6d5451fd]);
+        assert_eq!(
+            philox4x32([0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344], [0xa4093822, 0x299f31d0]),
+            [0xd16cfe09, 0x94fdcceb, 0x5001e420, 0x24126ea1]
+        );
+
+        // A sample draws the same numbers whenever it is generated, and differently from its neighbours
+        let draws = |sample: u64| -> Vec<f64> {
+            let mut rng = CounterRng::new(0xdeadbeef, sample);
+            (0..5).map(|_| rng.gen::<f64>()).collect()
+        };
+        let forward: Vec<Vec<f64>> = (0..100).map(draws).collect();
+        let backward: Vec<Vec<f64>> = (0..100).rev().map(draws).collect();
+        assert!(forward.iter().eq(backward.iter().rev()));
+        assert_ne!(forward[0], forward[1]);
+
+        let mean = forward.iter().flatten().sum::<f64>() / 500.0;
+        assert!((mean - 0.5).abs() < 0.05, "{}", mean);
+    }
+}
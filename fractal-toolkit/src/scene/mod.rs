@@ -0,0 +1,180 @@
+//! Scene files: everything needed to reproduce a render, in one JSON or TOML file
+//!
+//! A [`Scene`] is the `FractalParams` of a render together with what the command line adds
+//! around them: whether it is a Mandelbrot or a Julia set, the image dimensions, the palette and
+//! the output path. `ftk-render --scene` renders a scene file, and `--dump-scene` on the
+//! escape-time renderers writes the scene of any render, so a render found interactively can be
+//! kept, edited and repeated.
+//!
+//! The format follows the extension: `.toml` files are TOML, anything else JSON. The parameter
+//! fields sit at the top level next to the scene's own, and all but `bounds`, `max_iterations`
+//! and `dimensions` may be left out:
+//!
+//! ```text
+//! fractal = "julia"
+//! dimensions = [1920, 1080]
+//! output = "dendrite.png"
+//! palette = "[(#000000,0),(#FF8800,0.5),(#FFFFFF,1)]"
+//! bounds = [-1.6, 1.6, -0.9, 0.9]
+//! max_iterations = 500
+//! spawn = [0.0, 1.0]
+//! smooth_coloring = true
+//! ```
+
+use crate::quality::apply_profile;
+use crate::output::write_atomic;
+use crate::{generate_escape_time_image_and_results, parse_color_palette, FractalError, FractalParams};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Which set a scene renders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneFractal {
+    /// The Mandelbrot set of the formula, iterated from 0 with each pixel as c
+    #[default]
+    Mandelbrot,
+    /// The Julia set of the formula, iterated from each pixel with `spawn` as c
+    Julia,
+}
+
+/// A complete, reproducible escape-time render
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    /// Mandelbrot or Julia set
+    #[serde(default)]
+    pub fractal: SceneFractal,
+    /// Image width and height in pixels
+    pub dimensions: [u32; 2],
+    /// Path of the rendered image
+    #[serde(default = "default_output")]
+    pub output: String,
+    /// Color palette in the `--color-pallette` notation; `None` renders in grayscale
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+    /// Fractal, view and coloring parameters
+    #[serde(flatten)]
+    pub params: FractalParams,
+}
+
+fn default_output() -> String {
+    "scene_output.png".to_string()
+}
+
+impl Scene {
+    /// Read a scene file, as TOML if its extension is `.toml` and as JSON otherwise
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The scene file
+    ///
+    /// # Returns
+    ///
+    /// The scene, `FractalError::IoError` if the file can't be read, or
+    /// `FractalError::InvalidInput` if it isn't a valid scene
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FractalError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| FractalError::io("read", path, e))?;
+        let invalid = |e: &dyn std::fmt::Display| FractalError::InvalidInput(format!("Invalid scene '{}': {}", path.display(), e));
+        let scene: Scene = if is_toml(path) {
+            toml::from_str(&text).map_err(|e| invalid(&e))?
+        } else {
+            serde_json::from_str(&text).map_err(|e| invalid(&e))?
+        };
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Write the scene, as TOML if the extension is `.toml` and as pretty-printed JSON otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FractalError> {
+        let path = path.as_ref();
+        let unencoded = |e: &dyn std::fmt::Display| FractalError::InvalidInput(format!("Failed to encode scene: {}", e));
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| unencoded(&e))?
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| unencoded(&e))?
+        };
+        write_atomic(path, text.as_bytes())
+    }
+
+    /// Check that the scene can be rendered, failing with `FractalError::InvalidInput` if not
+    pub fn validate(&self) -> Result<(), FractalError> {
+        let [width, height] = self.dimensions;
+        if width == 0 || height == 0 {
+            return Err(FractalError::InvalidInput("Scene dimensions must be greater than 0".to_string()));
+        }
+        let [x_min, x_max, y_min, y_max] = self.params.bounds;
+        if !(x_min < x_max && y_min < y_max) {
+            return Err(FractalError::InvalidInput(format!(
+                "Scene bounds {:?} must be [x_min, x_max, y_min, y_max] with min < max",
+                self.params.bounds
+            )));
+        }
+        if let Some(ref palette) = self.palette {
+            parse_color_palette(palette).map_err(FractalError::InvalidInput)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+/// Render a scene
+///
+/// The scene's quality profile is applied first, as the command-line renderers do. Renders
+/// iterate in f64, or f32 where the precision asks for it.
+///
+/// # Arguments
+///
+/// * `scene` - The scene
+///
+/// # Returns
+///
+/// The image, or `FractalError::InvalidInput` if the scene is invalid
+pub fn render_scene(scene: &Scene) -> Result<RgbaImage, FractalError> {
+    scene.validate()?;
+    let [width, height] = scene.dimensions;
+    let palette = scene.palette.as_deref().map(parse_color_palette).transpose().map_err(FractalError::InvalidInput)?;
+    let mut params = scene.params.clone();
+    apply_profile(&mut params, height);
+    let julia = scene.fractal == SceneFractal::Julia;
+    Ok(generate_escape_time_image_and_results(width, height, &params, julia, palette.as_ref()).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_files_round_trip() {
+        let text = r#"
+            fractal = "julia"
+            dimensions = [8, 6]
+            palette = "[(#000000,0),(#FFFFFF,1)]"
+            bounds = [-1.6, 1.6, -1.2, 1.2]
+            max_iterations = 50
+            spawn = [-0.8, 0.156]
+        "#;
+        let scene: Scene = toml::from_str(text).unwrap();
+        assert_eq!(scene.fractal, SceneFractal::Julia);
+        assert_eq!(scene.params.formula, "z^2 + c");
+        assert_eq!(scene.output, "scene_output.png");
+
+        let directory = std::env::temp_dir();
+        for name in ["ftk_scene_test.toml", "ftk_scene_test.json"] {
+            let path = directory.join(format!("{}_{}", std::process::id(), name));
+            scene.save(&path).unwrap();
+            let loaded = Scene::load(&path).unwrap();
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&scene).unwrap());
+            std::fs::remove_file(&path).unwrap();
+        }
+        assert_eq!(render_scene(&scene).unwrap().dimensions(), (8, 6));
+
+        let flipped: Scene = serde_json::from_str(r#"{"dimensions": [8, 6], "bounds": [1, -1, -1, 1], "max_iterations": 10}"#).unwrap();
+        assert!(matches!(render_scene(&flipped), Err(FractalError::InvalidInput(_))));
+        assert!(matches!(Scene::load("/nonexistent/scene.toml"), Err(FractalError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound));
+    }
+}
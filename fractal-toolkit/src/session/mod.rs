@@ -0,0 +1,335 @@
+//! Render sessions for live previews
+//!
+//! A GUI re-renders every time the user edits the formula, drags the view or picks another
+//! palette, and most of those edits leave most of the work of the last render valid. A
+//! [`RenderSession`] keeps the compiled formula, the palette and the iteration results of the
+//! last render, and recomputes only what an update invalidates:
+//!
+//! - Palette and other coloring changes recolor the cached iteration results without iterating.
+//! - A pan by a whole number of pixels at the same scale keeps the results of the pixels still
+//!   on screen and iterates only the exposed strip.
+//! - Formula, iteration and size changes iterate the whole view again.
+//!
+//! Formula edits are hot-swapped: `set_formula` compiles the new text first and keeps the old
+//! formula if it doesn't parse, so a half-typed formula never breaks the preview.
+
+use crate::formula::{CompiledFormula, EvaluatorOptions};
+use crate::{
+    finish_fractal_image, julia_result_compiled, mandelbrot_result_compiled, pixel_to_complex, ColorStop,
+    FractalError, FractalParams, IterationResult,
+};
+use image::{ImageBuffer, Rgba};
+use num_complex::Complex;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// Work the last `render` call did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionWork {
+    /// Pixels iterated
+    pub iterated_pixels: usize,
+    /// Pixels whose iteration results were reused from the previous render
+    pub reused_pixels: usize,
+    /// Whether the image was colored again; false when nothing changed since the last render
+    pub recolored: bool,
+}
+
+/// A render kept warm between incremental parameter updates
+pub struct RenderSession {
+    params: FractalParams,
+    julia: bool,
+    width: u32,
+    height: u32,
+    formula: Arc<CompiledFormula>,
+    palette: Option<Vec<ColorStop>>,
+    /// Iteration results of the view in `params`; `None` when they all need iterating
+    results: Option<Vec<IterationResult>>,
+    /// Indices into `results` of the pixels still to iterate
+    stale: Vec<usize>,
+    image: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    work: SessionWork,
+}
+
+impl RenderSession {
+    /// Start a session; nothing is rendered until `render`
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters of the first render
+    /// * `width`, `height` - Image size in pixels
+    /// * `julia` - Render the Julia set of `params.spawn` instead of the Mandelbrot set
+    /// * `palette` - Color palette; `None` uses the default coloring
+    ///
+    /// # Returns
+    ///
+    /// The session, or the parse error if `params.formula` doesn't parse
+    pub fn new(
+        params: FractalParams,
+        width: u32,
+        height: u32,
+        julia: bool,
+        palette: Option<Vec<ColorStop>>,
+    ) -> Result<Self, FractalError> {
+        let formula = Arc::new(CompiledFormula::with_options(&params.formula, &EvaluatorOptions::default())?);
+        Ok(RenderSession {
+            params,
+            julia,
+            width,
+            height,
+            formula,
+            palette,
+            results: None,
+            stale: Vec::new(),
+            image: None,
+            work: SessionWork::default(),
+        })
+    }
+
+    /// Parameters of the current view
+    pub fn params(&self) -> &FractalParams {
+        &self.params
+    }
+
+    /// The compiled formula renders use
+    pub fn formula(&self) -> &Arc<CompiledFormula> {
+        &self.formula
+    }
+
+    /// Work the last `render` call did
+    pub fn last_work(&self) -> SessionWork {
+        self.work
+    }
+
+    /// Swap in a new formula, keeping the current one if the new text doesn't parse
+    ///
+    /// # Arguments
+    ///
+    /// * `formula` - The new formula text
+    ///
+    /// # Returns
+    ///
+    /// The parse error with its location if the formula was rejected
+    pub fn set_formula(&mut self, formula: &str) -> Result<(), FractalError> {
+        self.update(|params| params.formula = formula.to_string())
+    }
+
+    /// Replace the palette; the next render recolors without iterating
+    pub fn set_palette(&mut self, palette: Option<Vec<ColorStop>>) {
+        self.palette = palette;
+        self.image = None;
+    }
+
+    /// Move or zoom the view
+    ///
+    /// A pan by a whole number of pixels at the same scale keeps the results of the pixels that
+    /// stay in view; anything else iterates the whole view again.
+    pub fn set_bounds(&mut self, bounds: [f64; 4]) {
+        // Bounds never fail to apply, so the update can't be rejected
+        let _ = self.update(|params| params.bounds = bounds);
+    }
+
+    /// Change the image size, which iterates the whole view again
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.invalidate_results();
+        }
+    }
+
+    /// Edit any parameters, invalidating only what the edit affects
+    ///
+    /// A changed formula is compiled before anything else is applied; if it doesn't parse, the
+    /// whole edit is rolled back and the session keeps rendering the previous parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `edit` - Changes to apply to the parameters
+    ///
+    /// # Returns
+    ///
+    /// The parse error with its location if the edit was rejected
+    pub fn update<F: FnOnce(&mut FractalParams)>(&mut self, edit: F) -> Result<(), FractalError> {
+        let mut params = self.params.clone();
+        edit(&mut params);
+        if params.formula != self.params.formula {
+            self.formula = Arc::new(CompiledFormula::with_options(&params.formula, &EvaluatorOptions::default())?);
+        }
+        let previous = std::mem::replace(&mut self.params, params);
+        if !same_iteration(&previous, &self.params) {
+            self.invalidate_results();
+        } else if previous.bounds != self.params.bounds {
+            self.pan_from(previous.bounds);
+        }
+        // Coloring may depend on any of the fields, so recolor on every edit
+        self.image = None;
+        Ok(())
+    }
+
+    /// Bring the image up to date with the parameters, iterating and coloring only what the
+    /// updates since the last render invalidated
+    ///
+    /// The image is colored like `generate_fractal_image`, with anti-aliasing, coloring
+    /// pipelines and post effects, but without applying `params.quality`.
+    ///
+    /// # Returns
+    ///
+    /// The image, or `FractalError::Cancelled` if `params.cancel` was cancelled while iterating; the
+    /// pixels left un-iterated are iterated by the next render
+    pub fn render(&mut self) -> Result<&ImageBuffer<Rgba<u8>, Vec<u8>>, FractalError> {
+        let pixels = self.width as usize * self.height as usize;
+        let mut work = SessionWork::default();
+        let mut results = match self.results.take() {
+            Some(results) => results,
+            None => {
+                self.stale = (0..pixels).collect();
+                vec![IterationResult::UNRENDERED; pixels]
+            }
+        };
+        work.iterated_pixels = self.stale.len();
+        work.reused_pixels = pixels - self.stale.len();
+
+        let stale = std::mem::take(&mut self.stale);
+        let iterate = self.iteration_func();
+        let (width, height, bounds) = (self.width, self.height, self.params.bounds);
+        let params = &self.params;
+        let iterated: Vec<IterationResult> = stale
+            .par_iter()
+            .map(|&index| match params.cancel.is_cancelled() {
+                true => IterationResult::UNRENDERED,
+                false => {
+                    let (x, y) = ((index % width as usize) as u32, (index / width as usize) as u32);
+                    iterate(pixel_to_complex(x, y, width, height, bounds), params)
+                }
+            })
+            .collect();
+        for (&index, result) in stale.iter().zip(iterated) {
+            results[index] = result;
+        }
+        if params.cancel.is_cancelled() {
+            self.stale = stale.into_iter().filter(|&index| results[index] == IterationResult::UNRENDERED).collect();
+            self.results = Some(results);
+            self.work = work;
+            return Err(FractalError::Cancelled);
+        }
+
+        if work.iterated_pixels > 0 || self.image.is_none() {
+            let image = finish_fractal_image(width, height, params, &results, iterate, self.palette.as_ref());
+            self.image = Some(image);
+            work.recolored = true;
+        }
+        self.results = Some(results);
+        self.work = work;
+        Ok(self.image.as_ref().expect("the image was just rendered"))
+    }
+
+    /// Iterate one point with the session's compiled formula
+    fn iteration_func(&self) -> impl Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync + Copy + '_ {
+        let formula = self.formula.as_ref();
+        let julia = self.julia;
+        move |point, params| match julia {
+            true => julia_result_compiled(point, params, formula),
+            false => mandelbrot_result_compiled(point, params, formula),
+        }
+    }
+
+    fn invalidate_results(&mut self) {
+        self.results = None;
+        self.stale.clear();
+        self.image = None;
+    }
+
+    /// Shift the cached results from a view at `previous` bounds to the current bounds
+    fn pan_from(&mut self, previous: [f64; 4]) {
+        let Some(old) = self.results.take() else {
+            return;
+        };
+        let shift = match pixel_shift(previous, self.params.bounds, self.width, self.height) {
+            Some(shift) => shift,
+            None => return self.invalidate_results(),
+        };
+        let (width, height) = (self.width as i64, self.height as i64);
+        let stale_before: std::collections::HashSet<usize> = self.stale.drain(..).collect();
+        let mut results = vec![IterationResult::UNRENDERED; old.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                let (old_x, old_y) = (x + shift.0, y + shift.1);
+                let kept = (0..width).contains(&old_x) && (0..height).contains(&old_y);
+                let old_index = (old_y * width + old_x) as usize;
+                match kept && !stale_before.contains(&old_index) {
+                    true => results[index] = old[old_index],
+                    false => self.stale.push(index),
+                }
+            }
+        }
+        self.results = Some(results);
+    }
+}
+
+/// Whether two parameter sets give the same iteration results at the same points
+fn same_iteration(a: &FractalParams, b: &FractalParams) -> bool {
+    a.max_iterations == b.max_iterations
+        && a.spawn == b.spawn
+        && a.bailout == b.bailout
+        && a.formula == b.formula
+        && a.i_sqrt_value == b.i_sqrt_value
+        && a.precision == b.precision
+        && a.kind == b.kind
+        && a.bulb_check == b.bulb_check
+        && a.interior_coloring == b.interior_coloring
+}
+
+/// The whole-pixel offset from the view at `from` to the view at `to`, if `to` is `from`
+/// panned by a whole number of pixels at the same scale
+fn pixel_shift(from: [f64; 4], to: [f64; 4], width: u32, height: u32) -> Option<(i64, i64)> {
+    let axis = |from_min: f64, from_max: f64, to_min: f64, to_max: f64, pixels: u32| {
+        let span = from_max - from_min;
+        if pixels < 2 || ((to_max - to_min) - span).abs() > span.abs() * 1e-9 {
+            return None;
+        }
+        let offset = (to_min - from_min) / (span / (pixels - 1) as f64);
+        let whole = offset.round();
+        ((offset - whole).abs() < 1e-6 && whole.abs() < pixels as f64).then_some(whole as i64)
+    };
+    Some((axis(from[0], from[1], to[0], to[1], width)?, axis(from[2], from[3], to[2], to[3], height)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Easing;
+
+    fn palette() -> Vec<ColorStop> {
+        vec![
+            ColorStop { color: [0, 0, 0], position: 0.0, easing: Easing::Linear },
+            ColorStop { color: [255, 128, 0], position: 1.0, easing: Easing::Linear },
+        ]
+    }
+
+    #[test]
+    fn test_updates_recompute_only_what_they_invalidate() {
+        let params = FractalParams::new([-2.0, 1.5, -1.75, 1.75], 64, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        let mut session = RenderSession::new(params, 8, 8, false, None).unwrap();
+        let first = session.render().unwrap().clone();
+        assert_eq!(session.last_work().iterated_pixels, 64);
+
+        session.set_palette(Some(palette()));
+        assert_ne!(session.render().unwrap(), &first);
+        assert_eq!(session.last_work(), SessionWork { iterated_pixels: 0, reused_pixels: 64, recolored: true });
+
+        // A half-typed formula is rejected and the previous one keeps rendering
+        let error = session.set_formula("z^2 + sin(").unwrap_err();
+        assert!(matches!(error, FractalError::ParseError(_)));
+        assert_eq!(session.params().formula, "z^2 + c");
+        session.render().unwrap();
+        assert!(!session.last_work().recolored);
+
+        // Panning three pixels right iterates only the exposed columns, matching a fresh render
+        session.set_bounds([-0.5, 3.0, -1.75, 1.75]);
+        let panned = session.render().unwrap().clone();
+        assert_eq!(session.last_work().iterated_pixels, 3 * 8);
+        let mut fresh = RenderSession::new(session.params().clone(), 8, 8, false, Some(palette())).unwrap();
+        assert_eq!(fresh.render().unwrap(), &panned);
+    }
+}
@@ -0,0 +1,261 @@
+//! Sonification of escape-time fractals
+//!
+//! Turns iteration into sound, as another way to explore a fractal or to make one accessible
+//! without seeing it. A sound is a sequence of `Tone`s of equal length, taken either from the
+//! orbit of one point (one tone per iteration) or from a row of pixels (one tone per pixel):
+//!
+//! - The pitch follows |z|, mapped logarithmically from `min_frequency` at 0 to
+//!   `max_frequency` at the bailout radius, so orbits that settle into a cycle play a repeating
+//!   melody and escaping orbits rise.
+//! - The loudness follows the iteration density, the share of `max_iterations` a point used
+//!   before escaping, so the boundary and the inside of the set are loud and the background
+//!   outside is quiet.
+//!
+//! The tones are synthesized as sine waves with continuous phase and short ramps between
+//! amplitudes to avoid clicks, and written as a 16-bit mono WAV file.
+
+use crate::iteration::{iterate_batch_with, BatchOptions};
+use crate::{pixel_to_complex, CompiledFormula, FractalError, FractalKind, FractalParams, IterationResult};
+use num_complex::Complex;
+
+/// Quietest tone, so that the background outside the set stays audible
+const MIN_AMPLITUDE: f64 = 0.1;
+
+/// Length of the ramp between the amplitudes of consecutive tones, in seconds
+const RAMP_SECONDS: f64 = 0.005;
+
+/// One note of a sonification
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Pitch in Hz
+    pub frequency: f64,
+    /// Loudness from 0 to 1
+    pub amplitude: f64,
+}
+
+/// How iteration is mapped to sound
+#[derive(Debug, Clone, Copy)]
+pub struct SonifyOptions {
+    /// Samples per second of the output
+    pub sample_rate: u32,
+    /// Length of each tone in seconds
+    pub tone_seconds: f64,
+    /// Pitch in Hz of |z| = 0
+    pub min_frequency: f64,
+    /// Pitch in Hz of |z| at the bailout radius and beyond
+    pub max_frequency: f64,
+}
+
+impl Default for SonifyOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: 44_100,
+            tone_seconds: 0.02,
+            min_frequency: 110.0,
+            max_frequency: 1760.0,
+        }
+    }
+}
+
+impl SonifyOptions {
+    /// Pitch of a value of |z|
+    ///
+    /// # Arguments
+    ///
+    /// * `modulus` - |z|
+    /// * `bailout` - The bailout radius, which maps to `max_frequency`
+    pub fn frequency(&self, modulus: f64, bailout: f64) -> f64 {
+        let t = ((1.0 + modulus).ln() / (1.0 + bailout).ln()).clamp(0.0, 1.0);
+        let t = if t.is_nan() { 1.0 } else { t };
+        self.min_frequency * (self.max_frequency / self.min_frequency).powf(t)
+    }
+}
+
+/// Loudness of a point from its iteration density
+fn amplitude(iterations: u32, max_iterations: u32) -> f64 {
+    let density = iterations as f64 / max_iterations.max(1) as f64;
+    MIN_AMPLITUDE + (1.0 - MIN_AMPLITUDE) * density.min(1.0)
+}
+
+/// Tones of the orbit of one point, one per iteration
+///
+/// The orbit is iterated with `params.formula` (or `params.kind`) in standard complex
+/// arithmetic until it escapes or reaches `max_iterations`.
+///
+/// # Arguments
+///
+/// * `start` - The first value of z: `params.kind.critical_point()` (usually 0) for the Mandelbrot
+///   set, the point for a Julia set
+/// * `c` - The constant: the point for the Mandelbrot set, `params.spawn` for a Julia set
+/// * `params` - Fractal parameters
+/// * `options` - Pitch range
+///
+/// # Returns
+///
+/// A tone for every value of z after the first, all as loud as the point's iteration density
+pub fn orbit_tones(start: Complex<f64>, c: Complex<f64>, params: &FractalParams, options: &SonifyOptions) -> Vec<Tone> {
+    let formula = CompiledFormula::new(&params.formula);
+    formula.start_orbit();
+    let bailout_sqr = params.bailout * params.bailout;
+    let mut z = start;
+    let mut moduli = Vec::new();
+    while moduli.len() < params.max_iterations as usize {
+        z = match params.kind {
+            FractalKind::Formula => formula.eval(z, c),
+            kind => kind.step(z, c),
+        };
+        moduli.push(z.norm());
+        if z.norm_sqr() > bailout_sqr {
+            break;
+        }
+    }
+
+    // The escaping step isn't counted, as in the iteration loops
+    let iterations = if z.norm_sqr() > bailout_sqr { moduli.len() as u32 - 1 } else { params.max_iterations };
+    let amplitude = amplitude(iterations, params.max_iterations);
+    moduli
+        .into_iter()
+        .map(|modulus| Tone { frequency: options.frequency(modulus, params.bailout), amplitude })
+        .collect()
+}
+
+/// Tones of iteration results, one per result
+///
+/// # Arguments
+///
+/// * `results` - Results of neighbouring points, such as a row of pixels
+/// * `params` - Fractal parameters (bailout and iteration limit)
+/// * `options` - Pitch range
+pub fn result_tones(results: &[IterationResult], params: &FractalParams, options: &SonifyOptions) -> Vec<Tone> {
+    results
+        .iter()
+        .map(|result| Tone {
+            frequency: options.frequency(result.final_z.norm(), params.bailout),
+            amplitude: amplitude(result.iterations, params.max_iterations),
+        })
+        .collect()
+}
+
+/// Tones of one row of an image, from left to right
+///
+/// # Arguments
+///
+/// * `row` - The pixel row, 0 being `y_min` as in `pixel_to_complex`
+/// * `width` - Image width in pixels, the number of tones
+/// * `height` - Image height in pixels
+/// * `params` - Fractal parameters; `bounds` gives the view
+/// * `julia` - Iterate the Julia set of `params.spawn` instead of the Mandelbrot set
+/// * `options` - Pitch range
+pub fn scanline_tones(row: u32, width: u32, height: u32, params: &FractalParams, julia: bool, options: &SonifyOptions) -> Vec<Tone> {
+    let points: Vec<Complex<f64>> = (0..width).map(|x| pixel_to_complex(x, row, width, height, params.bounds)).collect();
+    let results = iterate_batch_with(&points, params, &BatchOptions { julia, ..BatchOptions::default() });
+    result_tones(&results, params, options)
+}
+
+/// Synthesize tones as 16-bit samples
+///
+/// # Arguments
+///
+/// * `tones` - The tones, played one after another for `options.tone_seconds` each
+/// * `options` - Sample rate and tone length
+///
+/// # Returns
+///
+/// Mono samples at `options.sample_rate`
+pub fn synthesize(tones: &[Tone], options: &SonifyOptions) -> Vec<i16> {
+    let rate = options.sample_rate as f64;
+    let tone_samples = (options.tone_seconds * rate).round().max(1.0) as usize;
+    let ramp_samples = (RAMP_SECONDS * rate).round().clamp(1.0, tone_samples as f64) as usize;
+    let mut samples = Vec::with_capacity(tones.len() * tone_samples);
+    let mut phase = 0.0_f64;
+    let mut previous_amplitude = 0.0;
+
+    for tone in tones {
+        let step = std::f64::consts::TAU * tone.frequency / rate;
+        for n in 0..tone_samples {
+            let ramp = (n as f64 / ramp_samples as f64).min(1.0);
+            let amplitude = previous_amplitude + (tone.amplitude - previous_amplitude) * ramp;
+            samples.push((phase.sin() * amplitude * 0.8 * i16::MAX as f64).round() as i16);
+            phase = (phase + step) % std::f64::consts::TAU;
+        }
+        previous_amplitude = tone.amplitude;
+    }
+    samples
+}
+
+/// Write 16-bit mono samples as a WAV file
+///
+/// # Arguments
+///
+/// * `path` - Output file
+/// * `samples` - The samples
+/// * `sample_rate` - Samples per second
+///
+/// # Returns
+///
+/// Ok(()) once written, or `FractalError::IoError` if the file can't be written
+pub fn write_wav(path: &str, samples: &[i16], sample_rate: u32) -> Result<(), FractalError> {
+    let data_bytes = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_bytes as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_bytes).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // mono
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_bytes.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    crate::output::write_atomic(path, &bytes)
+}
+
+/// Synthesize tones and write them as a WAV file
+///
+/// # Returns
+///
+/// The length of the sound in seconds, or an error as from `write_wav`
+pub fn write_tones(path: &str, tones: &[Tone], options: &SonifyOptions) -> Result<f64, FractalError> {
+    let samples = synthesize(tones, options);
+    write_wav(path, &samples, options.sample_rate)?;
+    Ok(samples.len() as f64 / options.sample_rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orbit_sonification() {
+        let options = SonifyOptions::default();
+        assert_eq!(options.frequency(0.0, 2.0), 110.0);
+        assert!((options.frequency(2.0, 2.0) - 1760.0).abs() < 1e-9);
+
+        // c = -1 settles into the cycle 0, -1, 0, -1, ...
+        let params = FractalParams::new([-2.0, 2.0, -2.0, 2.0], 8, [0.0, 0.0], 2.0, "z^2 + c".to_string());
+        let tones = orbit_tones(Complex::new(0.0, 0.0), Complex::new(-1.0, 0.0), &params, &options);
+        assert_eq!(tones.len(), 8);
+        assert_eq!(tones[1].frequency, 110.0);
+        assert_eq!(tones[0].frequency, tones[2].frequency);
+        assert_eq!(tones[0].amplitude, 1.0);
+        // c = 1 escapes through 1, 2, 5
+        let escaping = orbit_tones(Complex::new(0.0, 0.0), Complex::new(1.0, 0.0), &params, &options);
+        assert_eq!(escaping.len(), 3);
+        assert!(escaping[0].amplitude < 1.0);
+
+        let samples = synthesize(&tones, &options);
+        assert_eq!(samples.len(), 8 * 882);
+        let path = std::env::temp_dir().join("ftk_sonify_test.wav");
+        write_wav(path.to_str().unwrap(), &samples, options.sample_rate).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes.len(), 44 + samples.len() * 2);
+    }
+}
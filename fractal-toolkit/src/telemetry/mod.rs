@@ -0,0 +1,362 @@
+//! Health telemetry and a stall watchdog for long renders
+//!
+//! The renderers report progress with a line every few seconds, which says nothing about memory,
+//! how many threads are actually working, or whether one of them is stuck on a single point. When
+//! `TelemetryOptions` are installed with `install`, every render started afterwards runs a
+//! reporter thread that periodically hands a `TelemetrySnapshot` (elapsed time, throughput,
+//! resident memory and busy threads) to a callback. The same thread acts as a watchdog: a worker
+//! that has spent longer than `stall_timeout` on one pixel or sample is reported once per stall.
+//!
+//! Without installed options, `RenderTelemetry` does nothing and costs a branch per task.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Called with every periodic snapshot
+pub type SnapshotCallback = Arc<dyn Fn(&TelemetrySnapshot) + Send + Sync>;
+
+/// Called once for every stall the watchdog detects
+pub type StallCallback = Arc<dyn Fn(&TelemetrySnapshot, &StalledWorker) + Send + Sync>;
+
+/// How often to report and when to consider a worker stalled
+#[derive(Clone)]
+pub struct TelemetryOptions {
+    /// Time between snapshots
+    pub interval: Duration,
+    /// Time a worker may spend on one task before it counts as stalled
+    pub stall_timeout: Duration,
+    /// Receives the periodic snapshots
+    pub on_snapshot: Option<SnapshotCallback>,
+    /// Receives the stalls found by the watchdog
+    pub on_stall: Option<StallCallback>,
+}
+
+impl Default for TelemetryOptions {
+    fn default() -> Self {
+        Self { interval: Duration::from_secs(60), stall_timeout: Duration::from_secs(600), on_snapshot: None, on_stall: None }
+    }
+}
+
+impl TelemetryOptions {
+    /// Options that print snapshots to stdout and stalls to stderr
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Time between snapshots
+    /// * `stall_timeout` - Time a worker may spend on one task before it counts as stalled
+    pub fn printing(interval: Duration, stall_timeout: Duration) -> Self {
+        Self {
+            interval,
+            stall_timeout,
+            on_snapshot: Some(Arc::new(|snapshot: &TelemetrySnapshot| println!("{}", snapshot))),
+            on_stall: Some(Arc::new(|snapshot: &TelemetrySnapshot, stall: &StalledWorker| {
+                eprintln!(
+                    "Watchdog: {}: worker {} has been on one task for {:.0}s",
+                    snapshot.label,
+                    stall.worker,
+                    stall.busy_for.as_secs_f64()
+                );
+            })),
+        }
+    }
+}
+
+/// A worker the watchdog found stuck on one task
+#[derive(Debug, Clone, PartialEq)]
+pub struct StalledWorker {
+    /// Index of the worker in the rayon pool (the pool size for threads outside it)
+    pub worker: usize,
+    /// Time spent on the current task
+    pub busy_for: Duration,
+}
+
+/// State of a render at one moment
+#[derive(Debug, Clone)]
+pub struct TelemetrySnapshot {
+    /// What is being rendered, e.g. "Rendering fractal"
+    pub label: String,
+    /// Time since the render started
+    pub elapsed: Duration,
+    /// Tasks (pixels or samples) finished
+    pub completed: u64,
+    /// Tasks in the whole render
+    pub total: u64,
+    /// Tasks finished per second since the start
+    pub throughput: f64,
+    /// Resident memory of the process in bytes, where the platform reports it
+    pub resident_memory: Option<u64>,
+    /// Workers in the middle of a task
+    pub busy_workers: usize,
+    /// Workers available to the render
+    pub workers: usize,
+    /// Workers on one task for longer than the stall timeout
+    pub stalled: Vec<StalledWorker>,
+}
+
+impl TelemetrySnapshot {
+    /// Fraction of the workers that were busy, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        if self.workers == 0 {
+            0.0
+        } else {
+            self.busy_workers as f64 / self.workers as f64
+        }
+    }
+}
+
+impl std::fmt::Display for TelemetrySnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percentage = if self.total > 0 { self.completed as f64 / self.total as f64 * 100.0 } else { 100.0 };
+        write!(
+            f,
+            "Telemetry: {}: {:.1}% ({}/{}), Elapsed: {:.1}s, {:.0}/s, {}/{} workers busy",
+            self.label,
+            percentage,
+            self.completed,
+            self.total,
+            self.elapsed.as_secs_f64(),
+            self.throughput,
+            self.busy_workers,
+            self.workers
+        )?;
+        if let Some(bytes) = self.resident_memory {
+            write!(f, ", {:.1} MiB resident", bytes as f64 / (1024.0 * 1024.0))?;
+        }
+        if !self.stalled.is_empty() {
+            write!(f, ", {} stalled", self.stalled.len())?;
+        }
+        Ok(())
+    }
+}
+
+static INSTALLED: Mutex<Option<TelemetryOptions>> = Mutex::new(None);
+
+/// Enable telemetry for the renders started from now on
+pub fn install(options: TelemetryOptions) {
+    *INSTALLED.lock().unwrap() = Some(options);
+}
+
+/// Disable telemetry for the renders started from now on
+pub fn uninstall() {
+    *INSTALLED.lock().unwrap() = None;
+}
+
+/// Resident memory of this process in bytes (Linux only)
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Shared progress of one render
+struct Monitor {
+    label: String,
+    total: u64,
+    start: Instant,
+    completed: AtomicU64,
+    /// Per worker, milliseconds since `start` plus one when its current task began, 0 when idle
+    busy_since: Vec<AtomicU64>,
+    /// Per worker, the `busy_since` value already reported as stalled
+    reported: Vec<AtomicU64>,
+}
+
+impl Monitor {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64 + 1
+    }
+
+    fn snapshot(&self, stall_timeout: Duration) -> TelemetrySnapshot {
+        let now = self.now();
+        let elapsed = self.start.elapsed();
+        let completed = self.completed.load(Ordering::Relaxed);
+        let mut busy_workers = 0;
+        let mut stalled = Vec::new();
+        for (worker, busy_since) in self.busy_since.iter().enumerate() {
+            let since = busy_since.load(Ordering::Relaxed);
+            if since == 0 {
+                continue;
+            }
+            busy_workers += 1;
+            let busy_for = Duration::from_millis(now.saturating_sub(since));
+            if busy_for > stall_timeout {
+                stalled.push(StalledWorker { worker, busy_for });
+            }
+        }
+        TelemetrySnapshot {
+            label: self.label.clone(),
+            elapsed,
+            completed,
+            total: self.total,
+            throughput: completed as f64 / elapsed.as_secs_f64().max(1e-9),
+            resident_memory: resident_memory(),
+            busy_workers,
+            // The last slot is for threads outside the pool
+            workers: self.busy_since.len() - 1,
+            stalled,
+        }
+    }
+
+    /// The stalls in a snapshot that weren't reported before
+    fn new_stalls(&self, snapshot: &TelemetrySnapshot) -> Vec<StalledWorker> {
+        snapshot
+            .stalled
+            .iter()
+            .filter(|stall| {
+                let since = self.busy_since[stall.worker].load(Ordering::Relaxed);
+                since != 0 && self.reported[stall.worker].swap(since, Ordering::Relaxed) != since
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Telemetry of one render: hands out task guards to the workers and runs the reporter thread
+pub struct RenderTelemetry {
+    monitor: Option<Arc<Monitor>>,
+    reporter: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl RenderTelemetry {
+    /// Start telemetry for a render with the installed options, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - What is being rendered, shown in the snapshots
+    /// * `total` - Number of tasks (pixels or samples) in the render
+    pub fn start(label: &str, total: u64) -> Self {
+        let options = INSTALLED.lock().unwrap().clone();
+        match options {
+            Some(options) => Self::with_options(label, total, options),
+            None => Self { monitor: None, reporter: None },
+        }
+    }
+
+    /// Start telemetry for a render with the given options
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - What is being rendered, shown in the snapshots
+    /// * `total` - Number of tasks (pixels or samples) in the render
+    /// * `options` - Reporting interval, stall timeout and callbacks
+    pub fn with_options(label: &str, total: u64, options: TelemetryOptions) -> Self {
+        let slots = rayon::current_num_threads() + 1;
+        let monitor = Arc::new(Monitor {
+            label: label.to_string(),
+            total,
+            start: Instant::now(),
+            completed: AtomicU64::new(0),
+            busy_since: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+            reported: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+        });
+
+        let (stop, stopped) = channel::<()>();
+        let reporter_monitor = Arc::clone(&monitor);
+        let handle = std::thread::spawn(move || {
+            // Check for stalls more often than snapshots are due
+            let tick = options.interval.min(options.stall_timeout / 4).max(Duration::from_millis(10));
+            let mut last_report = Instant::now();
+            loop {
+                match stopped.recv_timeout(tick) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let snapshot = reporter_monitor.snapshot(options.stall_timeout);
+                if let Some(on_stall) = &options.on_stall {
+                    for stall in reporter_monitor.new_stalls(&snapshot) {
+                        on_stall(&snapshot, &stall);
+                    }
+                }
+                if last_report.elapsed() >= options.interval {
+                    if let Some(on_snapshot) = &options.on_snapshot {
+                        on_snapshot(&snapshot);
+                    }
+                    last_report = Instant::now();
+                }
+            }
+        });
+
+        Self { monitor: Some(monitor), reporter: Some((stop, handle)) }
+    }
+
+    /// Mark the calling worker busy until the returned guard is dropped, which counts the task
+    /// as completed
+    pub fn task(&self) -> TaskGuard<'_> {
+        let Some(monitor) = self.monitor.as_deref() else {
+            return TaskGuard { monitor: None };
+        };
+        let outside_pool = monitor.busy_since.len() - 1;
+        let worker = rayon::current_thread_index().map_or(outside_pool, |index| index.min(outside_pool));
+        // A nested task on the same worker keeps the outer start time
+        let started = monitor.busy_since[worker]
+            .compare_exchange(0, monitor.now(), Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok();
+        TaskGuard { monitor: Some((monitor, worker, started)) }
+    }
+
+    /// Current state of the render, or `None` when telemetry is off
+    pub fn snapshot(&self) -> Option<TelemetrySnapshot> {
+        self.monitor.as_ref().map(|monitor| monitor.snapshot(Duration::MAX))
+    }
+}
+
+impl Drop for RenderTelemetry {
+    fn drop(&mut self) {
+        if let Some((stop, handle)) = self.reporter.take() {
+            let _ = stop.send(());
+            let _ = handle.join();
+        }
+    }
+}
+
+/// A task in progress on one worker, see `RenderTelemetry::task`
+pub struct TaskGuard<'a> {
+    monitor: Option<(&'a Monitor, usize, bool)>,
+}
+
+impl Drop for TaskGuard<'_> {
+    fn drop(&mut self) {
+        if let Some((monitor, worker, started)) = self.monitor {
+            if started {
+                monitor.busy_since[worker].store(0, Ordering::Relaxed);
+            }
+            monitor.completed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_reports_stalled_worker_once() {
+        let stalls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&stalls);
+        let options = TelemetryOptions {
+            interval: Duration::from_millis(10),
+            stall_timeout: Duration::from_millis(40),
+            on_snapshot: None,
+            on_stall: Some(Arc::new(move |_: &TelemetrySnapshot, stall: &StalledWorker| recorded.lock().unwrap().push(stall.clone()))),
+        };
+        let telemetry = RenderTelemetry::with_options("test", 3, options);
+        drop(telemetry.task());
+        {
+            let _task = telemetry.task();
+            std::thread::sleep(Duration::from_millis(200));
+            let snapshot = telemetry.snapshot().unwrap();
+            assert_eq!((snapshot.completed, snapshot.busy_workers), (1, 1));
+        }
+        drop(telemetry.task());
+        let snapshot = telemetry.snapshot().unwrap();
+        assert_eq!((snapshot.completed, snapshot.busy_workers), (3, 0));
+        drop(telemetry);
+
+        let stalls = stalls.lock().unwrap();
+        assert_eq!(stalls.len(), 1, "{:?}", stalls);
+        assert!(stalls[0].busy_for >= Duration::from_millis(40));
+    }
+}
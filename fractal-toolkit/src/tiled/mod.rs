@@ -0,0 +1,366 @@
+//! Tiled escape-time rendering that can be resumed and spread across processes
+//!
+//! Renders in the 64K-pixel class take hours and don't fit in memory as iteration results. A
+//! `TiledRender` splits the image into square tiles and keeps them in a directory:
+//!
+//! - `manifest.json` records the image size, tile size, fractal kind and parameters, so that a
+//!   resumed or parallel invocation can check it is rendering the same image
+//! - `tile_<column>_<row>.raw` holds the iteration results of one finished tile
+//!
+//! Tiles are written under a temporary name and renamed once complete, so a crash loses at most
+//! the tiles in progress. Rendering again skips the tiles already on disk, and several processes
+//! or machines sharing the directory can each take every n-th tile. Once all tiles are present,
+//! `stitch_png` colors them and streams the image to a PNG one band of tiles at a time.
+//!
+//! Pixels map to the complex plane exactly as in an untiled render of the same size, so the
+//! stitched image matches `generate_fractal_image_from_results` apart from anti-aliasing and
+//! post-processing effects, which need neighbouring pixels and are not applied.
+//!
+//! Renders too large for one image file are split into tiles the same way, and the tiles are
+//! saved as separate images with a stitching manifest instead (see `split`).
+
+pub mod split;
+
+pub use split::{single_image_limit, stitch_tiles, StitchManifest, TileImage};
+
+use crate::output::{write_atomic, AtomicFile};
+use crate::{
+    color_for_result_equalized, escape_degree, pixel_to_complex, ColorStop, FractalError, FractalParams, IterationHistogram,
+    IterationResult,
+};
+use num_complex::Complex;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Bytes per pixel in a tile file: iterations (u32), escaped (u8) and the final z (2 x f64)
+const RESULT_BYTES: usize = 21;
+
+/// Description of a tiled render, stored as `manifest.json` in its directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TiledRenderManifest {
+    /// What is being rendered, e.g. "mandelbrot" or "julia"; the iteration function is supplied
+    /// by the caller, so this guards against mixing tiles of different kinds
+    pub kind: String,
+    /// Width of the whole image in pixels
+    pub width: u32,
+    /// Height of the whole image in pixels
+    pub height: u32,
+    /// Side length of the (square) tiles
+    pub tile_size: u32,
+    /// Parameters of the render, with bounds covering the whole image
+    pub params: FractalParams,
+}
+
+/// One tile of a tiled render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// Position in row-major tile order
+    pub index: usize,
+    /// Column of the tile's top-left pixel
+    pub x: u32,
+    /// Row of the tile's top-left pixel
+    pub y: u32,
+    /// Width of the tile; tiles on the right edge may be narrower than the tile size
+    pub width: u32,
+    /// Height of the tile; tiles on the bottom edge may be shorter than the tile size
+    pub height: u32,
+}
+
+/// A tiled render kept in a directory
+#[derive(Debug, Clone)]
+pub struct TiledRender {
+    directory: PathBuf,
+    manifest: TiledRenderManifest,
+}
+
+impl TiledRender {
+    /// Start a tiled render, or resume it if the directory already holds the same render
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Where the manifest and tiles are kept; created if missing
+    /// * `kind` - What is being rendered, e.g. "mandelbrot"
+    /// * `width` - Width of the whole image in pixels
+    /// * `height` - Height of the whole image in pixels
+    /// * `tile_size` - Side length of the tiles in pixels (at least 1)
+    /// * `params` - Parameters of the render, with bounds covering the whole image
+    ///
+    /// # Returns
+    ///
+    /// The render, or an error if the directory holds a different render or can't be written
+    pub fn create(
+        directory: &str,
+        kind: &str,
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        params: &FractalParams,
+    ) -> Result<Self, FractalError> {
+        let manifest = TiledRenderManifest { kind: kind.to_string(), width, height, tile_size: tile_size.max(1), params: params.clone() };
+        if width == 0 || height == 0 {
+            return Err(FractalError::InvalidInput("Tiled render dimensions must be greater than 0".to_string()));
+        }
+        if Path::new(directory).join("manifest.json").exists() {
+            let existing = Self::open(directory)?;
+            let as_json = |m: &TiledRenderManifest| serde_json::to_value(m).map_err(|e| FractalError::InvalidInput(e.to_string()));
+            if as_json(&existing.manifest)? != as_json(&manifest)? {
+                return Err(FractalError::InvalidInput(format!(
+                    "'{}' holds a different render ({} {}x{} in tiles of {}); use a new directory or the same parameters",
+                    directory, existing.manifest.kind, existing.manifest.width, existing.manifest.height, existing.manifest.tile_size
+                )));
+            }
+            // The token isn't stored, so the resumed render takes the caller's
+            let mut existing = existing;
+            existing.manifest.params.cancel = params.cancel.clone();
+            return Ok(existing);
+        }
+
+        std::fs::create_dir_all(directory).map_err(|e| FractalError::io("create", directory, e))?;
+        let render = Self { directory: PathBuf::from(directory), manifest };
+        let pretty = serde_json::to_string_pretty(&render.manifest).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+        write_atomic(render.directory.join("manifest.json"), pretty.as_bytes())?;
+        Ok(render)
+    }
+
+    /// Open a tiled render started by `create`
+    pub fn open(directory: &str) -> Result<Self, FractalError> {
+        let path = Path::new(directory).join("manifest.json");
+        let text = std::fs::read_to_string(&path).map_err(|e| FractalError::io("read", &path, e))?;
+        let manifest = serde_json::from_str(&text)
+            .map_err(|e| FractalError::InvalidInput(format!("Invalid tiled render manifest '{}': {}", path.display(), e)))?;
+        Ok(Self { directory: PathBuf::from(directory), manifest })
+    }
+
+    /// The render's manifest
+    pub fn manifest(&self) -> &TiledRenderManifest {
+        &self.manifest
+    }
+
+    /// All tiles of the image in row-major order
+    pub fn tiles(&self) -> Vec<Tile> {
+        let TiledRenderManifest { width, height, tile_size, .. } = self.manifest;
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(tile_size as usize) {
+            for x in (0..width).step_by(tile_size as usize) {
+                tiles.push(Tile { index: tiles.len(), x, y, width: tile_size.min(width - x), height: tile_size.min(height - y) });
+            }
+        }
+        tiles
+    }
+
+    fn tile_path(&self, tile: &Tile) -> PathBuf {
+        let size = self.manifest.tile_size;
+        self.directory.join(format!("tile_{}_{}.raw", tile.x / size, tile.y / size))
+    }
+
+    /// Whether a tile has been rendered and written completely
+    pub fn is_complete(&self, tile: &Tile) -> bool {
+        let expected = tile.width as u64 * tile.height as u64 * RESULT_BYTES as u64;
+        std::fs::metadata(self.tile_path(tile)).is_ok_and(|metadata| metadata.len() == expected)
+    }
+
+    /// Tiles that still need rendering
+    pub fn pending_tiles(&self) -> Vec<Tile> {
+        self.tiles().into_iter().filter(|tile| !self.is_complete(tile)).collect()
+    }
+
+    /// Render the tiles that aren't on disk yet
+    ///
+    /// # Arguments
+    ///
+    /// * `iteration_func` - Iteration of one point, as for `generate_fractal_image_from_results`;
+    ///   it must match the manifest's kind
+    /// * `worker` - `Some((k, n))` renders only the tiles whose index is `k` modulo `n`, so that
+    ///   `n` invocations can share the work; `None` renders every pending tile
+    ///
+    /// # Returns
+    ///
+    /// The number of tiles rendered, or an error if a tile can't be written or the render is
+    /// cancelled between tiles (see `cancel`); the tiles finished before are kept
+    pub fn render_pending<F>(&self, iteration_func: F, worker: Option<(usize, usize)>) -> Result<usize, FractalError>
+    where
+        F: Fn(Complex<f64>, &FractalParams) -> IterationResult + Sync,
+    {
+        let TiledRenderManifest { width, height, ref params, .. } = self.manifest;
+        let (worker, workers) = worker.unwrap_or((0, 1));
+        if workers == 0 || worker >= workers {
+            return Err(FractalError::InvalidInput(format!("Invalid worker {} of {}", worker, workers)));
+        }
+        let total = self.tiles().len();
+        let pending: Vec<Tile> = self.pending_tiles().into_iter().filter(|tile| tile.index % workers == worker).collect();
+        println!("Tiled render: {} of {} tiles to render in {}", pending.len(), total, self.directory.display());
+
+        for (done, tile) in pending.iter().enumerate() {
+            params.cancel.check()?;
+            let results: Vec<IterationResult> = (0..tile.height)
+                .into_par_iter()
+                .flat_map_iter(|row| {
+                    let iteration_func = &iteration_func;
+                    (0..tile.width).map(move |column| {
+                        let c = pixel_to_complex(tile.x + column, tile.y + row, width, height, params.bounds);
+                        iteration_func(c, params)
+                    })
+                })
+                .collect();
+
+            let mut bytes = Vec::with_capacity(results.len() * RESULT_BYTES);
+            for result in &results {
+                bytes.extend_from_slice(&result.iterations.to_le_bytes());
+                bytes.push(result.escaped as u8);
+                bytes.extend_from_slice(&result.final_z.re.to_le_bytes());
+                bytes.extend_from_slice(&result.final_z.im.to_le_bytes());
+            }
+            write_atomic(self.tile_path(tile), &bytes)?;
+            println!("Tiled render: tile {} at ({}, {}) done ({}/{})", tile.index, tile.x, tile.y, done + 1, pending.len());
+        }
+        Ok(pending.len())
+    }
+
+    /// Read the iteration results of a finished tile, in row-major order
+    pub fn read_tile(&self, tile: &Tile) -> Result<Vec<IterationResult>, FractalError> {
+        let path = self.tile_path(tile);
+        if !self.is_complete(tile) {
+            return Err(FractalError::InvalidInput(format!("Tile {} ('{}') has not been rendered", tile.index, path.display())));
+        }
+        let bytes = std::fs::read(&path).map_err(|e| FractalError::io("read", &path, e))?;
+        Ok(bytes
+            .chunks_exact(RESULT_BYTES)
+            .map(|pixel| {
+                let f64_at = |at: usize| f64::from_le_bytes(pixel[at..at + 8].try_into().unwrap());
+                IterationResult {
+                    iterations: u32::from_le_bytes(pixel[0..4].try_into().unwrap()),
+                    escaped: pixel[4] != 0,
+                    final_z: Complex::new(f64_at(5), f64_at(13)),
+                    period: None,
+                    multiplier: None,
+                }
+            })
+            .collect())
+    }
+
+    /// Color the finished tiles and write the whole image as an RGBA PNG
+    ///
+    /// Only one band of tiles is in memory at a time; histogram coloring reads every tile once
+    /// more beforehand to count the escape times of the whole image.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where to write the PNG
+    /// * `palette` - Optional color palette
+    /// * `checksum` - Also write a `.sha256` checksum file
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) once written, or an error naming the first missing tile
+    pub fn stitch_png(&self, path: &str, palette: Option<&[ColorStop]>, checksum: bool) -> Result<(), FractalError> {
+        let TiledRenderManifest { width, height, tile_size, ref params, .. } = self.manifest;
+        let tiles = self.tiles();
+        self.check_complete()?;
+        let histogram = self.histogram()?;
+        let degree = escape_degree(&params.formula);
+
+        let mut file = AtomicFile::create(path)?;
+        file.set_checksum(checksum);
+        let mut encoder = png::Encoder::new(&mut file, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let unencoded = |e: png::EncodingError| FractalError::InvalidInput(format!("Failed to encode '{}': {}", path, e));
+        let mut writer = encoder.write_header().map_err(unencoded)?;
+        let mut stream = writer.stream_writer().map_err(unencoded)?;
+
+        let columns = width.div_ceil(tile_size) as usize;
+        for band in tiles.chunks(columns) {
+            let band_height = band[0].height as usize;
+            let mut rows = vec![0u8; band_height * width as usize * 4];
+            for tile in band {
+                let colors: Vec<image::Rgba<u8>> = self
+                    .read_tile(tile)?
+                    .par_iter()
+                    .map(|result| color_for_result_equalized(result, degree, params, palette, histogram.as_ref()))
+                    .collect();
+                for (index, color) in colors.iter().enumerate() {
+                    let (x, y) = (tile.x as usize + index % tile.width as usize, index / tile.width as usize);
+                    let at = (y * width as usize + x) * 4;
+                    rows[at..at + 4].copy_from_slice(&color.0);
+                }
+            }
+            stream.write_all(&rows).map_err(|e| FractalError::io("write", path, e))?;
+        }
+        stream.finish().map_err(unencoded)?;
+        writer.finish().map_err(unencoded)?;
+        file.commit()
+    }
+
+    /// Fail with the first missing tile unless every tile has been rendered
+    fn check_complete(&self) -> Result<(), FractalError> {
+        let tiles = self.tiles();
+        match tiles.iter().find(|tile| !self.is_complete(tile)) {
+            Some(missing) => Err(FractalError::InvalidInput(format!(
+                "Tile {} at ({}, {}) has not been rendered; {} of {} tiles are missing",
+                missing.index,
+                missing.x,
+                missing.y,
+                self.pending_tiles().len(),
+                tiles.len()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The escape-time histogram of the whole image, read from every tile, if the render uses
+    /// histogram coloring
+    fn histogram(&self) -> Result<Option<IterationHistogram>, FractalError> {
+        let params = &self.manifest.params;
+        if !params.histogram_coloring {
+            return Ok(None);
+        }
+        let mut counts = vec![0u64; params.max_iterations as usize];
+        for tile in &self.tiles() {
+            for result in self.read_tile(tile)? {
+                if result.iterations < params.max_iterations {
+                    counts[result.iterations as usize] += 1;
+                }
+            }
+        }
+        Ok(Some(IterationHistogram::from_counts(&counts)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_fractal_image_from_results, mandelbrot_result_compiled, CompiledFormula};
+
+    #[test]
+    fn test_resumed_tiles_stitch_to_untiled_render() {
+        let directory = std::env::temp_dir().join(format!("ftk_tiled_{}", std::process::id()));
+        let directory = directory.to_string_lossy().into_owned();
+        let mut params = FractalParams::new([-2.0, 1.0, -1.2, 1.2], 50, [0.0, 0.0], 4.0, "z^2 + c".to_string());
+        params.histogram_coloring = true;
+        let formula = CompiledFormula::new(&params.formula);
+        let iterate = |c, p: &FractalParams| mandelbrot_result_compiled(c, p, &formula);
+
+        // Two workers, the second of which crashed before starting
+        let render = TiledRender::create(&directory, "mandelbrot", 37, 23, 16, &params).unwrap();
+        assert_eq!(render.tiles().len(), 3 * 2);
+        assert_eq!(render.render_pending(iterate, Some((0, 2))).unwrap(), 3);
+        let output = format!("{}/stitched.png", directory);
+        assert!(render.stitch_png(&output, None, false).is_err());
+
+        // Resuming renders only what is missing; different parameters are refused
+        let mut other = params.clone();
+        other.max_iterations = 60;
+        assert!(TiledRender::create(&directory, "mandelbrot", 37, 23, 16, &other).is_err());
+        let resumed = TiledRender::create(&directory, "mandelbrot", 37, 23, 16, &params).unwrap();
+        assert_eq!(resumed.render_pending(iterate, None).unwrap(), 3);
+        assert_eq!(resumed.render_pending(iterate, None).unwrap(), 0);
+
+        resumed.stitch_png(&output, None, false).unwrap();
+        let stitched = image::open(&output).unwrap().to_rgba8();
+        let untiled = generate_fractal_image_from_results(37, 23, &params, iterate, None);
+        assert!(stitched == untiled);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+//! Splitting renders too large for one image file
+//!
+//! Some sizes can't be saved as one image whatever the machine: the renderers index pixels
+//! with `u32`, so an image has at most `u32::MAX` pixels, and encoders have limits of their own,
+//! such as 65535 pixels a side for JPEG, 16383 for WebP and 4 GiB of pixels for TIFF and BMP.
+//! `single_image_limit` finds these up front, before anything is rendered, so that the
+//! renderers can fall back to a `TiledRender` whose tiles are saved as separate images
+//! (`TiledRender::export_tiles`) instead of failing once the render is done.
+//!
+//! The tile images are listed in a stitching manifest, `<name>.tiles.json` beside the output,
+//! with the position of each in the whole image. `stitch_tiles` streams them back into one PNG,
+//! for tools that can open it, one band of tiles at a time.
+
+use super::{TiledRender, TiledRenderManifest};
+use crate::output::{save_image_atomic, AtomicFile, OutputOptions};
+use crate::{color_for_result_equalized, escape_degree, ColorStop, FractalError};
+use image::{ImageBuffer, Rgba};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Side length of the tiles a render is split into when the format allows
+const SPLIT_TILE_SIZE: u32 = 8192;
+
+/// Largest side PNG files and streamed PNG encoders accept
+const PNG_MAX_SIDE: u32 = i32::MAX as u32;
+
+/// One image of a split render
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileImage {
+    /// File name of the image, relative to the manifest
+    pub file: String,
+    /// Column of the image's top-left pixel in the whole image
+    pub x: u32,
+    /// Row of the image's top-left pixel in the whole image
+    pub y: u32,
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+}
+
+/// Layout of the images of a split render, stored as `<name>.tiles.json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StitchManifest {
+    /// Width of the whole image in pixels
+    pub width: u32,
+    /// Height of the whole image in pixels
+    pub height: u32,
+    /// The images in row-major order
+    pub tiles: Vec<TileImage>,
+}
+
+impl StitchManifest {
+    /// Read a manifest written by `TiledRender::export_tiles`
+    pub fn load(path: &str) -> Result<Self, FractalError> {
+        let text = std::fs::read_to_string(path).map_err(|e| FractalError::io("read", path, e))?;
+        serde_json::from_str(&text).map_err(|e| FractalError::InvalidInput(format!("Invalid stitching manifest '{}': {}", path, e)))
+    }
+}
+
+/// The lowercase extension of an output path
+fn extension(path: &str) -> String {
+    Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase()
+}
+
+/// Largest width and height the encoder of an output format accepts
+///
+/// # Arguments
+///
+/// * `path` - Output path; the extension chooses the format
+pub fn max_image_side(path: &str) -> u32 {
+    match extension(path).as_str() {
+        "jpg" | "jpeg" | "gif" | "tga" => u16::MAX as u32,
+        "webp" => 16383,
+        "ico" => 256,
+        _ => PNG_MAX_SIDE,
+    }
+}
+
+/// Why an image of this size can't be saved as one file, if it can't
+///
+/// # Arguments
+///
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `path` - Output path; the extension chooses the format
+///
+/// # Returns
+///
+/// `None` if the image fits in one file, otherwise the limit it exceeds
+pub fn single_image_limit(width: u32, height: u32, path: &str) -> Option<String> {
+    let pixels = width as u64 * height as u64;
+    if pixels > u32::MAX as u64 {
+        return Some(format!("{} pixels is more than the {} a render can hold", pixels, u32::MAX));
+    }
+    let side = max_image_side(path);
+    if width.max(height) > side {
+        return Some(format!(".{} images are at most {} pixels wide and high", extension(path), side));
+    }
+    // Classic TIFF and BMP address their pixels with 32-bit offsets
+    if matches!(extension(path).as_str(), "tif" | "tiff" | "bmp") && pixels * 4 > u32::MAX as u64 {
+        return Some(format!(".{} files hold at most 4 GiB of pixels", extension(path)));
+    }
+    None
+}
+
+/// Side length of the tiles to split a render into, so that every tile can be saved
+///
+/// # Arguments
+///
+/// * `path` - Output path; the extension chooses the format of the tile images
+pub fn split_tile_size(path: &str) -> u32 {
+    SPLIT_TILE_SIZE.min(max_image_side(path))
+}
+
+/// The directory the tiles of a split render are kept in: `<name>_tiles` beside the output
+pub fn split_directory(path: &str) -> String {
+    let output = Path::new(path);
+    let stem = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+    output.with_file_name(format!("{}_tiles", stem)).to_string_lossy().into_owned()
+}
+
+/// The stitching manifest of a split render: `<name>.tiles.json` beside the output
+pub fn stitch_manifest_path(path: &str) -> PathBuf {
+    Path::new(path).with_extension("tiles.json")
+}
+
+impl TiledRender {
+    /// Color the finished tiles and save each as an image beside the output, with a stitching
+    /// manifest of their layout
+    ///
+    /// The images are named `<name>_<column>_<row>.<extension>` after the output and saved in
+    /// its format. As in `stitch_png`, histogram coloring uses the escape times of the whole image.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The output the images stand in for
+    /// * `palette` - Optional color palette
+    /// * `options` - How the images are written
+    ///
+    /// # Returns
+    ///
+    /// The path of the manifest, or an error naming the first missing tile
+    pub fn export_tiles(&self, path: &str, palette: Option<&[ColorStop]>, options: &OutputOptions) -> Result<PathBuf, FractalError> {
+        let TiledRenderManifest { width, height, tile_size, ref params, .. } = self.manifest;
+        if tile_size > max_image_side(path) {
+            return Err(FractalError::InvalidInput(format!(
+                "Tiles of {} pixels are too large for '{}' (at most {})",
+                tile_size,
+                path,
+                max_image_side(path)
+            )));
+        }
+        self.check_complete()?;
+        let histogram = self.histogram()?;
+        let degree = escape_degree(&params.formula);
+
+        let output = Path::new(path);
+        let stem = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+        let mut tiles = Vec::new();
+        for tile in self.tiles() {
+            let colors: Vec<u8> = self
+                .read_tile(&tile)?
+                .par_iter()
+                .flat_map_iter(|result| color_for_result_equalized(result, degree, params, palette, histogram.as_ref()).0)
+                .collect();
+            let image: ImageBuffer<Rgba<u8>, Vec<u8>> =
+                ImageBuffer::from_raw(tile.width, tile.height, colors)
+                    .ok_or_else(|| FractalError::InvalidInput("Tile size doesn't match its pixels".to_string()))?;
+            let file = format!("{}_{}_{}.{}", stem, tile.x / tile_size, tile.y / tile_size, extension(path));
+            save_image_atomic(&image, output.with_file_name(&file), options)?;
+            tiles.push(TileImage { file, x: tile.x, y: tile.y, width: tile.width, height: tile.height });
+        }
+
+        let manifest = StitchManifest { width, height, tiles };
+        let manifest_path = stitch_manifest_path(path);
+        let pretty = serde_json::to_string_pretty(&manifest).map_err(|e| FractalError::InvalidInput(e.to_string()))?;
+        crate::output::write_atomic(&manifest_path, pretty.as_bytes())?;
+        Ok(manifest_path)
+    }
+}
+
+/// Stitch the images of a split render into one PNG
+///
+/// The PNG is streamed one band of tiles at a time, so the whole image is never in memory;
+/// it can be larger than the renderers can produce directly, up to 2^31 - 1 pixels a side.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The stitching manifest written by `TiledRender::export_tiles`
+/// * `output` - Where to write the PNG
+/// * `checksum` - Also write a `.sha256` checksum file
+///
+/// # Returns
+///
+/// Ok(()) once written, `FractalError::IoError` if a tile can't be read or the PNG can't be
+/// written, or `FractalError::InvalidInput` if the tiles don't cover the image
+pub fn stitch_tiles(manifest_path: &str, output: &str, checksum: bool) -> Result<(), FractalError> {
+    let manifest = StitchManifest::load(manifest_path)?;
+    let StitchManifest { width, height, .. } = manifest;
+    let invalid = FractalError::InvalidInput;
+    if width > PNG_MAX_SIDE || height > PNG_MAX_SIDE {
+        return Err(invalid(format!("A {}x{} image is too large for one PNG", width, height)));
+    }
+    let directory = Path::new(manifest_path).parent().unwrap_or(Path::new(""));
+    let mut tiles = manifest.tiles.clone();
+    tiles.sort_by_key(|tile| (tile.y, tile.x));
+
+    let mut file = AtomicFile::create(output)?;
+    file.set_checksum(checksum);
+    let mut encoder = png::Encoder::new(&mut file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let unencoded = |e: png::EncodingError| invalid(format!("Failed to encode '{}': {}", output, e));
+    let mut writer = encoder.write_header().map_err(unencoded)?;
+    let mut stream = writer.stream_writer().map_err(unencoded)?;
+
+    let mut y = 0;
+    let mut remaining = tiles.as_slice();
+    while y < height {
+        let band_end = remaining.iter().position(|tile| tile.y != y).unwrap_or(remaining.len());
+        let (band, rest) = remaining.split_at(band_end);
+        remaining = rest;
+        let band_height = band.first().map(|tile| tile.height).ok_or_else(|| invalid(format!("No tiles cover row {} of '{}'", y, manifest_path)))?;
+
+        let mut rows = vec![0u8; band_height as usize * width as usize * 4];
+        let mut x = 0;
+        for tile in band {
+            if tile.x != x || tile.height != band_height || x + tile.width > width {
+                return Err(invalid(format!("Tile '{}' at ({}, {}) doesn't continue the band at ({}, {})", tile.file, tile.x, tile.y, x, y)));
+            }
+            let path = directory.join(&tile.file);
+            let image = image::open(&path).map_err(|e| FractalError::image("read", &path, e))?.into_rgba8();
+            if image.dimensions() != (tile.width, tile.height) {
+                return Err(invalid(format!("'{}' is {}x{}, not {}x{}", path.display(), image.width(), image.height(), tile.width, tile.height)));
+            }
+            for (row, pixels) in image.as_raw().chunks_exact(tile.width as usize * 4).enumerate() {
+                let at = (row * width as usize + x as usize) * 4;
+                rows[at..at + pixels.len()].copy_from_slice(pixels);
+            }
+            x += tile.width;
+        }
+        if x != width {
+            return Err(invalid(format!("The tiles at row {} of '{}' cover {} of {} pixels", y, manifest_path, x, width)));
+        }
+        stream.write_all(&rows).map_err(|e| FractalError::io("write", output, e))?;
+        y += band_height;
+    }
+    stream.finish().map_err(unencoded)?;
+    writer.finish().map_err(unencoded)?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_fractal_image_from_results, julia_result_compiled, CompiledFormula, FractalParams};
+
+    #[test]
+    fn test_split_render_stitches_to_untiled_render() {
+        assert_eq!(single_image_limit(65536, 65536, "big.png").unwrap(), "4294967296 pixels is more than the 4294967295 a render can hold");
+        assert!(single_image_limit(70000, 100, "wide.jpg").is_some());
+        assert!(single_image_limit(70000, 100, "wide.png").is_none());
+        assert!(single_image_limit(40000, 40000, "large.tif").is_some());
+        assert_eq!(split_tile_size("tiles.webp"), 16383.min(SPLIT_TILE_SIZE));
+
+        let directory = std::env::temp_dir().join(format!("ftk_split_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("julia.png").to_string_lossy().into_owned();
+        let mut params = FractalParams::new([-1.5, 1.5, -1.2, 1.2], 60, [-0.8, 0.156], 4.0, "z^2 + c".to_string());
+        params.histogram_coloring = true;
+        let formula = CompiledFormula::new(&params.formula);
+        let iterate = |z, p: &FractalParams| julia_result_compiled(z, p, &formula);
+
+        let render = TiledRender::create(&split_directory(&output), "julia", 41, 29, 16, &params).unwrap();
+        render.render_pending(iterate, None).unwrap();
+        let manifest_path = render.export_tiles(&output, None, &OutputOptions::default()).unwrap();
+        let manifest = StitchManifest::load(manifest_path.to_str().unwrap()).unwrap();
+        assert_eq!(manifest.tiles.len(), 3 * 2);
+        assert_eq!(manifest.tiles[5].file, "julia_2_1.png");
+
+        let stitched = directory.join("stitched.png").to_string_lossy().into_owned();
+        stitch_tiles(manifest_path.to_str().unwrap(), &stitched, false).unwrap();
+        let untiled = generate_fractal_image_from_results(41, 29, &params, iterate, None);
+        assert!(image::open(&stitched).unwrap().to_rgba8() == untiled);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
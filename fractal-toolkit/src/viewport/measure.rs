@@ -0,0 +1,161 @@
+//! Measurements in the complex plane from pixel coordinates
+//!
+//! Annotation tools and the HTML explorer work in pixels of a rendered image, while distances,
+//! angles and areas are wanted in complex units. A `Ruler` ties pixel coordinates to a
+//! `Viewport` and image size, using the same mapping as `Viewport::pixel_to_complex`.
+//!
+//! Measurements are taken from the pixels' offsets from the view center rather than from their
+//! absolute positions, so a distance of a few pixels at a zoom of 1e14 keeps all of its digits
+//! even though the positions themselves can't be told apart in `f64`.
+
+use super::Viewport;
+use num_complex::Complex;
+
+/// Measures distances, angles and areas of an image rendered from a viewport
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ruler {
+    /// The view the image was rendered from
+    pub viewport: Viewport,
+    /// Width of the image in pixels
+    pub width: u32,
+    /// Height of the image in pixels
+    pub height: u32,
+}
+
+impl Ruler {
+    /// Create a ruler for an image of the given size
+    pub fn new(viewport: Viewport, width: u32, height: u32) -> Self {
+        Self { viewport, width, height }
+    }
+
+    /// Create a ruler for an image rendered from rectangular bounds [x_min, x_max, y_min, y_max]
+    pub fn from_bounds(bounds: [f64; 4], width: u32, height: u32) -> Self {
+        Self::new(Viewport::from_bounds(bounds), width, height)
+    }
+
+    /// Point of the complex plane at a pixel position, rounded to `f64`
+    pub fn point(&self, pixel: (f64, f64)) -> Complex<f64> {
+        self.viewport.pixel_to_complex(pixel.0, pixel.1, self.width, self.height)
+    }
+
+    /// Complex displacement from one pixel to another
+    pub fn displacement(&self, from: (f64, f64), to: (f64, f64)) -> Complex<f64> {
+        self.offset(to) - self.offset(from)
+    }
+
+    /// Distance between two pixels in complex units
+    pub fn distance(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        self.displacement(from, to).norm()
+    }
+
+    /// Direction from one pixel to another as an angle in the complex plane
+    ///
+    /// # Returns
+    ///
+    /// The argument of the displacement in degrees, in (-180, 180], measured counter-clockwise
+    /// from the positive real axis, so it includes the rotation of the view
+    pub fn direction(&self, from: (f64, f64), to: (f64, f64)) -> f64 {
+        let displacement = self.displacement(from, to);
+        displacement.im.atan2(displacement.re).to_degrees()
+    }
+
+    /// Angle at a vertex between the rays to two other pixels
+    ///
+    /// # Arguments
+    ///
+    /// * `vertex` - Pixel at the corner of the angle
+    /// * `a` - Pixel on the first ray
+    /// * `b` - Pixel on the second ray
+    ///
+    /// # Returns
+    ///
+    /// The unsigned angle in degrees, between 0 and 180; 0 if either ray has zero length
+    pub fn angle(&self, vertex: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+        let first = self.displacement(vertex, a);
+        let second = self.displacement(vertex, b);
+        if first.norm_sqr() == 0.0 || second.norm_sqr() == 0.0 {
+            return 0.0;
+        }
+        // atan2 of the cross and dot products stays accurate for nearly parallel rays
+        let cross = first.re * second.im - first.im * second.re;
+        let dot = first.re * second.re + first.im * second.im;
+        cross.abs().atan2(dot).to_degrees()
+    }
+
+    /// Area enclosed by a polygon given by its corner pixels, in square complex units
+    ///
+    /// # Arguments
+    ///
+    /// * `corners` - Corners in order around the polygon, without repeating the first one
+    ///
+    /// # Returns
+    ///
+    /// The unsigned area from the shoelace formula (self-intersecting polygons count the
+    /// regions wound in opposite directions against each other); 0 for fewer than 3 corners
+    pub fn area(&self, corners: &[(f64, f64)]) -> f64 {
+        if corners.len() < 3 {
+            return 0.0;
+        }
+        let offsets: Vec<Complex<f64>> = corners.iter().map(|&corner| self.offset(corner)).collect();
+        let twice_area: f64 = offsets
+            .iter()
+            .zip(offsets.iter().cycle().skip(1))
+            .map(|(p, q)| p.re * q.im - q.re * p.im)
+            .sum();
+        twice_area.abs() / 2.0
+    }
+
+    /// Length of a path through the given pixels in complex units
+    ///
+    /// # Arguments
+    ///
+    /// * `points` - Pixels along the path
+    /// * `closed` - Also count the segment from the last point back to the first
+    pub fn path_length(&self, points: &[(f64, f64)], closed: bool) -> f64 {
+        let open: f64 = points.windows(2).map(|pair| self.distance(pair[0], pair[1])).sum();
+        match (closed, points.first(), points.last()) {
+            (true, Some(&first), Some(&last)) if points.len() > 2 => open + self.distance(last, first),
+            _ => open,
+        }
+    }
+
+    /// Complex units per pixel
+    pub fn units_per_pixel(&self) -> f64 {
+        self.viewport.pixel_size(self.height)
+    }
+
+    fn offset(&self, pixel: (f64, f64)) -> Complex<f64> {
+        self.viewport.pixel_offset(pixel.0, pixel.1, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measurements_in_complex_units() {
+        // 400x400 pixels over [-2, 2] x [-2, 2]: 100 pixels per unit
+        let ruler = Ruler::from_bounds([-2.0, 2.0, -2.0, 2.0], 400, 400);
+        assert_eq!(ruler.point((200.0, 200.0)), Complex::new(0.0, 0.0));
+        assert!((ruler.distance((0.0, 0.0), (300.0, 400.0)) - 5.0).abs() < 1e-12);
+        assert!((ruler.direction((200.0, 200.0), (200.0, 300.0)) - 90.0).abs() < 1e-12);
+        assert!((ruler.angle((0.0, 0.0), (100.0, 0.0), (100.0, 100.0)) - 45.0).abs() < 1e-12);
+
+        let square = [(100.0, 100.0), (300.0, 100.0), (300.0, 300.0), (100.0, 300.0)];
+        assert!((ruler.area(&square) - 4.0).abs() < 1e-12);
+        assert!((ruler.path_length(&square, true) - 8.0).abs() < 1e-12);
+
+        // Rotating the view turns directions but keeps distances, angles and areas
+        let rotated = Ruler::new(ruler.viewport.clone().with_rotation(30.0), 400, 400);
+        assert!((rotated.direction((200.0, 200.0), (300.0, 200.0)) - 30.0).abs() < 1e-9);
+        assert!((rotated.area(&square) - 4.0).abs() < 1e-12);
+        let pixel = rotated.viewport.complex_to_pixel(rotated.point((123.0, 45.0)), 400, 400);
+        assert!((pixel.0 - 123.0).abs() < 1e-9 && (pixel.1 - 45.0).abs() < 1e-9);
+
+        // Distances at deep zoom keep their digits
+        let deep = Ruler::new(Viewport::new("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139", 1e14).unwrap(), 1000, 1000);
+        let expected = 3.0 * deep.units_per_pixel();
+        assert!((deep.distance((10.0, 10.0), (13.0, 10.0)) - expected).abs() < expected * 1e-12);
+    }
+}
@@ -0,0 +1,272 @@
+//! Views of the complex plane described by center and magnification
+//!
+//! `FractalParams` stores a view as rectangular bounds, which is convenient for rendering but
+//! loses digits at deep zooms where the center needs more precision than an `f64` offers. A
+//! `Viewport` keeps the center as the exact decimal strings it was given, so locations can be
+//! passed between programs without rounding, and converts to bounds only when rendering.
+//!
+//! Views can be written as a single line of center + magnification + rotation notation,
+//! e.g. `re=-0.75 im=0.1 zoom=1e3 rotation=0`, which round-trips the center digits exactly.
+//!
+//! Distances, angles and areas between pixels of a rendered view are measured with
+//! `measure::Ruler`.
+
+pub mod measure;
+
+pub use measure::Ruler;
+
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+
+/// Height of the complex plane shown at magnification 1 (the classic -2..2 view)
+pub const BASE_VIEW_HEIGHT: f64 = 4.0;
+
+/// A view of the complex plane given by its center and magnification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    /// Real part of the view center as an exact decimal string
+    pub center_re: String,
+    /// Imaginary part of the view center as an exact decimal string
+    pub center_im: String,
+    /// Magnification relative to the default view, where 1 shows a height of 4 (radius 2)
+    pub zoom: f64,
+    /// Counter-clockwise rotation of the view around its center, in degrees
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+impl Viewport {
+    /// Create a viewport, validating that the center parts are plain decimal numbers
+    ///
+    /// # Arguments
+    ///
+    /// * `center_re` - Real part of the center, e.g. "-0.743643887037158704752191506114774"
+    /// * `center_im` - Imaginary part of the center
+    /// * `zoom` - Magnification, must be positive and finite
+    ///
+    /// # Returns
+    ///
+    /// The viewport, or an error message if a value is invalid
+    pub fn new(center_re: &str, center_im: &str, zoom: f64) -> Result<Self, String> {
+        let center_re = center_re.trim();
+        let center_im = center_im.trim();
+        if !is_decimal(center_re) {
+            return Err(format!("Invalid real part of center: '{}'", center_re));
+        }
+        if !is_decimal(center_im) {
+            return Err(format!("Invalid imaginary part of center: '{}'", center_im));
+        }
+        if !zoom.is_finite() || zoom <= 0.0 {
+            return Err(format!("Zoom must be a positive finite number, got {}", zoom));
+        }
+
+        Ok(Self {
+            center_re: center_re.to_string(),
+            center_im: center_im.to_string(),
+            zoom,
+            rotation: 0.0,
+        })
+    }
+
+    /// Set the rotation of the view in degrees (counter-clockwise)
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    /// Parse center + magnification + rotation notation
+    ///
+    /// The notation is a whitespace-separated list of `key=value` pairs with the keys `re`, `im`,
+    /// `zoom` and (optionally) `rotation`, as produced by `to_notation`. `center=re,im` is also
+    /// accepted in place of separate `re` and `im` keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `notation` - The notation string, e.g. "re=-0.75 im=0.1 zoom=1e3 rotation=30"
+    ///
+    /// # Returns
+    ///
+    /// The viewport, or an error message naming the missing or malformed value
+    pub fn from_notation(notation: &str) -> Result<Self, String> {
+        let mut center_re = None;
+        let mut center_im = None;
+        let mut zoom = None;
+        let mut rotation = 0.0;
+
+        for pair in notation.split_whitespace() {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected key=value in view notation, found '{}'", pair))?;
+            match key.to_lowercase().as_str() {
+                "re" => center_re = Some(value),
+                "im" => center_im = Some(value),
+                "center" => {
+                    let (re, im) = value
+                        .split_once(',')
+                        .ok_or_else(|| format!("Center must be written as re,im, found '{}'", value))?;
+                    center_re = Some(re);
+                    center_im = Some(im);
+                }
+                "zoom" | "magnification" => {
+                    zoom = Some(value.parse::<f64>().map_err(|_| format!("Invalid zoom: '{}'", value))?);
+                }
+                "rotation" => {
+                    rotation = value.parse::<f64>().map_err(|_| format!("Invalid rotation: '{}'", value))?;
+                }
+                _ => return Err(format!("Unknown key in view notation: '{}'", key)),
+            }
+        }
+
+        let center_re = center_re.ok_or("View notation has no real part of the center (re=)")?;
+        let center_im = center_im.ok_or("View notation has no imaginary part of the center (im=)")?;
+        let zoom = zoom.ok_or("View notation has no magnification (zoom=)")?;
+        Ok(Self::new(center_re, center_im, zoom)?.with_rotation(rotation))
+    }
+
+    /// Write the view as center + magnification + rotation notation
+    ///
+    /// The center is written exactly as stored, and the zoom uses the shortest representation
+    /// that parses back to the same `f64`, so `from_notation(to_notation())` is lossless.
+    pub fn to_notation(&self) -> String {
+        format!(
+            "re={} im={} zoom={:e} rotation={}",
+            self.center_re, self.center_im, self.zoom, self.rotation
+        )
+    }
+
+    /// Describe existing rectangular bounds as a viewport
+    ///
+    /// The magnification is taken from the height of the bounds.
+    pub fn from_bounds(bounds: [f64; 4]) -> Self {
+        let [x_min, x_max, y_min, y_max] = bounds;
+        Self {
+            center_re: format!("{}", (x_min + x_max) / 2.0),
+            center_im: format!("{}", (y_min + y_max) / 2.0),
+            zoom: BASE_VIEW_HEIGHT / (y_max - y_min).abs(),
+            rotation: 0.0,
+        }
+    }
+
+    /// The center rounded to double precision
+    pub fn center(&self) -> Complex<f64> {
+        Complex::new(
+            self.center_re.parse().unwrap_or(0.0),
+            self.center_im.parse().unwrap_or(0.0),
+        )
+    }
+
+    /// Rectangular bounds [x_min, x_max, y_min, y_max] for an image of the given size
+    ///
+    /// The height of the view is fixed by the magnification and the width follows the
+    /// image's aspect ratio, so pixels stay square. Bounds are always axis-aligned, so the
+    /// rotation is not represented; use `pixel_to_complex` to render rotated views.
+    pub fn to_bounds(&self, width: u32, height: u32) -> [f64; 4] {
+        let center = self.center();
+        let half_height = BASE_VIEW_HEIGHT / self.zoom / 2.0;
+        let half_width = half_height * width as f64 / height.max(1) as f64;
+        [
+            center.re - half_width,
+            center.re + half_width,
+            center.im - half_height,
+            center.im + half_height,
+        ]
+    }
+
+    /// Width and height of one pixel in an image with the given height
+    pub fn pixel_size(&self, height: u32) -> f64 {
+        BASE_VIEW_HEIGHT / self.zoom / height.max(1) as f64
+    }
+
+    /// Map a pixel of an image of the given size to its point in the complex plane
+    ///
+    /// Rows follow the crate's `pixel_to_complex` convention, where row 0 lies at the minimum
+    /// imaginary part. The rotation is applied around the center.
+    pub fn pixel_to_complex(&self, x: f64, y: f64, width: u32, height: u32) -> Complex<f64> {
+        self.center() + self.pixel_offset(x, y, width, height)
+    }
+
+    /// Offset of a pixel from the center, including rotation
+    ///
+    /// Offsets are small at deep zoom and keep full `f64` precision, so extended-precision
+    /// renderers add them to a center parsed at higher precision.
+    pub fn pixel_offset(&self, x: f64, y: f64, width: u32, height: u32) -> Complex<f64> {
+        let pixel_size = self.pixel_size(height);
+        let offset = Complex::new(
+            (x - width as f64 / 2.0) * pixel_size,
+            (y - height as f64 / 2.0) * pixel_size,
+        );
+        offset * Complex::from_polar(1.0, self.rotation.to_radians())
+    }
+
+    /// Pixel position of a point of the complex plane, the inverse of `pixel_to_complex`
+    ///
+    /// Positions are fractional and may lie outside the image for points outside the view.
+    pub fn complex_to_pixel(&self, point: Complex<f64>, width: u32, height: u32) -> (f64, f64) {
+        let offset = (point - self.center()) * Complex::from_polar(1.0, -self.rotation.to_radians()) / self.pixel_size(height);
+        (offset.re + width as f64 / 2.0, offset.im + height as f64 / 2.0)
+    }
+
+    /// True if neighbouring pixels can no longer be told apart in `f64` arithmetic
+    ///
+    /// Rendering such a view with the standard renderer produces blocky output, so callers
+    /// should warn the user.
+    pub fn exceeds_double_precision(&self, height: u32) -> bool {
+        let center = self.center();
+        let magnitude = center.re.abs().max(center.im.abs()).max(1.0);
+        self.pixel_size(height) < magnitude * f64::EPSILON * 16.0
+    }
+}
+
+impl std::fmt::Display for Viewport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_notation())
+    }
+}
+
+/// Check that a string is a plain decimal number, optionally signed and with an exponent
+fn is_decimal(text: &str) -> bool {
+    let unsigned = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(pos) => (&unsigned[..pos], Some(&unsigned[pos + 1..])),
+        None => (unsigned, None),
+    };
+
+    let mut parts = mantissa.splitn(2, '.');
+    let integer = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    let digits_ok = integer.chars().all(|c| c.is_ascii_digit()) && fraction.chars().all(|c| c.is_ascii_digit());
+    if !digits_ok || (integer.is_empty() && fraction.is_empty()) {
+        return false;
+    }
+
+    match exponent {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+            !exponent.is_empty() && exponent.chars().all(|c| c.is_ascii_digit())
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notation_round_trips_exactly() {
+        let view = Viewport::new("-1.7499576837060935036022145060706", "0.0000000000000000084", 3.5e17)
+            .unwrap()
+            .with_rotation(12.5);
+        let parsed = Viewport::from_notation(&view.to_notation()).unwrap();
+        assert_eq!(parsed, view);
+    }
+
+    #[test]
+    fn test_bounds_conversion() {
+        let view = Viewport::from_bounds([-2.5, 1.5, -1.0, 1.0]);
+        assert_eq!(view.center_re, "-0.5");
+        assert_eq!(view.center_im, "0");
+        assert_eq!(view.zoom, 2.0);
+        assert_eq!(view.to_bounds(400, 200), [-2.5, 1.5, -1.0, 1.0]);
+    }
+}